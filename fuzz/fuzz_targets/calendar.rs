@@ -0,0 +1,26 @@
+//! Parses operator-provided schedules and queries the parsed calendars.
+#![no_main]
+
+use chrono::{Duration, TimeZone};
+use libfuzzer_sys::fuzz_target;
+use router::schedule::{Calendar, Tz};
+use std::str::FromStr;
+
+fuzz_target!(|schedule: &str| {
+    let Ok(mut calendar) = Calendar::from_str(schedule) else {
+        return;
+    };
+    let start = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap();
+    let end = start + Duration::days(2);
+    calendar.is_available_between(start, end);
+    calendar.busy_slots(start, end);
+    calendar.add_blackout(start, start + Duration::hours(1));
+    calendar.free_slots(start, end);
+    // a serialized calendar must parse again
+    let serialized = calendar.to_string();
+    assert!(
+        Calendar::from_str(&serialized).is_ok(),
+        "Unable to parse serialized calendar: {}",
+        serialized
+    );
+});
@@ -0,0 +1,16 @@
+//! Validates requested flight time windows.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use prost_types::Timestamp;
+use router::time::validate_flight_window;
+
+fuzz_target!(|window: (Option<(i64, i32)>, Option<(i64, i32)>)| {
+    let (earliest, latest) = window;
+    let to_timestamp = |(seconds, nanos)| Timestamp { seconds, nanos };
+    let earliest = earliest.map(to_timestamp);
+    let latest = latest.map(to_timestamp);
+    if let Ok((earliest, latest)) = validate_flight_window(earliest.as_ref(), latest.as_ref()) {
+        assert!(earliest < latest);
+    }
+});
@@ -0,0 +1,65 @@
+/*
+ * C bindings of the router engine, built with the `ffi` feature:
+ *
+ *   cargo rustc --release --no-default-features --features ffi --crate-type cdylib
+ *
+ * Nodes are referred to by their position in the array passed to
+ * router_new, and legs are weighted by their Haversine distance in
+ * kilometers. See src/utils/ffi.rs for the safety requirements.
+ */
+#ifndef ROUTER_H
+#define ROUTER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The call succeeded */
+#define ROUTER_OK 0
+/* There is no route between the nodes */
+#define ROUTER_NO_ROUTE 1
+/* The buffer is too small for the route, its required length is written */
+#define ROUTER_BUFFER_TOO_SMALL 2
+/* A pointer is null or a node position is out of the array */
+#define ROUTER_INVALID_ARGUMENT -1
+
+/* A router owning its nodes */
+typedef struct RouterHandle RouterHandle;
+
+/* A node, its id is copied by router_new */
+typedef struct RouterNode {
+    const char *uid;
+    float latitude;
+    float longitude;
+    float altitude_meters;
+} RouterNode;
+
+/* Builds a router connecting the nodes at most max_distance_km apart,
+ * NULL if a pointer is null or an id isn't valid UTF-8 */
+RouterHandle *router_new(const RouterNode *nodes, size_t count, float max_distance_km);
+
+/* Frees a router built by router_new, NULL is ignored */
+void router_free(RouterHandle *router);
+
+/* Finds the shortest route between the nodes at positions from and to,
+ * writing the positions of its nodes to path, their number to path_length
+ * and its length to distance_km */
+int32_t router_find_route(const RouterHandle *router,
+                          size_t from,
+                          size_t to,
+                          size_t *path,
+                          size_t path_capacity,
+                          size_t *path_length,
+                          float *distance_km);
+
+/* Gets the number of nodes of the shortest route, 0 if there is none */
+size_t router_path_length(const RouterHandle *router, size_t from, size_t to);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* ROUTER_H */
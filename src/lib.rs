@@ -1,22 +1,166 @@
 //! Fleet Routing Algorithm Library.
 //! Handles routing and path-finding tasks.
+//!
+//! The routing core (nodes, the graph, the router engine and the Haversine
+//! geometry) only depends on pure Rust crates, so it also builds for
+//! `wasm32-unknown-unknown`. Scheduling flights, with date times, calendars
+//! and the storage resources, requires the `scheduling` feature, enabled by
+//! default.
 #[macro_use]
-extern crate log;
+extern crate tracing;
 
 mod types {
+    pub mod distance;
     pub mod edge;
     pub mod location;
     pub mod node;
+    #[cfg(feature = "scheduling")]
+    pub mod resources;
     pub mod router;
     pub mod status;
 }
 
 mod utils {
+    #[cfg(feature = "scheduling")]
+    pub mod alternate;
+    #[cfg(feature = "scheduling")]
+    pub mod amendment;
+    #[cfg(feature = "scheduling")]
+    pub mod approach;
+    #[cfg(any(test, feature = "proptest"))]
+    pub mod arbitrary;
+    #[cfg(feature = "scheduling")]
+    pub mod audit;
+    #[cfg(feature = "scheduling")]
+    pub mod batch;
+    #[cfg(feature = "scheduling")]
+    pub mod capacity;
+    #[cfg(feature = "scheduling")]
+    pub mod charging;
+    #[cfg(feature = "scheduling")]
+    pub mod clock;
+    #[cfg(feature = "scheduling")]
+    pub mod conflict;
+    #[cfg(feature = "scheduling")]
+    pub mod congestion;
+    #[cfg(feature = "scheduling")]
+    pub mod consolidation;
+    pub mod constraints;
+    pub mod context;
+    #[cfg(feature = "scheduling")]
+    pub mod cost;
+    #[cfg(feature = "scheduling")]
+    pub mod coverage;
+    #[cfg(feature = "scheduling")]
+    pub mod crew;
+    #[cfg(feature = "scheduling")]
+    pub mod curfew;
+    #[cfg(feature = "scheduling")]
+    pub mod demand;
+    #[cfg(feature = "scheduling")]
+    pub mod diversion;
+    #[cfg(feature = "scheduling")]
+    pub mod energy;
+    #[cfg(feature = "scheduling")]
+    pub mod eta;
+    pub mod federation;
+    #[cfg(feature = "ffi")]
+    pub mod ffi;
+    #[cfg(feature = "scheduling")]
+    pub mod fleet_state;
+    #[cfg(feature = "scheduling")]
+    pub mod flight_plan_stream;
+    #[cfg(feature = "scheduling")]
+    pub mod flight_query;
+    #[cfg(feature = "scheduling")]
+    pub mod fpl;
+    #[cfg(feature = "scheduling")]
+    pub mod gantt;
+    #[cfg(feature = "scheduling")]
     pub mod generator;
+    pub mod geohash;
     pub mod graph;
+    pub mod graph_patch;
+    #[cfg(feature = "scheduling")]
+    pub mod ground;
+    #[cfg(feature = "scheduling")]
+    pub mod ground_risk;
+    #[cfg(feature = "scheduling")]
+    pub mod gtfs;
     pub mod haversine;
+    pub mod hubs;
+    #[cfg(feature = "scheduling")]
+    pub mod ical;
+    #[cfg(feature = "scheduling")]
+    pub mod interop;
+    pub mod kml;
+    #[cfg(feature = "scheduling")]
+    pub mod kpi;
+    #[cfg(feature = "scheduling")]
+    pub mod maintenance;
+    #[cfg(feature = "scheduling")]
+    pub mod metrics;
+    #[cfg(feature = "scheduling")]
+    pub mod monte_carlo;
+    #[cfg(feature = "scheduling")]
+    pub mod multistop;
+    #[cfg(feature = "scheduling")]
+    pub mod network_import;
+    #[cfg(feature = "scheduling")]
+    pub mod noise;
+    pub mod notifications;
+    #[cfg(feature = "scheduling")]
+    pub mod operator;
+    #[cfg(feature = "scheduling")]
+    pub mod pareto;
+    #[cfg(feature = "scheduling")]
+    pub mod parking;
+    #[cfg(feature = "scheduling")]
+    pub mod position;
+    #[cfg(feature = "scheduling")]
+    pub mod profile;
+    #[cfg(feature = "scheduling")]
+    pub mod providers;
+    #[cfg(feature = "python")]
+    pub mod python;
+    #[cfg(feature = "scheduling")]
+    pub mod queueing;
+    #[cfg(feature = "scheduling")]
+    pub mod replanner;
+    #[cfg(feature = "scheduling")]
+    pub mod reservation;
+    #[cfg(feature = "scheduling")]
+    pub mod route_diff;
+    #[cfg(feature = "scheduling")]
+    pub mod route_validation;
+    #[cfg(feature = "scheduling")]
     pub mod router_state;
+    #[cfg(feature = "scheduling")]
+    pub mod scenario;
+    #[cfg(feature = "scheduling")]
     pub mod schedule;
+    #[cfg(feature = "scheduling")]
+    pub mod separation;
+    #[cfg(feature = "scheduling")]
+    pub mod simulation;
+    #[cfg(feature = "scheduling")]
+    pub mod spatial;
+    #[cfg(feature = "scheduling")]
+    pub mod terrain;
+    #[cfg(any(test, feature = "test_support"))]
+    pub mod test_support;
+    #[cfg(feature = "scheduling")]
+    pub mod time;
+    #[cfg(feature = "scheduling")]
+    pub mod traffic;
+    #[cfg(feature = "scheduling")]
+    pub mod turnaround;
+    #[cfg(feature = "scheduling")]
+    pub mod utm;
+    #[cfg(feature = "scheduling")]
+    pub mod vrp;
+    #[cfg(feature = "scheduling")]
+    pub mod weather;
 }
 
 pub use types::*;
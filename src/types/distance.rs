@@ -0,0 +1,118 @@
+//! Struct definitions and implementations for [`Distance`].
+//!
+//! Distances of the router are kilometers, as returned by
+//! [`haversine::distance`](crate::haversine::distance). Public APIs taking a
+//! radius or a range take a [`Distance`] instead of a bare float, so the unit
+//! is stated where the value is created.
+
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::ops::{Add, Sub};
+
+/// Meters in a kilometer
+const METERS_PER_KM: f32 = 1000.0;
+/// Kilometers in an international nautical mile
+const KM_PER_NAUTICAL_MILE: f32 = 1.852;
+
+/// A [`Distance`] is a length along the surface of the earth.
+///
+/// # Examples
+/// ```
+/// use router::distance::Distance;
+///
+/// assert_eq!(Distance::from_meters(1500.0), Distance::from_km(1.5));
+/// assert_eq!(Distance::from_nautical_miles(1.0).meters(), 1852.0);
+/// ```
+#[derive(
+    Debug, Default, PartialEq, PartialOrd, Hash, Eq, Ord, Copy, Clone, Serialize, Deserialize,
+)]
+pub struct Distance {
+    /// The distance in kilometers.
+    km: OrderedFloat<f32>,
+}
+
+impl Distance {
+    /// No distance at all
+    pub const ZERO: Distance = Distance {
+        km: OrderedFloat(0.0),
+    };
+
+    /// Creates a distance of `km` kilometers
+    pub const fn from_km(km: f32) -> Self {
+        Distance {
+            km: OrderedFloat(km),
+        }
+    }
+
+    /// Creates a distance of `meters` meters
+    pub fn from_meters(meters: f32) -> Self {
+        Distance::from_km(meters / METERS_PER_KM)
+    }
+
+    /// Creates a distance of `nautical_miles` international nautical miles
+    pub fn from_nautical_miles(nautical_miles: f32) -> Self {
+        Distance::from_km(nautical_miles * KM_PER_NAUTICAL_MILE)
+    }
+
+    /// Gets the distance in kilometers
+    pub fn km(&self) -> f32 {
+        self.km.into_inner()
+    }
+
+    /// Gets the distance in meters
+    pub fn meters(&self) -> f32 {
+        self.km() * METERS_PER_KM
+    }
+
+    /// Gets the distance in international nautical miles
+    pub fn nautical_miles(&self) -> f32 {
+        self.km() / KM_PER_NAUTICAL_MILE
+    }
+}
+
+impl Add for Distance {
+    type Output = Distance;
+
+    fn add(self, other: Distance) -> Distance {
+        Distance::from_km(self.km() + other.km())
+    }
+}
+
+impl Sub for Distance {
+    type Output = Distance;
+
+    fn sub(self, other: Distance) -> Distance {
+        Distance::from_km(self.km() - other.km())
+    }
+}
+
+impl Display for Distance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} km", self.km())
+    }
+}
+
+#[cfg(test)]
+mod distance_tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        let distance = Distance::from_km(3.704);
+        assert_eq!(distance.meters(), 3704.0);
+        assert_eq!(distance.nautical_miles(), 2.0);
+        assert_eq!(Distance::from_meters(250.0).km(), 0.25);
+        assert_eq!(Distance::from_nautical_miles(2.0), distance);
+        assert_eq!(Distance::default(), Distance::ZERO);
+    }
+
+    #[test]
+    fn test_arithmetic_and_order() {
+        let (short, long) = (Distance::from_meters(500.0), Distance::from_km(2.0));
+        assert!(short < long);
+        assert_eq!(short + long, Distance::from_km(2.5));
+        assert_eq!(long - short, Distance::from_meters(1500.0));
+        assert_eq!(long.to_string(), "2 km");
+    }
+}
@@ -15,7 +15,9 @@ use serde::{Deserialize, Serialize};
 ///
 /// Float values are used to achieve a 5-decimal precision (0.00001),
 /// which narrows the error margin to a meter.
-#[derive(Debug, PartialEq, Hash, Eq, Copy, Clone, Serialize, Deserialize)]
+///
+/// The default location is at the origin of the coordinates, on the ground.
+#[derive(Debug, Default, PartialEq, Hash, Eq, Copy, Clone, Serialize, Deserialize)]
 pub struct Location {
     /// The latitude of the location.
     pub latitude: OrderedFloat<f32>,
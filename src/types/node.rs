@@ -43,7 +43,20 @@ pub trait AsNode {
 ///
 /// Since the actual vertex can be any object, a generic struct is
 /// needed for the purpose of abstraction and clarity.
-#[derive(Debug, PartialEq, Hash, Eq, Serialize, Deserialize)]
+///
+/// Create a node with [`Node::new`] and set its optional fields with the
+/// `with_` methods:
+///
+/// ```
+/// use router::node::Node;
+/// use router::status::Status;
+///
+/// let node = Node::new("vertiport_1", 37.7749, -122.4194)
+///     .with_altitude_meters(120.0)
+///     .with_status(Status::Closed);
+/// assert_eq!(node.forward_to, None);
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Hash, Eq, Serialize, Deserialize)]
 pub struct Node {
     /// Typed as a [`String`] to allow for synthetic ids. One purpose of
     /// using a synthetic id is to allow for partitioned indexing on the
@@ -75,6 +88,48 @@ pub struct Node {
     pub schedule: Option<String>,
 }
 
+impl Node {
+    /// Creates an operating node on the ground at the given coordinates in
+    /// degrees, without forwarding or schedule.
+    pub fn new(uid: impl Into<String>, latitude: f32, longitude: f32) -> Self {
+        Node {
+            uid: uid.into(),
+            location: location::Location {
+                latitude: OrderedFloat(latitude),
+                longitude: OrderedFloat(longitude),
+                altitude_meters: OrderedFloat(0.0),
+            },
+            forward_to: None,
+            status: status::Status::Ok,
+            schedule: None,
+        }
+    }
+
+    /// Sets the altitude of the node in meters.
+    pub fn with_altitude_meters(mut self, altitude_meters: f32) -> Self {
+        self.location.altitude_meters = OrderedFloat(altitude_meters);
+        self
+    }
+
+    /// Sets the operation status of the node.
+    pub fn with_status(mut self, status: status::Status) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Sets the node incoming traffic is forwarded to.
+    pub fn with_forward_to(mut self, node: Node) -> Self {
+        self.forward_to = Some(Box::new(node));
+        self
+    }
+
+    /// Sets the calendar of the node as RRule string.
+    pub fn with_schedule(mut self, schedule: impl Into<String>) -> Self {
+        self.schedule = Some(schedule.into());
+        self
+    }
+}
+
 impl AsNode for Node {
     fn as_node(&self) -> &Node {
         self
@@ -348,6 +403,38 @@ mod node_type_tests {
             vertipads: vec![],
         };
         assert_eq!(vertiport.distance_to(&vertipad_1), 0.0);
-        assert_eq!(vertiport.distance_to(&vertipad_2), 3340.5833);
+        assert_eq!(vertiport.distance_to(&vertipad_2), 3340.5881);
+    }
+
+    #[test]
+    fn test_new_node() {
+        let node = Node::new("vertiport_1", 40.730610, -73.935242);
+        assert_eq!(
+            node,
+            Node {
+                uid: "vertiport_1".to_string(),
+                location: location::Location {
+                    longitude: OrderedFloat(-73.935242),
+                    latitude: OrderedFloat(40.730610),
+                    altitude_meters: OrderedFloat(0.0),
+                },
+                forward_to: None,
+                status: status::Status::Ok,
+                schedule: None,
+            }
+        );
+
+        let forward_to = Node::new("vertiport_2", 40.0, -73.0);
+        let node = node
+            .with_altitude_meters(50.0)
+            .with_status(status::Status::Closed)
+            .with_forward_to(forward_to.clone())
+            .with_schedule("FREQ=DAILY");
+        assert_eq!(node.location.altitude_meters, 50.0);
+        assert_eq!(node.status, status::Status::Closed);
+        assert_eq!(node.forward_to, Some(Box::new(forward_to)));
+        assert_eq!(node.schedule.as_deref(), Some("FREQ=DAILY"));
+
+        assert_eq!(Node::default().status, status::Status::Ok);
     }
 }
@@ -0,0 +1,345 @@
+//! Vehicles, vertiports, vertipads and flight plans used by the router.
+//!
+//! These mirror the resources of svc-storage, so the library can be used in
+//! simulations and wasm targets without the gRPC client. With the
+//! `svc-storage` feature, each type converts to and from its
+//! `svc_storage_client_grpc` counterpart with [`From`].
+
+/// Flight plans
+pub mod flight_plan {
+    use prost_types::Timestamp;
+
+    /// Data of a flight plan
+    #[derive(Clone, Debug, PartialEq, Default)]
+    pub struct Data {
+        /// id of the pilot
+        pub pilot_id: String,
+        /// id of the vehicle
+        pub vehicle_id: String,
+        /// weights of the cargo items
+        pub cargo_weight_grams: Vec<i64>,
+        /// weather conditions of the flight
+        pub weather_conditions: Option<String>,
+        /// id of the departure vertiport
+        pub departure_vertiport_id: Option<String>,
+        /// id of the destination vertiport
+        pub destination_vertiport_id: Option<String>,
+        /// scheduled departure time
+        pub scheduled_departure: Option<Timestamp>,
+        /// scheduled arrival time
+        pub scheduled_arrival: Option<Timestamp>,
+        /// actual departure time
+        pub actual_departure: Option<Timestamp>,
+        /// actual arrival time
+        pub actual_arrival: Option<Timestamp>,
+        /// time the flight was released
+        pub flight_release_approval: Option<Timestamp>,
+        /// time the flight plan was submitted
+        pub flight_plan_submitted: Option<Timestamp>,
+        /// id of the approver of the flight
+        pub approved_by: Option<String>,
+        /// status of the flight
+        pub flight_status: i32,
+        /// priority of the flight
+        pub flight_priority: i32,
+        /// id of the departure vertipad
+        pub departure_vertipad_id: String,
+        /// id of the destination vertipad
+        pub destination_vertipad_id: String,
+        /// distance of the flight
+        pub flight_distance_meters: i64,
+    }
+
+    /// Flight plan with its id
+    #[derive(Clone, Debug, PartialEq, Default)]
+    pub struct Object {
+        /// id of the flight plan
+        pub id: String,
+        /// data of the flight plan
+        pub data: Option<Data>,
+    }
+}
+
+/// Vehicles
+pub mod vehicle {
+    use prost_types::Timestamp;
+
+    /// Data of a vehicle
+    #[derive(Clone, Debug, PartialEq, Default)]
+    pub struct Data {
+        /// id of the vehicle model
+        pub vehicle_model_id: String,
+        /// serial number of the vehicle
+        pub serial_number: String,
+        /// registration number of the vehicle
+        pub registration_number: String,
+        /// description of the vehicle
+        pub description: Option<String>,
+        /// id of the asset group of the vehicle
+        pub asset_group_id: Option<String>,
+        /// RRULE schedule of the vehicle
+        pub schedule: Option<String>,
+        /// time of the last maintenance
+        pub last_maintenance: Option<Timestamp>,
+        /// time of the next maintenance
+        pub next_maintenance: Option<Timestamp>,
+        /// id of the vertiport the vehicle was last parked at
+        pub last_vertiport_id: Option<String>,
+        /// id of the operator of the vehicle, None if unknown
+        pub operator_id: Option<String>,
+        /// whether every operator may use the vehicle, whatever its operator
+        pub shared: bool,
+    }
+
+    /// Vehicle with its id
+    #[derive(Clone, Debug, PartialEq, Default)]
+    pub struct Object {
+        /// id of the vehicle
+        pub id: String,
+        /// data of the vehicle
+        pub data: Option<Data>,
+    }
+}
+
+/// Vertipads
+pub mod vertipad {
+    /// Data of a vertipad
+    #[derive(Clone, Debug, PartialEq, Default)]
+    pub struct Data {
+        /// id of the vertiport of the vertipad
+        pub vertiport_id: String,
+        /// name of the vertipad
+        pub name: String,
+        /// latitude in degrees
+        pub latitude: f64,
+        /// longitude in degrees
+        pub longitude: f64,
+        /// whether the vertipad is in service
+        pub enabled: bool,
+        /// whether the vertipad is occupied
+        pub occupied: bool,
+        /// RRULE schedule of the vertipad
+        pub schedule: Option<String>,
+    }
+
+    /// Vertipad with its id
+    #[derive(Clone, Debug, PartialEq, Default)]
+    pub struct Object {
+        /// id of the vertipad
+        pub id: String,
+        /// data of the vertipad
+        pub data: Option<Data>,
+    }
+}
+
+/// Vertiports
+pub mod vertiport {
+    /// Data of a vertiport
+    #[derive(Clone, Debug, PartialEq, Default)]
+    pub struct Data {
+        /// name of the vertiport
+        pub name: String,
+        /// description of the vertiport
+        pub description: String,
+        /// latitude in degrees
+        pub latitude: f64,
+        /// longitude in degrees
+        pub longitude: f64,
+        /// RRULE schedule of the vertiport
+        pub schedule: Option<String>,
+        /// id of the operator of the vertiport, None if unknown
+        pub operator_id: Option<String>,
+        /// whether every operator may use the vertiport, e.g. a public
+        /// vertiport, whatever its operator
+        pub shared: bool,
+    }
+
+    /// Vertiport with its id
+    #[derive(Clone, Debug, PartialEq, Default)]
+    pub struct Object {
+        /// id of the vertiport
+        pub id: String,
+        /// data of the vertiport
+        pub data: Option<Data>,
+    }
+}
+
+/// Implements [`From`] both ways between a resource and its svc-storage
+/// counterpart, copying the listed fields of their data
+/// Fields listed as `local` aren't stored by svc-storage: they are dropped
+/// when converting to storage and take their default value when converting
+/// from storage
+#[cfg(feature = "svc-storage")]
+macro_rules! storage_conversions {
+    ($resource:ident { $($field:ident),* $(,)? } $(local { $($local:ident),* $(,)? })?) => {
+        impl From<svc_storage_client_grpc::resources::$resource::Data> for $resource::Data {
+            fn from(data: svc_storage_client_grpc::resources::$resource::Data) -> Self {
+                $resource::Data {
+                    $($field: data.$field,)*
+                    $($($local: Default::default(),)*)?
+                }
+            }
+        }
+
+        impl From<$resource::Data> for svc_storage_client_grpc::resources::$resource::Data {
+            fn from(data: $resource::Data) -> Self {
+                svc_storage_client_grpc::resources::$resource::Data {
+                    $($field: data.$field,)*
+                }
+            }
+        }
+
+        impl From<svc_storage_client_grpc::resources::$resource::Object> for $resource::Object {
+            fn from(object: svc_storage_client_grpc::resources::$resource::Object) -> Self {
+                $resource::Object {
+                    id: object.id,
+                    data: object.data.map(Into::into),
+                }
+            }
+        }
+
+        impl From<$resource::Object> for svc_storage_client_grpc::resources::$resource::Object {
+            fn from(object: $resource::Object) -> Self {
+                svc_storage_client_grpc::resources::$resource::Object {
+                    id: object.id,
+                    data: object.data.map(Into::into),
+                }
+            }
+        }
+    };
+}
+
+#[cfg(feature = "svc-storage")]
+storage_conversions!(flight_plan {
+    pilot_id,
+    vehicle_id,
+    cargo_weight_grams,
+    weather_conditions,
+    departure_vertiport_id,
+    destination_vertiport_id,
+    scheduled_departure,
+    scheduled_arrival,
+    actual_departure,
+    actual_arrival,
+    flight_release_approval,
+    flight_plan_submitted,
+    approved_by,
+    flight_status,
+    flight_priority,
+    departure_vertipad_id,
+    destination_vertipad_id,
+    flight_distance_meters,
+});
+
+#[cfg(feature = "svc-storage")]
+storage_conversions!(vehicle {
+    vehicle_model_id,
+    serial_number,
+    registration_number,
+    description,
+    asset_group_id,
+    schedule,
+    last_maintenance,
+    next_maintenance,
+    last_vertiport_id,
+} local {
+    operator_id,
+    shared,
+});
+
+#[cfg(feature = "svc-storage")]
+storage_conversions!(vertipad {
+    vertiport_id,
+    name,
+    latitude,
+    longitude,
+    enabled,
+    occupied,
+    schedule,
+});
+
+#[cfg(feature = "svc-storage")]
+storage_conversions!(vertiport {
+    name,
+    description,
+    latitude,
+    longitude,
+    schedule,
+} local {
+    operator_id,
+    shared,
+});
+
+#[cfg(all(test, feature = "svc-storage"))]
+mod resources_tests {
+    use super::*;
+    use prost_types::Timestamp;
+
+    #[test]
+    fn test_flight_plan_round_trip() {
+        let flight_plan = flight_plan::Object {
+            id: "flight-plan".to_string(),
+            data: Some(flight_plan::Data {
+                vehicle_id: "vehicle".to_string(),
+                cargo_weight_grams: vec![1200, 800],
+                departure_vertiport_id: Some("SFO".to_string()),
+                scheduled_departure: Some(Timestamp {
+                    seconds: 1_900_000_000,
+                    nanos: 0,
+                }),
+                flight_priority: 2,
+                ..Default::default()
+            }),
+        };
+        let stored: svc_storage_client_grpc::resources::flight_plan::Object =
+            flight_plan.clone().into();
+        assert_eq!(
+            stored.data.as_ref().unwrap().cargo_weight_grams,
+            vec![1200, 800]
+        );
+        assert_eq!(flight_plan::Object::from(stored), flight_plan);
+    }
+
+    #[test]
+    fn test_operator_is_not_stored() {
+        let vehicle = vehicle::Data {
+            serial_number: "SN-1".to_string(),
+            operator_id: Some("operator-A".to_string()),
+            shared: true,
+            ..Default::default()
+        };
+        let stored: svc_storage_client_grpc::resources::vehicle::Data = vehicle.clone().into();
+        let loaded = vehicle::Data::from(stored);
+        assert_eq!(
+            loaded,
+            vehicle::Data {
+                operator_id: None,
+                shared: false,
+                ..vehicle
+            }
+        );
+        // the operator is unknown, no tenant may use the vehicle
+        let vehicle = vehicle::Object {
+            id: "vehicle".to_string(),
+            data: Some(loaded),
+        };
+        assert!(!crate::operator::OperatorFilter::new()
+            .with_operator("operator-A")
+            .allows_vehicle(&vehicle));
+    }
+
+    #[test]
+    fn test_vertiport_without_data() {
+        let vertiport = svc_storage_client_grpc::resources::vertiport::Object {
+            id: "SFO".to_string(),
+            data: None,
+        };
+        assert_eq!(
+            vertiport::Object::from(vertiport),
+            vertiport::Object {
+                id: "SFO".to_string(),
+                data: None,
+            }
+        );
+    }
+}
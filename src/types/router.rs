@@ -7,19 +7,31 @@
 /// The router engine module.
 pub mod engine {
     use std::{
-        collections::HashMap,
-        fmt::{Display, Formatter, Result},
+        cmp::Reverse,
+        collections::{BinaryHeap, HashMap, HashSet},
+        fmt::{Debug, Display, Formatter, Result},
         result::Result as StdResult,
+        sync::{Arc, RwLock},
     };
 
     use ordered_float::OrderedFloat;
-    use petgraph::{algo::astar, graph::NodeIndex, stable_graph::StableDiGraph};
+    use petgraph::{
+        algo::astar,
+        graph::NodeIndex,
+        stable_graph::{EdgeIndex, StableDiGraph},
+        visit::{EdgeFiltered, EdgeRef, IntoEdgeReferences},
+        Direction,
+    };
 
     use crate::{
-        edge::Edge,
-        haversine,
+        haversine::{self, EarthRadius},
+        types::location::Location,
         types::node::{AsNode, Node},
         utils::graph::build_edges,
+        utils::graph_patch::GraphPatch,
+        utils::notifications::{
+            notify_graph_changed, notify_node_status_changed, GraphChange, NodeStatusChange,
+        },
     };
 
     /// Error types for the router engine.
@@ -34,12 +46,25 @@ pub mod engine {
         ///
         /// Expected message: "Invalid path"
         InvalidNodesInPath,
+
+        /// A graph patch doesn't apply to the version of the graph or
+        /// refers to missing nodes.
+        ///
+        /// Expected message: "Invalid patch"
+        InvalidPatch,
+
+        /// No heuristic is registered with the name given in a query.
+        ///
+        /// Expected message: "Unknown heuristic"
+        UnknownHeuristic,
     }
 
     impl Display for RouterError {
         fn fmt(&self, f: &mut Formatter) -> Result {
             match self {
                 RouterError::InvalidNodesInPath => write!(f, "Invalid path"),
+                RouterError::InvalidPatch => write!(f, "Invalid patch"),
+                RouterError::UnknownHeuristic => write!(f, "Unknown heuristic"),
             }
         }
     }
@@ -52,7 +77,97 @@ pub mod engine {
     pub struct Router<'a> {
         pub(crate) graph: StableDiGraph<&'a Node, OrderedFloat<f32>>,
         pub(crate) node_indices: HashMap<&'a Node, NodeIndex>,
-        pub(crate) edges: Vec<Edge<'a>>,
+        pub(crate) version: u64,
+        pub(crate) heuristics: HeuristicRegistry,
+        pub(crate) all_pairs: RwLock<Option<AllPairs>>,
+        pub(crate) landmark_count: RwLock<Option<usize>>,
+        pub(crate) earth_radius: EarthRadius,
+    }
+
+    /// Name of the heuristic estimating no remaining cost, turning A* into
+    /// the Dijkstra algorithm.
+    pub const ZERO_HEURISTIC: &str = "zero";
+
+    /// Name of the heuristic estimating the remaining cost with the
+    /// Haversine distance to the node to end at, which never overestimates
+    /// when edges are weighted with their distance.
+    pub const HAVERSINE_HEURISTIC: &str = "haversine";
+
+    /// Name of the heuristic bounding the remaining cost with the
+    /// precomputed costs to and from landmarks and the triangle
+    /// inequality, registered by [`Router::with_landmarks`].
+    pub const LANDMARK_HEURISTIC: &str = "landmarks";
+
+    /// A heuristic for the A* algorithm, taking a node and the node to end
+    /// at and returning an estimate of the remaining cost.
+    pub type Heuristic = Arc<dyn Fn(&Node, &Node) -> f32 + Send + Sync>;
+
+    /// Heuristics of a router by name. The registry can be extended while
+    /// the router is shared, e.g. once initialized as a static.
+    pub struct HeuristicRegistry {
+        heuristics: RwLock<HashMap<String, Heuristic>>,
+    }
+
+    impl Default for HeuristicRegistry {
+        fn default() -> Self {
+            let heuristics: [(&str, Heuristic); 2] = [
+                (ZERO_HEURISTIC, Arc::new(|_, _| 0.0)),
+                (
+                    HAVERSINE_HEURISTIC,
+                    Arc::new(|node, to| haversine::distance(&node.location, &to.location)),
+                ),
+            ];
+            HeuristicRegistry {
+                heuristics: RwLock::new(
+                    heuristics
+                        .into_iter()
+                        .map(|(name, heuristic)| (name.to_string(), heuristic))
+                        .collect(),
+                ),
+            }
+        }
+    }
+
+    impl Debug for HeuristicRegistry {
+        fn fmt(&self, f: &mut Formatter) -> Result {
+            f.debug_list().entries(self.names()).finish()
+        }
+    }
+
+    impl HeuristicRegistry {
+        /// Register a heuristic under a name, replacing the previous one.
+        pub fn register(&self, name: &str, heuristic: Heuristic) {
+            match self.heuristics.write() {
+                Ok(mut heuristics) => {
+                    heuristics.insert(name.to_string(), heuristic);
+                }
+                Err(_) => error!("Heuristics unavailable"),
+            }
+        }
+
+        /// Get the heuristic registered under a name.
+        pub fn get(&self, name: &str) -> Option<Heuristic> {
+            match self.heuristics.read() {
+                Ok(heuristics) => heuristics.get(name).cloned(),
+                Err(_) => {
+                    error!("Heuristics unavailable");
+                    None
+                }
+            }
+        }
+
+        /// Get the names of the registered heuristics, sorted.
+        pub fn names(&self) -> Vec<String> {
+            let mut names: Vec<String> = match self.heuristics.read() {
+                Ok(heuristics) => heuristics.keys().cloned().collect(),
+                Err(_) => {
+                    error!("Heuristics unavailable");
+                    vec![]
+                }
+            };
+            names.sort();
+            names
+        }
     }
 
     /// Path finding algorithms.
@@ -62,11 +177,112 @@ pub mod engine {
         Dijkstra,
         /// The A Star algorithm.
         AStar,
+        /// The A Star algorithm with the landmark heuristic, see
+        /// [`Router::with_landmarks`]. Without landmarks, no heuristic is
+        /// used.
+        AStarLandmarks,
+        /// The Dijkstra algorithm searching from both nodes until the
+        /// searches meet, exploring about half as many nodes on large
+        /// graphs. No heuristic is used.
+        BidirectionalDijkstra,
+        /// A lookup of the paths precomputed by the Floyd–Warshall
+        /// algorithm, see [`Router::with_all_pairs`]. Without precomputed
+        /// paths, the Dijkstra algorithm is used.
+        PrecomputedAllPairs,
+    }
+
+    /// Limits of a path, see [`Router::find_shortest_path_with_limits`].
+    #[derive(Debug, Copy, Clone, Default, PartialEq)]
+    pub struct PathLimits {
+        /// The max number of legs, None if unlimited.
+        pub max_legs: Option<usize>,
+        /// The max total duration of the legs, None if unlimited.
+        pub max_duration: Option<f32>,
+    }
+
+    impl PathLimits {
+        /// Check if the limits don't restrict the path.
+        pub fn is_unlimited(&self) -> bool {
+            self.max_legs.is_none() && self.max_duration.is_none()
+        }
+    }
+
+    /// What alternative paths must not share, see
+    /// [`Router::find_disjoint_paths`].
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum Disjointness {
+        /// Paths don't share a leg between two nodes.
+        Edge,
+        /// Paths don't share a leg or an intermediate node.
+        Node,
+    }
+
+    /// Costs for each criterion and node indices of a path of a Pareto
+    /// frontier, see [`Router::find_pareto_paths`].
+    pub type ParetoPath<const N: usize> = ([f32; N], Vec<NodeIndex>);
+
+    /// Partial path of a multi-criteria search
+    #[derive(Debug, Copy, Clone)]
+    struct ParetoLabel<const N: usize> {
+        /// costs of the path for each criterion
+        costs: [f32; N],
+        /// last node of the path
+        index: NodeIndex,
+        /// label of the path without its last edge
+        previous: Option<usize>,
+    }
+
+    /// An edge of the graph, see [`Router::edges`].
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    pub struct EdgeView<'r> {
+        /// The node the edge starts from.
+        pub from: &'r Node,
+        /// The node the edge ends at.
+        pub to: &'r Node,
+        /// The id of the node the edge starts from.
+        pub from_id: &'r str,
+        /// The id of the node the edge ends at.
+        pub to_id: &'r str,
+        /// The weight of the edge in the graph.
+        pub cost: f32,
+    }
+
+    /// Shortest paths between all pairs of nodes, by position of the nodes
+    #[derive(Debug, Clone)]
+    pub(crate) struct AllPairs {
+        /// position of each node in the matrices
+        positions: HashMap<NodeIndex, usize>,
+        /// node at each position
+        indices: Vec<NodeIndex>,
+        /// cost of the shortest path between two positions
+        costs: Vec<Vec<f32>>,
+        /// position of the node following the first one on the shortest
+        /// path between two positions, None if there is no path
+        next: Vec<Vec<Option<usize>>>,
+    }
+
+    impl AllPairs {
+        /// Cost and nodes of the shortest path between two nodes, in
+        /// O(path length)
+        fn path(&self, from: NodeIndex, to: NodeIndex) -> Option<(f32, Vec<NodeIndex>)> {
+            let (mut position, end) = (*self.positions.get(&from)?, *self.positions.get(&to)?);
+            let cost = self.costs[position][end];
+            let mut path = vec![self.indices[position]];
+            while position != end {
+                position = self.next[position][end]?;
+                path.push(self.indices[position]);
+            }
+            Some((cost, path))
+        }
     }
 
     impl Router<'_> {
         /// Creates a new router with the given graph.
         ///
+        /// The nodes and edges are added to the graph sorted by node id,
+        /// so equal-cost paths are resolved the same way whatever the order
+        /// of `nodes`, and identical inputs always yield identical routes.
+        ///
         /// # Arguments
         /// * `nodes` - A vector of nodes.
         /// * `constraint` - Only nodes within a constraint can be connected.
@@ -76,47 +292,79 @@ pub mod engine {
         ///   two nodes.
         ///
         /// # Returns
-        /// A Router struct.
+        /// A Router struct, built on the radius of the Earth set with
+        /// [`haversine::set_earth_radius`].
         pub fn new(
             nodes: &[impl AsNode],
             constraint: f32,
             constraint_function: fn(&dyn AsNode, &dyn AsNode) -> f32,
             cost_function: fn(&dyn AsNode, &dyn AsNode) -> f32,
         ) -> Router {
+            Self::new_with_earth_radius(
+                nodes,
+                constraint,
+                constraint_function,
+                cost_function,
+                haversine::get_earth_radius(),
+            )
+        }
+
+        /// Creates a new router like [`Router::new`], with the Haversine
+        /// distances of the functions and of the router computed on the
+        /// given radius of the Earth.
+        ///
+        /// The router keeps the radius it was built on, so its edges and the
+        /// costs precomputed from them don't change with
+        /// [`haversine::set_earth_radius`].
+        pub fn new_with_earth_radius(
+            nodes: &[impl AsNode],
+            constraint: f32,
+            constraint_function: fn(&dyn AsNode, &dyn AsNode) -> f32,
+            cost_function: fn(&dyn AsNode, &dyn AsNode) -> f32,
+            earth_radius: EarthRadius,
+        ) -> Router<'_> {
             info!("[1/4] Initializing the router engine...");
             info!("[2/4] Building edges...");
 
-            let edges = build_edges(nodes, constraint, constraint_function, cost_function);
+            let mut edges = haversine::with_earth_radius(earth_radius, || {
+                build_edges(nodes, constraint, constraint_function, cost_function)
+            });
+            edges.sort_by(|a, b| (&a.from.uid, &a.to.uid).cmp(&(&b.from.uid, &b.to.uid)));
             let mut node_indices = HashMap::new();
             let mut graph = StableDiGraph::new();
 
             info!("[3/4] Building the graph...");
-            for edge in &edges {
-                let from_index = *node_indices
-                    .entry(edge.from)
-                    .or_insert_with(|| graph.add_node(edge.from));
-                let to_index = *node_indices
-                    .entry(edge.to)
-                    .or_insert_with(|| graph.add_node(edge.to));
-                graph.add_edge(from_index, to_index, edge.cost);
+            // node indices follow the ids, breaking ties of the searches
+            let mut sorted_nodes: Vec<&Node> = nodes.iter().map(|node| node.as_node()).collect();
+            sorted_nodes.sort_by(|a, b| a.uid.cmp(&b.uid));
+            for node in sorted_nodes {
+                node_indices
+                    .entry(node)
+                    .or_insert_with(|| graph.add_node(node));
             }
 
             info!("[4/4] Finalizing the router setup...");
-            for node in nodes {
-                if !node_indices.contains_key(node.as_node()) {
-                    let index = graph.add_node(node.as_node());
-                    node_indices.insert(node.as_node(), index);
-                }
+            for edge in &edges {
+                graph.add_edge(node_indices[edge.from], node_indices[edge.to], edge.cost);
             }
 
             info!("✨Done! Router engine is ready to use.");
             Router {
                 graph,
                 node_indices,
-                edges,
+                version: 0,
+                heuristics: HeuristicRegistry::default(),
+                all_pairs: RwLock::new(None),
+                landmark_count: RwLock::new(None),
+                earth_radius,
             }
         }
 
+        /// Gets the radius of the Earth the router was built on.
+        pub fn earth_radius(&self) -> EarthRadius {
+            self.earth_radius
+        }
+
         /// Get the NodeIndex struct for a given node. The NodeIndex
         /// struct is used to reference things in the graph.
         pub fn get_node_index(&self, node: &Node) -> Option<NodeIndex> {
@@ -200,11 +448,874 @@ pub mod engine {
                     heuristic_function.unwrap_or(|_| 0.0),
                 )
                 .unwrap_or((0.0, Vec::new())),
+
+                Algorithm::AStarLandmarks => {
+                    let heuristic = self.heuristics.get(LANDMARK_HEURISTIC);
+                    astar(
+                        &self.graph,
+                        from_index,
+                        |finish| finish == to_index,
+                        |e| (*e.weight()).into_inner(),
+                        |index| heuristic.as_ref().map_or(0.0, |h| h(self.graph[index], to)),
+                    )
+                    .unwrap_or((0.0, Vec::new()))
+                }
+
+                Algorithm::BidirectionalDijkstra => self
+                    .bidirectional_dijkstra(from_index, to_index)
+                    .unwrap_or((0.0, Vec::new())),
+
+                Algorithm::PrecomputedAllPairs => match self.all_pairs.read() {
+                    Ok(all_pairs) if all_pairs.is_some() => all_pairs
+                        .as_ref()
+                        .and_then(|all_pairs| all_pairs.path(from_index, to_index))
+                        .unwrap_or((0.0, Vec::new())),
+                    _ => {
+                        warn!("No precomputed paths, falling back to the Dijkstra algorithm");
+                        astar(
+                            &self.graph,
+                            from_index,
+                            |finish| finish == to_index,
+                            |e| (*e.weight()).into_inner(),
+                            |_| 0.0,
+                        )
+                        .unwrap_or((0.0, Vec::new()))
+                    }
+                },
             };
 
             Ok(result)
         }
 
+        /// Find the shortest path by alternating a forward search from
+        /// `from_index` and a backward search from `to_index`, stopping when
+        /// no path through an unsettled node can be shorter than the best
+        /// path through a node reached by both searches.
+        fn bidirectional_dijkstra(
+            &self,
+            from_index: NodeIndex,
+            to_index: NodeIndex,
+        ) -> Option<(f32, Vec<NodeIndex>)> {
+            // costs and previous node of each search, by direction
+            let mut costs: [HashMap<NodeIndex, f32>; 2] = [
+                HashMap::from([(from_index, 0.0)]),
+                HashMap::from([(to_index, 0.0)]),
+            ];
+            let mut previous: [HashMap<NodeIndex, NodeIndex>; 2] = Default::default();
+            let mut queues = [
+                BinaryHeap::from([Reverse((OrderedFloat(0.0), from_index))]),
+                BinaryHeap::from([Reverse((OrderedFloat(0.0), to_index))]),
+            ];
+            let directions = [Direction::Outgoing, Direction::Incoming];
+            let mut best: Option<(f32, NodeIndex)> =
+                (from_index == to_index).then_some((0.0, from_index));
+            let mut explored = 0;
+
+            loop {
+                let top = |side: usize| {
+                    queues[side]
+                        .peek()
+                        .map(|Reverse((OrderedFloat(cost), _))| *cost)
+                };
+                let side = match (top(0), top(1)) {
+                    (Some(forward), Some(backward)) => {
+                        if best.is_some_and(|(cost, _)| forward + backward >= cost) {
+                            break;
+                        }
+                        usize::from(backward < forward)
+                    }
+                    _ => break,
+                };
+                let Some(Reverse((OrderedFloat(cost), index))) = queues[side].pop() else {
+                    break;
+                };
+                if costs[side].get(&index).is_some_and(|best| cost > *best) {
+                    continue;
+                }
+                explored += 1;
+                for edge in self.graph.edges_directed(index, directions[side]) {
+                    let next = match directions[side] {
+                        Direction::Outgoing => edge.target(),
+                        Direction::Incoming => edge.source(),
+                    };
+                    let next_cost = cost + edge.weight().into_inner();
+                    if costs[side]
+                        .get(&next)
+                        .is_some_and(|best| next_cost >= *best)
+                    {
+                        continue;
+                    }
+                    costs[side].insert(next, next_cost);
+                    previous[side].insert(next, index);
+                    queues[side].push(Reverse((OrderedFloat(next_cost), next)));
+                    if let Some(other_cost) = costs[1 - side].get(&next) {
+                        if !best.is_some_and(|(cost, _)| next_cost + other_cost >= cost) {
+                            best = Some((next_cost + other_cost, next));
+                        }
+                    }
+                }
+            }
+            debug!("Bidirectional search explored {} nodes", explored);
+
+            let (cost, meeting) = best?;
+            let mut path = vec![meeting];
+            while let Some(&prior) = previous[0].get(path.last()?) {
+                path.push(prior);
+            }
+            path.reverse();
+            while let Some(&next) = previous[1].get(path.last()?) {
+                path.push(next);
+            }
+            Some((cost, path))
+        }
+
+        /// Find the shortest path between two nodes, evaluating every edge
+        /// with `penalty` at query time, e.g. for conditions changing after
+        /// the graph is built.
+        ///
+        /// # Arguments
+        /// * `from` - The node to start from.
+        /// * `to` - The node to end at.
+        /// * `penalty` - A function that takes the two nodes of an edge and
+        ///   returns the cost added to the edge, or None if the edge can't
+        ///   be used.
+        ///
+        /// # Returns
+        /// A tuple of the total cost including penalties and the path
+        /// consisting of node indices.
+        ///
+        /// An empty path with a total cost of 0.0 returned if no path
+        /// is found.
+        pub fn find_shortest_path_with_penalty(
+            &self,
+            from: &Node,
+            to: &Node,
+            penalty: impl Fn(&Node, &Node) -> Option<f32>,
+        ) -> StdResult<(f32, Vec<NodeIndex>), RouterError> {
+            self.find_path_with_penalty_and_heuristic(from, to, penalty, |_, _| 0.0)
+        }
+
+        fn find_path_with_penalty_and_heuristic(
+            &self,
+            from: &Node,
+            to: &Node,
+            penalty: impl Fn(&Node, &Node) -> Option<f32>,
+            heuristic: impl Fn(&Node, &Node) -> f32,
+        ) -> StdResult<(f32, Vec<NodeIndex>), RouterError> {
+            debug!(
+                "Finding shortest path from {:?} to {:?} with penalties",
+                from.location, to.location
+            );
+
+            let Some(from_index) = self.get_node_index(from) else {
+                return Err(RouterError::InvalidNodesInPath);
+            };
+
+            let Some(to_index) = self.get_node_index(to) else {
+                return Err(RouterError::InvalidNodesInPath);
+            };
+
+            let penalties: HashMap<EdgeIndex, f32> = self
+                .graph
+                .edge_references()
+                .filter_map(|e| {
+                    penalty(self.graph[e.source()], self.graph[e.target()])
+                        .map(|penalty| (e.id(), penalty))
+                })
+                .collect();
+            let graph = EdgeFiltered::from_fn(&self.graph, |e| penalties.contains_key(&e.id()));
+
+            Ok(astar(
+                &graph,
+                from_index,
+                |finish| finish == to_index,
+                |e| (*e.weight()).into_inner() + penalties[&e.id()],
+                |index| heuristic(self.graph[index], to),
+            )
+            .unwrap_or((0.0, Vec::new())))
+        }
+
+        /// Find the shortest path between two nodes with the A* algorithm,
+        /// guided by a heuristic registered with
+        /// [`Router::register_heuristic`] and evaluating every edge with
+        /// `penalty` like [`Router::find_shortest_path_with_penalty`].
+        ///
+        /// # Arguments
+        /// * `from` - The node to start from.
+        /// * `to` - The node to end at.
+        /// * `penalty` - A function that takes the two nodes of an edge and
+        ///   returns the cost added to the edge, or None if the edge can't
+        ///   be used.
+        /// * `heuristic` - The name of the heuristic.
+        ///
+        /// # Returns
+        /// A tuple of the total cost including penalties and the path
+        /// consisting of node indices. The path is only the shortest if the
+        /// heuristic never overestimates the remaining cost, including the
+        /// penalties.
+        ///
+        /// An empty path with a total cost of 0.0 returned if no path
+        /// is found.
+        ///
+        /// # Errors
+        /// * `UnknownHeuristic` - No heuristic is registered with the name.
+        pub fn find_shortest_path_with_heuristic(
+            &self,
+            from: &Node,
+            to: &Node,
+            penalty: impl Fn(&Node, &Node) -> Option<f32>,
+            heuristic: &str,
+        ) -> StdResult<(f32, Vec<NodeIndex>), RouterError> {
+            let Some(heuristic) = self.heuristics.get(heuristic) else {
+                error!("Unknown heuristic: {}", heuristic);
+                return Err(RouterError::UnknownHeuristic);
+            };
+            self.find_path_with_penalty_and_heuristic(from, to, penalty, |node, to| {
+                heuristic(node, to)
+            })
+        }
+
+        /// Register a heuristic for the A* algorithm under a name, replacing
+        /// the heuristic registered under that name if any.
+        ///
+        /// # Arguments
+        /// * `name` - The name to select the heuristic in queries.
+        /// * `heuristic` - A function that takes a node and the node to end
+        ///   at and returns an estimate of the remaining cost.
+        pub fn register_heuristic(
+            &self,
+            name: &str,
+            heuristic: impl Fn(&Node, &Node) -> f32 + Send + Sync + 'static,
+        ) {
+            self.heuristics.register(name, Arc::new(heuristic));
+        }
+
+        /// Get the names of the registered heuristics, sorted.
+        pub fn get_heuristic_names(&self) -> Vec<String> {
+            self.heuristics.names()
+        }
+
+        /// Select landmarks and precompute the costs of the shortest paths
+        /// to and from them, registering the [`LANDMARK_HEURISTIC`] used by
+        /// [`Algorithm::AStarLandmarks`].
+        ///
+        /// The landmark heuristic bounds the remaining cost with the
+        /// triangle inequality over the weights of the graph, so it stays
+        /// tight on sparse corridor graphs where the Haversine distance
+        /// underestimates badly. It never overestimates unless penalties
+        /// make edges cheaper than their weight. The landmarks are selected
+        /// again when a patch is applied.
+        ///
+        /// # Arguments
+        /// * `count` - The number of landmarks, each costing a search over
+        ///   the whole graph in both directions.
+        pub fn with_landmarks(self, count: usize) -> Self {
+            self.preprocess_landmarks(count);
+            self
+        }
+
+        /// Select landmarks and precompute their costs like
+        /// [`Router::with_landmarks`], on a router already shared.
+        pub fn preprocess_landmarks(&self, count: usize) {
+            info!("Preprocessing {} landmarks", count);
+            match self.landmark_count.write() {
+                Ok(mut landmark_count) => *landmark_count = Some(count),
+                Err(_) => error!("Landmark count unavailable"),
+            }
+            // nodes by id, so landmarks are selected deterministically
+            let mut nodes: Vec<(&str, NodeIndex)> = self
+                .node_indices
+                .iter()
+                .map(|(node, &index)| (node.uid.as_str(), index))
+                .collect();
+            nodes.sort();
+
+            // each landmark is the node farthest from the previous ones,
+            // starting with the node farthest from the first node
+            let mut landmarks: Vec<NodeIndex> = vec![];
+            let mut costs: Vec<(HashMap<String, f32>, HashMap<String, f32>)> = vec![];
+            let mut min_costs: HashMap<NodeIndex, f32> = HashMap::new();
+            if let Some(&(_, first)) = nodes.first() {
+                min_costs = self.costs_from(first, Direction::Outgoing);
+            }
+            while landmarks.len() < count.min(nodes.len()) {
+                let Some(&(_, landmark)) = nodes
+                    .iter()
+                    .filter(|(_, index)| !landmarks.contains(index))
+                    .max_by(|(_, a), (_, b)| {
+                        let cost = |index| min_costs.get(index).copied().unwrap_or(f32::INFINITY);
+                        cost(a).total_cmp(&cost(b))
+                    })
+                else {
+                    break;
+                };
+                let from_landmark = self.costs_from(landmark, Direction::Outgoing);
+                let to_landmark = self.costs_from(landmark, Direction::Incoming);
+                if landmarks.is_empty() {
+                    min_costs.clear();
+                }
+                for (&index, &cost) in &from_landmark {
+                    let min_cost = min_costs.entry(index).or_insert(cost);
+                    *min_cost = min_cost.min(cost);
+                }
+                let by_uid = |costs: HashMap<NodeIndex, f32>| {
+                    costs
+                        .into_iter()
+                        .map(|(index, cost)| (self.graph[index].uid.clone(), cost))
+                        .collect::<HashMap<String, f32>>()
+                };
+                landmarks.push(landmark);
+                costs.push((by_uid(from_landmark), by_uid(to_landmark)));
+            }
+            debug!("Landmarks: {:?}", landmarks);
+
+            self.register_heuristic(LANDMARK_HEURISTIC, move |node, to| {
+                costs
+                    .iter()
+                    .flat_map(|(from_landmark, to_landmark)| {
+                        let bound = |costs: &HashMap<String, f32>, a: &Node, b: &Node| {
+                            Some(costs.get(&a.uid)? - costs.get(&b.uid)?)
+                        };
+                        [bound(from_landmark, to, node), bound(to_landmark, node, to)]
+                    })
+                    .flatten()
+                    .fold(0.0, f32::max)
+            });
+        }
+
+        /// Precompute the shortest paths between all pairs of nodes with the
+        /// Floyd–Warshall algorithm, used by [`Algorithm::PrecomputedAllPairs`]
+        /// to look a path up in O(path length).
+        ///
+        /// The precomputation takes O(n³) time and O(n²) memory for n
+        /// nodes, so it suits regional graphs of up to a few hundred
+        /// vertiports. The paths are precomputed again when a patch is
+        /// applied.
+        pub fn with_all_pairs(self) -> Self {
+            self.precompute_all_pairs();
+            self
+        }
+
+        /// Precompute the shortest paths between all pairs of nodes like
+        /// [`Router::with_all_pairs`], on a router already shared.
+        pub fn precompute_all_pairs(&self) {
+            info!(
+                "Precomputing the shortest paths between {} nodes",
+                self.graph.node_count()
+            );
+            // nodes by id, so equal-cost paths are chosen deterministically
+            let mut nodes: Vec<(&str, NodeIndex)> = self
+                .node_indices
+                .iter()
+                .map(|(node, &index)| (node.uid.as_str(), index))
+                .collect();
+            nodes.sort();
+            let indices: Vec<NodeIndex> = nodes.into_iter().map(|(_, index)| index).collect();
+            let positions: HashMap<NodeIndex, usize> = indices
+                .iter()
+                .enumerate()
+                .map(|(position, &index)| (index, position))
+                .collect();
+
+            let count = indices.len();
+            let mut costs = vec![vec![f32::INFINITY; count]; count];
+            let mut next = vec![vec![None; count]; count];
+            for position in 0..count {
+                costs[position][position] = 0.0;
+                next[position][position] = Some(position);
+            }
+            for edge in self.graph.edge_references() {
+                let (from, to) = (positions[&edge.source()], positions[&edge.target()]);
+                let cost = edge.weight().into_inner();
+                if cost < costs[from][to] {
+                    costs[from][to] = cost;
+                    next[from][to] = Some(to);
+                }
+            }
+            for via in 0..count {
+                for from in 0..count {
+                    if costs[from][via] == f32::INFINITY {
+                        continue;
+                    }
+                    for to in 0..count {
+                        let cost = costs[from][via] + costs[via][to];
+                        if cost < costs[from][to] {
+                            costs[from][to] = cost;
+                            next[from][to] = next[from][via];
+                        }
+                    }
+                }
+            }
+
+            match self.all_pairs.write() {
+                Ok(mut all_pairs) => {
+                    *all_pairs = Some(AllPairs {
+                        positions,
+                        indices,
+                        costs,
+                        next,
+                    })
+                }
+                Err(_) => error!("Precomputed paths unavailable"),
+            }
+        }
+
+        /// Check if the shortest paths between all pairs of nodes are
+        /// precomputed, see [`Router::with_all_pairs`].
+        pub fn has_all_pairs(&self) -> bool {
+            self.all_pairs
+                .read()
+                .is_ok_and(|all_pairs| all_pairs.is_some())
+        }
+
+        /// Costs of the shortest paths from a node, or to it with
+        /// [`Direction::Incoming`], to all reachable nodes.
+        fn costs_from(&self, index: NodeIndex, direction: Direction) -> HashMap<NodeIndex, f32> {
+            let mut costs: HashMap<NodeIndex, f32> = HashMap::from([(index, 0.0)]);
+            let mut queue = BinaryHeap::from([Reverse((OrderedFloat(0.0), index))]);
+            while let Some(Reverse((OrderedFloat(cost), index))) = queue.pop() {
+                if costs.get(&index).is_some_and(|best| cost > *best) {
+                    continue;
+                }
+                for edge in self.graph.edges_directed(index, direction) {
+                    let next = match direction {
+                        Direction::Outgoing => edge.target(),
+                        Direction::Incoming => edge.source(),
+                    };
+                    let next_cost = cost + edge.weight().into_inner();
+                    if !costs.get(&next).is_some_and(|best| next_cost >= *best) {
+                        costs.insert(next, next_cost);
+                        queue.push(Reverse((OrderedFloat(next_cost), next)));
+                    }
+                }
+            }
+            costs
+        }
+
+        /// Find the shortest path between two nodes within limits on the
+        /// number of legs and the total duration of the path, evaluating
+        /// every edge with `penalty` like
+        /// [`Router::find_shortest_path_with_penalty`].
+        ///
+        /// The limits prune the partial paths during the search, so a
+        /// longer path within the limits is found when the shortest path
+        /// exceeds them.
+        ///
+        /// # Arguments
+        /// * `from` - The node to start from.
+        /// * `to` - The node to end at.
+        /// * `penalty` - A function that takes the two nodes of an edge and
+        ///   returns the cost added to the edge, or None if the edge can't
+        ///   be used.
+        /// * `limits` - The max legs and duration of the path.
+        /// * `duration` - A function that takes the two nodes of an edge and
+        ///   returns its duration, in the unit of the duration limit.
+        ///
+        /// # Returns
+        /// A tuple of the total cost including penalties and the path
+        /// consisting of node indices.
+        ///
+        /// An empty path with a total cost of 0.0 returned if no path
+        /// within the limits is found.
+        pub fn find_shortest_path_with_limits(
+            &self,
+            from: &Node,
+            to: &Node,
+            penalty: impl Fn(&Node, &Node) -> Option<f32>,
+            limits: PathLimits,
+            duration: impl Fn(&Node, &Node) -> f32,
+        ) -> StdResult<(f32, Vec<NodeIndex>), RouterError> {
+            debug!(
+                "Finding shortest path from {:?} to {:?} within {:?}",
+                from.location, to.location, limits
+            );
+
+            let Some(from_index) = self.get_node_index(from) else {
+                return Err(RouterError::InvalidNodesInPath);
+            };
+
+            let Some(to_index) = self.get_node_index(to) else {
+                return Err(RouterError::InvalidNodesInPath);
+            };
+
+            // labels of partial paths are expanded by increasing cost, a
+            // label is dropped if another path to its node is at most as
+            // costly, long and with as many legs
+            let mut labels = vec![ParetoLabel {
+                costs: [0.0, 0.0, 0.0],
+                index: from_index,
+                previous: None,
+            }];
+            let mut node_labels: HashMap<NodeIndex, Vec<usize>> =
+                HashMap::from([(from_index, vec![0])]);
+            let mut queue = BinaryHeap::from([Reverse((OrderedFloat(0.0), 0))]);
+            let dominates = |a: &[f32; 3], b: &[f32; 3]| a.iter().zip(b).all(|(a, b)| a <= b);
+            while let Some(Reverse((OrderedFloat(cost), label))) = queue.pop() {
+                let ParetoLabel { costs, index, .. } = labels[label];
+                if index == to_index {
+                    let mut path = vec![];
+                    let mut current = Some(label);
+                    while let Some(label) = current {
+                        path.push(labels[label].index);
+                        current = labels[label].previous;
+                    }
+                    path.reverse();
+                    return Ok((cost, path));
+                }
+                let [_, elapsed, legs] = costs;
+                if limits
+                    .max_legs
+                    .is_some_and(|max_legs| legs as usize >= max_legs)
+                {
+                    continue;
+                }
+                for edge in self.graph.edges(index) {
+                    let (source, target) = (self.graph[edge.source()], self.graph[edge.target()]);
+                    let Some(penalty) = penalty(source, target) else {
+                        continue;
+                    };
+                    let next_costs = [
+                        cost + edge.weight().into_inner() + penalty,
+                        elapsed + duration(source, target),
+                        legs + 1.0,
+                    ];
+                    if limits
+                        .max_duration
+                        .is_some_and(|max_duration| next_costs[1] > max_duration)
+                    {
+                        continue;
+                    }
+                    let existing = node_labels.entry(edge.target()).or_default();
+                    if existing
+                        .iter()
+                        .any(|&other| dominates(&labels[other].costs, &next_costs))
+                    {
+                        continue;
+                    }
+                    existing.retain(|&other| !dominates(&next_costs, &labels[other].costs));
+                    let next = labels.len();
+                    labels.push(ParetoLabel {
+                        costs: next_costs,
+                        index: edge.target(),
+                        previous: Some(label),
+                    });
+                    existing.push(next);
+                    queue.push(Reverse((OrderedFloat(next_costs[0]), next)));
+                }
+            }
+
+            Ok((0.0, Vec::new()))
+        }
+
+        /// Find up to `k` alternative paths between two nodes that don't
+        /// share a leg, or an intermediate node with
+        /// [`Disjointness::Node`], so that closing one corridor leaves the
+        /// other paths usable.
+        ///
+        /// Paths are found one after the other, each as the shortest path
+        /// avoiding the legs, in both directions, and the nodes used by the
+        /// previous ones. The first path is the shortest path.
+        ///
+        /// # Arguments
+        /// * `from` - The node to start from.
+        /// * `to` - The node to end at.
+        /// * `k` - The max number of paths.
+        /// * `disjointness` - What the paths must not share.
+        ///
+        /// # Returns
+        /// The total cost and the path consisting of node indices of each
+        /// path, from the shortest. Fewer than `k` paths are returned if
+        /// the graph doesn't have more disjoint paths.
+        pub fn find_disjoint_paths(
+            &self,
+            from: &Node,
+            to: &Node,
+            k: usize,
+            disjointness: Disjointness,
+        ) -> StdResult<Vec<(f32, Vec<NodeIndex>)>, RouterError> {
+            self.find_disjoint_paths_with_penalty(from, to, k, disjointness, |_, _| Some(0.0))
+        }
+
+        /// Find up to `k` alternative paths between two nodes like
+        /// [`Router::find_disjoint_paths`], evaluating every edge with
+        /// `penalty` like [`Router::find_shortest_path_with_penalty`].
+        pub fn find_disjoint_paths_with_penalty(
+            &self,
+            from: &Node,
+            to: &Node,
+            k: usize,
+            disjointness: Disjointness,
+            penalty: impl Fn(&Node, &Node) -> Option<f32>,
+        ) -> StdResult<Vec<(f32, Vec<NodeIndex>)>, RouterError> {
+            debug!(
+                "Finding {} {:?} disjoint paths from {:?} to {:?}",
+                k, disjointness, from.location, to.location
+            );
+
+            let mut used_legs: HashSet<(&Node, &Node)> = HashSet::new();
+            let mut used_nodes: HashSet<&Node> = HashSet::new();
+            let mut paths = vec![];
+            while paths.len() < k {
+                let (cost, path) = self.find_shortest_path_with_penalty(from, to, |a, b| {
+                    let blocked = used_legs.contains(&(a, b))
+                        || used_nodes.contains(a)
+                        || used_nodes.contains(b);
+                    if blocked {
+                        return None;
+                    }
+                    penalty(a, b)
+                })?;
+                if path.is_empty() {
+                    break;
+                }
+                let nodes: Vec<&Node> = path.iter().map(|&index| self.graph[index]).collect();
+                for leg in nodes.windows(2) {
+                    used_legs.insert((leg[0], leg[1]));
+                    used_legs.insert((leg[1], leg[0]));
+                }
+                if let Disjointness::Node = disjointness {
+                    used_nodes.extend(nodes.iter().filter(|node| **node != from && **node != to));
+                }
+                paths.push((cost, path));
+            }
+
+            Ok(paths)
+        }
+
+        /// Find the paths between two nodes that are optimal for several
+        /// criteria at once, the Pareto frontier: no other path is at most
+        /// as costly for every criterion and cheaper for one of them.
+        ///
+        /// The weights of the graph are ignored, only its edges are used.
+        ///
+        /// # Arguments
+        /// * `from` - The node to start from.
+        /// * `to` - The node to end at.
+        /// * `criteria` - A function that takes the two nodes of an edge and
+        ///   returns the non-negative cost of the edge for each criterion,
+        ///   or None if the edge can't be used. Negative and NaN costs
+        ///   count as 0.
+        /// * `max_paths` - Max number of paths returned. A larger frontier
+        ///   is thinned out evenly along the first criterion, keeping the
+        ///   paths optimal for it.
+        ///
+        /// # Returns
+        /// The costs and the path consisting of node indices of each path
+        /// of the frontier, by increasing cost of the first criterion.
+        ///
+        /// An empty frontier is returned if no path is found.
+        pub fn find_pareto_paths<const N: usize>(
+            &self,
+            from: &Node,
+            to: &Node,
+            criteria: impl Fn(&Node, &Node) -> Option<[f32; N]>,
+            max_paths: usize,
+        ) -> StdResult<Vec<ParetoPath<N>>, RouterError> {
+            debug!(
+                "Finding Pareto paths from {:?} to {:?}",
+                from.location, to.location
+            );
+
+            let Some(from_index) = self.get_node_index(from) else {
+                return Err(RouterError::InvalidNodesInPath);
+            };
+
+            let Some(to_index) = self.get_node_index(to) else {
+                return Err(RouterError::InvalidNodesInPath);
+            };
+
+            let dominates = |a: &[f32; N], b: &[f32; N]| a.iter().zip(b).all(|(a, b)| a <= b);
+            let edge_costs: HashMap<EdgeIndex, [f32; N]> = self
+                .graph
+                .edge_references()
+                .filter_map(|e| {
+                    criteria(self.graph[e.source()], self.graph[e.target()])
+                        .map(|costs| (e.id(), costs.map(|cost| cost.max(0.0))))
+                })
+                .collect();
+
+            // labels of partial paths are expanded by increasing sum of
+            // costs, so a label is only dominated by labels expanded before it
+            let mut labels = vec![ParetoLabel {
+                costs: [0.0; N],
+                index: from_index,
+                previous: None,
+            }];
+            let mut dominated = vec![false];
+            let mut node_labels: HashMap<NodeIndex, Vec<usize>> =
+                HashMap::from([(from_index, vec![0])]);
+            let mut frontier: Vec<usize> = vec![];
+            let mut queue = BinaryHeap::from([Reverse((OrderedFloat(0.0), 0))]);
+            while let Some(Reverse((_, label))) = queue.pop() {
+                if dominated[label] {
+                    continue;
+                }
+                let ParetoLabel { costs, index, .. } = labels[label];
+                if index == to_index {
+                    frontier.push(label);
+                    continue;
+                }
+                for edge in self.graph.edges(index) {
+                    let Some(edge_cost) = edge_costs.get(&edge.id()) else {
+                        continue;
+                    };
+                    let mut next_costs = costs;
+                    for (cost, edge_cost) in next_costs.iter_mut().zip(edge_cost) {
+                        *cost += edge_cost;
+                    }
+                    let target = edge.target();
+                    let existing = node_labels.entry(target).or_default();
+                    if frontier
+                        .iter()
+                        .chain(existing.iter())
+                        .any(|&other| dominates(&labels[other].costs, &next_costs))
+                    {
+                        continue;
+                    }
+                    existing.retain(|&other| {
+                        let keep = !dominates(&next_costs, &labels[other].costs);
+                        dominated[other] |= !keep;
+                        keep
+                    });
+                    let next = labels.len();
+                    labels.push(ParetoLabel {
+                        costs: next_costs,
+                        index: target,
+                        previous: Some(label),
+                    });
+                    dominated.push(false);
+                    existing.push(next);
+                    queue.push(Reverse((OrderedFloat(next_costs.iter().sum()), next)));
+                }
+            }
+
+            frontier.sort_by_key(|&label| labels[label].costs.map(OrderedFloat));
+            if frontier.len() > max_paths {
+                debug!("Thinning out a Pareto frontier of {} paths", frontier.len());
+                let last = frontier.len() - 1;
+                frontier = match max_paths {
+                    0 => vec![],
+                    1 => vec![frontier[0]],
+                    _ => (0..max_paths)
+                        .map(|i| frontier[i * last / (max_paths - 1)])
+                        .collect(),
+                };
+            }
+
+            Ok(frontier
+                .into_iter()
+                .map(|label| {
+                    let mut path = vec![];
+                    let mut current = Some(label);
+                    while let Some(label) = current {
+                        path.push(labels[label].index);
+                        current = labels[label].previous;
+                    }
+                    path.reverse();
+                    (labels[label].costs, path)
+                })
+                .collect())
+        }
+
+        /// Find the shortest paths from a location outside of the graph to
+        /// all reachable nodes.
+        ///
+        /// The location acts as a synthetic origin node connected to every
+        /// node within `constraint` kilometers, with the Haversine distance
+        /// as the cost of these edges.
+        ///
+        /// # Arguments
+        /// * `origin` - The location to start from.
+        /// * `constraint` - Max distance to the first node of a path.
+        ///
+        /// # Returns
+        /// The total cost and the path consisting of node indices for
+        /// every reachable node. Paths don't include the origin.
+        pub fn find_shortest_paths_from_location(
+            &self,
+            origin: &Location,
+            constraint: f32,
+        ) -> HashMap<NodeIndex, (f32, Vec<NodeIndex>)> {
+            self.find_shortest_paths_from_location_with_penalty(origin, constraint, |_, _| {
+                Some(0.0)
+            })
+        }
+
+        /// Find the shortest paths from a location outside of the graph to
+        /// all reachable nodes like
+        /// [`Router::find_shortest_paths_from_location`], evaluating every
+        /// edge with `penalty`.
+        ///
+        /// # Arguments
+        /// * `origin` - The location to start from.
+        /// * `constraint` - Max distance to the first node of a path.
+        /// * `penalty` - A function that takes the locations at the ends of
+        ///   an edge, the origin for the first edge of a path, and returns
+        ///   the cost added to the edge, or None if the edge can't be used.
+        pub fn find_shortest_paths_from_location_with_penalty(
+            &self,
+            origin: &Location,
+            constraint: f32,
+            penalty: impl Fn(&Location, &Location) -> Option<f32>,
+        ) -> HashMap<NodeIndex, (f32, Vec<NodeIndex>)> {
+            debug!("Finding shortest paths from {:?}", origin);
+            let mut costs: HashMap<NodeIndex, f32> = HashMap::new();
+            let mut previous: HashMap<NodeIndex, NodeIndex> = HashMap::new();
+            let mut queue = BinaryHeap::new();
+            let nodes = self.node_indices.iter().collect::<Vec<_>>();
+            let distances = haversine::with_earth_radius(self.earth_radius, || {
+                haversine::distance_batch_by(origin, &nodes, |(node, _)| &node.location)
+            });
+            for ((node, &index), distance) in nodes.into_iter().zip(distances) {
+                if distance > constraint {
+                    continue;
+                }
+                let Some(extra) = penalty(origin, &node.location) else {
+                    continue;
+                };
+                let cost = distance + extra;
+                costs.insert(index, cost);
+                queue.push(Reverse((OrderedFloat(cost), index)));
+            }
+
+            while let Some(Reverse((OrderedFloat(cost), index))) = queue.pop() {
+                if costs.get(&index).is_some_and(|best| cost > *best) {
+                    continue;
+                }
+                for edge in self.graph.edges(index) {
+                    let Some(extra) = penalty(
+                        &self.graph[index].location,
+                        &self.graph[edge.target()].location,
+                    ) else {
+                        continue;
+                    };
+                    let next_cost = cost + edge.weight().into_inner() + extra;
+                    if !costs
+                        .get(&edge.target())
+                        .is_some_and(|best| next_cost >= *best)
+                    {
+                        costs.insert(edge.target(), next_cost);
+                        previous.insert(edge.target(), index);
+                        queue.push(Reverse((OrderedFloat(next_cost), edge.target())));
+                    }
+                }
+            }
+
+            costs
+                .iter()
+                .map(|(&index, &cost)| {
+                    let mut path = vec![index];
+                    let mut current = index;
+                    while let Some(&prior) = previous.get(&current) {
+                        path.push(prior);
+                        current = prior;
+                    }
+                    path.reverse();
+                    (index, (cost, path))
+                })
+                .collect()
+        }
+
         /// Compute the total Haversine distance of a path.
         ///
         /// # Arguments
@@ -234,7 +1345,11 @@ pub mod engine {
                     return Err(RouterError::InvalidNodesInPath);
                 };
 
-                total_distance += haversine::distance(&node_from.location, &node_to.location);
+                total_distance += haversine::distance_with(
+                    self.earth_radius,
+                    &node_from.location,
+                    &node_to.location,
+                );
             }
             debug!("Total distance: {}", total_distance);
             Ok(total_distance)
@@ -247,11 +1362,159 @@ pub mod engine {
             self.graph.node_count()
         }
 
-        /// Get all the edges in the graph.
-        pub fn get_edges<'a>(&self) -> &'a Vec<Edge> {
-            info!("Getting all edges");
-            debug!("Edges: {:?}", self.edges);
-            &self.edges
+        /// Iterate over the edges of the graph, in the order they were
+        /// added, with the weights of the graph.
+        pub fn edges(&self) -> impl Iterator<Item = EdgeView<'_>> {
+            self.graph.edge_references().map(|e| {
+                let (from, to) = (self.graph[e.source()], self.graph[e.target()]);
+                EdgeView {
+                    from,
+                    to,
+                    from_id: &from.uid,
+                    to_id: &to.uid,
+                    cost: e.weight().into_inner(),
+                }
+            })
+        }
+    }
+    impl<'a> Router<'a> {
+        /// Get a node of the graph by its id, so queries can be made with
+        /// nodes borrowed from the router.
+        pub fn get_node_by_uid(&self, uid: &str) -> Option<&'a Node> {
+            self.node_indices
+                .keys()
+                .find(|node| node.uid == uid)
+                .copied()
+        }
+
+        /// Get the version of the graph, 0 when built and then the version
+        /// of the last patch applied.
+        pub fn get_version(&self) -> u64 {
+            self.version
+        }
+
+        /// Apply the changes of a patch to the graph, keeping the indices
+        /// of the nodes left unchanged, and notify the
+        /// [subscribers](crate::notifications) of the changes.
+        ///
+        /// The patch is checked before the graph is changed, so an invalid
+        /// patch leaves the graph as it was.
+        ///
+        /// # Arguments
+        /// * `patch` - The changes from the current version of the graph.
+        ///   The router refers to the nodes of the patch, so it must live
+        ///   as long as the router.
+        ///
+        /// # Errors
+        /// * `InvalidPatch` - The patch is made from another version, or
+        ///   refers to nodes missing from the patched graph.
+        pub fn apply_patch(&mut self, patch: &'a GraphPatch) -> StdResult<(), RouterError> {
+            info!(
+                "Applying graph patch from version {} to {}",
+                patch.from_version, patch.to_version
+            );
+            if patch.from_version != self.version {
+                error!(
+                    "Graph patch from version {} doesn't apply to version {}",
+                    patch.from_version, self.version
+                );
+                return Err(RouterError::InvalidPatch);
+            }
+            let mut indices: HashMap<&str, NodeIndex> = self
+                .node_indices
+                .iter()
+                .map(|(node, &index)| (node.uid.as_str(), index))
+                .collect();
+            let mut patched_ids: HashSet<&str> = indices.keys().copied().collect();
+            for id in &patch.removed_node_ids {
+                patched_ids.remove(id.as_str());
+            }
+            let valid = patch
+                .removed_node_ids
+                .iter()
+                .chain(patch.changed_nodes.iter().map(|node| &node.uid))
+                .all(|id| indices.contains_key(id.as_str()))
+                && patch
+                    .added_nodes
+                    .iter()
+                    .all(|node| patched_ids.insert(node.uid.as_str()))
+                && patch
+                    .changed_edges
+                    .iter()
+                    .map(|edge| (&edge.from_id, &edge.to_id))
+                    .chain(patch.removed_edges.iter().map(|(from, to)| (from, to)))
+                    .all(|(from, to)| {
+                        patched_ids.contains(from.as_str()) && patched_ids.contains(to.as_str())
+                    });
+            if !valid {
+                error!("Graph patch refers to missing nodes");
+                return Err(RouterError::InvalidPatch);
+            }
+
+            for id in &patch.removed_node_ids {
+                if let Some(index) = indices.remove(id.as_str()) {
+                    self.graph.remove_node(index);
+                }
+            }
+            let mut status_changes = vec![];
+            for node in &patch.changed_nodes {
+                let index = indices[node.uid.as_str()];
+                if self.graph[index].status != node.status {
+                    status_changes.push(NodeStatusChange {
+                        node_id: node.uid.clone(),
+                        previous: self.graph[index].status,
+                        current: node.status,
+                    });
+                }
+                self.graph[index] = node;
+            }
+            for node in &patch.added_nodes {
+                indices.insert(node.uid.as_str(), self.graph.add_node(node));
+            }
+            for (from, to) in &patch.removed_edges {
+                if let Some(edge) = self
+                    .graph
+                    .find_edge(indices[from.as_str()], indices[to.as_str()])
+                {
+                    self.graph.remove_edge(edge);
+                }
+            }
+            for edge in &patch.changed_edges {
+                self.graph.update_edge(
+                    indices[edge.from_id.as_str()],
+                    indices[edge.to_id.as_str()],
+                    OrderedFloat(edge.cost),
+                );
+            }
+
+            self.node_indices = self
+                .graph
+                .node_indices()
+                .map(|index| (self.graph[index], index))
+                .collect();
+            self.version = patch.to_version;
+            if self.has_all_pairs() {
+                self.precompute_all_pairs();
+            }
+            // the costs to and from the landmarks may have dropped
+            let landmark_count = self.landmark_count.read().ok().and_then(|count| *count);
+            if let Some(count) = landmark_count {
+                self.preprocess_landmarks(count);
+            }
+            debug!(
+                "Graph patched to version {} with {} nodes and {} edges",
+                self.version,
+                self.graph.node_count(),
+                self.graph.edge_count()
+            );
+            notify_graph_changed(&GraphChange {
+                version: self.version,
+                affected_node_ids: patch.affected_node_ids(),
+            });
+            for change in &status_changes {
+                notify_node_status_changed(change);
+            }
+            Ok(())
         }
     }
 }
@@ -259,17 +1522,24 @@ pub mod engine {
 #[cfg(test)]
 mod router_tests {
     use crate::{
+        distance::Distance,
         location::Location,
         node::{AsNode, Node},
-        router::engine::Algorithm,
+        router::engine::{
+            Algorithm, Disjointness, EdgeView, PathLimits, HAVERSINE_HEURISTIC, LANDMARK_HEURISTIC,
+            ZERO_HEURISTIC,
+        },
         types::router::engine::Router,
         utils::{
             generator::{generate_nodes, generate_nodes_near},
-            haversine,
+            graph_patch::{EdgeRecord, GraphPatch},
+            haversine::{self, EarthRadius},
         },
     };
 
     use ordered_float::OrderedFloat;
+    use petgraph::graph::NodeIndex;
+    use std::collections::HashSet;
 
     const SAN_FRANCISCO: Location = Location {
         latitude: OrderedFloat(37.7749),
@@ -280,7 +1550,7 @@ mod router_tests {
 
     #[test]
     fn test_correct_node_count() {
-        let nodes = generate_nodes_near(&SAN_FRANCISCO, 10000.0, CAPACITY);
+        let nodes = generate_nodes_near(&SAN_FRANCISCO, Distance::from_km(10000.0), CAPACITY);
 
         let router = Router::new(
             &nodes,
@@ -295,7 +1565,7 @@ mod router_tests {
     /// The graph has no edges.
     #[test]
     fn test_shortest_path_disconnected_graph() {
-        let nodes = generate_nodes_near(&SAN_FRANCISCO, 10000.0, CAPACITY);
+        let nodes = generate_nodes_near(&SAN_FRANCISCO, Distance::from_km(10000.0), CAPACITY);
 
         let router = Router::new(
             &nodes,
@@ -335,50 +1605,10 @@ mod router_tests {
     #[test]
     fn test_shortest_path_has_path() {
         let nodes = vec![
-            Node {
-                uid: "1".to_string(),
-                location: Location {
-                    latitude: OrderedFloat(37.777843),
-                    longitude: OrderedFloat(-122.468207),
-                    altitude_meters: OrderedFloat(0.0),
-                },
-                forward_to: None,
-                status: crate::status::Status::Ok,
-                schedule: None,
-            },
-            Node {
-                uid: "2".to_string(),
-                location: Location {
-                    latitude: OrderedFloat(37.778339),
-                    longitude: OrderedFloat(-122.460395),
-                    altitude_meters: OrderedFloat(0.0),
-                },
-                forward_to: None,
-                status: crate::status::Status::Ok,
-                schedule: None,
-            },
-            Node {
-                uid: "3".to_string(),
-                location: Location {
-                    latitude: OrderedFloat(37.780596),
-                    longitude: OrderedFloat(-122.434904),
-                    altitude_meters: OrderedFloat(0.0),
-                },
-                forward_to: None,
-                status: crate::status::Status::Ok,
-                schedule: None,
-            },
-            Node {
-                uid: "4".to_string(),
-                location: Location {
-                    latitude: OrderedFloat(37.774397),
-                    longitude: OrderedFloat(-122.445366),
-                    altitude_meters: OrderedFloat(0.0),
-                },
-                forward_to: None,
-                status: crate::status::Status::Ok,
-                schedule: None,
-            },
+            Node::new("1", 37.777843, -122.468207),
+            Node::new("2", 37.778339, -122.460395),
+            Node::new("3", 37.780596, -122.434904),
+            Node::new("4", 37.774397, -122.445366),
         ];
 
         let router = Router::new(
@@ -437,50 +1667,10 @@ mod router_tests {
     #[test]
     fn test_shortest_path_no_path() {
         let nodes = vec![
-            Node {
-                uid: "1".to_string(),
-                location: Location {
-                    latitude: OrderedFloat(37.777843),
-                    longitude: OrderedFloat(-122.468207),
-                    altitude_meters: OrderedFloat(0.0),
-                },
-                forward_to: None,
-                status: crate::status::Status::Ok,
-                schedule: None,
-            },
-            Node {
-                uid: "2".to_string(),
-                location: Location {
-                    latitude: OrderedFloat(37.778339),
-                    longitude: OrderedFloat(-122.460395),
-                    altitude_meters: OrderedFloat(0.0),
-                },
-                forward_to: None,
-                status: crate::status::Status::Ok,
-                schedule: None,
-            },
-            Node {
-                uid: "3".to_string(),
-                location: Location {
-                    latitude: OrderedFloat(37.780596),
-                    longitude: OrderedFloat(-122.434904),
-                    altitude_meters: OrderedFloat(0.0),
-                },
-                forward_to: None,
-                status: crate::status::Status::Ok,
-                schedule: None,
-            },
-            Node {
-                uid: "4".to_string(),
-                location: Location {
-                    latitude: OrderedFloat(40.738820),
-                    longitude: OrderedFloat(-73.990440),
-                    altitude_meters: OrderedFloat(0.0),
-                },
-                forward_to: None,
-                status: crate::status::Status::Ok,
-                schedule: None,
-            },
+            Node::new("1", 37.777843, -122.468207),
+            Node::new("2", 37.778339, -122.460395),
+            Node::new("3", 37.780596, -122.434904),
+            Node::new("4", 40.738820, -73.990440),
         ];
 
         let router = Router::new(
@@ -512,63 +1702,13 @@ mod router_tests {
     #[test]
     fn test_invalid_node_shortest_path() {
         let nodes = vec![
-            Node {
-                uid: "1".to_string(),
-                location: Location {
-                    latitude: OrderedFloat(37.777843),
-                    longitude: OrderedFloat(-122.468207),
-                    altitude_meters: OrderedFloat(0.0),
-                },
-                forward_to: None,
-                status: crate::status::Status::Ok,
-                schedule: None,
-            },
-            Node {
-                uid: "2".to_string(),
-                location: Location {
-                    latitude: OrderedFloat(37.778339),
-                    longitude: OrderedFloat(-122.460395),
-                    altitude_meters: OrderedFloat(0.0),
-                },
-                forward_to: None,
-                status: crate::status::Status::Ok,
-                schedule: None,
-            },
-            Node {
-                uid: "3".to_string(),
-                location: Location {
-                    latitude: OrderedFloat(37.780596),
-                    longitude: OrderedFloat(-122.434904),
-                    altitude_meters: OrderedFloat(0.0),
-                },
-                forward_to: None,
-                status: crate::status::Status::Ok,
-                schedule: None,
-            },
-            Node {
-                uid: "4".to_string(),
-                location: Location {
-                    latitude: OrderedFloat(40.738820),
-                    longitude: OrderedFloat(-73.990440),
-                    altitude_meters: OrderedFloat(0.0),
-                },
-                forward_to: None,
-                status: crate::status::Status::Ok,
-                schedule: None,
-            },
+            Node::new("1", 37.777843, -122.468207),
+            Node::new("2", 37.778339, -122.460395),
+            Node::new("3", 37.780596, -122.434904),
+            Node::new("4", 40.738820, -73.990440),
         ];
 
-        let not_in_graph_node = Node {
-            uid: "5".to_string(),
-            location: Location {
-                latitude: OrderedFloat(40.738820),
-                longitude: OrderedFloat(-73.990440),
-                altitude_meters: OrderedFloat(0.0),
-            },
-            forward_to: None,
-            status: crate::status::Status::Ok,
-            schedule: None,
-        };
+        let not_in_graph_node = Node::new("5", 40.738820, -73.990440);
 
         let router = Router::new(
             &nodes,
@@ -589,50 +1729,10 @@ mod router_tests {
     #[test]
     fn test_get_edges() {
         let nodes = vec![
-            Node {
-                uid: "1".to_string(),
-                location: Location {
-                    latitude: OrderedFloat(37.777843),
-                    longitude: OrderedFloat(-122.468207),
-                    altitude_meters: OrderedFloat(0.0),
-                },
-                forward_to: None,
-                status: crate::status::Status::Ok,
-                schedule: None,
-            },
-            Node {
-                uid: "2".to_string(),
-                location: Location {
-                    latitude: OrderedFloat(37.778339),
-                    longitude: OrderedFloat(-122.460395),
-                    altitude_meters: OrderedFloat(0.0),
-                },
-                forward_to: None,
-                status: crate::status::Status::Ok,
-                schedule: None,
-            },
-            Node {
-                uid: "3".to_string(),
-                location: Location {
-                    latitude: OrderedFloat(37.780596),
-                    longitude: OrderedFloat(-122.434904),
-                    altitude_meters: OrderedFloat(0.0),
-                },
-                forward_to: None,
-                status: crate::status::Status::Ok,
-                schedule: None,
-            },
-            Node {
-                uid: "4".to_string(),
-                location: Location {
-                    latitude: OrderedFloat(40.738820),
-                    longitude: OrderedFloat(-73.990440),
-                    altitude_meters: OrderedFloat(0.0),
-                },
-                forward_to: None,
-                status: crate::status::Status::Ok,
-                schedule: None,
-            },
+            Node::new("1", 37.777843, -122.468207),
+            Node::new("2", 37.778339, -122.460395),
+            Node::new("3", 37.780596, -122.434904),
+            Node::new("4", 40.738820, -73.990440),
         ];
 
         let router = Router::new(
@@ -642,10 +1742,16 @@ mod router_tests {
             |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
         );
 
-        let edges = router.get_edges();
+        let edges: Vec<EdgeView> = router.edges().collect();
         assert_eq!(edges.len(), 12);
         assert_eq!(edges[0].to.get_uid(), "2");
         assert_eq!(edges[1].to.get_uid(), "3");
+        assert_eq!(edges[0].from_id, "1");
+        assert_eq!(edges[1].to_id, "3");
+        assert_eq!(
+            edges[0].cost,
+            haversine::distance(&nodes[0].location, &nodes[1].location)
+        );
     }
 
     /// Test get_total_distance
@@ -677,4 +1783,550 @@ mod router_tests {
         path.append(&mut invalid_path);
         assert_eq!(router.get_total_distance(&path).is_ok(), false);
     }
+
+    /// Find the shortest paths from a location outside of the graph.
+    ///
+    /// The nodes lie on the equator about 55.6 kilometers apart, so
+    /// only the first node is within reach of the origin.
+    #[test]
+    fn test_shortest_paths_from_location() {
+        let nodes: Vec<Node> = (0..3)
+            .map(|i| Node::new(i.to_string(), 0.0, i as f32 * 0.5))
+            .collect();
+
+        let router = Router::new(
+            &nodes,
+            60.0,
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+        );
+
+        let origin = Location {
+            latitude: OrderedFloat(0.0),
+            longitude: OrderedFloat(-0.1),
+            altitude_meters: OrderedFloat(0.0),
+        };
+        let paths = router.find_shortest_paths_from_location(&origin, 60.0);
+        assert_eq!(paths.len(), 3);
+
+        let indices: Vec<_> = nodes
+            .iter()
+            .map(|node| router.get_node_index(node).unwrap())
+            .collect();
+        let (cost, path) = &paths[&indices[2]];
+        assert_eq!(path, &indices);
+        let expected = haversine::distance(&origin, &nodes[0].location)
+            + router.get_total_distance(&indices).unwrap();
+        assert!((cost - expected).abs() < 0.01);
+
+        assert!(router
+            .find_shortest_paths_from_location(&origin, 5.0)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_shortest_path_with_penalty() {
+        let nodes: Vec<Node> = [(0.0, 0.0), (0.05, 0.25), (0.0, 0.5)]
+            .iter()
+            .enumerate()
+            .map(|(i, (latitude, longitude))| Node::new(i.to_string(), *latitude, *longitude))
+            .collect();
+
+        let router = Router::new(
+            &nodes,
+            100.0,
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+        );
+        let indices: Vec<_> = nodes
+            .iter()
+            .map(|node| router.get_node_index(node).unwrap())
+            .collect();
+
+        let (cost, path) = router
+            .find_shortest_path_with_penalty(&nodes[0], &nodes[2], |_, _| Some(0.0))
+            .unwrap();
+        assert_eq!(path, vec![indices[0], indices[2]]);
+        let direct = haversine::distance(&nodes[0].location, &nodes[2].location);
+        assert!((cost - direct).abs() < 0.01);
+
+        // the direct edge is blocked
+        let (_, path) = router
+            .find_shortest_path_with_penalty(&nodes[0], &nodes[2], |from, to| {
+                (from.uid == "1" || to.uid == "1").then_some(1.0)
+            })
+            .unwrap();
+        assert_eq!(path, indices);
+
+        let (cost, path) = router
+            .find_shortest_path_with_penalty(&nodes[0], &nodes[2], |_, _| None)
+            .unwrap();
+        assert_eq!(cost, 0.0);
+        assert!(path.is_empty());
+    }
+
+    #[test]
+    fn test_pareto_paths() {
+        let nodes: Vec<Node> = [(0.0, 0.0), (0.05, 0.25), (0.0, 0.5), (-0.05, 0.25)]
+            .iter()
+            .enumerate()
+            .map(|(i, (latitude, longitude))| Node::new(i.to_string(), *latitude, *longitude))
+            .collect();
+
+        let router = Router::new(
+            &nodes,
+            100.0,
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+        );
+        let indices: Vec<_> = nodes
+            .iter()
+            .map(|node| router.get_node_index(node).unwrap())
+            .collect();
+        // the direct edge overflies a risky area, the northern detour is safer
+        // than the southern one
+        let criteria = |from: &Node, to: &Node| {
+            let risk = match (from.uid.as_str(), to.uid.as_str()) {
+                ("0", "2") | ("2", "0") => 10.0,
+                ("1", _) | (_, "1") => 1.0,
+                _ => 5.0,
+            };
+            Some([haversine::distance(&from.location, &to.location), risk])
+        };
+
+        let frontier = router
+            .find_pareto_paths(&nodes[0], &nodes[2], criteria, 5)
+            .unwrap();
+        assert_eq!(frontier.len(), 2);
+        assert_eq!(frontier[0].1, vec![indices[0], indices[2]]);
+        assert_eq!(frontier[0].0[1], 10.0);
+        assert_eq!(frontier[1].1, vec![indices[0], indices[1], indices[2]]);
+        assert_eq!(frontier[1].0[1], 2.0);
+        assert!(frontier[0].0[0] < frontier[1].0[0]);
+
+        // the limit keeps the path optimal for the first criterion
+        let frontier = router
+            .find_pareto_paths(&nodes[0], &nodes[2], criteria, 1)
+            .unwrap();
+        assert_eq!(frontier.len(), 1);
+        assert_eq!(frontier[0].1, vec![indices[0], indices[2]]);
+
+        let frontier = router
+            .find_pareto_paths(&nodes[0], &nodes[2], |_, _| None::<[f32; 2]>, 5)
+            .unwrap();
+        assert!(frontier.is_empty());
+
+        // unknown and infinite costs are still ordered
+        let frontier = router
+            .find_pareto_paths(
+                &nodes[0],
+                &nodes[2],
+                |from, to| match (from.uid.as_str(), to.uid.as_str()) {
+                    ("0", "2") => Some([f32::NAN, f32::INFINITY]),
+                    ("0", "1") | ("1", "2") => Some([f32::INFINITY, 1.0]),
+                    _ => Some([f32::INFINITY, f32::NAN]),
+                },
+                5,
+            )
+            .unwrap();
+        assert_eq!(frontier[0].1, vec![indices[0], indices[2]]);
+        assert!(frontier
+            .iter()
+            .all(|(costs, _)| costs.iter().all(|cost| !cost.is_nan())));
+    }
+
+    #[test]
+    fn test_disjoint_paths() {
+        // A to C through the hub H, or around it through B and D
+        let nodes: Vec<Node> = [
+            ("A", 0.0, 0.0),
+            ("H", 0.0, 0.25),
+            ("C", 0.0, 0.5),
+            ("B", 0.1, 0.1),
+            ("D", 0.1, 0.4),
+        ]
+        .iter()
+        .map(|(uid, latitude, longitude)| Node::new(*uid, *latitude, *longitude))
+        .collect();
+
+        let router = Router::new(
+            &nodes,
+            0.5,
+            |from, to| {
+                let mut leg = [from.get_uid(), to.get_uid()];
+                leg.sort();
+                match [leg[0].as_str(), leg[1].as_str()] {
+                    ["A", "H"] | ["C", "H"] | ["A", "B"] | ["B", "H"] | ["D", "H"] | ["C", "D"] => {
+                        0.0
+                    }
+                    _ => 1.0,
+                }
+            },
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+        );
+        let index = |i: usize| router.get_node_index(&nodes[i]).unwrap();
+
+        let paths = router
+            .find_disjoint_paths(&nodes[0], &nodes[2], 3, Disjointness::Edge)
+            .unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].1, vec![index(0), index(1), index(2)]);
+        assert_eq!(
+            paths[1].1,
+            vec![index(0), index(3), index(1), index(4), index(2)]
+        );
+        assert!(paths[0].0 < paths[1].0);
+
+        // the detour lands at the hub too
+        let paths = router
+            .find_disjoint_paths(&nodes[0], &nodes[2], 3, Disjointness::Node)
+            .unwrap();
+        assert_eq!(paths.len(), 1);
+
+        let paths = router
+            .find_disjoint_paths(&nodes[0], &nodes[2], 0, Disjointness::Edge)
+            .unwrap();
+        assert!(paths.is_empty());
+    }
+
+    #[test]
+    fn test_shortest_path_with_limits() {
+        let nodes: Vec<Node> = [(0.0, 0.0), (0.0, 0.25), (0.0, 0.5)]
+            .iter()
+            .enumerate()
+            .map(|(i, (latitude, longitude))| Node::new(i.to_string(), *latitude, *longitude))
+            .collect();
+
+        let router = Router::new(
+            &nodes,
+            100.0,
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+        );
+        let indices: Vec<_> = nodes
+            .iter()
+            .map(|node| router.get_node_index(node).unwrap())
+            .collect();
+        // the direct edge is penalized, each leg lasts 10 plus its distance
+        let penalty = |from: &Node, to: &Node| {
+            Some(if from.uid == "0" && to.uid == "2" {
+                100.0
+            } else {
+                0.0
+            })
+        };
+        let duration =
+            |from: &Node, to: &Node| 10.0 + haversine::distance(&from.location, &to.location);
+        let find = |limits: PathLimits| {
+            router
+                .find_shortest_path_with_limits(&nodes[0], &nodes[2], penalty, limits, duration)
+                .unwrap()
+        };
+
+        let (cost, path) = find(PathLimits::default());
+        assert_eq!(path, indices);
+        let (expected_cost, _) = router
+            .find_shortest_path_with_penalty(&nodes[0], &nodes[2], penalty)
+            .unwrap();
+        assert!((cost - expected_cost).abs() < 0.01);
+
+        let (_, path) = find(PathLimits {
+            max_legs: Some(1),
+            max_duration: None,
+        });
+        assert_eq!(path, vec![indices[0], indices[2]]);
+
+        // the stop in the middle takes 10 more
+        let direct = 10.0 + haversine::distance(&nodes[0].location, &nodes[2].location);
+        let (_, path) = find(PathLimits {
+            max_legs: None,
+            max_duration: Some(direct + 5.0),
+        });
+        assert_eq!(path, vec![indices[0], indices[2]]);
+
+        let (cost, path) = find(PathLimits {
+            max_legs: Some(2),
+            max_duration: Some(direct - 5.0),
+        });
+        assert_eq!(cost, 0.0);
+        assert!(path.is_empty());
+    }
+
+    #[test]
+    fn test_shortest_path_with_heuristic() {
+        let nodes = generate_nodes_near(&SAN_FRANCISCO, Distance::from_km(50.0), 100);
+        let router = Router::new(
+            &nodes,
+            20.0,
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+        );
+        assert_eq!(router.get_heuristic_names(), vec!["haversine", "zero"]);
+
+        let (expected_cost, _) = router
+            .find_shortest_path_with_penalty(&nodes[0], &nodes[1], |_, _| Some(0.0))
+            .unwrap();
+        for heuristic in [ZERO_HEURISTIC, HAVERSINE_HEURISTIC] {
+            let (cost, _) = router
+                .find_shortest_path_with_heuristic(
+                    &nodes[0],
+                    &nodes[1],
+                    |_, _| Some(0.0),
+                    heuristic,
+                )
+                .unwrap();
+            assert!((cost - expected_cost).abs() < 0.01);
+        }
+
+        assert!(router
+            .find_shortest_path_with_heuristic(&nodes[0], &nodes[1], |_, _| Some(0.0), "custom")
+            .is_err());
+        router.register_heuristic("custom", |node, to| {
+            0.5 * haversine::distance(&node.location, &to.location)
+        });
+        assert_eq!(
+            router.get_heuristic_names(),
+            vec!["custom", "haversine", "zero"]
+        );
+        let (cost, _) = router
+            .find_shortest_path_with_heuristic(&nodes[0], &nodes[1], |_, _| Some(0.0), "custom")
+            .unwrap();
+        assert!((cost - expected_cost).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_shortest_path_with_landmarks() {
+        let nodes = generate_nodes_near(&SAN_FRANCISCO, Distance::from_km(50.0), 150);
+        let router = Router::new(
+            &nodes,
+            15.0,
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+        );
+        // without landmarks, no heuristic is used
+        let (cost, _) = router
+            .find_shortest_path(&nodes[0], &nodes[1], Algorithm::AStarLandmarks, None)
+            .unwrap();
+        let (expected_cost, _) = router
+            .find_shortest_path(&nodes[0], &nodes[1], Algorithm::Dijkstra, None)
+            .unwrap();
+        assert_eq!(cost, expected_cost);
+
+        let router = router.with_landmarks(4);
+        assert!(router
+            .get_heuristic_names()
+            .contains(&LANDMARK_HEURISTIC.to_string()));
+        for pair in nodes.windows(2).take(30) {
+            let (expected_cost, _) = router
+                .find_shortest_path(&pair[0], &pair[1], Algorithm::Dijkstra, None)
+                .unwrap();
+            let (cost, _) = router
+                .find_shortest_path(&pair[0], &pair[1], Algorithm::AStarLandmarks, None)
+                .unwrap();
+            assert!((cost - expected_cost).abs() < 0.01);
+            // the bound never overestimates
+            let heuristic = router.heuristics.get(LANDMARK_HEURISTIC).unwrap();
+            if !(expected_cost == 0.0 && pair[0] != pair[1]) {
+                assert!(heuristic(&pair[0], &pair[1]) <= expected_cost + 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn test_landmarks_preprocessed_again_on_patch() {
+        // B, A, E and D 0.1° apart along the equator, connected in a line
+        let nodes = vec![
+            Node::new("A", 0.0, 0.0),
+            Node::new("B", 0.0, -0.1),
+            Node::new("D", 0.0, 0.2),
+            Node::new("E", 0.0, 0.1),
+        ];
+        // a shortcut from B to D, far cheaper than the 3 legs from B to D
+        let patch = GraphPatch {
+            from_version: 0,
+            to_version: 1,
+            changed_edges: vec![EdgeRecord {
+                from_id: "B".to_string(),
+                to_id: "D".to_string(),
+                cost: 1.0,
+            }],
+            ..Default::default()
+        };
+        let distance = |from: &dyn AsNode, to: &dyn AsNode| {
+            haversine::distance(&from.as_node().location, &to.as_node().location)
+        };
+        let mut router = Router::new(&nodes, 12.0, distance, distance).with_landmarks(4);
+        router.apply_patch(&patch).unwrap();
+
+        let (expected_cost, expected_path) = router
+            .find_shortest_path(&nodes[0], &nodes[2], Algorithm::Dijkstra, None)
+            .unwrap();
+        assert_eq!(expected_path.len(), 3);
+        let (cost, path) = router
+            .find_shortest_path(&nodes[0], &nodes[2], Algorithm::AStarLandmarks, None)
+            .unwrap();
+        assert!((cost - expected_cost).abs() < 0.01);
+        assert_eq!(path, expected_path);
+    }
+
+    #[test]
+    fn test_bidirectional_dijkstra_matches_dijkstra() {
+        let nodes = generate_nodes_near(&SAN_FRANCISCO, Distance::from_km(50.0), 150);
+        let router = Router::new(
+            &nodes,
+            15.0,
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+        );
+        for pair in nodes.windows(2).take(40) {
+            let (expected_cost, expected_path) = router
+                .find_shortest_path(&pair[0], &pair[1], Algorithm::Dijkstra, None)
+                .unwrap();
+            let (cost, path) = router
+                .find_shortest_path(&pair[0], &pair[1], Algorithm::BidirectionalDijkstra, None)
+                .unwrap();
+            assert!((cost - expected_cost).abs() < 0.01);
+            assert_eq!(path.is_empty(), expected_path.is_empty());
+            if !path.is_empty() {
+                assert_eq!(path.first(), router.get_node_index(&pair[0]).as_ref());
+                assert_eq!(path.last(), router.get_node_index(&pair[1]).as_ref());
+                let length = router.get_total_distance(&path).unwrap();
+                assert!((length - cost).abs() < 0.01);
+            }
+        }
+
+        let (cost, path) = router
+            .find_shortest_path(&nodes[0], &nodes[0], Algorithm::BidirectionalDijkstra, None)
+            .unwrap();
+        assert_eq!(cost, 0.0);
+        assert_eq!(path, vec![router.get_node_index(&nodes[0]).unwrap()]);
+    }
+
+    #[test]
+    fn test_precomputed_all_pairs_matches_dijkstra() {
+        let nodes = generate_nodes_near(&SAN_FRANCISCO, Distance::from_km(50.0), 60);
+        let router = Router::new(
+            &nodes,
+            15.0,
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+        );
+        assert!(!router.has_all_pairs());
+        // without precomputed paths, the Dijkstra algorithm is used
+        let (cost, _) = router
+            .find_shortest_path(&nodes[0], &nodes[1], Algorithm::PrecomputedAllPairs, None)
+            .unwrap();
+        let (expected_cost, _) = router
+            .find_shortest_path(&nodes[0], &nodes[1], Algorithm::Dijkstra, None)
+            .unwrap();
+        assert_eq!(cost, expected_cost);
+
+        let router = router.with_all_pairs();
+        assert!(router.has_all_pairs());
+        for from in nodes.iter().take(10) {
+            for to in &nodes {
+                let (expected_cost, expected_path) = router
+                    .find_shortest_path(from, to, Algorithm::Dijkstra, None)
+                    .unwrap();
+                let (cost, path) = router
+                    .find_shortest_path(from, to, Algorithm::PrecomputedAllPairs, None)
+                    .unwrap();
+                assert!((cost - expected_cost).abs() < 0.01);
+                assert_eq!(path.is_empty(), expected_path.is_empty());
+                if !path.is_empty() {
+                    assert_eq!(path.first(), router.get_node_index(from).as_ref());
+                    assert_eq!(path.last(), router.get_node_index(to).as_ref());
+                    let length = router.get_total_distance(&path).unwrap();
+                    assert!((length - cost).abs() < 0.01);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_router_keeps_its_earth_radius() {
+        // far apart, so the radii differ by more than the rounding
+        let nodes = vec![Node::new("A", 0.0, 0.0), Node::new("B", 0.0, 5.0)];
+        let distance = |from: &dyn AsNode, to: &dyn AsNode| {
+            haversine::distance(&from.as_node().location, &to.as_node().location)
+        };
+        let mean = Router::new(&nodes, 1000.0, distance, distance);
+        let authalic = Router::new_with_earth_radius(
+            &nodes,
+            1000.0,
+            distance,
+            distance,
+            EarthRadius::Authalic,
+        )
+        .with_all_pairs();
+        assert_eq!(mean.earth_radius(), EarthRadius::Mean);
+        assert_eq!(authalic.earth_radius(), EarthRadius::Authalic);
+        assert_eq!(haversine::get_earth_radius(), EarthRadius::Mean);
+
+        let expected =
+            |radius| haversine::distance_with(radius, &nodes[0].location, &nodes[1].location);
+        let (cost, _) = mean
+            .find_shortest_path(&nodes[0], &nodes[1], Algorithm::Dijkstra, None)
+            .unwrap();
+        assert_eq!(cost, expected(EarthRadius::Mean));
+        for algorithm in [Algorithm::Dijkstra, Algorithm::PrecomputedAllPairs] {
+            let (cost, path) = authalic
+                .find_shortest_path(&nodes[0], &nodes[1], algorithm, None)
+                .unwrap();
+            assert_eq!(cost, expected(EarthRadius::Authalic));
+            assert_eq!(authalic.get_total_distance(&path).unwrap(), cost);
+        }
+        assert_ne!(expected(EarthRadius::Mean), expected(EarthRadius::Authalic));
+
+        let paths = authalic.find_shortest_paths_from_location(&nodes[0].location, 1000.0);
+        let index = authalic.get_node_index(&nodes[1]).unwrap();
+        assert_eq!(paths[&index].0, expected(EarthRadius::Authalic));
+    }
+
+    #[test]
+    fn test_equal_cost_paths_are_deterministic() {
+        // a square without diagonals, every edge costs the same so both
+        // paths from A to D cost the same
+        let square = [
+            Node::new("A", 0.0, 0.0),
+            Node::new("B", 0.0, 0.1),
+            Node::new("C", 0.1, 0.0),
+            Node::new("D", 0.1, 0.1),
+        ];
+        let orders = [[0, 1, 2, 3], [3, 2, 1, 0], [2, 0, 3, 1], [1, 3, 0, 2]];
+
+        let mut routes = HashSet::new();
+        for order in orders {
+            let nodes: Vec<Node> = order.iter().map(|&i| square[i].clone()).collect();
+            let router = Router::new(
+                &nodes,
+                15.0,
+                |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+                |_, _| 1.0,
+            )
+            .with_all_pairs();
+            let uids = |path: Vec<NodeIndex>| {
+                path.into_iter()
+                    .map(|index| router.get_node_by_id(index).unwrap().uid.clone())
+                    .collect::<Vec<String>>()
+            };
+            for algorithm in [
+                Algorithm::Dijkstra,
+                Algorithm::BidirectionalDijkstra,
+                Algorithm::PrecomputedAllPairs,
+            ] {
+                let (cost, path) = router
+                    .find_shortest_path(&square[0], &square[3], algorithm, None)
+                    .unwrap();
+                assert_eq!(cost, 2.0);
+                routes.insert((format!("{:?}", algorithm), uids(path)));
+            }
+            let (_, path) = router
+                .find_shortest_path_with_penalty(&square[0], &square[3], |_, _| Some(0.0))
+                .unwrap();
+            routes.insert(("penalty".to_string(), uids(path)));
+        }
+        // one route per algorithm whatever the order of the nodes
+        assert_eq!(routes.len(), 4);
+    }
 }
@@ -2,10 +2,11 @@
 use serde::{Deserialize, Serialize};
 
 /// Represent the operating status of a [`super::node::Node`].
-#[derive(Debug, PartialEq, Hash, Eq, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, PartialEq, Hash, Eq, Copy, Clone, Serialize, Deserialize)]
 #[allow(dead_code)]
 pub enum Status {
     /// Indicate that the node is currently operating.
+    #[default]
     Ok,
     /// Indicate that the node is currently down.
     Closed,
@@ -0,0 +1,139 @@
+//! Alternate vertiports for generated flight plans.
+//!
+//! Every flight plan designates an alternate vertiport the aircraft can
+//! divert to if its destination becomes unavailable on approach. The
+//! alternate must be reachable from the destination with the reserve energy
+//! the aircraft still carries when it arrives there. The cargo weight isn't
+//! known when a flight is queried, so the alternate of a possible flight
+//! must be reachable with [`MAX_PAYLOAD_GRAMS`] on board.
+
+use crate::distance::Distance;
+use crate::diversion::diversion_range_km;
+use crate::node::Node;
+use crate::router::engine::Router;
+use crate::router_state::{get_node_by_id, ARROW_CARGO_CONSTRAINT, ARROW_CARGO_ROUTER};
+use crate::status::Status;
+
+/// Energy an aircraft must still carry when arriving at its destination
+pub const RESERVE_ENERGY_KWH: f32 = 12.0;
+
+/// Heaviest cargo an aircraft carries
+pub const MAX_PAYLOAD_GRAMS: i64 = 50_000;
+
+/// Finds the nearest alternate vertiport reachable from the destination on the given router
+///
+/// # Arguments
+/// * `router` - router with the graph of vertiports
+/// * `constraint` - max distance of a leg of the graph
+/// * `destination` - destination vertiport of the flight
+/// * `payload_grams` - weight of the cargo on board
+///
+/// # Returns
+/// The id of the alternate vertiport, `None` if no vertiport is reachable
+pub fn find_alternate_vertiport_with(
+    router: &Router,
+    constraint: Distance,
+    destination: &Node,
+    payload_grams: i64,
+) -> Option<String> {
+    let range_km = diversion_range_km(RESERVE_ENERGY_KWH, payload_grams);
+    let alternate = router
+        .find_shortest_paths_from_location(&destination.location, constraint.km().min(range_km))
+        .into_iter()
+        .filter(|(_, (distance_km, _))| *distance_km <= range_km)
+        .filter_map(|(index, (distance_km, _))| {
+            let node = router.get_node_by_id(index)?;
+            (node.uid != destination.uid && node.status == Status::Ok)
+                .then_some((distance_km, node.uid.clone()))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)))
+        .map(|(_, uid)| uid);
+    debug!(
+        "Alternate vertiport for destination {}: {:?}",
+        destination.uid, alternate
+    );
+    alternate
+}
+
+/// Finds the nearest alternate vertiport reachable from the destination
+///
+/// # Arguments
+/// * `destination_vertiport_id` - id of the destination vertiport of the flight
+/// * `payload_grams` - weight of the cargo on board
+///
+/// # Returns
+/// The id of the alternate vertiport, `None` if no vertiport is reachable
+pub fn find_alternate_vertiport(
+    destination_vertiport_id: &str,
+    payload_grams: i64,
+) -> Result<Option<String>, String> {
+    let router = ARROW_CARGO_ROUTER
+        .get()
+        .ok_or("Arrow XL router not initialized. Try to initialize it first.")?;
+    Ok(find_alternate_vertiport_with(
+        router,
+        ARROW_CARGO_CONSTRAINT,
+        get_node_by_id(destination_vertiport_id)?,
+        payload_grams,
+    ))
+}
+
+#[cfg(test)]
+mod alternate_tests {
+    use super::*;
+    use crate::haversine;
+
+    /// vertiports on the equator at the given longitudes
+    fn nodes(longitudes: &[f32]) -> Vec<Node> {
+        longitudes
+            .iter()
+            .enumerate()
+            .map(|(i, longitude)| Node::new(i.to_string(), 0.0, *longitude))
+            .collect()
+    }
+
+    fn router(nodes: &[Node]) -> Router<'_> {
+        Router::new(
+            nodes,
+            100.0,
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+        )
+    }
+
+    #[test]
+    fn test_nearest_alternate() {
+        // 22 km and 11 km from the destination
+        let nodes = nodes(&[0.0, 0.2, 0.3]);
+        let router = router(&nodes);
+        assert_eq!(
+            find_alternate_vertiport_with(&router, Distance::from_km(100.0), &nodes[1], 0),
+            Some("2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_no_alternate_within_reserve() {
+        // 55 km from the destination, beyond the reserve range of 27 km
+        let nodes = nodes(&[0.0, 0.5]);
+        assert_eq!(
+            find_alternate_vertiport_with(&router(&nodes), Distance::from_km(100.0), &nodes[0], 0),
+            None
+        );
+    }
+
+    #[test]
+    fn test_payload_shortens_range() {
+        // 20 km from the destination
+        let nodes = nodes(&[0.0, 0.18]);
+        let router = router(&nodes);
+        assert!(
+            find_alternate_vertiport_with(&router, Distance::from_km(100.0), &nodes[0], 0)
+                .is_some()
+        );
+        assert_eq!(
+            find_alternate_vertiport_with(&router, Distance::from_km(100.0), &nodes[0], 200_000),
+            None
+        );
+    }
+}
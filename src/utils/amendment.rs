@@ -0,0 +1,535 @@
+//! Propagates the impact of a delayed or cancelled flight plan.
+//!
+//! When an existing flight plan is delayed or cancelled, subsequent flight
+//! plans using the same vehicle or the same vertipad may become infeasible.
+//! [`propagate_flight_plan_change`] finds those flight plans and proposes
+//! an amendment for each one: a substitute vehicle if one is idle at the
+//! departure vertiport, or otherwise a shifted departure time. Amendments
+//! are applied to a working copy of the schedule while walking the flight
+//! plans in departure order, so knock-on effects of the amendments
+//! themselves are included in the result.
+
+use chrono::{DateTime, TimeZone};
+use rrule::Tz;
+use std::collections::{BTreeSet, HashSet};
+
+use crate::router_state::{
+    create_flight_plan_data, is_vehicle_available, FlightPlan, FlightPlanData, Vehicle,
+    LANDING_AND_UNLOADING_TIME_MIN, LOADING_AND_TAKEOFF_TIME_MIN,
+};
+use crate::time::seconds_to_datetime;
+
+/// Max number of consecutive flight plans a shifted flight plan may be
+/// pushed behind before giving up on the shift
+const MAX_SHIFT_ITERATIONS: usize = 10;
+
+/// Change applied to an existing flight plan
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FlightPlanChange {
+    /// The flight plan departs and arrives later by the given number of minutes
+    Delayed(i64),
+    /// The flight plan will not be flown
+    Cancelled,
+}
+
+/// Why a flight plan became infeasible
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImpactReason {
+    /// The vehicle is still busy with another flight at the departure time
+    VehicleNotAvailable,
+    /// The vehicle will not be at the departure vertiport at the departure time
+    VehicleNotAtDeparture,
+    /// The departure or arrival vertipad is blocked by a moved flight plan
+    VertipadConflict,
+}
+
+/// Proposed amendment for an impacted flight plan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Amendment {
+    /// Shift departure and arrival of the flight plan by the given number of minutes
+    ShiftTime(i64),
+    /// Fly the flight plan with another vehicle
+    SubstituteVehicle(String),
+    /// No feasible amendment found, the flight plan needs to be rescheduled manually
+    Unresolved,
+}
+
+/// A flight plan impacted by a change and the amendment proposed for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlightPlanImpact {
+    /// id of the impacted flight plan
+    pub flight_plan_id: String,
+    /// why the flight plan became infeasible
+    pub reason: ImpactReason,
+    /// proposed amendment
+    pub amendment: Amendment,
+}
+
+/// Flight plan fields relevant for the propagation, with times in seconds since epoch
+#[derive(Debug, Clone)]
+pub(crate) struct PlanTimes {
+    pub(crate) id: String,
+    pub(crate) vehicle_id: String,
+    pub(crate) departure_vertiport_id: String,
+    pub(crate) destination_vertiport_id: String,
+    pub(crate) departure_vertipad_id: String,
+    pub(crate) destination_vertipad_id: String,
+    pub(crate) departure: i64,
+    pub(crate) arrival: i64,
+}
+
+impl PlanTimes {
+    pub(crate) fn from_flight_plan(flight_plan: &FlightPlan) -> Result<PlanTimes, String> {
+        let data = flight_plan
+            .data
+            .as_ref()
+            .ok_or_else(|| format!("Flight plan {} has no data", flight_plan.id))?;
+        PlanTimes::from_data(&flight_plan.id, data)
+    }
+
+    pub(crate) fn from_data(id: &str, data: &FlightPlanData) -> Result<PlanTimes, String> {
+        let departure = data
+            .scheduled_departure
+            .as_ref()
+            .ok_or_else(|| format!("Flight plan {} has no departure time", id))?
+            .seconds;
+        let arrival = data
+            .scheduled_arrival
+            .as_ref()
+            .ok_or_else(|| format!("Flight plan {} has no arrival time", id))?
+            .seconds;
+        Ok(PlanTimes {
+            id: id.to_string(),
+            vehicle_id: data.vehicle_id.clone(),
+            departure_vertiport_id: data.departure_vertiport_id.clone().unwrap_or_default(),
+            destination_vertiport_id: data.destination_vertiport_id.clone().unwrap_or_default(),
+            departure_vertipad_id: data.departure_vertipad_id.clone(),
+            destination_vertipad_id: data.destination_vertipad_id.clone(),
+            departure,
+            arrival,
+        })
+    }
+
+    fn shift(&mut self, seconds: i64) {
+        self.departure += seconds;
+        self.arrival += seconds;
+    }
+}
+
+/// Returns true if the two flight plans use the same pad: the same vertipad
+/// if both vertipads are known, otherwise the same vertiport
+fn same_pad(vertiport_a: &str, vertipad_a: &str, vertiport_b: &str, vertipad_b: &str) -> bool {
+    if !vertipad_a.is_empty() && !vertipad_b.is_empty() {
+        return vertipad_a == vertipad_b;
+    }
+    vertiport_a == vertiport_b
+}
+
+/// Returns the minutes `plan` would need to be shifted by to clear the pads
+/// blocked by the `moved` flight plan, if they conflict
+pub(crate) fn pad_conflict_minutes(plan: &PlanTimes, moved: &PlanTimes) -> Option<i64> {
+    let departure_block = LOADING_AND_TAKEOFF_TIME_MIN as i64 * 60;
+    let arrival_block = LANDING_AND_UNLOADING_TIME_MIN as i64 * 60;
+    let mut shift = 0;
+    if same_pad(
+        &plan.departure_vertiport_id,
+        &plan.departure_vertipad_id,
+        &moved.departure_vertiport_id,
+        &moved.departure_vertipad_id,
+    ) && (plan.departure - moved.departure).abs() < departure_block
+    {
+        shift = shift.max(moved.departure + departure_block - plan.departure);
+    }
+    if same_pad(
+        &plan.destination_vertiport_id,
+        &plan.destination_vertipad_id,
+        &moved.destination_vertiport_id,
+        &moved.destination_vertipad_id,
+    ) && (plan.arrival - moved.arrival).abs() < arrival_block
+    {
+        shift = shift.max(moved.arrival + arrival_block - plan.arrival);
+    }
+    if shift > 0 {
+        // round up to whole minutes
+        Some((shift + 59) / 60)
+    } else {
+        None
+    }
+}
+
+/// Builds flight plans with the working times, leaving out the flight plan being checked
+fn to_flight_plans(plans: &[PlanTimes], exclude_id: &str) -> Vec<FlightPlan> {
+    plans
+        .iter()
+        .filter(|plan| plan.id != exclude_id)
+        .map(|plan| {
+            let mut data = create_flight_plan_data(
+                plan.vehicle_id.clone(),
+                plan.departure_vertiport_id.clone(),
+                plan.destination_vertiport_id.clone(),
+                timestamp_to_datetime(plan.departure),
+                timestamp_to_datetime(plan.arrival),
+            );
+            data.departure_vertipad_id = plan.departure_vertipad_id.clone();
+            data.destination_vertipad_id = plan.destination_vertipad_id.clone();
+            FlightPlan {
+                id: plan.id.clone(),
+                data: Some(data),
+            }
+        })
+        .collect()
+}
+
+/// Converts seconds since epoch into a UTC date time, the epoch if out of range
+pub(crate) fn timestamp_to_datetime(seconds: i64) -> DateTime<Tz> {
+    seconds_to_datetime(seconds, 0).unwrap_or_else(|_| Tz::UTC.timestamp_nanos(0))
+}
+
+/// Returns the vehicle's flight plan (other than `plan`) overlapping the given time window
+fn conflicting_vehicle_plan<'a>(
+    plans: &'a [PlanTimes],
+    plan: &PlanTimes,
+    departure: i64,
+    arrival: i64,
+) -> Option<&'a PlanTimes> {
+    plans.iter().find(|other| {
+        other.id != plan.id
+            && other.vehicle_id == plan.vehicle_id
+            && other.departure < arrival
+            && departure < other.arrival
+    })
+}
+
+/// Gets the vertiport the vehicle is at (or flying to) at the given time and
+/// the arrival time there, based on its last flight plan departed before
+fn scheduled_location<'a>(
+    plans: &'a [PlanTimes],
+    vehicle_id: &str,
+    exclude_id: &str,
+    timestamp: i64,
+) -> Option<&'a PlanTimes> {
+    plans
+        .iter()
+        .filter(|other| {
+            other.id != exclude_id && other.vehicle_id == vehicle_id && other.departure <= timestamp
+        })
+        .max_by_key(|other| other.departure)
+}
+
+/// Checks whether the vehicle of `plan` can fly it, returns the reason if not
+fn vehicle_impact(plans: &[PlanTimes], plan: &PlanTimes) -> Option<ImpactReason> {
+    if conflicting_vehicle_plan(plans, plan, plan.departure, plan.arrival).is_some() {
+        return Some(ImpactReason::VehicleNotAvailable);
+    }
+    // location of a vehicle without any previous flight plan is unknown here
+    let last = scheduled_location(plans, &plan.vehicle_id, &plan.id, plan.departure)?;
+    if last.arrival > plan.departure {
+        return Some(ImpactReason::VehicleNotAvailable);
+    }
+    if last.destination_vertiport_id != plan.departure_vertiport_id {
+        return Some(ImpactReason::VehicleNotAtDeparture);
+    }
+    None
+}
+
+/// Finds an idle vehicle at the departure vertiport which can fly `plan` instead
+fn find_substitute_vehicle(
+    plans: &[PlanTimes],
+    plan: &PlanTimes,
+    vehicles: &[Vehicle],
+) -> Option<String> {
+    let flight_plans = to_flight_plans(plans, &plan.id);
+    vehicles
+        .iter()
+        .filter(|vehicle| vehicle.id != plan.vehicle_id)
+        .find(|vehicle| {
+            let Some(data) = vehicle.data.as_ref() else {
+                return false;
+            };
+            let at_departure =
+                match scheduled_location(plans, &vehicle.id, &plan.id, plan.departure) {
+                    Some(last) => {
+                        last.arrival <= plan.departure
+                            && last.destination_vertiport_id == plan.departure_vertiport_id
+                    }
+                    None => data.last_vertiport_id.as_deref() == Some(&plan.departure_vertiport_id),
+                };
+            at_departure
+                && is_vehicle_available(
+                    vehicle,
+                    timestamp_to_datetime(plan.departure),
+                    (plan.arrival - plan.departure) / 60,
+                    &flight_plans,
+                )
+                .unwrap_or(false)
+        })
+        .map(|vehicle| vehicle.id.clone())
+}
+
+/// Finds the minutes `plan` needs to be postponed until its vehicle is free
+/// and at the departure vertiport
+fn find_vehicle_shift(plans: &[PlanTimes], plan: &PlanTimes) -> Option<i64> {
+    let mut candidate = plan.clone();
+    for _ in 0..MAX_SHIFT_ITERATIONS {
+        if let Some(other) =
+            conflicting_vehicle_plan(plans, &candidate, candidate.departure, candidate.arrival)
+        {
+            candidate.shift(other.arrival - candidate.departure);
+            continue;
+        }
+        return match vehicle_impact(plans, &candidate) {
+            None => Some((candidate.departure - plan.departure + 59) / 60),
+            Some(_) => None,
+        };
+    }
+    None
+}
+
+/// Recomputes the downstream impact of a delayed or cancelled flight plan
+///
+/// # Arguments
+/// * `flight_plan_id` - id of the changed flight plan
+/// * `change` - the delay or cancellation
+/// * `vehicles` - vehicles which can be used as a substitute
+/// * `existing_flight_plans` - all scheduled flight plans including the changed one
+///
+/// # Returns
+/// Impacted flight plans (departing after the changed one) in the order they
+/// were amended, each with the reason and proposed amendment.
+pub fn propagate_flight_plan_change(
+    flight_plan_id: &str,
+    change: FlightPlanChange,
+    vehicles: &[Vehicle],
+    existing_flight_plans: &[FlightPlan],
+) -> Result<Vec<FlightPlanImpact>, String> {
+    info!(
+        "Propagating change {:?} of flight plan {}",
+        change, flight_plan_id
+    );
+    let mut plans = existing_flight_plans
+        .iter()
+        .map(PlanTimes::from_flight_plan)
+        .collect::<Result<Vec<PlanTimes>, String>>()?;
+    let Some(changed_index) = plans.iter().position(|plan| plan.id == flight_plan_id) else {
+        return Err(format!("Flight plan {} not found", flight_plan_id));
+    };
+    let original_departure = plans[changed_index].departure;
+
+    // flight plans which have been moved and can block pads of other flight plans
+    let mut moved: HashSet<String> = HashSet::new();
+    match change {
+        FlightPlanChange::Delayed(minutes) => {
+            plans[changed_index].shift(minutes * 60);
+            moved.insert(flight_plan_id.to_string());
+        }
+        FlightPlanChange::Cancelled => {
+            // keep the vehicle parked at the departure vertiport
+            let cancelled = &mut plans[changed_index];
+            cancelled.destination_vertiport_id = cancelled.departure_vertiport_id.clone();
+            cancelled.destination_vertipad_id = cancelled.departure_vertipad_id.clone();
+            cancelled.arrival = cancelled.departure;
+        }
+    }
+
+    let downstream: Vec<String> = plans
+        .iter()
+        .filter(|plan| plan.id != flight_plan_id && plan.departure >= original_departure)
+        .map(|plan| (plan.departure, plan.id.clone()))
+        .collect::<BTreeSet<(i64, String)>>()
+        .into_iter()
+        .map(|(_, id)| id)
+        .collect();
+    debug!("Downstream flight plans: {:?}", downstream);
+
+    // downstream flight plans not processed yet are ignored when checking the
+    // vehicle, they get moved along with the flight plans they depend on
+    let mut pending: HashSet<String> = downstream.iter().cloned().collect();
+    let mut impacts: Vec<FlightPlanImpact> = vec![];
+    for id in downstream {
+        pending.remove(&id);
+        let Some(index) = plans.iter().position(|plan| plan.id == id) else {
+            continue;
+        };
+        let plan = plans[index].clone();
+        let settled: Vec<PlanTimes> = plans
+            .iter()
+            .filter(|other| !pending.contains(&other.id))
+            .cloned()
+            .collect();
+
+        if let Some(reason) = vehicle_impact(&settled, &plan) {
+            let amendment =
+                if let Some(vehicle_id) = find_substitute_vehicle(&plans, &plan, vehicles) {
+                    plans[index].vehicle_id = vehicle_id.clone();
+                    Amendment::SubstituteVehicle(vehicle_id)
+                } else if let Some(minutes) = find_vehicle_shift(&settled, &plan) {
+                    plans[index].shift(minutes * 60);
+                    moved.insert(id.clone());
+                    Amendment::ShiftTime(minutes)
+                } else {
+                    Amendment::Unresolved
+                };
+            debug!(
+                "Flight plan {} impacted: {:?} -> {:?}",
+                id, reason, amendment
+            );
+            impacts.push(FlightPlanImpact {
+                flight_plan_id: id,
+                reason,
+                amendment,
+            });
+            continue;
+        }
+
+        let pad_conflict = plans
+            .iter()
+            .filter(|other| other.id != id && moved.contains(&other.id))
+            .filter_map(|other| pad_conflict_minutes(&plan, other))
+            .max();
+        if let Some(minutes) = pad_conflict {
+            plans[index].shift(minutes * 60);
+            moved.insert(id.clone());
+            debug!(
+                "Flight plan {} pad conflict, shifting by {} minutes",
+                id, minutes
+            );
+            impacts.push(FlightPlanImpact {
+                flight_plan_id: id,
+                reason: ImpactReason::VertipadConflict,
+                amendment: Amendment::ShiftTime(minutes),
+            });
+        }
+    }
+    info!("Found {} impacted flight plans", impacts.len());
+    Ok(impacts)
+}
+
+#[cfg(test)]
+mod amendment_tests {
+    use super::*;
+    use chrono::Duration;
+
+    const START: i64 = 1_666_692_000; // 2022-10-25T10:00:00Z
+
+    fn flight_plan(id: &str, vehicle_id: &str, from: &str, to: &str, dep_min: i64) -> FlightPlan {
+        let departure = timestamp_to_datetime(START) + Duration::minutes(dep_min);
+        let data: FlightPlanData = crate::router_state::create_flight_plan_data(
+            vehicle_id.to_string(),
+            from.to_string(),
+            to.to_string(),
+            departure,
+            departure + Duration::minutes(30),
+        );
+        FlightPlan {
+            id: id.to_string(),
+            data: Some(data),
+        }
+    }
+
+    fn vehicle(id: &str, last_vertiport_id: &str) -> Vehicle {
+        Vehicle {
+            id: id.to_string(),
+            data: Some(crate::resources::vehicle::Data {
+                last_vertiport_id: Some(last_vertiport_id.to_string()),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_delay_shifts_next_vehicle_leg() {
+        let plans = vec![
+            flight_plan("fp1", "v1", "A", "B", 0),
+            flight_plan("fp2", "v1", "B", "C", 40),
+            flight_plan("fp3", "v1", "C", "A", 120),
+        ];
+        let impacts =
+            propagate_flight_plan_change("fp1", FlightPlanChange::Delayed(30), &[], &plans)
+                .unwrap();
+        assert_eq!(
+            impacts,
+            vec![FlightPlanImpact {
+                flight_plan_id: "fp2".to_string(),
+                reason: ImpactReason::VehicleNotAvailable,
+                amendment: Amendment::ShiftTime(20),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_delay_cascades() {
+        let plans = vec![
+            flight_plan("fp1", "v1", "A", "B", 0),
+            flight_plan("fp2", "v1", "B", "C", 30),
+            flight_plan("fp3", "v1", "C", "A", 60),
+        ];
+        let impacts =
+            propagate_flight_plan_change("fp1", FlightPlanChange::Delayed(15), &[], &plans)
+                .unwrap();
+        assert_eq!(impacts.len(), 2);
+        assert_eq!(impacts[0].amendment, Amendment::ShiftTime(15));
+        assert_eq!(impacts[1].flight_plan_id, "fp3");
+        assert_eq!(impacts[1].amendment, Amendment::ShiftTime(15));
+    }
+
+    #[test]
+    fn test_delay_with_substitute_vehicle() {
+        let plans = vec![
+            flight_plan("fp1", "v1", "A", "B", 0),
+            flight_plan("fp2", "v1", "B", "C", 40),
+        ];
+        let vehicles = vec![vehicle("v1", "A"), vehicle("v2", "B")];
+        let impacts =
+            propagate_flight_plan_change("fp1", FlightPlanChange::Delayed(30), &vehicles, &plans)
+                .unwrap();
+        assert_eq!(
+            impacts[0].amendment,
+            Amendment::SubstituteVehicle("v2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cancel_leaves_vehicle_at_departure() {
+        let plans = vec![
+            flight_plan("fp1", "v1", "A", "B", 0),
+            flight_plan("fp2", "v1", "B", "C", 40),
+        ];
+        let impacts =
+            propagate_flight_plan_change("fp1", FlightPlanChange::Cancelled, &[], &plans).unwrap();
+        assert_eq!(
+            impacts,
+            vec![FlightPlanImpact {
+                flight_plan_id: "fp2".to_string(),
+                reason: ImpactReason::VehicleNotAtDeparture,
+                amendment: Amendment::Unresolved,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_delay_blocks_arrival_pad() {
+        let plans = vec![
+            flight_plan("fp1", "v1", "A", "B", 0),
+            flight_plan("fp2", "v2", "C", "B", 20),
+        ];
+        let impacts =
+            propagate_flight_plan_change("fp1", FlightPlanChange::Delayed(15), &[], &plans)
+                .unwrap();
+        assert_eq!(
+            impacts,
+            vec![FlightPlanImpact {
+                flight_plan_id: "fp2".to_string(),
+                reason: ImpactReason::VertipadConflict,
+                amendment: Amendment::ShiftTime(5),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unknown_flight_plan() {
+        let plans = vec![flight_plan("fp1", "v1", "A", "B", 0)];
+        assert!(
+            propagate_flight_plan_change("fp9", FlightPlanChange::Cancelled, &[], &plans).is_err()
+        );
+    }
+}
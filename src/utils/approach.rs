@@ -0,0 +1,222 @@
+//! Approach and departure directions of the vertipads in the wind.
+//!
+//! Obstacles around a vertipad often leave only a few directions to approach
+//! and depart it along. A [`PadApproach`] lists them, with the crosswind and
+//! tailwind the vehicles tolerate, and is configured per vertipad id with
+//! [`set_pad_approach`]. The surface wind is configured per vertiport id with
+//! [`set_surface_wind`] and applies until it is updated or cleared.
+//!
+//! The vertiport availability check only counts the pads usable in the
+//! current wind, so the flights fall back to the other pads of the vertiport,
+//! and a vertiport whose pads are all unusable takes no movements. Pads
+//! without a configured approach, or at a vertiport without a known wind,
+//! are always usable.
+
+use crate::context::current_context;
+use crate::eta::Wind;
+use crate::router_state::Vertipad;
+
+/// Directions a vertipad can be approached and departed along, and the wind
+/// limits of the vehicles flying them
+#[derive(Debug, Clone, PartialEq)]
+pub struct PadApproach {
+    /// headings of the approaches and departures, in degrees clockwise from
+    /// north. Without headings the pad can be flown in any direction, i.e.
+    /// into the wind.
+    pub headings_degrees: Vec<f32>,
+    /// highest wind component across the heading
+    pub max_crosswind_kmh: f32,
+    /// highest wind component from behind along the heading
+    pub max_tailwind_kmh: f32,
+}
+
+impl PadApproach {
+    /// Checks if a heading of the pad is within the crosswind and tailwind
+    /// limits in the wind
+    pub fn is_usable_in(&self, wind: Wind) -> bool {
+        if self.headings_degrees.is_empty() {
+            return true;
+        }
+        self.headings_degrees.iter().any(|heading| {
+            let angle = (wind.from_degrees - heading).to_radians();
+            let crosswind = (wind.speed_kmh * angle.sin()).abs();
+            let tailwind = -wind.speed_kmh * angle.cos();
+            crosswind <= self.max_crosswind_kmh && tailwind <= self.max_tailwind_kmh
+        })
+    }
+}
+
+/// Sets the approach and departure directions of a vertipad
+pub fn set_pad_approach(vertipad_id: &str, approach: PadApproach) {
+    match current_context().pad_approaches.write() {
+        Ok(mut approaches) => {
+            approaches.insert(vertipad_id.to_string(), approach);
+        }
+        Err(_) => error!("Pad approaches unavailable"),
+    }
+}
+
+/// Removes the approach directions of a vertipad, it can be flown in any
+/// direction
+pub fn clear_pad_approach(vertipad_id: &str) {
+    match current_context().pad_approaches.write() {
+        Ok(mut approaches) => {
+            approaches.remove(vertipad_id);
+        }
+        Err(_) => error!("Pad approaches unavailable"),
+    }
+}
+
+/// Gets the approach directions of a vertipad, if any
+pub fn get_pad_approach(vertipad_id: &str) -> Option<PadApproach> {
+    current_context()
+        .pad_approaches
+        .read()
+        .ok()
+        .and_then(|approaches| approaches.get(vertipad_id).cloned())
+}
+
+/// Sets the current surface wind at a vertiport
+pub fn set_surface_wind(vertiport_id: &str, wind: Wind) {
+    match current_context().surface_winds.write() {
+        Ok(mut winds) => {
+            winds.insert(vertiport_id.to_string(), wind);
+        }
+        Err(_) => error!("Surface winds unavailable"),
+    }
+}
+
+/// Removes the surface wind of a vertiport
+pub fn clear_surface_wind(vertiport_id: &str) {
+    match current_context().surface_winds.write() {
+        Ok(mut winds) => {
+            winds.remove(vertiport_id);
+        }
+        Err(_) => error!("Surface winds unavailable"),
+    }
+}
+
+/// Gets the current surface wind at a vertiport, if known
+pub fn get_surface_wind(vertiport_id: &str) -> Option<Wind> {
+    current_context()
+        .surface_winds
+        .read()
+        .ok()
+        .and_then(|winds| winds.get(vertiport_id).copied())
+}
+
+/// Checks if a vertipad of the vertiport can be flown in its surface wind
+pub fn is_pad_usable(vertiport_id: &str, vertipad_id: &str) -> bool {
+    let (Some(wind), Some(approach)) = (
+        get_surface_wind(vertiport_id),
+        get_pad_approach(vertipad_id),
+    ) else {
+        return true;
+    };
+    approach.is_usable_in(wind)
+}
+
+/// Filters the vertipads of a vertiport usable in its surface wind
+pub fn usable_vertipads(vertiport_id: &str, vertipads: &[Vertipad]) -> Vec<Vertipad> {
+    let usable: Vec<Vertipad> = vertipads
+        .iter()
+        .filter(|vertipad| is_pad_usable(vertiport_id, &vertipad.id))
+        .cloned()
+        .collect();
+    if usable.len() < vertipads.len() {
+        debug!(
+            "{} of {} vertipads of vertiport {} unusable in the wind",
+            vertipads.len() - usable.len(),
+            vertipads.len(),
+            vertiport_id
+        );
+    }
+    usable
+}
+
+#[cfg(test)]
+mod approach_tests {
+    use super::*;
+    use crate::router_state::is_vertiport_available;
+    use chrono::TimeZone;
+    use rrule::Tz;
+
+    fn vertipad(id: &str) -> Vertipad {
+        Vertipad {
+            id: id.to_string(),
+            data: None,
+        }
+    }
+
+    #[test]
+    fn test_usable_vertipads() {
+        // a runway-like pad flown north or south, and a pad flown east only
+        let north_south = PadApproach {
+            headings_degrees: vec![0.0, 180.0],
+            max_crosswind_kmh: 20.0,
+            max_tailwind_kmh: 5.0,
+        };
+        let east = PadApproach {
+            headings_degrees: vec![90.0],
+            max_crosswind_kmh: 20.0,
+            max_tailwind_kmh: 5.0,
+        };
+        let wind = |speed_kmh: f32, from_degrees: f32| Wind {
+            speed_kmh,
+            from_degrees,
+        };
+        assert!(north_south.is_usable_in(wind(30.0, 180.0)));
+        assert!(!north_south.is_usable_in(wind(30.0, 90.0)));
+        assert!(north_south.is_usable_in(wind(25.0, 40.0)));
+        assert!(east.is_usable_in(wind(30.0, 90.0)));
+        // tailwind
+        assert!(!east.is_usable_in(wind(10.0, 270.0)));
+        assert!(PadApproach {
+            headings_degrees: vec![],
+            max_crosswind_kmh: 0.0,
+            max_tailwind_kmh: 0.0,
+        }
+        .is_usable_in(wind(50.0, 10.0)));
+
+        let vertipads = vec![
+            vertipad("APPROACH-pad-1"),
+            vertipad("APPROACH-pad-2"),
+            vertipad("APPROACH-pad-3"),
+        ];
+        set_pad_approach("APPROACH-pad-1", north_south);
+        set_pad_approach("APPROACH-pad-2", east);
+        assert_eq!(usable_vertipads("APPROACH", &vertipads).len(), 3);
+
+        // crosswind from the east, pad 3 has no constraint
+        set_surface_wind("APPROACH", wind(30.0, 90.0));
+        let ids: Vec<String> = usable_vertipads("APPROACH", &vertipads)
+            .into_iter()
+            .map(|vertipad| vertipad.id)
+            .collect();
+        assert_eq!(ids, ["APPROACH-pad-2", "APPROACH-pad-3"]);
+        assert!(!is_pad_usable("APPROACH", "APPROACH-pad-1"));
+        let departure = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap();
+        let available = |vertipads: &[Vertipad]| {
+            is_vertiport_available(
+                "APPROACH".to_string(),
+                None,
+                vertipads,
+                departure,
+                &[],
+                true,
+            )
+            .unwrap()
+            .0
+        };
+        assert!(available(&vertipads[..2]));
+        // no fallback left
+        assert!(!available(&vertipads[..1]));
+
+        clear_pad_approach("APPROACH-pad-1");
+        assert!(is_pad_usable("APPROACH", "APPROACH-pad-1"));
+        clear_surface_wind("APPROACH");
+        clear_pad_approach("APPROACH-pad-2");
+        assert!(get_surface_wind("APPROACH").is_none());
+        assert!(get_pad_approach("APPROACH-pad-2").is_none());
+    }
+}
@@ -0,0 +1,226 @@
+//! Property-based test generators.
+//!
+//! [`Arbitrary`] implementations of the router types, enabled in downstream
+//! crates with the `proptest` feature. Flight plans are drawn from a few
+//! vehicles and vertiports on 5 minute boundaries of a single day, so
+//! conflicting and touching flight plans are generated often.
+
+use chrono::{Duration, TimeZone};
+use ordered_float::OrderedFloat;
+use proptest::prelude::*;
+use rrule::Tz;
+use std::str::FromStr;
+
+use crate::location::Location;
+use crate::node::Node;
+use crate::resources::flight_plan;
+use crate::router_state::create_flight_plan_data;
+use crate::schedule::Calendar;
+use crate::status::Status;
+
+/// Number of vehicles the generated flight plans are assigned to
+pub const ARBITRARY_VEHICLES: usize = 3;
+/// Number of vertiports the generated flight plans fly between
+pub const ARBITRARY_VERTIPORTS: usize = 4;
+
+/// Id of the n-th generated vehicle
+pub fn arbitrary_vehicle_id(n: usize) -> String {
+    format!("vehicle-{}", n)
+}
+
+/// Id of the n-th generated vertiport
+pub fn arbitrary_vertiport_id(n: usize) -> String {
+    format!("vertiport-{}", n)
+}
+
+impl Arbitrary for Location {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (-90.0f32..=90.0, -180.0f32..=180.0, 0.0f32..1000.0)
+            .prop_map(|(latitude, longitude, altitude_meters)| Location {
+                latitude: OrderedFloat(latitude),
+                longitude: OrderedFloat(longitude),
+                altitude_meters: OrderedFloat(altitude_meters),
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Node {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        ("[a-z]{1,8}:[0-9]{1,5}", any::<Location>(), any::<bool>())
+            .prop_map(|(uid, location, open)| Node {
+                uid,
+                location,
+                forward_to: None,
+                status: if open { Status::Ok } else { Status::Closed },
+                schedule: None,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Calendar {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    /// Calendars blocking a few hours daily or on some weekdays
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        let weekdays =
+            proptest::sample::subsequence(vec!["MO", "TU", "WE", "TH", "FR", "SA", "SU"], 1..=7);
+        (
+            0u32..24,
+            1u32..=12,
+            prop_oneof![Just(None), weekdays.prop_map(Some)],
+        )
+            .prop_map(|(start_hour, duration_hours, weekdays)| {
+                let rule = match weekdays {
+                    Some(weekdays) => format!("FREQ=WEEKLY;BYDAY={}", weekdays.join(",")),
+                    None => "FREQ=DAILY".to_string(),
+                };
+                let calendar = format!(
+                    "DTSTART:20221020T{:02}0000Z;DURATION:PT{}H\nRRULE:{}",
+                    start_hour, duration_hours, rule
+                );
+                Calendar::from_str(&calendar)
+                    .unwrap_or_else(|_| panic!("Invalid generated calendar: {}", calendar))
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for flight_plan::Object {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    /// Flight plans of 5 minutes to 2 hours on 2030-01-01
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            "[a-f0-9]{8}",
+            0..ARBITRARY_VEHICLES,
+            0..ARBITRARY_VERTIPORTS,
+            0..ARBITRARY_VERTIPORTS,
+            0i64..288,
+            1i64..=24,
+        )
+            .prop_map(|(id, vehicle, departure, destination, slot, slots)| {
+                let day = Tz::UTC.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+                let departure_time = day + Duration::minutes(slot * 5);
+                flight_plan::Object {
+                    id,
+                    data: Some(create_flight_plan_data(
+                        arbitrary_vehicle_id(vehicle),
+                        arbitrary_vertiport_id(departure),
+                        arbitrary_vertiport_id(destination),
+                        departure_time,
+                        departure_time + Duration::minutes(slots * 5),
+                    )),
+                }
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod arbitrary_tests {
+    use super::*;
+    use crate::amendment::PlanTimes;
+    use crate::haversine;
+    use crate::node::AsNode;
+    use crate::router::engine::{Algorithm, Router};
+    use crate::router_state::{is_vehicle_available, FlightPlan, Vehicle};
+    use crate::time::seconds_to_datetime;
+
+    /// Vehicle whose schedule only blocks a minute in 2000
+    fn vehicle(vehicle_id: &str) -> Vehicle {
+        Vehicle {
+            id: vehicle_id.to_string(),
+            data: Some(crate::resources::vehicle::Data {
+                schedule: Some(
+                    "DTSTART:20000101T000000Z;DURATION:PT1M\nRRULE:FREQ=DAILY;COUNT=1".to_string(),
+                ),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn distance(from: &dyn AsNode, to: &dyn AsNode) -> f32 {
+        haversine::distance(&from.as_node().location, &to.as_node().location)
+    }
+
+    /// Checks if the vehicle of `plan` can fly it alongside `other`
+    fn is_available_alongside(plan: &FlightPlan, other: &FlightPlan) -> bool {
+        let times = PlanTimes::from_flight_plan(plan).unwrap();
+        is_vehicle_available(
+            &vehicle(&times.vehicle_id),
+            seconds_to_datetime(times.departure, 0).unwrap(),
+            (times.arrival - times.departure) / 60,
+            std::slice::from_ref(other),
+        )
+        .unwrap()
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_penalties_never_lower_route_cost(
+            nodes in proptest::collection::vec(any::<Node>(), 2..12),
+            penalty in 0.0f32..100.0,
+        ) {
+            let router = Router::new(&nodes, 5000.0, distance, distance);
+            let (from, to) = (&nodes[0], &nodes[nodes.len() - 1]);
+            let (cost, path) = router
+                .find_shortest_path(from, to, Algorithm::Dijkstra, None)
+                .unwrap();
+            let (penalized_cost, penalized_path) = router
+                .find_shortest_path_with_penalty(from, to, |_, _| Some(penalty))
+                .unwrap();
+            prop_assert_eq!(path.is_empty(), penalized_path.is_empty());
+            prop_assert!(penalized_cost >= cost);
+        }
+
+        #[test]
+        fn prop_vehicle_availability_is_symmetric(
+            plan in any::<FlightPlan>(),
+            other in any::<FlightPlan>(),
+        ) {
+            let mut other = other;
+            other.data.as_mut().unwrap().vehicle_id = plan.data.as_ref().unwrap().vehicle_id.clone();
+            let touching = {
+                let (plan, other) = (
+                    PlanTimes::from_flight_plan(&plan).unwrap(),
+                    PlanTimes::from_flight_plan(&other).unwrap(),
+                );
+                plan.arrival == other.departure || other.arrival == plan.departure
+            };
+            let available = is_available_alongside(&plan, &other);
+            prop_assert_eq!(available, is_available_alongside(&other, &plan));
+            if touching {
+                prop_assert!(available);
+            }
+        }
+
+        #[test]
+        fn prop_calendar_availability_holds_within_window(
+            calendar in any::<Calendar>(),
+            start in 0i64..(14 * 24 * 4),
+            quarters in 3i64..16,
+            trim in 0i64..2,
+        ) {
+            let day = Tz::UTC.with_ymd_and_hms(2022, 11, 1, 0, 0, 0).unwrap();
+            let start = day + Duration::minutes(start * 15);
+            let end = start + Duration::minutes(quarters * 15);
+            let (inner_start, inner_end) =
+                (start + Duration::minutes(trim * 15), end - Duration::minutes(trim * 15));
+            if calendar.is_available_between(start, end) {
+                prop_assert!(calendar.is_available_between(inner_start, inner_end));
+            }
+        }
+    }
+}
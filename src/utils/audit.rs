@@ -0,0 +1,772 @@
+//! Audit trail of scheduling decisions and replay of recorded queries.
+//!
+//! When an [`AuditSink`] is set with [`set_audit_sink`], every call of
+//! [`get_possible_flights`](crate::router_state::get_possible_flights)
+//! records an [`AuditRecord`]: the inputs of the query, each candidate
+//! departure slot evaluated with the reason it was rejected, and the flight
+//! plans returned. [`JsonLinesAuditSink`] writes the records as one JSON
+//! object per line.
+//!
+//! [`replay`] re-runs a recorded query against the current version of the
+//! library and [`compare_records`] lists where the decisions differ, to
+//! track down scheduling regressions. The router must be initialized with
+//! the same vertiports as when the query was recorded. Vehicle telemetry is
+//! not recorded, so a replay locates the vehicles by their schedule.
+
+use chrono::DateTime;
+use prost_types::Timestamp;
+use rrule::Tz;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use crate::amendment::timestamp_to_datetime;
+use crate::clock::SimulatedClock;
+use crate::context::current_context;
+use crate::router_state::{
+    find_possible_flights, FlightPlan, FlightPlanData, PossibleFlight, Vehicle, Vertipad, Vertiport,
+};
+
+/// Vertiport as seen by a recorded query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedVertiport {
+    /// id of the vertiport
+    pub id: String,
+    /// latitude of the vertiport
+    pub latitude: f64,
+    /// longitude of the vertiport
+    pub longitude: f64,
+    /// operating schedule of the vertiport
+    pub schedule: Option<String>,
+}
+
+/// Vertipad as seen by a recorded query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedVertipad {
+    /// id of the vertipad
+    pub id: String,
+    /// id of the vertiport of the vertipad
+    pub vertiport_id: String,
+    /// whether the vertipad is in service
+    pub enabled: bool,
+    /// operating schedule of the vertipad
+    pub schedule: Option<String>,
+}
+
+/// Vehicle as seen by a recorded query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedVehicle {
+    /// id of the vehicle
+    pub id: String,
+    /// id of the vehicle model
+    pub vehicle_model_id: String,
+    /// vertiport the vehicle was last parked at
+    pub last_vertiport_id: Option<String>,
+    /// operating schedule of the vehicle
+    pub schedule: Option<String>,
+}
+
+/// Flight plan as seen by a recorded query, times in seconds since the epoch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedFlightPlan {
+    /// id of the flight plan, empty for draft flight plans
+    pub id: String,
+    /// id of the vehicle
+    pub vehicle_id: String,
+    /// weights of the cargo
+    pub cargo_weight_grams: Vec<i64>,
+    /// id of the departure vertiport
+    pub departure_vertiport_id: Option<String>,
+    /// id of the destination vertiport
+    pub destination_vertiport_id: Option<String>,
+    /// id of the departure vertipad
+    pub departure_vertipad_id: String,
+    /// id of the destination vertipad
+    pub destination_vertipad_id: String,
+    /// scheduled departure time
+    pub scheduled_departure: Option<i64>,
+    /// scheduled arrival time
+    pub scheduled_arrival: Option<i64>,
+    /// actual departure time
+    pub actual_departure: Option<i64>,
+    /// actual arrival time
+    pub actual_arrival: Option<i64>,
+}
+
+/// Inputs of a recorded query, times in seconds since the epoch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedQuery {
+    /// departure vertiport
+    pub departure_vertiport: RecordedVertiport,
+    /// arrival vertiport
+    pub arrival_vertiport: RecordedVertiport,
+    /// vertipads of the departure vertiport
+    pub departure_vertipads: Vec<RecordedVertipad>,
+    /// vertipads of the arrival vertiport
+    pub arrival_vertipads: Vec<RecordedVertipad>,
+    /// earliest departure time of the time window
+    pub earliest_departure_time: Option<i64>,
+    /// latest arrival time of the time window
+    pub latest_arrival_time: Option<i64>,
+    /// vehicles serving the route
+    pub vehicles: Vec<RecordedVehicle>,
+    /// existing flight plans, including the held ones
+    pub existing_flight_plans: Vec<RecordedFlightPlan>,
+}
+
+/// Reason a candidate departure slot was rejected
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RejectionReason {
+    /// no pad free at the departure vertiport, or the vertiport is closed
+    DepartureVertiportUnavailable,
+    /// no pad free at the arrival vertiport and no parked vehicle could be moved away
+    ArrivalVertiportUnavailable,
+    /// no vehicle at or deadheading to the departure vertiport is available
+    NoVehicleAvailable,
+    /// the flight can't keep separated from an active flight, even staggered
+    SeparationConflict,
+    /// the UTM service didn't accept the intent of the flight or of its deadhead flights
+    UtmRejected,
+    /// no pilot within duty limits can fly the vehicle or a deadheading vehicle
+    NoPilotAvailable,
+    /// no vertiport is reachable from the arrival vertiport with the reserve energy
+    NoAlternateVertiport,
+}
+
+/// Decision on a candidate departure slot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SlotOutcome {
+    /// a flight plan was created with the given vehicle
+    Accepted {
+        /// id of the vehicle flying the flight plan
+        vehicle_id: String,
+    },
+    /// no flight plan was created
+    Rejected(RejectionReason),
+}
+
+/// Candidate departure slot evaluated by a query
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CandidateSlot {
+    /// departure time in seconds since the epoch
+    pub departure_time: i64,
+    /// decision on the slot
+    pub outcome: SlotOutcome,
+}
+
+impl CandidateSlot {
+    /// Slot for which a flight plan was created
+    pub fn accepted(departure_time: DateTime<Tz>, vehicle_id: &str) -> Self {
+        CandidateSlot {
+            departure_time: departure_time.timestamp(),
+            outcome: SlotOutcome::Accepted {
+                vehicle_id: vehicle_id.to_string(),
+            },
+        }
+    }
+
+    /// Slot rejected for the given reason
+    pub fn rejected(departure_time: DateTime<Tz>, reason: RejectionReason) -> Self {
+        CandidateSlot {
+            departure_time: departure_time.timestamp(),
+            outcome: SlotOutcome::Rejected(reason),
+        }
+    }
+}
+
+/// Flight plan returned by a recorded query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedFlight {
+    /// flight plan of the requested flight
+    pub flight_plan: RecordedFlightPlan,
+    /// deadhead flight plans bringing the vehicle to the departure vertiport
+    pub deadhead_flight_plans: Vec<RecordedFlightPlan>,
+    /// vertiport to divert to if the destination becomes unavailable
+    pub alternate_vertiport_id: String,
+}
+
+/// Result of a recorded query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AuditOutcome {
+    /// the flight plans returned
+    Flights(Vec<RecordedFlight>),
+    /// the error returned
+    Error(String),
+}
+
+/// Record of a scheduling decision
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// id of the record
+    pub id: String,
+    /// time of the query in seconds since the epoch
+    pub recorded_at: i64,
+    /// version of the library which took the decision
+    pub library_version: String,
+    /// inputs of the query
+    pub query: RecordedQuery,
+    /// candidate departure slots in the order evaluated
+    pub candidates: Vec<CandidateSlot>,
+    /// result of the query
+    pub outcome: AuditOutcome,
+}
+
+fn to_seconds(timestamp: &Option<Timestamp>) -> Option<i64> {
+    timestamp.as_ref().map(|timestamp| timestamp.seconds)
+}
+
+fn to_timestamp(seconds: Option<i64>) -> Option<Timestamp> {
+    seconds.map(|seconds| Timestamp { seconds, nanos: 0 })
+}
+
+impl From<&Vertiport> for RecordedVertiport {
+    fn from(vertiport: &Vertiport) -> Self {
+        let data = vertiport.data.clone().unwrap_or_default();
+        RecordedVertiport {
+            id: vertiport.id.clone(),
+            latitude: data.latitude,
+            longitude: data.longitude,
+            schedule: data.schedule,
+        }
+    }
+}
+
+impl From<&RecordedVertiport> for Vertiport {
+    fn from(vertiport: &RecordedVertiport) -> Self {
+        Vertiport {
+            id: vertiport.id.clone(),
+            data: Some(crate::resources::vertiport::Data {
+                latitude: vertiport.latitude,
+                longitude: vertiport.longitude,
+                schedule: vertiport.schedule.clone(),
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+impl From<&Vertipad> for RecordedVertipad {
+    fn from(vertipad: &Vertipad) -> Self {
+        let data = vertipad.data.clone().unwrap_or_default();
+        RecordedVertipad {
+            id: vertipad.id.clone(),
+            vertiport_id: data.vertiport_id,
+            enabled: data.enabled,
+            schedule: data.schedule,
+        }
+    }
+}
+
+impl From<&RecordedVertipad> for Vertipad {
+    fn from(vertipad: &RecordedVertipad) -> Self {
+        Vertipad {
+            id: vertipad.id.clone(),
+            data: Some(crate::resources::vertipad::Data {
+                vertiport_id: vertipad.vertiport_id.clone(),
+                enabled: vertipad.enabled,
+                schedule: vertipad.schedule.clone(),
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+impl From<&Vehicle> for RecordedVehicle {
+    fn from(vehicle: &Vehicle) -> Self {
+        let data = vehicle.data.clone().unwrap_or_default();
+        RecordedVehicle {
+            id: vehicle.id.clone(),
+            vehicle_model_id: data.vehicle_model_id,
+            last_vertiport_id: data.last_vertiport_id,
+            schedule: data.schedule,
+        }
+    }
+}
+
+impl From<&RecordedVehicle> for Vehicle {
+    fn from(vehicle: &RecordedVehicle) -> Self {
+        Vehicle {
+            id: vehicle.id.clone(),
+            data: Some(crate::resources::vehicle::Data {
+                vehicle_model_id: vehicle.vehicle_model_id.clone(),
+                last_vertiport_id: vehicle.last_vertiport_id.clone(),
+                schedule: vehicle.schedule.clone(),
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+impl RecordedFlightPlan {
+    /// Records flight plan data under the given id
+    pub fn new(id: &str, data: &FlightPlanData) -> Self {
+        RecordedFlightPlan {
+            id: id.to_string(),
+            vehicle_id: data.vehicle_id.clone(),
+            cargo_weight_grams: data.cargo_weight_grams.clone(),
+            departure_vertiport_id: data.departure_vertiport_id.clone(),
+            destination_vertiport_id: data.destination_vertiport_id.clone(),
+            departure_vertipad_id: data.departure_vertipad_id.clone(),
+            destination_vertipad_id: data.destination_vertipad_id.clone(),
+            scheduled_departure: to_seconds(&data.scheduled_departure),
+            scheduled_arrival: to_seconds(&data.scheduled_arrival),
+            actual_departure: to_seconds(&data.actual_departure),
+            actual_arrival: to_seconds(&data.actual_arrival),
+        }
+    }
+
+    /// Flight plan data of the recorded flight plan
+    pub fn to_flight_plan_data(&self) -> FlightPlanData {
+        FlightPlanData {
+            vehicle_id: self.vehicle_id.clone(),
+            cargo_weight_grams: self.cargo_weight_grams.clone(),
+            departure_vertiport_id: self.departure_vertiport_id.clone(),
+            destination_vertiport_id: self.destination_vertiport_id.clone(),
+            departure_vertipad_id: self.departure_vertipad_id.clone(),
+            destination_vertipad_id: self.destination_vertipad_id.clone(),
+            scheduled_departure: to_timestamp(self.scheduled_departure),
+            scheduled_arrival: to_timestamp(self.scheduled_arrival),
+            actual_departure: to_timestamp(self.actual_departure),
+            actual_arrival: to_timestamp(self.actual_arrival),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&FlightPlan> for RecordedFlightPlan {
+    fn from(flight_plan: &FlightPlan) -> Self {
+        RecordedFlightPlan::new(
+            &flight_plan.id,
+            &flight_plan.data.clone().unwrap_or_default(),
+        )
+    }
+}
+
+impl From<&RecordedFlightPlan> for FlightPlan {
+    fn from(flight_plan: &RecordedFlightPlan) -> Self {
+        FlightPlan {
+            id: flight_plan.id.clone(),
+            data: Some(flight_plan.to_flight_plan_data()),
+        }
+    }
+}
+
+impl From<&PossibleFlight> for RecordedFlight {
+    fn from(flight: &PossibleFlight) -> Self {
+        RecordedFlight {
+            flight_plan: RecordedFlightPlan::new("", &flight.flight_plan),
+            deadhead_flight_plans: flight
+                .deadhead_flight_plans
+                .iter()
+                .map(|data| RecordedFlightPlan::new("", data))
+                .collect(),
+            alternate_vertiport_id: flight.alternate_vertiport_id.clone(),
+        }
+    }
+}
+
+impl From<&Result<Vec<PossibleFlight>, String>> for AuditOutcome {
+    fn from(result: &Result<Vec<PossibleFlight>, String>) -> Self {
+        match result {
+            Ok(flights) => {
+                AuditOutcome::Flights(flights.iter().map(RecordedFlight::from).collect())
+            }
+            Err(e) => AuditOutcome::Error(e.clone()),
+        }
+    }
+}
+
+impl RecordedQuery {
+    /// Records the inputs of a query
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        vertiport_depart: &Vertiport,
+        vertiport_arrive: &Vertiport,
+        vertipads_depart: &[Vertipad],
+        vertipads_arrive: &[Vertipad],
+        earliest_departure_time: &Option<Timestamp>,
+        latest_arrival_time: &Option<Timestamp>,
+        vehicles: &[Vehicle],
+        existing_flight_plans: &[FlightPlan],
+    ) -> Self {
+        RecordedQuery {
+            departure_vertiport: vertiport_depart.into(),
+            arrival_vertiport: vertiport_arrive.into(),
+            departure_vertipads: vertipads_depart.iter().map(Into::into).collect(),
+            arrival_vertipads: vertipads_arrive.iter().map(Into::into).collect(),
+            earliest_departure_time: to_seconds(earliest_departure_time),
+            latest_arrival_time: to_seconds(latest_arrival_time),
+            vehicles: vehicles.iter().map(Into::into).collect(),
+            existing_flight_plans: existing_flight_plans.iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl AuditRecord {
+    /// Creates a record of a decision taken by this version of the library
+    pub fn new(
+        recorded_at: DateTime<Tz>,
+        query: RecordedQuery,
+        candidates: Vec<CandidateSlot>,
+        outcome: AuditOutcome,
+    ) -> Self {
+        AuditRecord {
+            id: Uuid::new_v4().to_string(),
+            recorded_at: recorded_at.timestamp(),
+            library_version: env!("CARGO_PKG_VERSION").to_string(),
+            query,
+            candidates,
+            outcome,
+        }
+    }
+
+    /// Serializes the record to a single line of JSON
+    pub fn to_json_line(&self) -> Result<String, String> {
+        serde_json::to_string(self).map_err(|e| e.to_string())
+    }
+
+    /// Parses a record from a line of JSON
+    pub fn from_json_line(line: &str) -> Result<Self, String> {
+        serde_json::from_str(line).map_err(|e| e.to_string())
+    }
+}
+
+/// Destination of the audit records
+pub trait AuditSink: Send + Sync {
+    /// Stores a record
+    fn record(&self, record: &AuditRecord);
+}
+
+/// Audit sink writing one JSON object per line
+#[derive(Debug)]
+pub struct JsonLinesAuditSink<W: Write + Send> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonLinesAuditSink<W> {
+    /// Creates a sink writing to `writer`
+    pub fn new(writer: W) -> Self {
+        JsonLinesAuditSink {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    /// Returns the writer
+    pub fn into_inner(self) -> W {
+        self.writer
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<W: Write + Send> AuditSink for JsonLinesAuditSink<W> {
+    fn record(&self, record: &AuditRecord) {
+        let line = match record.to_json_line() {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Unable to serialize audit record {}: {}", record.id, e);
+                return;
+            }
+        };
+        let Ok(mut writer) = self.writer.lock() else {
+            error!("Audit log unavailable");
+            return;
+        };
+        if let Err(e) = writeln!(writer, "{}", line).and_then(|_| writer.flush()) {
+            error!("Unable to write audit record {}: {}", record.id, e);
+        }
+    }
+}
+
+/// Audit sink keeping the records in memory
+#[derive(Debug, Default)]
+pub struct MemoryAuditSink {
+    records: Mutex<Vec<AuditRecord>>,
+}
+
+impl MemoryAuditSink {
+    /// Creates an empty sink
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the records stored so far
+    pub fn records(&self) -> Vec<AuditRecord> {
+        self.records
+            .lock()
+            .map(|records| records.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl AuditSink for MemoryAuditSink {
+    fn record(&self, record: &AuditRecord) {
+        match self.records.lock() {
+            Ok(mut records) => records.push(record.clone()),
+            Err(_) => error!("Audit log unavailable"),
+        }
+    }
+}
+
+/// Records all following decisions to the sink
+pub fn set_audit_sink(sink: Arc<dyn AuditSink>) {
+    match current_context().audit_sink.write() {
+        Ok(mut current) => *current = Some(sink),
+        Err(_) => error!("Audit log unavailable"),
+    }
+}
+
+/// Stops recording decisions
+pub fn clear_audit_sink() {
+    match current_context().audit_sink.write() {
+        Ok(mut current) => *current = None,
+        Err(_) => error!("Audit log unavailable"),
+    }
+}
+
+/// Checks if decisions are recorded
+pub fn is_audit_enabled() -> bool {
+    current_context()
+        .audit_sink
+        .read()
+        .is_ok_and(|sink| sink.is_some())
+}
+
+/// Passes a record to the audit sink, if one is set
+pub fn record(record: &AuditRecord) {
+    let sink = match current_context().audit_sink.read() {
+        Ok(sink) => sink.clone(),
+        Err(_) => {
+            error!("Audit log unavailable");
+            return;
+        }
+    };
+    if let Some(sink) = sink {
+        sink.record(record);
+    }
+}
+
+/// Reads the records of an audit log written by [`JsonLinesAuditSink`]
+///
+/// # Arguments
+/// * `reader` - the audit log, one JSON object per line
+///
+/// # Returns
+/// The records in the order of the log. Blank lines are skipped.
+pub fn read_audit_log<R: BufRead>(reader: R) -> Result<Vec<AuditRecord>, String> {
+    reader
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+        .map(|(index, line)| {
+            let line = line.map_err(|e| e.to_string())?;
+            AuditRecord::from_json_line(&line)
+                .map_err(|e| format!("Invalid audit record on line {}: {}", index + 1, e))
+        })
+        .collect()
+}
+
+/// Re-runs a recorded query against this version of the library
+///
+/// # Arguments
+/// * `record` - the recorded decision
+///
+/// # Returns
+/// A new record of the decision taken now, as of the time of the original
+/// query. The held flight plans are part of the recorded existing flight
+/// plans, so the current reservation ledger is not consulted.
+/// The UTM service and the crew provider are not consulted either, as their
+/// responses are not recorded.
+pub fn replay(record: &AuditRecord) -> AuditRecord {
+    info!(
+        "Replaying audit record {} recorded by version {}",
+        record.id, record.library_version
+    );
+    let query = &record.query;
+    let recorded_at: DateTime<Tz> = timestamp_to_datetime(record.recorded_at);
+    let clock = SimulatedClock::new(recorded_at);
+    let mut candidates = vec![];
+    let vertipads = |vertipads: &[RecordedVertipad]| -> Vec<Vertipad> {
+        vertipads.iter().map(Into::into).collect()
+    };
+    let vehicles: Vec<Vehicle> = query.vehicles.iter().map(Into::into).collect();
+    let existing_flight_plans: Vec<FlightPlan> =
+        query.existing_flight_plans.iter().map(Into::into).collect();
+    let result = find_possible_flights(
+        &(&query.departure_vertiport).into(),
+        &(&query.arrival_vertiport).into(),
+        &vertipads(&query.departure_vertipads),
+        &vertipads(&query.arrival_vertipads),
+        to_timestamp(query.earliest_departure_time),
+        to_timestamp(query.latest_arrival_time),
+        &vehicles,
+        &existing_flight_plans,
+        &clock,
+        None,
+        None,
+        None,
+        &mut candidates,
+    );
+    AuditRecord::new(recorded_at, query.clone(), candidates, (&result).into())
+}
+
+/// Lists the differences between the decisions of two records of the same query
+///
+/// # Arguments
+/// * `expected` - the recorded decision
+/// * `actual` - the decision of the replay
+///
+/// # Returns
+/// A description of each difference, empty if both decisions are the same
+pub fn compare_records(expected: &AuditRecord, actual: &AuditRecord) -> Vec<String> {
+    let mut differences = vec![];
+    if expected.query != actual.query {
+        differences.push("Queries differ".to_string());
+    }
+    let len = expected.candidates.len().max(actual.candidates.len());
+    for index in 0..len {
+        let expected_slot = expected.candidates.get(index);
+        let actual_slot = actual.candidates.get(index);
+        if expected_slot != actual_slot {
+            differences.push(format!(
+                "Candidate slot {}: expected {:?}, got {:?}",
+                index, expected_slot, actual_slot
+            ));
+        }
+    }
+    if expected.outcome != actual.outcome {
+        differences.push(format!(
+            "Outcome: expected {:?}, got {:?}",
+            expected.outcome, actual.outcome
+        ));
+    }
+    differences
+}
+
+#[cfg(test)]
+mod audit_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use chrono::{Duration, TimeZone};
+
+    fn start() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap()
+    }
+
+    fn query() -> RecordedQuery {
+        let vertiport = |id: &str| Vertiport {
+            id: id.to_string(),
+            data: Some(crate::resources::vertiport::Data {
+                schedule: Some("DTSTART:20221020T180000Z;DURATION:PT24H".to_string()),
+                ..Default::default()
+            }),
+        };
+        let vertipad = Vertipad {
+            id: "pad-a".to_string(),
+            data: Some(crate::resources::vertipad::Data {
+                vertiport_id: "A".to_string(),
+                enabled: true,
+                ..Default::default()
+            }),
+        };
+        let vehicle = Vehicle {
+            id: "v1".to_string(),
+            data: Some(crate::resources::vehicle::Data {
+                last_vertiport_id: Some("A".to_string()),
+                ..Default::default()
+            }),
+        };
+        let existing = FlightPlan {
+            id: "fp1".to_string(),
+            data: Some(create_flight_plan_data(
+                "v1".to_string(),
+                "B".to_string(),
+                "A".to_string(),
+                start() - Duration::minutes(60),
+                start() - Duration::minutes(30),
+            )),
+        };
+        RecordedQuery::new(
+            &vertiport("A"),
+            &vertiport("B"),
+            &[vertipad],
+            &[],
+            &Some(Timestamp {
+                seconds: start().timestamp(),
+                nanos: 0,
+            }),
+            &Some(Timestamp {
+                seconds: (start() + Duration::hours(2)).timestamp(),
+                nanos: 0,
+            }),
+            &[vehicle],
+            &[existing],
+        )
+    }
+
+    fn record() -> AuditRecord {
+        AuditRecord::new(
+            start() - Duration::hours(1),
+            query(),
+            vec![
+                CandidateSlot::rejected(start(), RejectionReason::NoVehicleAvailable),
+                CandidateSlot::accepted(start() + Duration::minutes(5), "v1"),
+            ],
+            AuditOutcome::Error("No flight plans found for given time window".to_string()),
+        )
+    }
+
+    #[test]
+    fn test_recorded_inputs_round_trip() {
+        let query = query();
+        let vehicle: Vehicle = (&query.vehicles[0]).into();
+        assert_eq!(RecordedVehicle::from(&vehicle), query.vehicles[0]);
+        let vertipad: Vertipad = (&query.departure_vertipads[0]).into();
+        assert_eq!(
+            RecordedVertipad::from(&vertipad),
+            query.departure_vertipads[0]
+        );
+        let vertiport: Vertiport = (&query.arrival_vertiport).into();
+        assert_eq!(RecordedVertiport::from(&vertiport), query.arrival_vertiport);
+        let flight_plan: FlightPlan = (&query.existing_flight_plans[0]).into();
+        assert_eq!(
+            RecordedFlightPlan::from(&flight_plan),
+            query.existing_flight_plans[0]
+        );
+        assert_eq!(
+            query.existing_flight_plans[0].scheduled_arrival,
+            Some((start() - Duration::minutes(30)).timestamp())
+        );
+    }
+
+    #[test]
+    fn test_json_lines_log() {
+        let sink = JsonLinesAuditSink::new(vec![]);
+        let first = record();
+        let second = record();
+        sink.record(&first);
+        sink.record(&second);
+        let log = sink.into_inner();
+        assert_eq!(log.iter().filter(|&&byte| byte == b'\n').count(), 2);
+        let records = read_audit_log(log.as_slice()).unwrap();
+        assert_eq!(records, vec![first, second]);
+        assert!(read_audit_log("{}\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_compare_records() {
+        let expected = record();
+        let mut actual = expected.clone();
+        actual.id = "replay".to_string();
+        assert!(compare_records(&expected, &actual).is_empty());
+
+        actual.candidates[0] =
+            CandidateSlot::rejected(start(), RejectionReason::DepartureVertiportUnavailable);
+        actual.candidates.pop();
+        let differences = compare_records(&expected, &actual);
+        assert_eq!(differences.len(), 2);
+        assert!(differences[0].starts_with("Candidate slot 0"));
+        assert!(differences[1].starts_with("Candidate slot 1"));
+    }
+}
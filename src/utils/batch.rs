@@ -0,0 +1,324 @@
+//! Joint assignment of vehicles to a batch of concurrent flight requests.
+//!
+//! Assigning vehicles greedily per request can use up a vehicle which was the
+//! only option for a later request. [`assign_batch`] instead computes the
+//! earliest feasible departure of every vehicle for every request and solves
+//! the assignment with the Hungarian algorithm, maximizing the number of
+//! served requests first and minimizing the total departure delay second.
+//! Every vehicle serves at most one request of the batch.
+
+use chrono::{DateTime, Duration};
+use rrule::Tz;
+
+use crate::conflict::{validate_flight_plans, ConflictKind};
+use crate::fleet_state::get_vehicle_location;
+use crate::router_state::{
+    create_flight_plan_data, is_vehicle_available, FlightPlan, FlightPlanData, Vehicle,
+    FLIGHT_PLAN_GAP_MINUTES,
+};
+
+/// Cost of leaving a request unserved, larger than any departure delay
+const UNSERVED_COST: i64 = 1_000_000_000;
+
+/// A flight request of a batch
+#[derive(Debug, Clone)]
+pub struct BatchRequest {
+    /// id of the request
+    pub id: String,
+    /// departure vertiport id
+    pub vertiport_depart_id: String,
+    /// arrival vertiport id
+    pub vertiport_arrive_id: String,
+    /// earliest departure time of the time window
+    pub earliest_departure_time: DateTime<Tz>,
+    /// latest arrival time of the time window
+    pub latest_arrival_time: DateTime<Tz>,
+    /// estimated flight time including takeoff and landing
+    pub flight_duration_minutes: i64,
+}
+
+/// Returns departure times in the request window at which the vehicle can fly the request
+fn feasible_departures(
+    request: &BatchRequest,
+    vehicle: &Vehicle,
+    existing_flight_plans: &[FlightPlan],
+) -> Vec<DateTime<Tz>> {
+    let latest_departure =
+        request.latest_arrival_time - Duration::minutes(request.flight_duration_minutes);
+    let mut departures = vec![];
+    let mut departure_time = request.earliest_departure_time;
+    while departure_time <= latest_departure {
+        let location = get_vehicle_location(vehicle, departure_time, existing_flight_plans);
+        if location.is_ok_and(|(vertiport_id, minutes_to_arrival)| {
+            vertiport_id == request.vertiport_depart_id && minutes_to_arrival == 0
+        }) && is_vehicle_available(
+            vehicle,
+            departure_time,
+            request.flight_duration_minutes,
+            existing_flight_plans,
+        )
+        .unwrap_or(false)
+        {
+            departures.push(departure_time);
+        }
+        departure_time += Duration::minutes(FLIGHT_PLAN_GAP_MINUTES as i64);
+    }
+    departures
+}
+
+/// Solves the assignment problem for a square cost matrix
+/// Returns the column assigned to each row with minimal total cost
+fn hungarian(cost: &[Vec<i64>]) -> Vec<usize> {
+    let n = cost.len();
+    // potentials and matching use 1-based indices, 0 is the virtual column
+    let mut u = vec![0_i64; n + 1];
+    let mut v = vec![0_i64; n + 1];
+    let mut row_of_col = vec![0_usize; n + 1];
+    let mut way = vec![0_usize; n + 1];
+    for row in 1..=n {
+        row_of_col[0] = row;
+        let mut col0 = 0;
+        let mut min_v = vec![i64::MAX; n + 1];
+        let mut used = vec![false; n + 1];
+        loop {
+            used[col0] = true;
+            let row0 = row_of_col[col0];
+            let mut delta = i64::MAX;
+            let mut col1 = 0;
+            for col in 1..=n {
+                if used[col] {
+                    continue;
+                }
+                let reduced = cost[row0 - 1][col - 1] - u[row0] - v[col];
+                if reduced < min_v[col] {
+                    min_v[col] = reduced;
+                    way[col] = col0;
+                }
+                if min_v[col] < delta {
+                    delta = min_v[col];
+                    col1 = col;
+                }
+            }
+            for col in 0..=n {
+                if used[col] {
+                    u[row_of_col[col]] += delta;
+                    v[col] -= delta;
+                } else {
+                    min_v[col] -= delta;
+                }
+            }
+            col0 = col1;
+            if row_of_col[col0] == 0 {
+                break;
+            }
+        }
+        loop {
+            let col1 = way[col0];
+            row_of_col[col0] = row_of_col[col1];
+            col0 = col1;
+            if col0 == 0 {
+                break;
+            }
+        }
+    }
+    let mut col_of_row = vec![0; n];
+    for col in 1..=n {
+        if row_of_col[col] > 0 {
+            col_of_row[row_of_col[col] - 1] = col - 1;
+        }
+    }
+    col_of_row
+}
+
+/// Assigns vehicles to a batch of concurrent requests
+///
+/// # Arguments
+/// * `requests` - flight requests of the batch
+/// * `vehicles` - vehicles available for the batch
+/// * `existing_flight_plans` - already scheduled flight plans
+///
+/// # Returns
+/// A draft flight plan for each request in the same order, or `None` if the
+/// request could not be served.
+pub fn assign_batch(
+    requests: &[BatchRequest],
+    vehicles: &[Vehicle],
+    existing_flight_plans: &[FlightPlan],
+) -> Vec<Option<FlightPlanData>> {
+    info!(
+        "Assigning {} vehicles to a batch of {} requests",
+        vehicles.len(),
+        requests.len()
+    );
+    let options: Vec<Vec<Vec<DateTime<Tz>>>> = requests
+        .iter()
+        .map(|request| {
+            vehicles
+                .iter()
+                .map(|vehicle| feasible_departures(request, vehicle, existing_flight_plans))
+                .collect()
+        })
+        .collect();
+
+    // square matrix: extra columns are dummy vehicles, extra rows dummy requests
+    let size = requests.len().max(vehicles.len());
+    let cost: Vec<Vec<i64>> = (0..size)
+        .map(|row| {
+            (0..size)
+                .map(|col| {
+                    match options
+                        .get(row)
+                        .and_then(|row| row.get(col))
+                        .and_then(|departures| departures.first())
+                    {
+                        Some(departure) => {
+                            (*departure - requests[row].earliest_departure_time).num_minutes()
+                        }
+                        None => UNSERVED_COST,
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let assignment = hungarian(&cost);
+
+    // accept assignments in departure order, moving to a later departure of the
+    // same vehicle if the pads are already taken by an accepted flight plan
+    let mut order: Vec<usize> = (0..requests.len())
+        .filter(|&row| cost[row][assignment[row]] < UNSERVED_COST)
+        .collect();
+    order.sort_by_key(|&row| options[row][assignment[row]][0]);
+    let mut flight_plans: Vec<Option<FlightPlanData>> = vec![None; requests.len()];
+    let mut accepted: Vec<FlightPlan> = existing_flight_plans.to_vec();
+    for row in order {
+        let request = &requests[row];
+        let vehicle = &vehicles[assignment[row]];
+        let flight_plan = options[row][assignment[row]]
+            .iter()
+            .map(|departure| {
+                create_flight_plan_data(
+                    vehicle.id.clone(),
+                    request.vertiport_depart_id.clone(),
+                    request.vertiport_arrive_id.clone(),
+                    *departure,
+                    *departure + Duration::minutes(request.flight_duration_minutes),
+                )
+            })
+            .find(|draft| {
+                validate_flight_plans(std::slice::from_ref(draft), &accepted)
+                    .iter()
+                    .all(|conflict| conflict.kind != ConflictKind::VertipadOverlap)
+            });
+        match flight_plan {
+            Some(flight_plan) => {
+                debug!("Request {} assigned to vehicle {}", request.id, vehicle.id);
+                accepted.push(FlightPlan {
+                    id: request.id.clone(),
+                    data: Some(flight_plan.clone()),
+                });
+                flight_plans[row] = Some(flight_plan);
+            }
+            None => debug!("Request {} has no free pads", request.id),
+        }
+    }
+    info!(
+        "Served {} of {} requests",
+        flight_plans.iter().filter(|plan| plan.is_some()).count(),
+        requests.len()
+    );
+    flight_plans
+}
+
+#[cfg(test)]
+mod batch_tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn start() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2022, 10, 25, 10, 0, 0).unwrap()
+    }
+
+    fn vehicle(id: &str, last_vertiport_id: &str) -> Vehicle {
+        Vehicle {
+            id: id.to_string(),
+            data: Some(crate::resources::vehicle::Data {
+                last_vertiport_id: Some(last_vertiport_id.to_string()),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn request(id: &str, from: &str, to: &str, window_minutes: i64) -> BatchRequest {
+        BatchRequest {
+            id: id.to_string(),
+            vertiport_depart_id: from.to_string(),
+            vertiport_arrive_id: to.to_string(),
+            earliest_departure_time: start(),
+            latest_arrival_time: start() + Duration::minutes(window_minutes),
+            flight_duration_minutes: 30,
+        }
+    }
+
+    #[test]
+    fn test_hungarian() {
+        let cost = vec![vec![4, 1, 3], vec![2, 0, 5], vec![3, 2, 2]];
+        assert_eq!(hungarian(&cost), vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn test_batch_serves_all_requests() {
+        // only v3 is parked at B
+        let vehicles = vec![vehicle("v1", "A"), vehicle("v2", "A"), vehicle("v3", "B")];
+        let requests = vec![request("r1", "A", "C", 120), request("r2", "B", "C", 120)];
+        let plans = assign_batch(&requests, &vehicles, &[]);
+        assert_eq!(plans[0].as_ref().unwrap().vehicle_id, "v1");
+        assert_eq!(plans[1].as_ref().unwrap().vehicle_id, "v3");
+    }
+
+    #[test]
+    fn test_batch_maximizes_served_requests() {
+        // v1 can serve both requests, v2 only the second
+        let vehicles = vec![vehicle("v1", "A"), vehicle("v2", "A")];
+        let existing = vec![FlightPlan {
+            id: "fp1".to_string(),
+            data: Some(create_flight_plan_data(
+                "v2".to_string(),
+                "A".to_string(),
+                "D".to_string(),
+                start() - Duration::minutes(60),
+                start() + Duration::minutes(20),
+            )),
+        }];
+        let requests = vec![request("r1", "A", "C", 40), request("r2", "D", "C", 120)];
+        let plans = assign_batch(&requests, &vehicles, &existing);
+        assert_eq!(plans[0].as_ref().unwrap().vehicle_id, "v1");
+        assert_eq!(plans[1].as_ref().unwrap().vehicle_id, "v2");
+    }
+
+    #[test]
+    fn test_batch_shifts_for_pad_conflict() {
+        let vehicles = vec![vehicle("v1", "A"), vehicle("v2", "A")];
+        let requests = vec![request("r1", "A", "B", 120), request("r2", "A", "C", 120)];
+        let plans = assign_batch(&requests, &vehicles, &[]);
+        let departures: Vec<i64> = plans
+            .iter()
+            .map(|plan| {
+                plan.as_ref()
+                    .unwrap()
+                    .scheduled_departure
+                    .as_ref()
+                    .unwrap()
+                    .seconds
+            })
+            .collect();
+        assert!((departures[0] - departures[1]).abs() >= 10 * 60);
+    }
+
+    #[test]
+    fn test_batch_unserved_request() {
+        let vehicles = vec![vehicle("v1", "A")];
+        let requests = vec![request("r1", "A", "B", 60), request("r2", "A", "C", 60)];
+        let plans = assign_batch(&requests, &vehicles, &[]);
+        assert_eq!(plans.iter().filter(|plan| plan.is_some()).count(), 1);
+    }
+}
@@ -0,0 +1,172 @@
+//! Hourly throughput limits of vertiports.
+//!
+//! Besides blocking a pad for takeoff and landing, a vertiport can only
+//! handle a limited number of movements (takeoffs and landings) per hour.
+//! Capacities are configured per vertiport id with [`set_vertiport_capacity`]
+//! and checked by [`is_vertiport_available`](crate::router_state::is_vertiport_available)
+//! over every rolling hour containing the requested movement. Vertiports
+//! without a configured capacity are not limited.
+
+use chrono::{DateTime, Duration};
+use rrule::Tz;
+
+use crate::context::current_context;
+use crate::router_state::{valid_plan_times, FlightPlan};
+
+/// Sets the max number of takeoffs and landings per hour at a vertiport
+pub fn set_vertiport_capacity(vertiport_id: &str, max_movements_per_hour: u32) {
+    match current_context().vertiport_capacities.write() {
+        Ok(mut capacities) => {
+            capacities.insert(vertiport_id.to_string(), max_movements_per_hour);
+        }
+        Err(_) => error!("Vertiport capacities unavailable"),
+    }
+}
+
+/// Removes the capacity limit of a vertiport
+pub fn clear_vertiport_capacity(vertiport_id: &str) {
+    match current_context().vertiport_capacities.write() {
+        Ok(mut capacities) => {
+            capacities.remove(vertiport_id);
+        }
+        Err(_) => error!("Vertiport capacities unavailable"),
+    }
+}
+
+/// Gets the max number of movements per hour at a vertiport, if limited
+pub fn get_vertiport_capacity(vertiport_id: &str) -> Option<u32> {
+    current_context()
+        .vertiport_capacities
+        .read()
+        .ok()
+        .and_then(|capacities| capacities.get(vertiport_id).copied())
+}
+
+/// Returns times of all scheduled takeoffs and landings at the vertiport, in seconds since epoch
+/// Malformed flight plans are skipped and reported
+pub fn get_vertiport_movements(
+    vertiport_id: &str,
+    existing_flight_plans: &[FlightPlan],
+) -> Vec<i64> {
+    valid_plan_times(existing_flight_plans)
+        .flat_map(|plan| {
+            let mut movements = vec![];
+            if plan.departure_vertiport_id == vertiport_id {
+                movements.push(plan.departure);
+            }
+            if plan.destination_vertiport_id == vertiport_id {
+                movements.push(plan.arrival);
+            }
+            movements
+        })
+        .collect()
+}
+
+/// Checks if a new movement at `timestamp` would exceed `capacity` in any
+/// rolling hour, given the already scheduled movements
+pub fn exceeds_hourly_capacity(capacity: u32, timestamp: i64, movements: &[i64]) -> bool {
+    let hour = Duration::hours(1).num_seconds();
+    // the busiest hour containing the new movement starts at a movement
+    std::iter::once(timestamp)
+        .chain(
+            movements
+                .iter()
+                .copied()
+                .filter(|movement| *movement > timestamp - hour && *movement <= timestamp),
+        )
+        .any(|window_start| {
+            let count = movements
+                .iter()
+                .filter(|movement| **movement >= window_start && **movement < window_start + hour)
+                .count()
+                + 1;
+            count > capacity as usize
+        })
+}
+
+/// Checks if a takeoff or landing at the vertiport is within its hourly capacity
+pub fn is_within_vertiport_capacity(
+    vertiport_id: &str,
+    date_from: DateTime<Tz>,
+    existing_flight_plans: &[FlightPlan],
+) -> bool {
+    let Some(capacity) = get_vertiport_capacity(vertiport_id) else {
+        return true;
+    };
+    let movements = get_vertiport_movements(vertiport_id, existing_flight_plans);
+    let exceeded = exceeds_hourly_capacity(capacity, date_from.timestamp(), &movements);
+    if exceeded {
+        debug!(
+            "Vertiport {} reached its capacity of {} movements per hour at {}",
+            vertiport_id, capacity, date_from
+        );
+    }
+    !exceeded
+}
+
+#[cfg(test)]
+mod capacity_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use chrono::TimeZone;
+
+    const MINUTE: i64 = 60;
+
+    #[test]
+    fn test_exceeds_hourly_capacity() {
+        let movements = vec![0, 20 * MINUTE, 40 * MINUTE];
+        assert!(exceeds_hourly_capacity(3, 50 * MINUTE, &movements));
+        assert!(!exceeds_hourly_capacity(4, 50 * MINUTE, &movements));
+        // window starting at 20 min holds 20, 40 and 70
+        assert!(!exceeds_hourly_capacity(3, 70 * MINUTE, &movements));
+        assert!(exceeds_hourly_capacity(2, 70 * MINUTE, &movements));
+        // window starting at the new movement
+        assert!(exceeds_hourly_capacity(3, -10 * MINUTE, &movements));
+        assert!(!exceeds_hourly_capacity(1, 120 * MINUTE, &movements));
+    }
+
+    #[test]
+    fn test_vertiport_capacity() {
+        let start = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap();
+        let mut flight_plans: Vec<FlightPlan> = (0..2)
+            .map(|i| FlightPlan {
+                id: format!("fp{}", i),
+                data: Some(create_flight_plan_data(
+                    "v1".to_string(),
+                    "capacity-test-a".to_string(),
+                    "capacity-test-b".to_string(),
+                    start + Duration::minutes(i * 20),
+                    start + Duration::minutes(i * 20 + 15),
+                )),
+            })
+            .collect();
+        // without scheduled times
+        flight_plans.push(FlightPlan {
+            id: "fp-malformed".to_string(),
+            data: None,
+        });
+        assert_eq!(
+            get_vertiport_movements("capacity-test-a", &flight_plans).len(),
+            2
+        );
+        let date_from = start + Duration::minutes(30);
+        assert!(is_within_vertiport_capacity(
+            "capacity-test-a",
+            date_from,
+            &flight_plans
+        ));
+        set_vertiport_capacity("capacity-test-a", 2);
+        assert_eq!(get_vertiport_capacity("capacity-test-a"), Some(2));
+        assert!(!is_within_vertiport_capacity(
+            "capacity-test-a",
+            date_from,
+            &flight_plans
+        ));
+        clear_vertiport_capacity("capacity-test-a");
+        assert!(is_within_vertiport_capacity(
+            "capacity-test-a",
+            date_from,
+            &flight_plans
+        ));
+    }
+}
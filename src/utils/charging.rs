@@ -0,0 +1,425 @@
+//! Charging sessions of the vehicles between their flights.
+//!
+//! A vertiport has a number of chargers of the same power, configured per
+//! vertiport id with [`set_vertiport_chargers`]. [`plan_charging_sessions`]
+//! charges a vehicle on the ground between its flights, and like pads are
+//! checked against the flight plans already scheduled, a session is only
+//! planned on a charger free of the charging reservations already made at
+//! the vertiport. Each session is an explicit [`ChargingReservation`] of a
+//! charger, to keep with the flight plans so two vehicles never plan to use
+//! the same charger at the same time. Vertiports without configured chargers
+//! can't charge vehicles.
+
+use chrono::{DateTime, Duration};
+use rrule::Tz;
+
+use crate::amendment::{timestamp_to_datetime, PlanTimes};
+use crate::context::current_context;
+use crate::energy::{
+    get_effective_capacity_kwh, projected_state_of_charge, ChargingBlock, VehicleBattery,
+};
+use crate::router_state::FlightPlan;
+
+/// Chargers of a vertiport
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VertiportChargers {
+    /// number of chargers
+    pub count: u32,
+    /// power of each charger
+    pub power_kw: f32,
+}
+
+/// Charger reserved for a vehicle
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChargingReservation {
+    /// id of the reservation, `charging-<vehicle id>-<start in seconds>`
+    pub id: String,
+    /// id of the charging vehicle
+    pub vehicle_id: String,
+    /// id of the vertiport of the charger
+    pub vertiport_id: String,
+    /// index of the charger at the vertiport, from 0
+    pub charger: u32,
+    /// start of the charge
+    pub start: DateTime<Tz>,
+    /// end of the charge
+    pub end: DateTime<Tz>,
+    /// power charged
+    pub power_kw: f32,
+}
+
+impl ChargingReservation {
+    /// Energy charged during the reservation
+    pub fn energy_kwh(&self) -> f32 {
+        self.power_kw * (self.end - self.start).num_seconds() as f32 / 3600.0
+    }
+
+    /// Charging block of the vehicle, for its
+    /// [`VehicleBattery`](crate::energy::VehicleBattery)
+    pub fn charging_block(&self) -> ChargingBlock {
+        ChargingBlock {
+            start: self.start,
+            end: self.end,
+            power_kw: Some(self.power_kw),
+        }
+    }
+
+    fn overlaps(&self, start: DateTime<Tz>, end: DateTime<Tz>) -> bool {
+        self.start < end && start < self.end
+    }
+}
+
+/// Finds a charger of the vertiport free from `start` to `end`
+///
+/// # Returns
+/// The index of the first free charger, None if all are reserved at some
+/// point of the time or the vertiport has no chargers
+pub fn find_free_charger(
+    vertiport_id: &str,
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+    existing_reservations: &[ChargingReservation],
+) -> Option<u32> {
+    let chargers = get_vertiport_chargers(vertiport_id)?;
+    let reserved: Vec<u32> = existing_reservations
+        .iter()
+        .filter(|reservation| {
+            reservation.vertiport_id == vertiport_id && reservation.overlaps(start, end)
+        })
+        .map(|reservation| reservation.charger)
+        .collect();
+    (0..chargers.count).find(|charger| !reserved.contains(charger))
+}
+
+/// Plans the charging sessions of a vehicle between its flights
+///
+/// The vehicle charges at the destination of each of its flights until its
+/// next departure, and before its first flight at its departure vertiport,
+/// from the last known state of charge of the battery until `until`. Each
+/// session lasts until the battery is full, at the lower power of the
+/// battery and the chargers, and starts as soon as a charger is free. A
+/// session starting when another one at the vertiport ends may be shorter
+/// than needed.
+///
+/// # Arguments
+/// * `vehicle_id` - the vehicle to charge
+/// * `battery` - battery of the vehicle, its charging blocks are kept
+/// * `until` - end of the planning, the end of the charge after the last flight
+/// * `existing_flight_plans` - flight plans of the vehicle, others are ignored
+/// * `existing_reservations` - chargers already reserved, by any vehicle
+///
+/// # Returns
+/// The new reservations, in time order
+pub fn plan_charging_sessions(
+    vehicle_id: &str,
+    battery: &VehicleBattery,
+    until: DateTime<Tz>,
+    existing_flight_plans: &[FlightPlan],
+    existing_reservations: &[ChargingReservation],
+) -> Vec<ChargingReservation> {
+    let mut flights: Vec<PlanTimes> = existing_flight_plans
+        .iter()
+        .filter_map(|flight_plan| PlanTimes::from_flight_plan(flight_plan).ok())
+        .filter(|plan| {
+            plan.vehicle_id == vehicle_id && plan.departure >= battery.updated_at.timestamp()
+        })
+        .collect();
+    flights.sort_by_key(|plan| plan.departure);
+    // (vertiport id, start, end) of the time on the ground
+    let mut ground: Vec<(&str, i64, i64)> = vec![];
+    if let Some(first) = flights.first() {
+        ground.push((
+            &first.departure_vertiport_id,
+            battery.updated_at.timestamp(),
+            first.departure,
+        ));
+    }
+    for (index, flight) in flights.iter().enumerate() {
+        let next_departure = flights
+            .get(index + 1)
+            .map_or(until.timestamp(), |next| next.departure);
+        ground.push((
+            &flight.destination_vertiport_id,
+            flight.arrival,
+            next_departure,
+        ));
+    }
+
+    let mut battery = battery.clone();
+    let mut reservations: Vec<ChargingReservation> = vec![];
+    for (vertiport_id, start, end) in ground {
+        let (start, end) = (timestamp_to_datetime(start), timestamp_to_datetime(end));
+        let Some(chargers) = get_vertiport_chargers(vertiport_id) else {
+            continue;
+        };
+        let power_kw = chargers.power_kw.min(battery.charge_rate_kw);
+        if start >= end || power_kw <= 0.0 {
+            continue;
+        }
+        let state_of_charge =
+            projected_state_of_charge(vehicle_id, &battery, start, existing_flight_plans);
+        let capacity_kwh = get_effective_capacity_kwh(vehicle_id, &battery);
+        let needed_kwh = capacity_kwh * (1.0 - state_of_charge);
+        if needed_kwh <= 0.0 {
+            continue;
+        }
+        let duration = Duration::seconds((needed_kwh / power_kw * 3600.0).ceil() as i64);
+
+        // a charger frees up at the start or when a reservation ends
+        let reserved: Vec<ChargingReservation> = existing_reservations
+            .iter()
+            .chain(&reservations)
+            .filter(|reservation| reservation.vertiport_id == vertiport_id)
+            .cloned()
+            .collect();
+        let mut starts: Vec<DateTime<Tz>> = std::iter::once(start)
+            .chain(reserved.iter().map(|reservation| reservation.end))
+            .filter(|time| start <= *time && *time < end)
+            .collect();
+        starts.sort();
+        let session = starts.into_iter().find_map(|session_start| {
+            let session_end = (session_start + duration).min(end);
+            let charger = find_free_charger(vertiport_id, session_start, session_end, &reserved)?;
+            Some((charger, session_start, session_end))
+        });
+        let Some((charger, session_start, session_end)) = session else {
+            debug!(
+                "No charger free for vehicle {} at {} from {} to {}",
+                vehicle_id, vertiport_id, start, end
+            );
+            continue;
+        };
+        let reservation = ChargingReservation {
+            id: format!("charging-{}-{}", vehicle_id, session_start.timestamp()),
+            vehicle_id: vehicle_id.to_string(),
+            vertiport_id: vertiport_id.to_string(),
+            charger,
+            start: session_start,
+            end: session_end,
+            power_kw,
+        };
+        debug!(
+            "Charging vehicle {} at {} on charger {} from {} to {}",
+            vehicle_id, vertiport_id, charger, session_start, session_end
+        );
+        battery.charging_blocks.push(reservation.charging_block());
+        reservations.push(reservation);
+    }
+    reservations
+}
+
+/// Sets the chargers of a vertiport
+pub fn set_vertiport_chargers(vertiport_id: &str, chargers: VertiportChargers) {
+    match current_context().vertiport_chargers.write() {
+        Ok(mut all_chargers) => {
+            all_chargers.insert(vertiport_id.to_string(), chargers);
+        }
+        Err(_) => error!("Vertiport chargers unavailable"),
+    }
+}
+
+/// Removes the chargers of a vertiport
+pub fn clear_vertiport_chargers(vertiport_id: &str) {
+    match current_context().vertiport_chargers.write() {
+        Ok(mut all_chargers) => {
+            all_chargers.remove(vertiport_id);
+        }
+        Err(_) => error!("Vertiport chargers unavailable"),
+    }
+}
+
+/// Gets the chargers of a vertiport, if any
+pub fn get_vertiport_chargers(vertiport_id: &str) -> Option<VertiportChargers> {
+    current_context()
+        .vertiport_chargers
+        .read()
+        .ok()
+        .and_then(|all_chargers| all_chargers.get(vertiport_id).copied())
+}
+
+#[cfg(test)]
+mod charging_tests {
+    use super::*;
+    use crate::context::RouterContext;
+    use crate::test_support::{mock_start, MockFlightPlan};
+    use std::sync::Arc;
+
+    /// Full 40 kWh battery charging at up to 48 kW
+    fn battery() -> VehicleBattery {
+        VehicleBattery {
+            capacity_kwh: 40.0,
+            charge_rate_kw: 48.0,
+            reserve_state_of_charge: 0.2,
+            state_of_charge: 1.0,
+            updated_at: mock_start(),
+            charging_blocks: vec![],
+        }
+    }
+
+    #[test]
+    fn test_plan_charging_sessions() {
+        // flights of 50 minutes using 12 kWh
+        let flight = |vehicle_id: &str, from: &str, to: &str, hour: i64| {
+            MockFlightPlan::new(&format!("{}-{}", vehicle_id, hour), vehicle_id, from, to)
+                .with_departure(mock_start() + Duration::hours(hour))
+                .with_duration(Duration::minutes(50))
+                .build()
+        };
+        set_vertiport_chargers(
+            "CHG-A",
+            VertiportChargers {
+                count: 1,
+                power_kw: 24.0,
+            },
+        );
+        let flight_plans = vec![
+            flight("charging-1", "CHG-B", "CHG-A", 0),
+            flight("charging-1", "CHG-A", "CHG-B", 2),
+            flight("charging-2", "CHG-B", "CHG-A", 0),
+        ];
+        let until = mock_start() + Duration::hours(4);
+
+        // 12 kWh at 24 kW from the landing at 8:50, nothing at CHG-B
+        let first = plan_charging_sessions("charging-1", &battery(), until, &flight_plans, &[]);
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].vertiport_id, "CHG-A");
+        assert_eq!(first[0].charger, 0);
+        assert_eq!(first[0].start, mock_start() + Duration::minutes(50));
+        assert_eq!(first[0].end, mock_start() + Duration::minutes(80));
+        assert!((first[0].energy_kwh() - 12.0).abs() < 1e-4);
+
+        // the only charger is reserved until 9:20
+        let second = plan_charging_sessions("charging-2", &battery(), until, &flight_plans, &first);
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].start, mock_start() + Duration::minutes(80));
+        assert_eq!(second[0].end, mock_start() + Duration::minutes(110));
+        assert!(!first[0].overlaps(second[0].start, second[0].end));
+        assert_eq!(
+            find_free_charger(
+                "CHG-A",
+                mock_start() + Duration::minutes(60),
+                mock_start() + Duration::minutes(70),
+                &first
+            ),
+            None
+        );
+        assert_eq!(
+            find_free_charger(
+                "CHG-B",
+                mock_start(),
+                mock_start() + Duration::minutes(1),
+                &[]
+            ),
+            None
+        );
+
+        // charged, the vehicle is projected full again
+        let mut charged = battery();
+        charged
+            .charging_blocks
+            .extend(first.iter().map(ChargingReservation::charging_block));
+        let state_of_charge = projected_state_of_charge(
+            "charging-1",
+            &charged,
+            mock_start() + Duration::hours(2),
+            &flight_plans,
+        );
+        assert!((state_of_charge - 1.0).abs() < 1e-4);
+
+        clear_vertiport_chargers("CHG-A");
+        assert!(get_vertiport_chargers("CHG-A").is_none());
+        assert!(
+            plan_charging_sessions("charging-1", &battery(), until, &flight_plans, &[]).is_empty()
+        );
+    }
+    #[test]
+    fn test_charging_contention() {
+        // chargers configured in a context of their own
+        Arc::new(RouterContext::new()).scope(|| {
+            // flights of 50 minutes using 12 kWh
+            let flight = |vehicle_id: &str, from: &str, to: &str, hour: i64| {
+                MockFlightPlan::new(&format!("{}-{}", vehicle_id, hour), vehicle_id, from, to)
+                    .with_departure(mock_start() + Duration::hours(hour))
+                    .with_duration(Duration::minutes(50))
+                    .build()
+            };
+            set_vertiport_chargers(
+                "CHG-C",
+                VertiportChargers {
+                    count: 2,
+                    power_kw: 24.0,
+                },
+            );
+            let reservation =
+                |vertiport_id: &str, charger: u32, from: i64, to: i64| ChargingReservation {
+                    id: format!("charging-other-{}-{}", charger, from),
+                    vehicle_id: "charging-other".to_string(),
+                    vertiport_id: vertiport_id.to_string(),
+                    charger,
+                    start: mock_start() + Duration::minutes(from),
+                    end: mock_start() + Duration::minutes(to),
+                    power_kw: 24.0,
+                };
+            // overlapping blocks on both chargers, and a block at another vertiport
+            let reserved = vec![
+                reservation("CHG-C", 0, 0, 60),
+                reservation("CHG-C", 1, 30, 90),
+                reservation("CHG-D", 0, 0, 240),
+            ];
+            let free = |from: i64, to: i64| {
+                find_free_charger(
+                    "CHG-C",
+                    mock_start() + Duration::minutes(from),
+                    mock_start() + Duration::minutes(to),
+                    &reserved,
+                )
+            };
+            assert_eq!(free(0, 20), Some(1));
+            assert_eq!(free(40, 50), None);
+            assert_eq!(free(20, 70), None);
+            // a block ending as the session starts is free
+            assert_eq!(free(60, 80), Some(0));
+            assert_eq!(free(90, 100), Some(0));
+
+            // lands at 8:50 with both chargers busy, the first frees up at 9:00
+            let until = mock_start() + Duration::hours(4);
+            let flight_plans = vec![flight("charging-3", "CHG-D", "CHG-C", 0)];
+            let sessions =
+                plan_charging_sessions("charging-3", &battery(), until, &flight_plans, &reserved);
+            assert_eq!(sessions.len(), 1);
+            assert_eq!(sessions[0].charger, 0);
+            assert_eq!(sessions[0].start, mock_start() + Duration::minutes(60));
+            assert_eq!(sessions[0].end, mock_start() + Duration::minutes(90));
+
+            // the next departure at 9:00 cuts the session short, or leaves no
+            // time at all if the chargers are busy until then
+            let turnaround = vec![
+                flight("charging-3", "CHG-D", "CHG-C", 0),
+                flight("charging-3", "CHG-C", "CHG-D", 1),
+            ];
+            let sessions =
+                plan_charging_sessions("charging-3", &battery(), until, &turnaround, &[]);
+            assert_eq!(sessions.len(), 1);
+            assert_eq!(sessions[0].end, mock_start() + Duration::minutes(60));
+            assert!((sessions[0].energy_kwh() - 4.0).abs() < 1e-4);
+            assert!(plan_charging_sessions(
+                "charging-3",
+                &battery(),
+                until,
+                &turnaround,
+                &reserved
+            )
+            .is_empty());
+
+            // a battery which can't take a charge
+            let no_charge = VehicleBattery {
+                charge_rate_kw: 0.0,
+                ..battery()
+            };
+            assert!(
+                plan_charging_sessions("charging-3", &no_charge, until, &flight_plans, &[])
+                    .is_empty()
+            );
+        });
+    }
+}
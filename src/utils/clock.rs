@@ -0,0 +1,116 @@
+//! Source of the current time.
+//!
+//! Holds, telemetry staleness and other time dependent checks ask a
+//! [`Clock`] for the current time instead of reading the system time
+//! directly, so unit tests and the simulator can control time. Functions
+//! taking a `clock` argument use the given clock; the others use the clock
+//! of the current [context](crate::context) set with [`set_clock`], which is
+//! the [`SystemClock`] by default.
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rrule::Tz;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+use crate::amendment::timestamp_to_datetime;
+use crate::context::current_context;
+
+/// Source of the current time
+pub trait Clock: Send + Sync {
+    /// Current time
+    fn now(&self) -> DateTime<Tz>;
+}
+
+/// Clock reading the system time
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Tz> {
+        Tz::UTC.from_utc_datetime(&Utc::now().naive_utc())
+    }
+}
+
+/// Clock only moving when set or advanced, with a precision of a second
+#[derive(Debug)]
+pub struct SimulatedClock {
+    seconds: AtomicI64,
+}
+
+impl SimulatedClock {
+    /// Creates a clock stopped at `start`
+    pub fn new(start: DateTime<Tz>) -> Self {
+        SimulatedClock {
+            seconds: AtomicI64::new(start.timestamp()),
+        }
+    }
+
+    /// Sets the clock to `time`
+    pub fn set(&self, time: DateTime<Tz>) {
+        self.seconds.store(time.timestamp(), Ordering::SeqCst);
+    }
+
+    /// Moves the clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        self.seconds
+            .fetch_add(duration.num_seconds(), Ordering::SeqCst);
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> DateTime<Tz> {
+        timestamp_to_datetime(self.seconds.load(Ordering::SeqCst))
+    }
+}
+
+/// Sets the clock of the current [context](crate::context)
+pub fn set_clock(clock: Arc<dyn Clock>) {
+    match current_context().clock.write() {
+        Ok(mut current) => *current = clock,
+        Err(_) => error!("Clock unavailable"),
+    }
+}
+
+/// Restores the system clock as the clock of the current context
+pub fn reset_clock() {
+    set_clock(Arc::new(SystemClock));
+}
+
+/// Gets the clock of the current context
+pub fn get_clock() -> Arc<dyn Clock> {
+    match current_context().clock.read() {
+        Ok(clock) => clock.clone(),
+        Err(_) => {
+            error!("Clock unavailable");
+            Arc::new(SystemClock)
+        }
+    }
+}
+
+/// Current time of the clock of the current context
+pub fn now() -> DateTime<Tz> {
+    get_clock().now()
+}
+
+#[cfg(test)]
+mod clock_tests {
+    use super::*;
+
+    #[test]
+    fn test_simulated_clock() {
+        let start = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap();
+        let clock = SimulatedClock::new(start);
+        assert_eq!(clock.now(), start);
+        clock.advance(Duration::minutes(90));
+        assert_eq!(clock.now(), start + Duration::minutes(90));
+        clock.set(start);
+        assert_eq!(clock.now(), start);
+    }
+
+    #[test]
+    fn test_system_clock() {
+        let before = Utc::now().timestamp();
+        let now = SystemClock.now().timestamp();
+        assert!(now >= before && now <= Utc::now().timestamp());
+    }
+}
@@ -0,0 +1,262 @@
+//! Conflict detection for a batch of proposed flight plans.
+//!
+//! [`validate_flight_plans`] checks draft flight plans against each other and
+//! against existing flight plans, and returns every conflict found so the
+//! scheduler can decide how to resolve them. Conflicts between two existing
+//! flight plans are not reported.
+
+use crate::amendment::{pad_conflict_minutes, timestamp_to_datetime, PlanTimes};
+use crate::curfew::is_in_curfew;
+use crate::router_state::{FlightPlan, FlightPlanData};
+
+/// Reference to a flight plan involved in a conflict
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FlightPlanRef {
+    /// Index into the draft flight plans
+    Draft(usize),
+    /// Id of an existing flight plan
+    Existing(String),
+}
+
+/// Type of a conflict between flight plans
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ConflictKind {
+    /// The same vehicle is scheduled for overlapping flights
+    VehicleDoubleBooked,
+    /// Two flights use the same vertipad within the takeoff or landing block time
+    VertipadOverlap,
+    /// The vehicle lands at a different vertiport than it departs from next
+    ImpossibleTurnaround,
+    /// The draft flight plan is missing its times or arrives before departing
+    InvalidSchedule,
+    /// The draft flight plan takes off or lands during a vertiport curfew
+    Curfew,
+}
+
+/// A conflict found between flight plans
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// type of the conflict
+    pub kind: ConflictKind,
+    /// flight plans involved, in departure order
+    pub flight_plans: Vec<FlightPlanRef>,
+}
+
+/// Checks draft flight plans against each other and against existing flight plans
+///
+/// # Arguments
+/// * `drafts` - proposed flight plans, referenced by index in the conflicts
+/// * `existing_flight_plans` - already scheduled flight plans
+///
+/// # Returns
+/// All conflicts involving at least one draft flight plan.
+pub fn validate_flight_plans(
+    drafts: &[FlightPlanData],
+    existing_flight_plans: &[FlightPlan],
+) -> Vec<Conflict> {
+    let mut conflicts: Vec<Conflict> = vec![];
+    let mut plans: Vec<(FlightPlanRef, PlanTimes)> = vec![];
+    for (index, draft) in drafts.iter().enumerate() {
+        match PlanTimes::from_data(&format!("draft-{}", index), draft) {
+            Ok(plan) if plan.arrival > plan.departure => {
+                if is_in_curfew(
+                    &plan.departure_vertiport_id,
+                    timestamp_to_datetime(plan.departure),
+                ) || is_in_curfew(
+                    &plan.destination_vertiport_id,
+                    timestamp_to_datetime(plan.arrival),
+                ) {
+                    conflicts.push(Conflict {
+                        kind: ConflictKind::Curfew,
+                        flight_plans: vec![FlightPlanRef::Draft(index)],
+                    });
+                }
+                plans.push((FlightPlanRef::Draft(index), plan))
+            }
+            _ => conflicts.push(Conflict {
+                kind: ConflictKind::InvalidSchedule,
+                flight_plans: vec![FlightPlanRef::Draft(index)],
+            }),
+        }
+    }
+    for flight_plan in existing_flight_plans {
+        match PlanTimes::from_flight_plan(flight_plan) {
+            Ok(plan) => plans.push((FlightPlanRef::Existing(flight_plan.id.clone()), plan)),
+            Err(e) => debug!("Skipping existing flight plan: {}", e),
+        }
+    }
+    plans.sort_by_key(|(_, plan)| plan.departure);
+
+    for (i, (first_ref, first)) in plans.iter().enumerate() {
+        for (second_ref, second) in plans.iter().skip(i + 1) {
+            if matches!(first_ref, FlightPlanRef::Existing(_))
+                && matches!(second_ref, FlightPlanRef::Existing(_))
+            {
+                continue;
+            }
+            let flight_plans = vec![first_ref.clone(), second_ref.clone()];
+            if first.vehicle_id == second.vehicle_id && second.departure < first.arrival {
+                conflicts.push(Conflict {
+                    kind: ConflictKind::VehicleDoubleBooked,
+                    flight_plans: flight_plans.clone(),
+                });
+            }
+            if pad_conflict_minutes(second, first).is_some() {
+                conflicts.push(Conflict {
+                    kind: ConflictKind::VertipadOverlap,
+                    flight_plans,
+                });
+            }
+        }
+    }
+
+    // consecutive flights of the same vehicle must connect at the same vertiport
+    for (i, (first_ref, first)) in plans.iter().enumerate() {
+        let Some((second_ref, second)) = plans
+            .iter()
+            .skip(i + 1)
+            .find(|(_, other)| other.vehicle_id == first.vehicle_id)
+        else {
+            continue;
+        };
+        if matches!(first_ref, FlightPlanRef::Existing(_))
+            && matches!(second_ref, FlightPlanRef::Existing(_))
+        {
+            continue;
+        }
+        if second.departure >= first.arrival
+            && second.departure_vertiport_id != first.destination_vertiport_id
+        {
+            conflicts.push(Conflict {
+                kind: ConflictKind::ImpossibleTurnaround,
+                flight_plans: vec![first_ref.clone(), second_ref.clone()],
+            });
+        }
+    }
+    info!(
+        "Validated {} draft flight plans, found {} conflicts",
+        drafts.len(),
+        conflicts.len()
+    );
+    conflicts
+}
+
+#[cfg(test)]
+mod conflict_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use chrono::{Duration, TimeZone};
+    use rrule::Tz;
+
+    fn draft(vehicle_id: &str, from: &str, to: &str, dep_min: i64) -> FlightPlanData {
+        let departure =
+            Tz::UTC.with_ymd_and_hms(2022, 10, 25, 10, 0, 0).unwrap() + Duration::minutes(dep_min);
+        create_flight_plan_data(
+            vehicle_id.to_string(),
+            from.to_string(),
+            to.to_string(),
+            departure,
+            departure + Duration::minutes(30),
+        )
+    }
+
+    fn existing(id: &str, vehicle_id: &str, from: &str, to: &str, dep_min: i64) -> FlightPlan {
+        FlightPlan {
+            id: id.to_string(),
+            data: Some(draft(vehicle_id, from, to, dep_min)),
+        }
+    }
+
+    #[test]
+    fn test_no_conflicts() {
+        let drafts = vec![draft("v1", "A", "B", 0), draft("v1", "B", "A", 40)];
+        let existing = vec![existing("fp1", "v2", "C", "D", 0)];
+        assert!(validate_flight_plans(&drafts, &existing).is_empty());
+    }
+
+    #[test]
+    fn test_vehicle_double_booked() {
+        let drafts = vec![draft("v1", "A", "B", 0)];
+        let existing = vec![existing("fp1", "v1", "C", "D", 20)];
+        assert_eq!(
+            validate_flight_plans(&drafts, &existing),
+            vec![Conflict {
+                kind: ConflictKind::VehicleDoubleBooked,
+                flight_plans: vec![
+                    FlightPlanRef::Draft(0),
+                    FlightPlanRef::Existing("fp1".to_string())
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_vertipad_overlap() {
+        let drafts = vec![draft("v1", "A", "B", 0), draft("v2", "A", "C", 5)];
+        assert_eq!(
+            validate_flight_plans(&drafts, &[]),
+            vec![Conflict {
+                kind: ConflictKind::VertipadOverlap,
+                flight_plans: vec![FlightPlanRef::Draft(0), FlightPlanRef::Draft(1)],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_impossible_turnaround() {
+        let drafts = vec![draft("v1", "C", "A", 60)];
+        let existing = vec![existing("fp1", "v1", "A", "B", 0)];
+        assert_eq!(
+            validate_flight_plans(&drafts, &existing),
+            vec![Conflict {
+                kind: ConflictKind::ImpossibleTurnaround,
+                flight_plans: vec![
+                    FlightPlanRef::Existing("fp1".to_string()),
+                    FlightPlanRef::Draft(0)
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_invalid_schedule() {
+        let mut invalid = draft("v1", "A", "B", 0);
+        invalid.scheduled_arrival = None;
+        assert_eq!(
+            validate_flight_plans(&[invalid], &[]),
+            vec![Conflict {
+                kind: ConflictKind::InvalidSchedule,
+                flight_plans: vec![FlightPlanRef::Draft(0)],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_curfew() {
+        crate::curfew::set_vertiport_curfew(
+            "conflict-test-curfew",
+            crate::curfew::Curfew {
+                start: chrono::NaiveTime::from_hms_opt(10, 15, 0).unwrap(),
+                end: chrono::NaiveTime::from_hms_opt(11, 0, 0).unwrap(),
+                timezone: Tz::UTC,
+            },
+        );
+        let drafts = vec![draft("v1", "A", "conflict-test-curfew", 0)];
+        assert_eq!(
+            validate_flight_plans(&drafts, &[]),
+            vec![Conflict {
+                kind: ConflictKind::Curfew,
+                flight_plans: vec![FlightPlanRef::Draft(0)],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_existing_conflicts_ignored() {
+        let existing = vec![
+            existing("fp1", "v1", "A", "B", 0),
+            existing("fp2", "v1", "A", "B", 10),
+        ];
+        assert!(validate_flight_plans(&[], &existing).is_empty());
+    }
+}
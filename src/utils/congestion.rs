@@ -0,0 +1,230 @@
+//! Congestion of the corridors between vertiports over the day.
+//!
+//! The cost of a leg may vary with the time of day, e.g. when a corridor is
+//! saturated during peak hours. [`CongestionMultipliers`] scale the cost of
+//! each leg of the graph per hour of the day in the local time of the
+//! network, either configured by hand or derived from the existing flight
+//! plans with
+//! [`CongestionMultipliers::from_flight_plans`]. Once set with
+//! [`set_congestion_multipliers`], routes queried with a departure time, see
+//! [`RouteQuery::departure_time`](crate::router_state::RouteQuery::departure_time),
+//! avoid the congested legs.
+
+use chrono::{DateTime, Timelike};
+use rrule::Tz;
+use std::collections::HashMap;
+
+use crate::amendment::{timestamp_to_datetime, PlanTimes};
+use crate::context::current_context;
+use crate::haversine;
+use crate::node::Node;
+use crate::router_state::FlightPlan;
+
+/// Hours in a day
+const HOURS_PER_DAY: usize = 24;
+
+/// Cost multipliers of the legs of the graph by hour of the day
+#[derive(Debug, Clone)]
+pub struct CongestionMultipliers {
+    /// timezone of the network, the hours of the multipliers are local
+    pub timezone: Tz,
+    /// multiplier of each hour by departure and arrival node id
+    by_leg: HashMap<(String, String), [f32; HOURS_PER_DAY]>,
+}
+
+impl Default for CongestionMultipliers {
+    fn default() -> Self {
+        Self::new(Tz::UTC)
+    }
+}
+
+impl CongestionMultipliers {
+    /// Creates multipliers leaving the cost of every leg unchanged, by hour
+    /// of the day in `timezone`
+    pub fn new(timezone: Tz) -> Self {
+        CongestionMultipliers {
+            timezone,
+            by_leg: HashMap::new(),
+        }
+    }
+
+    /// Sets the multiplier of the leg from `from` to `to` during the hour
+    /// starting at `hour` local time
+    /// Multipliers above 1 make the leg costlier, multipliers below 1 cheaper
+    pub fn with_multiplier(mut self, from: &str, to: &str, hour: u32, multiplier: f32) -> Self {
+        self.by_leg
+            .entry((from.to_string(), to.to_string()))
+            .or_insert([1.0; HOURS_PER_DAY])[hour as usize % HOURS_PER_DAY] = multiplier;
+        self
+    }
+
+    /// Derives the multipliers from the saturation of the corridors by the
+    /// existing flight plans
+    ///
+    /// Each flight plan counts towards the corridor between its departure
+    /// and destination vertiports during the local hour of the day it
+    /// departs, so the peaks keep their hour across DST shifts. The
+    /// multiplier of a corridor is `1 + flights / capacity_per_hour`.
+    ///
+    /// # Arguments
+    /// * `flight_plans` - flight plans loading the corridors, e.g. of the past weeks
+    /// * `capacity_per_hour` - flights a corridor takes per hour of the day
+    /// * `timezone` - timezone of the network
+    pub fn from_flight_plans(
+        flight_plans: &[FlightPlan],
+        capacity_per_hour: u32,
+        timezone: Tz,
+    ) -> Self {
+        let mut flights: HashMap<(String, String), [u32; HOURS_PER_DAY]> = HashMap::new();
+        for plan in flight_plans
+            .iter()
+            .filter_map(|flight_plan| PlanTimes::from_flight_plan(flight_plan).ok())
+        {
+            let hour = timestamp_to_datetime(plan.departure)
+                .with_timezone(&timezone)
+                .hour() as usize;
+            flights
+                .entry((plan.departure_vertiport_id, plan.destination_vertiport_id))
+                .or_insert([0; HOURS_PER_DAY])[hour] += 1;
+        }
+        let capacity = capacity_per_hour.max(1) as f32;
+        CongestionMultipliers {
+            timezone,
+            by_leg: flights
+                .into_iter()
+                .map(|(leg, counts)| (leg, counts.map(|count| 1.0 + count as f32 / capacity)))
+                .collect(),
+        }
+    }
+
+    /// Gets the multiplier of the leg from `from` to `to` at `time`, 1 if
+    /// not configured
+    pub fn multiplier(&self, from: &str, to: &str, time: DateTime<Tz>) -> f32 {
+        self.by_leg
+            .get(&(from.to_string(), to.to_string()))
+            .map_or(1.0, |hours| {
+                hours[time.with_timezone(&self.timezone).hour() as usize]
+            })
+    }
+}
+
+/// Extra cost of a leg congested at `departure_time`, to use as the penalty of
+/// [`get_route_with_penalty`](crate::router_state::get_route_with_penalty)
+///
+/// The multiplier scales the distance of the leg, so it matches the cost of
+/// a router weighting legs by their distance. All legs of a route are costed
+/// at the departure time, as flights are short compared to an hour.
+pub fn congestion_penalty(
+    multipliers: &CongestionMultipliers,
+    departure_time: DateTime<Tz>,
+    from: &Node,
+    to: &Node,
+) -> Option<f32> {
+    let multiplier = multipliers.multiplier(&from.uid, &to.uid, departure_time);
+    Some(haversine::distance(&from.location, &to.location) * (multiplier.max(0.0) - 1.0))
+}
+
+/// Sets the congestion of the legs, used by the routes queried with a departure time
+pub fn set_congestion_multipliers(multipliers: CongestionMultipliers) {
+    match current_context().congestion_multipliers.write() {
+        Ok(mut current) => *current = Some(multipliers),
+        Err(_) => error!("Congestion multipliers unavailable"),
+    }
+}
+
+/// Makes the legs cost the same all day
+pub fn clear_congestion_multipliers() {
+    match current_context().congestion_multipliers.write() {
+        Ok(mut current) => *current = None,
+        Err(_) => error!("Congestion multipliers unavailable"),
+    }
+}
+
+/// Gets the congestion of the legs, if any
+pub fn get_congestion_multipliers() -> Option<CongestionMultipliers> {
+    current_context()
+        .congestion_multipliers
+        .read()
+        .map(|multipliers| multipliers.clone())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod congestion_tests {
+    use super::*;
+    use crate::node::AsNode;
+    use crate::router::engine::Router;
+    use crate::router_state::create_flight_plan_data;
+    use chrono::{Duration, TimeZone};
+
+    fn at(hour: u32) -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2030, 1, 1, hour, 0, 0).unwrap()
+    }
+
+    fn distance(from: &dyn AsNode, to: &dyn AsNode) -> f32 {
+        haversine::distance(&from.as_node().location, &to.as_node().location)
+    }
+
+    #[test]
+    fn test_multipliers_from_flight_plans() {
+        let flight_plans: Vec<FlightPlan> = (0..3)
+            .map(|i| FlightPlan {
+                id: format!("plan-{}", i),
+                data: Some(create_flight_plan_data(
+                    "vehicle".to_string(),
+                    "SFO".to_string(),
+                    "OAK".to_string(),
+                    at(8) + Duration::minutes(10 * i),
+                    at(8) + Duration::minutes(10 * i + 30),
+                )),
+            })
+            .collect();
+        let multipliers = CongestionMultipliers::from_flight_plans(&flight_plans, 2, Tz::UTC);
+        assert_eq!(multipliers.multiplier("SFO", "OAK", at(8)), 2.5);
+        assert_eq!(
+            multipliers.multiplier("SFO", "OAK", at(8) + Duration::minutes(59)),
+            2.5
+        );
+        assert_eq!(multipliers.multiplier("SFO", "OAK", at(9)), 1.0);
+        assert_eq!(multipliers.multiplier("OAK", "SFO", at(8)), 1.0);
+
+        // the peak at 03:00 New York time in winter (UTC-5) stays at 03:00 in
+        // summer (UTC-4)
+        let multipliers =
+            CongestionMultipliers::from_flight_plans(&flight_plans, 2, Tz::America__New_York);
+        assert_eq!(multipliers.multiplier("SFO", "OAK", at(8)), 2.5);
+        let summer = Tz::UTC.with_ymd_and_hms(2030, 7, 1, 12, 0, 0).unwrap();
+        assert_eq!(multipliers.multiplier("SFO", "OAK", summer), 1.0);
+        assert_eq!(
+            multipliers.multiplier("SFO", "OAK", summer - Duration::hours(5)),
+            2.5
+        );
+    }
+
+    #[test]
+    fn test_route_changes_over_the_day() {
+        // the direct leg A-B is congested in the morning peak
+        let nodes = vec![
+            Node::new("congestion-A", 0.0, 0.0),
+            Node::new("congestion-B", 0.0, 0.4),
+            Node::new("congestion-C", 0.05, 0.2),
+        ];
+        let router = Router::new(&nodes, 50.0, distance, distance);
+        let multipliers = CongestionMultipliers::new(Tz::UTC)
+            .with_multiplier("congestion-A", "congestion-B", 8, 1.5)
+            .with_multiplier("congestion-A", "congestion-B", 9, 1.5);
+        let route_at = |time: DateTime<Tz>| {
+            router
+                .find_shortest_path_with_penalty(&nodes[0], &nodes[1], |from, to| {
+                    congestion_penalty(&multipliers, time, from, to)
+                })
+                .unwrap()
+                .1
+                .len()
+        };
+        assert_eq!(route_at(at(3)), 2);
+        assert_eq!(route_at(at(8)), 3);
+        assert_eq!(route_at(at(9) + Duration::minutes(30)), 3);
+        assert_eq!(route_at(at(10)), 2);
+    }
+}
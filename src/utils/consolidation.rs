@@ -0,0 +1,255 @@
+//! Consolidation of small shipments onto shared flights.
+//!
+//! Shipments with the same pickup and delivery vertiports whose time windows
+//! overlap can share one flight as long as the total weight stays within the
+//! payload limit. [`consolidate_shipments`] packs the shipments of each
+//! origin and destination pair onto as few flights as possible and reports
+//! which flight every shipment was allocated to.
+
+use chrono::{DateTime, Duration};
+use rrule::Tz;
+use std::collections::BTreeMap;
+
+use crate::multistop::Shipment;
+use crate::router_state::{create_flight_plan_data, estimate_flight_time_between, FlightPlanData};
+
+/// A flight carrying one or more shipments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsolidatedFlight {
+    /// vertiport id where all shipments are picked up
+    pub departure_vertiport_id: String,
+    /// vertiport id where all shipments are delivered
+    pub destination_vertiport_id: String,
+    /// departure time, when the last shipment is ready
+    pub departure_time: DateTime<Tz>,
+    /// arrival time
+    pub arrival_time: DateTime<Tz>,
+    /// ids of the shipments on board
+    pub shipment_ids: Vec<String>,
+    /// weights of the shipments on board, in the order of `shipment_ids`
+    pub shipment_weights_grams: Vec<i64>,
+}
+
+impl ConsolidatedFlight {
+    /// Total weight of the shipments on board
+    pub fn payload_grams(&self) -> i64 {
+        self.shipment_weights_grams.iter().sum()
+    }
+
+    /// Creates the flight plan data for the flight flown by the given vehicle
+    pub fn to_flight_plan_data(&self, vehicle_id: String) -> FlightPlanData {
+        let mut flight_plan = create_flight_plan_data(
+            vehicle_id,
+            self.departure_vertiport_id.clone(),
+            self.destination_vertiport_id.clone(),
+            self.departure_time,
+            self.arrival_time,
+        );
+        flight_plan.cargo_weight_grams = self.shipment_weights_grams.clone();
+        flight_plan
+    }
+}
+
+/// Consolidated flights and the allocation of the shipments to them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsolidationResult {
+    /// flights, ordered by departure vertiport, destination and departure time
+    pub flights: Vec<ConsolidatedFlight>,
+    /// index into `flights` for each shipment in input order, `None` if the
+    /// shipment can't be flown (too heavy, no route or time window too short)
+    pub allocations: Vec<Option<usize>>,
+}
+
+/// A flight being filled, with the window in which it may depart
+struct OpenFlight {
+    shipments: Vec<usize>,
+    payload_grams: i64,
+    earliest_departure: DateTime<Tz>,
+    latest_departure: DateTime<Tz>,
+}
+
+/// Consolidates shipments onto flights using the given flight time estimate
+///
+/// # Arguments
+/// * `shipments` - shipments to consolidate
+/// * `payload_limit_grams` - max payload of a flight
+/// * `flight_minutes` - flight time in minutes between two vertiports, `None` if there is no route
+pub fn consolidate_shipments_with<F>(
+    shipments: &[Shipment],
+    payload_limit_grams: i64,
+    flight_minutes: F,
+) -> ConsolidationResult
+where
+    F: Fn(&str, &str) -> Option<i64>,
+{
+    info!("Consolidating {} shipments", shipments.len());
+    let mut groups: BTreeMap<(&str, &str), Vec<usize>> = BTreeMap::new();
+    for (index, shipment) in shipments.iter().enumerate() {
+        groups
+            .entry((
+                shipment.pickup_vertiport_id.as_str(),
+                shipment.delivery_vertiport_id.as_str(),
+            ))
+            .or_default()
+            .push(index);
+    }
+
+    let mut flights: Vec<ConsolidatedFlight> = vec![];
+    let mut allocations: Vec<Option<usize>> = vec![None; shipments.len()];
+    for ((from, to), mut group) in groups {
+        let Some(minutes) = flight_minutes(from, to) else {
+            debug!("No route from {} to {}", from, to);
+            continue;
+        };
+        let duration = Duration::minutes(minutes);
+        // most urgent shipments first, heavier first on ties
+        group.sort_by_key(|&index| {
+            (
+                shipments[index].latest_delivery_time,
+                -shipments[index].weight_grams,
+            )
+        });
+
+        let mut open: Vec<OpenFlight> = vec![];
+        for index in group {
+            let shipment = &shipments[index];
+            let latest_departure = shipment.latest_delivery_time - duration;
+            if shipment.weight_grams > payload_limit_grams
+                || shipment.earliest_pickup_time > latest_departure
+            {
+                debug!("Shipment {} can't be flown", shipment.id);
+                continue;
+            }
+            let fitting = open.iter_mut().find(|flight| {
+                flight.payload_grams + shipment.weight_grams <= payload_limit_grams
+                    && flight.earliest_departure.max(shipment.earliest_pickup_time)
+                        <= flight.latest_departure.min(latest_departure)
+            });
+            match fitting {
+                Some(flight) => {
+                    flight.shipments.push(index);
+                    flight.payload_grams += shipment.weight_grams;
+                    flight.earliest_departure =
+                        flight.earliest_departure.max(shipment.earliest_pickup_time);
+                    flight.latest_departure = flight.latest_departure.min(latest_departure);
+                }
+                None => open.push(OpenFlight {
+                    shipments: vec![index],
+                    payload_grams: shipment.weight_grams,
+                    earliest_departure: shipment.earliest_pickup_time,
+                    latest_departure,
+                }),
+            }
+        }
+
+        open.sort_by_key(|flight| flight.earliest_departure);
+        for flight in open {
+            for &index in &flight.shipments {
+                allocations[index] = Some(flights.len());
+            }
+            flights.push(ConsolidatedFlight {
+                departure_vertiport_id: from.to_string(),
+                destination_vertiport_id: to.to_string(),
+                departure_time: flight.earliest_departure,
+                arrival_time: flight.earliest_departure + duration,
+                shipment_ids: flight
+                    .shipments
+                    .iter()
+                    .map(|&index| shipments[index].id.clone())
+                    .collect(),
+                shipment_weights_grams: flight
+                    .shipments
+                    .iter()
+                    .map(|&index| shipments[index].weight_grams)
+                    .collect(),
+            });
+        }
+    }
+    info!(
+        "Consolidated {} shipments onto {} flights",
+        allocations.iter().filter(|flight| flight.is_some()).count(),
+        flights.len()
+    );
+    ConsolidationResult {
+        flights,
+        allocations,
+    }
+}
+
+/// Consolidates shipments onto flights with flight times estimated from the router
+pub fn consolidate_shipments(
+    shipments: &[Shipment],
+    payload_limit_grams: i64,
+) -> ConsolidationResult {
+    consolidate_shipments_with(shipments, payload_limit_grams, |from, to| {
+        estimate_flight_time_between(from, to).ok()
+    })
+}
+
+#[cfg(test)]
+mod consolidation_tests {
+    use super::*;
+    use crate::test_support::mock_flight_minutes;
+    use chrono::TimeZone;
+
+    fn start() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap()
+    }
+
+    fn shipment(id: &str, to: &str, weight_grams: i64, ready_min: i64, due_min: i64) -> Shipment {
+        Shipment {
+            id: id.to_string(),
+            pickup_vertiport_id: "A".to_string(),
+            delivery_vertiport_id: to.to_string(),
+            weight_grams,
+            earliest_pickup_time: start() + Duration::minutes(ready_min),
+            latest_delivery_time: start() + Duration::minutes(due_min),
+        }
+    }
+
+    #[test]
+    fn test_combines_compatible_shipments() {
+        let shipments = vec![
+            shipment("s1", "B", 100, 0, 120),
+            shipment("s2", "B", 200, 30, 90),
+            shipment("s3", "C", 100, 0, 120),
+        ];
+        let result = consolidate_shipments_with(&shipments, 1000, mock_flight_minutes);
+        assert_eq!(result.flights.len(), 2);
+        assert_eq!(result.allocations, vec![Some(0), Some(0), Some(1)]);
+        assert_eq!(result.flights[0].payload_grams(), 300);
+        // waits for the later shipment
+        assert_eq!(
+            result.flights[0].departure_time,
+            start() + Duration::minutes(30)
+        );
+    }
+
+    #[test]
+    fn test_respects_payload_limit() {
+        let shipments = vec![
+            shipment("s1", "B", 600, 0, 120),
+            shipment("s2", "B", 600, 0, 120),
+            shipment("s3", "B", 1200, 0, 120),
+        ];
+        let result = consolidate_shipments_with(&shipments, 1000, mock_flight_minutes);
+        assert_eq!(result.flights.len(), 2);
+        assert_eq!(result.allocations[2], None);
+        assert!(result
+            .flights
+            .iter()
+            .all(|flight| flight.payload_grams() <= 1000));
+    }
+
+    #[test]
+    fn test_separates_incompatible_time_windows() {
+        let shipments = vec![
+            shipment("s1", "B", 100, 0, 40),
+            shipment("s2", "B", 100, 60, 180),
+        ];
+        let result = consolidate_shipments_with(&shipments, 1000, mock_flight_minutes);
+        assert_eq!(result.flights.len(), 2);
+        let flight_plan = result.flights[1].to_flight_plan_data("v1".to_string());
+        assert_eq!(flight_plan.cargo_weight_grams, vec![100]);
+    }
+}
@@ -0,0 +1,182 @@
+//! Regulatory constraints on routes.
+//!
+//! Deployments operate under rules the graph doesn't know about, such as
+//! BVLOS corridors, altitude caps or bans on crossing a border. Each rule is
+//! a [`RouteConstraint`] plugin, registered with
+//! [`register_route_constraint`]. The route search skips the legs a
+//! registered constraint rejects, and the route found is validated as a
+//! whole before it is returned, for the rules that can't be checked leg by
+//! leg.
+
+use std::sync::Arc;
+
+use crate::context::current_context;
+use crate::location::Location;
+
+/// A rule a route must comply with
+pub trait RouteConstraint: Send + Sync {
+    /// Name of the rule, a constraint registered with the same name replaces it
+    fn name(&self) -> &str;
+
+    /// Checks the straight leg between two locations
+    ///
+    /// # Returns
+    /// An error describing the violation if the leg can't be flown
+    fn check_leg(&self, from: &Location, to: &Location) -> Result<(), String>;
+
+    /// Checks a whole route, by default each of its legs
+    ///
+    /// # Returns
+    /// An error describing the first violation
+    fn check_route(&self, route: &[Location]) -> Result<(), String> {
+        route
+            .windows(2)
+            .try_for_each(|leg| self.check_leg(&leg[0], &leg[1]))
+    }
+}
+
+/// Registers a constraint applied to every route, replacing the constraint
+/// registered with the same name
+pub fn register_route_constraint(constraint: Arc<dyn RouteConstraint>) {
+    match current_context().route_constraints.write() {
+        Ok(mut constraints) => {
+            constraints.insert(constraint.name().to_string(), constraint);
+        }
+        Err(_) => error!("Route constraints unavailable"),
+    }
+}
+
+/// Removes the constraint registered with a name
+pub fn unregister_route_constraint(name: &str) {
+    match current_context().route_constraints.write() {
+        Ok(mut constraints) => {
+            constraints.remove(name);
+        }
+        Err(_) => error!("Route constraints unavailable"),
+    }
+}
+
+/// Gets the registered constraints, by name
+pub fn get_route_constraints() -> Vec<Arc<dyn RouteConstraint>> {
+    match current_context().route_constraints.read() {
+        Ok(constraints) => constraints.values().cloned().collect(),
+        Err(_) => {
+            error!("Route constraints unavailable");
+            vec![]
+        }
+    }
+}
+
+/// Checks a leg against the constraints
+///
+/// # Returns
+/// An error naming the first constraint violated
+pub fn check_leg_constraints(
+    constraints: &[Arc<dyn RouteConstraint>],
+    from: &Location,
+    to: &Location,
+) -> Result<(), String> {
+    constraints.iter().try_for_each(|constraint| {
+        constraint
+            .check_leg(from, to)
+            .map_err(|e| format!("Constraint {} violated: {}", constraint.name(), e))
+    })
+}
+
+/// Checks a whole route against the constraints
+///
+/// # Returns
+/// An error naming the first constraint violated
+pub fn check_route_constraints(
+    constraints: &[Arc<dyn RouteConstraint>],
+    route: &[Location],
+) -> Result<(), String> {
+    constraints.iter().try_for_each(|constraint| {
+        constraint
+            .check_route(route)
+            .map_err(|e| format!("Constraint {} violated: {}", constraint.name(), e))
+    })
+}
+
+#[cfg(test)]
+mod constraints_tests {
+    use super::*;
+    use crate::test_support::mock_location_at_altitude;
+
+    /// Caps the altitude of every location
+    struct AltitudeCap(f32);
+
+    impl RouteConstraint for AltitudeCap {
+        fn name(&self) -> &str {
+            "constraints-altitude-cap"
+        }
+
+        fn check_leg(&self, from: &Location, to: &Location) -> Result<(), String> {
+            match [from, to]
+                .into_iter()
+                .find(|location| location.altitude_meters.into_inner() > self.0)
+            {
+                Some(location) => Err(format!("{} m above the cap", location.altitude_meters)),
+                None => Ok(()),
+            }
+        }
+    }
+
+    /// Bans crossing the meridian at longitude 0 more than once
+    struct SingleCrossing;
+
+    impl RouteConstraint for SingleCrossing {
+        fn name(&self) -> &str {
+            "constraints-single-crossing"
+        }
+
+        fn check_leg(&self, _from: &Location, _to: &Location) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn check_route(&self, route: &[Location]) -> Result<(), String> {
+            let crossings = route
+                .windows(2)
+                .filter(|leg| (leg[0].longitude.0 < 0.0) != (leg[1].longitude.0 < 0.0))
+                .count();
+            if crossings > 1 {
+                return Err(format!("{} crossings", crossings));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_route_constraints() {
+        let constraints: Vec<Arc<dyn RouteConstraint>> =
+            vec![Arc::new(AltitudeCap(120.0)), Arc::new(SingleCrossing)];
+        let low = mock_location_at_altitude(0.0, -0.1, 100.0);
+        let high = mock_location_at_altitude(0.0, 0.1, 150.0);
+        assert!(check_leg_constraints(&constraints, &low, &low).is_ok());
+        assert_eq!(
+            check_leg_constraints(&constraints, &low, &high),
+            Err("Constraint constraints-altitude-cap violated: 150 m above the cap".to_string())
+        );
+        let back_and_forth = [low, mock_location_at_altitude(0.0, 0.1, 0.0), low];
+        assert_eq!(
+            check_route_constraints(&constraints, &back_and_forth),
+            Err("Constraint constraints-single-crossing violated: 2 crossings".to_string())
+        );
+        assert!(check_route_constraints(&constraints, &back_and_forth[..2]).is_ok());
+
+        // a cap no route reaches, the constraints apply to the routes of other tests
+        register_route_constraint(Arc::new(AltitudeCap(1e6)));
+        register_route_constraint(Arc::new(AltitudeCap(1e7)));
+        assert_eq!(
+            get_route_constraints()
+                .iter()
+                .filter(|constraint| constraint.name() == "constraints-altitude-cap")
+                .count(),
+            1
+        );
+        unregister_route_constraint("constraints-altitude-cap");
+        assert!(get_route_constraints()
+            .iter()
+            .all(|constraint| constraint.name() != "constraints-altitude-cap"));
+    }
+}
@@ -0,0 +1,277 @@
+//! Configuration and models the router reads during a search.
+//!
+//! The `set_`, `load_` and `register_` functions of the modules configure a
+//! [`RouterContext`]: the route constraints, the ETA and cost models, the
+//! fleet state, the vertiport capacities and curfews, and so on. Outside of
+//! any scope they configure the global context, shared by the whole process.
+//!
+//! A service routing for several networks or tenants creates a context per
+//! network and passes it to the `_with` entry points, e.g.
+//! [`get_route_with`](crate::router_state::get_route_with), or runs any
+//! code within [`RouterContext::scope`]. Within a scope, the module functions
+//! read and configure that context on the current thread instead of the
+//! global one, so two searches don't see each other's configuration.
+
+use once_cell::sync::Lazy;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+#[cfg(feature = "scheduling")]
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::constraints::RouteConstraint;
+use crate::notifications::{Callback, SubscriptionId};
+
+#[cfg(feature = "scheduling")]
+use crate::{
+    approach::PadApproach,
+    audit::AuditSink,
+    charging::VertiportChargers,
+    clock::{Clock, SystemClock},
+    congestion::CongestionMultipliers,
+    cost::CostModel,
+    crew::CrewProvider,
+    curfew::Curfew,
+    energy::{DegradationModel, NoDegradation, VehicleBattery},
+    eta::{EtaModel, Wind},
+    fleet_state::FleetState,
+    ground::GroundLegEstimator,
+    ground_risk::RiskGrid,
+    maintenance::MaintenanceInterval,
+    metrics::MetricsRecorder,
+    noise::NoiseZone,
+    reservation::ReservationLedger,
+    separation::SeparationMinima,
+    terrain::TerrainClearance,
+    turnaround::{ConstantTurnaround, TurnaroundModel},
+    utm::UtmService,
+    weather::WeatherCell,
+};
+
+/// Registries of the configuration and models of the router
+pub struct RouterContext {
+    /// route constraints by name
+    pub(crate) route_constraints: RwLock<BTreeMap<String, Arc<dyn RouteConstraint>>>,
+    /// change notification callbacks by subscription
+    pub(crate) subscriptions: RwLock<Vec<(SubscriptionId, Callback)>>,
+    /// clock of the functions without a `clock` argument
+    #[cfg(feature = "scheduling")]
+    pub(crate) clock: RwLock<Arc<dyn Clock>>,
+    /// wind and historical delays of the arrival time predictions
+    #[cfg(feature = "scheduling")]
+    pub(crate) eta_model: RwLock<EtaModel>,
+    /// prices of the cost estimates
+    #[cfg(feature = "scheduling")]
+    pub(crate) cost_model: RwLock<CostModel>,
+    /// provider of the pilots of the possible flights
+    #[cfg(feature = "scheduling")]
+    pub(crate) crew_provider: RwLock<Option<Arc<dyn CrewProvider>>>,
+    /// service deconflicting the possible flights
+    #[cfg(feature = "scheduling")]
+    pub(crate) utm_service: RwLock<Option<Arc<dyn UtmService>>>,
+    /// sink of the audit trail
+    #[cfg(feature = "scheduling")]
+    pub(crate) audit_sink: RwLock<Option<Arc<dyn AuditSink>>>,
+    /// recorder of the metrics
+    #[cfg(feature = "scheduling")]
+    pub(crate) metrics_recorder: RwLock<Option<Arc<dyn MetricsRecorder>>>,
+    /// live positions and states of the vehicles
+    #[cfg(feature = "scheduling")]
+    pub(crate) fleet_state: RwLock<FleetState>,
+    /// flight plans held until confirmed
+    #[cfg(feature = "scheduling")]
+    pub(crate) reservations: Mutex<ReservationLedger>,
+    /// minima enforced between the possible flights and active flights
+    #[cfg(feature = "scheduling")]
+    pub(crate) separation_minima: RwLock<Option<SeparationMinima>>,
+    /// congestion multipliers of the legs
+    #[cfg(feature = "scheduling")]
+    pub(crate) congestion_multipliers: RwLock<Option<CongestionMultipliers>>,
+    /// weather cells by id
+    #[cfg(feature = "scheduling")]
+    pub(crate) weather_cells: RwLock<HashMap<String, WeatherCell>>,
+    /// noise zones by id
+    #[cfg(feature = "scheduling")]
+    pub(crate) noise_zones: RwLock<HashMap<String, NoiseZone>>,
+    /// ground risk layer and its weight
+    #[cfg(feature = "scheduling")]
+    pub(crate) ground_risk: RwLock<Option<(RiskGrid, f32)>>,
+    /// clearance of the legs above the terrain
+    #[cfg(feature = "scheduling")]
+    pub(crate) terrain_clearance: RwLock<Option<TerrainClearance>>,
+    /// estimator of the ground legs to and from the vertiports
+    #[cfg(feature = "scheduling")]
+    pub(crate) ground_leg_estimator: RwLock<Option<Arc<dyn GroundLegEstimator>>>,
+    /// chargers by vertiport id
+    #[cfg(feature = "scheduling")]
+    pub(crate) vertiport_chargers: RwLock<HashMap<String, VertiportChargers>>,
+    /// capacities by vertiport id
+    #[cfg(feature = "scheduling")]
+    pub(crate) vertiport_capacities: RwLock<HashMap<String, u32>>,
+    /// curfews by vertiport id
+    #[cfg(feature = "scheduling")]
+    pub(crate) vertiport_curfews: RwLock<HashMap<String, Curfew>>,
+    /// approaches by vertipad id
+    #[cfg(feature = "scheduling")]
+    pub(crate) pad_approaches: RwLock<HashMap<String, PadApproach>>,
+    /// surface winds by vertiport id
+    #[cfg(feature = "scheduling")]
+    pub(crate) surface_winds: RwLock<HashMap<String, Wind>>,
+    /// battery degradation model
+    #[cfg(feature = "scheduling")]
+    pub(crate) degradation_model: RwLock<Box<dyn DegradationModel>>,
+    /// batteries by vehicle id
+    #[cfg(feature = "scheduling")]
+    pub(crate) vehicle_batteries: RwLock<HashMap<String, VehicleBattery>>,
+    /// maintenance intervals by vehicle id
+    #[cfg(feature = "scheduling")]
+    pub(crate) maintenance_intervals: RwLock<HashMap<String, MaintenanceInterval>>,
+    /// turnaround model of the vehicles
+    #[cfg(feature = "scheduling")]
+    pub(crate) turnaround_model: RwLock<Box<dyn TurnaroundModel>>,
+    /// taxi minutes by vertiport id
+    #[cfg(feature = "scheduling")]
+    pub(crate) taxi_minutes: RwLock<HashMap<String, i64>>,
+}
+
+impl Default for RouterContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for RouterContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RouterContext").finish_non_exhaustive()
+    }
+}
+
+impl RouterContext {
+    /// Creates a context without configuration: no constraint, model or
+    /// provider, the system clock, and vehicles turned around immediately
+    pub fn new() -> Self {
+        RouterContext {
+            route_constraints: RwLock::new(BTreeMap::new()),
+            subscriptions: RwLock::new(vec![]),
+            #[cfg(feature = "scheduling")]
+            clock: RwLock::new(Arc::new(SystemClock)),
+            #[cfg(feature = "scheduling")]
+            eta_model: RwLock::new(EtaModel::default()),
+            #[cfg(feature = "scheduling")]
+            cost_model: RwLock::new(CostModel::default()),
+            #[cfg(feature = "scheduling")]
+            crew_provider: RwLock::new(None),
+            #[cfg(feature = "scheduling")]
+            utm_service: RwLock::new(None),
+            #[cfg(feature = "scheduling")]
+            audit_sink: RwLock::new(None),
+            #[cfg(feature = "scheduling")]
+            metrics_recorder: RwLock::new(None),
+            #[cfg(feature = "scheduling")]
+            fleet_state: RwLock::new(FleetState::default()),
+            #[cfg(feature = "scheduling")]
+            reservations: Mutex::new(ReservationLedger::new()),
+            #[cfg(feature = "scheduling")]
+            separation_minima: RwLock::new(None),
+            #[cfg(feature = "scheduling")]
+            congestion_multipliers: RwLock::new(None),
+            #[cfg(feature = "scheduling")]
+            weather_cells: RwLock::new(HashMap::new()),
+            #[cfg(feature = "scheduling")]
+            noise_zones: RwLock::new(HashMap::new()),
+            #[cfg(feature = "scheduling")]
+            ground_risk: RwLock::new(None),
+            #[cfg(feature = "scheduling")]
+            terrain_clearance: RwLock::new(None),
+            #[cfg(feature = "scheduling")]
+            ground_leg_estimator: RwLock::new(None),
+            #[cfg(feature = "scheduling")]
+            vertiport_chargers: RwLock::new(HashMap::new()),
+            #[cfg(feature = "scheduling")]
+            vertiport_capacities: RwLock::new(HashMap::new()),
+            #[cfg(feature = "scheduling")]
+            vertiport_curfews: RwLock::new(HashMap::new()),
+            #[cfg(feature = "scheduling")]
+            pad_approaches: RwLock::new(HashMap::new()),
+            #[cfg(feature = "scheduling")]
+            surface_winds: RwLock::new(HashMap::new()),
+            #[cfg(feature = "scheduling")]
+            degradation_model: RwLock::new(Box::new(NoDegradation)),
+            #[cfg(feature = "scheduling")]
+            vehicle_batteries: RwLock::new(HashMap::new()),
+            #[cfg(feature = "scheduling")]
+            maintenance_intervals: RwLock::new(HashMap::new()),
+            #[cfg(feature = "scheduling")]
+            turnaround_model: RwLock::new(Box::new(ConstantTurnaround(0))),
+            #[cfg(feature = "scheduling")]
+            taxi_minutes: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Runs `f` with this context as the current context of the thread:
+    /// the module functions called within read and configure this context
+    /// instead of the global one
+    pub fn scope<T>(self: &Arc<Self>, f: impl FnOnce() -> T) -> T {
+        /// Restores the previous context, even if `f` panics
+        struct Restore(Option<Arc<RouterContext>>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                let previous = self.0.take();
+                SCOPED_CONTEXT.with(|scoped| *scoped.borrow_mut() = previous);
+            }
+        }
+
+        let previous = SCOPED_CONTEXT.with(|scoped| scoped.borrow_mut().replace(self.clone()));
+        let _restore = Restore(previous);
+        f()
+    }
+}
+
+/// Context configured outside of any scope
+static GLOBAL_CONTEXT: Lazy<Arc<RouterContext>> = Lazy::new(|| Arc::new(RouterContext::new()));
+
+thread_local! {
+    /// Context of the innermost [`RouterContext::scope`] on the current thread
+    static SCOPED_CONTEXT: RefCell<Option<Arc<RouterContext>>> = const { RefCell::new(None) };
+}
+
+/// Gets the global context, configured by the module functions called
+/// outside of any scope
+pub fn global_context() -> Arc<RouterContext> {
+    GLOBAL_CONTEXT.clone()
+}
+
+/// Gets the context of the innermost scope on the current thread, or the
+/// global context outside of any scope
+pub fn current_context() -> Arc<RouterContext> {
+    SCOPED_CONTEXT
+        .with(|scoped| scoped.borrow().clone())
+        .unwrap_or_else(global_context)
+}
+
+#[cfg(test)]
+mod context_tests {
+    use super::*;
+
+    #[test]
+    fn test_scoped_context() {
+        let outer = Arc::new(RouterContext::new());
+        let inner = Arc::new(RouterContext::new());
+        assert!(Arc::ptr_eq(&current_context(), &global_context()));
+        outer.scope(|| {
+            assert!(Arc::ptr_eq(&current_context(), &outer));
+            inner.scope(|| assert!(Arc::ptr_eq(&current_context(), &inner)));
+            assert!(Arc::ptr_eq(&current_context(), &outer));
+            // another thread starts from the global context
+            std::thread::spawn(|| assert!(Arc::ptr_eq(&current_context(), &global_context())))
+                .join()
+                .unwrap();
+        });
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            inner.scope(|| panic!("search failed"))
+        }));
+        assert!(result.is_err());
+        assert!(Arc::ptr_eq(&current_context(), &global_context()));
+    }
+}
@@ -0,0 +1,331 @@
+//! Operating cost estimates of flight plans.
+//!
+//! A [`CostModel`] prices the energy used on a leg, the landing fee at its
+//! destination, the time of the crew operating the aircraft and an extra
+//! penalty for deadhead legs. Costs are in the currency the model is
+//! configured in. Every flight returned by
+//! [`get_possible_flights`](crate::router_state::get_possible_flights)
+//! carries the estimate of its legs, so pricing services don't need to
+//! repeat the distance and time calculations of the router.
+//!
+//! The landing fees of the model can also weight the graph of the router
+//! with [`distance_with_landing_fees`], so that routes with intermediate
+//! stops prefer cheaper vertiports.
+
+use std::collections::HashMap;
+use std::ops::{Add, AddAssign};
+
+use crate::context::current_context;
+use crate::diversion::{ENERGY_KWH_PER_KM, PAYLOAD_ENERGY_KWH_PER_KM_PER_KG};
+use crate::haversine;
+use crate::node::AsNode;
+use crate::router_state::{
+    get_node_by_id, get_route, Aircraft, FlightPlanData, RouteQuery, AVG_SPEED_KMH,
+};
+use crate::turnaround::LegKind;
+
+/// Prices of the components of the operating cost
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CostModel {
+    /// price of a kilowatt hour of energy
+    pub energy_cost_per_kwh: f32,
+    /// landing and handling fee by vertiport id
+    pub landing_fees: HashMap<String, f32>,
+    /// landing fee of vertiports without a configured fee
+    pub default_landing_fee: f32,
+    /// extra cost per kilometer flown without cargo
+    pub deadhead_penalty_per_km: f32,
+    /// cost of the crew per hour of block time
+    pub crew_cost_per_hour: f32,
+}
+
+impl CostModel {
+    /// Landing fee at the vertiport
+    pub fn landing_fee(&self, vertiport_id: &str) -> f32 {
+        self.landing_fees
+            .get(vertiport_id)
+            .copied()
+            .unwrap_or(self.default_landing_fee)
+    }
+
+    /// Cost of flying a kilometer with an empty aircraft
+    pub fn cost_per_km(&self) -> f32 {
+        self.energy_cost_per_kwh * ENERGY_KWH_PER_KM + self.crew_cost_per_hour / AVG_SPEED_KMH
+    }
+
+    /// Landing fee at the vertiport expressed as the distance costing the same
+    /// to fly, 0.0 if flying is free in the model
+    pub fn landing_fee_km(&self, vertiport_id: &str) -> f32 {
+        let cost_per_km = self.cost_per_km();
+        if cost_per_km <= 0.0 {
+            return 0.0;
+        }
+        self.landing_fee(vertiport_id) / cost_per_km
+    }
+}
+
+/// Estimated operating cost by component
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct CostEstimate {
+    /// cost of the energy used
+    pub energy: f32,
+    /// landing fees at the destinations
+    pub landing_fees: f32,
+    /// penalty of the deadhead legs
+    pub deadhead_penalty: f32,
+    /// cost of the crew time
+    pub crew: f32,
+}
+
+impl CostEstimate {
+    /// Sum of all components
+    pub fn total(&self) -> f32 {
+        self.energy + self.landing_fees + self.deadhead_penalty + self.crew
+    }
+}
+
+impl Add for CostEstimate {
+    type Output = CostEstimate;
+
+    fn add(self, other: CostEstimate) -> CostEstimate {
+        CostEstimate {
+            energy: self.energy + other.energy,
+            landing_fees: self.landing_fees + other.landing_fees,
+            deadhead_penalty: self.deadhead_penalty + other.deadhead_penalty,
+            crew: self.crew + other.crew,
+        }
+    }
+}
+
+impl AddAssign for CostEstimate {
+    fn add_assign(&mut self, other: CostEstimate) {
+        *self = *self + other;
+    }
+}
+
+/// Estimates the operating cost of a leg
+///
+/// # Arguments
+/// * `model` - prices of the cost components
+/// * `flight_plan` - the leg, its cargo and scheduled times
+/// * `distance_km` - length of the route of the leg
+/// * `kind` - whether the leg carries cargo
+///
+/// # Returns
+/// The cost by component. A leg without scheduled times has no crew cost.
+pub fn estimate_leg_cost(
+    model: &CostModel,
+    flight_plan: &FlightPlanData,
+    distance_km: f32,
+    kind: LegKind,
+) -> CostEstimate {
+    let payload_kg = flight_plan
+        .cargo_weight_grams
+        .iter()
+        .map(|grams| (*grams).max(0))
+        .sum::<i64>() as f32
+        / 1000.0;
+    let energy_kwh =
+        distance_km * (ENERGY_KWH_PER_KM + PAYLOAD_ENERGY_KWH_PER_KM_PER_KG * payload_kg);
+    let block_hours = match (
+        &flight_plan.scheduled_departure,
+        &flight_plan.scheduled_arrival,
+    ) {
+        (Some(departure), Some(arrival)) => {
+            (arrival.seconds - departure.seconds).max(0) as f32 / 3600.0
+        }
+        _ => 0.0,
+    };
+    CostEstimate {
+        energy: energy_kwh * model.energy_cost_per_kwh,
+        landing_fees: flight_plan
+            .destination_vertiport_id
+            .as_deref()
+            .map_or(0.0, |vertiport_id| model.landing_fee(vertiport_id)),
+        deadhead_penalty: match kind {
+            LegKind::Loaded => 0.0,
+            LegKind::Deadhead => distance_km * model.deadhead_penalty_per_km,
+        },
+        crew: block_hours * model.crew_cost_per_hour,
+    }
+}
+
+/// Gets the length of the route of a flight plan between its vertiports
+pub fn get_route_distance_km(flight_plan: &FlightPlanData) -> Result<f32, String> {
+    let (Some(from), Some(to)) = (
+        flight_plan.departure_vertiport_id.as_deref(),
+        flight_plan.destination_vertiport_id.as_deref(),
+    ) else {
+        return Err("Flight plan has no departure or destination vertiport".to_string());
+    };
+    if from == to {
+        return Ok(0.0);
+    }
+    let (_, distance_km) = get_route(RouteQuery::new(
+        Aircraft::Cargo,
+        get_node_by_id(from)?,
+        get_node_by_id(to)?,
+    ))?;
+    Ok(distance_km)
+}
+
+/// Estimates the operating cost of a flight plan with the given model,
+/// routing it between its vertiports
+pub fn estimate_flight_plan_cost_with(
+    model: &CostModel,
+    flight_plan: &FlightPlanData,
+) -> Result<CostEstimate, String> {
+    let distance_km = get_route_distance_km(flight_plan)?;
+    Ok(estimate_leg_cost(
+        model,
+        flight_plan,
+        distance_km,
+        LegKind::of(flight_plan),
+    ))
+}
+
+/// Estimates the operating cost of a flight plan, routing it between its vertiports
+pub fn estimate_flight_plan_cost(flight_plan: &FlightPlanData) -> Result<CostEstimate, String> {
+    estimate_flight_plan_cost_with(&get_cost_model(), flight_plan)
+}
+
+/// Cost function component adding the landing fee at the end of a leg,
+/// expressed as distance with [`CostModel::landing_fee_km`] of the cost model
+pub fn landing_fee_cost(_from: &dyn AsNode, to: &dyn AsNode) -> f32 {
+    match current_context().cost_model.read() {
+        Ok(model) => model.landing_fee_km(&to.as_node().uid),
+        Err(_) => {
+            error!("Cost model unavailable");
+            0.0
+        }
+    }
+}
+
+/// Cost function weighting each leg with its distance plus the landing fee
+/// at its end, for [`Router::new`](crate::router::engine::Router::new) or
+/// [`init_router_with_cost`](crate::router_state::init_router_with_cost)
+///
+/// The fees are read from the cost model when the graph is built, so the
+/// cost model must be set before the router is initialized.
+pub fn distance_with_landing_fees(from: &dyn AsNode, to: &dyn AsNode) -> f32 {
+    haversine::distance(&from.as_node().location, &to.as_node().location)
+        + landing_fee_cost(from, to)
+}
+
+/// Sets the prices used for cost estimates
+pub fn set_cost_model(model: CostModel) {
+    match current_context().cost_model.write() {
+        Ok(mut current) => *current = model,
+        Err(_) => error!("Cost model unavailable"),
+    }
+}
+
+/// Gets the prices used for cost estimates
+pub fn get_cost_model() -> CostModel {
+    current_context()
+        .cost_model
+        .read()
+        .map(|model| model.clone())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod cost_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use chrono::{Duration, TimeZone};
+    use rrule::Tz;
+
+    fn model() -> CostModel {
+        CostModel {
+            energy_cost_per_kwh: 0.5,
+            landing_fees: HashMap::from([("B".to_string(), 20.0)]),
+            default_landing_fee: 5.0,
+            deadhead_penalty_per_km: 1.0,
+            crew_cost_per_hour: 60.0,
+        }
+    }
+
+    fn flight_plan(to: &str, cargo_grams: Vec<i64>) -> FlightPlanData {
+        let departure = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap();
+        let mut flight_plan = create_flight_plan_data(
+            "v1".to_string(),
+            "A".to_string(),
+            to.to_string(),
+            departure,
+            departure + Duration::minutes(30),
+        );
+        flight_plan.cargo_weight_grams = cargo_grams;
+        flight_plan
+    }
+
+    #[test]
+    fn test_loaded_leg_cost() {
+        let flight_plan = flight_plan("B", vec![50_000, 50_000]);
+        let estimate = estimate_leg_cost(&model(), &flight_plan, 100.0, LegKind::Loaded);
+        // 100 km * (0.4 + 0.002 * 100 kg) kWh/km * 0.5
+        assert!((estimate.energy - 30.0).abs() < 1e-3);
+        assert_eq!(estimate.landing_fees, 20.0);
+        assert_eq!(estimate.deadhead_penalty, 0.0);
+        assert_eq!(estimate.crew, 30.0);
+        assert!((estimate.total() - 80.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_deadhead_leg_cost() {
+        let flight_plan = flight_plan("C", vec![]);
+        let estimate = estimate_leg_cost(&model(), &flight_plan, 50.0, LegKind::of(&flight_plan));
+        assert!((estimate.energy - 10.0).abs() < 1e-3);
+        assert_eq!(estimate.landing_fees, 5.0);
+        assert_eq!(estimate.deadhead_penalty, 50.0);
+
+        let mut sum = CostEstimate::default();
+        sum += estimate;
+        sum += estimate;
+        assert!((sum.total() - 2.0 * estimate.total()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_landing_fee_km() {
+        let model = model();
+        // 0.5 * 0.4 + 60 / 60 per km
+        assert!((model.cost_per_km() - 1.2).abs() < 1e-6);
+        assert!((model.landing_fee_km("B") - 20.0 / 1.2).abs() < 1e-4);
+        assert_eq!(CostModel::default().landing_fee_km("B"), 0.0);
+    }
+
+    #[test]
+    fn test_routes_prefer_cheaper_stops() {
+        use crate::node::Node;
+        use crate::router::engine::{Algorithm, Router};
+
+        // A and C only connect through one of the stops, B1 is slightly closer
+        let nodes = vec![
+            Node::new("fee-A", 0.0, 0.0),
+            Node::new("fee-B1", 0.04, 0.3),
+            Node::new("fee-B2", -0.06, 0.3),
+            Node::new("fee-C", 0.0, 0.6),
+        ];
+        let stop = |cost_function: fn(&dyn AsNode, &dyn AsNode) -> f32| {
+            let router = Router::new(
+                &nodes,
+                40.0,
+                |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+                cost_function,
+            );
+            let (_, path) = router
+                .find_shortest_path(&nodes[0], &nodes[3], Algorithm::Dijkstra, None)
+                .unwrap();
+            router.get_node_by_id(path[1]).unwrap().uid.clone()
+        };
+
+        set_cost_model(CostModel {
+            landing_fees: HashMap::from([("fee-B1".to_string(), 10.0)]),
+            ..model()
+        });
+        let with_fees = stop(distance_with_landing_fees);
+        set_cost_model(CostModel::default());
+        assert_eq!(with_fees, "fee-B2");
+        assert_eq!(stop(distance_with_landing_fees), "fee-B1");
+    }
+}
@@ -0,0 +1,290 @@
+//! Command and control (C2) link coverage of routes.
+//!
+//! A vehicle flown beyond visual line of sight must keep its C2 link along
+//! the whole route. A [`CoverageMap`] is the union of the circles around the
+//! ground stations and the polygons where the link is available, and
+//! [`CoverageMap::find_gaps`] samples a route every
+//! [`COVERAGE_SAMPLE_KM`] to report the stretches flown without it.
+//! Registered as a [route constraint](crate::constraints), a
+//! [`C2CoverageConstraint`] keeps the routes within coverage and reports the
+//! first gap of a route that isn't.
+
+use ordered_float::OrderedFloat;
+
+use crate::constraints::RouteConstraint;
+use crate::distance::Distance;
+use crate::haversine;
+use crate::location::Location;
+use crate::weather::polygon_contains;
+
+/// Distance between the points of a route checked for coverage
+pub const COVERAGE_SAMPLE_KM: f32 = 0.1;
+
+/// Name of the [`C2CoverageConstraint`]
+pub const C2_COVERAGE_CONSTRAINT: &str = "c2-coverage";
+
+/// Area where the C2 link is available
+#[derive(Debug, Clone, PartialEq)]
+pub enum CoverageArea {
+    /// within a range of a ground station
+    Circle {
+        /// location of the station
+        center: Location,
+        /// range of the station
+        radius: Distance,
+    },
+    /// within a polygon, altitudes are ignored
+    Polygon(Vec<Location>),
+}
+
+impl CoverageArea {
+    /// Checks if the location is covered by the area
+    pub fn contains(&self, location: &Location) -> bool {
+        match self {
+            CoverageArea::Circle { center, radius } => {
+                haversine::distance(center, location) <= radius.km()
+            }
+            CoverageArea::Polygon(polygon) => {
+                polygon.len() >= 3 && polygon_contains(polygon, location)
+            }
+        }
+    }
+}
+
+/// Stretch of a route without C2 link
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoverageGap {
+    /// first uncovered point
+    pub start: Location,
+    /// last uncovered point
+    pub end: Location,
+    /// distance along the route to the first uncovered point
+    pub start_km: f32,
+    /// distance along the route to the last uncovered point
+    pub end_km: f32,
+}
+
+/// Union of the areas where the C2 link is available
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CoverageMap {
+    /// covered areas
+    pub areas: Vec<CoverageArea>,
+}
+
+impl CoverageMap {
+    /// Creates the map of the areas
+    pub fn new(areas: Vec<CoverageArea>) -> Self {
+        CoverageMap { areas }
+    }
+
+    /// Checks if the location is covered by an area
+    pub fn contains(&self, location: &Location) -> bool {
+        self.areas.iter().any(|area| area.contains(location))
+    }
+
+    /// Finds the stretches of a route without coverage
+    ///
+    /// # Arguments
+    /// * `route` - locations along the route, the legs between them are
+    ///   straight and sampled every [`COVERAGE_SAMPLE_KM`]
+    ///
+    /// # Returns
+    /// The gaps in route order, each from its first to its last uncovered
+    /// sample. A gap shorter than the sampling may be missed.
+    pub fn find_gaps(&self, route: &[Location]) -> Vec<CoverageGap> {
+        let mut gaps: Vec<CoverageGap> = vec![];
+        let mut open = false;
+        let mut traveled_km = 0.0;
+        let mut check = |location: Location, km: f32| {
+            if self.contains(&location) {
+                open = false;
+                return;
+            }
+            match gaps.last_mut() {
+                Some(gap) if open => {
+                    gap.end = location;
+                    gap.end_km = km;
+                }
+                _ => gaps.push(CoverageGap {
+                    start: location,
+                    end: location,
+                    start_km: km,
+                    end_km: km,
+                }),
+            }
+            open = true;
+        };
+        if let Some(first) = route.first() {
+            check(*first, 0.0);
+        }
+        for leg in route.windows(2) {
+            let length_km = haversine::distance(&leg[0], &leg[1]);
+            let samples = (length_km / COVERAGE_SAMPLE_KM).ceil().max(1.0) as u32;
+            for sample in 1..=samples {
+                let fraction = sample as f32 / samples as f32;
+                check(
+                    interpolate(&leg[0], &leg[1], fraction),
+                    traveled_km + length_km * fraction,
+                );
+            }
+            traveled_km += length_km;
+        }
+        gaps
+    }
+}
+
+/// Location at a fraction of the straight leg between two locations
+fn interpolate(from: &Location, to: &Location, fraction: f32) -> Location {
+    let lerp =
+        |a: OrderedFloat<f32>, b: OrderedFloat<f32>| OrderedFloat(a.0 + (b.0 - a.0) * fraction);
+    Location {
+        latitude: lerp(from.latitude, to.latitude),
+        longitude: lerp(from.longitude, to.longitude),
+        altitude_meters: lerp(from.altitude_meters, to.altitude_meters),
+    }
+}
+
+/// Constraint keeping every point of the routes within C2 link coverage
+#[derive(Debug, Clone, PartialEq)]
+pub struct C2CoverageConstraint {
+    /// areas with C2 link
+    pub coverage: CoverageMap,
+}
+
+impl C2CoverageConstraint {
+    /// Creates the constraint of a coverage map
+    pub fn new(coverage: CoverageMap) -> Self {
+        C2CoverageConstraint { coverage }
+    }
+}
+
+impl RouteConstraint for C2CoverageConstraint {
+    fn name(&self) -> &str {
+        C2_COVERAGE_CONSTRAINT
+    }
+
+    fn check_leg(&self, from: &Location, to: &Location) -> Result<(), String> {
+        self.check_route(&[*from, *to])
+    }
+
+    fn check_route(&self, route: &[Location]) -> Result<(), String> {
+        let Some(gap) = self.coverage.find_gaps(route).into_iter().next() else {
+            return Ok(());
+        };
+        Err(format!(
+            "no C2 link from {:.1} km to {:.1} km along the route, between ({}, {}) and ({}, {})",
+            gap.start_km,
+            gap.end_km,
+            gap.start.latitude,
+            gap.start.longitude,
+            gap.end.latitude,
+            gap.end.longitude
+        ))
+    }
+}
+
+#[cfg(test)]
+mod coverage_tests {
+    use super::*;
+    use crate::test_support::mock_location;
+
+    #[test]
+    fn test_coverage_gaps() {
+        // stations at both ends of a route along the equator, 2 km of range
+        // each, and a polygon over the middle of the route
+        let coverage = CoverageMap::new(vec![
+            CoverageArea::Circle {
+                center: mock_location(0.0, 0.0),
+                radius: Distance::from_km(2.0),
+            },
+            CoverageArea::Circle {
+                center: mock_location(0.0, 0.1),
+                radius: Distance::from_km(2.0),
+            },
+            CoverageArea::Polygon(vec![
+                mock_location(-0.01, 0.04),
+                mock_location(0.01, 0.04),
+                mock_location(0.01, 0.06),
+                mock_location(-0.01, 0.06),
+            ]),
+        ]);
+        let route = [
+            mock_location(0.0, 0.0),
+            mock_location(0.0, 0.05),
+            mock_location(0.0, 0.1),
+        ];
+        let gaps = coverage.find_gaps(&route);
+        assert_eq!(gaps.len(), 2);
+        // uncovered from 2 km to 0.04° (4.4 km) and from 0.06° to 2 km of the end
+        let degree_km = haversine::distance(&route[0], &route[2]) / 0.1;
+        assert!((gaps[0].start_km - 2.0).abs() <= COVERAGE_SAMPLE_KM);
+        assert!((gaps[0].end_km - 0.04 * degree_km).abs() <= COVERAGE_SAMPLE_KM);
+        assert!((gaps[1].start_km - 0.06 * degree_km).abs() <= COVERAGE_SAMPLE_KM);
+        assert!(gaps[1].end_km < 0.1 * degree_km - 2.0);
+        assert!(gaps[0].start.longitude.0 > 0.0 && gaps[0].end.longitude.0 < 0.04);
+
+        let constraint = C2CoverageConstraint::new(coverage.clone());
+        let error = constraint.check_route(&route).unwrap_err();
+        assert!(error.starts_with("no C2 link from 2."), "{}", error);
+        assert!(constraint
+            .check_leg(&mock_location(0.0, 0.0), &mock_location(0.0, 0.01))
+            .is_ok());
+
+        // a station over the whole route
+        let covered = CoverageMap::new(vec![CoverageArea::Circle {
+            center: mock_location(0.0, 0.05),
+            radius: Distance::from_km(6.0),
+        }]);
+        assert!(covered.find_gaps(&route).is_empty());
+        assert_eq!(CoverageMap::default().find_gaps(&route[..1]).len(), 1);
+    }
+
+    #[test]
+    fn test_coverage_edge_cases() {
+        let route = [mock_location(0.0, 0.0), mock_location(0.0, 0.1)];
+        let length_km = haversine::distance(&route[0], &route[1]);
+
+        // without any area, the whole route is a single gap
+        let empty = CoverageMap::default();
+        let gaps = empty.find_gaps(&route);
+        assert_eq!(gaps.len(), 1);
+        assert_eq!((gaps[0].start_km, gaps[0].start), (0.0, route[0]));
+        assert!((gaps[0].end_km - length_km).abs() < 1e-3);
+        assert!(empty.find_gaps(&[]).is_empty());
+        let constraint = C2CoverageConstraint::new(empty);
+        assert!(constraint.check_route(&[]).is_ok());
+        let error = constraint.check_route(&route).unwrap_err();
+        assert!(error.starts_with("no C2 link from 0.0 km"), "{}", error);
+
+        // a polygon of less than three vertices covers nothing
+        let line = CoverageMap::new(vec![CoverageArea::Polygon(vec![
+            mock_location(-0.01, -0.01),
+            mock_location(0.01, 0.11),
+        ])]);
+        assert_eq!(line.find_gaps(&route).len(), 1);
+
+        // stations every 0.04°, 1 km of range each, leave a gap between
+        // each pair and one reaching the destination
+        let stations = CoverageMap::new(
+            [0.0, 0.04, 0.08]
+                .iter()
+                .map(|longitude| CoverageArea::Circle {
+                    center: mock_location(0.0, *longitude),
+                    radius: Distance::from_km(1.0),
+                })
+                .collect(),
+        );
+        let gaps = stations.find_gaps(&route);
+        assert_eq!(gaps.len(), 3);
+        assert!(gaps
+            .windows(2)
+            .all(|pair| pair[0].end_km < pair[1].start_km));
+        assert!((gaps[2].end_km - length_km).abs() < 1e-3);
+        assert_eq!(gaps[2].end, route[1]);
+
+        // hovering over a station is covered, the empty leg is sampled once
+        let hover = [route[0], route[0]];
+        assert!(stations.find_gaps(&hover).is_empty());
+        assert_eq!(CoverageMap::default().find_gaps(&hover).len(), 1);
+    }
+}
@@ -0,0 +1,349 @@
+//! Pilots of the flights and their duty limits.
+//!
+//! Flight plans are created without a pilot, unless a [`CrewProvider`] is set
+//! with [`set_crew_provider`]. Each vehicle flying a possible flight, either
+//! the requested flight or a deadhead flight, is then assigned a pilot from
+//! the provider by [`assign_crew`]. A pilot is only assigned if the flights
+//! keep their duty within its [`DutyLimits`], given the flight plans already
+//! assigned to the pilot; flights no pilot can fly are not returned.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::amendment::PlanTimes;
+use crate::context::current_context;
+use crate::router_state::{FlightPlan, FlightPlanData};
+
+/// Duty and rest limits of a pilot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DutyLimits {
+    /// longest duty period, from the first departure to the last arrival
+    pub max_duty_minutes: i64,
+    /// most minutes flown in a duty period
+    pub max_flight_minutes: i64,
+    /// shortest rest between duty periods; flights closer together share a duty period
+    pub min_rest_minutes: i64,
+}
+
+impl Default for DutyLimits {
+    /// 12 hours of duty with at most 8 hours of flight, then 10 hours of rest
+    fn default() -> Self {
+        DutyLimits {
+            max_duty_minutes: 12 * 60,
+            max_flight_minutes: 8 * 60,
+            min_rest_minutes: 10 * 60,
+        }
+    }
+}
+
+/// Source of the pilots able to fly the vehicles
+pub trait CrewProvider: Send + Sync {
+    /// Ids of the pilots able to fly the vehicle from the vertiport, preferred first
+    fn pilots(&self, vehicle_id: &str, vertiport_id: &str) -> Vec<String>;
+
+    /// Duty limits of the pilot
+    fn duty_limits(&self, _pilot_id: &str) -> DutyLimits {
+        DutyLimits::default()
+    }
+}
+
+/// Pilots able to fly any vehicle from any vertiport, with the default duty limits
+impl CrewProvider for Vec<String> {
+    fn pilots(&self, _vehicle_id: &str, _vertiport_id: &str) -> Vec<String> {
+        self.clone()
+    }
+}
+
+/// Checks if a pilot can fly new flights without exceeding its duty limits
+///
+/// # Arguments
+/// * `limits` - duty limits of the pilot
+/// * `pilot_id` - id of the pilot
+/// * `flights` - departure and arrival in seconds since epoch of the new flights
+/// * `existing_flight_plans` - flight plans, those of the pilot count towards its duty
+///
+/// # Returns
+/// false if a new flight overlaps a flight of the pilot, or if a duty period
+/// with a new flight is too long or has too many minutes of flight
+pub fn is_within_duty_limits(
+    limits: &DutyLimits,
+    pilot_id: &str,
+    flights: &[(i64, i64)],
+    existing_flight_plans: &[FlightPlan],
+) -> bool {
+    let assigned: Vec<(i64, i64)> = existing_flight_plans
+        .iter()
+        .filter(|flight_plan| {
+            flight_plan
+                .data
+                .as_ref()
+                .is_some_and(|data| data.pilot_id == pilot_id)
+        })
+        .filter_map(|flight_plan| PlanTimes::from_flight_plan(flight_plan).ok())
+        .map(|plan| (plan.departure, plan.arrival))
+        .collect();
+    let overlaps = flights.iter().any(|&(departure, arrival)| {
+        assigned
+            .iter()
+            .any(|&(start, end)| departure < end && start < arrival)
+    });
+    if overlaps {
+        return false;
+    }
+
+    // (departure, arrival, is new) of all flights of the pilot
+    let mut duty: Vec<(i64, i64, bool)> = assigned
+        .into_iter()
+        .map(|(departure, arrival)| (departure, arrival, false))
+        .chain(
+            flights
+                .iter()
+                .map(|&(departure, arrival)| (departure, arrival, true)),
+        )
+        .collect();
+    duty.sort();
+    let mut periods: Vec<Vec<(i64, i64, bool)>> = vec![];
+    for flight in duty {
+        match periods.last_mut() {
+            Some(period)
+                if flight.0
+                    - period
+                        .iter()
+                        .map(|flight| flight.1)
+                        .max()
+                        .unwrap_or(flight.0)
+                    < limits.min_rest_minutes * 60 =>
+            {
+                period.push(flight)
+            }
+            _ => periods.push(vec![flight]),
+        }
+    }
+    periods
+        .iter()
+        .filter(|period| period.iter().any(|flight| flight.2))
+        .all(|period| {
+            let start = period.iter().map(|flight| flight.0).min().unwrap_or(0);
+            let end = period.iter().map(|flight| flight.1).max().unwrap_or(0);
+            let flown: i64 = period
+                .iter()
+                .map(|(departure, arrival, _)| arrival - departure)
+                .sum();
+            end - start <= limits.max_duty_minutes * 60 && flown <= limits.max_flight_minutes * 60
+        })
+}
+
+/// Flights of a vehicle, with the vertiport of its first flight
+struct VehicleFlights {
+    vehicle_id: String,
+    vertiport_id: String,
+    /// departure and arrival in seconds since epoch
+    flights: Vec<(i64, i64)>,
+}
+
+/// Assigns a pilot to each vehicle flying the given flight plans
+///
+/// The flights of a vehicle are flown by a single pilot, picked among the
+/// pilots able to fly the vehicle from the departure vertiport of its first
+/// flight. A pilot flies a single vehicle.
+///
+/// # Arguments
+/// * `crew` - provider of the pilots
+/// * `flight_plans` - the flight plans to crew, e.g. a flight and its deadhead flights
+/// * `existing_flight_plans` - flight plans counting towards the duty of the pilots
+///
+/// # Returns
+/// The id of the pilot by vehicle id, or the reason a vehicle could not be crewed
+pub fn assign_crew(
+    crew: &dyn CrewProvider,
+    flight_plans: &[&FlightPlanData],
+    existing_flight_plans: &[FlightPlan],
+) -> Result<HashMap<String, String>, String> {
+    let mut plans: Vec<PlanTimes> = flight_plans
+        .iter()
+        .map(|data| PlanTimes::from_data("draft", data))
+        .collect::<Result<_, _>>()?;
+    plans.sort_by_key(|plan| plan.departure);
+    let mut flights_by_vehicle: Vec<VehicleFlights> = vec![];
+    for plan in plans {
+        match flights_by_vehicle
+            .iter_mut()
+            .find(|vehicle| vehicle.vehicle_id == plan.vehicle_id)
+        {
+            Some(vehicle) => vehicle.flights.push((plan.departure, plan.arrival)),
+            None => flights_by_vehicle.push(VehicleFlights {
+                vehicle_id: plan.vehicle_id,
+                vertiport_id: plan.departure_vertiport_id,
+                flights: vec![(plan.departure, plan.arrival)],
+            }),
+        }
+    }
+
+    let mut pilots: HashMap<String, String> = HashMap::new();
+    for VehicleFlights {
+        vehicle_id,
+        vertiport_id,
+        flights,
+    } in flights_by_vehicle
+    {
+        let pilot_id = crew
+            .pilots(&vehicle_id, &vertiport_id)
+            .into_iter()
+            .filter(|pilot_id| !pilots.values().any(|assigned| assigned == pilot_id))
+            .find(|pilot_id| {
+                is_within_duty_limits(
+                    &crew.duty_limits(pilot_id),
+                    pilot_id,
+                    &flights,
+                    existing_flight_plans,
+                )
+            })
+            .ok_or_else(|| {
+                format!(
+                    "No pilot within duty limits for vehicle {} at vertiport {}",
+                    vehicle_id, vertiport_id
+                )
+            })?;
+        debug!("Pilot {} assigned to vehicle {}", pilot_id, vehicle_id);
+        pilots.insert(vehicle_id, pilot_id);
+    }
+    Ok(pilots)
+}
+
+/// Sets the provider of the pilots of the possible flights
+pub fn set_crew_provider(provider: Arc<dyn CrewProvider>) {
+    match current_context().crew_provider.write() {
+        Ok(mut current) => *current = Some(provider),
+        Err(_) => error!("Crew provider unavailable"),
+    }
+}
+
+/// Stops assigning pilots to the possible flights
+pub fn clear_crew_provider() {
+    match current_context().crew_provider.write() {
+        Ok(mut current) => *current = None,
+        Err(_) => error!("Crew provider unavailable"),
+    }
+}
+
+/// Gets the provider of the pilots of the possible flights, if any
+pub fn get_crew_provider() -> Option<Arc<dyn CrewProvider>> {
+    current_context()
+        .crew_provider
+        .read()
+        .map(|provider| provider.clone())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod crew_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use chrono::{Duration, TimeZone};
+    use rrule::Tz;
+
+    /// Flight of an hour starting `hour` hours after midnight
+    fn flight(vehicle_id: &str, from: &str, to: &str, hour: i64) -> FlightPlanData {
+        let departure =
+            Tz::UTC.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap() + Duration::hours(hour);
+        create_flight_plan_data(
+            vehicle_id.to_string(),
+            from.to_string(),
+            to.to_string(),
+            departure,
+            departure + Duration::hours(1),
+        )
+    }
+
+    fn assigned(pilot_id: &str, hour: i64) -> FlightPlan {
+        FlightPlan {
+            id: format!("{}-{}", pilot_id, hour),
+            data: Some(FlightPlanData {
+                pilot_id: pilot_id.to_string(),
+                ..flight("vehicle-0", "SFO", "OAK", hour)
+            }),
+        }
+    }
+
+    fn times(hour: i64) -> (i64, i64) {
+        let plan = PlanTimes::from_data("test", &flight("vehicle", "SFO", "OAK", hour)).unwrap();
+        (plan.departure, plan.arrival)
+    }
+
+    #[test]
+    fn test_duty_limits() {
+        let limits = DutyLimits::default();
+        // flying every other hour from 06:00
+        let existing: Vec<FlightPlan> = (0..4).map(|i| assigned("pilot-1", 6 + 2 * i)).collect();
+
+        assert!(is_within_duty_limits(
+            &limits,
+            "pilot-1",
+            &[times(15)],
+            &existing
+        ));
+        // overlaps the flight at 08:00
+        assert!(!is_within_duty_limits(
+            &limits,
+            "pilot-1",
+            &[times(8)],
+            &existing
+        ));
+        // 13 hours of duty from 06:00
+        assert!(!is_within_duty_limits(
+            &limits,
+            "pilot-1",
+            &[times(18)],
+            &existing
+        ));
+        // after 10 hours of rest
+        assert!(is_within_duty_limits(
+            &limits,
+            "pilot-1",
+            &[times(24)],
+            &existing
+        ));
+        // 9 hours of flight in 9 hours of duty
+        let flights: Vec<(i64, i64)> = (0..9).map(times).collect();
+        assert!(!is_within_duty_limits(&limits, "pilot-2", &flights, &[]));
+        assert!(is_within_duty_limits(
+            &limits,
+            "pilot-2",
+            &flights[..8],
+            &[]
+        ));
+        // flights of other pilots don't count
+        assert!(is_within_duty_limits(
+            &limits,
+            "pilot-2",
+            &[times(8)],
+            &existing
+        ));
+    }
+
+    #[test]
+    fn test_assign_crew() {
+        let crew = vec!["pilot-1".to_string(), "pilot-2".to_string()];
+        let existing = vec![assigned("pilot-1", 10)];
+        let deadhead = flight("vehicle-1", "SJC", "SFO", 9);
+        let loaded = flight("vehicle-1", "SFO", "OAK", 10);
+        let rerouted = flight("vehicle-2", "OAK", "SJC", 10);
+
+        // pilot-1 is flying at 10:00
+        let pilots = assign_crew(&crew, &[&loaded, &deadhead], &existing).unwrap();
+        assert_eq!(pilots.len(), 1);
+        assert_eq!(pilots["vehicle-1"], "pilot-2");
+        // pilot-2 flies vehicle-1, no one is left for vehicle-2
+        assert!(assign_crew(&crew, &[&loaded, &rerouted], &existing).is_err());
+        let mut crew = crew;
+        crew.push("pilot-3".to_string());
+        let pilots = assign_crew(&crew, &[&loaded, &deadhead, &rerouted], &existing).unwrap();
+        assert_eq!(pilots["vehicle-1"], "pilot-2");
+        assert_eq!(pilots["vehicle-2"], "pilot-3");
+
+        let pilots =
+            assign_crew(&crew, &[&flight("vehicle-1", "SFO", "OAK", 14)], &existing).unwrap();
+        assert_eq!(pilots["vehicle-1"], "pilot-1");
+        assert!(assign_crew(&Vec::<String>::new(), &[&loaded], &[]).is_err());
+    }
+}
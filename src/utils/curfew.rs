@@ -0,0 +1,210 @@
+//! Curfews (quiet hours) of vertiports.
+//!
+//! A curfew forbids takeoffs and landings at a vertiport during a daily
+//! window in the vertiport's local time, e.g. 23:00 to 06:00, regardless of
+//! its operating schedule. Curfews are configured per vertiport id with
+//! [`set_vertiport_curfew`] and enforced by the vertiport availability check,
+//! the flight plan conflict validation and the fleet scheduling.
+
+use chrono::{DateTime, Duration, NaiveTime, TimeZone};
+use rrule::Tz;
+
+use crate::context::current_context;
+
+/// Max number of curfews a flight is postponed past
+const MAX_CURFEW_POSTPONEMENTS: usize = 4;
+
+/// Daily window without takeoffs and landings
+#[derive(Debug, Clone, Copy)]
+pub struct Curfew {
+    /// local time the curfew starts
+    pub start: NaiveTime,
+    /// local time the curfew ends, before `start` if the curfew spans midnight
+    pub end: NaiveTime,
+    /// timezone of the vertiport
+    pub timezone: Tz,
+}
+
+impl Curfew {
+    /// Returns the end of the curfew if `time` falls within it
+    pub fn end_of_curfew_at(&self, time: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let local = time.with_timezone(&self.timezone);
+        let local_time = local.time();
+        let date = local.date_naive();
+        let end_date = if self.start <= self.end {
+            if local_time < self.start || local_time >= self.end {
+                return None;
+            }
+            date
+        } else if local_time >= self.start {
+            date + Duration::days(1)
+        } else if local_time < self.end {
+            date
+        } else {
+            return None;
+        };
+        // if the end falls into a DST gap, the curfew ends an hour later
+        let end_local = end_date.and_time(self.end);
+        let end = self
+            .timezone
+            .from_local_datetime(&end_local)
+            .earliest()
+            .or_else(|| {
+                self.timezone
+                    .from_local_datetime(&(end_local + Duration::hours(1)))
+                    .earliest()
+            })?;
+        Some(end.with_timezone(&time.timezone()))
+    }
+
+    /// Checks if `time` falls within the curfew
+    pub fn contains(&self, time: DateTime<Tz>) -> bool {
+        self.end_of_curfew_at(time).is_some()
+    }
+}
+
+/// Sets the curfew of a vertiport
+pub fn set_vertiport_curfew(vertiport_id: &str, curfew: Curfew) {
+    match current_context().vertiport_curfews.write() {
+        Ok(mut curfews) => {
+            curfews.insert(vertiport_id.to_string(), curfew);
+        }
+        Err(_) => error!("Vertiport curfews unavailable"),
+    }
+}
+
+/// Removes the curfew of a vertiport
+pub fn clear_vertiport_curfew(vertiport_id: &str) {
+    match current_context().vertiport_curfews.write() {
+        Ok(mut curfews) => {
+            curfews.remove(vertiport_id);
+        }
+        Err(_) => error!("Vertiport curfews unavailable"),
+    }
+}
+
+/// Gets the curfew of a vertiport, if any
+pub fn get_vertiport_curfew(vertiport_id: &str) -> Option<Curfew> {
+    current_context()
+        .vertiport_curfews
+        .read()
+        .ok()
+        .and_then(|curfews| curfews.get(vertiport_id).copied())
+}
+
+/// Checks if a takeoff or landing at the vertiport is forbidden at `time`
+pub fn is_in_curfew(vertiport_id: &str, time: DateTime<Tz>) -> bool {
+    get_vertiport_curfew(vertiport_id).is_some_and(|curfew| curfew.contains(time))
+}
+
+/// Checks if any time within `from` to `to` falls into the curfew of the vertiport
+pub fn overlaps_curfew(vertiport_id: &str, from: DateTime<Tz>, to: DateTime<Tz>) -> bool {
+    let Some(curfew) = get_vertiport_curfew(vertiport_id) else {
+        return false;
+    };
+    if curfew.contains(from) || (to > from && curfew.contains(to - Duration::seconds(1))) {
+        return true;
+    }
+    // a curfew starting and ending within the window
+    let local_from = from.with_timezone(&curfew.timezone);
+    (-1..=1).any(|days| {
+        let date = local_from.date_naive() + Duration::days(days);
+        curfew
+            .timezone
+            .from_local_datetime(&date.and_time(curfew.start))
+            .earliest()
+            .is_some_and(|start| start > from && start < to)
+    })
+}
+
+/// Returns the earliest departure at or after `departure` for which neither
+/// the takeoff nor the landing falls into a curfew
+pub fn next_departure_outside_curfew(
+    departure_vertiport_id: &str,
+    destination_vertiport_id: &str,
+    departure: DateTime<Tz>,
+    flight_duration_minutes: i64,
+) -> Option<DateTime<Tz>> {
+    let departure_curfew = get_vertiport_curfew(departure_vertiport_id);
+    let destination_curfew = get_vertiport_curfew(destination_vertiport_id);
+    let duration = Duration::minutes(flight_duration_minutes);
+    let mut departure = departure;
+    for _ in 0..MAX_CURFEW_POSTPONEMENTS {
+        if let Some(end) = departure_curfew.and_then(|curfew| curfew.end_of_curfew_at(departure)) {
+            departure = end;
+            continue;
+        }
+        if let Some(end) =
+            destination_curfew.and_then(|curfew| curfew.end_of_curfew_at(departure + duration))
+        {
+            departure = end - duration;
+            continue;
+        }
+        return Some(departure);
+    }
+    None
+}
+
+#[cfg(test)]
+mod curfew_tests {
+    use super::*;
+    use chrono_tz::Tz as ChronoTz;
+
+    fn night_curfew() -> Curfew {
+        Curfew {
+            start: NaiveTime::from_hms_opt(23, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+            timezone: Tz::Tz(ChronoTz::America__New_York),
+        }
+    }
+
+    fn utc(hour: u32, minute: u32) -> DateTime<Tz> {
+        Tz::UTC
+            .with_ymd_and_hms(2022, 10, 25, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_curfew_over_midnight() {
+        let curfew = night_curfew();
+        // 23:30 local is 03:30 UTC
+        assert_eq!(curfew.end_of_curfew_at(utc(3, 30)), Some(utc(10, 0)));
+        // 05:00 local is 09:00 UTC
+        assert_eq!(curfew.end_of_curfew_at(utc(9, 0)), Some(utc(10, 0)));
+        // 22:00 local is 02:00 UTC
+        assert!(!curfew.contains(utc(2, 0)));
+        assert!(!curfew.contains(utc(10, 0)));
+    }
+
+    #[test]
+    fn test_daytime_curfew() {
+        let curfew = Curfew {
+            start: NaiveTime::from_hms_opt(12, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(13, 0, 0).unwrap(),
+            timezone: Tz::UTC,
+        };
+        assert!(curfew.contains(utc(12, 30)));
+        assert!(!curfew.contains(utc(13, 0)));
+        assert!(!curfew.contains(utc(11, 59)));
+    }
+
+    #[test]
+    fn test_vertiport_curfew() {
+        set_vertiport_curfew("curfew-test-a", night_curfew());
+        assert!(is_in_curfew("curfew-test-a", utc(4, 0)));
+        assert!(!is_in_curfew("curfew-test-b", utc(4, 0)));
+        assert!(overlaps_curfew("curfew-test-a", utc(2, 50), utc(3, 10)));
+        assert!(!overlaps_curfew("curfew-test-a", utc(2, 30), utc(2, 50)));
+        // arrival at 03:10 UTC falls into the curfew at the destination
+        assert_eq!(
+            next_departure_outside_curfew("curfew-test-b", "curfew-test-a", utc(2, 40), 30),
+            Some(utc(9, 30))
+        );
+        assert_eq!(
+            next_departure_outside_curfew("curfew-test-a", "curfew-test-b", utc(4, 0), 30),
+            Some(utc(10, 0))
+        );
+        clear_vertiport_curfew("curfew-test-a");
+        assert!(!is_in_curfew("curfew-test-a", utc(4, 0)));
+    }
+}
@@ -0,0 +1,196 @@
+//! Forecasts of the flight requests at each vertiport.
+//!
+//! The planners repositioning vehicles ask a [`DemandForecast`] how many
+//! requests to expect at a vertiport, so vehicles wait where they will be
+//! needed, e.g. [`plan_overnight_parking_for_demand`] parks vehicles where
+//! the first flights of the next day depart.
+//!
+//! [`MovingAverageForecast`] expects the mean number of loaded departures of
+//! the last days at the same time of the day.
+//!
+//! [`plan_overnight_parking_for_demand`]: crate::parking::plan_overnight_parking_for_demand
+
+use chrono::{DateTime, Duration};
+use rrule::Tz;
+use std::collections::HashMap;
+
+use crate::router_state::FlightPlan;
+use crate::turnaround::LegKind;
+
+/// Minutes in a day
+const MINUTES_PER_DAY: i64 = 24 * 60;
+
+/// Forecast of the flight requests departing each vertiport
+pub trait DemandForecast: Send + Sync {
+    /// Length of the time buckets of the forecast, in minutes
+    fn bucket_minutes(&self) -> i64;
+
+    /// Expected number of requests departing the vertiport within the time
+    /// bucket containing `time`
+    fn expected_requests(&self, vertiport_id: &str, time: DateTime<Tz>) -> f32;
+
+    /// Expected number of requests departing the vertiport from `start` to
+    /// `end`, counting the buckets containing `start` and every following
+    /// bucket starting before `end` in full
+    fn expected_requests_between(
+        &self,
+        vertiport_id: &str,
+        start: DateTime<Tz>,
+        end: DateTime<Tz>,
+    ) -> f32 {
+        let bucket = Duration::minutes(self.bucket_minutes().max(1));
+        let mut time = start;
+        let mut requests = 0.0;
+        while time < end {
+            requests += self.expected_requests(vertiport_id, time);
+            time += bucket;
+        }
+        requests
+    }
+}
+
+/// Forecast of the mean number of requests of the last days in the same
+/// bucket of the day, UTC
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovingAverageForecast {
+    bucket_minutes: i64,
+    /// mean departures per day, by vertiport id and bucket of the day
+    averages: HashMap<(String, i64), f32>,
+}
+
+impl MovingAverageForecast {
+    /// Averages the loaded departures of historical flight plans
+    ///
+    /// # Arguments
+    /// * `flight_plans` - historical flight plans, counted at their scheduled
+    ///   departure. Legs without cargo are repositioning, not requests, and
+    ///   are ignored.
+    /// * `until` - end of the history, the `days` before it are averaged
+    /// * `days` - number of days averaged
+    /// * `bucket_minutes` - length of the time buckets, dividing a day
+    ///
+    /// # Returns
+    /// The forecast, or an error if there are no days or the buckets don't
+    /// divide a day
+    pub fn from_flight_plans(
+        flight_plans: &[FlightPlan],
+        until: DateTime<Tz>,
+        days: u32,
+        bucket_minutes: i64,
+    ) -> Result<Self, String> {
+        if days == 0 {
+            return Err("Demand must be averaged over at least one day".to_string());
+        }
+        if bucket_minutes <= 0 || MINUTES_PER_DAY % bucket_minutes != 0 {
+            return Err(format!(
+                "Buckets of {} minutes don't divide a day",
+                bucket_minutes
+            ));
+        }
+        let end = until.timestamp();
+        let start = end - Duration::days(days as i64).num_seconds();
+        let mut counts: HashMap<(String, i64), u32> = HashMap::new();
+        for data in flight_plans.iter().filter_map(|plan| plan.data.as_ref()) {
+            let (Some(departure), Some(vertiport_id)) = (
+                data.scheduled_departure.as_ref(),
+                data.departure_vertiport_id.as_ref(),
+            ) else {
+                continue;
+            };
+            if LegKind::of(data) == LegKind::Deadhead || !(start..end).contains(&departure.seconds)
+            {
+                continue;
+            }
+            let bucket = bucket_of_day(departure.seconds, bucket_minutes);
+            *counts.entry((vertiport_id.clone(), bucket)).or_default() += 1;
+        }
+        debug!(
+            "Averaged the demand of {} vertiport buckets over {} days",
+            counts.len(),
+            days
+        );
+        Ok(MovingAverageForecast {
+            bucket_minutes,
+            averages: counts
+                .into_iter()
+                .map(|(key, count)| (key, count as f32 / days as f32))
+                .collect(),
+        })
+    }
+}
+
+impl DemandForecast for MovingAverageForecast {
+    fn bucket_minutes(&self) -> i64 {
+        self.bucket_minutes
+    }
+
+    fn expected_requests(&self, vertiport_id: &str, time: DateTime<Tz>) -> f32 {
+        let bucket = bucket_of_day(time.timestamp(), self.bucket_minutes);
+        self.averages
+            .get(&(vertiport_id.to_string(), bucket))
+            .copied()
+            .unwrap_or(0.0)
+    }
+}
+
+/// Index of the bucket of the day, UTC, of a time in seconds since epoch
+fn bucket_of_day(seconds: i64, bucket_minutes: i64) -> i64 {
+    seconds.rem_euclid(MINUTES_PER_DAY * 60) / (bucket_minutes * 60)
+}
+
+#[cfg(test)]
+mod demand_tests {
+    use super::*;
+    use crate::test_support::MockFlightPlan;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_moving_average_forecast() {
+        let day = |day: u32, hour: u32, minute: u32| {
+            Tz::UTC
+                .with_ymd_and_hms(2030, 1, day, hour, minute, 0)
+                .unwrap()
+        };
+        // flights of 20 minutes to OAK, with or without cargo
+        let flight = |from: &str, departure: DateTime<Tz>, loaded: bool| {
+            MockFlightPlan::new(
+                &format!("{}-{}", from, departure.timestamp()),
+                "vehicle-1",
+                from,
+                "OAK",
+            )
+            .with_departure(departure)
+            .with_duration(Duration::minutes(20))
+            .with_cargo_weight_grams(vec![i64::from(loaded) * 1000])
+            .build()
+        };
+        let flight_plans = vec![
+            // 3 requests at 8:00-9:00 from SFO over the last 2 days
+            flight("SFO", day(2, 8, 0), true),
+            flight("SFO", day(2, 8, 45), true),
+            flight("SFO", day(3, 8, 30), true),
+            // repositioning, out of the history, another vertiport
+            flight("SFO", day(3, 8, 15), false),
+            flight("SFO", day(1, 8, 15), true),
+            flight("SJC", day(3, 17, 0), true),
+        ];
+        let forecast =
+            MovingAverageForecast::from_flight_plans(&flight_plans, day(4, 0, 0), 2, 60).unwrap();
+        assert_eq!(forecast.bucket_minutes(), 60);
+        assert_eq!(forecast.expected_requests("SFO", day(5, 8, 59)), 1.5);
+        assert_eq!(forecast.expected_requests("SFO", day(5, 9, 0)), 0.0);
+        assert_eq!(forecast.expected_requests("SJC", day(5, 17, 30)), 0.5);
+        assert_eq!(forecast.expected_requests("LAX", day(5, 8, 0)), 0.0);
+        assert_eq!(
+            forecast.expected_requests_between("SFO", day(5, 7, 0), day(5, 9, 0)),
+            1.5
+        );
+
+        assert!(
+            MovingAverageForecast::from_flight_plans(&flight_plans, day(4, 0, 0), 0, 60).is_err()
+        );
+        assert!(
+            MovingAverageForecast::from_flight_plans(&flight_plans, day(4, 0, 0), 2, 7).is_err()
+        );
+    }
+}
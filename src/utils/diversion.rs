@@ -0,0 +1,320 @@
+//! Emergency diversion of an aircraft to an alternate vertiport.
+//!
+//! When an aircraft can't continue to its destination, [`plan_diversion`]
+//! ranks the vertiports it can still reach with its remaining energy and
+//! where a pad is free at the time of arrival. The live position of the
+//! aircraft is routed as a synthetic origin node over the same graph as
+//! normal routing, and keeps to the [route constraints](crate::constraints).
+//! Operating schedules and curfews of the vertiports are not applied to
+//! emergency landings.
+
+use chrono::{DateTime, Duration};
+use rrule::Tz;
+use std::sync::Arc;
+
+use crate::constraints::{
+    check_leg_constraints, check_route_constraints, get_route_constraints, RouteConstraint,
+};
+use crate::distance::Distance;
+use crate::location::Location;
+use crate::router::engine::Router;
+use crate::router_state::{
+    get_all_vehicles_scheduled_for_vertiport, FlightPlan, Vertipad, ARROW_CARGO_CONSTRAINT,
+    ARROW_CARGO_ROUTER, AVG_SPEED_KMH,
+};
+use crate::status::Status;
+
+/// Energy used per kilometer by an empty cargo aircraft
+pub const ENERGY_KWH_PER_KM: f32 = 0.4;
+
+/// Additional energy used per kilometer and kilogram of payload
+pub const PAYLOAD_ENERGY_KWH_PER_KM_PER_KG: f32 = 0.002;
+
+/// Fraction of the remaining energy kept as reserve for the landing
+pub const ENERGY_RESERVE_FRACTION: f32 = 0.1;
+
+/// State of the aircraft requesting a diversion
+#[derive(Debug, Clone)]
+pub struct DiversionRequest {
+    /// id of the diverting vehicle
+    pub vehicle_id: String,
+    /// current position
+    pub location: Location,
+    /// time of the request
+    pub timestamp: DateTime<Tz>,
+    /// energy left in the battery
+    pub remaining_energy_kwh: f32,
+    /// weight of the cargo on board
+    pub payload_grams: i64,
+}
+
+/// A reachable alternate vertiport
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiversionOption {
+    /// id of the alternate vertiport
+    pub vertiport_id: String,
+    /// route from the current position to the vertiport
+    pub route: Vec<Location>,
+    /// length of the route
+    pub distance_km: f32,
+    /// estimated time of arrival
+    pub eta: DateTime<Tz>,
+}
+
+/// Distance the aircraft can fly with the remaining energy, keeping the reserve
+pub fn diversion_range_km(remaining_energy_kwh: f32, payload_grams: i64) -> f32 {
+    let payload_kg = payload_grams.max(0) as f32 / 1000.0;
+    let usable_kwh = remaining_energy_kwh.max(0.0) * (1.0 - ENERGY_RESERVE_FRACTION);
+    usable_kwh / (ENERGY_KWH_PER_KM + PAYLOAD_ENERGY_KWH_PER_KM_PER_KG * payload_kg)
+}
+
+/// Checks if a pad of the vertiport is free for a landing at `eta`
+fn has_free_pad(
+    vertiport_id: &str,
+    vehicle_id: &str,
+    vertipads: &[Vertipad],
+    eta: DateTime<Tz>,
+    existing_flight_plans: &[FlightPlan],
+) -> bool {
+    let num_vertipads = vertipads
+        .iter()
+        .filter(|vertipad| {
+            vertipad
+                .data
+                .as_ref()
+                .is_some_and(|data| data.vertiport_id == vertiport_id && data.enabled)
+        })
+        .count()
+        .max(1);
+    let occupied =
+        get_all_vehicles_scheduled_for_vertiport(vertiport_id, eta, existing_flight_plans)
+            .iter()
+            .filter(|(id, _)| id != vehicle_id)
+            .count();
+    occupied < num_vertipads
+}
+
+/// Ranks the alternate vertiports reachable from the aircraft's position on the given router
+///
+/// # Arguments
+/// * `router` - router with the graph of vertiports
+/// * `constraint` - max distance of a leg of the graph
+/// * `request` - state of the diverting aircraft
+/// * `vertipads` - vertipads of all vertiports
+/// * `existing_flight_plans` - scheduled flight plans
+/// * `constraints` - constraints the routes comply with
+///
+/// # Returns
+/// Reachable vertiports with a free pad, ordered by time of arrival
+pub fn plan_diversion_with(
+    router: &Router,
+    constraint: Distance,
+    request: &DiversionRequest,
+    vertipads: &[Vertipad],
+    existing_flight_plans: &[FlightPlan],
+    constraints: &[Arc<dyn RouteConstraint>],
+) -> Vec<DiversionOption> {
+    let range_km = diversion_range_km(request.remaining_energy_kwh, request.payload_grams);
+    info!(
+        "Planning diversion of vehicle {} with a range of {:.1} km",
+        request.vehicle_id, range_km
+    );
+    let mut options: Vec<DiversionOption> = router
+        .find_shortest_paths_from_location_with_penalty(
+            &request.location,
+            constraint.km().min(range_km),
+            |from, to| {
+                check_leg_constraints(constraints, from, to)
+                    .ok()
+                    .map(|_| 0.0)
+            },
+        )
+        .into_iter()
+        .filter(|(_, (distance_km, _))| *distance_km <= range_km)
+        .filter_map(|(index, (distance_km, path))| {
+            let node = router.get_node_by_id(index)?;
+            if node.status != Status::Ok {
+                return None;
+            }
+            let eta = request.timestamp
+                + Duration::seconds((distance_km / AVG_SPEED_KMH * 3600.0).ceil() as i64);
+            if !has_free_pad(
+                &node.uid,
+                &request.vehicle_id,
+                vertipads,
+                eta,
+                existing_flight_plans,
+            ) {
+                debug!("No free pad at vertiport {} at {}", node.uid, eta);
+                return None;
+            }
+            let route = std::iter::once(Some(request.location))
+                .chain(
+                    path.iter()
+                        .map(|index| router.get_node_by_id(*index).map(|node| node.location)),
+                )
+                .collect::<Option<Vec<Location>>>()?;
+            if let Err(e) = check_route_constraints(constraints, &route) {
+                debug!("Route to vertiport {} rejected: {}", node.uid, e);
+                return None;
+            }
+            Some(DiversionOption {
+                vertiport_id: node.uid.clone(),
+                route,
+                distance_km,
+                eta,
+            })
+        })
+        .collect();
+    options.sort_by(|a, b| {
+        a.eta
+            .cmp(&b.eta)
+            .then_with(|| a.vertiport_id.cmp(&b.vertiport_id))
+    });
+    debug!("Diversion options: {:?}", options);
+    options
+}
+
+/// Ranks the alternate vertiports reachable from the aircraft's position,
+/// keeping to the registered route constraints
+///
+/// # Arguments
+/// * `request` - state of the diverting aircraft
+/// * `vertipads` - vertipads of all vertiports
+/// * `existing_flight_plans` - scheduled flight plans
+///
+/// # Returns
+/// Reachable vertiports with a free pad, ordered by time of arrival
+pub fn plan_diversion(
+    request: &DiversionRequest,
+    vertipads: &[Vertipad],
+    existing_flight_plans: &[FlightPlan],
+) -> Result<Vec<DiversionOption>, String> {
+    let router = ARROW_CARGO_ROUTER
+        .get()
+        .ok_or("Arrow XL router not initialized. Try to initialize it first.")?;
+    Ok(plan_diversion_with(
+        router,
+        ARROW_CARGO_CONSTRAINT,
+        request,
+        vertipads,
+        existing_flight_plans,
+        &get_route_constraints(),
+    ))
+}
+
+#[cfg(test)]
+mod diversion_tests {
+    use super::*;
+    use crate::haversine;
+    use crate::node::Node;
+    use crate::router_state::create_flight_plan_data;
+    use crate::test_support::mock_location;
+    use chrono::TimeZone;
+
+    fn location(longitude: f32) -> Location {
+        mock_location(0.0, longitude)
+    }
+
+    /// vertiports on the equator, about 22 km apart
+    fn nodes() -> Vec<Node> {
+        ["A", "B", "C", "D"]
+            .iter()
+            .enumerate()
+            .map(|(i, uid)| Node::new(*uid, 0.0, i as f32 * 0.2))
+            .collect()
+    }
+
+    /// Bans the legs ending at a longitude
+    struct LongitudeBan(f32);
+
+    impl RouteConstraint for LongitudeBan {
+        fn name(&self) -> &str {
+            "diversion-longitude-ban"
+        }
+
+        fn check_leg(&self, _from: &Location, to: &Location) -> Result<(), String> {
+            if (to.longitude.into_inner() - self.0).abs() < 1e-6 {
+                return Err(format!("longitude {} banned", self.0));
+            }
+            Ok(())
+        }
+    }
+
+    fn request() -> DiversionRequest {
+        DiversionRequest {
+            vehicle_id: "v1".to_string(),
+            location: location(0.25),
+            timestamp: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap(),
+            remaining_energy_kwh: 15.0,
+            payload_grams: 0,
+        }
+    }
+
+    #[test]
+    fn test_diversion_range() {
+        assert!((diversion_range_km(20.0, 0) - 45.0).abs() < 0.01);
+        assert!(diversion_range_km(20.0, 100_000) < 40.0);
+        assert_eq!(diversion_range_km(-1.0, 0), 0.0);
+    }
+
+    #[test]
+    fn test_plan_diversion() {
+        let nodes = nodes();
+        let router = Router::new(
+            &nodes,
+            30.0,
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+        );
+        let options =
+            plan_diversion_with(&router, Distance::from_km(30.0), &request(), &[], &[], &[]);
+        let ids: Vec<&str> = options
+            .iter()
+            .map(|option| option.vertiport_id.as_str())
+            .collect();
+        // D is about 39 km away along the route, beyond the range of 33.75 km
+        assert_eq!(ids, vec!["B", "C", "A"]);
+        assert_eq!(options[0].route, vec![location(0.25), location(0.2)]);
+        assert!(options[0].eta < options[1].eta);
+
+        // the only pad of B is taken by another vehicle
+        let timestamp = request().timestamp;
+        let flight_plans = vec![FlightPlan {
+            id: "fp1".to_string(),
+            data: Some(create_flight_plan_data(
+                "v2".to_string(),
+                "C".to_string(),
+                "B".to_string(),
+                timestamp - Duration::minutes(30),
+                timestamp,
+            )),
+        }];
+        let options = plan_diversion_with(
+            &router,
+            Distance::from_km(30.0),
+            &request(),
+            &[],
+            &flight_plans,
+            &[],
+        );
+        assert_eq!(options[0].vertiport_id, "C");
+
+        // B can't be flown to, A is reached without stopping over B
+        let constraints: Vec<Arc<dyn RouteConstraint>> = vec![Arc::new(LongitudeBan(0.2))];
+        let options = plan_diversion_with(
+            &router,
+            Distance::from_km(30.0),
+            &request(),
+            &[],
+            &[],
+            &constraints,
+        );
+        let ids: Vec<&str> = options
+            .iter()
+            .map(|option| option.vertiport_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["C", "A"]);
+        assert_eq!(options[1].route, vec![location(0.25), location(0.0)]);
+    }
+}
@@ -0,0 +1,403 @@
+//! State of charge of the vehicles through their scheduled flights.
+//!
+//! The battery of a vehicle is configured per vehicle id with
+//! [`set_vehicle_battery`], with its last known state of charge, e.g. from
+//! telemetry, and the blocks of time the vehicle is charging.
+//! [`projected_state_of_charge`] plays the flight plans and charging blocks
+//! of the vehicle forward from the last known state, and the vehicle
+//! availability check only assigns a flight to a vehicle projected to land
+//! with its reserve. Batteries lose capacity as they age, the capacity left
+//! of each battery is supplied by the [`DegradationModel`] set with
+//! [`set_degradation_model`]. Flights use the energy of the
+//! [`diversion`](crate::diversion) model, and later flights already
+//! scheduled for the vehicle aren't checked again.
+
+use chrono::DateTime;
+use rrule::Tz;
+use std::collections::HashMap;
+
+use crate::context::current_context;
+use crate::diversion::{ENERGY_KWH_PER_KM, PAYLOAD_ENERGY_KWH_PER_KM_PER_KG};
+use crate::router_state::{
+    FlightPlan, FlightPlanData, Vehicle, AVG_SPEED_KMH, LANDING_AND_UNLOADING_TIME_MIN,
+    LOADING_AND_TAKEOFF_TIME_MIN,
+};
+
+/// Time a vehicle is charging on the ground
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChargingBlock {
+    /// start of the charge
+    pub start: DateTime<Tz>,
+    /// end of the charge
+    pub end: DateTime<Tz>,
+    /// power charged, the charge rate of the battery if None, e.g. on a
+    /// slower charger
+    pub power_kw: Option<f32>,
+}
+
+/// Battery of a vehicle and its last known state of charge
+#[derive(Debug, Clone, PartialEq)]
+pub struct VehicleBattery {
+    /// nameplate energy of a full battery, degraded by the
+    /// [`DegradationModel`] as the battery ages
+    pub capacity_kwh: f32,
+    /// power charged during the charging blocks
+    pub charge_rate_kw: f32,
+    /// fraction of the effective capacity to keep on landing
+    pub reserve_state_of_charge: f32,
+    /// last known fraction of the effective capacity charged
+    pub state_of_charge: f32,
+    /// time of the last known state of charge
+    pub updated_at: DateTime<Tz>,
+    /// charging blocks of the vehicle, in any order
+    pub charging_blocks: Vec<ChargingBlock>,
+}
+
+/// Usable capacity of the batteries as they age
+pub trait DegradationModel: Send + Sync {
+    /// Energy of the full battery of the vehicle, from its nameplate capacity
+    fn effective_capacity_kwh(&self, vehicle_id: &str, nameplate_kwh: f32) -> f32;
+}
+
+/// Batteries keeping their nameplate capacity
+#[derive(Debug, Copy, Clone, Default)]
+pub struct NoDegradation;
+
+impl DegradationModel for NoDegradation {
+    fn effective_capacity_kwh(&self, _vehicle_id: &str, nameplate_kwh: f32) -> f32 {
+        nameplate_kwh
+    }
+}
+
+/// Fraction of the nameplate capacity left by vehicle id, e.g. the state of
+/// health reported by the battery management systems, the nameplate capacity
+/// for the other vehicles
+impl DegradationModel for HashMap<String, f32> {
+    fn effective_capacity_kwh(&self, vehicle_id: &str, nameplate_kwh: f32) -> f32 {
+        self.get(vehicle_id).map_or(nameplate_kwh, |health| {
+            health.clamp(0.0, 1.0) * nameplate_kwh
+        })
+    }
+}
+
+/// Sets the degradation model used by the energy checks
+pub fn set_degradation_model(model: Box<dyn DegradationModel>) {
+    match current_context().degradation_model.write() {
+        Ok(mut current) => *current = model,
+        Err(_) => error!("Degradation model unavailable"),
+    }
+}
+
+/// Gets the energy of the full battery of the vehicle with the configured
+/// [`DegradationModel`], the nameplate capacity of the battery if it's
+/// unavailable
+pub fn get_effective_capacity_kwh(vehicle_id: &str, battery: &VehicleBattery) -> f32 {
+    match current_context().degradation_model.read() {
+        Ok(model) => model
+            .effective_capacity_kwh(vehicle_id, battery.capacity_kwh)
+            .max(0.0),
+        Err(_) => {
+            error!("Degradation model unavailable");
+            battery.capacity_kwh
+        }
+    }
+}
+
+/// Estimates the energy used by a flight, from its distance if recorded or
+/// else from its time en route, with its cargo
+pub fn flight_energy_kwh(flight_plan: &FlightPlanData) -> f32 {
+    let distance_km = if flight_plan.flight_distance_meters > 0 {
+        flight_plan.flight_distance_meters as f32 / 1000.0
+    } else {
+        match (
+            &flight_plan.scheduled_departure,
+            &flight_plan.scheduled_arrival,
+        ) {
+            (Some(departure), Some(arrival)) => {
+                let minutes = (arrival.seconds - departure.seconds) as f32 / 60.0;
+                en_route_km(minutes)
+            }
+            _ => 0.0,
+        }
+    };
+    let payload_kg = flight_plan
+        .cargo_weight_grams
+        .iter()
+        .map(|grams| (*grams).max(0))
+        .sum::<i64>() as f32
+        / 1000.0;
+    distance_km * (ENERGY_KWH_PER_KM + PAYLOAD_ENERGY_KWH_PER_KM_PER_KG * payload_kg)
+}
+
+/// Distance flown at the average speed in a flight of `flight_minutes`,
+/// including takeoff and landing
+fn en_route_km(flight_minutes: f32) -> f32 {
+    let en_route_minutes =
+        flight_minutes - LOADING_AND_TAKEOFF_TIME_MIN - LANDING_AND_UNLOADING_TIME_MIN;
+    en_route_minutes.max(0.0) * AVG_SPEED_KMH / 60.0
+}
+
+/// Projects the state of charge of a vehicle at a time
+///
+/// Starting from the last known state of charge of the battery, the energy
+/// of each flight plan of the vehicle departing before `time` is used at its
+/// departure and the charging blocks add their charge until `time`, within
+/// the effective capacity of the battery.
+///
+/// `existing_flight_plans` must hold the flight plans of the vehicle since
+/// the last known state of charge, not only those around `time`.
+///
+/// # Returns
+/// The fraction of the effective capacity charged at `time`, the last known
+/// state of charge if `time` is before it
+pub fn projected_state_of_charge(
+    vehicle_id: &str,
+    battery: &VehicleBattery,
+    time: DateTime<Tz>,
+    existing_flight_plans: &[FlightPlan],
+) -> f32 {
+    let (start, end) = (battery.updated_at.timestamp(), time.timestamp());
+    // (seconds, kWh) of each change of energy, charges ending at their time
+    let mut changes: Vec<(i64, f32)> = existing_flight_plans
+        .iter()
+        .filter_map(|flight_plan| flight_plan.data.as_ref())
+        .filter(|data| data.vehicle_id == vehicle_id)
+        .filter_map(|data| {
+            let departure = data.scheduled_departure.as_ref()?.seconds;
+            (start..end)
+                .contains(&departure)
+                .then(|| (departure, -flight_energy_kwh(data)))
+        })
+        .collect();
+    changes.extend(battery.charging_blocks.iter().filter_map(|block| {
+        let from = block.start.timestamp().max(start);
+        let to = block.end.timestamp().min(end);
+        let power_kw = block.power_kw.unwrap_or(battery.charge_rate_kw);
+        (from < to).then(|| (to, power_kw * (to - from) as f32 / 3600.0))
+    }));
+    changes.sort_by_key(|(seconds, _)| *seconds);
+    let capacity_kwh = get_effective_capacity_kwh(vehicle_id, battery);
+    let energy_kwh = changes.into_iter().fold(
+        battery.state_of_charge * capacity_kwh,
+        |energy_kwh, (_, change_kwh)| (energy_kwh + change_kwh).clamp(0.0, capacity_kwh),
+    );
+    if capacity_kwh > 0.0 {
+        energy_kwh / capacity_kwh
+    } else {
+        0.0
+    }
+}
+
+/// Gets the earliest departure of the flight plans projected by
+/// [`projected_state_of_charge`] for a vehicle, i.e. the time of the last
+/// known state of charge of its battery
+///
+/// # Returns
+/// None if no battery is configured for the vehicle
+pub fn charge_projected_since(vehicle_id: &str) -> Option<DateTime<Tz>> {
+    get_vehicle_battery(vehicle_id).map(|battery| battery.updated_at)
+}
+
+/// Checks if the vehicle is projected to land with its reserve from a flight
+/// of `flight_minutes` departing at `departure_time`, flown empty
+/// Always true if no battery is configured for the vehicle
+pub fn has_sufficient_charge(
+    vehicle: &Vehicle,
+    departure_time: DateTime<Tz>,
+    flight_minutes: i64,
+    existing_flight_plans: &[FlightPlan],
+) -> bool {
+    let Some(battery) = get_vehicle_battery(&vehicle.id) else {
+        return true;
+    };
+    let capacity_kwh = get_effective_capacity_kwh(&vehicle.id, &battery);
+    let state_of_charge =
+        projected_state_of_charge(&vehicle.id, &battery, departure_time, existing_flight_plans);
+    let needed_kwh = en_route_km(flight_minutes as f32) * ENERGY_KWH_PER_KM
+        + battery.reserve_state_of_charge * capacity_kwh;
+    if state_of_charge * capacity_kwh < needed_kwh {
+        debug!(
+            "Vehicle {} projected at {:.0}% charge, {:.1} kWh needed",
+            vehicle.id,
+            state_of_charge * 100.0,
+            needed_kwh
+        );
+        return false;
+    }
+    true
+}
+
+/// Distance the vehicle can fly on a full battery of its effective capacity,
+/// keeping its reserve
+pub fn vehicle_range_km(vehicle_id: &str, battery: &VehicleBattery, payload_grams: i64) -> f32 {
+    let payload_kg = payload_grams.max(0) as f32 / 1000.0;
+    let usable_kwh = get_effective_capacity_kwh(vehicle_id, battery)
+        * (1.0 - battery.reserve_state_of_charge).max(0.0);
+    usable_kwh / (ENERGY_KWH_PER_KM + PAYLOAD_ENERGY_KWH_PER_KM_PER_KG * payload_kg)
+}
+
+/// Sets the battery of a vehicle
+pub fn set_vehicle_battery(vehicle_id: &str, battery: VehicleBattery) {
+    match current_context().vehicle_batteries.write() {
+        Ok(mut batteries) => {
+            batteries.insert(vehicle_id.to_string(), battery);
+        }
+        Err(_) => error!("Vehicle batteries unavailable"),
+    }
+}
+
+/// Removes the battery of a vehicle, its charge is no longer checked
+pub fn clear_vehicle_battery(vehicle_id: &str) {
+    match current_context().vehicle_batteries.write() {
+        Ok(mut batteries) => {
+            batteries.remove(vehicle_id);
+        }
+        Err(_) => error!("Vehicle batteries unavailable"),
+    }
+}
+
+/// Gets the battery of a vehicle, if any
+pub fn get_vehicle_battery(vehicle_id: &str) -> Option<VehicleBattery> {
+    current_context()
+        .vehicle_batteries
+        .read()
+        .ok()
+        .and_then(|batteries| batteries.get(vehicle_id).cloned())
+}
+
+#[cfg(test)]
+mod energy_tests {
+    use super::*;
+    use crate::router_state::is_vehicle_available;
+    use crate::test_support::{mock_start, MockFlightPlan, MockVehicle};
+    use chrono::Duration;
+
+    /// 40 kWh battery, half charged at the start, charging at 24 kW from 3:00 to 3:30
+    fn battery() -> VehicleBattery {
+        VehicleBattery {
+            capacity_kwh: 40.0,
+            charge_rate_kw: 24.0,
+            reserve_state_of_charge: 0.2,
+            state_of_charge: 0.5,
+            updated_at: mock_start(),
+            charging_blocks: vec![ChargingBlock {
+                start: mock_start() + Duration::hours(3),
+                end: mock_start() + Duration::minutes(210),
+                power_kw: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_projected_state_of_charge() {
+        // flights of 50 minutes, 30 en route or 30 km
+        let flight = |id: &str, vehicle_id: &str, hour: i64| {
+            MockFlightPlan::new(id, vehicle_id, "SFO", "OAK")
+                .with_departure(mock_start() + Duration::hours(hour))
+                .with_duration(Duration::minutes(50))
+                .build()
+        };
+        let flight_plans = vec![
+            flight("plan-1", "energy-projection", 1),
+            flight("plan-2", "energy-projection", 2),
+            flight("plan-3", "other", 2),
+            flight("plan-4", "energy-projection", -1),
+        ];
+        let battery = battery();
+        let soc = |minutes| {
+            projected_state_of_charge(
+                "energy-projection",
+                &battery,
+                mock_start() + Duration::minutes(minutes),
+                &flight_plans,
+            )
+        };
+        assert_eq!(soc(-60), 0.5);
+        assert_eq!(soc(60), 0.5);
+        // 12 kWh per flight
+        assert!((soc(61) - 0.2).abs() < 1e-6);
+        assert_eq!(soc(150), 0.0);
+        // 6 kWh charged by 3:15, 12 kWh by the end of the block
+        assert!((soc(195) - 0.15).abs() < 1e-6);
+        assert!((soc(600) - 0.3).abs() < 1e-6);
+
+        let mut full = battery.clone();
+        full.state_of_charge = 1.0;
+        full.charging_blocks[0].end = mock_start() + Duration::hours(10);
+        assert_eq!(
+            projected_state_of_charge("none", &full, mock_start() + Duration::hours(5), &[]),
+            1.0
+        );
+
+        let mut flight = flight("plan-5", "energy-projection", 0).data.unwrap();
+        flight.flight_distance_meters = 10_000;
+        flight.cargo_weight_grams = vec![100_000];
+        assert!((flight_energy_kwh(&flight) - 6.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_vehicle_needs_charge() {
+        let vehicle = MockVehicle::new("energy-availability").build();
+        let flight_plans = vec![MockFlightPlan::new("plan-1", &vehicle.id, "SFO", "OAK")
+            .with_duration(Duration::minutes(50))
+            .build()];
+        let after_flight = mock_start() + Duration::hours(1);
+        assert!(is_vehicle_available(&vehicle, after_flight, 50, &flight_plans).unwrap());
+
+        set_vehicle_battery(&vehicle.id, battery());
+        // 8 kWh left, 10 kWh needed for a flight of 25 km and 8 kWh of reserve
+        assert!(!has_sufficient_charge(
+            &vehicle,
+            after_flight,
+            45,
+            &flight_plans
+        ));
+        assert!(!is_vehicle_available(&vehicle, after_flight, 45, &flight_plans).unwrap());
+        // 20 kWh before the flight
+        assert!(has_sufficient_charge(&vehicle, mock_start(), 45, &[]));
+        // 20 kWh after charging
+        let charged = mock_start() + Duration::hours(4);
+        assert!(is_vehicle_available(&vehicle, charged, 45, &flight_plans).unwrap());
+        assert_eq!(
+            charge_projected_since(&vehicle.id),
+            Some(battery().updated_at)
+        );
+
+        clear_vehicle_battery(&vehicle.id);
+        assert!(get_vehicle_battery(&vehicle.id).is_none());
+        assert!(charge_projected_since(&vehicle.id).is_none());
+        assert!(is_vehicle_available(&vehicle, after_flight, 50, &flight_plans).unwrap());
+    }
+
+    #[test]
+    fn test_degraded_capacity() {
+        let aged = MockVehicle::new("energy-aged").build();
+        let battery = battery();
+        // 24 kWh usable at nameplate
+        assert!((vehicle_range_km(&aged.id, &battery, 0) - 80.0).abs() < 1e-3);
+        set_vehicle_battery(&aged.id, battery.clone());
+        assert!(has_sufficient_charge(&aged, mock_start(), 45, &[]));
+
+        set_degradation_model(Box::new(HashMap::from([(aged.id.clone(), 0.5)])));
+        assert_eq!(get_effective_capacity_kwh(&aged.id, &battery), 20.0);
+        assert_eq!(get_effective_capacity_kwh("energy-new", &battery), 40.0);
+        assert!((vehicle_range_km(&aged.id, &battery, 0) - 40.0).abs() < 1e-3);
+        // 10 kWh left, 10 kWh needed for the flight and 4 kWh of reserve
+        assert!(!has_sufficient_charge(&aged, mock_start(), 45, &[]));
+        let flight_plans = vec![MockFlightPlan::new("plan-1", &aged.id, "SFO", "OAK")
+            .with_duration(Duration::minutes(50))
+            .build()];
+        assert_eq!(
+            projected_state_of_charge(
+                &aged.id,
+                &battery,
+                mock_start() + Duration::hours(1),
+                &flight_plans
+            ),
+            0.0
+        );
+
+        set_degradation_model(Box::new(NoDegradation));
+        clear_vehicle_battery(&aged.id);
+    }
+}
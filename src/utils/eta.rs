@@ -0,0 +1,256 @@
+//! Arrival time predictions with uncertainty.
+//!
+//! The scheduled arrival of a flight plan is a point estimate assuming still
+//! air and no delays. [`predict_eta`] flies the route with the ground speed
+//! of the aircraft profile in the configured wind and spreads the nominal
+//! arrival with a historical delay model, giving a distribution of arrival
+//! times from which quantiles such as P50 and P90 are read.
+
+use chrono::{DateTime, Duration};
+use rrule::Tz;
+
+use crate::context::current_context;
+use crate::haversine;
+use crate::location::Location;
+use crate::router_state::{
+    Aircraft, AVG_SPEED_KMH, LANDING_AND_UNLOADING_TIME_MIN, LOADING_AND_TAKEOFF_TIME_MIN,
+};
+
+/// Min ground speed as a fraction of the airspeed, however strong the headwind
+const MIN_GROUND_SPEED_FRACTION: f32 = 0.1;
+
+/// Speeds and ground times of an aircraft type
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AircraftProfile {
+    /// cruise airspeed
+    pub cruise_speed_kmh: f32,
+    /// loading and takeoff time before the aircraft is en route
+    pub takeoff_minutes: f32,
+    /// landing and unloading time after the aircraft is en route
+    pub landing_minutes: f32,
+}
+
+impl AircraftProfile {
+    /// Profile of the given aircraft type
+    pub fn of(aircraft: Aircraft) -> Self {
+        match aircraft {
+            Aircraft::Cargo => AircraftProfile {
+                cruise_speed_kmh: AVG_SPEED_KMH,
+                takeoff_minutes: LOADING_AND_TAKEOFF_TIME_MIN,
+                landing_minutes: LANDING_AND_UNLOADING_TIME_MIN,
+            },
+        }
+    }
+}
+
+/// Wind along the route
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Wind {
+    /// wind speed
+    pub speed_kmh: f32,
+    /// direction the wind blows from, in degrees clockwise from north
+    pub from_degrees: f32,
+}
+
+/// Empirical distribution of arrival delays
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistoricalDelays {
+    samples_minutes: Vec<f32>,
+}
+
+impl HistoricalDelays {
+    /// Creates the model from observed delays in minutes, negative if early
+    pub fn new(samples_minutes: Vec<f32>) -> Self {
+        let mut samples_minutes: Vec<f32> = samples_minutes
+            .into_iter()
+            .filter(|sample| sample.is_finite())
+            .collect();
+        samples_minutes.sort_by(f32::total_cmp);
+        Self { samples_minutes }
+    }
+
+    /// Delay in minutes at quantile `q` from 0.0 to 1.0, 0.0 without samples
+    pub fn quantile_minutes(&self, q: f32) -> f32 {
+        let Some(last) = self.samples_minutes.len().checked_sub(1) else {
+            return 0.0;
+        };
+        let rank = q.clamp(0.0, 1.0) * last as f32;
+        let lower = rank.floor() as usize;
+        let upper = rank.ceil() as usize;
+        let weight = rank - lower as f32;
+        self.samples_minutes[lower] * (1.0 - weight) + self.samples_minutes[upper] * weight
+    }
+}
+
+/// Configuration of the arrival time predictions
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EtaModel {
+    /// historical delays added to the nominal arrival
+    pub delays: HistoricalDelays,
+    /// wind along the routes, still air if `None`
+    pub wind: Option<Wind>,
+}
+
+/// Predicted distribution of the arrival time of a flight
+#[derive(Debug, Clone, PartialEq)]
+pub struct EtaDistribution {
+    /// arrival without delays
+    pub nominal_arrival: DateTime<Tz>,
+    delays: HistoricalDelays,
+}
+
+impl EtaDistribution {
+    /// Arrival time at quantile `q` from 0.0 to 1.0
+    pub fn quantile(&self, q: f32) -> DateTime<Tz> {
+        let delay_seconds = (self.delays.quantile_minutes(q) * 60.0).round() as i64;
+        self.nominal_arrival + Duration::seconds(delay_seconds)
+    }
+
+    /// Median arrival time
+    pub fn p50(&self) -> DateTime<Tz> {
+        self.quantile(0.5)
+    }
+
+    /// Arrival time not exceeded in 90% of the flights
+    pub fn p90(&self) -> DateTime<Tz> {
+        self.quantile(0.9)
+    }
+}
+
+/// Initial bearing from one location to another, in degrees clockwise from north
+pub(crate) fn bearing_degrees(from: &Location, to: &Location) -> f32 {
+    let lat1 = from.latitude.into_inner().to_radians();
+    let lat2 = to.latitude.into_inner().to_radians();
+    let d_lon = (to.longitude.into_inner() - from.longitude.into_inner()).to_radians();
+    let y = d_lon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lon.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+/// Ground speed flying towards `track_degrees` in the wind
+pub(crate) fn ground_speed_kmh(
+    cruise_speed_kmh: f32,
+    wind: Option<Wind>,
+    track_degrees: f32,
+) -> f32 {
+    let Some(wind) = wind else {
+        return cruise_speed_kmh;
+    };
+    let headwind = wind.speed_kmh * (wind.from_degrees - track_degrees).to_radians().cos();
+    (cruise_speed_kmh - headwind).max(cruise_speed_kmh * MIN_GROUND_SPEED_FRACTION)
+}
+
+/// Predicts the arrival time distribution of a flight
+///
+/// # Arguments
+/// * `departure` - scheduled departure time
+/// * `route` - locations along the route, from departure to destination
+/// * `profile` - speeds and ground times of the aircraft
+/// * `model` - wind and historical delays
+///
+/// # Returns
+/// The distribution of the arrival time
+pub fn predict_eta(
+    departure: DateTime<Tz>,
+    route: &[Location],
+    profile: &AircraftProfile,
+    model: &EtaModel,
+) -> EtaDistribution {
+    let en_route_minutes: f32 = route
+        .windows(2)
+        .map(|leg| {
+            let speed = ground_speed_kmh(
+                profile.cruise_speed_kmh,
+                model.wind,
+                bearing_degrees(&leg[0], &leg[1]),
+            );
+            haversine::distance(&leg[0], &leg[1]) / speed * 60.0
+        })
+        .sum();
+    let minutes = profile.takeoff_minutes + en_route_minutes + profile.landing_minutes;
+    EtaDistribution {
+        nominal_arrival: departure + Duration::seconds((minutes * 60.0).round() as i64),
+        delays: model.delays.clone(),
+    }
+}
+
+/// Sets the wind and historical delays used for arrival time predictions
+pub fn set_eta_model(model: EtaModel) {
+    match current_context().eta_model.write() {
+        Ok(mut current) => *current = model,
+        Err(_) => error!("ETA model unavailable"),
+    }
+}
+
+/// Gets the wind and historical delays used for arrival time predictions
+pub fn get_eta_model() -> EtaModel {
+    current_context()
+        .eta_model
+        .read()
+        .map(|model| model.clone())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod eta_tests {
+    use super::*;
+    use crate::test_support::mock_location;
+    use chrono::TimeZone;
+
+    fn departure() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap()
+    }
+
+    /// 60 km due east along the equator
+    fn route() -> Vec<Location> {
+        vec![mock_location(0.0, 0.0), mock_location(0.0, 60.0 / 111.195)]
+    }
+
+    #[test]
+    fn test_delay_quantiles() {
+        let delays = HistoricalDelays::new(vec![10.0, 0.0, -2.0, 4.0, 6.0, f32::NAN]);
+        assert_eq!(delays.quantile_minutes(0.0), -2.0);
+        assert_eq!(delays.quantile_minutes(0.5), 4.0);
+        assert_eq!(delays.quantile_minutes(0.875), 8.0);
+        assert_eq!(delays.quantile_minutes(1.0), 10.0);
+        assert_eq!(HistoricalDelays::default().quantile_minutes(0.9), 0.0);
+    }
+
+    #[test]
+    fn test_nominal_eta_matches_schedule() {
+        let profile = AircraftProfile::of(Aircraft::Cargo);
+        let eta = predict_eta(departure(), &route(), &profile, &EtaModel::default());
+        // 10 minutes takeoff, 60 minutes en route, 10 minutes landing
+        let expected = departure() + Duration::minutes(80);
+        assert!((eta.nominal_arrival - expected).num_seconds().abs() <= 1);
+        assert_eq!(eta.p50(), eta.p90());
+    }
+
+    #[test]
+    fn test_eta_with_wind_and_delays() {
+        let profile = AircraftProfile::of(Aircraft::Cargo);
+        let model = EtaModel {
+            delays: HistoricalDelays::new(vec![0.0, 2.0, 4.0, 6.0, 8.0, 10.0]),
+            // headwind from the east halves the ground speed
+            wind: Some(Wind {
+                speed_kmh: 30.0,
+                from_degrees: 90.0,
+            }),
+        };
+        let eta = predict_eta(departure(), &route(), &profile, &model);
+        let expected = departure() + Duration::minutes(140);
+        assert!((eta.nominal_arrival - expected).num_seconds().abs() <= 1);
+        assert_eq!(eta.p50() - eta.nominal_arrival, Duration::minutes(5));
+        assert_eq!(eta.p90() - eta.nominal_arrival, Duration::minutes(9));
+
+        let tailwind = EtaModel {
+            wind: Some(Wind {
+                speed_kmh: 30.0,
+                from_degrees: 270.0,
+            }),
+            ..Default::default()
+        };
+        let eta = predict_eta(departure(), &route(), &profile, &tailwind);
+        assert!(eta.nominal_arrival < departure() + Duration::minutes(61));
+    }
+}
@@ -0,0 +1,515 @@
+//! Routing across regions of a large network, each with its own router.
+//!
+//! A nationwide network is split into regions with [`partition_nodes`], and
+//! each region gets a [`Router`] of its own nodes, so no single graph holds
+//! every leg. Nodes put in several regions, e.g. vertiports on a border, are
+//! the gateways between them.
+//!
+//! A [`Federation`] precomputes the costs between the gateways of each
+//! region once. A route across regions is then searched on the small graph
+//! of the gateways and the two ends of the route, and each of its hops is
+//! expanded into the path found by the router of its region. Both searches
+//! keep to the [route constraints](crate::constraints).
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::sync::Arc;
+
+use ordered_float::OrderedFloat;
+
+use crate::constraints::{
+    check_leg_constraints, check_route_constraints, get_route_constraints, RouteConstraint,
+};
+use crate::node::Node;
+use crate::router::engine::Router;
+
+/// A region of the network with the router of its nodes
+#[derive(Debug)]
+pub struct Region<'a> {
+    /// name of the region
+    pub name: String,
+    /// router of the nodes of the region
+    pub router: Router<'a>,
+}
+
+/// A hop of a route between two nodes of the same region
+#[derive(Debug, Clone, PartialEq)]
+struct Hop {
+    /// uid of the node at the end of the hop
+    to: String,
+    /// index of the region the hop is flown in
+    region: usize,
+    /// cost of the shortest path of the hop in its region
+    cost: f32,
+}
+
+/// Regional routers stitched together at their gateways
+pub struct Federation<'a> {
+    regions: Vec<Region<'a>>,
+    /// uids of the gateways of each region
+    gateways: Vec<Vec<String>>,
+    /// hops between the gateways of each region, by uid of their start
+    overlay: HashMap<String, Vec<Hop>>,
+    /// constraints the routes comply with
+    constraints: Vec<Arc<dyn RouteConstraint>>,
+}
+
+impl Debug for Federation<'_> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct("Federation")
+            .field("regions", &self.regions)
+            .field("gateways", &self.gateways)
+            .field("overlay", &self.overlay)
+            .field(
+                "constraints",
+                &self
+                    .constraints
+                    .iter()
+                    .map(|constraint| constraint.name())
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+/// Splits nodes into regions
+///
+/// # Arguments
+/// * `nodes` - the nodes of the whole network
+/// * `regions_of` - the names of the regions of a node, e.g. from the
+///   prefix of its uid or its geohash. A node in several regions is a
+///   gateway between them, and a node in none is left out.
+///
+/// # Returns
+/// The nodes of each region by name of the region
+pub fn partition_nodes<I>(
+    nodes: &[Node],
+    regions_of: impl Fn(&Node) -> I,
+) -> BTreeMap<String, Vec<Node>>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut regions: BTreeMap<String, Vec<Node>> = BTreeMap::new();
+    for node in nodes {
+        for region in regions_of(node) {
+            regions.entry(region).or_default().push(node.clone());
+        }
+    }
+    regions
+}
+
+impl<'a> Federation<'a> {
+    /// Federates the regional routers, computing the costs between the
+    /// gateways of each region
+    ///
+    /// Gateways are the nodes with a uid found in the routers of several
+    /// regions. The routes keep to the registered route constraints.
+    pub fn new(regions: Vec<Region<'a>>) -> Self {
+        Self::with_constraints(regions, get_route_constraints())
+    }
+
+    /// Federates the regional routers like [`Federation::new`], with the
+    /// routes keeping to the given constraints
+    pub fn with_constraints(
+        regions: Vec<Region<'a>>,
+        constraints: Vec<Arc<dyn RouteConstraint>>,
+    ) -> Self {
+        let mut region_count: HashMap<&str, usize> = HashMap::new();
+        for region in &regions {
+            for node in region.router.node_indices.keys() {
+                *region_count.entry(node.uid.as_str()).or_default() += 1;
+            }
+        }
+        let gateways: Vec<Vec<String>> = regions
+            .iter()
+            .map(|region| {
+                let mut gateways: Vec<String> = region
+                    .router
+                    .node_indices
+                    .keys()
+                    .filter(|node| region_count[node.uid.as_str()] > 1)
+                    .map(|node| node.uid.clone())
+                    .collect();
+                gateways.sort();
+                gateways
+            })
+            .collect();
+
+        let mut federation = Federation {
+            regions,
+            gateways,
+            overlay: HashMap::new(),
+            constraints,
+        };
+        for (region, gateways) in federation.gateways.iter().enumerate() {
+            for from in gateways {
+                for to in gateways.iter().filter(|to| *to != from) {
+                    if let Some((cost, _)) = federation.find_regional_path(region, from, to) {
+                        federation
+                            .overlay
+                            .entry(from.clone())
+                            .or_default()
+                            .push(Hop {
+                                to: to.clone(),
+                                region,
+                                cost,
+                            });
+                    }
+                }
+            }
+        }
+        info!(
+            "Federated {} regions with {} gateway hops",
+            federation.regions.len(),
+            federation.overlay.values().map(Vec::len).sum::<usize>()
+        );
+        federation
+    }
+
+    /// Gets the regions of the federation
+    pub fn regions(&self) -> &[Region<'a>] {
+        &self.regions
+    }
+
+    /// Gets the uids of the gateways of the region with the name, sorted
+    pub fn get_gateways(&self, region_name: &str) -> Option<&[String]> {
+        self.regions
+            .iter()
+            .position(|region| region.name == region_name)
+            .map(|region| self.gateways[region].as_slice())
+    }
+
+    /// Finds the shortest route between two nodes of any regions
+    ///
+    /// # Returns
+    /// The cost of the route and its nodes, with an empty route if the
+    /// nodes aren't connected, like
+    /// [`Router::find_shortest_path`], or an error if a node isn't in any
+    /// region or the route violates a constraint.
+    pub fn find_shortest_path(
+        &self,
+        from_uid: &str,
+        to_uid: &str,
+    ) -> Result<(f32, Vec<&Node>), String> {
+        let from_regions = self.regions_of(from_uid);
+        let to_regions = self.regions_of(to_uid);
+        if from_regions.is_empty() {
+            return Err(format!("Node not found by id: {}", from_uid));
+        }
+        if to_regions.is_empty() {
+            return Err(format!("Node not found by id: {}", to_uid));
+        }
+
+        // hops from the start to the gateways and end of its regions
+        let from_hops: Vec<Hop> = from_regions
+            .iter()
+            .flat_map(|&region| {
+                let ends = self.gateways[region].iter().map(String::as_str);
+                let ends = ends.chain(to_regions.contains(&region).then_some(to_uid));
+                ends.filter_map(move |to| {
+                    let (cost, _) = self.find_regional_path(region, from_uid, to)?;
+                    Some(Hop {
+                        to: to.to_string(),
+                        region,
+                        cost,
+                    })
+                })
+            })
+            .collect();
+        // hops from the gateways of the regions of the end to the end
+        let mut to_hops: HashMap<&str, Vec<Hop>> = HashMap::new();
+        for &region in &to_regions {
+            for gateway in &self.gateways[region] {
+                if let Some((cost, _)) = self.find_regional_path(region, gateway, to_uid) {
+                    to_hops.entry(gateway).or_default().push(Hop {
+                        to: to_uid.to_string(),
+                        region,
+                        cost,
+                    });
+                }
+            }
+        }
+
+        // Dijkstra on the gateways, from the start to the end
+        let mut costs: HashMap<&str, f32> = HashMap::from([(from_uid, 0.0)]);
+        let mut previous: HashMap<&str, (&str, usize)> = HashMap::new();
+        let mut queue = BinaryHeap::from([Reverse((OrderedFloat(0.0), from_uid))]);
+        while let Some(Reverse((OrderedFloat(cost), uid))) = queue.pop() {
+            if uid == to_uid {
+                break;
+            }
+            if costs.get(uid).is_some_and(|&best| cost > best) {
+                continue;
+            }
+            let hops = self.overlay.get(uid).into_iter().flatten();
+            let hops = hops.chain(to_hops.get(uid).into_iter().flatten());
+            let hops = hops.chain(
+                (uid == from_uid)
+                    .then_some(&from_hops)
+                    .into_iter()
+                    .flatten(),
+            );
+            for hop in hops {
+                let next_cost = cost + hop.cost;
+                if costs
+                    .get(hop.to.as_str())
+                    .is_none_or(|&best| next_cost < best)
+                {
+                    costs.insert(hop.to.as_str(), next_cost);
+                    previous.insert(hop.to.as_str(), (uid, hop.region));
+                    queue.push(Reverse((OrderedFloat(next_cost), hop.to.as_str())));
+                }
+            }
+        }
+        let Some(&cost) = costs.get(to_uid) else {
+            debug!("No route from {} to {}", from_uid, to_uid);
+            return Ok((0.0, vec![]));
+        };
+
+        // expand the hops into the paths of their regions
+        let mut hops = vec![];
+        let mut uid = to_uid;
+        while let Some(&(before, region)) = previous.get(uid) {
+            hops.push((before, uid, region));
+            uid = before;
+        }
+        let mut path: Vec<&Node> = vec![];
+        for (from, to, region) in hops.into_iter().rev() {
+            let (_, nodes) = self
+                .find_regional_path(region, from, to)
+                .ok_or_else(|| format!("No path from {} to {} in its region", from, to))?;
+            let skip = usize::from(!path.is_empty());
+            path.extend(nodes.into_iter().skip(skip));
+        }
+        if path.is_empty() {
+            // the start is the end
+            path.extend(
+                self.regions[from_regions[0]]
+                    .router
+                    .get_node_by_uid(from_uid),
+            );
+        }
+        let locations: Vec<_> = path.iter().map(|node| node.location).collect();
+        check_route_constraints(&self.constraints, &locations)?;
+        Ok((cost, path))
+    }
+
+    /// Indices of the regions with a node of the uid
+    fn regions_of(&self, uid: &str) -> Vec<usize> {
+        (0..self.regions.len())
+            .filter(|&region| self.regions[region].router.get_node_by_uid(uid).is_some())
+            .collect()
+    }
+
+    /// Shortest path between two nodes of a region over the legs complying
+    /// with the constraints, None if they aren't connected
+    fn find_regional_path(
+        &self,
+        region: usize,
+        from_uid: &str,
+        to_uid: &str,
+    ) -> Option<(f32, Vec<&Node>)> {
+        let router = &self.regions[region].router;
+        let from = router.get_node_by_uid(from_uid)?;
+        let to = router.get_node_by_uid(to_uid)?;
+        let (cost, path) = router
+            .find_shortest_path_with_penalty(from, to, |a, b| {
+                check_leg_constraints(&self.constraints, &a.location, &b.location)
+                    .ok()
+                    .map(|_| 0.0)
+            })
+            .ok()?;
+        if path.is_empty() {
+            return None;
+        }
+        let nodes = path
+            .into_iter()
+            .map(|index| router.get_node_by_id(index))
+            .collect::<Option<Vec<&Node>>>()?;
+        Some((cost, nodes))
+    }
+}
+
+#[cfg(test)]
+mod federation_tests {
+    use super::*;
+    use crate::haversine;
+    use crate::location::Location;
+    use crate::node::AsNode;
+
+    fn distance(from: &dyn AsNode, to: &dyn AsNode) -> f32 {
+        haversine::distance(&from.as_node().location, &to.as_node().location)
+    }
+
+    fn uids(path: &[&Node]) -> Vec<String> {
+        path.iter().map(|node| node.uid.clone()).collect()
+    }
+
+    /// Bans the legs ending at a longitude
+    struct LongitudeBan(f32);
+
+    impl RouteConstraint for LongitudeBan {
+        fn name(&self) -> &str {
+            "federation-longitude-ban"
+        }
+
+        fn check_leg(&self, _from: &Location, to: &Location) -> Result<(), String> {
+            if (to.longitude.into_inner() - self.0).abs() < 1e-6 {
+                return Err(format!("longitude {} banned", self.0));
+            }
+            Ok(())
+        }
+    }
+
+    /// Limits the number of legs of a route
+    struct MaxLegs(usize);
+
+    impl RouteConstraint for MaxLegs {
+        fn name(&self) -> &str {
+            "federation-max-legs"
+        }
+
+        fn check_leg(&self, _from: &Location, _to: &Location) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn check_route(&self, route: &[Location]) -> Result<(), String> {
+            if route.len() > self.0 + 1 {
+                return Err(format!("{} legs", route.len() - 1));
+            }
+            Ok(())
+        }
+    }
+
+    /// Nodes 0.1° apart along the equator, split in a west and an east
+    /// region joined by G
+    fn regions() -> BTreeMap<String, Vec<Node>> {
+        let nodes: Vec<Node> = [
+            ("W1", 0.0),
+            ("W2", 0.1),
+            ("G", 0.2),
+            ("E1", 0.3),
+            ("E2", 0.4),
+        ]
+        .iter()
+        .map(|(uid, longitude)| Node::new(*uid, 0.0, *longitude))
+        .collect();
+        partition_nodes(&nodes, |node| {
+            let longitude = node.location.longitude.into_inner();
+            [("west", longitude <= 0.2), ("east", longitude >= 0.2)]
+                .into_iter()
+                .filter(|(_, within)| *within)
+                .map(|(name, _)| name.to_string())
+                .collect::<Vec<_>>()
+        })
+    }
+
+    fn routers(regions: &BTreeMap<String, Vec<Node>>) -> Vec<Region<'_>> {
+        regions
+            .iter()
+            .map(|(name, nodes)| Region {
+                name: name.clone(),
+                router: Router::new(nodes, 12.0, distance, distance),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_find_shortest_path_across_regions() {
+        // nodes 0.1° apart along the equator, G joins the west and the east,
+        // I is isolated in its own region
+        let nodes: Vec<Node> = [
+            ("W1", 0.0),
+            ("W2", 0.1),
+            ("G", 0.2),
+            ("E1", 0.3),
+            ("E2", 0.4),
+        ]
+        .iter()
+        .map(|(uid, longitude)| Node::new(*uid, 0.0, *longitude))
+        .chain([Node::new("I", 10.0, 10.0)])
+        .collect();
+        let regions = partition_nodes(&nodes, |node| {
+            let longitude = node.location.longitude.into_inner();
+            let mut regions = vec![];
+            if node.uid == "I" {
+                regions.push("island".to_string());
+            } else {
+                if longitude <= 0.2 {
+                    regions.push("west".to_string());
+                }
+                if longitude >= 0.2 {
+                    regions.push("east".to_string());
+                }
+            }
+            regions
+        });
+        assert_eq!(
+            uids(&regions["west"].iter().collect::<Vec<_>>()),
+            ["W1", "W2", "G"]
+        );
+        assert_eq!(
+            uids(&regions["east"].iter().collect::<Vec<_>>()),
+            ["G", "E1", "E2"]
+        );
+
+        let federation = Federation::new(
+            regions
+                .iter()
+                .map(|(name, nodes)| Region {
+                    name: name.clone(),
+                    router: Router::new(nodes, 12.0, distance, distance),
+                })
+                .collect(),
+        );
+        assert_eq!(federation.regions().len(), 3);
+        assert_eq!(federation.get_gateways("west").unwrap(), ["G".to_string()]);
+        assert!(federation.get_gateways("island").unwrap().is_empty());
+
+        let (cost, path) = federation.find_shortest_path("W1", "E2").unwrap();
+        assert_eq!(uids(&path), ["W1", "W2", "G", "E1", "E2"]);
+        let direct = haversine::distance(&nodes[0].location, &nodes[4].location);
+        assert!((cost - direct).abs() < 0.01);
+
+        let (_, path) = federation.find_shortest_path("E2", "G").unwrap();
+        assert_eq!(uids(&path), ["E2", "E1", "G"]);
+        let (_, path) = federation.find_shortest_path("W2", "W1").unwrap();
+        assert_eq!(uids(&path), ["W2", "W1"]);
+        let (cost, path) = federation.find_shortest_path("G", "G").unwrap();
+        assert_eq!((cost, uids(&path)), (0.0, vec!["G".to_string()]));
+
+        assert_eq!(federation.find_shortest_path("W1", "I").unwrap().1.len(), 0);
+        assert!(federation.find_shortest_path("W1", "X").is_err());
+    }
+
+    #[test]
+    fn test_federation_constraints() {
+        let regions = regions();
+        let federation = Federation::with_constraints(routers(&regions), vec![]);
+        let (_, path) = federation.find_shortest_path("W1", "E2").unwrap();
+        assert_eq!(uids(&path), ["W1", "W2", "G", "E1", "E2"]);
+
+        // E1 can't be flown to, and the legs of the east are too long without it
+        let federation =
+            Federation::with_constraints(routers(&regions), vec![Arc::new(LongitudeBan(0.3))]);
+        assert!(federation
+            .find_shortest_path("W1", "E2")
+            .unwrap()
+            .1
+            .is_empty());
+        // legs leaving E1 are allowed
+        let (_, path) = federation.find_shortest_path("E1", "W1").unwrap();
+        assert_eq!(uids(&path), ["E1", "G", "W2", "W1"]);
+
+        // the route is found leg by leg, then rejected as a whole
+        let federation =
+            Federation::with_constraints(routers(&regions), vec![Arc::new(MaxLegs(2))]);
+        let (_, path) = federation.find_shortest_path("W1", "G").unwrap();
+        assert_eq!(uids(&path), ["W1", "W2", "G"]);
+        assert_eq!(
+            federation.find_shortest_path("W1", "E2"),
+            Err("Constraint federation-max-legs violated: 4 legs".to_string())
+        );
+    }
+}
@@ -0,0 +1,393 @@
+//! C bindings of the router engine.
+//!
+//! Simulation environments not written in Rust call the same routing logic
+//! as production through this small C ABI, declared in `include/router.h`:
+//! * [`router_new`] builds a router from an array of nodes
+//! * [`router_find_route`] finds the shortest route between two nodes and
+//!   writes the positions of its nodes in the array to a buffer
+//! * [`router_path_length`] gets the number of nodes of a route, to size
+//!   the buffer
+//! * [`router_free`] frees the router
+//!
+//! Nodes are referred to by their position in the array passed to
+//! [`router_new`], and legs are weighted by their Haversine distance in
+//! kilometers. Build the library with
+//! `cargo rustc --release --no-default-features --features ffi --crate-type cdylib`.
+
+use std::collections::HashMap;
+use std::ffi::{c_char, CStr};
+use std::mem::ManuallyDrop;
+
+use petgraph::graph::NodeIndex;
+
+use crate::haversine;
+use crate::node::Node;
+use crate::router::engine::{Algorithm, Router};
+
+/// The call succeeded
+pub const ROUTER_OK: i32 = 0;
+/// There is no route between the nodes
+pub const ROUTER_NO_ROUTE: i32 = 1;
+/// The buffer is too small for the route, its required length is written
+pub const ROUTER_BUFFER_TOO_SMALL: i32 = 2;
+/// A pointer is null or a node position is out of the array
+pub const ROUTER_INVALID_ARGUMENT: i32 = -1;
+
+/// A node passed from C
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct RouterNode {
+    /// id of the node, a NUL-terminated UTF-8 string copied by the router
+    pub uid: *const c_char,
+    /// latitude in degrees
+    pub latitude: f32,
+    /// longitude in degrees
+    pub longitude: f32,
+    /// altitude in meters
+    pub altitude_meters: f32,
+}
+
+/// A router owning its nodes, opaque to C
+pub struct RouterHandle {
+    /// router borrowing the nodes, dropped before them
+    router: ManuallyDrop<Router<'static>>,
+    /// position of each node of the graph in the array of nodes
+    positions: HashMap<NodeIndex, usize>,
+    /// nodes in the order of the array
+    nodes: &'static [Node],
+    /// box of the nodes, freed with the router
+    boxed_nodes: *mut [Node],
+}
+
+impl Drop for RouterHandle {
+    fn drop(&mut self) {
+        // nothing borrows the nodes once the router is dropped, and they
+        // were boxed by `router_new`
+        unsafe {
+            ManuallyDrop::drop(&mut self.router);
+            drop(Box::from_raw(self.boxed_nodes));
+        }
+    }
+}
+
+impl RouterHandle {
+    /// Shortest route between two positions, as positions, and its length
+    fn find_route(&self, from: usize, to: usize) -> Option<(Vec<usize>, f32)> {
+        let (from, to) = (self.nodes.get(from)?, self.nodes.get(to)?);
+        let (cost, path) = self
+            .router
+            .find_shortest_path(from, to, Algorithm::Dijkstra, None)
+            .ok()?;
+        if path.is_empty() {
+            return None;
+        }
+        let positions = path.iter().map(|index| self.positions[index]).collect();
+        Some((positions, cost))
+    }
+}
+
+/// Builds a router from an array of nodes, connecting the nodes at most
+/// `max_distance_km` apart.
+///
+/// Returns null if a pointer is null or an id isn't valid UTF-8. The
+/// router must be freed with [`router_free`].
+///
+/// # Safety
+/// `nodes` must point to `count` nodes, each with a valid NUL-terminated
+/// id. The nodes can be freed once the call returns.
+#[no_mangle]
+pub unsafe extern "C" fn router_new(
+    nodes: *const RouterNode,
+    count: usize,
+    max_distance_km: f32,
+) -> *mut RouterHandle {
+    if nodes.is_null() && count > 0 {
+        error!("Null array of nodes");
+        return std::ptr::null_mut();
+    }
+    let c_nodes = if count == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(nodes, count)
+    };
+    let mut owned_nodes = Vec::with_capacity(count);
+    for node in c_nodes {
+        if node.uid.is_null() {
+            error!("Null node id");
+            return std::ptr::null_mut();
+        }
+        let Ok(uid) = CStr::from_ptr(node.uid).to_str() else {
+            error!("Node id isn't valid UTF-8");
+            return std::ptr::null_mut();
+        };
+        owned_nodes.push(
+            Node::new(uid, node.latitude, node.longitude)
+                .with_altitude_meters(node.altitude_meters),
+        );
+    }
+
+    let boxed_nodes = Box::into_raw(owned_nodes.into_boxed_slice());
+    let nodes: &'static [Node] = &*boxed_nodes;
+    let router = Router::new(
+        nodes,
+        max_distance_km,
+        |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+        |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+    );
+    let positions = nodes
+        .iter()
+        .enumerate()
+        .filter_map(|(position, node)| Some((router.get_node_index(node)?, position)))
+        .collect();
+    debug!("Router built from {} nodes", nodes.len());
+    Box::into_raw(Box::new(RouterHandle {
+        router: ManuallyDrop::new(router),
+        positions,
+        nodes,
+        boxed_nodes,
+    }))
+}
+
+/// Frees a router built by [`router_new`]; null is ignored.
+///
+/// # Safety
+/// `router` must be null or returned by [`router_new`] and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn router_free(router: *mut RouterHandle) {
+    if !router.is_null() {
+        drop(Box::from_raw(router));
+    }
+}
+
+/// Finds the shortest route between the nodes at positions `from` and `to`
+/// of the array the router was built from.
+///
+/// On success, writes the positions of the nodes of the route to `path`,
+/// its number of nodes to `path_length` and its length in kilometers to
+/// `distance_km`, and returns [`ROUTER_OK`]. If `path_capacity` is too
+/// small, only writes the number of nodes and returns
+/// [`ROUTER_BUFFER_TOO_SMALL`]. Returns [`ROUTER_NO_ROUTE`] if the nodes
+/// aren't connected and [`ROUTER_INVALID_ARGUMENT`] for a null pointer or
+/// a position out of the array.
+///
+/// # Safety
+/// `router` must be returned by [`router_new`] and not freed yet, `path`
+/// must point to `path_capacity` writable positions, and `path_length` and
+/// `distance_km` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn router_find_route(
+    router: *const RouterHandle,
+    from: usize,
+    to: usize,
+    path: *mut usize,
+    path_capacity: usize,
+    path_length: *mut usize,
+    distance_km: *mut f32,
+) -> i32 {
+    let Some(router) = router.as_ref() else {
+        return ROUTER_INVALID_ARGUMENT;
+    };
+    if path_length.is_null()
+        || distance_km.is_null()
+        || (path.is_null() && path_capacity > 0)
+        || from >= router.nodes.len()
+        || to >= router.nodes.len()
+    {
+        return ROUTER_INVALID_ARGUMENT;
+    }
+    let Some((positions, cost)) = router.find_route(from, to) else {
+        return ROUTER_NO_ROUTE;
+    };
+    *path_length = positions.len();
+    if positions.len() > path_capacity {
+        return ROUTER_BUFFER_TOO_SMALL;
+    }
+    std::ptr::copy_nonoverlapping(positions.as_ptr(), path, positions.len());
+    *distance_km = cost;
+    ROUTER_OK
+}
+
+/// Gets the number of nodes of the shortest route between the nodes at
+/// positions `from` and `to`, 0 if there is no route or an argument is
+/// invalid.
+///
+/// # Safety
+/// `router` must be null or returned by [`router_new`] and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn router_path_length(
+    router: *const RouterHandle,
+    from: usize,
+    to: usize,
+) -> usize {
+    router
+        .as_ref()
+        .and_then(|router| router.find_route(from, to))
+        .map_or(0, |(positions, _)| positions.len())
+}
+
+#[cfg(test)]
+mod ffi_tests {
+    use super::*;
+    use std::ffi::CString;
+
+    #[test]
+    fn test_find_route() {
+        let uids: Vec<CString> = ["A", "B", "C", "D"]
+            .iter()
+            .map(|uid| CString::new(*uid).unwrap())
+            .collect();
+        // A, B and C are 0.1° apart along the equator, D is far away
+        let nodes: Vec<RouterNode> = [0.0, 0.1, 0.2, 10.0]
+            .iter()
+            .zip(&uids)
+            .map(|(&longitude, uid)| RouterNode {
+                uid: uid.as_ptr(),
+                latitude: 0.0,
+                longitude,
+                altitude_meters: 0.0,
+            })
+            .collect();
+
+        unsafe {
+            let router = router_new(nodes.as_ptr(), nodes.len(), 12.0);
+            assert!(!router.is_null());
+            assert_eq!(router_path_length(router, 0, 2), 3);
+            assert_eq!(router_path_length(router, 0, 3), 0);
+
+            let mut path = [0; 3];
+            let (mut path_length, mut distance_km) = (0, 0.0);
+            let status = router_find_route(
+                router,
+                0,
+                2,
+                path.as_mut_ptr(),
+                path.len(),
+                &mut path_length,
+                &mut distance_km,
+            );
+            assert_eq!(status, ROUTER_OK);
+            assert_eq!(path, [0, 1, 2]);
+            assert_eq!(path_length, 3);
+            let expected_km = haversine::distance(
+                &Node::new("A", 0.0, 0.0).location,
+                &Node::new("C", 0.0, 0.2).location,
+            );
+            assert!((distance_km - expected_km).abs() < 0.01);
+
+            let status = router_find_route(
+                router,
+                0,
+                2,
+                path.as_mut_ptr(),
+                2,
+                &mut path_length,
+                &mut distance_km,
+            );
+            assert_eq!(status, ROUTER_BUFFER_TOO_SMALL);
+            assert_eq!(path_length, 3);
+            let status = router_find_route(
+                router,
+                0,
+                3,
+                path.as_mut_ptr(),
+                path.len(),
+                &mut path_length,
+                &mut distance_km,
+            );
+            assert_eq!(status, ROUTER_NO_ROUTE);
+            let status = router_find_route(
+                router,
+                0,
+                4,
+                path.as_mut_ptr(),
+                path.len(),
+                &mut path_length,
+                &mut distance_km,
+            );
+            assert_eq!(status, ROUTER_INVALID_ARGUMENT);
+
+            router_free(router);
+            router_free(std::ptr::null_mut());
+            assert!(router_new(std::ptr::null(), 1, 12.0).is_null());
+        }
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        let uid = CString::new("A").unwrap();
+        let node = |uid: *const c_char| RouterNode {
+            uid,
+            latitude: 0.0,
+            longitude: 0.0,
+            altitude_meters: 0.0,
+        };
+        // "\xff" isn't valid UTF-8
+        let invalid_uid = [0xff_u8, 0];
+
+        unsafe {
+            assert!(router_new(&node(std::ptr::null()), 1, 12.0).is_null());
+            assert!(router_new(&node(invalid_uid.as_ptr().cast()), 1, 12.0).is_null());
+
+            // a router without nodes has no valid position
+            let empty = router_new(std::ptr::null(), 0, 12.0);
+            assert!(!empty.is_null());
+            assert_eq!(router_path_length(empty, 0, 0), 0);
+            router_free(empty);
+
+            let router = router_new(&node(uid.as_ptr()), 1, 12.0);
+            assert!(!router.is_null());
+            let mut path = [0; 1];
+            let (mut path_length, mut distance_km) = (0, 0.0);
+            let find_route = |router: *const RouterHandle,
+                              path: *mut usize,
+                              path_capacity: usize,
+                              path_length: *mut usize,
+                              distance_km: *mut f32| {
+                router_find_route(router, 0, 0, path, path_capacity, path_length, distance_km)
+            };
+            assert_eq!(
+                find_route(
+                    std::ptr::null(),
+                    path.as_mut_ptr(),
+                    1,
+                    &mut path_length,
+                    &mut distance_km
+                ),
+                ROUTER_INVALID_ARGUMENT
+            );
+            assert_eq!(
+                find_route(
+                    router,
+                    std::ptr::null_mut(),
+                    1,
+                    &mut path_length,
+                    &mut distance_km
+                ),
+                ROUTER_INVALID_ARGUMENT
+            );
+            assert_eq!(
+                find_route(
+                    router,
+                    path.as_mut_ptr(),
+                    1,
+                    std::ptr::null_mut(),
+                    &mut distance_km
+                ),
+                ROUTER_INVALID_ARGUMENT
+            );
+            assert_eq!(
+                find_route(
+                    router,
+                    path.as_mut_ptr(),
+                    1,
+                    &mut path_length,
+                    std::ptr::null_mut()
+                ),
+                ROUTER_INVALID_ARGUMENT
+            );
+            assert_eq!(router_path_length(std::ptr::null(), 0, 0), 0);
+            assert_eq!(router_path_length(router, 0, 1), 0);
+            router_free(router);
+        }
+    }
+}
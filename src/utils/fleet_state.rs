@@ -0,0 +1,313 @@
+//! Live state of the fleet from vehicle telemetry.
+//!
+//! Without telemetry the router assumes every vehicle flies exactly as
+//! scheduled. [`FleetState`] keeps the latest reported position of each
+//! vehicle, and location queries prefer a fresh report over the schedule:
+//! a vehicle reported on the ground at its destination has arrived, while a
+//! vehicle still far from it arrives later than scheduled. Reports older than
+//! the staleness threshold are ignored and the scheduled model is used.
+
+use chrono::{DateTime, Duration};
+use rrule::Tz;
+use std::collections::HashMap;
+
+use crate::amendment::PlanTimes;
+use crate::clock::{get_clock, Clock};
+use crate::context::current_context;
+use crate::haversine;
+use crate::location::Location;
+use crate::router_state::{
+    get_vehicle_scheduled_location, FlightPlan, Vehicle, AVG_SPEED_KMH,
+    LANDING_AND_UNLOADING_TIME_MIN, NODES,
+};
+
+/// Default age in minutes after which telemetry is considered stale
+pub const DEFAULT_TELEMETRY_STALENESS_MINUTES: i64 = 5;
+
+/// Max distance in kilometers from a vertiport at which a vehicle is considered at the vertiport
+pub const AT_VERTIPORT_DISTANCE_KM: f32 = 0.1;
+
+/// A position report of a vehicle
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Telemetry {
+    /// reported location
+    pub location: Location,
+    /// time of the report
+    pub timestamp: DateTime<Tz>,
+}
+
+/// Latest telemetry of the fleet
+#[derive(Debug, Clone)]
+pub struct FleetState {
+    telemetry: HashMap<String, Telemetry>,
+    staleness: Duration,
+}
+
+impl Default for FleetState {
+    fn default() -> Self {
+        Self::new(DEFAULT_TELEMETRY_STALENESS_MINUTES)
+    }
+}
+
+impl FleetState {
+    /// Creates an empty fleet state ignoring telemetry older than `staleness_minutes`
+    pub fn new(staleness_minutes: i64) -> Self {
+        Self {
+            telemetry: HashMap::new(),
+            staleness: Duration::minutes(staleness_minutes),
+        }
+    }
+
+    /// Records a position report, unless a newer one is already known
+    pub fn ingest_position(
+        &mut self,
+        vehicle_id: &str,
+        location: Location,
+        timestamp: DateTime<Tz>,
+    ) {
+        let report = Telemetry {
+            location,
+            timestamp,
+        };
+        self.telemetry
+            .entry(vehicle_id.to_string())
+            .and_modify(|latest| {
+                if latest.timestamp <= timestamp {
+                    *latest = report;
+                }
+            })
+            .or_insert(report);
+    }
+
+    /// Gets the latest telemetry of a vehicle if it isn't stale at `now`
+    pub fn get_fresh_telemetry(&self, vehicle_id: &str, now: DateTime<Tz>) -> Option<Telemetry> {
+        self.telemetry
+            .get(vehicle_id)
+            .filter(|telemetry| now - telemetry.timestamp <= self.staleness)
+            .copied()
+    }
+
+    /// Gets vehicle location (vertiport_id) at given timestamp, preferring fresh telemetry
+    ///
+    /// # Arguments
+    /// * `vehicle` - vehicle to locate
+    /// * `timestamp` - time of the query
+    /// * `now` - current time, to judge the staleness of the telemetry
+    /// * `existing_flight_plans` - scheduled flight plans
+    /// * `vertiport_location` - location of a vertiport by id
+    ///
+    /// # Returns
+    /// Tuple of (vertiport_id, minutes_to_arrival) like
+    /// [`get_vehicle_scheduled_location`], or its error
+    pub fn get_vehicle_location_with<L>(
+        &self,
+        vehicle: &Vehicle,
+        timestamp: DateTime<Tz>,
+        now: DateTime<Tz>,
+        existing_flight_plans: &[FlightPlan],
+        vertiport_location: L,
+    ) -> Result<(String, i64), String>
+    where
+        L: Fn(&str) -> Option<Location>,
+    {
+        let scheduled = get_vehicle_scheduled_location(vehicle, timestamp, existing_flight_plans)?;
+        let Some(telemetry) = self.get_fresh_telemetry(&vehicle.id, now) else {
+            return Ok(scheduled);
+        };
+        // a flight departing after the report supersedes it
+        let departs_since_report = existing_flight_plans
+            .iter()
+            .filter(|flight_plan| {
+                flight_plan
+                    .data
+                    .as_ref()
+                    .is_some_and(|data| data.vehicle_id == vehicle.id)
+            })
+            .filter_map(|flight_plan| PlanTimes::from_flight_plan(flight_plan).ok())
+            .any(|plan| {
+                plan.departure > telemetry.timestamp.timestamp()
+                    && plan.departure <= timestamp.timestamp()
+            });
+        if departs_since_report || timestamp < telemetry.timestamp {
+            return Ok(scheduled);
+        }
+        let Ok((vertiport_id, _)) =
+            get_vehicle_scheduled_location(vehicle, telemetry.timestamp, existing_flight_plans)
+        else {
+            return Ok(scheduled);
+        };
+        let Some(destination) = vertiport_location(&vertiport_id) else {
+            return Ok(scheduled);
+        };
+        let distance_km = haversine::distance(&telemetry.location, &destination);
+        if distance_km <= AT_VERTIPORT_DISTANCE_KM {
+            return Ok((vertiport_id, 0));
+        }
+        let minutes_from_report =
+            distance_km / AVG_SPEED_KMH * 60.0 + LANDING_AND_UNLOADING_TIME_MIN;
+        let elapsed_minutes = (timestamp - telemetry.timestamp).num_minutes();
+        let minutes_to_arrival = (minutes_from_report.ceil() as i64 - elapsed_minutes).max(0);
+        debug!(
+            "Vehicle {} reported {:.1} km from vertiport {}, arriving in {} minutes",
+            vehicle.id, distance_km, vertiport_id, minutes_to_arrival
+        );
+        Ok((vertiport_id, minutes_to_arrival))
+    }
+}
+
+/// Records a position report of a vehicle in the live fleet state
+pub fn ingest_position(vehicle_id: &str, location: Location, timestamp: DateTime<Tz>) {
+    match current_context().fleet_state.write() {
+        Ok(mut fleet_state) => fleet_state.ingest_position(vehicle_id, location, timestamp),
+        Err(_) => error!("Fleet state unavailable"),
+    }
+}
+
+/// Sets the age in minutes after which telemetry is considered stale
+pub fn set_telemetry_staleness(staleness_minutes: i64) {
+    match current_context().fleet_state.write() {
+        Ok(mut fleet_state) => fleet_state.staleness = Duration::minutes(staleness_minutes),
+        Err(_) => error!("Fleet state unavailable"),
+    }
+}
+
+/// Gets vehicle location (vertiport_id) at given timestamp, preferring
+/// telemetry of the live fleet state that is fresh at the time of the clock
+/// Returns tuple of (vertiport_id, minutes_to_arrival)
+pub fn get_vehicle_location_with(
+    clock: &dyn Clock,
+    vehicle: &Vehicle,
+    timestamp: DateTime<Tz>,
+    existing_flight_plans: &[FlightPlan],
+) -> Result<(String, i64), String> {
+    let vertiport_location = |vertiport_id: &str| {
+        NODES
+            .get()?
+            .iter()
+            .find(|node| node.uid == vertiport_id)
+            .map(|node| node.location)
+    };
+    match current_context().fleet_state.read() {
+        Ok(fleet_state) => fleet_state.get_vehicle_location_with(
+            vehicle,
+            timestamp,
+            clock.now(),
+            existing_flight_plans,
+            vertiport_location,
+        ),
+        Err(_) => {
+            error!("Fleet state unavailable");
+            get_vehicle_scheduled_location(vehicle, timestamp, existing_flight_plans)
+        }
+    }
+}
+
+/// Gets vehicle location (vertiport_id) at given timestamp, preferring fresh
+/// telemetry of the live fleet state over the schedule
+/// Returns tuple of (vertiport_id, minutes_to_arrival)
+pub fn get_vehicle_location(
+    vehicle: &Vehicle,
+    timestamp: DateTime<Tz>,
+    existing_flight_plans: &[FlightPlan],
+) -> Result<(String, i64), String> {
+    get_vehicle_location_with(
+        get_clock().as_ref(),
+        vehicle,
+        timestamp,
+        existing_flight_plans,
+    )
+}
+
+#[cfg(test)]
+mod fleet_state_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use crate::test_support::mock_location;
+    use chrono::TimeZone;
+
+    fn location(longitude: f32) -> Location {
+        mock_location(0.0, longitude)
+    }
+
+    fn vertiport_location(vertiport_id: &str) -> Option<Location> {
+        match vertiport_id {
+            "A" => Some(location(0.0)),
+            // about 111 km east of A
+            "B" => Some(location(1.0)),
+            _ => None,
+        }
+    }
+
+    fn start() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap()
+    }
+
+    fn vehicle() -> Vehicle {
+        Vehicle {
+            id: "v1".to_string(),
+            data: Some(crate::resources::vehicle::Data {
+                last_vertiport_id: Some("A".to_string()),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn flight_plans() -> Vec<FlightPlan> {
+        vec![FlightPlan {
+            id: "fp1".to_string(),
+            data: Some(create_flight_plan_data(
+                "v1".to_string(),
+                "A".to_string(),
+                "B".to_string(),
+                start(),
+                start() + Duration::minutes(60),
+            )),
+        }]
+    }
+
+    #[test]
+    fn test_ingest_position() {
+        let mut fleet_state = FleetState::new(5);
+        fleet_state.ingest_position("v1", location(0.5), start() + Duration::minutes(10));
+        // older reports don't replace newer ones
+        fleet_state.ingest_position("v1", location(0.2), start());
+        let telemetry = fleet_state.get_fresh_telemetry("v1", start() + Duration::minutes(12));
+        assert_eq!(telemetry.map(|t| t.location), Some(location(0.5)));
+        assert_eq!(
+            fleet_state.get_fresh_telemetry("v1", start() + Duration::minutes(20)),
+            None
+        );
+        assert_eq!(fleet_state.get_fresh_telemetry("v2", start()), None);
+    }
+
+    #[test]
+    fn test_location_prefers_fresh_telemetry() {
+        let mut fleet_state = FleetState::new(5);
+        let vehicle = vehicle();
+        let flight_plans = flight_plans();
+        let query = start() + Duration::minutes(60);
+        let location_at = |fleet_state: &FleetState, now: DateTime<Tz>| {
+            fleet_state
+                .get_vehicle_location_with(&vehicle, query, now, &flight_plans, vertiport_location)
+                .unwrap()
+        };
+        // without telemetry the vehicle arrives as scheduled
+        assert_eq!(location_at(&fleet_state, start()), ("B".to_string(), 0));
+
+        // still half way at the scheduled arrival
+        let report_time = start() + Duration::minutes(58);
+        fleet_state.ingest_position("v1", location(0.5), report_time);
+        let (vertiport_id, minutes_to_arrival) = location_at(&fleet_state, report_time);
+        assert_eq!(vertiport_id, "B");
+        // 55.6 km at 60 km/h plus landing, 2 minutes after the report
+        assert_eq!(minutes_to_arrival, 64);
+
+        // stale telemetry falls back to the schedule
+        let later = report_time + Duration::minutes(10);
+        assert_eq!(location_at(&fleet_state, later), ("B".to_string(), 0));
+
+        // landed early
+        fleet_state.ingest_position("v1", location(1.0), start() + Duration::minutes(59));
+        assert_eq!(location_at(&fleet_state, report_time), ("B".to_string(), 0));
+    }
+}
@@ -0,0 +1,280 @@
+//! Streamed input of existing flight plans.
+//!
+//! Availability checks only need the flight plans around the requested time
+//! window, and the last flight plan of each vehicle before it, which tells
+//! where the vehicle is parked. [`collect_flight_plans_for_window`] keeps
+//! just those from an iterator of flight plans, so callers can stream months
+//! of flight plans from storage, e.g. page by page with
+//! `pages.into_iter().flatten()`, without loading all of them in memory.
+//! The maintenance usage and the projected state of charge of a vehicle also
+//! count its flights since its last maintenance or its last known charge,
+//! which [`collect_flight_plans_for_vehicles`] keeps as well.
+//! [`get_possible_flights_streamed`] does so for the possible flight search.
+
+use chrono::{DateTime, Duration};
+use prost_types::Timestamp;
+use rrule::Tz;
+use std::collections::{HashMap, HashSet};
+
+use crate::amendment::PlanTimes;
+use crate::energy::charge_projected_since;
+use crate::maintenance::usage_counted_since;
+use crate::providers::FLIGHT_PLAN_WINDOW_MARGIN_HOURS;
+use crate::router_state::{
+    get_possible_flights, FlightPlan, PossibleFlight, Vehicle, Vertipad, Vertiport,
+};
+use crate::time::validate_flight_window;
+
+/// Gets the earliest departure of the flight plans of a vehicle counting
+/// towards its maintenance usage or its projected state of charge
+///
+/// # Returns
+/// None if the vehicle has neither a maintenance interval nor a battery
+pub fn vehicle_history_since(vehicle: &Vehicle) -> Option<DateTime<Tz>> {
+    [
+        usage_counted_since(vehicle),
+        charge_projected_since(&vehicle.id),
+    ]
+    .into_iter()
+    .flatten()
+    .min()
+}
+
+/// Collects the flight plans relevant to the time window from `from` to `to`
+///
+/// # Returns
+/// The flight plans in flight during the window and the last flight plan of
+/// each vehicle landing before it, sorted by departure. Flight plans without
+/// scheduled times are left out.
+pub fn collect_flight_plans_for_window(
+    flight_plans: impl IntoIterator<Item = FlightPlan>,
+    from: DateTime<Tz>,
+    to: DateTime<Tz>,
+) -> Vec<FlightPlan> {
+    collect_flight_plans_for_vehicles(flight_plans, &[], from, to)
+}
+
+/// Collects the flight plans relevant to the time window from `from` to
+/// `to` like [`collect_flight_plans_for_window`], and the flight plans of
+/// each of `vehicles` departing since its [`vehicle_history_since`]
+pub fn collect_flight_plans_for_vehicles(
+    flight_plans: impl IntoIterator<Item = FlightPlan>,
+    vehicles: &[Vehicle],
+    from: DateTime<Tz>,
+    to: DateTime<Tz>,
+) -> Vec<FlightPlan> {
+    let (from, to) = (from.timestamp(), to.timestamp());
+    let history_since: HashMap<&str, i64> = vehicles
+        .iter()
+        .filter_map(|vehicle| {
+            Some((
+                vehicle.id.as_str(),
+                vehicle_history_since(vehicle)?.timestamp(),
+            ))
+        })
+        .collect();
+    let mut within: Vec<(i64, FlightPlan)> = vec![];
+    // vehicles with flight plans kept before the window, which locate them
+    let mut with_history: HashSet<String> = HashSet::new();
+    // last flight plan of each vehicle before the window, with its arrival and departure
+    let mut last_before: HashMap<String, (i64, i64, FlightPlan)> = HashMap::new();
+    for flight_plan in flight_plans {
+        let plan = match PlanTimes::from_flight_plan(&flight_plan) {
+            Ok(plan) => plan,
+            Err(e) => {
+                debug!("Skipping flight plan: {}", e);
+                continue;
+            }
+        };
+        if plan.departure > to {
+            continue;
+        }
+        if plan.arrival >= from {
+            within.push((plan.departure, flight_plan));
+            continue;
+        }
+        if history_since
+            .get(plan.vehicle_id.as_str())
+            .is_some_and(|since| plan.departure >= *since)
+        {
+            within.push((plan.departure, flight_plan));
+            with_history.insert(plan.vehicle_id);
+            continue;
+        }
+        match last_before.get(&plan.vehicle_id) {
+            Some((arrival, _, _)) if *arrival >= plan.arrival => {}
+            _ => {
+                last_before.insert(plan.vehicle_id, (plan.arrival, plan.departure, flight_plan));
+            }
+        }
+    }
+    // the flight plans kept for the history depart after the last one before it
+    within.extend(
+        last_before
+            .into_iter()
+            .filter(|(vehicle_id, _)| !with_history.contains(vehicle_id))
+            .map(|(_, (_, departure, flight_plan))| (departure, flight_plan)),
+    );
+    within.sort_by(|(a, a_plan), (b, b_plan)| a.cmp(b).then_with(|| a_plan.id.cmp(&b_plan.id)));
+    within
+        .into_iter()
+        .map(|(_, flight_plan)| flight_plan)
+        .collect()
+}
+
+/// Creates all possible flight plans based on the given request, with
+/// existing flight plans streamed from an iterator
+///
+/// Only the flight plans within [`FLIGHT_PLAN_WINDOW_MARGIN_HOURS`] of the
+/// requested window, the last flight plan of each vehicle before it and the
+/// flight plans of each vehicle since its [`vehicle_history_since`] are kept
+/// in memory.
+/// See [`get_possible_flights`] for the arguments and result
+#[allow(clippy::too_many_arguments)]
+pub fn get_possible_flights_streamed(
+    vertiport_depart: Vertiport,
+    vertiport_arrive: Vertiport,
+    vertipads_depart: Vec<Vertipad>,
+    vertipads_arrive: Vec<Vertipad>,
+    earliest_departure_time: Option<Timestamp>,
+    latest_arrival_time: Option<Timestamp>,
+    vehicles: Vec<Vehicle>,
+    existing_flight_plans: impl IntoIterator<Item = FlightPlan>,
+) -> Result<Vec<PossibleFlight>, String> {
+    let (earliest, latest) = validate_flight_window(
+        earliest_departure_time.as_ref(),
+        latest_arrival_time.as_ref(),
+    )?;
+    let margin = Duration::hours(FLIGHT_PLAN_WINDOW_MARGIN_HOURS);
+    let existing_flight_plans = collect_flight_plans_for_vehicles(
+        existing_flight_plans,
+        &vehicles,
+        earliest - margin,
+        latest + margin,
+    );
+    get_possible_flights(
+        vertiport_depart,
+        vertiport_arrive,
+        vertipads_depart,
+        vertipads_arrive,
+        earliest_departure_time,
+        latest_arrival_time,
+        vehicles,
+        existing_flight_plans,
+    )
+}
+
+#[cfg(test)]
+mod flight_plan_stream_tests {
+    use super::*;
+    use crate::maintenance::{
+        clear_maintenance_interval, set_maintenance_interval, MaintenanceInterval,
+    };
+    use crate::resources::vehicle;
+    use crate::router_state::create_flight_plan_data;
+    use crate::time::datetime_to_timestamp;
+    use chrono::TimeZone;
+
+    fn flight_plan(id: &str, vehicle_id: &str, departure: DateTime<Tz>) -> FlightPlan {
+        FlightPlan {
+            id: id.to_string(),
+            data: Some(create_flight_plan_data(
+                vehicle_id.to_string(),
+                "SFO".to_string(),
+                "OAK".to_string(),
+                departure,
+                departure + Duration::minutes(30),
+            )),
+        }
+    }
+
+    fn ids(flight_plans: &[FlightPlan]) -> Vec<&str> {
+        flight_plans
+            .iter()
+            .map(|flight_plan| flight_plan.id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_collect_flight_plans_for_window() {
+        let start = Tz::UTC.with_ymd_and_hms(2030, 1, 10, 8, 0, 0).unwrap();
+        // pages of flight plans streamed from storage
+        let pages = vec![
+            vec![
+                flight_plan("a-old", "a", start - Duration::days(30)),
+                flight_plan("a-last", "a", start - Duration::days(2)),
+                flight_plan("b-last", "b", start - Duration::days(9)),
+            ],
+            vec![
+                flight_plan("a-landing", "a", start - Duration::minutes(10)),
+                flight_plan("b-within", "b", start + Duration::hours(1)),
+                flight_plan("b-later", "b", start + Duration::days(3)),
+                FlightPlan {
+                    id: "no-data".to_string(),
+                    data: None,
+                },
+            ],
+        ];
+        let flight_plans = collect_flight_plans_for_window(
+            pages.into_iter().flatten(),
+            start,
+            start + Duration::hours(2),
+        );
+        assert_eq!(
+            ids(&flight_plans),
+            vec!["b-last", "a-last", "a-landing", "b-within"]
+        );
+    }
+
+    #[test]
+    fn test_last_flight_plan_before_window() {
+        let start = Tz::UTC.with_ymd_and_hms(2030, 1, 10, 8, 0, 0).unwrap();
+        // streamed newest first
+        let flight_plans = collect_flight_plans_for_window(
+            vec![
+                flight_plan("a-2", "a", start - Duration::days(1)),
+                flight_plan("a-1", "a", start - Duration::days(5)),
+                flight_plan("c-1", "c", start - Duration::days(60)),
+            ],
+            start,
+            start + Duration::hours(2),
+        );
+        assert_eq!(ids(&flight_plans), vec!["c-1", "a-2"]);
+    }
+
+    #[test]
+    fn test_vehicle_history_kept() {
+        let start = Tz::UTC.with_ymd_and_hms(2030, 1, 10, 8, 0, 0).unwrap();
+        let vehicle = Vehicle {
+            id: "stream-history".to_string(),
+            data: Some(vehicle::Data {
+                last_maintenance: Some(datetime_to_timestamp(&(start - Duration::days(10)))),
+                ..Default::default()
+            }),
+        };
+        let flight_plans = || {
+            vec![
+                flight_plan("h-3", "stream-history", start - Duration::days(2)),
+                flight_plan("h-2", "stream-history", start - Duration::days(8)),
+                flight_plan("h-1", "stream-history", start - Duration::days(20)),
+                flight_plan("a-2", "a", start - Duration::days(1)),
+                flight_plan("a-1", "a", start - Duration::days(5)),
+            ]
+        };
+        let collect = || {
+            collect_flight_plans_for_vehicles(
+                flight_plans(),
+                std::slice::from_ref(&vehicle),
+                start,
+                start + Duration::hours(2),
+            )
+        };
+        // no maintenance interval, only the last flight plan locates the vehicle
+        assert!(vehicle_history_since(&vehicle).is_none());
+        assert_eq!(ids(&collect()), vec!["h-3", "a-2"]);
+
+        set_maintenance_interval("stream-history", MaintenanceInterval::default());
+        assert_eq!(ids(&collect()), vec!["h-2", "h-3", "a-2"]);
+        clear_maintenance_interval("stream-history");
+    }
+}
@@ -0,0 +1,356 @@
+//! Request and response of a flight query, shared by svc-scheduler and the
+//! REST gateways.
+//!
+//! [`FlightQueryRequest`] carries the inputs of
+//! [`get_possible_flights`](crate::router_state::get_possible_flights) and
+//! [`FlightQueryResponse`] the flights it returns, as plain JSON with times
+//! in seconds since the epoch. [`FlightQueryRequest::schema`] and
+//! [`FlightQueryResponse::schema`] generate their JSON schemas, so clients
+//! are checked against the contract defined here instead of a copy of it.
+
+use prost_types::Timestamp;
+use schemars::schema::RootSchema;
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+use crate::router_state::{
+    get_possible_flights, FlightPlan, FlightPlanData, PossibleFlight, Vehicle, Vertipad, Vertiport,
+};
+
+/// Vertiport of a flight query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct QueryVertiport {
+    /// id of the vertiport
+    pub id: String,
+    /// latitude in degrees
+    pub latitude: f64,
+    /// longitude in degrees
+    pub longitude: f64,
+    /// RRULE schedule of the vertiport
+    pub schedule: Option<String>,
+}
+
+/// Vertipad of a flight query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct QueryVertipad {
+    /// id of the vertipad
+    pub id: String,
+    /// id of the vertiport of the vertipad
+    pub vertiport_id: String,
+    /// whether the vertipad is in service
+    pub enabled: bool,
+    /// whether the vertipad is occupied
+    #[serde(default)]
+    pub occupied: bool,
+    /// RRULE schedule of the vertipad
+    pub schedule: Option<String>,
+}
+
+/// Vehicle of a flight query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct QueryVehicle {
+    /// id of the vehicle
+    pub id: String,
+    /// id of the vehicle model
+    #[serde(default)]
+    pub vehicle_model_id: String,
+    /// id of the vertiport the vehicle was last parked at
+    pub last_vertiport_id: Option<String>,
+    /// RRULE schedule of the vehicle
+    pub schedule: Option<String>,
+}
+
+/// Flight plan of a flight query, times in seconds since the epoch
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+pub struct QueryFlightPlan {
+    /// id of the flight plan, empty for the flight plans found
+    #[serde(default)]
+    pub id: String,
+    /// id of the vehicle
+    pub vehicle_id: String,
+    /// weights of the cargo items
+    #[serde(default)]
+    pub cargo_weight_grams: Vec<i64>,
+    /// id of the departure vertiport
+    pub departure_vertiport_id: Option<String>,
+    /// id of the destination vertiport
+    pub destination_vertiport_id: Option<String>,
+    /// id of the departure vertipad
+    #[serde(default)]
+    pub departure_vertipad_id: String,
+    /// id of the destination vertipad
+    #[serde(default)]
+    pub destination_vertipad_id: String,
+    /// scheduled departure time
+    pub scheduled_departure: Option<i64>,
+    /// scheduled arrival time
+    pub scheduled_arrival: Option<i64>,
+    /// distance of the flight
+    #[serde(default)]
+    pub flight_distance_meters: i64,
+}
+
+/// Inputs of [`get_possible_flights`], times in seconds since the epoch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct FlightQueryRequest {
+    /// departure vertiport
+    pub departure_vertiport: QueryVertiport,
+    /// arrival vertiport
+    pub arrival_vertiport: QueryVertiport,
+    /// vertipads of the departure vertiport
+    pub departure_vertipads: Vec<QueryVertipad>,
+    /// vertipads of the arrival vertiport
+    pub arrival_vertipads: Vec<QueryVertipad>,
+    /// earliest departure time of the time window
+    pub earliest_departure_time: Option<i64>,
+    /// latest arrival time of the time window
+    pub latest_arrival_time: Option<i64>,
+    /// vehicles serving the route and vertiports
+    pub vehicles: Vec<QueryVehicle>,
+    /// flight plans already scheduled
+    #[serde(default)]
+    pub existing_flight_plans: Vec<QueryFlightPlan>,
+}
+
+/// A flight found by [`get_possible_flights`], times in seconds since the
+/// epoch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct QueryFlight {
+    /// flight plan of the requested flight
+    pub flight_plan: QueryFlightPlan,
+    /// deadhead flight plans bringing the vehicle to the departure vertiport
+    pub deadhead_flight_plans: Vec<QueryFlightPlan>,
+    /// vertiport to divert to if the destination becomes unavailable
+    pub alternate_vertiport_id: String,
+    /// median predicted arrival time
+    pub eta_p50: i64,
+    /// predicted arrival time not exceeded in 90% of the flights
+    pub eta_p90: i64,
+    /// estimated operating cost of the flight and its deadhead flights
+    pub estimated_cost: f32,
+    /// ids of the operation intents accepted by the UTM service
+    pub utm_intent_ids: Vec<String>,
+    /// minutes the flight was delayed to queue for a pad
+    pub queue_minutes: i64,
+}
+
+/// Flights returned by [`get_possible_flights`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct FlightQueryResponse {
+    /// the flights found
+    pub flights: Vec<QueryFlight>,
+}
+
+fn to_seconds(timestamp: &Option<Timestamp>) -> Option<i64> {
+    timestamp.as_ref().map(|timestamp| timestamp.seconds)
+}
+
+fn to_timestamp(seconds: Option<i64>) -> Option<Timestamp> {
+    seconds.map(|seconds| Timestamp { seconds, nanos: 0 })
+}
+
+impl From<&QueryVertiport> for Vertiport {
+    fn from(vertiport: &QueryVertiport) -> Self {
+        Vertiport {
+            id: vertiport.id.clone(),
+            data: Some(crate::resources::vertiport::Data {
+                latitude: vertiport.latitude,
+                longitude: vertiport.longitude,
+                schedule: vertiport.schedule.clone(),
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+impl From<&QueryVertipad> for Vertipad {
+    fn from(vertipad: &QueryVertipad) -> Self {
+        Vertipad {
+            id: vertipad.id.clone(),
+            data: Some(crate::resources::vertipad::Data {
+                vertiport_id: vertipad.vertiport_id.clone(),
+                enabled: vertipad.enabled,
+                occupied: vertipad.occupied,
+                schedule: vertipad.schedule.clone(),
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+impl From<&QueryVehicle> for Vehicle {
+    fn from(vehicle: &QueryVehicle) -> Self {
+        Vehicle {
+            id: vehicle.id.clone(),
+            data: Some(crate::resources::vehicle::Data {
+                vehicle_model_id: vehicle.vehicle_model_id.clone(),
+                last_vertiport_id: vehicle.last_vertiport_id.clone(),
+                schedule: vehicle.schedule.clone(),
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+impl QueryFlightPlan {
+    /// Flight plan data under the given id
+    pub fn new(id: &str, data: &FlightPlanData) -> Self {
+        QueryFlightPlan {
+            id: id.to_string(),
+            vehicle_id: data.vehicle_id.clone(),
+            cargo_weight_grams: data.cargo_weight_grams.clone(),
+            departure_vertiport_id: data.departure_vertiport_id.clone(),
+            destination_vertiport_id: data.destination_vertiport_id.clone(),
+            departure_vertipad_id: data.departure_vertipad_id.clone(),
+            destination_vertipad_id: data.destination_vertipad_id.clone(),
+            scheduled_departure: to_seconds(&data.scheduled_departure),
+            scheduled_arrival: to_seconds(&data.scheduled_arrival),
+            flight_distance_meters: data.flight_distance_meters,
+        }
+    }
+}
+
+impl From<&QueryFlightPlan> for FlightPlan {
+    fn from(flight_plan: &QueryFlightPlan) -> Self {
+        FlightPlan {
+            id: flight_plan.id.clone(),
+            data: Some(FlightPlanData {
+                vehicle_id: flight_plan.vehicle_id.clone(),
+                cargo_weight_grams: flight_plan.cargo_weight_grams.clone(),
+                departure_vertiport_id: flight_plan.departure_vertiport_id.clone(),
+                destination_vertiport_id: flight_plan.destination_vertiport_id.clone(),
+                departure_vertipad_id: flight_plan.departure_vertipad_id.clone(),
+                destination_vertipad_id: flight_plan.destination_vertipad_id.clone(),
+                scheduled_departure: to_timestamp(flight_plan.scheduled_departure),
+                scheduled_arrival: to_timestamp(flight_plan.scheduled_arrival),
+                flight_distance_meters: flight_plan.flight_distance_meters,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+impl From<&PossibleFlight> for QueryFlight {
+    fn from(flight: &PossibleFlight) -> Self {
+        QueryFlight {
+            flight_plan: QueryFlightPlan::new("", &flight.flight_plan),
+            deadhead_flight_plans: flight
+                .deadhead_flight_plans
+                .iter()
+                .map(|data| QueryFlightPlan::new("", data))
+                .collect(),
+            alternate_vertiport_id: flight.alternate_vertiport_id.clone(),
+            eta_p50: flight.eta.p50().timestamp(),
+            eta_p90: flight.eta.p90().timestamp(),
+            estimated_cost: flight.cost.total(),
+            utm_intent_ids: flight.utm_intent_ids.clone(),
+            queue_minutes: flight.queue_minutes,
+        }
+    }
+}
+
+impl FlightQueryRequest {
+    /// JSON schema of the request
+    pub fn schema() -> RootSchema {
+        schema_for!(FlightQueryRequest)
+    }
+
+    /// Finds the possible flights of the request with [`get_possible_flights`]
+    pub fn query(&self) -> Result<FlightQueryResponse, String> {
+        let flights = get_possible_flights(
+            (&self.departure_vertiport).into(),
+            (&self.arrival_vertiport).into(),
+            self.departure_vertipads.iter().map(Into::into).collect(),
+            self.arrival_vertipads.iter().map(Into::into).collect(),
+            to_timestamp(self.earliest_departure_time),
+            to_timestamp(self.latest_arrival_time),
+            self.vehicles.iter().map(Into::into).collect(),
+            self.existing_flight_plans.iter().map(Into::into).collect(),
+        )?;
+        Ok(FlightQueryResponse::from(flights.as_slice()))
+    }
+}
+
+impl From<&[PossibleFlight]> for FlightQueryResponse {
+    fn from(flights: &[PossibleFlight]) -> Self {
+        FlightQueryResponse {
+            flights: flights.iter().map(QueryFlight::from).collect(),
+        }
+    }
+}
+
+impl FlightQueryResponse {
+    /// JSON schema of the response
+    pub fn schema() -> RootSchema {
+        schema_for!(FlightQueryResponse)
+    }
+}
+
+#[cfg(test)]
+mod flight_query_tests {
+    use super::*;
+
+    const REQUEST: &str = r#"{
+        "departure_vertiport": {"id": "A", "latitude": 37.7, "longitude": -122.4},
+        "arrival_vertiport": {"id": "B", "latitude": 37.8, "longitude": -122.3},
+        "departure_vertipads": [{"id": "pad-a", "vertiport_id": "A", "enabled": true}],
+        "arrival_vertipads": [],
+        "earliest_departure_time": 1666684800,
+        "latest_arrival_time": null,
+        "vehicles": [{"id": "v1", "last_vertiport_id": "A"}]
+    }"#;
+
+    #[test]
+    fn test_request_from_json() {
+        let request: FlightQueryRequest = serde_json::from_str(REQUEST).unwrap();
+        assert_eq!(request.departure_vertiport.schedule, None);
+        assert!(request.existing_flight_plans.is_empty());
+        assert!(!request.departure_vertipads[0].occupied);
+
+        let vehicle = Vehicle::from(&request.vehicles[0]);
+        assert_eq!(
+            vehicle.data.unwrap().last_vertiport_id.as_deref(),
+            Some("A")
+        );
+        let vertiport = Vertiport::from(&request.arrival_vertiport);
+        assert_eq!(vertiport.data.unwrap().latitude, 37.8);
+
+        let json = serde_json::to_string(&request).unwrap();
+        assert_eq!(
+            serde_json::from_str::<FlightQueryRequest>(&json).unwrap(),
+            request
+        );
+        assert!(serde_json::from_str::<FlightQueryRequest>("{}").is_err());
+    }
+
+    #[test]
+    fn test_flight_plan_round_trip() {
+        let flight_plan = QueryFlightPlan {
+            id: "fp1".to_string(),
+            vehicle_id: "v1".to_string(),
+            departure_vertiport_id: Some("A".to_string()),
+            destination_vertiport_id: Some("B".to_string()),
+            scheduled_departure: Some(1666684800),
+            scheduled_arrival: Some(1666686600),
+            flight_distance_meters: 12000,
+            ..Default::default()
+        };
+        let converted = FlightPlan::from(&flight_plan);
+        assert_eq!(
+            QueryFlightPlan::new(&converted.id, &converted.data.unwrap()),
+            flight_plan
+        );
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema = serde_json::to_value(FlightQueryRequest::schema()).unwrap();
+        let required = schema["required"].as_array().unwrap();
+        assert!(required.contains(&"departure_vertiport".into()));
+        assert!(!required.contains(&"existing_flight_plans".into()));
+        assert!(schema["definitions"]["QueryVertipad"].is_object());
+
+        let schema = serde_json::to_value(FlightQueryResponse::schema()).unwrap();
+        assert!(schema["definitions"]["QueryFlight"]["properties"]["eta_p50"].is_object());
+    }
+}
@@ -0,0 +1,470 @@
+//! Conversion between flight plans and ICAO FPL 2012 messages.
+//!
+//! [`IcaoFlightPlan::to_fpl`] renders a flight plan and its route as an FPL
+//! message which can be filed with air navigation service providers.
+//! Vertiports have no ICAO location indicator, so the aerodromes are filed
+//! as `ZZZZ` with their coordinates in the `DEP/`, `DEST/` and `ALTN/` items
+//! of field 18. Times are block times: the estimated off-block time is the
+//! scheduled departure and the total estimated elapsed time lasts until the
+//! scheduled arrival.
+//!
+//! [`parse_fpl_amendment`] reads the delay (`DLA`) and change (`CHG`)
+//! messages sent back for a filed flight plan.
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Timelike};
+use ordered_float::OrderedFloat;
+use prost_types::Timestamp;
+use rrule::Tz;
+
+use crate::amendment::{timestamp_to_datetime, PlanTimes};
+use crate::location::Location;
+use crate::router_state::{FlightPlanData, AVG_SPEED_KMH};
+
+/// Location indicator of an aerodrome without ICAO code
+const NO_LOCATION_INDICATOR: &str = "ZZZZ";
+/// Kilometers in a nautical mile
+const KM_PER_NAUTICAL_MILE: f32 = 1.852;
+
+/// Flight plan in the fields of an ICAO FPL message
+#[derive(Debug, Clone, PartialEq)]
+pub struct IcaoFlightPlan {
+    /// field 7, aircraft identification of up to 7 characters
+    pub aircraft_id: String,
+    /// field 8, `V` for visual flight rules
+    pub flight_rules: char,
+    /// field 8, `N` for non-scheduled air transport
+    pub flight_type: char,
+    /// field 18 `TYP/`, type of the aircraft, filed as `ZZZZ` in field 9
+    pub aircraft_type: String,
+    /// field 9, wake turbulence category
+    pub wake_turbulence: char,
+    /// field 10, equipment and capabilities
+    pub equipment: String,
+    /// field 13, estimated off-block time
+    pub departure_time: DateTime<Tz>,
+    /// field 15, cruising speed
+    pub cruise_speed_knots: u32,
+    /// field 15, points of the route between departure and destination
+    pub waypoints: Vec<Location>,
+    /// field 16, total estimated elapsed time
+    pub total_eet_minutes: i64,
+    /// field 18 `DEP/`, departure vertiport
+    pub departure: Location,
+    /// field 18 `DEST/`, destination vertiport
+    pub destination: Location,
+    /// field 18 `ALTN/`, alternate vertiport
+    pub alternate: Option<Location>,
+}
+
+impl IcaoFlightPlan {
+    /// Creates the ICAO flight plan of a flight plan flown along `route`
+    ///
+    /// # Arguments
+    /// * `aircraft_id` - Identification filed for the aircraft, e.g. its registration
+    /// * `data` - Flight plan to file
+    /// * `route` - Locations along the route, from the departure to the destination vertiport
+    /// * `alternate` - Location of the alternate vertiport
+    pub fn from_flight_plan(
+        aircraft_id: &str,
+        data: &FlightPlanData,
+        route: &[Location],
+        alternate: Option<Location>,
+    ) -> Result<Self, String> {
+        let plan = PlanTimes::from_data(aircraft_id, data)?;
+        let (Some(departure), Some(destination)) = (route.first(), route.last()) else {
+            return Err("Flight plan has no route".to_string());
+        };
+        let aircraft_id: String = aircraft_id
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        if aircraft_id.is_empty() || aircraft_id.len() > 7 {
+            return Err(format!("Invalid aircraft identification: {}", aircraft_id));
+        }
+        Ok(IcaoFlightPlan {
+            aircraft_id,
+            flight_rules: 'V',
+            flight_type: 'N',
+            aircraft_type: "UAS".to_string(),
+            wake_turbulence: 'L',
+            equipment: "S/N".to_string(),
+            departure_time: timestamp_to_datetime(plan.departure),
+            cruise_speed_knots: (AVG_SPEED_KMH / KM_PER_NAUTICAL_MILE).round() as u32,
+            waypoints: route[1..route.len() - 1].to_vec(),
+            total_eet_minutes: (plan.arrival - plan.departure) / 60,
+            departure: *departure,
+            destination: *destination,
+            alternate,
+        })
+    }
+
+    /// Formats the flight plan as an ICAO FPL message
+    pub fn to_fpl(&self) -> String {
+        let route = if self.waypoints.is_empty() {
+            "DCT".to_string()
+        } else {
+            self.waypoints
+                .iter()
+                .map(format_coordinates)
+                .collect::<Vec<_>>()
+                .join(" DCT ")
+        };
+        let mut other_information = vec![
+            format!("DOF/{}", self.departure_time.format("%y%m%d")),
+            format!("DEP/{}", format_coordinates(&self.departure)),
+            format!("DEST/{}", format_coordinates(&self.destination)),
+        ];
+        let mut destination = format!(
+            "{}{}",
+            NO_LOCATION_INDICATOR,
+            format_hhmm(self.total_eet_minutes)
+        );
+        if let Some(alternate) = &self.alternate {
+            destination += &format!(" {}", NO_LOCATION_INDICATOR);
+            other_information.push(format!("ALTN/{}", format_coordinates(alternate)));
+        }
+        other_information.push(format!("TYP/{}", self.aircraft_type));
+
+        [
+            format!(
+                "(FPL-{}-{}{}",
+                self.aircraft_id, self.flight_rules, self.flight_type
+            ),
+            format!(
+                "-{}/{}-{}",
+                NO_LOCATION_INDICATOR, self.wake_turbulence, self.equipment
+            ),
+            format!(
+                "-{}{}",
+                NO_LOCATION_INDICATOR,
+                self.departure_time.format("%H%M")
+            ),
+            format!("-N{:04}VFR {}", self.cruise_speed_knots, route),
+            format!("-{}", destination),
+            format!("-{})", other_information.join(" ")),
+        ]
+        .join("\n")
+    }
+}
+
+/// Formats minutes as `HHMM`
+fn format_hhmm(minutes: i64) -> String {
+    format!("{:02}{:02}", minutes / 60, minutes % 60)
+}
+
+/// Formats a location in degrees and minutes, e.g. `3746N12225W`
+pub fn format_coordinates(location: &Location) -> String {
+    let format = |value: f32, width: usize, positive: char, negative: char| {
+        let total_minutes = (value.abs() * 60.0).round() as u32;
+        format!(
+            "{:0width$}{:02}{}",
+            total_minutes / 60,
+            total_minutes % 60,
+            if value < 0.0 { negative } else { positive },
+            width = width
+        )
+    };
+    format!(
+        "{}{}",
+        format(location.latitude.into_inner(), 2, 'N', 'S'),
+        format(location.longitude.into_inner(), 3, 'E', 'W')
+    )
+}
+
+/// Parses a location in degrees and minutes, e.g. `3746N12225W`
+pub fn parse_coordinates(coordinates: &str) -> Result<Location, String> {
+    let invalid = || format!("Invalid coordinates: {}", coordinates);
+    if coordinates.len() != 11 || !coordinates.is_ascii() {
+        return Err(invalid());
+    }
+    let parse = |digits: &str, hemisphere: &str, negative: &str| -> Result<f32, String> {
+        let (degrees, minutes) = digits.split_at(digits.len() - 2);
+        let degrees = degrees.parse::<u32>().map_err(|_| invalid())?;
+        let minutes = minutes.parse::<u32>().map_err(|_| invalid())?;
+        if minutes >= 60 {
+            return Err(invalid());
+        }
+        let value = degrees as f32 + minutes as f32 / 60.0;
+        Ok(if hemisphere == negative {
+            -value
+        } else {
+            value
+        })
+    };
+    let latitude_hemisphere = &coordinates[4..5];
+    let longitude_hemisphere = &coordinates[10..11];
+    if !matches!(latitude_hemisphere, "N" | "S") || !matches!(longitude_hemisphere, "E" | "W") {
+        return Err(invalid());
+    }
+    Ok(Location {
+        latitude: OrderedFloat(parse(&coordinates[0..4], latitude_hemisphere, "S")?),
+        longitude: OrderedFloat(parse(&coordinates[5..10], longitude_hemisphere, "W")?),
+        altitude_meters: OrderedFloat(0.0),
+    })
+}
+
+/// Changes to a filed flight plan received in a `DLA` or `CHG` message
+#[derive(Debug, Clone, PartialEq)]
+pub struct FplAmendment {
+    /// aircraft identification of the amended flight plan
+    pub aircraft_id: String,
+    /// new estimated off-block time
+    pub departure_time: Option<DateTime<Tz>>,
+    /// new points of the route between departure and destination
+    pub waypoints: Option<Vec<Location>>,
+    /// new total estimated elapsed time
+    pub total_eet_minutes: Option<i64>,
+}
+
+impl FplAmendment {
+    /// Applies the new times to a flight plan
+    ///
+    /// A new off-block time delays both the departure and the arrival, and a
+    /// new elapsed time moves the arrival. The route of the flight plan is
+    /// not stored in the flight plan, so new waypoints are left to the caller.
+    pub fn apply(&self, data: &mut FlightPlanData) -> Result<(), String> {
+        let plan = PlanTimes::from_data(&self.aircraft_id, data)?;
+        let departure = self
+            .departure_time
+            .map(|time| time.timestamp())
+            .unwrap_or(plan.departure);
+        let arrival = match self.total_eet_minutes {
+            Some(minutes) => departure + minutes * 60,
+            None => plan.arrival + departure - plan.departure,
+        };
+        data.scheduled_departure = Some(Timestamp {
+            seconds: departure,
+            nanos: 0,
+        });
+        data.scheduled_arrival = Some(Timestamp {
+            seconds: arrival,
+            nanos: 0,
+        });
+        Ok(())
+    }
+}
+
+/// Parses a time field of 4 digits, e.g. `ZZZZ0930`
+fn parse_time_field(field: &str) -> Result<NaiveTime, String> {
+    let invalid = || format!("Invalid time field: {}", field);
+    if field.len() < 4 || !field.is_ascii() {
+        return Err(invalid());
+    }
+    NaiveTime::parse_from_str(&field[field.len() - 4..], "%H%M").map_err(|_| invalid())
+}
+
+/// Parses an inbound `DLA` or `CHG` message of a filed flight plan
+///
+/// # Arguments
+/// * `message` - The message, e.g.
+///   `(DLA-N123AB-ZZZZ0930-ZZZZ-DOF/301025)` or
+///   `(CHG-N123AB-ZZZZ0900-ZZZZ-DOF/301025-13/ZZZZ0930-16/ZZZZ0045)`
+///
+/// # Returns
+/// The amendment, with the new off-block time on the day of flight or the
+/// day after if it is earlier than the filed one
+pub fn parse_fpl_amendment(message: &str) -> Result<FplAmendment, String> {
+    let body = message
+        .trim()
+        .strip_prefix('(')
+        .and_then(|body| body.strip_suffix(')'))
+        .ok_or("Message must be enclosed in parentheses")?;
+    let fields: Vec<&str> = body.split('-').map(|field| field.trim()).collect();
+    let [kind, aircraft_id, departure, _destination, other_information, amended @ ..] =
+        fields.as_slice()
+    else {
+        return Err("Message is missing fields".to_string());
+    };
+
+    let date_of_flight = other_information
+        .split_whitespace()
+        .find_map(|item| item.strip_prefix("DOF/"))
+        .ok_or("Message has no date of flight")?;
+    let date_of_flight = NaiveDate::parse_from_str(date_of_flight, "%y%m%d")
+        .map_err(|_| format!("Invalid date of flight: {}", date_of_flight))?;
+    let filed_time = parse_time_field(departure)?;
+    let off_block = |field: &str| -> Result<DateTime<Tz>, String> {
+        let time = parse_time_field(field)?;
+        let date = if time < filed_time {
+            date_of_flight + Duration::days(1)
+        } else {
+            date_of_flight
+        };
+        Ok(Tz::UTC.from_utc_datetime(&date.and_time(time)))
+    };
+
+    let mut amendment = FplAmendment {
+        aircraft_id: aircraft_id.to_string(),
+        departure_time: None,
+        waypoints: None,
+        total_eet_minutes: None,
+    };
+    match *kind {
+        "DLA" => amendment.departure_time = Some(off_block(departure)?),
+        "CHG" => {
+            for field in amended {
+                let Some((number, value)) = field.split_once('/') else {
+                    return Err(format!("Invalid amended field: {}", field));
+                };
+                match number {
+                    "13" => amendment.departure_time = Some(off_block(value)?),
+                    "15" => {
+                        amendment.waypoints = Some(
+                            value
+                                .split_whitespace()
+                                .skip(1)
+                                .filter(|item| *item != "DCT")
+                                .map(parse_coordinates)
+                                .collect::<Result<_, _>>()?,
+                        )
+                    }
+                    "16" => {
+                        let eet = value.split_whitespace().next().unwrap_or_default();
+                        let time = parse_time_field(eet)?;
+                        amendment.total_eet_minutes =
+                            Some(time.hour() as i64 * 60 + time.minute() as i64);
+                    }
+                    _ => debug!("Ignoring amended field {}", number),
+                }
+            }
+        }
+        _ => return Err(format!("Unsupported message type: {}", kind)),
+    }
+    if *kind == "DLA" && !amended.is_empty() {
+        return Err("Delay message has too many fields".to_string());
+    }
+    Ok(amendment)
+}
+
+#[cfg(test)]
+mod fpl_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use crate::test_support::mock_location;
+
+    fn departure() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2030, 10, 25, 9, 0, 0).unwrap()
+    }
+
+    fn flight_plan() -> FlightPlanData {
+        create_flight_plan_data(
+            "fpl-vehicle".to_string(),
+            "fpl-A".to_string(),
+            "fpl-B".to_string(),
+            departure(),
+            departure() + Duration::minutes(45),
+        )
+    }
+
+    #[test]
+    fn test_coordinates() {
+        let san_francisco = mock_location(37.7749, -122.4194);
+        assert_eq!(format_coordinates(&san_francisco), "3746N12225W");
+        assert_eq!(
+            format_coordinates(&mock_location(-33.8688, 151.2093)),
+            "3352S15113E"
+        );
+        // rounding to the next degree
+        assert_eq!(
+            format_coordinates(&mock_location(0.9999, 0.0)),
+            "0100N00000E"
+        );
+
+        let parsed = parse_coordinates("3746N12225W").unwrap();
+        assert!((parsed.latitude.into_inner() - 37.7667).abs() < 1e-3);
+        assert!((parsed.longitude.into_inner() + 122.4167).abs() < 1e-3);
+        assert!(parse_coordinates("3746X12225W").is_err());
+        assert!(parse_coordinates("3776N12225W").is_err());
+        assert!(parse_coordinates("3746N1222W").is_err());
+    }
+
+    #[test]
+    fn test_to_fpl() {
+        let route = vec![
+            mock_location(37.7749, -122.4194),
+            mock_location(37.8, -122.35),
+            mock_location(37.8044, -122.2712),
+        ];
+        let plan = IcaoFlightPlan::from_flight_plan(
+            "n-123ab",
+            &flight_plan(),
+            &route,
+            Some(mock_location(37.7, -122.2)),
+        )
+        .unwrap();
+        assert_eq!(
+            plan.to_fpl(),
+            [
+                "(FPL-N123AB-VN",
+                "-ZZZZ/L-S/N",
+                "-ZZZZ0900",
+                "-N0032VFR 3748N12221W",
+                "-ZZZZ0045 ZZZZ",
+                "-DOF/301025 DEP/3746N12225W DEST/3748N12216W ALTN/3742N12212W TYP/UAS)",
+            ]
+            .join("\n")
+        );
+
+        let direct =
+            IcaoFlightPlan::from_flight_plan("N123AB", &flight_plan(), &[route[0], route[2]], None)
+                .unwrap();
+        assert!(direct.to_fpl().contains("\n-N0032VFR DCT\n-ZZZZ0045\n"));
+
+        assert!(
+            IcaoFlightPlan::from_flight_plan("TOOLONGID", &flight_plan(), &route, None).is_err()
+        );
+        assert!(IcaoFlightPlan::from_flight_plan("N123AB", &flight_plan(), &[], None).is_err());
+    }
+
+    #[test]
+    fn test_parse_delay() {
+        let amendment = parse_fpl_amendment("(DLA-N123AB-ZZZZ0930-ZZZZ-DOF/301025)").unwrap();
+        assert_eq!(amendment.aircraft_id, "N123AB");
+        assert_eq!(
+            amendment.departure_time,
+            Some(departure() + Duration::minutes(30))
+        );
+
+        let mut data = flight_plan();
+        amendment.apply(&mut data).unwrap();
+        let plan = PlanTimes::from_data("N123AB", &data).unwrap();
+        assert_eq!(
+            plan.departure,
+            (departure() + Duration::minutes(30)).timestamp()
+        );
+        assert_eq!(
+            plan.arrival,
+            (departure() + Duration::minutes(75)).timestamp()
+        );
+    }
+
+    #[test]
+    fn test_parse_change() {
+        let amendment = parse_fpl_amendment(
+            "(CHG-N123AB-ZZZZ2330-ZZZZ-DOF/301025-13/ZZZZ0015-15/N0032VFR 3748N12221W DCT 3749N12218W-16/ZZZZ0100 ZZZZ)",
+        )
+        .unwrap();
+        // the new off-block time is after midnight
+        assert_eq!(
+            amendment.departure_time,
+            Some(Tz::UTC.with_ymd_and_hms(2030, 10, 26, 0, 15, 0).unwrap())
+        );
+        assert_eq!(amendment.total_eet_minutes, Some(60));
+        assert_eq!(amendment.waypoints.as_ref().map(Vec::len), Some(2));
+
+        let mut data = flight_plan();
+        amendment.apply(&mut data).unwrap();
+        let plan = PlanTimes::from_data("N123AB", &data).unwrap();
+        assert_eq!(plan.arrival - plan.departure, 3600);
+    }
+
+    #[test]
+    fn test_parse_invalid_amendments() {
+        assert!(parse_fpl_amendment("DLA-N123AB-ZZZZ0930-ZZZZ-DOF/301025").is_err());
+        assert!(parse_fpl_amendment("(DLA-N123AB-ZZZZ0930-ZZZZ-0)").is_err());
+        assert!(parse_fpl_amendment("(ARR-N123AB-ZZZZ0930-ZZZZ-DOF/301025)").is_err());
+        assert!(parse_fpl_amendment("(CHG-N123AB-ZZZZ0930-ZZZZ-DOF/301025-13)").is_err());
+        assert!(parse_fpl_amendment("(DLA-N123AB-ZZZZ0960-ZZZZ-DOF/301025)").is_err());
+    }
+}
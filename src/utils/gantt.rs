@@ -0,0 +1,445 @@
+//! Gantt-style timelines of a schedule.
+//!
+//! [`build_schedule_timeline`] turns flight plans into one row per vehicle
+//! and one row per vertipad, each holding the typed intervals the resource
+//! is busy: flights on the vehicle rows, takeoffs and landings on the
+//! vertipad rows. Turnaround and charging blocks from
+//! [`turnaround_blocks`] or [`charging_blocks`] are added to the vehicle
+//! rows. The timeline serializes to JSON for rendering in dashboards; times
+//! are in seconds since the epoch.
+
+use chrono::{DateTime, Duration};
+use rrule::Tz;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::amendment::{timestamp_to_datetime, PlanTimes};
+use crate::context::current_context;
+use crate::router_state::{
+    FlightPlan, Vehicle, LANDING_AND_UNLOADING_TIME_MIN, LOADING_AND_TAKEOFF_TIME_MIN,
+};
+use crate::simulation::{SimulationEvent, SimulationEventKind};
+use crate::turnaround::{ground_movement_minutes, LegKind, TurnaroundModel};
+
+/// Kind of resource of a timeline row
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceKind {
+    /// an aircraft
+    Vehicle,
+    /// a takeoff and landing pad
+    Vertipad,
+}
+
+/// What a resource is busy with during an interval
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntervalKind {
+    /// flight carrying cargo
+    LoadedFlight,
+    /// empty repositioning flight
+    DeadheadFlight,
+    /// pad blocked for loading and takeoff
+    Takeoff,
+    /// pad blocked for landing and unloading
+    Landing,
+    /// vehicle charging
+    Charging,
+    /// vehicle being turned around between two flights
+    Turnaround,
+}
+
+/// Busy interval of a resource
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TimelineInterval {
+    /// start in seconds since the epoch
+    pub start: i64,
+    /// end in seconds since the epoch
+    pub end: i64,
+    /// what the resource is busy with
+    pub kind: IntervalKind,
+    /// flight plan the interval belongs to, if any
+    pub flight_plan_id: Option<String>,
+}
+
+/// Row of the timeline
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TimelineRow {
+    /// kind of the resource
+    pub resource_kind: ResourceKind,
+    /// id of the vehicle or vertipad
+    pub resource_id: String,
+    /// busy intervals ordered by start
+    pub intervals: Vec<TimelineInterval>,
+}
+
+/// Per-resource timeline of a schedule
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ScheduleTimeline {
+    /// start of the earliest interval in seconds since the epoch
+    pub start: Option<i64>,
+    /// end of the latest interval in seconds since the epoch
+    pub end: Option<i64>,
+    /// vehicle rows ordered by id, followed by vertipad rows ordered by id
+    pub rows: Vec<TimelineRow>,
+}
+
+impl ScheduleTimeline {
+    /// Serializes the timeline to JSON
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string(self).map_err(|e| e.to_string())
+    }
+}
+
+/// Busy block of a resource not derived from a flight plan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceBlock {
+    /// kind of the resource
+    pub resource_kind: ResourceKind,
+    /// id of the vehicle or vertipad
+    pub resource_id: String,
+    /// start of the block
+    pub start: DateTime<Tz>,
+    /// end of the block
+    pub end: DateTime<Tz>,
+    /// what the resource is busy with
+    pub kind: IntervalKind,
+}
+
+/// Flight plans with their times, grouped by vehicle and ordered by departure
+fn legs_by_vehicle(flight_plans: &[FlightPlan]) -> BTreeMap<String, Vec<(PlanTimes, LegKind)>> {
+    let mut legs: BTreeMap<String, Vec<(PlanTimes, LegKind)>> = BTreeMap::new();
+    for flight_plan in flight_plans {
+        let (Some(data), Ok(plan)) = (
+            flight_plan.data.as_ref(),
+            PlanTimes::from_flight_plan(flight_plan),
+        ) else {
+            continue;
+        };
+        legs.entry(plan.vehicle_id.clone())
+            .or_default()
+            .push((plan, LegKind::of(data)));
+    }
+    for vehicle_legs in legs.values_mut() {
+        vehicle_legs.sort_by_key(|(plan, _)| plan.departure);
+    }
+    legs
+}
+
+/// Computes the turnaround blocks between consecutive flights of each vehicle with the given model
+///
+/// # Arguments
+/// * `model` - turnaround model
+/// * `vehicles` - vehicles of the flight plans, unknown vehicles get no turnaround blocks
+/// * `flight_plans` - flight plans of the schedule
+///
+/// # Returns
+/// A block starting at each arrival followed by another flight of the vehicle,
+/// cut short by the next departure
+pub fn turnaround_blocks_with(
+    model: &dyn TurnaroundModel,
+    vehicles: &[Vehicle],
+    flight_plans: &[FlightPlan],
+) -> Vec<ResourceBlock> {
+    let vehicles: HashMap<&str, &Vehicle> = vehicles
+        .iter()
+        .map(|vehicle| (vehicle.id.as_str(), vehicle))
+        .collect();
+    let mut blocks = vec![];
+    for (vehicle_id, legs) in legs_by_vehicle(flight_plans) {
+        let Some(vehicle) = vehicles.get(vehicle_id.as_str()) else {
+            continue;
+        };
+        for pair in legs.windows(2) {
+            let ((previous, previous_kind), (next, next_kind)) = (&pair[0], &pair[1]);
+            let minutes = model.turnaround_minutes(vehicle, *previous_kind, *next_kind)
+                + ground_movement_minutes(
+                    &previous.destination_vertiport_id,
+                    &previous.destination_vertipad_id,
+                    Some(&next.departure_vertipad_id),
+                );
+            let end =
+                (previous.arrival + Duration::minutes(minutes).num_seconds()).min(next.departure);
+            if minutes <= 0 || end <= previous.arrival {
+                continue;
+            }
+            blocks.push(ResourceBlock {
+                resource_kind: ResourceKind::Vehicle,
+                resource_id: vehicle_id.clone(),
+                start: timestamp_to_datetime(previous.arrival),
+                end: timestamp_to_datetime(end),
+                kind: IntervalKind::Turnaround,
+            });
+        }
+    }
+    blocks
+}
+
+/// Computes the turnaround blocks between consecutive flights of each vehicle
+/// with the turnaround model used when chaining flights
+pub fn turnaround_blocks(vehicles: &[Vehicle], flight_plans: &[FlightPlan]) -> Vec<ResourceBlock> {
+    match current_context().turnaround_model.read() {
+        Ok(model) => turnaround_blocks_with(model.as_ref(), vehicles, flight_plans),
+        Err(_) => {
+            error!("Turnaround model unavailable");
+            vec![]
+        }
+    }
+}
+
+/// Collects the charging blocks of a simulation
+///
+/// # Arguments
+/// * `events` - events of a [`Simulation`](crate::simulation::Simulation) in order
+///
+/// # Returns
+/// A block for each charge started and finished during the simulation
+pub fn charging_blocks(events: &[SimulationEvent]) -> Vec<ResourceBlock> {
+    let mut started: HashMap<&str, DateTime<Tz>> = HashMap::new();
+    let mut blocks = vec![];
+    for event in events {
+        match &event.kind {
+            SimulationEventKind::ChargingStarted { vehicle_id, .. } => {
+                started.insert(vehicle_id, event.time);
+            }
+            SimulationEventKind::ChargingFinished { vehicle_id, .. } => {
+                if let Some(start) = started.remove(vehicle_id.as_str()) {
+                    blocks.push(ResourceBlock {
+                        resource_kind: ResourceKind::Vehicle,
+                        resource_id: vehicle_id.clone(),
+                        start,
+                        end: event.time,
+                        kind: IntervalKind::Charging,
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+    blocks
+}
+
+/// Builds the per-resource timeline of a schedule
+///
+/// # Arguments
+/// * `flight_plans` - flight plans of the schedule, flight plans without times are left out
+/// * `blocks` - turnaround, charging or other busy blocks of the resources
+///
+/// # Returns
+/// A row for every vehicle and every vertipad used by the flight plans or blocks.
+/// Vertipads are taken from the flight plans; an empty vertipad id gets no row.
+pub fn build_schedule_timeline(
+    flight_plans: &[FlightPlan],
+    blocks: &[ResourceBlock],
+) -> ScheduleTimeline {
+    let takeoff = Duration::minutes(LOADING_AND_TAKEOFF_TIME_MIN as i64).num_seconds();
+    let landing = Duration::minutes(LANDING_AND_UNLOADING_TIME_MIN as i64).num_seconds();
+    let mut rows: BTreeMap<(ResourceKind, String), Vec<TimelineInterval>> = BTreeMap::new();
+    let mut add = |resource_kind, resource_id: &str, interval: TimelineInterval| {
+        if resource_id.is_empty() || interval.end <= interval.start {
+            return;
+        }
+        rows.entry((resource_kind, resource_id.to_string()))
+            .or_default()
+            .push(interval);
+    };
+
+    for (vehicle_id, legs) in legs_by_vehicle(flight_plans) {
+        for (plan, kind) in legs {
+            let interval = |start, end, kind| TimelineInterval {
+                start,
+                end,
+                kind,
+                flight_plan_id: Some(plan.id.clone()),
+            };
+            let flight_kind = match kind {
+                LegKind::Loaded => IntervalKind::LoadedFlight,
+                LegKind::Deadhead => IntervalKind::DeadheadFlight,
+            };
+            add(
+                ResourceKind::Vehicle,
+                &vehicle_id,
+                interval(plan.departure, plan.arrival, flight_kind),
+            );
+            add(
+                ResourceKind::Vertipad,
+                &plan.departure_vertipad_id,
+                interval(
+                    plan.departure,
+                    plan.departure + takeoff,
+                    IntervalKind::Takeoff,
+                ),
+            );
+            add(
+                ResourceKind::Vertipad,
+                &plan.destination_vertipad_id,
+                interval(plan.arrival - landing, plan.arrival, IntervalKind::Landing),
+            );
+        }
+    }
+    for block in blocks {
+        add(
+            block.resource_kind,
+            &block.resource_id,
+            TimelineInterval {
+                start: block.start.timestamp(),
+                end: block.end.timestamp(),
+                kind: block.kind,
+                flight_plan_id: None,
+            },
+        );
+    }
+
+    let rows: Vec<TimelineRow> = rows
+        .into_iter()
+        .map(|((resource_kind, resource_id), mut intervals)| {
+            intervals.sort_by_key(|interval| (interval.start, interval.end));
+            TimelineRow {
+                resource_kind,
+                resource_id,
+                intervals,
+            }
+        })
+        .collect();
+    let intervals = || rows.iter().flat_map(|row| &row.intervals);
+    ScheduleTimeline {
+        start: intervals().map(|interval| interval.start).min(),
+        end: intervals().map(|interval| interval.end).max(),
+        rows,
+    }
+}
+
+#[cfg(test)]
+mod gantt_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use crate::turnaround::ConstantTurnaround;
+    use chrono::TimeZone;
+
+    fn start() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap()
+    }
+
+    fn flight_plan(id: &str, from: &str, to: &str, minutes: (i64, i64), cargo: i64) -> FlightPlan {
+        let mut data = create_flight_plan_data(
+            "v1".to_string(),
+            from.to_string(),
+            to.to_string(),
+            start() + Duration::minutes(minutes.0),
+            start() + Duration::minutes(minutes.1),
+        );
+        data.departure_vertipad_id = format!("{}-pad", from);
+        data.destination_vertipad_id = format!("{}-pad", to);
+        data.cargo_weight_grams = vec![cargo];
+        FlightPlan {
+            id: id.to_string(),
+            data: Some(data),
+        }
+    }
+
+    fn vehicle() -> Vehicle {
+        Vehicle {
+            id: "v1".to_string(),
+            data: None,
+        }
+    }
+
+    fn flight_plans() -> Vec<FlightPlan> {
+        vec![
+            flight_plan("fp2", "B", "A", (60, 90), 0),
+            flight_plan("fp1", "A", "B", (0, 30), 1000),
+        ]
+    }
+
+    #[test]
+    fn test_timeline_rows() {
+        let timeline = build_schedule_timeline(&flight_plans(), &[]);
+        let rows: Vec<(ResourceKind, &str, usize)> = timeline
+            .rows
+            .iter()
+            .map(|row| {
+                (
+                    row.resource_kind,
+                    row.resource_id.as_str(),
+                    row.intervals.len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (ResourceKind::Vehicle, "v1", 2),
+                (ResourceKind::Vertipad, "A-pad", 2),
+                (ResourceKind::Vertipad, "B-pad", 2),
+            ]
+        );
+        let vehicle_row = &timeline.rows[0];
+        assert_eq!(vehicle_row.intervals[0].kind, IntervalKind::LoadedFlight);
+        assert_eq!(vehicle_row.intervals[1].kind, IntervalKind::DeadheadFlight);
+        let b_pad = &timeline.rows[2];
+        assert_eq!(b_pad.intervals[0].kind, IntervalKind::Landing);
+        assert_eq!(
+            b_pad.intervals[0].end,
+            (start() + Duration::minutes(30)).timestamp()
+        );
+        assert_eq!(b_pad.intervals[1].kind, IntervalKind::Takeoff);
+        assert_eq!(timeline.start, Some(start().timestamp()));
+        assert_eq!(
+            timeline.end,
+            Some((start() + Duration::minutes(90)).timestamp())
+        );
+    }
+
+    #[test]
+    fn test_turnaround_and_charging_blocks() {
+        let flight_plans = flight_plans();
+        let mut blocks =
+            turnaround_blocks_with(&ConstantTurnaround(45), &[vehicle()], &flight_plans);
+        assert_eq!(blocks.len(), 1);
+        // cut short by the next departure
+        assert_eq!(blocks[0].start, start() + Duration::minutes(30));
+        assert_eq!(blocks[0].end, start() + Duration::minutes(60));
+
+        let charging = |minutes, kind| SimulationEvent {
+            time: start() + Duration::minutes(minutes),
+            kind,
+        };
+        let events = vec![
+            charging(
+                30,
+                SimulationEventKind::ChargingStarted {
+                    vehicle_id: "v1".to_string(),
+                    vertiport_id: "B".to_string(),
+                },
+            ),
+            charging(
+                50,
+                SimulationEventKind::ChargingFinished {
+                    vehicle_id: "v1".to_string(),
+                    vertiport_id: "B".to_string(),
+                },
+            ),
+        ];
+        blocks.extend(charging_blocks(&events));
+        assert_eq!(blocks[1].kind, IntervalKind::Charging);
+
+        let timeline = build_schedule_timeline(&flight_plans, &blocks);
+        let kinds: Vec<IntervalKind> = timeline.rows[0]
+            .intervals
+            .iter()
+            .map(|interval| interval.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                IntervalKind::LoadedFlight,
+                IntervalKind::Charging,
+                IntervalKind::Turnaround,
+                IntervalKind::DeadheadFlight,
+            ]
+        );
+        let json = timeline.to_json().unwrap();
+        assert!(json.contains(r#""resource_kind":"vehicle""#));
+        assert!(json.contains(r#""kind":"deadhead_flight""#));
+    }
+}
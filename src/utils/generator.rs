@@ -1,19 +1,55 @@
 //! A number of methods to generate random data for testing.
+//!
+//! Each generator has a `_with_rng` variant drawing from the given random
+//! number generator, and the node and location generators a `_with_seed`
+//! variant, so failures found with random data can be reproduced. The
+//! schedule generators produce valid calendar strings, see
+//! [`Calendar`](crate::schedule::Calendar), so tests can exercise the
+//! calendar logic with varied schedules. The zone generators produce
+//! polygons inside a [`BoundingBox`], e.g. no-fly zones to be avoided as
+//! severe [`WeatherCell`](crate::weather::WeatherCell)s.
 
 use std::collections::HashSet;
+use std::f64::consts::{PI, TAU};
 
-use crate::types::{location::Location, node::Node, status};
+use crate::types::{distance::Distance, location::Location, node::Node, status};
+use crate::utils::haversine;
 use ordered_float::OrderedFloat;
-use quaternion::Quaternion;
-use rand::{rngs::ThreadRng, Rng};
-use uuid::Uuid;
-use vecmath::Vector3;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use uuid::Builder;
 
 //-----------------------------------------------------
 // Constants
 //-----------------------------------------------------
-const DEG_TO_RAD: f32 = std::f32::consts::PI / 180.0;
-const RAD_TO_DEG: f32 = 180.0 / std::f32::consts::PI;
+/// Draws of a location near another before falling back to the location itself
+const MAX_SAMPLING_ATTEMPTS: usize = 100;
+/// Date the recurrences of the generated schedules start from
+const SCHEDULE_START_DATE: &str = "20221020";
+/// Weekdays of the generated schedules, in RRULE notation
+const WEEKDAYS: [&str; 7] = ["MO", "TU", "WE", "TH", "FR", "SA", "SU"];
+/// Vertices of the polygon approximating a circular zone
+const CIRCLE_VERTICES: usize = 32;
+
+/// Area the zones are generated in, in degrees
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    /// southern edge
+    pub min_latitude: f32,
+    /// northern edge
+    pub max_latitude: f32,
+    /// western edge
+    pub min_longitude: f32,
+    /// eastern edge
+    pub max_longitude: f32,
+}
+
+impl BoundingBox {
+    /// Checks if the location is within the box, edges included
+    pub fn contains(&self, location: &Location) -> bool {
+        (self.min_latitude..=self.max_latitude).contains(&location.latitude.into_inner())
+            && (self.min_longitude..=self.max_longitude).contains(&location.longitude.into_inner())
+    }
+}
 
 /// Generate a vector of random nodes.
 ///
@@ -23,11 +59,25 @@ const RAD_TO_DEG: f32 = 180.0 / std::f32::consts::PI;
 /// # Returns
 /// A vector of nodes.
 pub fn generate_nodes(capacity: i32) -> Vec<Node> {
+    generate_nodes_with_rng(&mut rand::thread_rng(), capacity)
+}
+
+/// Generate a vector of random nodes, the same for the same seed.
+///
+/// See [`generate_nodes`].
+pub fn generate_nodes_with_seed(seed: u64, capacity: i32) -> Vec<Node> {
+    generate_nodes_with_rng(&mut StdRng::seed_from_u64(seed), capacity)
+}
+
+/// Generate a vector of random nodes drawn from `rng`.
+///
+/// See [`generate_nodes`].
+pub fn generate_nodes_with_rng(rng: &mut impl Rng, capacity: i32) -> Vec<Node> {
     let mut nodes = Vec::new();
     let mut uuid_set = HashSet::<String>::new();
     for _ in 0..capacity {
         loop {
-            let node = generate_random_node();
+            let node = generate_random_node_with_rng(rng);
             if !uuid_set.contains(&node.uid) {
                 uuid_set.insert(node.uid.clone());
                 nodes.push(node);
@@ -42,17 +92,42 @@ pub fn generate_nodes(capacity: i32) -> Vec<Node> {
 ///
 /// # Arguments
 /// * `location` - The location to generate nodes near.
-/// * `radius` - The radius in kilometers to generate nodes within.
+/// * `radius` - The radius to generate nodes within.
 /// * `capacity` - The number of nodes to generate.
 ///
 /// # Returns
 /// A vector of nodes.
-pub fn generate_nodes_near(location: &Location, radius: f32, capacity: i32) -> Vec<Node> {
+pub fn generate_nodes_near(location: &Location, radius: Distance, capacity: i32) -> Vec<Node> {
+    generate_nodes_near_with_rng(&mut rand::thread_rng(), location, radius, capacity)
+}
+
+/// Generate a vector of random nodes near a location, the same for the
+/// same seed.
+///
+/// See [`generate_nodes_near`].
+pub fn generate_nodes_near_with_seed(
+    seed: u64,
+    location: &Location,
+    radius: Distance,
+    capacity: i32,
+) -> Vec<Node> {
+    generate_nodes_near_with_rng(&mut StdRng::seed_from_u64(seed), location, radius, capacity)
+}
+
+/// Generate a vector of random nodes near a location drawn from `rng`.
+///
+/// See [`generate_nodes_near`].
+pub fn generate_nodes_near_with_rng(
+    rng: &mut impl Rng,
+    location: &Location,
+    radius: Distance,
+    capacity: i32,
+) -> Vec<Node> {
     let mut nodes = Vec::new();
     let mut uuid_set = HashSet::<String>::new();
     for _ in 0..capacity {
         loop {
-            let node = generate_random_node_near(location, radius);
+            let node = generate_random_node_near_with_rng(rng, location, radius);
             if !uuid_set.contains(&node.uid) {
                 uuid_set.insert(node.uid.clone());
                 nodes.push(node);
@@ -70,20 +145,27 @@ pub fn generate_nodes_near(location: &Location, radius: f32, capacity: i32) -> V
 /// Note that the UUID generation does not guarantee uniqueness. Please
 /// make sure to check for potential duplicates, albeit very unlikely.
 pub fn generate_random_node() -> Node {
+    generate_random_node_with_rng(&mut rand::thread_rng())
+}
+
+/// Generate a single random node drawn from `rng`.
+///
+/// See [`generate_random_node`].
+pub fn generate_random_node_with_rng(rng: &mut impl Rng) -> Node {
     Node {
-        uid: Uuid::new_v4().to_string(),
-        location: generate_location(),
+        uid: generate_uuid(rng),
+        location: generate_location_with_rng(rng),
         forward_to: None,
         status: status::Status::Ok,
         schedule: None,
     }
 }
 
-/// Generate a random node near a location within radius in kilometers.
+/// Generate a random node near a location within radius.
 ///
 /// # Arguments
 /// * `location` - The location to generate nodes near.
-/// * `radius` - The radius in kilometers to generate nodes within.
+/// * `radius` - The radius to generate nodes within.
 ///
 /// # Returns
 /// A node with a location near the given location.
@@ -91,10 +173,21 @@ pub fn generate_random_node() -> Node {
 /// # Caution
 /// Note that the UUID generation does not guarantee uniqueness. Please
 /// make sure to check for potential duplicates, albeit very unlikely.
-pub fn generate_random_node_near(location: &Location, radius: f32) -> Node {
+pub fn generate_random_node_near(location: &Location, radius: Distance) -> Node {
+    generate_random_node_near_with_rng(&mut rand::thread_rng(), location, radius)
+}
+
+/// Generate a random node near a location drawn from `rng`.
+///
+/// See [`generate_random_node_near`].
+pub fn generate_random_node_near_with_rng(
+    rng: &mut impl Rng,
+    location: &Location,
+    radius: Distance,
+) -> Node {
     Node {
-        uid: Uuid::new_v4().to_string(),
-        location: generate_location_near(location, radius),
+        uid: generate_uuid(rng),
+        location: generate_location_near_with_rng(rng, location, radius),
         forward_to: None,
         status: status::Status::Ok,
         schedule: None,
@@ -106,7 +199,16 @@ pub fn generate_random_node_near(location: &Location, radius: f32) -> Node {
 /// # Returns
 /// A random location anywhere on earth.
 pub fn generate_location() -> Location {
-    let mut rng = rand::thread_rng();
+    generate_location_with_rng(&mut rand::thread_rng())
+}
+
+/// Generate a random location anywhere on earth, the same for the same seed.
+pub fn generate_location_with_seed(seed: u64) -> Location {
+    generate_location_with_rng(&mut StdRng::seed_from_u64(seed))
+}
+
+/// Generate a random location anywhere on earth drawn from `rng`.
+pub fn generate_location_with_rng(rng: &mut impl Rng) -> Location {
     let latitude = OrderedFloat(rng.gen_range(-90.0..=90.0));
     let longitude = OrderedFloat(rng.gen_range(-180.0..=180.0));
     let altitude_meters = OrderedFloat(rng.gen_range(0.0..=10000.0));
@@ -121,17 +223,40 @@ pub fn generate_location() -> Location {
 ///
 /// # Arguments
 /// * `location` - The location to generate a random location near.
-/// * `radius` - The radius in kilometers.
+/// * `radius` - The radius.
 ///
 /// # Returns
 /// A random location near the given location and radius.
-pub fn generate_location_near(location: &Location, radius: f32) -> Location {
-    let mut rng = rand::thread_rng();
+pub fn generate_location_near(location: &Location, radius: Distance) -> Location {
+    generate_location_near_with_rng(&mut rand::thread_rng(), location, radius)
+}
+
+/// Generate a random location near a given location and radius, the same
+/// for the same seed.
+///
+/// See [`generate_location_near`].
+pub fn generate_location_near_with_seed(
+    seed: u64,
+    location: &Location,
+    radius: Distance,
+) -> Location {
+    generate_location_near_with_rng(&mut StdRng::seed_from_u64(seed), location, radius)
+}
+
+/// Generate a random location near a given location and radius drawn from
+/// `rng`.
+///
+/// See [`generate_location_near`].
+pub fn generate_location_near_with_rng(
+    rng: &mut impl Rng,
+    location: &Location,
+    radius: Distance,
+) -> Location {
     let (latitude, longitude) = gen_around_location(
-        &mut rng,
+        rng,
         location.latitude.into_inner(),
         location.longitude.into_inner(),
-        radius,
+        radius.km(),
     );
 
     let altitude_meters = OrderedFloat(rng.gen_range(0.0..=10000.0));
@@ -142,9 +267,263 @@ pub fn generate_location_near(location: &Location, radius: f32) -> Location {
     }
 }
 
+/// Generate a random calendar for a vertiport or vehicle, combining some of
+/// a night closure, a weekday closure and a maintenance block.
+///
+/// # Returns
+/// A valid calendar string of blocking events, or None for a schedule
+/// without any, e.g. for [`Vehicle::schedule`](crate::resources::vehicle::Data::schedule).
+pub fn generate_schedule() -> Option<String> {
+    generate_schedule_with_rng(&mut rand::thread_rng())
+}
+
+/// Generate a random calendar drawn from `rng`.
+///
+/// See [`generate_schedule`].
+pub fn generate_schedule_with_rng(rng: &mut impl Rng) -> Option<String> {
+    let mut events = vec![];
+    if rng.gen_bool(0.5) {
+        events.push(generate_night_closure_with_rng(rng));
+    }
+    if rng.gen_bool(0.5) {
+        events.push(generate_weekday_closure_with_rng(rng));
+    }
+    if rng.gen_bool(0.5) {
+        events.push(generate_maintenance_block_with_rng(rng));
+    }
+    (!events.is_empty()).then(|| events.join("\n"))
+}
+
+/// Generate a calendar closing every night, from between 18:00 and 23:00 UTC
+/// for 6 to 10 hours.
+pub fn generate_night_closure() -> String {
+    generate_night_closure_with_rng(&mut rand::thread_rng())
+}
+
+/// Generate a night closure drawn from `rng`.
+///
+/// See [`generate_night_closure`].
+pub fn generate_night_closure_with_rng(rng: &mut impl Rng) -> String {
+    format!(
+        "DTSTART:{}T{:02}0000Z;DURATION:PT{}H\nRRULE:FREQ=DAILY",
+        SCHEDULE_START_DATE,
+        rng.gen_range(18..=23),
+        rng.gen_range(6..=10)
+    )
+}
+
+/// Generate a calendar closing all day on one or two weekdays every week.
+pub fn generate_weekday_closure() -> String {
+    generate_weekday_closure_with_rng(&mut rand::thread_rng())
+}
+
+/// Generate a weekday closure drawn from `rng`.
+///
+/// See [`generate_weekday_closure`].
+pub fn generate_weekday_closure_with_rng(rng: &mut impl Rng) -> String {
+    let count = rng.gen_range(1..=2);
+    let mut weekdays = WEEKDAYS.choose_multiple(rng, count).collect::<Vec<_>>();
+    weekdays.sort_by_key(|weekday| WEEKDAYS.iter().position(|day| day == *weekday));
+    format!(
+        "DTSTART:{}T000000Z;DURATION:PT24H\nRRULE:FREQ=WEEKLY;BYDAY={}",
+        SCHEDULE_START_DATE,
+        weekdays.into_iter().copied().collect::<Vec<_>>().join(",")
+    )
+}
+
+/// Generate a calendar blocking 1 to 4 hours for maintenance, starting on
+/// the hour, on one weekday every week.
+pub fn generate_maintenance_block() -> String {
+    generate_maintenance_block_with_rng(&mut rand::thread_rng())
+}
+
+/// Generate a maintenance block drawn from `rng`.
+///
+/// See [`generate_maintenance_block`].
+pub fn generate_maintenance_block_with_rng(rng: &mut impl Rng) -> String {
+    format!(
+        "DTSTART:{}T{:02}0000Z;DURATION:PT{}H\nRRULE:FREQ=WEEKLY;BYDAY={}",
+        SCHEDULE_START_DATE,
+        rng.gen_range(0..24),
+        rng.gen_range(1..=4),
+        WEEKDAYS.choose(rng).unwrap_or(&WEEKDAYS[0])
+    )
+}
+
+/// Generate a random zone shaped as a polygon inside the bounding box.
+///
+/// The vertices are spread all around a random center, at 50 to 100% of
+/// `max_radius` from it, so the polygon is never self-intersecting. The
+/// radius is reduced if the zone wouldn't fit into the box.
+///
+/// # Arguments
+/// * `bounds` - The area to generate the zone in.
+/// * `max_radius` - The largest distance from the center to a vertex.
+/// * `vertices` - The number of vertices, at least 3.
+///
+/// # Returns
+/// The vertices of the polygon in counterclockwise order, on the ground.
+pub fn generate_polygon(
+    bounds: &BoundingBox,
+    max_radius: Distance,
+    vertices: usize,
+) -> Vec<Location> {
+    generate_polygon_with_rng(&mut rand::thread_rng(), bounds, max_radius, vertices)
+}
+
+/// Generate a random polygon zone drawn from `rng`.
+///
+/// See [`generate_polygon`].
+pub fn generate_polygon_with_rng(
+    rng: &mut impl Rng,
+    bounds: &BoundingBox,
+    max_radius: Distance,
+    vertices: usize,
+) -> Vec<Location> {
+    let vertices = vertices.max(3);
+    let (center, radius) = gen_zone_center(rng, bounds, max_radius.km());
+    (0..vertices)
+        .map(|i| {
+            let bearing = (i as f64 + rng.gen_range(0.0..1.0)) * TAU / vertices as f64;
+            let scale = rng.gen_range(0.5..=1.0);
+            zone_vertex(
+                bounds,
+                center,
+                (radius.0 * scale, radius.1 * scale),
+                bearing,
+            )
+        })
+        .collect()
+}
+
+/// Generate a random circular zone inside the bounding box.
+///
+/// # Arguments
+/// * `bounds` - The area to generate the zone in.
+/// * `radius` - The radius of the zone, reduced if the zone wouldn't fit
+///   into the box.
+///
+/// # Returns
+/// The vertices of a polygon approximating the circle, in counterclockwise
+/// order, on the ground.
+pub fn generate_circular_zone(bounds: &BoundingBox, radius: Distance) -> Vec<Location> {
+    generate_circular_zone_with_rng(&mut rand::thread_rng(), bounds, radius)
+}
+
+/// Generate a random circular zone drawn from `rng`.
+///
+/// See [`generate_circular_zone`].
+pub fn generate_circular_zone_with_rng(
+    rng: &mut impl Rng,
+    bounds: &BoundingBox,
+    radius: Distance,
+) -> Vec<Location> {
+    let (center, radius) = gen_zone_center(rng, bounds, radius.km());
+    (0..CIRCLE_VERTICES)
+        .map(|i| {
+            zone_vertex(
+                bounds,
+                center,
+                radius,
+                i as f64 * TAU / CIRCLE_VERTICES as f64,
+            )
+        })
+        .collect()
+}
+
+/// Generate random zones inside the bounding box, each a polygon of 3 to 8
+/// vertices or a circle, of up to `max_radius`.
+///
+/// # Arguments
+/// * `bounds` - The area to generate the zones in.
+/// * `max_radius` - The largest radius of a zone.
+/// * `capacity` - The number of zones to generate.
+///
+/// # Returns
+/// The polygons of the zones, which may overlap each other.
+pub fn generate_zones(
+    bounds: &BoundingBox,
+    max_radius: Distance,
+    capacity: i32,
+) -> Vec<Vec<Location>> {
+    generate_zones_with_rng(&mut rand::thread_rng(), bounds, max_radius, capacity)
+}
+
+/// Generate random zones drawn from `rng`.
+///
+/// See [`generate_zones`].
+pub fn generate_zones_with_rng(
+    rng: &mut impl Rng,
+    bounds: &BoundingBox,
+    max_radius: Distance,
+    capacity: i32,
+) -> Vec<Vec<Location>> {
+    (0..capacity)
+        .map(|_| {
+            let radius = Distance::from_km(rng.gen_range(0.0..=max_radius.km().max(0.0)));
+            if rng.gen_bool(0.5) {
+                generate_circular_zone_with_rng(rng, bounds, radius)
+            } else {
+                let vertices = rng.gen_range(3..=8);
+                generate_polygon_with_rng(rng, bounds, radius, vertices)
+            }
+        })
+        .collect()
+}
+
+/// Draws the center of a zone, so a zone of the given radius fits into the
+/// box, and returns it with the radius in degrees of latitude and longitude
+fn gen_zone_center(
+    rng: &mut impl Rng,
+    bounds: &BoundingBox,
+    radius_km: f32,
+) -> ((f64, f64), (f64, f64)) {
+    let km_per_degree = haversine::get_earth_radius().km() * PI / 180.0;
+    let (min_latitude, max_latitude) = (bounds.min_latitude as f64, bounds.max_latitude as f64);
+    let (min_longitude, max_longitude) = (bounds.min_longitude as f64, bounds.max_longitude as f64);
+    let latitude_radius = (radius_km.max(0.0) as f64 / km_per_degree)
+        .min((max_latitude - min_latitude).max(0.0) / 2.0);
+    let latitude = rng.gen_range(min_latitude + latitude_radius..=max_latitude - latitude_radius);
+    // degrees of longitude shrink towards the poles
+    let longitude_radius = (radius_km.max(0.0) as f64
+        / (km_per_degree * latitude.to_radians().cos()).max(f64::EPSILON))
+    .min((max_longitude - min_longitude).max(0.0) / 2.0);
+    let longitude =
+        rng.gen_range(min_longitude + longitude_radius..=max_longitude - longitude_radius);
+    ((latitude, longitude), (latitude_radius, longitude_radius))
+}
+
+/// Vertex of a zone in the direction of the bearing, counterclockwise from
+/// east, kept within the box despite rounding
+fn zone_vertex(
+    bounds: &BoundingBox,
+    (latitude, longitude): (f64, f64),
+    (latitude_radius, longitude_radius): (f64, f64),
+    bearing: f64,
+) -> Location {
+    let latitude = (latitude + latitude_radius * bearing.sin()) as f32;
+    let longitude = (longitude + longitude_radius * bearing.cos()) as f32;
+    Location {
+        latitude: OrderedFloat(latitude.clamp(bounds.min_latitude, bounds.max_latitude)),
+        longitude: OrderedFloat(longitude.clamp(bounds.min_longitude, bounds.max_longitude)),
+        altitude_meters: OrderedFloat(0.0),
+    }
+}
+
+/// Generate a random version 4 UUID drawn from `rng`.
+pub(crate) fn generate_uuid(rng: &mut impl Rng) -> String {
+    Builder::from_random_bytes(rng.gen())
+        .into_uuid()
+        .to_string()
+}
+
 /// Generate a random location within a radius.
 ///
-/// Source: [Reddit](https://www.reddit.com/r/rust/comments/f08lqu/comment/fgsxeik/)
+/// Locations are uniformly distributed on the spherical cap of the given
+/// radius around the location: the cosine of the angular distance from the
+/// location is drawn uniformly between the cosine of the angular radius and
+/// 1, and the bearing uniformly in all directions. Locations beyond the
+/// radius after rounding to `f32` are drawn again.
 ///
 /// # Arguments
 /// * `rng` - The random number generator.
@@ -153,48 +532,54 @@ pub fn generate_location_near(location: &Location, radius: f32) -> Location {
 /// * `radius` - The radius in kilometers.
 ///
 /// # Returns
-/// A latitude and longitude pair.
-///
-/// # Notes
-/// @GoodluckH: This function sometimes output invalid coordinates. I'm not sure why.
+/// A latitude and longitude pair, or the location itself if no location
+/// within the radius was drawn in [`MAX_SAMPLING_ATTEMPTS`] attempts.
 fn gen_around_location(
-    rng: &mut ThreadRng,
+    rng: &mut impl Rng,
     latitude: f32,
     longitude: f32,
     radius: f32,
 ) -> (OrderedFloat<f32>, OrderedFloat<f32>) {
-    // Transform to cartesian coordinates
-    let x = (DEG_TO_RAD * longitude).cos();
-    let y = (DEG_TO_RAD * longitude).sin();
-    let z = (DEG_TO_RAD * latitude).sin();
-
-    // Generate random unit vector
-    let x1 = 2.0 * rng.gen::<f32>() - 1.0;
-    let y1 = 2.0 * rng.gen::<f32>() - 1.0;
-    let z1 = 2.0 * rng.gen::<f32>() - 1.0;
-    let len = (x1 * x1 + y1 * y1 + z1 * z1).sqrt();
-
-    // Generate random angle
-    let ang = 0.5 * (radius / 1000.0 * DEG_TO_RAD) * rng.gen::<f32>();
-    let ca = ang.cos();
-    let sa = ang.sin() / len;
-
-    // Create Quaternion components
-    let vec: Vector3<f32> = [x, y, z]; // Todo handle 0 case
-    let q: Quaternion<f32> = (ca, [sa * x1, sa * y1, sa * z1]);
-    let vec = quaternion::rotate_vector(q, vec);
-
-    let r_lon = RAD_TO_DEG * vec[1].atan2(vec[0]);
-    let r_lat = RAD_TO_DEG * vec[2].asin();
-    if r_lat.is_nan() {
-        return gen_around_location(rng, latitude, longitude, radius);
+    let center = Location {
+        latitude: OrderedFloat(latitude),
+        longitude: OrderedFloat(longitude),
+        altitude_meters: OrderedFloat(0.0),
+    };
+    let max_angle = (radius.max(0.0) as f64 / haversine::get_earth_radius().km()).min(PI);
+    let (lat1, lon1) = (
+        (latitude as f64).to_radians(),
+        (longitude as f64).to_radians(),
+    );
+    for _ in 0..MAX_SAMPLING_ATTEMPTS {
+        let angle = rng.gen_range(max_angle.cos()..=1.0).acos();
+        let bearing = rng.gen_range(0.0..TAU);
+        // destination given the angular distance and bearing from the location
+        let lat2 = (lat1.sin() * angle.cos() + lat1.cos() * angle.sin() * bearing.cos())
+            .clamp(-1.0, 1.0)
+            .asin();
+        let lon2 = lon1
+            + (bearing.sin() * angle.sin() * lat1.cos())
+                .atan2(angle.cos() - lat1.sin() * lat2.sin());
+        let lon2 = (lon2 + PI).rem_euclid(TAU) - PI;
+        let candidate = Location {
+            latitude: OrderedFloat(lat2.to_degrees() as f32),
+            longitude: OrderedFloat(lon2.to_degrees() as f32),
+            altitude_meters: OrderedFloat(0.0),
+        };
+        if haversine::distance(&center, &candidate) <= radius {
+            return (candidate.latitude, candidate.longitude);
+        }
     }
-    (OrderedFloat(r_lat), OrderedFloat(r_lon))
+    (OrderedFloat(latitude), OrderedFloat(longitude))
 }
 
 #[cfg(test)]
 mod tests {
     use crate::haversine;
+    use crate::schedule::{Calendar, Tz};
+    use crate::test_support::mock_location;
+    use chrono::TimeZone;
+    use std::str::FromStr;
 
     use super::*;
 
@@ -210,43 +595,262 @@ mod tests {
     }
 
     /// Test that the distance between two locations is less than the radius.
-    ///
-    /// # Note
-    /// Sometimes the test will fail. TODO: Double check the
-    /// [`gen_around_location`] function for improvements.
     #[test]
     fn test_generate_location_near() {
         let location = generate_location();
-        let location_near = generate_location_near(&location, 10.0);
-        println!("Original location: {:?}", location);
-        println!("Nearby location: {:?}", location_near);
-        println!(
-            "Distance: {}",
-            haversine::distance(&location, &location_near)
-        );
+        let location_near = generate_location_near(&location, Distance::from_km(10.0));
         assert!(haversine::distance(&location, &location_near) <= 10.0);
     }
 
+    /// Test locations near the poles, the antimeridian and the equator, with
+    /// radii from nothing to beyond the other side of the earth
+    #[test]
+    fn test_generate_location_near_within_radius() {
+        let centers = [
+            (0.0, 0.0),
+            (37.7749, -122.4194),
+            (89.9999, 45.0),
+            (-90.0, 0.0),
+            (12.5, 179.999),
+            (-45.0, -180.0),
+        ];
+        let mut rng = StdRng::seed_from_u64(3620);
+        for (latitude, longitude) in centers {
+            let center = mock_location(latitude, longitude);
+            for radius in [0.0, 0.01, 1.0, 10.0, 250.0, 5000.0, 25000.0] {
+                for _ in 0..500 {
+                    let location = generate_location_near_with_rng(
+                        &mut rng,
+                        &center,
+                        Distance::from_km(radius),
+                    );
+                    let (latitude, longitude) = (
+                        location.latitude.into_inner(),
+                        location.longitude.into_inner(),
+                    );
+                    assert!((-90.0..=90.0).contains(&latitude), "{:?}", location);
+                    assert!((-180.0..=180.0).contains(&longitude), "{:?}", location);
+                    assert!(
+                        haversine::distance(&center, &location) <= radius,
+                        "{:?} beyond {} km of {:?}",
+                        location,
+                        radius,
+                        center
+                    );
+                }
+            }
+        }
+    }
+
+    /// Test that the locations spread over the whole cap rather than
+    /// clustering at its center
+    #[test]
+    fn test_generate_location_near_is_uniform() {
+        let center = generate_location_with_seed(1);
+        let mut rng = StdRng::seed_from_u64(3620);
+        let within_half_radius = (0..4000)
+            .map(|_| generate_location_near_with_rng(&mut rng, &center, Distance::from_km(100.0)))
+            .filter(|location| haversine::distance(&center, location) <= 50.0)
+            .count();
+        // a quarter of a small cap lies within half of its radius
+        assert!((800..1200).contains(&within_half_radius));
+    }
+
     #[test]
     fn test_generate_random_nodes() {
         let node = generate_nodes(100);
         assert_eq!(node.len(), 100);
     }
 
-    // Disregard this test. generate_nodes_near may fail occasionally.
-    // This is due to unknown reasons. However, generate_nodes_near is
-    // only used for testing purposes.
-    //
-    // Failure with generate_nodes_near does not impact the production
-    // functionality of the library.
-    //
-    // #[test]
-    // fn test_generate_random_nodes_near() {
-    //     let location = generate_location();
-    //     let nodes = generate_nodes_near(&location, 10.0, 100);
-    //     assert_eq!(nodes.len(), 100);
-    //     for node in nodes {
-    //         assert!(haversine::distance(&location, &node.location) <= 10.0);
-    //     }
-    // }
+    #[test]
+    fn test_generate_with_seed() {
+        assert_eq!(
+            generate_nodes_with_seed(7, 20),
+            generate_nodes_with_seed(7, 20)
+        );
+        assert_ne!(
+            generate_nodes_with_seed(7, 20),
+            generate_nodes_with_seed(8, 20)
+        );
+        let location = generate_location_with_seed(7);
+        assert_eq!(location, generate_location_with_seed(7));
+        assert_eq!(
+            generate_nodes_near_with_seed(7, &location, Distance::from_km(10.0), 20),
+            generate_nodes_near_with_seed(7, &location, Distance::from_km(10.0), 20)
+        );
+        assert_eq!(
+            generate_location_near_with_seed(7, &location, Distance::from_km(10.0)),
+            generate_location_near_with_seed(7, &location, Distance::from_km(10.0))
+        );
+    }
+
+    #[test]
+    fn test_generate_schedule() {
+        let mut rng = StdRng::seed_from_u64(3623);
+        let mut generated = HashSet::new();
+        for _ in 0..200 {
+            let Some(schedule) = generate_schedule_with_rng(&mut rng) else {
+                continue;
+            };
+            assert!(
+                Calendar::from_str(&schedule).is_ok(),
+                "Invalid schedule: {}",
+                schedule
+            );
+            generated.insert(schedule);
+        }
+        assert!(generated.len() > 100);
+    }
+
+    #[test]
+    fn test_generate_schedule_blocks() {
+        let mut rng = StdRng::seed_from_u64(3623);
+        // Thursday, as the start of the schedules
+        let day = Tz::UTC.with_ymd_and_hms(2022, 10, 20, 0, 0, 0).unwrap();
+        for _ in 0..20 {
+            let calendar = Calendar::from_str(&generate_night_closure_with_rng(&mut rng)).unwrap();
+            // a night closure covers midnight
+            let midnight = day + chrono::Duration::days(8);
+            assert!(!calendar.is_available_between(
+                midnight - chrono::Duration::minutes(30),
+                midnight + chrono::Duration::minutes(30)
+            ));
+            assert!(calendar.is_available_between(
+                day + chrono::Duration::hours(8),
+                day + chrono::Duration::hours(14)
+            ));
+
+            let schedule = generate_weekday_closure_with_rng(&mut rng);
+            let calendar = Calendar::from_str(&schedule).unwrap();
+            let closed_days = (0..7)
+                .filter(|days| {
+                    let start = day + chrono::Duration::days(7 + days);
+                    !calendar.is_available_between(
+                        start + chrono::Duration::hours(11),
+                        start + chrono::Duration::hours(12),
+                    )
+                })
+                .count();
+            assert_eq!(closed_days, schedule.matches(',').count() + 1);
+
+            let calendar =
+                Calendar::from_str(&generate_maintenance_block_with_rng(&mut rng)).unwrap();
+            assert!(!calendar.is_available_between(
+                day + chrono::Duration::days(7),
+                day + chrono::Duration::days(14)
+            ));
+        }
+    }
+
+    /// Bay Area, about 110 by 90 km
+    fn bounds() -> BoundingBox {
+        BoundingBox {
+            min_latitude: 37.2,
+            max_latitude: 38.0,
+            min_longitude: -122.6,
+            max_longitude: -121.6,
+        }
+    }
+
+    #[test]
+    fn test_generate_zones() {
+        let mut rng = StdRng::seed_from_u64(3624);
+        let zones = generate_zones_with_rng(&mut rng, &bounds(), Distance::from_km(20.0), 200);
+        assert_eq!(zones.len(), 200);
+        for zone in zones {
+            assert!(zone.len() >= 3);
+            assert!(zone.iter().all(|vertex| bounds().contains(vertex)));
+        }
+
+        let polygon = generate_polygon_with_rng(&mut rng, &bounds(), Distance::from_km(10.0), 6);
+        assert_eq!(polygon.len(), 6);
+        // zones larger than the box are shrunk to fit
+        let circle =
+            generate_circular_zone_with_rng(&mut rng, &bounds(), Distance::from_km(1000.0));
+        assert_eq!(circle.len(), CIRCLE_VERTICES);
+        assert!(circle.iter().all(|vertex| bounds().contains(vertex)));
+    }
+
+    #[test]
+    fn test_generate_circular_zone() {
+        let mut rng = StdRng::seed_from_u64(3624);
+        let circle = generate_circular_zone_with_rng(&mut rng, &bounds(), Distance::from_km(10.0));
+        let center = Location {
+            latitude: OrderedFloat(
+                circle.iter().map(|v| v.latitude.into_inner()).sum::<f32>() / circle.len() as f32,
+            ),
+            longitude: OrderedFloat(
+                circle.iter().map(|v| v.longitude.into_inner()).sum::<f32>() / circle.len() as f32,
+            ),
+            altitude_meters: OrderedFloat(0.0),
+        };
+        for vertex in &circle {
+            assert!((haversine::distance(&center, vertex) - 10.0).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn test_routes_avoid_generated_zones() {
+        use crate::router::engine::Router;
+        use crate::weather::{weather_penalty, WeatherCell, WeatherSeverity};
+
+        let mut rng = StdRng::seed_from_u64(3624);
+        let start = Tz::UTC.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let cells = generate_zones_with_rng(&mut rng, &bounds(), Distance::from_km(5.0), 10)
+            .into_iter()
+            .enumerate()
+            .map(|(i, polygon)| WeatherCell {
+                id: format!("zone-{}", i),
+                polygon,
+                severity: WeatherSeverity::Severe,
+                valid_from: start,
+                valid_until: start + chrono::Duration::days(1),
+            })
+            .collect::<Vec<_>>();
+        let nodes = generate_nodes_near_with_rng(
+            &mut rng,
+            &Location {
+                latitude: OrderedFloat(37.6),
+                longitude: OrderedFloat(-122.1),
+                altitude_meters: OrderedFloat(0.0),
+            },
+            Distance::from_km(40.0),
+            50,
+        )
+        .into_iter()
+        .filter(|node| !cells.iter().any(|cell| cell.contains(&node.location)))
+        .collect::<Vec<_>>();
+        let distance = |from: &dyn crate::node::AsNode, to: &dyn crate::node::AsNode| {
+            haversine::distance(&from.as_node().location, &to.as_node().location)
+        };
+        let router = Router::new(&nodes, 30.0, distance, distance);
+        let mut reachable = 0;
+        for to in &nodes[1..] {
+            let (_, path) = router
+                .find_shortest_path_with_penalty(&nodes[0], to, |from, to| {
+                    weather_penalty(&cells, &from.location, &to.location)
+                })
+                .unwrap();
+            if !path.is_empty() {
+                reachable += 1;
+            }
+            for leg in path.windows(2) {
+                let (from, to) = (router.get_node_by_id(leg[0]), router.get_node_by_id(leg[1]));
+                assert!(!cells.iter().any(
+                    |cell| cell.intersects_leg(&from.unwrap().location, &to.unwrap().location)
+                ));
+            }
+        }
+        assert!(reachable > nodes.len() / 2);
+    }
+
+    #[test]
+    fn test_generate_random_nodes_near() {
+        let location = generate_location();
+        let nodes = generate_nodes_near(&location, Distance::from_km(10.0), 100);
+        assert_eq!(nodes.len(), 100);
+        for node in nodes {
+            assert!(haversine::distance(&location, &node.location) <= 10.0);
+        }
+    }
 }
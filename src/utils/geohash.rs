@@ -0,0 +1,202 @@
+//! Geohash encoding of locations, for bucketing nodes and partitioned ids.
+//!
+//! A geohash interleaves the bits of the longitude and latitude of a
+//! location into a base 32 string, so locations sharing a prefix lie in the
+//! same cell, one more character splitting a cell in 32. See
+//! [Wikipedia](https://en.wikipedia.org/wiki/Geohash) for more.
+//!
+//! As the [`Node`] uid docs suggest, [`partitioned_uid`] builds ids of the
+//! form `region:geohash:id`, so nodes of a region or a cell are selected
+//! by id prefix with [`filter_nodes_by_prefix`], without computing
+//! distances.
+
+use ordered_float::OrderedFloat;
+
+use crate::location::Location;
+use crate::node::Node;
+
+/// Characters of the geohash base 32 alphabet, by value
+const BASE32: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// Longest geohash, a cell of a few centimeters
+pub const MAX_PRECISION: usize = 12;
+
+/// Encodes a location as a geohash
+///
+/// # Arguments
+/// * `location` - the location to encode, its altitude is ignored
+/// * `precision` - number of characters, from 1 to [`MAX_PRECISION`]
+///
+/// # Returns
+/// The geohash of the cell containing the location, clamped to the range of
+/// precisions. Coordinates are 32-bit floats, precise to about a meter, so
+/// the characters after the 9th, for cells under 5 meters, aren't reliable.
+pub fn encode(location: &Location, precision: usize) -> String {
+    let precision = precision.clamp(1, MAX_PRECISION);
+    let latitude = location.latitude.into_inner() as f64;
+    let longitude = location.longitude.into_inner() as f64;
+    let (mut latitude_range, mut longitude_range) = ((-90.0, 90.0), (-180.0, 180.0));
+    let mut geohash = String::with_capacity(precision);
+    let mut even_bit = true;
+    while geohash.len() < precision {
+        let mut value = 0;
+        for _ in 0..5 {
+            // bits alternate between longitude and latitude, longitude first
+            let (range, coordinate) = if even_bit {
+                (&mut longitude_range, longitude)
+            } else {
+                (&mut latitude_range, latitude)
+            };
+            let middle = (range.0 + range.1) / 2.0;
+            value <<= 1;
+            if coordinate >= middle {
+                value |= 1;
+                range.0 = middle;
+            } else {
+                range.1 = middle;
+            }
+            even_bit = !even_bit;
+        }
+        geohash.push(BASE32[value] as char);
+    }
+    geohash
+}
+
+/// Decodes a geohash into the corners of its cell
+///
+/// # Returns
+/// The south-west and north-east corners of the cell, on the ground, or an
+/// error if the geohash is empty, longer than [`MAX_PRECISION`] or has a
+/// character out of the alphabet.
+pub fn decode_bounds(geohash: &str) -> Result<(Location, Location), String> {
+    if geohash.is_empty() || geohash.len() > MAX_PRECISION {
+        return Err(format!("Invalid geohash length: {}", geohash));
+    }
+    let (mut latitude_range, mut longitude_range) = ((-90.0, 90.0), (-180.0, 180.0));
+    let mut even_bit = true;
+    for character in geohash.bytes() {
+        let value = BASE32
+            .iter()
+            .position(|&base32| base32 == character.to_ascii_lowercase())
+            .ok_or_else(|| format!("Invalid geohash character in: {}", geohash))?;
+        for bit in (0..5).rev() {
+            let range: &mut (f64, f64) = if even_bit {
+                &mut longitude_range
+            } else {
+                &mut latitude_range
+            };
+            let middle = (range.0 + range.1) / 2.0;
+            if value >> bit & 1 == 1 {
+                range.0 = middle;
+            } else {
+                range.1 = middle;
+            }
+            even_bit = !even_bit;
+        }
+    }
+    let corner = |latitude: f64, longitude: f64| Location {
+        latitude: OrderedFloat(latitude as f32),
+        longitude: OrderedFloat(longitude as f32),
+        altitude_meters: OrderedFloat(0.0),
+    };
+    Ok((
+        corner(latitude_range.0, longitude_range.0),
+        corner(latitude_range.1, longitude_range.1),
+    ))
+}
+
+/// Decodes a geohash into the center of its cell
+///
+/// See [`decode_bounds`] for the errors.
+pub fn decode(geohash: &str) -> Result<Location, String> {
+    let (south_west, north_east) = decode_bounds(geohash)?;
+    Ok(Location {
+        latitude: (south_west.latitude + north_east.latitude) / 2.0,
+        longitude: (south_west.longitude + north_east.longitude) / 2.0,
+        altitude_meters: OrderedFloat(0.0),
+    })
+}
+
+/// Builds a partitioned synthetic uid of the form `region:geohash:id`
+///
+/// # Arguments
+/// * `region` - the region of the node, e.g. `usa:ny`
+/// * `location` - the location of the node
+/// * `precision` - number of characters of the geohash, see [`encode`]
+/// * `id` - the id of the node unique within its cell, e.g. a UUID
+pub fn partitioned_uid(region: &str, location: &Location, precision: usize, id: &str) -> String {
+    format!("{}:{}:{}", region, encode(location, precision), id)
+}
+
+/// Selects the nodes with an uid starting with the prefix, e.g. the nodes of
+/// a region or of a geohash cell of a region with partitioned uids
+pub fn filter_nodes_by_prefix<'a>(nodes: &'a [Node], prefix: &str) -> Vec<&'a Node> {
+    nodes
+        .iter()
+        .filter(|node| node.uid.starts_with(prefix))
+        .collect()
+}
+
+/// Selects the nodes located in the geohash cell, whatever their uid
+pub fn filter_nodes_by_geohash<'a>(nodes: &'a [Node], geohash: &str) -> Vec<&'a Node> {
+    let precision = geohash.len();
+    nodes
+        .iter()
+        .filter(|node| encode(&node.location, precision).eq_ignore_ascii_case(geohash))
+        .collect()
+}
+
+#[cfg(test)]
+mod geohash_tests {
+    use super::*;
+    use crate::test_support::mock_location;
+
+    #[test]
+    fn test_encode_decode() {
+        assert_eq!(encode(&mock_location(42.6, -5.6), 5), "ezs42");
+        assert_eq!(encode(&mock_location(57.64911, 10.40744), 9), "u4pruydqq");
+        assert_eq!(encode(&mock_location(37.7749, -122.4194), 0), "9");
+        assert_eq!(
+            encode(&mock_location(37.7749, -122.4194), 20).len(),
+            MAX_PRECISION
+        );
+
+        let center = decode("ezs42").unwrap();
+        assert!((center.latitude.into_inner() - 42.605).abs() < 0.001);
+        assert!((center.longitude.into_inner() + 5.603).abs() < 0.001);
+        let (south_west, north_east) = decode_bounds("EZS42").unwrap();
+        assert!(south_west.latitude < center.latitude && center.latitude < north_east.latitude);
+
+        let san_francisco = mock_location(37.7749, -122.4194);
+        let decoded = decode(&encode(&san_francisco, 9)).unwrap();
+        assert!((decoded.latitude.into_inner() - 37.7749).abs() < 0.0001);
+        assert!((decoded.longitude.into_inner() + 122.4194).abs() < 0.0001);
+
+        assert!(decode("").is_err());
+        assert!(decode("ezs4a").is_err());
+        assert!(decode("ezs42ezs42ezs").is_err());
+    }
+
+    #[test]
+    fn test_partitioned_uids() {
+        let uid = partitioned_uid("usa:ca", &mock_location(37.7749, -122.4194), 5, "1234");
+        assert_eq!(uid, "usa:ca:9q8yy:1234");
+
+        let nodes = vec![
+            Node::new(uid, 37.7749, -122.4194),
+            Node::new(
+                partitioned_uid("usa:ca", &mock_location(34.0522, -118.2437), 5, "5678"),
+                34.0522,
+                -118.2437,
+            ),
+            Node::new("usa:ny:dr5re:9012", 40.7128, -74.006),
+        ];
+        assert_eq!(filter_nodes_by_prefix(&nodes, "usa:ca:").len(), 2);
+        assert_eq!(filter_nodes_by_prefix(&nodes, "usa:ca:9q8").len(), 1);
+        assert_eq!(filter_nodes_by_prefix(&nodes, "usa:").len(), 3);
+
+        let san_francisco = filter_nodes_by_geohash(&nodes, "9q8");
+        assert_eq!(san_francisco, vec![&nodes[0]]);
+        assert!(filter_nodes_by_geohash(&nodes, "u4").is_empty());
+    }
+}
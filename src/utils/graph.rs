@@ -55,6 +55,7 @@ pub fn build_edges(
 #[cfg(test)]
 mod tests {
     use crate::{
+        distance::Distance,
         generator::{generate_location, generate_nodes_near},
         haversine,
     };
@@ -65,7 +66,7 @@ mod tests {
     fn test_build_edges() {
         let capacity = 1000;
         let location = generate_location();
-        let nodes = generate_nodes_near(&location, 1000.0, capacity);
+        let nodes = generate_nodes_near(&location, Distance::from_km(1000.0), capacity);
 
         // set constraint to 2000 so that all nodes should be connected
         let edges = build_edges(
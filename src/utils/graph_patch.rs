@@ -0,0 +1,322 @@
+//! Versioned snapshots of the graph of the router and patches between them.
+//!
+//! When vertiports change in storage, rebuilding every router instance is
+//! slow and drops their caches. A [`GraphSnapshot`] records the nodes and
+//! edges of a graph at a version, and [`GraphPatch::between`] the changes
+//! from one snapshot to the next. Patches are serializable, so instances
+//! applying the same patches with
+//! [`Router::apply_patch`](crate::router::engine::Router::apply_patch)
+//! converge without a full rebuild, and route caches only need to drop the
+//! routes through [`GraphPatch::affected_node_ids`].
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::graph::build_edges;
+use crate::node::{AsNode, Node};
+use crate::router::engine::Router;
+
+/// Edge of a snapshot between two nodes by id
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EdgeRecord {
+    /// id of the node the edge starts from
+    pub from_id: String,
+    /// id of the node the edge ends at
+    pub to_id: String,
+    /// weight of the edge
+    pub cost: f32,
+}
+
+/// Nodes and edges of a graph at a version, sorted by id
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct GraphSnapshot {
+    /// version of the graph
+    pub version: u64,
+    /// nodes by id
+    pub nodes: Vec<Node>,
+    /// edges by id of their nodes
+    pub edges: Vec<EdgeRecord>,
+}
+
+impl GraphSnapshot {
+    fn sorted(mut self) -> Self {
+        self.nodes.sort_by(|a, b| a.uid.cmp(&b.uid));
+        self.edges
+            .sort_by(|a, b| (&a.from_id, &a.to_id).cmp(&(&b.from_id, &b.to_id)));
+        self
+    }
+
+    /// Records the graph of a router at its current version
+    pub fn from_router(router: &Router) -> Self {
+        GraphSnapshot {
+            version: router.get_version(),
+            nodes: router
+                .node_indices
+                .keys()
+                .map(|&node| node.clone())
+                .collect(),
+            edges: router
+                .edges()
+                .map(|edge| EdgeRecord {
+                    from_id: edge.from_id.to_string(),
+                    to_id: edge.to_id.to_string(),
+                    cost: edge.cost,
+                })
+                .collect(),
+        }
+        .sorted()
+    }
+
+    /// Records the graph the router would build from the nodes, see
+    /// [`Router::new`](crate::router::engine::Router::new) for the arguments
+    pub fn from_nodes(
+        version: u64,
+        nodes: &[Node],
+        constraint: f32,
+        constraint_function: fn(&dyn AsNode, &dyn AsNode) -> f32,
+        cost_function: fn(&dyn AsNode, &dyn AsNode) -> f32,
+    ) -> Self {
+        GraphSnapshot {
+            version,
+            nodes: nodes.to_vec(),
+            edges: build_edges(nodes, constraint, constraint_function, cost_function)
+                .into_iter()
+                .map(|edge| EdgeRecord {
+                    from_id: edge.from.uid.clone(),
+                    to_id: edge.to.uid.clone(),
+                    cost: edge.cost.into_inner(),
+                })
+                .collect(),
+        }
+        .sorted()
+    }
+
+    /// Applies a patch made from this version, see [`Router::apply_patch`]
+    pub fn apply(&mut self, patch: &GraphPatch) -> Result<(), String> {
+        if patch.from_version != self.version {
+            return Err(format!(
+                "Patch from version {} can't be applied to version {}",
+                patch.from_version, self.version
+            ));
+        }
+        let mut nodes: HashMap<String, Node> = self
+            .nodes
+            .drain(..)
+            .map(|node| (node.uid.clone(), node))
+            .collect();
+        for id in &patch.removed_node_ids {
+            nodes.remove(id);
+        }
+        for node in patch.added_nodes.iter().chain(&patch.changed_nodes) {
+            nodes.insert(node.uid.clone(), node.clone());
+        }
+        let mut edges: HashMap<(String, String), f32> = self
+            .edges
+            .drain(..)
+            .filter(|edge| nodes.contains_key(&edge.from_id) && nodes.contains_key(&edge.to_id))
+            .map(|edge| ((edge.from_id, edge.to_id), edge.cost))
+            .collect();
+        for leg in &patch.removed_edges {
+            edges.remove(leg);
+        }
+        for edge in &patch.changed_edges {
+            edges.insert((edge.from_id.clone(), edge.to_id.clone()), edge.cost);
+        }
+        self.nodes = nodes.into_values().collect();
+        self.edges = edges
+            .into_iter()
+            .map(|((from_id, to_id), cost)| EdgeRecord {
+                from_id,
+                to_id,
+                cost,
+            })
+            .collect();
+        self.version = patch.to_version;
+        *self = std::mem::take(self).sorted();
+        Ok(())
+    }
+}
+
+/// Changes from a version of a graph to another one
+///
+/// Edges of removed nodes are removed with them. Edges of added nodes are
+/// listed in the changed edges.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct GraphPatch {
+    /// version the patch applies to
+    pub from_version: u64,
+    /// version of the graph once patched
+    pub to_version: u64,
+    /// new nodes
+    pub added_nodes: Vec<Node>,
+    /// nodes with a new location, status or schedule, by id
+    pub changed_nodes: Vec<Node>,
+    /// ids of the removed nodes
+    pub removed_node_ids: Vec<String>,
+    /// new edges and edges with a new cost
+    pub changed_edges: Vec<EdgeRecord>,
+    /// ids of the nodes of the removed edges between remaining nodes
+    pub removed_edges: Vec<(String, String)>,
+}
+
+impl GraphPatch {
+    /// Computes the changes from the `old` snapshot to the `new` one
+    pub fn between(old: &GraphSnapshot, new: &GraphSnapshot) -> Self {
+        let old_nodes: HashMap<&str, &Node> = old
+            .nodes
+            .iter()
+            .map(|node| (node.uid.as_str(), node))
+            .collect();
+        let new_nodes: HashSet<&str> = new.nodes.iter().map(|node| node.uid.as_str()).collect();
+        let old_edges: HashMap<(&str, &str), f32> = old
+            .edges
+            .iter()
+            .map(|edge| ((edge.from_id.as_str(), edge.to_id.as_str()), edge.cost))
+            .collect();
+        let new_edges: HashSet<(&str, &str)> = new
+            .edges
+            .iter()
+            .map(|edge| (edge.from_id.as_str(), edge.to_id.as_str()))
+            .collect();
+
+        let mut patch = GraphPatch {
+            from_version: old.version,
+            to_version: new.version,
+            ..Default::default()
+        };
+        for node in &new.nodes {
+            match old_nodes.get(node.uid.as_str()) {
+                None => patch.added_nodes.push(node.clone()),
+                Some(&old_node) if old_node != node => patch.changed_nodes.push(node.clone()),
+                Some(_) => (),
+            }
+        }
+        patch.removed_node_ids = old
+            .nodes
+            .iter()
+            .filter(|node| !new_nodes.contains(node.uid.as_str()))
+            .map(|node| node.uid.clone())
+            .collect();
+        patch.changed_edges = new
+            .edges
+            .iter()
+            .filter(|edge| {
+                old_edges.get(&(edge.from_id.as_str(), edge.to_id.as_str())) != Some(&edge.cost)
+            })
+            .cloned()
+            .collect();
+        patch.removed_edges = old
+            .edges
+            .iter()
+            .filter(|edge| {
+                new_nodes.contains(edge.from_id.as_str())
+                    && new_nodes.contains(edge.to_id.as_str())
+                    && !new_edges.contains(&(edge.from_id.as_str(), edge.to_id.as_str()))
+            })
+            .map(|edge| (edge.from_id.clone(), edge.to_id.clone()))
+            .collect();
+        debug!(
+            "Graph patch from version {} to {}: {} nodes added, {} changed, {} removed",
+            patch.from_version,
+            patch.to_version,
+            patch.added_nodes.len(),
+            patch.changed_nodes.len(),
+            patch.removed_node_ids.len()
+        );
+        patch
+    }
+
+    /// Checks if the patch changes nothing
+    pub fn is_empty(&self) -> bool {
+        self.added_nodes.is_empty()
+            && self.changed_nodes.is_empty()
+            && self.removed_node_ids.is_empty()
+            && self.changed_edges.is_empty()
+            && self.removed_edges.is_empty()
+    }
+
+    /// Ids of the nodes added, changed or removed, or with a changed edge;
+    /// cached routes through other nodes are still valid
+    pub fn affected_node_ids(&self) -> HashSet<String> {
+        self.added_nodes
+            .iter()
+            .chain(&self.changed_nodes)
+            .map(|node| node.uid.clone())
+            .chain(self.removed_node_ids.iter().cloned())
+            .chain(
+                self.changed_edges
+                    .iter()
+                    .flat_map(|edge| [edge.from_id.clone(), edge.to_id.clone()]),
+            )
+            .chain(
+                self.removed_edges
+                    .iter()
+                    .flat_map(|(from_id, to_id)| [from_id.clone(), to_id.clone()]),
+            )
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod graph_patch_tests {
+    use super::*;
+    use crate::haversine;
+    use crate::status::Status;
+
+    fn node(uid: &str, longitude: f32) -> Node {
+        Node::new(uid, 0.0, longitude)
+    }
+
+    fn distance(from: &dyn AsNode, to: &dyn AsNode) -> f32 {
+        haversine::distance(&from.as_node().location, &to.as_node().location)
+    }
+
+    #[test]
+    fn test_patch_router() {
+        let old_nodes = vec![node("A", 0.0), node("B", 0.3), node("C", 0.6)];
+        let mut closed = node("B", 0.3);
+        closed.status = Status::Closed;
+        // C moves out of range of A, D is new, A is removed
+        let new_nodes = vec![closed, node("C", 0.9), node("D", 1.1)];
+
+        let mut router = Router::new(&old_nodes, 40.0, distance, distance);
+        let old = GraphSnapshot::from_router(&router);
+        assert_eq!(old.version, 0);
+        assert_eq!(old.edges.len(), 4);
+        let new = GraphSnapshot::from_nodes(1, &new_nodes, 40.0, distance, distance);
+
+        let patch = GraphPatch::between(&old, &new);
+        assert_eq!(patch.added_nodes, vec![node("D", 1.1)]);
+        assert_eq!(patch.changed_nodes.len(), 2);
+        assert_eq!(patch.removed_node_ids, vec!["A"]);
+        assert_eq!(
+            patch.removed_edges,
+            vec![
+                ("B".to_string(), "C".to_string()),
+                ("C".to_string(), "B".to_string())
+            ]
+        );
+        assert_eq!(patch.changed_edges.len(), 2);
+        assert_eq!(
+            patch.affected_node_ids(),
+            HashSet::from(["A", "B", "C", "D"].map(String::from))
+        );
+
+        let mut snapshot = old.clone();
+        snapshot.apply(&patch).unwrap();
+        assert_eq!(snapshot, new);
+
+        router.apply_patch(&patch).unwrap();
+        assert_eq!(router.get_version(), 1);
+        assert_eq!(GraphSnapshot::from_router(&router), new);
+        let (_, path) = router
+            .find_shortest_path_with_penalty(&new_nodes[1], &new_nodes[2], |_, _| Some(0.0))
+            .unwrap();
+        assert_eq!(path.len(), 2);
+
+        // the patch was already applied
+        assert!(router.apply_patch(&patch).is_err());
+        assert!(snapshot.apply(&patch).is_err());
+        assert!(GraphPatch::between(&new, &new).is_empty());
+    }
+}
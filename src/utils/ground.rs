@@ -0,0 +1,280 @@
+//! First and last mile of a trip on the ground.
+//!
+//! [`get_nearest_vertiports`](crate::router_state::get_nearest_vertiports)
+//! picks the vertiports closest to the customer locations in a straight
+//! line, although a vertiport a little farther away may be quicker to reach
+//! by road. A [`GroundLegEstimator`], e.g. backed by a routing service for
+//! walking or driving, estimates the time to and from the vertiports, and
+//! [`select_vertiports_by_time`] picks the pair of vertiports with the
+//! shortest door-to-door time among the nearest candidates.
+
+use std::sync::Arc;
+
+use crate::context::current_context;
+use crate::haversine;
+use crate::location::Location;
+use crate::node::Node;
+use crate::router_state::{estimate_flight_time_minutes, Aircraft};
+use crate::spatial::k_nearest_nodes;
+
+/// Vertiports nearest to each end of a trip considered for the ground legs
+pub const GROUND_LEG_CANDIDATES: usize = 5;
+
+/// Estimates the time of a trip on the ground
+pub trait GroundLegEstimator: Send + Sync {
+    /// Estimates the minutes from `from` to `to` on the ground, None if
+    /// `to` can't be reached
+    fn ground_minutes(&self, from: &Location, to: &Location) -> Option<f32>;
+}
+
+/// Ground legs in a straight line at a constant speed, lengthened by a
+/// detour factor for the road network
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StraightLineGround {
+    /// average speed on the ground
+    pub speed_kmh: f32,
+    /// ratio of the road distance to the straight line distance
+    pub detour_factor: f32,
+}
+
+impl StraightLineGround {
+    /// Walking at 5 km/h
+    pub fn walking() -> Self {
+        StraightLineGround {
+            speed_kmh: 5.0,
+            detour_factor: 1.3,
+        }
+    }
+
+    /// Driving at 30 km/h in a city
+    pub fn driving() -> Self {
+        StraightLineGround {
+            speed_kmh: 30.0,
+            detour_factor: 1.4,
+        }
+    }
+}
+
+impl GroundLegEstimator for StraightLineGround {
+    fn ground_minutes(&self, from: &Location, to: &Location) -> Option<f32> {
+        (self.speed_kmh > 0.0)
+            .then(|| haversine::distance(from, to) * self.detour_factor / self.speed_kmh * 60.0)
+    }
+}
+
+/// Vertiports of a trip with the estimated time of each leg
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DoorToDoor<'a> {
+    /// vertiport the cargo is brought to
+    pub departure: &'a Node,
+    /// vertiport the cargo is picked up from
+    pub arrival: &'a Node,
+    /// minutes on the ground from the origin to the departure vertiport
+    pub first_mile_minutes: f32,
+    /// minutes of the flight between the vertiports
+    pub flight_minutes: f32,
+    /// minutes on the ground from the arrival vertiport to the destination
+    pub last_mile_minutes: f32,
+}
+
+impl DoorToDoor<'_> {
+    /// Minutes from the origin to the destination
+    pub fn total_minutes(&self) -> f32 {
+        self.first_mile_minutes + self.flight_minutes + self.last_mile_minutes
+    }
+}
+
+/// Picks the vertiports with the shortest door-to-door time
+///
+/// The [`GROUND_LEG_CANDIDATES`] vertiports nearest to each end of the trip
+/// are combined, and flights between them are estimated along the straight
+/// line between the vertiports.
+///
+/// # Arguments
+/// * `estimator` - estimator of the ground legs
+/// * `src_location` - origin of the trip, e.g. the customer
+/// * `dst_location` - destination of the trip
+/// * `vertiports` - vertiports of the network
+///
+/// # Returns
+/// The quickest pair of distinct vertiports, `None` if no pair is reachable
+/// on the ground
+pub fn select_vertiports_by_time_with<'a>(
+    estimator: &dyn GroundLegEstimator,
+    src_location: &Location,
+    dst_location: &Location,
+    vertiports: &'a [Node],
+) -> Option<DoorToDoor<'a>> {
+    let first_miles: Vec<(&Node, f32)> =
+        k_nearest_nodes(src_location, GROUND_LEG_CANDIDATES, vertiports)
+            .into_iter()
+            .filter_map(|(vertiport, _)| {
+                Some((
+                    vertiport,
+                    estimator.ground_minutes(src_location, &vertiport.location)?,
+                ))
+            })
+            .collect();
+    let last_miles: Vec<(&Node, f32)> =
+        k_nearest_nodes(dst_location, GROUND_LEG_CANDIDATES, vertiports)
+            .into_iter()
+            .filter_map(|(vertiport, _)| {
+                Some((
+                    vertiport,
+                    estimator.ground_minutes(&vertiport.location, dst_location)?,
+                ))
+            })
+            .collect();
+    let mut best: Option<DoorToDoor> = None;
+    for &(departure, first_mile_minutes) in &first_miles {
+        for &(arrival, last_mile_minutes) in &last_miles {
+            if departure.uid == arrival.uid {
+                continue;
+            }
+            let trip = DoorToDoor {
+                departure,
+                arrival,
+                first_mile_minutes,
+                flight_minutes: estimate_flight_time_minutes(
+                    haversine::distance(&departure.location, &arrival.location),
+                    Aircraft::Cargo,
+                ),
+                last_mile_minutes,
+            };
+            if best.is_none_or(|best| trip.total_minutes() < best.total_minutes()) {
+                best = Some(trip);
+            }
+        }
+    }
+    debug!("Quickest door-to-door vertiports: {:?}", best);
+    best
+}
+
+/// Picks the vertiports with the shortest door-to-door time with the ground
+/// leg estimator, or driving in a straight line if none is set
+/// See [`select_vertiports_by_time_with`]
+pub fn select_vertiports_by_time<'a>(
+    src_location: &Location,
+    dst_location: &Location,
+    vertiports: &'a [Node],
+) -> Option<DoorToDoor<'a>> {
+    match get_ground_leg_estimator() {
+        Some(estimator) => select_vertiports_by_time_with(
+            estimator.as_ref(),
+            src_location,
+            dst_location,
+            vertiports,
+        ),
+        None => select_vertiports_by_time_with(
+            &StraightLineGround::driving(),
+            src_location,
+            dst_location,
+            vertiports,
+        ),
+    }
+}
+
+/// Sets the estimator of the ground legs
+pub fn set_ground_leg_estimator(estimator: Arc<dyn GroundLegEstimator>) {
+    match current_context().ground_leg_estimator.write() {
+        Ok(mut current) => *current = Some(estimator),
+        Err(_) => error!("Ground leg estimator unavailable"),
+    }
+}
+
+/// Goes back to driving in a straight line for the ground legs
+pub fn clear_ground_leg_estimator() {
+    match current_context().ground_leg_estimator.write() {
+        Ok(mut current) => *current = None,
+        Err(_) => error!("Ground leg estimator unavailable"),
+    }
+}
+
+/// Gets the estimator of the ground legs, if any
+pub fn get_ground_leg_estimator() -> Option<Arc<dyn GroundLegEstimator>> {
+    current_context()
+        .ground_leg_estimator
+        .read()
+        .map(|estimator| estimator.clone())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod ground_tests {
+    use super::*;
+    use crate::test_support::mock_location;
+
+    /// Driving, except across the river at longitude 0.05 which takes an hour
+    struct RiverCrossing;
+
+    impl GroundLegEstimator for RiverCrossing {
+        fn ground_minutes(&self, from: &Location, to: &Location) -> Option<f32> {
+            let minutes = StraightLineGround::driving().ground_minutes(from, to)?;
+            let crosses =
+                (from.longitude.into_inner() < 0.05) != (to.longitude.into_inner() < 0.05);
+            Some(if crosses { minutes + 60.0 } else { minutes })
+        }
+    }
+
+    fn vertiports() -> Vec<Node> {
+        vec![
+            // nearest to the origin, but across the river
+            Node::new("ground-A", 0.0, 0.06),
+            Node::new("ground-B", 0.0, -0.03),
+            Node::new("ground-C", 0.0, 1.0),
+        ]
+    }
+
+    #[test]
+    fn test_select_vertiports_by_time() {
+        let (origin, destination) = (mock_location(0.0, 0.04), mock_location(0.0, 1.01));
+        let vertiports = vertiports();
+
+        let trip = select_vertiports_by_time_with(
+            &StraightLineGround::driving(),
+            &origin,
+            &destination,
+            &vertiports,
+        )
+        .unwrap();
+        assert_eq!(
+            (trip.departure.uid.as_str(), trip.arrival.uid.as_str()),
+            ("ground-A", "ground-C")
+        );
+
+        let trip =
+            select_vertiports_by_time_with(&RiverCrossing, &origin, &destination, &vertiports)
+                .unwrap();
+        assert_eq!(
+            (trip.departure.uid.as_str(), trip.arrival.uid.as_str()),
+            ("ground-B", "ground-C")
+        );
+        assert!(trip.first_mile_minutes < 60.0);
+        assert_eq!(
+            trip.total_minutes(),
+            trip.first_mile_minutes + trip.flight_minutes + trip.last_mile_minutes
+        );
+    }
+
+    #[test]
+    fn test_unreachable_ground_legs() {
+        let stranded = StraightLineGround {
+            speed_kmh: 0.0,
+            detour_factor: 1.0,
+        };
+        assert!(select_vertiports_by_time_with(
+            &stranded,
+            &mock_location(0.0, 0.0),
+            &mock_location(0.0, 1.0),
+            &vertiports()
+        )
+        .is_none());
+        assert!(select_vertiports_by_time_with(
+            &StraightLineGround::walking(),
+            &mock_location(0.0, 0.0),
+            &mock_location(0.0, 1.0),
+            &vertiports()[..1]
+        )
+        .is_none());
+    }
+}
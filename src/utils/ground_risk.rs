@@ -0,0 +1,331 @@
+//! Ground risk overlay for routing.
+//!
+//! A [`RiskGrid`] holds a risk score per cell of a regular latitude and
+//! longitude grid, such as the population density of the area or scores
+//! defined by the operator. The risk of a leg is the risk of the cells it
+//! overflies integrated over the distance flown above them, so that
+//! [`distance_with_ground_risk`] can weight the graph of the router to
+//! avoid densely populated areas.
+
+use crate::context::current_context;
+use crate::haversine;
+use crate::location::Location;
+use crate::node::AsNode;
+use ordered_float::OrderedFloat;
+
+/// Risk scores on a regular grid of latitude and longitude cells
+#[derive(Debug, Clone, PartialEq)]
+pub struct RiskGrid {
+    /// latitude of the southern edge of the grid
+    south: f32,
+    /// longitude of the western edge of the grid
+    west: f32,
+    /// width and height of a cell in degrees
+    cell_size_degrees: f32,
+    /// number of cells from west to east
+    columns: usize,
+    /// risk scores row by row, from the southern row to the northern one
+    values: Vec<f32>,
+}
+
+impl RiskGrid {
+    /// Creates a grid from its rows of risk scores
+    ///
+    /// # Arguments
+    /// * `south` - Latitude of the southern edge of the grid
+    /// * `west` - Longitude of the western edge of the grid
+    /// * `cell_size_degrees` - Width and height of a cell in degrees
+    /// * `rows` - Risk scores of the cells, from the southern row to the
+    ///   northern one and from west to east within a row
+    ///
+    /// # Returns
+    /// An error if the rows don't have the same length or a score is negative
+    pub fn new(
+        south: f32,
+        west: f32,
+        cell_size_degrees: f32,
+        rows: Vec<Vec<f32>>,
+    ) -> Result<Self, String> {
+        if cell_size_degrees <= 0.0 {
+            return Err(format!("Invalid cell size: {}", cell_size_degrees));
+        }
+        let columns = rows.first().map(|row| row.len()).unwrap_or(0);
+        if columns == 0 {
+            return Err("Risk grid has no cells".to_string());
+        }
+        if rows.iter().any(|row| row.len() != columns) {
+            return Err("Rows of the risk grid have different lengths".to_string());
+        }
+        let values: Vec<f32> = rows.into_iter().flatten().collect();
+        if values.iter().any(|value| value.is_nan() || *value < 0.0) {
+            return Err("Risk scores can't be negative".to_string());
+        }
+        Ok(RiskGrid {
+            south,
+            west,
+            cell_size_degrees,
+            columns,
+            values,
+        })
+    }
+
+    /// Parses a grid in the ESRI ASCII raster format, in which population
+    /// density layers are commonly distributed
+    ///
+    /// Cells without data have no risk.
+    pub fn from_ascii_grid(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let mut columns = None;
+        let mut rows = None;
+        let mut west = None;
+        let mut south = None;
+        let mut cell_size = None;
+        let mut no_data = None;
+        let mut data: Vec<Vec<f32>> = vec![];
+
+        for line in lines.by_ref() {
+            let mut fields = line.split_whitespace();
+            let key = fields.next().unwrap_or_default().to_lowercase();
+            let value = fields.next();
+            if key.parse::<f32>().is_ok() {
+                data.push(parse_row(line)?);
+                break;
+            }
+            let Some(value) = value else {
+                return Err(format!("Missing value for header {}", key));
+            };
+            let value = value
+                .parse::<f32>()
+                .map_err(|_| format!("Invalid value for header {}: {}", key, value))?;
+            match key.as_str() {
+                "ncols" => columns = Some(value as usize),
+                "nrows" => rows = Some(value as usize),
+                "xllcorner" => west = Some(value),
+                "yllcorner" => south = Some(value),
+                "cellsize" => cell_size = Some(value),
+                "nodata_value" => no_data = Some(value),
+                _ => return Err(format!("Unknown header: {}", key)),
+            }
+        }
+        for line in lines {
+            data.push(parse_row(line)?);
+        }
+
+        let (Some(columns), Some(rows), Some(west), Some(south), Some(cell_size)) =
+            (columns, rows, west, south, cell_size)
+        else {
+            return Err("Incomplete risk grid header".to_string());
+        };
+        if data.len() != rows || data.iter().any(|row| row.len() != columns) {
+            return Err(format!(
+                "Risk grid doesn't have {} rows of {} cells",
+                rows, columns
+            ));
+        }
+
+        // the raster lists the northern row first
+        data.reverse();
+        for value in data.iter_mut().flatten() {
+            if Some(*value) == no_data {
+                *value = 0.0;
+            }
+        }
+        RiskGrid::new(south, west, cell_size, data)
+    }
+
+    /// Risk score of the cell containing the location, 0.0 outside of the grid
+    pub fn risk_at(&self, location: &Location) -> f32 {
+        let row = (location.latitude.into_inner() - self.south) / self.cell_size_degrees;
+        let column = (location.longitude.into_inner() - self.west) / self.cell_size_degrees;
+        if row < 0.0 || column < 0.0 || column >= self.columns as f32 {
+            return 0.0;
+        }
+        self.values
+            .get(row as usize * self.columns + column as usize)
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// Risk of flying straight between two locations, as the sum of the
+    /// risk of each overflown cell times the kilometers flown above it
+    pub fn path_risk(&self, from: &Location, to: &Location) -> f32 {
+        let d_lat = to.latitude.into_inner() - from.latitude.into_inner();
+        let d_lon = to.longitude.into_inner() - from.longitude.into_inner();
+        // sample at least twice per cell crossed
+        let samples =
+            ((d_lat.abs().max(d_lon.abs()) * 2.0 / self.cell_size_degrees).ceil() as usize).max(1);
+        let step_km = haversine::distance(from, to) / samples as f32;
+
+        (0..samples)
+            .map(|i| {
+                let fraction = (i as f32 + 0.5) / samples as f32;
+                let location = Location {
+                    latitude: OrderedFloat(from.latitude.into_inner() + d_lat * fraction),
+                    longitude: OrderedFloat(from.longitude.into_inner() + d_lon * fraction),
+                    altitude_meters: from.altitude_meters,
+                };
+                self.risk_at(&location) * step_km
+            })
+            .sum()
+    }
+}
+
+/// Parses a row of risk scores separated by whitespace
+fn parse_row(line: &str) -> Result<Vec<f32>, String> {
+    line.split_whitespace()
+        .map(|value| {
+            value
+                .parse::<f32>()
+                .map_err(|_| format!("Invalid risk score: {}", value))
+        })
+        .collect()
+}
+
+/// Sets the ground risk layer penalizing the legs of the router
+///
+/// # Arguments
+/// * `grid` - Risk scores of the area
+/// * `km_per_risk` - Extra distance a unit of [`RiskGrid::path_risk`] costs
+pub fn set_ground_risk_layer(grid: RiskGrid, km_per_risk: f32) {
+    match current_context().ground_risk.write() {
+        Ok(mut layer) => *layer = Some((grid, km_per_risk)),
+        Err(_) => error!("Ground risk layer unavailable"),
+    }
+}
+
+/// Removes the ground risk layer, legs are no longer penalized
+pub fn clear_ground_risk_layer() {
+    match current_context().ground_risk.write() {
+        Ok(mut layer) => *layer = None,
+        Err(_) => error!("Ground risk layer unavailable"),
+    }
+}
+
+/// Gets the risk grid of the layer, if any
+pub fn get_ground_risk_grid() -> Option<RiskGrid> {
+    current_context()
+        .ground_risk
+        .read()
+        .map(|layer| layer.as_ref().map(|(grid, _)| grid.clone()))
+        .unwrap_or_default()
+}
+
+/// Cost function component penalizing a leg with the risk of the cells it
+/// overflies, expressed as distance
+pub fn ground_risk_cost(from: &dyn AsNode, to: &dyn AsNode) -> f32 {
+    match current_context().ground_risk.read() {
+        Ok(layer) => layer.as_ref().map_or(0.0, |(grid, km_per_risk)| {
+            grid.path_risk(&from.as_node().location, &to.as_node().location) * km_per_risk
+        }),
+        Err(_) => {
+            error!("Ground risk layer unavailable");
+            0.0
+        }
+    }
+}
+
+/// Cost function weighting each leg with its distance plus its ground risk
+/// penalty, for [`Router::new`](crate::router::engine::Router::new) or
+/// [`init_router_with_cost`](crate::router_state::init_router_with_cost)
+///
+/// The risk is read from the layer when the graph is built, so the layer
+/// must be set before the router is initialized.
+pub fn distance_with_ground_risk(from: &dyn AsNode, to: &dyn AsNode) -> f32 {
+    haversine::distance(&from.as_node().location, &to.as_node().location)
+        + ground_risk_cost(from, to)
+}
+
+#[cfg(test)]
+mod ground_risk_tests {
+    use super::*;
+    use crate::node::Node;
+    use crate::router::engine::{Algorithm, Router};
+    use crate::test_support::mock_location;
+
+    /// 3x3 grid of 0.1 degree cells with a dense center
+    fn grid() -> RiskGrid {
+        RiskGrid::new(
+            -0.15,
+            -0.05,
+            0.1,
+            vec![
+                vec![0.0, 0.0, 0.0],
+                vec![0.0, 5.0, 0.0],
+                vec![0.0, 0.0, 0.0],
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_risk_at() {
+        let grid = grid();
+        assert_eq!(grid.risk_at(&mock_location(-0.05, 0.1)), 5.0);
+        assert_eq!(grid.risk_at(&mock_location(-0.12, 0.1)), 0.0);
+        assert_eq!(grid.risk_at(&mock_location(1.0, 0.1)), 0.0);
+        assert_eq!(grid.risk_at(&mock_location(-0.05, -0.1)), 0.0);
+    }
+
+    #[test]
+    fn test_invalid_grids() {
+        assert!(RiskGrid::new(0.0, 0.0, 0.1, vec![]).is_err());
+        assert!(RiskGrid::new(0.0, 0.0, 0.0, vec![vec![1.0]]).is_err());
+        assert!(RiskGrid::new(0.0, 0.0, 0.1, vec![vec![1.0], vec![1.0, 2.0]]).is_err());
+        assert!(RiskGrid::new(0.0, 0.0, 0.1, vec![vec![-1.0]]).is_err());
+    }
+
+    #[test]
+    fn test_from_ascii_grid() {
+        let text = "ncols 3\nnrows 2\nxllcorner -0.05\nyllcorner -0.15\ncellsize 0.1\nNODATA_value -9999\n1 2 3\n4 -9999 6\n";
+        let grid = RiskGrid::from_ascii_grid(text).unwrap();
+        // the first row of the raster is the northern one
+        assert_eq!(grid.risk_at(&mock_location(0.0, 0.0)), 1.0);
+        assert_eq!(grid.risk_at(&mock_location(-0.1, 0.0)), 4.0);
+        assert_eq!(grid.risk_at(&mock_location(-0.1, 0.1)), 0.0);
+
+        assert!(RiskGrid::from_ascii_grid("ncols 3\nnrows 2\n1 2 3\n").is_err());
+        assert!(RiskGrid::from_ascii_grid(&text.replace("6\n", "")).is_err());
+    }
+
+    #[test]
+    fn test_path_risk() {
+        let grid = grid();
+        let through = grid.path_risk(&mock_location(-0.05, -0.05), &mock_location(-0.05, 0.25));
+        let distance =
+            haversine::distance(&mock_location(-0.05, -0.05), &mock_location(-0.05, 0.25));
+        // a third of the leg is above the dense cell
+        assert!((through - 5.0 * distance / 3.0).abs() < 0.1);
+        assert_eq!(
+            grid.path_risk(&mock_location(-0.12, -0.05), &mock_location(-0.12, 0.25)),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_routes_avoid_risky_cells() {
+        // the direct leg overflies the dense center, the detour doesn't
+        let nodes = vec![
+            Node::new("risk-A", -0.05, -0.04),
+            Node::new("risk-B", -0.12, 0.1),
+            Node::new("risk-C", -0.05, 0.24),
+        ];
+        let route = |cost_function: fn(&dyn AsNode, &dyn AsNode) -> f32| {
+            let router = Router::new(
+                &nodes,
+                100.0,
+                |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+                cost_function,
+            );
+            let (_, path) = router
+                .find_shortest_path(&nodes[0], &nodes[2], Algorithm::Dijkstra, None)
+                .unwrap();
+            path.len()
+        };
+
+        set_ground_risk_layer(grid(), 1.0);
+        let with_risk = route(distance_with_ground_risk);
+        clear_ground_risk_layer();
+        assert_eq!(with_risk, 3);
+        assert_eq!(route(distance_with_ground_risk), 2);
+    }
+}
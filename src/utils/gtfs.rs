@@ -0,0 +1,322 @@
+//! GTFS export of scheduled network flights.
+//!
+//! [`build_gtfs_feed`] turns the published flight plans of the network into
+//! the files of a GTFS feed, so journey planners and analytics tooling can
+//! consume the flight network like any transit network:
+//! * `agency.txt` - the operator of the network
+//! * `stops.txt` - the vertiports served
+//! * `routes.txt` - one route per pair of departure and destination vertiports
+//! * `trips.txt` - one trip per flight plan
+//! * `stop_times.txt` - the departure and arrival of each trip
+//! * `calendar_dates.txt` - the days each trip is flown
+//!
+//! Like on-demand services of GTFS-Flex, cargo must be booked with the
+//! operator, so the pickups and drop-offs of the stop times are marked as
+//! arranged with the agency. Times are in the timezone of the agency and may
+//! exceed `24:00:00` for flights arriving after midnight.
+
+use chrono::{DateTime, NaiveDate, TimeZone};
+use rrule::Tz;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::amendment::{timestamp_to_datetime, PlanTimes};
+use crate::node::Node;
+use crate::router_state::FlightPlan;
+
+/// GTFS route type of air services
+const AIR_SERVICE_ROUTE_TYPE: u32 = 1100;
+/// GTFS pickup and drop-off type of stops arranged with the agency
+const ARRANGED_WITH_AGENCY: u32 = 2;
+
+/// Operator of the network
+#[derive(Debug, Clone)]
+pub struct GtfsAgency {
+    /// id of the agency
+    pub id: String,
+    /// name of the agency
+    pub name: String,
+    /// website of the agency
+    pub url: String,
+    /// timezone of the times of the feed
+    pub timezone: Tz,
+}
+
+/// Files of a GTFS feed, as CSV documents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GtfsFeed {
+    /// `agency.txt`
+    pub agency: String,
+    /// `stops.txt`
+    pub stops: String,
+    /// `routes.txt`
+    pub routes: String,
+    /// `trips.txt`
+    pub trips: String,
+    /// `stop_times.txt`
+    pub stop_times: String,
+    /// `calendar_dates.txt`
+    pub calendar_dates: String,
+}
+
+impl GtfsFeed {
+    /// Lists the files of the feed with their names
+    pub fn files(&self) -> Vec<(&'static str, &str)> {
+        vec![
+            ("agency.txt", &self.agency),
+            ("stops.txt", &self.stops),
+            ("routes.txt", &self.routes),
+            ("trips.txt", &self.trips),
+            ("stop_times.txt", &self.stop_times),
+            ("calendar_dates.txt", &self.calendar_dates),
+        ]
+    }
+}
+
+/// Formats a CSV row, quoting the fields containing separators or quotes
+fn csv_row(fields: &[&str]) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+        + "\n"
+}
+
+/// Formats the time elapsed since the midnight of the service date, e.g. `25:10:00`
+fn gtfs_time(time: DateTime<Tz>, service_date: NaiveDate, timezone: &Tz) -> String {
+    let midnight = timezone
+        .from_local_datetime(&service_date.and_hms_opt(0, 0, 0).unwrap_or_default())
+        .earliest()
+        .unwrap_or(time);
+    let seconds = (time - midnight).num_seconds();
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Builds the GTFS feed of scheduled flights
+///
+/// # Arguments
+/// * `agency` - Operator of the network
+/// * `nodes` - Vertiports of the network
+/// * `flight_plans` - Published flight plans
+///
+/// # Returns
+/// The files of the feed. Flight plans without times or between vertiports
+/// missing from `nodes` are left out.
+pub fn build_gtfs_feed(
+    agency: &GtfsAgency,
+    nodes: &[Node],
+    flight_plans: &[FlightPlan],
+) -> GtfsFeed {
+    let nodes_by_id: HashMap<&str, &Node> =
+        nodes.iter().map(|node| (node.uid.as_str(), node)).collect();
+    let mut stop_ids: BTreeSet<&str> = BTreeSet::new();
+    let mut routes: BTreeMap<String, (String, String)> = BTreeMap::new();
+    let mut service_dates: BTreeSet<NaiveDate> = BTreeSet::new();
+    let mut trips = csv_row(&["route_id", "service_id", "trip_id"]);
+    let mut stop_times = csv_row(&[
+        "trip_id",
+        "arrival_time",
+        "departure_time",
+        "stop_id",
+        "stop_sequence",
+        "pickup_type",
+        "drop_off_type",
+    ]);
+
+    let mut plans: Vec<PlanTimes> = flight_plans
+        .iter()
+        .filter_map(|flight_plan| PlanTimes::from_flight_plan(flight_plan).ok())
+        .collect();
+    plans.sort_by_key(|plan| (plan.departure, plan.id.clone()));
+    for plan in plans {
+        let (Some(departure), Some(destination)) = (
+            nodes_by_id.get(plan.departure_vertiport_id.as_str()),
+            nodes_by_id.get(plan.destination_vertiport_id.as_str()),
+        ) else {
+            debug!("Flight plan {} is not between known vertiports", plan.id);
+            continue;
+        };
+        stop_ids.insert(&departure.uid);
+        stop_ids.insert(&destination.uid);
+        let route_id = format!("{}_{}", departure.uid, destination.uid);
+        routes
+            .entry(route_id.clone())
+            .or_insert_with(|| (departure.uid.clone(), destination.uid.clone()));
+
+        let departure_time = timestamp_to_datetime(plan.departure).with_timezone(&agency.timezone);
+        let arrival_time = timestamp_to_datetime(plan.arrival).with_timezone(&agency.timezone);
+        let service_date = departure_time.date_naive();
+        service_dates.insert(service_date);
+        let service_id = service_date.format("%Y%m%d").to_string();
+        let departs = gtfs_time(departure_time, service_date, &agency.timezone);
+        let arrives = gtfs_time(arrival_time, service_date, &agency.timezone);
+        let arranged = ARRANGED_WITH_AGENCY.to_string();
+
+        trips += &csv_row(&[&route_id, &service_id, &plan.id]);
+        stop_times += &csv_row(&[
+            &plan.id,
+            &departs,
+            &departs,
+            &departure.uid,
+            "1",
+            &arranged,
+            "1",
+        ]);
+        stop_times += &csv_row(&[
+            &plan.id,
+            &arrives,
+            &arrives,
+            &destination.uid,
+            "2",
+            "1",
+            &arranged,
+        ]);
+    }
+
+    let mut stops = csv_row(&["stop_id", "stop_name", "stop_lat", "stop_lon"]);
+    for stop_id in stop_ids {
+        let node = nodes_by_id[stop_id];
+        stops += &csv_row(&[
+            &node.uid,
+            &node.uid,
+            &format!("{:.6}", node.location.latitude.into_inner()),
+            &format!("{:.6}", node.location.longitude.into_inner()),
+        ]);
+    }
+
+    let route_type = AIR_SERVICE_ROUTE_TYPE.to_string();
+    let mut routes_file = csv_row(&[
+        "route_id",
+        "agency_id",
+        "route_short_name",
+        "route_long_name",
+        "route_type",
+    ]);
+    for (route_id, (departure, destination)) in &routes {
+        routes_file += &csv_row(&[
+            route_id,
+            &agency.id,
+            route_id,
+            &format!("{} - {}", departure, destination),
+            &route_type,
+        ]);
+    }
+
+    let mut calendar_dates = csv_row(&["service_id", "date", "exception_type"]);
+    for date in service_dates {
+        let date = date.format("%Y%m%d").to_string();
+        calendar_dates += &csv_row(&[&date, &date, "1"]);
+    }
+
+    GtfsFeed {
+        agency: csv_row(&["agency_id", "agency_name", "agency_url", "agency_timezone"])
+            + &csv_row(&[
+                &agency.id,
+                &agency.name,
+                &agency.url,
+                agency.timezone.name(),
+            ]),
+        stops,
+        routes: routes_file,
+        trips,
+        stop_times,
+        calendar_dates,
+    }
+}
+
+#[cfg(test)]
+mod gtfs_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use chrono::Duration;
+
+    fn flight_plan(id: &str, from: &str, to: &str, departure: DateTime<Tz>) -> FlightPlan {
+        FlightPlan {
+            id: id.to_string(),
+            data: Some(create_flight_plan_data(
+                "gtfs-vehicle".to_string(),
+                from.to_string(),
+                to.to_string(),
+                departure,
+                departure + Duration::minutes(40),
+            )),
+        }
+    }
+
+    #[test]
+    fn test_build_gtfs_feed() {
+        let timezone = Tz::America__Los_Angeles;
+        let agency = GtfsAgency {
+            id: "arrow".to_string(),
+            name: "Arrow Cargo, Inc.".to_string(),
+            url: "https://arrowair.com".to_string(),
+            timezone,
+        };
+        let nodes = vec![
+            Node::new("SFO", 37.615223, -122.389977),
+            Node::new("OAK", 37.712569, -122.219743),
+            Node::new("SJC", 37.363947, -121.928938),
+        ];
+        let morning = timezone.with_ymd_and_hms(2030, 6, 1, 8, 0, 0).unwrap();
+        let flight_plans = vec![
+            // late flight arriving after midnight
+            flight_plan(
+                "trip-2",
+                "OAK",
+                "SFO",
+                morning + Duration::hours(15) + Duration::minutes(50),
+            ),
+            flight_plan("trip-1", "SFO", "OAK", morning),
+            flight_plan("trip-3", "SFO", "OAK", morning + Duration::days(1)),
+            flight_plan("trip-4", "SFO", "LAX", morning),
+        ];
+        let feed = build_gtfs_feed(&agency, &nodes, &flight_plans);
+
+        assert_eq!(
+            feed.agency,
+            "agency_id,agency_name,agency_url,agency_timezone\narrow,\"Arrow Cargo, Inc.\",https://arrowair.com,America/Los_Angeles\n"
+        );
+        assert_eq!(
+            feed.stops,
+            "stop_id,stop_name,stop_lat,stop_lon\nOAK,OAK,37.712570,-122.219742\nSFO,SFO,37.615223,-122.389977\n"
+        );
+        assert_eq!(
+            feed.routes.lines().skip(1).collect::<Vec<_>>(),
+            vec![
+                "OAK_SFO,arrow,OAK_SFO,OAK - SFO,1100",
+                "SFO_OAK,arrow,SFO_OAK,SFO - OAK,1100"
+            ]
+        );
+        assert_eq!(
+            feed.trips.lines().skip(1).collect::<Vec<_>>(),
+            vec![
+                "SFO_OAK,20300601,trip-1",
+                "OAK_SFO,20300601,trip-2",
+                "SFO_OAK,20300602,trip-3"
+            ]
+        );
+        assert_eq!(
+            feed.stop_times.lines().skip(3).take(2).collect::<Vec<_>>(),
+            vec![
+                "trip-2,23:50:00,23:50:00,OAK,1,2,1",
+                "trip-2,24:30:00,24:30:00,SFO,2,1,2"
+            ]
+        );
+        assert_eq!(
+            feed.calendar_dates,
+            "service_id,date,exception_type\n20300601,20300601,1\n20300602,20300602,1\n"
+        );
+        assert_eq!(feed.files().len(), 6);
+    }
+}
@@ -5,9 +5,92 @@
 //! more.
 //!
 //! **Distance is returned in kilometers**.
+//!
+//! The Earth is approximated by a sphere of the [`EarthRadius`] set with
+//! [`set_earth_radius`], the mean radius by default, or of the radius a
+//! router was built on, see
+//! [`Router::new_with_earth_radius`](crate::router::engine::Router::new_with_earth_radius).
+//! The sphere is off the
+//! WGS84 ellipsoid by up to 0.6% of the distance, depending on the latitude
+//! and direction of the leg.
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU8, Ordering};
 
 use crate::types::location::Location;
 
+/// Radius of the sphere approximating the Earth
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum EarthRadius {
+    /// Mean radius of the WGS84 ellipsoid, 6371.0088 km, the least error
+    /// on average over all legs
+    #[default]
+    Mean,
+    /// Radius of the sphere with the area of the WGS84 ellipsoid,
+    /// 6371.0072 km
+    Authalic,
+}
+
+impl EarthRadius {
+    /// The radius in kilometers
+    pub fn km(&self) -> f64 {
+        match self {
+            EarthRadius::Mean => 6371.0088,
+            EarthRadius::Authalic => 6371.0072,
+        }
+    }
+}
+
+/// Radius used for all distances, as the index of its variant
+static EARTH_RADIUS: AtomicU8 = AtomicU8::new(0);
+
+thread_local! {
+    /// Radius of the distances computed within [`with_earth_radius`] on the
+    /// current thread
+    static SCOPED_EARTH_RADIUS: Cell<Option<EarthRadius>> = const { Cell::new(None) };
+}
+
+/// Sets the radius of the Earth used for all distances
+///
+/// The legs of a router are weighted when it is built, and a router keeps
+/// the radius it was built on, so the radius must be set before the router
+/// is initialized.
+pub fn set_earth_radius(radius: EarthRadius) {
+    let index = match radius {
+        EarthRadius::Mean => 0,
+        EarthRadius::Authalic => 1,
+    };
+    EARTH_RADIUS.store(index, Ordering::Relaxed);
+}
+
+/// Gets the radius of the Earth used for all distances
+pub fn get_earth_radius() -> EarthRadius {
+    if let Some(radius) = SCOPED_EARTH_RADIUS.with(Cell::get) {
+        return radius;
+    }
+    match EARTH_RADIUS.load(Ordering::Relaxed) {
+        1 => EarthRadius::Authalic,
+        _ => EarthRadius::Mean,
+    }
+}
+
+/// Computes the distances within `f` on the given radius instead of the
+/// radius set with [`set_earth_radius`], e.g. to build a router on its own
+/// radius
+pub fn with_earth_radius<T>(radius: EarthRadius, f: impl FnOnce() -> T) -> T {
+    /// Restores the previous radius, even if `f` panics
+    struct Restore(Option<EarthRadius>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SCOPED_EARTH_RADIUS.with(|scoped| scoped.set(self.0));
+        }
+    }
+
+    let _restore = Restore(SCOPED_EARTH_RADIUS.with(|scoped| scoped.replace(Some(radius))));
+    f()
+}
+
 /// Calculate the distance between two points on a sphere.
 ///
 /// # Arguments
@@ -21,27 +104,77 @@ use crate::types::location::Location;
 /// The current formula does ***not*** take into account the altitude of the
 /// points.
 ///
-/// Float 32 values are used to achieve a 5-decimal precision (0.00001),
-/// which narrows the error margin to a meter.
+/// The distance is computed with 64-bit floats and the radius set with
+/// [`set_earth_radius`], then rounded to 32 bits, which keeps the rounding
+/// error under 10 centimeters on legs up to 1,000 km.
 pub fn distance(start: &Location, end: &Location) -> f32 {
-    // km in radians
-    let kilometers: f32 = 6371.0;
+    distance_with(get_earth_radius(), start, end)
+}
 
-    let d_lat: f32 = (end.latitude.into_inner() - start.latitude.into_inner()).to_radians();
-    let d_lon: f32 = (end.longitude.into_inner() - start.longitude.into_inner()).to_radians();
-    let lat1: f32 = (start.latitude.into_inner()).to_radians();
-    let lat2: f32 = (end.latitude.into_inner()).to_radians();
+/// Calculate the distance between two points on a sphere of the given
+/// radius.
+///
+/// See [`distance`].
+pub fn distance_with(radius: EarthRadius, start: &Location, end: &Location) -> f32 {
+    let cos_lat1 = (start.latitude.into_inner() as f64).to_radians().cos();
+    let cos_lat2 = (end.latitude.into_inner() as f64).to_radians().cos();
+    (radius.km() * central_angle(start, end, cos_lat1, cos_lat2)) as f32
+}
 
-    let a: f32 = ((d_lat / 2.0).sin()) * ((d_lat / 2.0).sin())
-        + ((d_lon / 2.0).sin()) * ((d_lon / 2.0).sin()) * (lat1.cos()) * (lat2.cos());
-    let c: f32 = 2.0 * ((a.sqrt()).atan2((1.0 - a).sqrt()));
+/// Calculate the distances from one point to many points on a sphere.
+///
+/// The trigonometry of the origin is computed once, which saves a third of
+/// the calls when scanning many candidates, e.g. for the nearest nodes.
+///
+/// # Arguments
+/// * `origin` - The starting point.
+/// * `destinations` - The ending points.
+///
+/// # Returns
+/// The distance to each destination in kilometers, in the order of the
+/// destinations, the same as with [`distance`].
+pub fn distance_batch(origin: &Location, destinations: &[Location]) -> Vec<f32> {
+    distance_batch_by(origin, destinations, |location| location)
+}
 
-    kilometers * c
+/// Calculate the distances from one point to the locations of many items,
+/// e.g. nodes.
+///
+/// See [`distance_batch`].
+pub fn distance_batch_by<T>(
+    origin: &Location,
+    destinations: &[T],
+    location: impl Fn(&T) -> &Location,
+) -> Vec<f32> {
+    let radius_km = get_earth_radius().km();
+    let cos_lat1 = (origin.latitude.into_inner() as f64).to_radians().cos();
+    destinations
+        .iter()
+        .map(|destination| {
+            let end = location(destination);
+            let cos_lat2 = (end.latitude.into_inner() as f64).to_radians().cos();
+            (radius_km * central_angle(origin, end, cos_lat1, cos_lat2)) as f32
+        })
+        .collect()
+}
+
+/// Central angle in radians between two points, given the cosines of their
+/// latitudes.
+fn central_angle(start: &Location, end: &Location, cos_lat1: f64, cos_lat2: f64) -> f64 {
+    let d_lat =
+        (end.latitude.into_inner() as f64 - start.latitude.into_inner() as f64).to_radians();
+    let d_lon =
+        (end.longitude.into_inner() as f64 - start.longitude.into_inner() as f64).to_radians();
+
+    let a = ((d_lat / 2.0).sin()) * ((d_lat / 2.0).sin())
+        + ((d_lon / 2.0).sin()) * ((d_lon / 2.0).sin()) * cos_lat1 * cos_lat2;
+    2.0 * ((a.sqrt()).atan2((1.0 - a).sqrt()))
 }
 
 #[cfg(test)]
 pub mod haversine_test {
     use super::*;
+    use crate::test_support::mock_location;
     use ordered_float::OrderedFloat;
 
     #[test]
@@ -56,6 +189,99 @@ pub mod haversine_test {
             longitude: OrderedFloat(-77.043934),
             altitude_meters: OrderedFloat(0.0),
         };
-        assert_eq!(0.5496312, distance(&start, &end));
+        assert_eq!(0.5496319, distance(&start, &end));
+    }
+
+    #[test]
+    fn haversine_distance_batch_matches_distance() {
+        let origin = crate::generator::generate_location_with_seed(3627);
+        let destinations = (0..1000)
+            .map(crate::generator::generate_location_with_seed)
+            .collect::<Vec<_>>();
+        let distances = distance_batch(&origin, &destinations);
+        assert_eq!(distances.len(), destinations.len());
+        for (destination, batch_distance) in destinations.iter().zip(distances) {
+            assert_eq!(distance(&origin, destination), batch_distance);
+        }
+        assert!(distance_batch(&origin, &[]).is_empty());
+    }
+
+    /// Latitude and longitude of two locations, and their distance on the
+    /// WGS84 ellipsoid in km
+    type SurveyedDistance = ((f32, f32), (f32, f32), f32);
+
+    const SURVEYED_DISTANCES: [SurveyedDistance; 6] = [
+        // one degree of longitude along the equator
+        ((0.0, 0.0), (0.0, 1.0), 111.3195),
+        // one degree of latitude from the equator
+        ((0.0, 0.0), (1.0, 0.0), 110.5744),
+        // San Francisco to Sacramento
+        ((37.7749, -122.4194), (38.5816, -121.4944), 120.7744),
+        // Paris to London
+        ((48.8566, 2.3522), (51.5074, -0.1278), 343.9231),
+        // San Francisco to Los Angeles
+        ((37.7749, -122.4194), (34.0522, -118.2437), 559.0423),
+        // White House to the Washington Monument
+        ((38.898556, -77.037852), (38.897147, -77.043934), 0.5503),
+    ];
+
+    #[test]
+    fn haversine_distance_close_to_surveyed_distances() {
+        for ((lat1, lon1), (lat2, lon2), surveyed_km) in SURVEYED_DISTANCES {
+            let km = distance(&mock_location(lat1, lon1), &mock_location(lat2, lon2));
+            let error = (km - surveyed_km).abs() / surveyed_km;
+            assert!(
+                error < 0.006,
+                "{} km instead of {} km from ({}, {}) to ({}, {})",
+                km,
+                surveyed_km,
+                lat1,
+                lon1,
+                lat2,
+                lon2
+            );
+        }
+    }
+
+    #[test]
+    fn haversine_distance_precise_on_long_legs() {
+        // spherical distances computed with 64-bit floats from the same coordinates
+        let san_francisco = mock_location(37.7749, -122.4194);
+        let los_angeles = mock_location(34.0522, -118.2437);
+        let km = distance_with(EarthRadius::Mean, &san_francisco, &los_angeles);
+        assert!((km - 559.12150).abs() < 0.001);
+        let km = distance_with(EarthRadius::Authalic, &san_francisco, &los_angeles);
+        assert!((km - 559.12136).abs() < 0.001);
+
+        let paris = mock_location(48.8566, 2.3522);
+        let london = mock_location(51.5074, -0.1278);
+        let km = distance_with(EarthRadius::Mean, &paris, &london);
+        assert!((km - 343.55642).abs() < 0.001);
+        assert_eq!(distance_with(EarthRadius::Mean, &paris, &paris), 0.0);
+    }
+
+    #[test]
+    fn haversine_earth_radius() {
+        assert_eq!(get_earth_radius(), EarthRadius::Mean);
+        assert_eq!(EarthRadius::default().km(), 6371.0088);
+        assert!(EarthRadius::Authalic.km() < EarthRadius::Mean.km());
+
+        let (paris, london) = (
+            mock_location(48.8566, 2.3522),
+            mock_location(51.5074, -0.1278),
+        );
+        let km = with_earth_radius(EarthRadius::Authalic, || {
+            assert_eq!(get_earth_radius(), EarthRadius::Authalic);
+            with_earth_radius(EarthRadius::Mean, || {
+                assert_eq!(get_earth_radius(), EarthRadius::Mean)
+            });
+            distance(&paris, &london)
+        });
+        assert_eq!(km, distance_with(EarthRadius::Authalic, &paris, &london));
+        assert_eq!(get_earth_radius(), EarthRadius::Mean);
+        assert_eq!(
+            with_earth_radius(EarthRadius::Authalic, || distance_batch(&paris, &[london])),
+            vec![km]
+        );
     }
 }
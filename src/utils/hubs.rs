@@ -0,0 +1,267 @@
+//! Detection of the natural hubs of a network.
+//!
+//! Vertiports gather in dense clusters around cities and business parks.
+//! [`cluster_nodes`] finds them with the DBSCAN algorithm over the node
+//! locations: a cluster grows from every node with enough neighbors within
+//! a radius, and nodes reached by no cluster are left out as noise.
+//! [`degree_centrality`] measures how connected each node of the graph is,
+//! and [`find_hubs`] designates the most central node of each cluster as its
+//! hub, e.g. to base vehicles for rebalancing or to place new chargers.
+//!
+//! Neighbors are found by scanning all nodes, so clustering takes a time
+//! quadratic in the number of nodes.
+
+use std::collections::HashMap;
+
+use ordered_float::OrderedFloat;
+
+use crate::distance::Distance;
+use crate::haversine;
+use crate::location::Location;
+use crate::node::Node;
+use crate::router::engine::Router;
+
+/// A dense cluster of nodes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cluster<'a> {
+    /// the nodes of the cluster, in the order of the nodes clustered
+    pub nodes: Vec<&'a Node>,
+    /// mean location of the nodes, on the ground
+    pub centroid: Location,
+}
+
+/// The most central node of a cluster
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hub<'a> {
+    /// the hub
+    pub node: &'a Node,
+    /// degree centrality of the hub, see [`degree_centrality`]
+    pub centrality: f32,
+    /// the cluster served by the hub
+    pub cluster: Cluster<'a>,
+}
+
+/// Groups the nodes into clusters with the DBSCAN algorithm
+///
+/// # Arguments
+/// * `nodes` - the nodes to cluster
+/// * `radius` - largest distance between neighbors, included
+/// * `min_nodes` - least number of nodes within the radius of a node,
+///   counting itself, for a cluster to grow from it
+///
+/// # Returns
+/// The clusters in the order they were found from the nodes. Nodes in no
+/// cluster are left out.
+pub fn cluster_nodes<'a>(
+    nodes: impl IntoIterator<Item = &'a Node>,
+    radius: Distance,
+    min_nodes: usize,
+) -> Vec<Cluster<'a>> {
+    let nodes: Vec<&Node> = nodes.into_iter().collect();
+    let neighbors = |index: usize| -> Vec<usize> {
+        haversine::distance_batch_by(&nodes[index].location, &nodes, |node| &node.location)
+            .into_iter()
+            .enumerate()
+            .filter(|(_, km)| Distance::from_km(*km) <= radius)
+            .map(|(neighbor, _)| neighbor)
+            .collect()
+    };
+
+    // cluster of each node, None until visited
+    let mut labels: Vec<Option<Label>> = vec![None; nodes.len()];
+    let mut clusters: Vec<Vec<usize>> = vec![];
+    for index in 0..nodes.len() {
+        if labels[index].is_some() {
+            continue;
+        }
+        let mut queue = neighbors(index);
+        if queue.len() < min_nodes {
+            labels[index] = Some(Label::Noise);
+            continue;
+        }
+        let cluster = clusters.len();
+        clusters.push(vec![]);
+        labels[index] = Some(Label::Cluster(cluster));
+        while let Some(neighbor) = queue.pop() {
+            match labels[neighbor] {
+                Some(Label::Cluster(_)) => continue,
+                // a border node, reached but too sparse to grow the cluster
+                Some(Label::Noise) => {
+                    labels[neighbor] = Some(Label::Cluster(cluster));
+                    continue;
+                }
+                None => labels[neighbor] = Some(Label::Cluster(cluster)),
+            }
+            let next = neighbors(neighbor);
+            if next.len() >= min_nodes {
+                queue.extend(next.into_iter().filter(|next| labels[*next].is_none()));
+            }
+        }
+    }
+    for (index, label) in labels.iter().enumerate() {
+        if let Some(Label::Cluster(cluster)) = label {
+            clusters[*cluster].push(index);
+        }
+    }
+
+    clusters
+        .into_iter()
+        .map(|members| {
+            let count = members.len() as f32;
+            let (latitude, longitude) = members.iter().fold((0.0, 0.0), |(lat, lon), index| {
+                let location = &nodes[*index].location;
+                (
+                    lat + location.latitude.into_inner(),
+                    lon + location.longitude.into_inner(),
+                )
+            });
+            Cluster {
+                nodes: members.iter().map(|index| nodes[*index]).collect(),
+                centroid: Location {
+                    latitude: OrderedFloat(latitude / count),
+                    longitude: OrderedFloat(longitude / count),
+                    altitude_meters: OrderedFloat(0.0),
+                },
+            }
+        })
+        .collect()
+}
+
+/// Label of a node while clustering
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Label {
+    /// too sparse to grow a cluster, unless a cluster reaches it later
+    Noise,
+    /// in the cluster with the index
+    Cluster(usize),
+}
+
+/// Computes the degree centrality of each node of the graph
+///
+/// # Returns
+/// The number of edges from and to each node by uid, divided by the most
+/// possible, from 0.0 for an isolated node to 1.0 for a node connected both
+/// ways to all others.
+pub fn degree_centrality(router: &Router) -> HashMap<String, f32> {
+    let mut degrees: HashMap<String, usize> = router
+        .node_indices
+        .keys()
+        .map(|node| (node.uid.clone(), 0))
+        .collect();
+    for edge in router.edges() {
+        for uid in [edge.from_id, edge.to_id] {
+            if let Some(degree) = degrees.get_mut(uid) {
+                *degree += 1;
+            }
+        }
+    }
+    let most = (2 * degrees.len().saturating_sub(1)).max(1) as f32;
+    degrees
+        .into_iter()
+        .map(|(uid, degree)| (uid, degree as f32 / most))
+        .collect()
+}
+
+/// Finds the hubs of the network of a router
+///
+/// The nodes of the router are clustered with [`cluster_nodes`], and the
+/// hub of each cluster is its node of highest [`degree_centrality`], the
+/// first by uid on ties.
+///
+/// # Returns
+/// The hubs, most central first, then serving the largest cluster first.
+pub fn find_hubs<'a>(router: &Router<'a>, radius: Distance, min_nodes: usize) -> Vec<Hub<'a>> {
+    let mut nodes: Vec<&'a Node> = router.node_indices.keys().copied().collect();
+    nodes.sort_by(|a, b| a.uid.cmp(&b.uid));
+    let centrality = degree_centrality(router);
+    let mut hubs: Vec<Hub<'a>> = cluster_nodes(nodes, radius, min_nodes)
+        .into_iter()
+        .filter_map(|cluster| {
+            let (node, centrality) = cluster
+                .nodes
+                .iter()
+                .map(|node| (*node, centrality.get(&node.uid).copied().unwrap_or(0.0)))
+                .min_by(|(a, a_centrality), (b, b_centrality)| {
+                    OrderedFloat(*b_centrality)
+                        .cmp(&OrderedFloat(*a_centrality))
+                        .then_with(|| a.uid.cmp(&b.uid))
+                })?;
+            Some(Hub {
+                node,
+                centrality,
+                cluster,
+            })
+        })
+        .collect();
+    hubs.sort_by(|a, b| {
+        OrderedFloat(b.centrality)
+            .cmp(&OrderedFloat(a.centrality))
+            .then_with(|| b.cluster.nodes.len().cmp(&a.cluster.nodes.len()))
+    });
+    hubs
+}
+
+#[cfg(test)]
+mod hubs_tests {
+    use super::*;
+    use crate::node::AsNode;
+
+    fn distance(from: &dyn AsNode, to: &dyn AsNode) -> f32 {
+        haversine::distance(&from.as_node().location, &to.as_node().location)
+    }
+
+    /// A cross of 5 nodes 0.01° apart around (0, 0), a line of 3 nodes
+    /// around (1, 1) and a lone node
+    fn nodes() -> Vec<Node> {
+        vec![
+            Node::new("A-north", 0.01, 0.0),
+            Node::new("A-center", 0.0, 0.0),
+            Node::new("A-south", -0.01, 0.0),
+            Node::new("A-east", 0.0, 0.01),
+            Node::new("A-west", 0.0, -0.01),
+            Node::new("B-1", 1.0, 1.0),
+            Node::new("B-2", 1.0, 1.01),
+            Node::new("B-3", 1.0, 1.02),
+            Node::new("lone", 5.0, 5.0),
+        ]
+    }
+
+    #[test]
+    fn test_cluster_nodes() {
+        let nodes = nodes();
+        let clusters = cluster_nodes(&nodes, Distance::from_km(1.2), 3);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].nodes.len(), 5);
+        assert!(clusters[0].centroid.latitude.into_inner().abs() < 1e-6);
+        assert!(clusters[0].centroid.longitude.into_inner().abs() < 1e-6);
+        // B-1 and B-3 only have 2 nodes within the radius, they border B-2
+        let uids: Vec<&str> = clusters[1]
+            .nodes
+            .iter()
+            .map(|node| node.uid.as_str())
+            .collect();
+        assert_eq!(uids, ["B-1", "B-2", "B-3"]);
+        assert!((clusters[1].centroid.longitude.into_inner() - 1.01).abs() < 1e-6);
+
+        assert!(cluster_nodes(&nodes, Distance::from_km(1.2), 6).is_empty());
+        assert_eq!(cluster_nodes(&nodes, Distance::from_km(1.2), 1).len(), 3);
+        assert!(cluster_nodes(&[], Distance::from_km(1.2), 1).is_empty());
+    }
+
+    #[test]
+    fn test_find_hubs() {
+        let nodes = nodes();
+        let router = Router::new(&nodes, 2.0, distance, distance);
+        let centrality = degree_centrality(&router);
+        assert_eq!(centrality.len(), nodes.len());
+        assert_eq!(centrality["lone"], 0.0);
+        assert!(centrality["A-center"] > centrality["A-north"]);
+
+        let hubs = find_hubs(&router, Distance::from_km(1.2), 3);
+        assert_eq!(hubs.len(), 2);
+        assert_eq!(hubs[0].node.uid, "A-center");
+        assert_eq!(hubs[0].centrality, centrality["A-center"]);
+        assert_eq!(hubs[0].cluster.nodes.len(), 5);
+        assert_eq!(hubs[1].node.uid, "B-2");
+    }
+}
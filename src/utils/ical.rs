@@ -0,0 +1,357 @@
+//! Conversion between [`Calendar`] and iCalendar (RFC 5545) documents.
+//!
+//! Every recurring blocking event is exported as a `VEVENT` with
+//! `DTSTART`, `DURATION` and its `RRULE`/`RDATE`/`EXRULE`/`EXDATE`
+//! properties. Ad-hoc blackouts are exported as one-off `VEVENT`s with
+//! `DTSTART` and `DTEND`.
+//!
+//! Timezones are referenced by their IANA name in the `TZID` parameter
+//! (e.g. `DTSTART;TZID=America/New_York:20221020T080000`). `VTIMEZONE`
+//! components are skipped on import since the timezone database is
+//! already bundled.
+
+use chrono::Duration;
+use std::str::FromStr;
+
+use crate::schedule::{
+    datetime_property, parse_duration, Calendar, RRuleSet, RecurrentEvent, TimeSlot, Tz,
+};
+
+const PRODUCT_ID: &str = "-//Arrow DAO//lib-router//EN";
+
+/// Parses the value and parameters of a date-time property (everything after
+/// the property name) by handing it over to the rrule parser as a `DTSTART`
+fn parse_datetime_property(params_and_value: &str) -> Option<chrono::DateTime<Tz>> {
+    RRuleSet::from_str(&format!("DTSTART{}", params_and_value))
+        .ok()
+        .map(|rrule_set| *rrule_set.get_dt_start())
+}
+
+/// Formats a chrono Duration as an iso8601 duration, e.g. `P1DT2H30M`
+fn duration_to_iso8601(duration: Duration) -> String {
+    let mut seconds = duration.num_seconds();
+    let days = seconds / 86400;
+    seconds %= 86400;
+    let hours = seconds / 3600;
+    seconds %= 3600;
+    let minutes = seconds / 60;
+    seconds %= 60;
+
+    let mut result = "P".to_string();
+    if days > 0 {
+        result += &format!("{}D", days);
+    }
+    if hours > 0 || minutes > 0 || seconds > 0 || days == 0 {
+        result += "T";
+        if hours > 0 {
+            result += &format!("{}H", hours);
+        }
+        if minutes > 0 {
+            result += &format!("{}M", minutes);
+        }
+        if seconds > 0 || (hours == 0 && minutes == 0) {
+            result += &format!("{}S", seconds);
+        }
+    }
+    result
+}
+
+/// Unfolds content lines (RFC 5545 section 3.1): a line starting with a
+/// space or a tab continues the previous line
+fn unfold_lines(ical_str: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for line in ical_str.lines() {
+        if let Some(continuation) = line.strip_prefix([' ', '\t']) {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(continuation);
+                continue;
+            }
+        }
+        lines.push(line.trim_end().to_string());
+    }
+    lines
+}
+
+/// Properties of a single `VEVENT` relevant for scheduling
+#[derive(Debug, Default)]
+struct VEvent {
+    dtstart: Option<String>,
+    dtend: Option<String>,
+    duration: Option<String>,
+    rules: Vec<String>,
+}
+
+impl VEvent {
+    /// Converts the `VEVENT` into a recurrent event or, if it has no
+    /// recurrence rules, into a blackout
+    fn into_calendar_entry(self) -> Result<(Option<RecurrentEvent>, Option<TimeSlot>), ()> {
+        let Some(dtstart) = self.dtstart else {
+            error!("VEVENT without DTSTART");
+            return Err(());
+        };
+        let Some(start) = parse_datetime_property(&dtstart) else {
+            error!("Invalid DTSTART: {}", dtstart);
+            return Err(());
+        };
+        let duration = match (self.duration, self.dtend) {
+            (Some(duration), _) => duration,
+            (None, Some(dtend)) => {
+                let Some(end) = parse_datetime_property(&dtend) else {
+                    error!("Invalid DTEND: {}", dtend);
+                    return Err(());
+                };
+                duration_to_iso8601(end - start)
+            }
+            (None, None) => {
+                error!("VEVENT without DURATION or DTEND");
+                return Err(());
+            }
+        };
+        let Some(parsed_duration) = parse_duration(&duration) else {
+            error!("Invalid DURATION: {}", duration);
+            return Err(());
+        };
+
+        if self.rules.is_empty() {
+            return Ok((
+                None,
+                Some(TimeSlot {
+                    start,
+                    end: start + parsed_duration,
+                }),
+            ));
+        }
+
+        let rrule_set_str = format!("DTSTART{}\n{}", dtstart, self.rules.join("\n"));
+        let rrset_res = RRuleSet::from_str(&rrule_set_str);
+        let Ok(rrule_set) = rrset_res else {
+            error!("Invalid rrule set: {:?}", rrset_res.unwrap_err());
+            return Err(());
+        };
+        Ok((
+            Some(RecurrentEvent {
+                rrule_set,
+                duration,
+            }),
+            None,
+        ))
+    }
+}
+
+impl Calendar {
+    /// Formats `Calendar` into an iCalendar (RFC 5545) `VCALENDAR` document
+    /// which can be imported into standard calendar tools
+    pub fn to_ical(&self) -> String {
+        let dtstamp = datetime_property("DTSTAMP", &crate::clock::now());
+        let mut lines: Vec<String> = vec![
+            "BEGIN:VCALENDAR".to_string(),
+            "VERSION:2.0".to_string(),
+            format!("PRODID:{}", PRODUCT_ID),
+        ];
+        for (i, event) in self.events.iter().enumerate() {
+            lines.push("BEGIN:VEVENT".to_string());
+            lines.push(format!("UID:event-{}", i));
+            lines.push(dtstamp.clone());
+            lines.push(datetime_property("DTSTART", event.rrule_set.get_dt_start()));
+            lines.push(format!("DURATION:{}", event.duration));
+            for rrule in event.rrule_set.get_rrule() {
+                lines.push(format!("RRULE:{}", rrule));
+            }
+            for rdate in event.rrule_set.get_rdate() {
+                lines.push(datetime_property("RDATE", rdate));
+            }
+            for exrule in event.rrule_set.get_exrule() {
+                lines.push(format!("EXRULE:{}", exrule));
+            }
+            for exdate in event.rrule_set.get_exdate() {
+                lines.push(datetime_property("EXDATE", exdate));
+            }
+            lines.push("END:VEVENT".to_string());
+        }
+        for (i, blackout) in self.blackouts.iter().enumerate() {
+            lines.push("BEGIN:VEVENT".to_string());
+            lines.push(format!("UID:blackout-{}", i));
+            lines.push(dtstamp.clone());
+            lines.push(datetime_property("DTSTART", &blackout.start));
+            lines.push(datetime_property("DTEND", &blackout.end));
+            lines.push("END:VEVENT".to_string());
+        }
+        lines.push("END:VCALENDAR".to_string());
+
+        // RFC 5545 requires CRLF line breaks
+        lines.join("\r\n") + "\r\n"
+    }
+
+    /// Parses an iCalendar (RFC 5545) document into a `Calendar`
+    ///
+    /// `VEVENT`s with recurrence rules become recurring blocking events,
+    /// one-off `VEVENT`s become blackouts. Event durations are taken from
+    /// `DURATION` or computed from `DTEND`.
+    #[allow(clippy::result_unit_err)]
+    pub fn from_ical(ical_str: &str) -> Result<Calendar, ()> {
+        debug!("Parsing iCalendar: {}", ical_str);
+        let mut calendar = Calendar {
+            events: vec![],
+            blackouts: vec![],
+        };
+        let mut current: Option<VEvent> = None;
+        let mut nested_components: Vec<String> = vec![];
+        for line in unfold_lines(ical_str) {
+            if let Some(component) = line.strip_prefix("BEGIN:") {
+                match component {
+                    "VCALENDAR" => {}
+                    "VEVENT" if current.is_none() && nested_components.is_empty() => {
+                        current = Some(VEvent::default());
+                    }
+                    _ => nested_components.push(component.to_string()),
+                }
+                continue;
+            }
+            if let Some(component) = line.strip_prefix("END:") {
+                match component {
+                    "VCALENDAR" => {}
+                    "VEVENT" if nested_components.is_empty() => {
+                        let Some(vevent) = current.take() else {
+                            error!("END:VEVENT without BEGIN:VEVENT");
+                            return Err(());
+                        };
+                        let (event, blackout) = vevent.into_calendar_entry()?;
+                        calendar.events.extend(event);
+                        calendar.blackouts.extend(blackout);
+                    }
+                    _ => {
+                        if nested_components.pop().as_deref() != Some(component) {
+                            error!("Unbalanced iCalendar component: {}", component);
+                            return Err(());
+                        }
+                    }
+                }
+                continue;
+            }
+            // skip VTIMEZONE, VALARM, etc. as well as properties outside of VEVENTs
+            let (Some(vevent), true) = (current.as_mut(), nested_components.is_empty()) else {
+                continue;
+            };
+            let Some(name_end) = line.find([':', ';']) else {
+                continue;
+            };
+            let (name, rest) = line.split_at(name_end);
+            match name {
+                "DTSTART" => vevent.dtstart = Some(rest.to_string()),
+                "DTEND" => vevent.dtend = Some(rest.to_string()),
+                "DURATION" => vevent.duration = Some(rest.trim_start_matches(':').to_string()),
+                "RRULE" | "RDATE" | "EXRULE" | "EXDATE" => vevent.rules.push(line.clone()),
+                _ => {}
+            }
+        }
+        if current.is_some() || !nested_components.is_empty() {
+            error!("Unterminated iCalendar component");
+            return Err(());
+        }
+        debug!("Parsed calendar: {:?}", calendar);
+        Ok(calendar)
+    }
+}
+
+#[cfg(test)]
+mod ical_tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const CAL_WORKDAYS_8AM_6PM: &str = "DTSTART:20221020T180000Z;DURATION:PT14H\n\
+    RRULE:FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR\n\
+    DTSTART:20221022T000000Z;DURATION:PT24H\n\
+    RRULE:FREQ=WEEKLY;BYDAY=SA,SU\n\
+    BLACKOUT:20221025T090000Z/20221025T110000Z";
+
+    const ICAL_NEW_YORK: &str = "BEGIN:VCALENDAR\r\n\
+    VERSION:2.0\r\n\
+    PRODID:-//Example//Calendar//EN\r\n\
+    BEGIN:VTIMEZONE\r\n\
+    TZID:America/New_York\r\n\
+    BEGIN:STANDARD\r\n\
+    DTSTART:19701101T020000\r\n\
+    TZOFFSETFROM:-0400\r\n\
+    TZOFFSETTO:-0500\r\n\
+    END:STANDARD\r\n\
+    END:VTIMEZONE\r\n\
+    BEGIN:VEVENT\r\n\
+    UID:night-closure\r\n\
+    SUMMARY:Closed at night\r\n\
+    DTSTART;TZID=America/New_York:20221020T200000\r\n\
+    DTEND;TZID=America/New_York:20221021T080000\r\n\
+    RRULE:FREQ=DAILY\r\n\
+    END:VEVENT\r\n\
+    END:VCALENDAR\r\n";
+
+    #[test]
+    fn test_duration_to_iso8601() {
+        assert_eq!(duration_to_iso8601(Duration::hours(14)), "PT14H");
+        assert_eq!(duration_to_iso8601(Duration::hours(24)), "P1D");
+        assert_eq!(
+            duration_to_iso8601(Duration::minutes(24 * 60 + 90)),
+            "P1DT1H30M"
+        );
+        assert_eq!(duration_to_iso8601(Duration::zero()), "PT0S");
+    }
+
+    #[test]
+    fn test_ical_round_trip() {
+        let calendar = Calendar::from_str(CAL_WORKDAYS_8AM_6PM).unwrap();
+        let ical = calendar.to_ical();
+        assert!(ical.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ical.contains("DTSTART:20221020T180000Z\r\nDURATION:PT14H\r\n"));
+        assert!(ical.contains("DTEND:20221025T110000Z\r\n"));
+
+        let imported = Calendar::from_ical(&ical).unwrap();
+        assert_eq!(imported.events.len(), 2);
+        assert_eq!(imported.events[0].duration, "PT14H");
+        assert_eq!(imported.blackouts, calendar.blackouts);
+        assert_eq!(imported.to_string(), calendar.to_string());
+    }
+
+    #[test]
+    fn test_import_with_timezone() {
+        let calendar = Calendar::from_ical(ICAL_NEW_YORK).unwrap();
+        assert_eq!(calendar.events.len(), 1);
+        assert_eq!(calendar.events[0].duration, "PT12H");
+
+        // 21:00 in New York is 01:00 UTC next day (EDT)
+        let start = Tz::UTC.with_ymd_and_hms(2022, 10, 26, 1, 0, 0).unwrap();
+        let end = Tz::UTC.with_ymd_and_hms(2022, 10, 26, 2, 0, 0).unwrap();
+        assert_eq!(calendar.is_available_between(start, end), false);
+
+        // 12:00 in New York is 16:00 UTC
+        let start = Tz::UTC.with_ymd_and_hms(2022, 10, 26, 16, 0, 0).unwrap();
+        let end = Tz::UTC.with_ymd_and_hms(2022, 10, 26, 17, 0, 0).unwrap();
+        assert_eq!(calendar.is_available_between(start, end), true);
+
+        let exported = calendar.to_ical();
+        assert!(exported.contains("DTSTART;TZID=America/New_York:20221020T200000\r\n"));
+    }
+
+    #[test]
+    fn test_folded_lines() {
+        let ical = "BEGIN:VCALENDAR\r\n\
+        BEGIN:VEVENT\r\n\
+        DTSTART:20221020T120000Z\r\n\
+        DURATION:PT1H\r\n\
+        RRULE:FREQ=WEEKLY;\r\n BYDAY=MO,TU,WE,TH,FR\r\n\
+        END:VEVENT\r\n\
+        END:VCALENDAR\r\n";
+        let calendar = Calendar::from_ical(ical).unwrap();
+        assert_eq!(calendar.events.len(), 1);
+        assert!(calendar.events[0].rrule_set.get_rrule()[0]
+            .to_string()
+            .contains("BYDAY=MO,TU,WE,TH,FR"));
+    }
+
+    #[test]
+    fn test_invalid_ical() {
+        assert!(Calendar::from_ical("BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nEND:VCALENDAR").is_err());
+        assert!(Calendar::from_ical(
+            "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nDURATION:PT1H\r\nEND:VEVENT\r\nEND:VCALENDAR"
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,260 @@
+//! Conversions between the router types and the storage resources.
+//!
+//! Storage resources wrap their fields in an optional `data` and carry
+//! protobuf timestamps, and both may be missing or out of range in what the
+//! storage service returns. The [`TryFrom`] implementations here check them
+//! once, failing with an [`InteropError`] naming the resource instead of
+//! panicking, so the rest of the router computes with validated [`Node`]s,
+//! date times and [`Route`]s.
+//!
+//! `Timestamp` and `DateTime` both come from other crates, so they convert
+//! through the [`StorageTime`] wrapper.
+
+use chrono::DateTime;
+use prost_types::Timestamp;
+use rrule::Tz;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::location::Location;
+use crate::node::Node;
+use crate::router_state::{FlightPlanData, Vertiport};
+use crate::time::{datetime_to_timestamp, timestamp_to_datetime};
+
+/// Errors converting between router types and storage resources
+#[derive(Debug, Clone, PartialEq)]
+pub enum InteropError {
+    /// The resource with the id has no data
+    MissingData(String),
+    /// The coordinates of the vertiport with the id are out of range
+    InvalidCoordinates {
+        /// id of the vertiport
+        id: String,
+        /// latitude in degrees
+        latitude: f64,
+        /// longitude in degrees
+        longitude: f64,
+    },
+    /// The timestamp is out of the range of date times or has invalid
+    /// nanoseconds
+    InvalidTime(String),
+    /// The route can't be flown as a flight plan
+    InvalidRoute(String),
+}
+
+impl Display for InteropError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            InteropError::MissingData(id) => write!(f, "Missing data of resource id: {}", id),
+            InteropError::InvalidCoordinates {
+                id,
+                latitude,
+                longitude,
+            } => write!(
+                f,
+                "Invalid coordinates of vertiport id {}: {}, {}",
+                id, latitude, longitude
+            ),
+            InteropError::InvalidTime(error) => write!(f, "Invalid time: {}", error),
+            InteropError::InvalidRoute(error) => write!(f, "Invalid route: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for InteropError {}
+
+impl From<InteropError> for String {
+    fn from(error: InteropError) -> Self {
+        error.to_string()
+    }
+}
+
+/// Converts a storage vertiport into an operating node on the ground
+impl TryFrom<&Vertiport> for Node {
+    type Error = InteropError;
+
+    fn try_from(vertiport: &Vertiport) -> Result<Self, Self::Error> {
+        let data = vertiport
+            .data
+            .as_ref()
+            .ok_or_else(|| InteropError::MissingData(vertiport.id.clone()))?;
+        if !(-90.0..=90.0).contains(&data.latitude) || !(-180.0..=180.0).contains(&data.longitude) {
+            return Err(InteropError::InvalidCoordinates {
+                id: vertiport.id.clone(),
+                latitude: data.latitude,
+                longitude: data.longitude,
+            });
+        }
+        Ok(Node {
+            schedule: data.schedule.clone(),
+            ..Node::new(
+                vertiport.id.clone(),
+                data.latitude as f32,
+                data.longitude as f32,
+            )
+        })
+    }
+}
+
+/// A UTC date time converted from or to a storage timestamp
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StorageTime(pub DateTime<Tz>);
+
+impl TryFrom<&Timestamp> for StorageTime {
+    type Error = InteropError;
+
+    fn try_from(timestamp: &Timestamp) -> Result<Self, Self::Error> {
+        timestamp_to_datetime(timestamp)
+            .map(StorageTime)
+            .map_err(InteropError::InvalidTime)
+    }
+}
+
+impl From<StorageTime> for Timestamp {
+    fn from(time: StorageTime) -> Self {
+        datetime_to_timestamp(&time.0)
+    }
+}
+
+/// A route found by the router, scheduled between two times
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    /// ids of the vertiports along the route, from departure to destination
+    pub vertiport_ids: Vec<String>,
+    /// locations along the route
+    pub locations: Vec<Location>,
+    /// length of the route in kilometers
+    pub distance_km: f32,
+    /// scheduled departure time
+    pub departure_time: DateTime<Tz>,
+    /// scheduled arrival time
+    pub arrival_time: DateTime<Tz>,
+}
+
+/// Fills the vertiports, scheduled times and distance of a flight plan from
+/// a route; the vehicle, pilot and cargo are left to the caller
+impl TryFrom<&Route> for FlightPlanData {
+    type Error = InteropError;
+
+    fn try_from(route: &Route) -> Result<Self, Self::Error> {
+        let (Some(departure), Some(destination)) =
+            (route.vertiport_ids.first(), route.vertiport_ids.last())
+        else {
+            return Err(InteropError::InvalidRoute("No vertiports".to_string()));
+        };
+        if route.vertiport_ids.len() < 2 {
+            return Err(InteropError::InvalidRoute(format!(
+                "Single vertiport id: {}",
+                departure
+            )));
+        }
+        if route.arrival_time <= route.departure_time {
+            return Err(InteropError::InvalidRoute(format!(
+                "Arrival at {} is not after departure at {}",
+                route.arrival_time, route.departure_time
+            )));
+        }
+        if !route.distance_km.is_finite() || route.distance_km < 0.0 {
+            return Err(InteropError::InvalidRoute(format!(
+                "Invalid distance: {} km",
+                route.distance_km
+            )));
+        }
+        Ok(FlightPlanData {
+            departure_vertiport_id: Some(departure.clone()),
+            destination_vertiport_id: Some(destination.clone()),
+            scheduled_departure: Some(StorageTime(route.departure_time).into()),
+            scheduled_arrival: Some(StorageTime(route.arrival_time).into()),
+            flight_distance_meters: (route.distance_km * 1000.0).round() as i64,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod interop_tests {
+    use super::*;
+    use crate::resources::vertiport::Data as VertiportData;
+    use chrono::{Duration, TimeZone};
+
+    fn vertiport(latitude: f64, longitude: f64) -> Vertiport {
+        Vertiport {
+            id: "vertiport_1".to_string(),
+            data: Some(VertiportData {
+                latitude,
+                longitude,
+                schedule: Some("FREQ=DAILY".to_string()),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_node_from_vertiport() {
+        let node = Node::try_from(&vertiport(40.730610, -73.935242)).unwrap();
+        assert_eq!(
+            node,
+            Node::new("vertiport_1", 40.730610, -73.935242).with_schedule("FREQ=DAILY")
+        );
+
+        assert!(matches!(
+            Node::try_from(&vertiport(91.0, 0.0)),
+            Err(InteropError::InvalidCoordinates { .. })
+        ));
+        assert!(Node::try_from(&vertiport(0.0, f64::NAN)).is_err());
+        let missing = Vertiport {
+            id: "vertiport_2".to_string(),
+            data: None,
+        };
+        assert_eq!(
+            Node::try_from(&missing),
+            Err(InteropError::MissingData("vertiport_2".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_storage_time() {
+        let timestamp = Timestamp {
+            seconds: 1_700_000_000,
+            nanos: 500,
+        };
+        let time = StorageTime::try_from(&timestamp).unwrap();
+        assert_eq!(Timestamp::from(time), timestamp);
+
+        let invalid = Timestamp {
+            seconds: 0,
+            nanos: -1,
+        };
+        assert!(matches!(
+            StorageTime::try_from(&invalid),
+            Err(InteropError::InvalidTime(_))
+        ));
+    }
+
+    #[test]
+    fn test_flight_plan_from_route() {
+        let departure_time = Tz::UTC.with_ymd_and_hms(2023, 5, 1, 10, 0, 0).unwrap();
+        let mut route = Route {
+            vertiport_ids: vec!["A".to_string(), "B".to_string(), "C".to_string()],
+            locations: vec![],
+            distance_km: 12.3456,
+            departure_time,
+            arrival_time: departure_time + Duration::minutes(30),
+        };
+        let data = FlightPlanData::try_from(&route).unwrap();
+        assert_eq!(data.departure_vertiport_id.as_deref(), Some("A"));
+        assert_eq!(data.destination_vertiport_id.as_deref(), Some("C"));
+        assert_eq!(
+            data.scheduled_departure,
+            Some(datetime_to_timestamp(&departure_time))
+        );
+        assert_eq!(data.flight_distance_meters, 12346);
+        assert!(data.vehicle_id.is_empty());
+
+        route.arrival_time = departure_time;
+        assert!(FlightPlanData::try_from(&route).is_err());
+        route.arrival_time = departure_time + Duration::minutes(30);
+        route.vertiport_ids.truncate(1);
+        let error = String::from(FlightPlanData::try_from(&route).unwrap_err());
+        assert_eq!(error, "Invalid route: Single vertiport id: A");
+    }
+}
@@ -0,0 +1,288 @@
+//! KML and KMZ export of routes, nodes and geofences.
+//!
+//! A [`KmlDocument`] collects placemarks which operations staff can inspect
+//! in Google Earth or any other KML viewer. Routes are drawn at the altitude
+//! of their locations and extruded to the ground, so their vertical profile
+//! shows in 3D views. Geofences are drawn as polygons, extruded up to their
+//! ceiling if they have one.
+//!
+//! [`KmlDocument::to_kmz`] packs the document as `doc.kml` in an
+//! uncompressed ZIP archive, the format expected for `.kmz` files.
+
+use crate::location::Location;
+use crate::node::Node;
+
+/// Name of the document in a KMZ archive
+const KMZ_DOCUMENT_NAME: &str = "doc.kml";
+
+/// KML document under construction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KmlDocument {
+    /// name of the document
+    name: String,
+    /// KML elements of the placemarks and folders
+    features: Vec<String>,
+}
+
+/// Escapes the characters reserved in XML text
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Formats locations as KML coordinates, e.g. `-122.4194,37.7749,120`
+fn coordinates(locations: &[Location], altitude: impl Fn(&Location) -> f32) -> String {
+    locations
+        .iter()
+        .map(|location| {
+            format!(
+                "{},{},{}",
+                location.longitude.into_inner(),
+                location.latitude.into_inner(),
+                altitude(location)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl KmlDocument {
+    /// Creates an empty document
+    pub fn new(name: &str) -> Self {
+        KmlDocument {
+            name: name.to_string(),
+            features: vec![],
+        }
+    }
+
+    /// Adds a route drawn at the altitudes of its locations and extruded to the ground
+    pub fn add_route(&mut self, name: &str, route: &[Location]) -> &mut Self {
+        self.features.push(format!(
+            "<Placemark><name>{}</name><LineString><extrude>1</extrude><tessellate>1</tessellate>\
+             <altitudeMode>absolute</altitudeMode><coordinates>{}</coordinates></LineString></Placemark>",
+            escape_xml(name),
+            coordinates(route, |location| location.altitude_meters.into_inner())
+        ));
+        self
+    }
+
+    /// Adds a folder with a point for each node, named after its id
+    pub fn add_nodes(&mut self, name: &str, nodes: &[Node]) -> &mut Self {
+        let points: String = nodes
+            .iter()
+            .map(|node| {
+                format!(
+                    "<Placemark><name>{}</name><Point><coordinates>{}</coordinates></Point></Placemark>",
+                    escape_xml(&node.uid),
+                    coordinates(&[node.location], |location| location
+                        .altitude_meters
+                        .into_inner())
+                )
+            })
+            .collect();
+        self.features.push(format!(
+            "<Folder><name>{}</name>{}</Folder>",
+            escape_xml(name),
+            points
+        ));
+        self
+    }
+
+    /// Adds a geofence polygon on the ground, extruded up to `ceiling_meters`
+    /// above the ground if it has a ceiling
+    pub fn add_geofence(
+        &mut self,
+        name: &str,
+        polygon: &[Location],
+        ceiling_meters: Option<f32>,
+    ) -> &mut Self {
+        // the ring of a KML polygon ends with its first location
+        let mut ring = polygon.to_vec();
+        if let Some(first) = polygon.first() {
+            if polygon.last() != Some(first) {
+                ring.push(*first);
+            }
+        }
+        let geometry = match ceiling_meters {
+            Some(ceiling) => format!(
+                "<extrude>1</extrude><altitudeMode>relativeToGround</altitudeMode>\
+                 <outerBoundaryIs><LinearRing><coordinates>{}</coordinates></LinearRing></outerBoundaryIs>",
+                coordinates(&ring, |_| ceiling)
+            ),
+            None => format!(
+                "<altitudeMode>clampToGround</altitudeMode>\
+                 <outerBoundaryIs><LinearRing><coordinates>{}</coordinates></LinearRing></outerBoundaryIs>",
+                coordinates(&ring, |_| 0.0)
+            ),
+        };
+        self.features.push(format!(
+            "<Placemark><name>{}</name><Polygon>{}</Polygon></Placemark>",
+            escape_xml(name),
+            geometry
+        ));
+        self
+    }
+
+    /// Formats the document as KML
+    pub fn to_kml(&self) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <kml xmlns=\"http://www.opengis.net/kml/2.2\"><Document><name>{}</name>\n{}\n</Document></kml>\n",
+            escape_xml(&self.name),
+            self.features.join("\n")
+        )
+    }
+
+    /// Packs the document in a KMZ archive
+    pub fn to_kmz(&self) -> Vec<u8> {
+        zip_stored(KMZ_DOCUMENT_NAME, self.to_kml().as_bytes())
+    }
+}
+
+/// CRC-32 checksum of ZIP archives
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Creates a ZIP archive storing a single file without compression
+fn zip_stored(file_name: &str, data: &[u8]) -> Vec<u8> {
+    let crc = crc32(data).to_le_bytes();
+    let size = (data.len() as u32).to_le_bytes();
+    let name_length = (file_name.len() as u16).to_le_bytes();
+    // version 2.0, no flags, stored, no modification time
+    let common: Vec<u8> = [
+        &[20, 0, 0, 0, 0, 0, 0, 0, 0x21, 0][..],
+        &crc,
+        &size,
+        &size,
+        &name_length,
+        &[0, 0],
+    ]
+    .concat();
+
+    let mut archive: Vec<u8> = vec![];
+    archive.extend_from_slice(&[0x50, 0x4B, 0x03, 0x04]);
+    archive.extend_from_slice(&common);
+    archive.extend_from_slice(file_name.as_bytes());
+    archive.extend_from_slice(data);
+
+    let central_directory_offset = (archive.len() as u32).to_le_bytes();
+    let mut central_directory: Vec<u8> = vec![0x50, 0x4B, 0x01, 0x02, 20, 0];
+    central_directory.extend_from_slice(&common);
+    // no comment, disk 0, no attributes, local header at offset 0
+    central_directory.extend_from_slice(&[0; 14]);
+    central_directory.extend_from_slice(file_name.as_bytes());
+    let central_directory_size = (central_directory.len() as u32).to_le_bytes();
+    archive.extend_from_slice(&central_directory);
+
+    archive.extend_from_slice(&[0x50, 0x4B, 0x05, 0x06, 0, 0, 0, 0, 1, 0, 1, 0]);
+    archive.extend_from_slice(&central_directory_size);
+    archive.extend_from_slice(&central_directory_offset);
+    archive.extend_from_slice(&[0, 0]);
+    archive
+}
+
+#[cfg(test)]
+mod kml_tests {
+    use super::*;
+    use crate::test_support::mock_location_at_altitude;
+
+    fn document() -> KmlDocument {
+        let mut document = KmlDocument::new("Network & routes");
+        document
+            .add_route(
+                "SFO to OAK",
+                &[
+                    mock_location_at_altitude(37.6, -122.4, 0.0),
+                    mock_location_at_altitude(37.7, -122.2, 120.0),
+                ],
+            )
+            .add_nodes(
+                "Vertiports",
+                &[Node::new("SFO", 37.6, -122.4).with_altitude_meters(5.0)],
+            )
+            .add_geofence(
+                "Stadium",
+                &[
+                    mock_location_at_altitude(37.0, -122.0, 0.0),
+                    mock_location_at_altitude(37.0, -121.9, 0.0),
+                    mock_location_at_altitude(37.1, -121.9, 0.0),
+                ],
+                Some(400.0),
+            );
+        document
+    }
+
+    #[test]
+    fn test_to_kml() {
+        let kml = document().to_kml();
+        assert!(kml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>"));
+        assert!(kml.contains("<Document><name>Network &amp; routes</name>"));
+        assert!(kml.contains(
+            "<extrude>1</extrude><tessellate>1</tessellate><altitudeMode>absolute</altitudeMode>\
+             <coordinates>-122.4,37.6,0 -122.2,37.7,120</coordinates>"
+        ));
+        assert!(kml.contains(
+            "<Folder><name>Vertiports</name><Placemark><name>SFO</name><Point><coordinates>-122.4,37.6,5</coordinates>"
+        ));
+        // the ring of the geofence is closed at its ceiling
+        assert!(kml.contains(
+            "<coordinates>-122,37,400 -121.9,37,400 -121.9,37.1,400 -122,37,400</coordinates>"
+        ));
+        assert!(kml.ends_with("</Document></kml>\n"));
+    }
+
+    #[test]
+    fn test_geofence_on_ground() {
+        let mut document = KmlDocument::new("Geofences");
+        document.add_geofence(
+            "Closed",
+            &[
+                mock_location_at_altitude(37.0, -122.0, 0.0),
+                mock_location_at_altitude(37.0, -121.9, 0.0),
+                mock_location_at_altitude(37.1, -121.9, 0.0),
+                mock_location_at_altitude(37.0, -122.0, 0.0),
+            ],
+            None,
+        );
+        let kml = document.to_kml();
+        assert!(kml.contains("<altitudeMode>clampToGround</altitudeMode>"));
+        assert!(kml.contains("-121.9,37.1,0 -122,37,0</coordinates>"));
+        assert!(!kml.contains("-122,37,0 -122,37,0"));
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_to_kmz() {
+        let document = document();
+        let kml = document.to_kml();
+        let kmz = document.to_kmz();
+
+        assert_eq!(&kmz[0..4], &[0x50, 0x4B, 0x03, 0x04]);
+        assert_eq!(&kmz[14..18], &crc32(kml.as_bytes()).to_le_bytes());
+        assert_eq!(&kmz[30..37], KMZ_DOCUMENT_NAME.as_bytes());
+        assert_eq!(&kmz[37..37 + kml.len()], kml.as_bytes());
+
+        // the end of central directory record points to the central directory
+        let end = &kmz[kmz.len() - 22..];
+        assert_eq!(&end[0..4], &[0x50, 0x4B, 0x05, 0x06]);
+        let offset = u32::from_le_bytes([end[16], end[17], end[18], end[19]]) as usize;
+        assert_eq!(offset, 37 + kml.len());
+        assert_eq!(&kmz[offset..offset + 4], &[0x50, 0x4B, 0x01, 0x02]);
+    }
+}
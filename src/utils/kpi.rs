@@ -0,0 +1,428 @@
+//! Fleet and vertiport KPIs computed from flight plans.
+//!
+//! Operators report on the same flight plans the router schedules with.
+//! Flown legs count with their actual times, the others with their
+//! scheduled times. Legs without cargo count as deadhead legs, the way the
+//! [`turnaround`](crate::turnaround) model classifies them. A pad is
+//! occupied for the loading and takeoff time after a departure and for the
+//! landing and unloading time before an arrival, as in
+//! [`is_vertiport_available`](crate::router_state::is_vertiport_available).
+
+use chrono::{DateTime, Duration};
+use prost_types::Timestamp;
+use rrule::Tz;
+use std::collections::BTreeMap;
+
+use crate::router_state::{
+    FlightPlan, Vertipad, LANDING_AND_UNLOADING_TIME_MIN, LOADING_AND_TAKEOFF_TIME_MIN,
+};
+use crate::turnaround::LegKind;
+
+/// Flight time of a vehicle within the reporting period
+#[derive(Debug, Clone, PartialEq)]
+pub struct VehicleUtilization {
+    /// id of the vehicle
+    pub vehicle_id: String,
+    /// hours in the air
+    pub flight_hours: f32,
+    /// hours in the air without cargo
+    pub deadhead_hours: f32,
+    /// fraction of the period spent in the air
+    pub utilization: f32,
+}
+
+/// Utilization of the whole fleet within the reporting period
+#[derive(Debug, Clone, PartialEq)]
+pub struct FleetKpis {
+    /// hours in the air of all vehicles
+    pub flight_hours: f32,
+    /// hours in the air without cargo of all vehicles
+    pub deadhead_hours: f32,
+    /// fraction of the flight hours flown without cargo
+    pub deadhead_ratio: f32,
+    /// utilization by vehicle, ordered by vehicle id
+    pub vehicles: Vec<VehicleUtilization>,
+}
+
+/// Hourly pad occupancy of a vertiport
+#[derive(Debug, Clone, PartialEq)]
+pub struct PadOccupancy {
+    /// id of the vertiport
+    pub vertiport_id: String,
+    /// number of pads of the vertiport
+    pub pads: usize,
+    /// fraction of the pad time occupied in each hour of the period
+    pub hourly_occupancy: Vec<f32>,
+}
+
+/// KPIs of a reporting period
+#[derive(Debug, Clone, PartialEq)]
+pub struct KpiReport {
+    /// start of the period
+    pub period_start: DateTime<Tz>,
+    /// end of the period
+    pub period_end: DateTime<Tz>,
+    /// fleet utilization
+    pub fleet: FleetKpis,
+    /// pad occupancy by vertiport, ordered by vertiport id
+    pub pad_occupancy: Vec<PadOccupancy>,
+    /// mean minutes from the submission of a flight plan to its departure,
+    /// `None` if no flight plan departing in the period records its submission
+    pub average_request_to_departure_minutes: Option<f32>,
+}
+
+/// Times of a leg in seconds since epoch, actual times preferred over scheduled ones
+struct Leg<'a> {
+    vehicle_id: &'a str,
+    departure_vertiport_id: &'a str,
+    destination_vertiport_id: &'a str,
+    kind: LegKind,
+    submitted: Option<i64>,
+    departure: i64,
+    arrival: i64,
+}
+
+fn legs(flight_plans: &[FlightPlan]) -> Vec<Leg<'_>> {
+    let seconds = |actual: &Option<Timestamp>, scheduled: &Option<Timestamp>| {
+        actual
+            .as_ref()
+            .or(scheduled.as_ref())
+            .map(|time| time.seconds)
+    };
+    flight_plans
+        .iter()
+        .filter_map(|flight_plan| {
+            let data = flight_plan.data.as_ref()?;
+            let departure = seconds(&data.actual_departure, &data.scheduled_departure)?;
+            let arrival = seconds(&data.actual_arrival, &data.scheduled_arrival)?;
+            Some(Leg {
+                vehicle_id: &data.vehicle_id,
+                departure_vertiport_id: data.departure_vertiport_id.as_deref().unwrap_or_default(),
+                destination_vertiport_id: data
+                    .destination_vertiport_id
+                    .as_deref()
+                    .unwrap_or_default(),
+                kind: LegKind::of(data),
+                submitted: data.flight_plan_submitted.as_ref().map(|time| time.seconds),
+                departure,
+                arrival,
+            })
+        })
+        .collect()
+}
+
+/// Seconds of the interval `[from, to)` within the period `[start, end)`
+fn overlap_seconds(from: i64, to: i64, start: i64, end: i64) -> i64 {
+    (to.min(end) - from.max(start)).max(0)
+}
+
+/// Computes the utilization of the fleet within the period
+///
+/// # Arguments
+/// * `flight_plans` - flight plans flown or scheduled
+/// * `period_start` - start of the period
+/// * `period_end` - end of the period
+///
+/// # Returns
+/// The flight and deadhead hours of the fleet and of each vehicle. Legs
+/// are clipped to the period.
+pub fn fleet_utilization(
+    flight_plans: &[FlightPlan],
+    period_start: DateTime<Tz>,
+    period_end: DateTime<Tz>,
+) -> FleetKpis {
+    let (start, end) = (period_start.timestamp(), period_end.timestamp());
+    let mut by_vehicle: BTreeMap<&str, (i64, i64)> = BTreeMap::new();
+    let legs = legs(flight_plans);
+    for leg in &legs {
+        let seconds = overlap_seconds(leg.departure, leg.arrival, start, end);
+        if seconds == 0 {
+            continue;
+        }
+        let (flight, deadhead) = by_vehicle.entry(leg.vehicle_id).or_default();
+        *flight += seconds;
+        if leg.kind == LegKind::Deadhead {
+            *deadhead += seconds;
+        }
+    }
+
+    let hours = |seconds: i64| seconds as f32 / 3600.0;
+    let period_hours = hours(end - start);
+    let vehicles: Vec<VehicleUtilization> = by_vehicle
+        .into_iter()
+        .map(|(vehicle_id, (flight, deadhead))| VehicleUtilization {
+            vehicle_id: vehicle_id.to_string(),
+            flight_hours: hours(flight),
+            deadhead_hours: hours(deadhead),
+            utilization: if period_hours > 0.0 {
+                hours(flight) / period_hours
+            } else {
+                0.0
+            },
+        })
+        .collect();
+    let flight_hours: f32 = vehicles.iter().map(|vehicle| vehicle.flight_hours).sum();
+    let deadhead_hours: f32 = vehicles.iter().map(|vehicle| vehicle.deadhead_hours).sum();
+    FleetKpis {
+        flight_hours,
+        deadhead_hours,
+        deadhead_ratio: if flight_hours > 0.0 {
+            deadhead_hours / flight_hours
+        } else {
+            0.0
+        },
+        vehicles,
+    }
+}
+
+/// Computes the hourly pad occupancy of each vertiport within the period
+///
+/// # Arguments
+/// * `flight_plans` - flight plans flown or scheduled
+/// * `vertipads` - vertipads of the vertiports, a vertiport without enabled pads counts as one pad
+/// * `period_start` - start of the first hour
+/// * `period_end` - end of the period, which may cut the last hour short
+///
+/// # Returns
+/// The occupancy of each vertiport with a takeoff or landing in the period
+pub fn pad_occupancy_heatmap(
+    flight_plans: &[FlightPlan],
+    vertipads: &[Vertipad],
+    period_start: DateTime<Tz>,
+    period_end: DateTime<Tz>,
+) -> Vec<PadOccupancy> {
+    let (start, end) = (period_start.timestamp(), period_end.timestamp());
+    let hour = Duration::hours(1).num_seconds();
+    let hours = ((end - start).max(0) + hour - 1) / hour;
+    let takeoff = Duration::minutes(LOADING_AND_TAKEOFF_TIME_MIN as i64).num_seconds();
+    let landing = Duration::minutes(LANDING_AND_UNLOADING_TIME_MIN as i64).num_seconds();
+
+    let mut occupied: BTreeMap<&str, Vec<i64>> = BTreeMap::new();
+    let mut occupy = |vertiport_id, from: i64, to: i64| {
+        for index in 0..hours {
+            let hour_start = start + index * hour;
+            let seconds = overlap_seconds(from, to, hour_start, (hour_start + hour).min(end));
+            if seconds > 0 {
+                occupied
+                    .entry(vertiport_id)
+                    .or_insert_with(|| vec![0; hours as usize])[index as usize] += seconds;
+            }
+        }
+    };
+    let legs = legs(flight_plans);
+    for leg in &legs {
+        occupy(
+            leg.departure_vertiport_id,
+            leg.departure,
+            leg.departure + takeoff,
+        );
+        occupy(
+            leg.destination_vertiport_id,
+            leg.arrival - landing,
+            leg.arrival,
+        );
+    }
+
+    occupied
+        .into_iter()
+        .map(|(vertiport_id, seconds)| {
+            let pads = vertipads
+                .iter()
+                .filter_map(|vertipad| vertipad.data.as_ref())
+                .filter(|data| data.vertiport_id == vertiport_id && data.enabled)
+                .count()
+                .max(1);
+            let hourly_occupancy = seconds
+                .iter()
+                .enumerate()
+                .map(|(index, &seconds)| {
+                    let hour_start = start + index as i64 * hour;
+                    let hour_seconds = (hour_start + hour).min(end) - hour_start;
+                    seconds as f32 / (hour_seconds * pads as i64) as f32
+                })
+                .collect();
+            PadOccupancy {
+                vertiport_id: vertiport_id.to_string(),
+                pads,
+                hourly_occupancy,
+            }
+        })
+        .collect()
+}
+
+/// Computes the mean time from the submission of a flight plan to its departure
+///
+/// # Arguments
+/// * `flight_plans` - flight plans flown or scheduled
+/// * `period_start` - start of the period
+/// * `period_end` - end of the period
+///
+/// # Returns
+/// The mean latency in minutes of the flight plans departing in the period,
+/// `None` if none of them records its submission time
+pub fn average_request_to_departure_minutes(
+    flight_plans: &[FlightPlan],
+    period_start: DateTime<Tz>,
+    period_end: DateTime<Tz>,
+) -> Option<f32> {
+    let (start, end) = (period_start.timestamp(), period_end.timestamp());
+    let latencies: Vec<i64> = legs(flight_plans)
+        .iter()
+        .filter(|leg| leg.departure >= start && leg.departure < end)
+        .filter_map(|leg| Some(leg.departure - leg.submitted?))
+        .collect();
+    if latencies.is_empty() {
+        return None;
+    }
+    Some(latencies.iter().sum::<i64>() as f32 / latencies.len() as f32 / 60.0)
+}
+
+/// Computes all KPIs of the period
+///
+/// # Arguments
+/// * `flight_plans` - flight plans flown or scheduled
+/// * `vertipads` - vertipads of the vertiports
+/// * `period_start` - start of the period
+/// * `period_end` - end of the period
+///
+/// # Returns
+/// The fleet utilization, pad occupancy and request latency of the period
+pub fn compute_kpis(
+    flight_plans: &[FlightPlan],
+    vertipads: &[Vertipad],
+    period_start: DateTime<Tz>,
+    period_end: DateTime<Tz>,
+) -> KpiReport {
+    info!(
+        "Computing KPIs of {} flight plans between {} and {}",
+        flight_plans.len(),
+        period_start,
+        period_end
+    );
+    KpiReport {
+        period_start,
+        period_end,
+        fleet: fleet_utilization(flight_plans, period_start, period_end),
+        pad_occupancy: pad_occupancy_heatmap(flight_plans, vertipads, period_start, period_end),
+        average_request_to_departure_minutes: average_request_to_departure_minutes(
+            flight_plans,
+            period_start,
+            period_end,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod kpi_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use chrono::TimeZone;
+
+    fn start() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap()
+    }
+
+    fn flight_plan(
+        id: &str,
+        vehicle_id: &str,
+        from: &str,
+        to: &str,
+        minutes: (i64, i64),
+        cargo_grams: i64,
+    ) -> FlightPlan {
+        let mut data = create_flight_plan_data(
+            vehicle_id.to_string(),
+            from.to_string(),
+            to.to_string(),
+            start() + Duration::minutes(minutes.0),
+            start() + Duration::minutes(minutes.1),
+        );
+        data.cargo_weight_grams = vec![cargo_grams];
+        FlightPlan {
+            id: id.to_string(),
+            data: Some(data),
+        }
+    }
+
+    fn vertipad(id: &str, vertiport_id: &str) -> Vertipad {
+        Vertipad {
+            id: id.to_string(),
+            data: Some(crate::resources::vertipad::Data {
+                vertiport_id: vertiport_id.to_string(),
+                enabled: true,
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn flight_plans() -> Vec<FlightPlan> {
+        vec![
+            flight_plan("fp1", "v1", "A", "B", (0, 60), 1000),
+            flight_plan("fp2", "v1", "B", "A", (90, 120), 0),
+            flight_plan("fp3", "v2", "B", "A", (30, 90), 500),
+        ]
+    }
+
+    #[test]
+    fn test_fleet_utilization() {
+        let fleet = fleet_utilization(&flight_plans(), start(), start() + Duration::hours(4));
+        assert_eq!(fleet.flight_hours, 2.5);
+        assert_eq!(fleet.deadhead_hours, 0.5);
+        assert_eq!(fleet.deadhead_ratio, 0.2);
+        assert_eq!(fleet.vehicles.len(), 2);
+        assert_eq!(fleet.vehicles[0].vehicle_id, "v1");
+        assert_eq!(fleet.vehicles[0].flight_hours, 1.5);
+        assert_eq!(fleet.vehicles[0].utilization, 0.375);
+
+        // legs are clipped to the period
+        let fleet = fleet_utilization(&flight_plans(), start(), start() + Duration::minutes(30));
+        assert_eq!(fleet.flight_hours, 0.5);
+        assert_eq!(fleet.vehicles.len(), 1);
+    }
+
+    #[test]
+    fn test_pad_occupancy_heatmap() {
+        let vertipads = vec![vertipad("pad-b1", "B"), vertipad("pad-b2", "B")];
+        let heatmap = pad_occupancy_heatmap(
+            &flight_plans(),
+            &vertipads,
+            start(),
+            start() + Duration::hours(2),
+        );
+        assert_eq!(heatmap.len(), 2);
+        let (a, b) = (&heatmap[0], &heatmap[1]);
+        assert_eq!(a.vertiport_id, "A");
+        assert_eq!(a.pads, 1);
+        // takeoff of fp1 in the first hour, landings of fp2 and fp3 in the second
+        assert_eq!(a.hourly_occupancy, vec![10.0 / 60.0, 20.0 / 60.0]);
+        assert_eq!(b.pads, 2);
+        // takeoff of fp3 and landing of fp1 over two pads, then takeoff of fp2
+        assert_eq!(b.hourly_occupancy, vec![20.0 / 120.0, 10.0 / 120.0]);
+    }
+
+    #[test]
+    fn test_request_to_departure_latency() {
+        let mut flight_plans = flight_plans();
+        let end = start() + Duration::hours(4);
+        assert_eq!(
+            average_request_to_departure_minutes(&flight_plans, start(), end),
+            None
+        );
+        let submitted = |minutes: i64| {
+            Some(Timestamp {
+                seconds: (start() + Duration::minutes(minutes)).timestamp(),
+                nanos: 0,
+            })
+        };
+        flight_plans[0].data.as_mut().unwrap().flight_plan_submitted = submitted(-60);
+        flight_plans[2].data.as_mut().unwrap().flight_plan_submitted = submitted(0);
+        assert_eq!(
+            average_request_to_departure_minutes(&flight_plans, start(), end),
+            Some(45.0)
+        );
+
+        let report = compute_kpis(&flight_plans, &[], start(), end);
+        assert_eq!(report.average_request_to_departure_minutes, Some(45.0));
+        assert_eq!(report.pad_occupancy[0].hourly_occupancy.len(), 4);
+    }
+}
@@ -0,0 +1,372 @@
+//! Maintenance of the vehicles after a number of flight hours or cycles.
+//!
+//! A vehicle may be due for maintenance after flying a number of minutes or
+//! cycles (takeoff and landing) since its `last_maintenance`. Intervals are
+//! configured per vehicle id with [`set_maintenance_interval`] and enforced by
+//! the vehicle availability check: a vehicle isn't assigned a flight that
+//! would take it past its due point, counting the flight plans already
+//! scheduled for it. [`propose_maintenance_flight`] positions a vehicle at a
+//! vertiport where it can be maintained.
+
+use chrono::{DateTime, Duration, Utc};
+use rrule::Tz;
+
+use crate::amendment::PlanTimes;
+use crate::context::current_context;
+use crate::router_state::{
+    create_flight_plan_data, estimate_flight_time_between, get_vehicle_free_time,
+    get_vehicle_scheduled_location, FlightPlan, FlightPlanData, Vehicle,
+};
+use crate::time::timestamp_to_datetime;
+
+/// Use of a vehicle counting towards its maintenance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MaintenanceUsage {
+    /// minutes flown, including takeoff and landing
+    pub flight_minutes: i64,
+    /// number of flights
+    pub cycles: u32,
+}
+
+/// Use of a vehicle after which it's due for maintenance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MaintenanceInterval {
+    /// minutes flown before maintenance, unlimited if None
+    pub max_flight_minutes: Option<i64>,
+    /// number of flights before maintenance, unlimited if None
+    pub max_cycles: Option<u32>,
+}
+
+impl MaintenanceInterval {
+    /// Use left before the maintenance is due, unlimited use is reported as `i64::MAX`
+    /// minutes or `u32::MAX` cycles
+    pub fn remaining(&self, usage: &MaintenanceUsage) -> MaintenanceUsage {
+        MaintenanceUsage {
+            flight_minutes: self
+                .max_flight_minutes
+                .map_or(i64::MAX, |max| (max - usage.flight_minutes).max(0)),
+            cycles: self
+                .max_cycles
+                .map_or(u32::MAX, |max| max.saturating_sub(usage.cycles)),
+        }
+    }
+
+    /// Checks if a flight of `flight_minutes` keeps the vehicle within the interval
+    pub fn allows_flight(&self, usage: &MaintenanceUsage, flight_minutes: i64) -> bool {
+        let remaining = self.remaining(usage);
+        remaining.cycles >= 1 && remaining.flight_minutes >= flight_minutes
+    }
+}
+
+/// Gets the earliest departure of the flight plans counting towards the
+/// maintenance of a vehicle, i.e. its last maintenance
+///
+/// # Returns
+/// None if no interval is configured for the vehicle, the earliest time
+/// representable if it has no last maintenance
+pub fn usage_counted_since(vehicle: &Vehicle) -> Option<DateTime<Tz>> {
+    get_maintenance_interval(&vehicle.id)?;
+    let last_maintenance = vehicle
+        .data
+        .as_ref()
+        .and_then(|data| data.last_maintenance.as_ref())
+        .and_then(|time| timestamp_to_datetime(time).ok());
+    Some(last_maintenance.unwrap_or_else(|| DateTime::<Utc>::MIN_UTC.with_timezone(&Tz::UTC)))
+}
+
+/// Gets the use of a vehicle since its last maintenance, counting all its
+/// flight plans departing after the maintenance, past and scheduled
+/// All flight plans count if the vehicle has no last maintenance
+/// `existing_flight_plans` must hold the flight plans of the vehicle since
+/// [`usage_counted_since`], not only those around the requested window
+pub fn get_vehicle_usage(
+    vehicle: &Vehicle,
+    existing_flight_plans: &[FlightPlan],
+) -> MaintenanceUsage {
+    let last_maintenance = vehicle
+        .data
+        .as_ref()
+        .and_then(|data| data.last_maintenance.as_ref())
+        .map_or(i64::MIN, |time| time.seconds);
+    existing_flight_plans
+        .iter()
+        .filter_map(|flight_plan| PlanTimes::from_flight_plan(flight_plan).ok())
+        .filter(|plan| plan.vehicle_id == vehicle.id && plan.departure >= last_maintenance)
+        .fold(MaintenanceUsage::default(), |usage, plan| {
+            MaintenanceUsage {
+                flight_minutes: usage.flight_minutes + (plan.arrival - plan.departure) / 60,
+                cycles: usage.cycles + 1,
+            }
+        })
+}
+
+/// Checks if the vehicle can fly a flight of `flight_minutes` before it's due
+/// for maintenance, always true if no interval is configured for the vehicle
+pub fn is_within_maintenance_interval(
+    vehicle: &Vehicle,
+    flight_minutes: i64,
+    existing_flight_plans: &[FlightPlan],
+) -> bool {
+    let Some(interval) = get_maintenance_interval(&vehicle.id) else {
+        return true;
+    };
+    let usage = get_vehicle_usage(vehicle, existing_flight_plans);
+    if !interval.allows_flight(&usage, flight_minutes) {
+        debug!(
+            "Vehicle {} due for maintenance: flown {:?} of {:?}",
+            vehicle.id, usage, interval
+        );
+        return false;
+    }
+    true
+}
+
+/// Proposes a flight positioning a vehicle at the nearest vertiport it can be
+/// maintained at
+///
+/// # Arguments
+/// * `flight_minutes` - estimates the minutes of flight between two vertiports
+/// * `vehicle` - the vehicle to maintain
+/// * `maintenance_vertiport_ids` - vertiports with maintenance facilities
+/// * `earliest_departure_time` - the vehicle departs once parked after this time
+/// * `existing_flight_plans` - flight plans locating the vehicle
+///
+/// # Returns
+/// None if the vehicle is already at a maintenance vertiport, an error if it
+/// can't be located or no maintenance vertiport is reachable
+pub fn propose_maintenance_flight_with(
+    flight_minutes: impl Fn(&str, &str) -> Result<i64, String>,
+    vehicle: &Vehicle,
+    maintenance_vertiport_ids: &[String],
+    earliest_departure_time: DateTime<Tz>,
+    existing_flight_plans: &[FlightPlan],
+) -> Result<Option<FlightPlanData>, String> {
+    let departure_time =
+        get_vehicle_free_time(vehicle, earliest_departure_time, existing_flight_plans);
+    let (vertiport_id, _) =
+        get_vehicle_scheduled_location(vehicle, departure_time, existing_flight_plans)?;
+    if maintenance_vertiport_ids.contains(&vertiport_id) {
+        return Ok(None);
+    }
+    let (maintenance_vertiport_id, minutes) = maintenance_vertiport_ids
+        .iter()
+        .filter_map(|id| match flight_minutes(&vertiport_id, id) {
+            Ok(minutes) => Some((id, minutes)),
+            Err(e) => {
+                debug!("Maintenance vertiport {} unreachable: {}", id, e);
+                None
+            }
+        })
+        .min_by_key(|(_, minutes)| *minutes)
+        .ok_or_else(|| {
+            format!(
+                "No maintenance vertiport reachable from vertiport {}",
+                vertiport_id
+            )
+        })?;
+    info!(
+        "Positioning vehicle {} from {} to {} for maintenance",
+        vehicle.id, vertiport_id, maintenance_vertiport_id
+    );
+    Ok(Some(create_flight_plan_data(
+        vehicle.id.clone(),
+        vertiport_id,
+        maintenance_vertiport_id.clone(),
+        departure_time,
+        departure_time + Duration::minutes(minutes),
+    )))
+}
+
+/// Proposes a flight positioning a vehicle at the nearest vertiport it can be
+/// maintained at, along the routes of the router
+/// See [`propose_maintenance_flight_with`]
+pub fn propose_maintenance_flight(
+    vehicle: &Vehicle,
+    maintenance_vertiport_ids: &[String],
+    earliest_departure_time: DateTime<Tz>,
+    existing_flight_plans: &[FlightPlan],
+) -> Result<Option<FlightPlanData>, String> {
+    propose_maintenance_flight_with(
+        estimate_flight_time_between,
+        vehicle,
+        maintenance_vertiport_ids,
+        earliest_departure_time,
+        existing_flight_plans,
+    )
+}
+
+/// Sets the maintenance interval of a vehicle
+pub fn set_maintenance_interval(vehicle_id: &str, interval: MaintenanceInterval) {
+    match current_context().maintenance_intervals.write() {
+        Ok(mut intervals) => {
+            intervals.insert(vehicle_id.to_string(), interval);
+        }
+        Err(_) => error!("Maintenance intervals unavailable"),
+    }
+}
+
+/// Removes the maintenance interval of a vehicle
+pub fn clear_maintenance_interval(vehicle_id: &str) {
+    match current_context().maintenance_intervals.write() {
+        Ok(mut intervals) => {
+            intervals.remove(vehicle_id);
+        }
+        Err(_) => error!("Maintenance intervals unavailable"),
+    }
+}
+
+/// Gets the maintenance interval of a vehicle, if any
+pub fn get_maintenance_interval(vehicle_id: &str) -> Option<MaintenanceInterval> {
+    current_context()
+        .maintenance_intervals
+        .read()
+        .ok()
+        .and_then(|intervals| intervals.get(vehicle_id).copied())
+}
+
+#[cfg(test)]
+mod maintenance_tests {
+    use super::*;
+    use crate::test_support::{mock_start, MockFlightPlan, MockVehicle};
+    use crate::time::datetime_to_timestamp;
+
+    #[test]
+    fn test_vehicle_usage() {
+        let vehicle = MockVehicle::new("maintenance-usage")
+            .with_last_vertiport("SFO")
+            .with_last_maintenance(mock_start())
+            .build();
+        let flight = |id: &str, vehicle_id: &str, from: &str, to: &str, hour: i64| {
+            MockFlightPlan::new(id, vehicle_id, from, to)
+                .with_departure(mock_start() + Duration::hours(hour))
+                .with_duration(Duration::minutes(50))
+                .build()
+        };
+        let flight_plans = vec![
+            // before the last maintenance
+            flight("plan-1", "maintenance-usage", "SFO", "OAK", -2),
+            flight("plan-2", "maintenance-usage", "OAK", "SFO", 1),
+            flight("plan-3", "maintenance-usage", "SFO", "OAK", 3),
+            flight("plan-4", "other", "SFO", "OAK", 3),
+        ];
+        let usage = get_vehicle_usage(&vehicle, &flight_plans);
+        assert_eq!(
+            usage,
+            MaintenanceUsage {
+                flight_minutes: 100,
+                cycles: 2
+            }
+        );
+
+        let interval = MaintenanceInterval {
+            max_flight_minutes: Some(120),
+            max_cycles: None,
+        };
+        assert_eq!(interval.remaining(&usage).flight_minutes, 20);
+        assert_eq!(interval.remaining(&usage).cycles, u32::MAX);
+        assert!(interval.allows_flight(&usage, 20));
+        assert!(!interval.allows_flight(&usage, 21));
+        let interval = MaintenanceInterval {
+            max_flight_minutes: None,
+            max_cycles: Some(2),
+        };
+        assert!(!interval.allows_flight(&usage, 1));
+        assert!(MaintenanceInterval::default().allows_flight(&usage, 1000));
+    }
+
+    #[test]
+    fn test_maintenance_interval_of_vehicle() {
+        let vehicle = MockVehicle::new("maintenance-interval")
+            .with_last_vertiport("SFO")
+            .with_last_maintenance(mock_start())
+            .build();
+        let flight_plans =
+            vec![
+                MockFlightPlan::new("plan-1", "maintenance-interval", "SFO", "OAK")
+                    .with_departure(mock_start() + Duration::hours(1))
+                    .with_duration(Duration::minutes(50))
+                    .build(),
+            ];
+        assert!(is_within_maintenance_interval(&vehicle, 500, &flight_plans));
+
+        set_maintenance_interval(
+            "maintenance-interval",
+            MaintenanceInterval {
+                max_flight_minutes: Some(100),
+                max_cycles: Some(10),
+            },
+        );
+        assert!(is_within_maintenance_interval(&vehicle, 50, &flight_plans));
+        assert!(!is_within_maintenance_interval(&vehicle, 51, &flight_plans));
+        assert_eq!(usage_counted_since(&vehicle), Some(mock_start()));
+        let unmaintained = Vehicle {
+            data: None,
+            ..vehicle.clone()
+        };
+        assert_eq!(
+            usage_counted_since(&unmaintained).map(|time| time.timestamp()),
+            Some(DateTime::<Utc>::MIN_UTC.timestamp())
+        );
+        clear_maintenance_interval("maintenance-interval");
+        assert!(get_maintenance_interval("maintenance-interval").is_none());
+        assert!(usage_counted_since(&vehicle).is_none());
+    }
+
+    #[test]
+    fn test_propose_maintenance_flight() {
+        let vehicle = MockVehicle::new("maintenance-flight")
+            .with_last_vertiport("SFO")
+            .with_last_maintenance(mock_start())
+            .build();
+        let flight_plans = vec![
+            MockFlightPlan::new("plan-1", "maintenance-flight", "SFO", "OAK")
+                .with_departure(mock_start() + Duration::hours(1))
+                .with_duration(Duration::minutes(50))
+                .build(),
+        ];
+        let minutes = |from: &str, to: &str| match (from, to) {
+            ("OAK", "SJC") => Ok(40),
+            ("OAK", "HUB") => Ok(25),
+            _ => Err("No route".to_string()),
+        };
+        let maintenance_vertiport_ids = vec!["SJC".to_string(), "HUB".to_string()];
+
+        let flight = propose_maintenance_flight_with(
+            minutes,
+            &vehicle,
+            &maintenance_vertiport_ids,
+            mock_start(),
+            &flight_plans,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(flight.departure_vertiport_id.as_deref(), Some("OAK"));
+        assert_eq!(flight.destination_vertiport_id.as_deref(), Some("HUB"));
+        // departs once the vehicle landed at OAK
+        assert_eq!(
+            flight.scheduled_departure,
+            Some(datetime_to_timestamp(
+                &(mock_start() + Duration::minutes(110))
+            ))
+        );
+
+        // parked at a maintenance vertiport
+        assert!(propose_maintenance_flight_with(
+            minutes,
+            &vehicle,
+            &["SFO".to_string()],
+            mock_start(),
+            &[]
+        )
+        .unwrap()
+        .is_none());
+        assert!(propose_maintenance_flight_with(
+            minutes,
+            &vehicle,
+            &["LAX".to_string()],
+            mock_start(),
+            &flight_plans
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,148 @@
+//! Counters and histograms of the routing layer.
+//!
+//! When a [`MetricsRecorder`] is set with [`set_metrics_recorder`], the
+//! router reports the metrics named by the constants of this module, e.g.
+//! the number of routes computed and the duration of the availability checks
+//! of each query. Implement the trait to forward them to an exporter such as
+//! Prometheus; [`MemoryMetrics`] keeps them in memory.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::context::current_context;
+
+/// Counter of the routes computed between two vertiports
+pub const ROUTES_COMPUTED: &str = "router_routes_computed_total";
+/// Counter of the candidate departure slots evaluated
+pub const SLOTS_EVALUATED: &str = "router_slots_evaluated_total";
+/// Histogram of the time spent checking vertiport and vehicle availability
+/// for a query, in seconds
+pub const AVAILABILITY_CHECK_SECONDS: &str = "router_availability_check_seconds";
+/// Histogram of the flight plans returned per query
+pub const PLANS_RETURNED: &str = "router_plans_returned";
+
+/// Destination of the metrics
+pub trait MetricsRecorder: Send + Sync {
+    /// Adds `value` to the counter `name`
+    fn increment_counter(&self, name: &'static str, value: u64);
+
+    /// Records an observation of the histogram `name`
+    fn record_histogram(&self, name: &'static str, value: f64);
+}
+
+/// Metrics recorder keeping the metrics in memory
+#[derive(Debug, Default)]
+pub struct MemoryMetrics {
+    counters: Mutex<HashMap<&'static str, u64>>,
+    histograms: Mutex<HashMap<&'static str, Vec<f64>>>,
+}
+
+impl MemoryMetrics {
+    /// Creates a recorder without metrics
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the value of a counter, 0 if never incremented
+    pub fn counter(&self, name: &str) -> u64 {
+        self.counters
+            .lock()
+            .map(|counters| counters.get(name).copied().unwrap_or_default())
+            .unwrap_or_default()
+    }
+
+    /// Gets the observations of a histogram in the order recorded
+    pub fn histogram(&self, name: &str) -> Vec<f64> {
+        self.histograms
+            .lock()
+            .map(|histograms| histograms.get(name).cloned().unwrap_or_default())
+            .unwrap_or_default()
+    }
+}
+
+impl MetricsRecorder for MemoryMetrics {
+    fn increment_counter(&self, name: &'static str, value: u64) {
+        match self.counters.lock() {
+            Ok(mut counters) => *counters.entry(name).or_default() += value,
+            Err(_) => error!("Metrics unavailable"),
+        }
+    }
+
+    fn record_histogram(&self, name: &'static str, value: f64) {
+        match self.histograms.lock() {
+            Ok(mut histograms) => histograms.entry(name).or_default().push(value),
+            Err(_) => error!("Metrics unavailable"),
+        }
+    }
+}
+
+/// Reports all following metrics to the recorder
+pub fn set_metrics_recorder(recorder: Arc<dyn MetricsRecorder>) {
+    match current_context().metrics_recorder.write() {
+        Ok(mut current) => *current = Some(recorder),
+        Err(_) => error!("Metrics unavailable"),
+    }
+}
+
+/// Stops reporting metrics
+pub fn clear_metrics_recorder() {
+    match current_context().metrics_recorder.write() {
+        Ok(mut current) => *current = None,
+        Err(_) => error!("Metrics unavailable"),
+    }
+}
+
+fn get_metrics_recorder() -> Option<Arc<dyn MetricsRecorder>> {
+    match current_context().metrics_recorder.read() {
+        Ok(recorder) => recorder.clone(),
+        Err(_) => {
+            error!("Metrics unavailable");
+            None
+        }
+    }
+}
+
+/// Adds `value` to a counter, if a recorder is set
+pub(crate) fn increment_counter(name: &'static str, value: u64) {
+    if let Some(recorder) = get_metrics_recorder() {
+        recorder.increment_counter(name, value);
+    }
+}
+
+/// Records an observation of a histogram, if a recorder is set
+pub(crate) fn record_histogram(name: &'static str, value: f64) {
+    if let Some(recorder) = get_metrics_recorder() {
+        recorder.record_histogram(name, value);
+    }
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_metrics() {
+        let metrics = MemoryMetrics::new();
+        metrics.increment_counter(SLOTS_EVALUATED, 3);
+        metrics.increment_counter(SLOTS_EVALUATED, 2);
+        metrics.record_histogram(PLANS_RETURNED, 4.0);
+        metrics.record_histogram(PLANS_RETURNED, 0.0);
+        assert_eq!(metrics.counter(SLOTS_EVALUATED), 5);
+        assert_eq!(metrics.counter(ROUTES_COMPUTED), 0);
+        assert_eq!(metrics.histogram(PLANS_RETURNED), vec![4.0, 0.0]);
+        assert!(metrics.histogram(AVAILABILITY_CHECK_SECONDS).is_empty());
+    }
+
+    #[test]
+    fn test_global_recorder() {
+        let metrics = Arc::new(MemoryMetrics::new());
+        set_metrics_recorder(metrics.clone());
+        increment_counter(ROUTES_COMPUTED, 1);
+        record_histogram(AVAILABILITY_CHECK_SECONDS, 0.5);
+        clear_metrics_recorder();
+        increment_counter(ROUTES_COMPUTED, 1);
+        // other tests may compute routes while the recorder is set
+        assert!(metrics.counter(ROUTES_COMPUTED) >= 1);
+        assert!(metrics.histogram(AVAILABILITY_CHECK_SECONDS).contains(&0.5));
+    }
+}
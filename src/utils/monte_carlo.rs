@@ -0,0 +1,257 @@
+//! Monte Carlo simulation of delay propagation through a schedule.
+//!
+//! A delayed flight delays the next flights of its vehicle unless the
+//! schedule has enough slack between them. [`simulate_delay_propagation`]
+//! replays a day's flight plans many times with randomly perturbed flight
+//! durations and turnaround times and reports how often each flight, and the
+//! flights departing from each vertiport, inherit a delay from an earlier
+//! flight. Flights with a high probability of knock-on delays mark the
+//! fragile parts of the schedule.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::amendment::PlanTimes;
+use crate::router_state::FlightPlan;
+
+/// Random variations applied to each run of the simulation
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DelayPerturbation {
+    /// max relative deviation of a flight duration, e.g. 0.2 for up to 20% shorter or longer
+    pub flight_duration_variation: f32,
+    /// mean of the exponentially distributed extra turnaround time after a flight
+    pub mean_turnaround_delay_minutes: f32,
+}
+
+/// Configuration of a delay propagation simulation
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DelaySimulationConfig {
+    /// number of simulated runs
+    pub runs: usize,
+    /// seed of the random number generator, for reproducible results
+    pub seed: u64,
+    /// random variations of the schedule
+    pub perturbation: DelayPerturbation,
+    /// min departure delay in minutes counted as a knock-on delay
+    pub delay_threshold_minutes: i64,
+}
+
+/// Simulated delays of a flight plan
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlightDelayStats {
+    /// id of the flight plan
+    pub flight_plan_id: String,
+    /// fraction of the runs in which the departure was delayed by an earlier flight
+    pub knock_on_delay_probability: f32,
+    /// mean departure delay in minutes
+    pub mean_departure_delay_minutes: f32,
+    /// mean arrival delay in minutes, negative if early
+    pub mean_arrival_delay_minutes: f32,
+}
+
+/// Simulated delays of the flights departing from a vertiport
+#[derive(Debug, Clone, PartialEq)]
+pub struct VertiportDelayStats {
+    /// id of the vertiport
+    pub vertiport_id: String,
+    /// fraction of the departures delayed by an earlier flight
+    pub knock_on_delay_probability: f32,
+    /// mean departure delay in minutes
+    pub mean_departure_delay_minutes: f32,
+}
+
+/// Result of a delay propagation simulation
+#[derive(Debug, Clone, PartialEq)]
+pub struct DelaySimulationReport {
+    /// delays by flight plan, in the order of the input
+    pub flights: Vec<FlightDelayStats>,
+    /// delays by departure vertiport, ordered by vertiport id
+    pub vertiports: Vec<VertiportDelayStats>,
+}
+
+/// Delays of a flight plan summed over all runs
+#[derive(Default)]
+struct DelayTotals {
+    knock_on_delays: usize,
+    departure_delay_seconds: i64,
+    arrival_delay_seconds: i64,
+}
+
+/// Samples the exponential distribution with the given mean
+fn sample_exponential(rng: &mut StdRng, mean: f32) -> f32 {
+    if mean <= 0.0 {
+        return 0.0;
+    }
+    -mean * (1.0 - rng.gen::<f32>()).ln()
+}
+
+/// Simulates the propagation of random delays through the flight plans
+///
+/// # Arguments
+/// * `flight_plans` - scheduled flight plans of the day
+/// * `config` - number of runs, seed and random variations
+///
+/// # Returns
+/// The knock-on delay probabilities by flight plan and by vertiport.
+/// Flight plans without times are left out.
+pub fn simulate_delay_propagation(
+    flight_plans: &[FlightPlan],
+    config: &DelaySimulationConfig,
+) -> DelaySimulationReport {
+    let plans: Vec<PlanTimes> = flight_plans
+        .iter()
+        .filter_map(|flight_plan| PlanTimes::from_flight_plan(flight_plan).ok())
+        .collect();
+    info!(
+        "Simulating delay propagation of {} flight plans in {} runs",
+        plans.len(),
+        config.runs
+    );
+
+    // flights of each vehicle in order of departure
+    let mut chains: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, plan) in plans.iter().enumerate() {
+        chains.entry(&plan.vehicle_id).or_default().push(index);
+    }
+    let mut chains: Vec<Vec<usize>> = chains.into_values().collect();
+    for chain in &mut chains {
+        chain.sort_by_key(|&index| (plans[index].departure, plans[index].id.clone()));
+    }
+    // a fixed order keeps the random draws reproducible
+    chains.sort_by_key(|chain| plans[chain[0]].vehicle_id.clone());
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let variation = config.perturbation.flight_duration_variation.abs();
+    let threshold_seconds = config.delay_threshold_minutes * 60;
+    let mut totals: Vec<DelayTotals> = plans.iter().map(|_| DelayTotals::default()).collect();
+    for _ in 0..config.runs {
+        for chain in &chains {
+            let mut ready = i64::MIN;
+            for &index in chain {
+                let plan = &plans[index];
+                let departure = plan.departure.max(ready);
+                let factor = 1.0 + rng.gen_range(-variation..=variation);
+                let duration = ((plan.arrival - plan.departure) as f32 * factor).round() as i64;
+                let arrival = departure + duration;
+                let turnaround =
+                    sample_exponential(&mut rng, config.perturbation.mean_turnaround_delay_minutes);
+                ready = arrival + (turnaround * 60.0).round() as i64;
+
+                let total = &mut totals[index];
+                let departure_delay = departure - plan.departure;
+                if departure_delay >= threshold_seconds.max(1) {
+                    total.knock_on_delays += 1;
+                }
+                total.departure_delay_seconds += departure_delay;
+                total.arrival_delay_seconds += arrival - plan.arrival;
+            }
+        }
+    }
+
+    let runs = config.runs.max(1) as f32;
+    let flights: Vec<FlightDelayStats> = plans
+        .iter()
+        .zip(&totals)
+        .map(|(plan, total)| FlightDelayStats {
+            flight_plan_id: plan.id.clone(),
+            knock_on_delay_probability: total.knock_on_delays as f32 / runs,
+            mean_departure_delay_minutes: total.departure_delay_seconds as f32 / runs / 60.0,
+            mean_arrival_delay_minutes: total.arrival_delay_seconds as f32 / runs / 60.0,
+        })
+        .collect();
+
+    let mut by_vertiport: BTreeMap<&str, (usize, DelayTotals)> = BTreeMap::new();
+    for (plan, total) in plans.iter().zip(&totals) {
+        let (departures, vertiport_total) = by_vertiport
+            .entry(&plan.departure_vertiport_id)
+            .or_default();
+        *departures += 1;
+        vertiport_total.knock_on_delays += total.knock_on_delays;
+        vertiport_total.departure_delay_seconds += total.departure_delay_seconds;
+    }
+    let vertiports = by_vertiport
+        .into_iter()
+        .map(|(vertiport_id, (departures, total))| {
+            let samples = departures as f32 * runs;
+            VertiportDelayStats {
+                vertiport_id: vertiport_id.to_string(),
+                knock_on_delay_probability: total.knock_on_delays as f32 / samples,
+                mean_departure_delay_minutes: total.departure_delay_seconds as f32 / samples / 60.0,
+            }
+        })
+        .collect();
+
+    DelaySimulationReport {
+        flights,
+        vertiports,
+    }
+}
+
+#[cfg(test)]
+mod monte_carlo_tests {
+    use super::*;
+    use crate::test_support::mock_flight_plan;
+
+    fn config(variation: f32, mean_turnaround_delay_minutes: f32) -> DelaySimulationConfig {
+        DelaySimulationConfig {
+            runs: 500,
+            seed: 7,
+            perturbation: DelayPerturbation {
+                flight_duration_variation: variation,
+                mean_turnaround_delay_minutes,
+            },
+            delay_threshold_minutes: 1,
+        }
+    }
+
+    /// v1 flies back to back, v2 has an hour of slack
+    fn flight_plans() -> Vec<FlightPlan> {
+        vec![
+            mock_flight_plan("tight-1", "v1", "A", "B", 0),
+            mock_flight_plan("tight-2", "v1", "B", "A", 30),
+            mock_flight_plan("slack-1", "v2", "C", "D", 0),
+            mock_flight_plan("slack-2", "v2", "D", "C", 90),
+        ]
+    }
+
+    #[test]
+    fn test_no_perturbation_no_delays() {
+        let report = simulate_delay_propagation(&flight_plans(), &config(0.0, 0.0));
+        assert!(report
+            .flights
+            .iter()
+            .all(|flight| flight.knock_on_delay_probability == 0.0
+                && flight.mean_arrival_delay_minutes == 0.0));
+        assert_eq!(report.vertiports.len(), 4);
+    }
+
+    #[test]
+    fn test_tight_connections_are_fragile() {
+        let report = simulate_delay_propagation(&flight_plans(), &config(0.2, 5.0));
+        let probability = |id: &str| {
+            report
+                .flights
+                .iter()
+                .find(|flight| flight.flight_plan_id == id)
+                .unwrap()
+                .knock_on_delay_probability
+        };
+        assert_eq!(probability("tight-1"), 0.0);
+        assert_eq!(probability("slack-1"), 0.0);
+        assert!(probability("tight-2") > 0.5);
+        assert!(probability("slack-2") < 0.05);
+        let vertiport_b = &report.vertiports[1];
+        assert_eq!(vertiport_b.vertiport_id, "B");
+        assert_eq!(
+            vertiport_b.knock_on_delay_probability,
+            probability("tight-2")
+        );
+
+        // the same seed gives the same report
+        assert_eq!(
+            report,
+            simulate_delay_propagation(&flight_plans(), &config(0.2, 5.0))
+        );
+    }
+}
@@ -0,0 +1,378 @@
+//! Multi-stop pickup-and-delivery routing for a single aircraft.
+//!
+//! A [`MultiStopRequest`] bundles several shipments which are picked up and
+//! delivered by one vehicle. [`plan_multi_stop`] orders the stops so that
+//! every shipment is picked up before it is delivered, the payload limit is
+//! never exceeded on any leg and all time windows are met, preferring the
+//! ordering with the earliest final arrival. The result is a chain of flight
+//! plans, one per leg between different vertiports.
+//!
+//! The flight plans are not checked against existing flight plans, use
+//! [`validate_flight_plans`](crate::conflict::validate_flight_plans) for that.
+
+use chrono::{DateTime, Duration};
+use rrule::Tz;
+
+use crate::router_state::{create_flight_plan_data, estimate_flight_time_between, FlightPlanData};
+
+/// Max number of shipments in a single multi-stop request, the search over
+/// stop orderings grows factorially
+pub const MAX_MULTI_STOP_SHIPMENTS: usize = 6;
+
+/// A shipment picked up at one vertiport and delivered to another
+#[derive(Debug, Clone)]
+pub struct Shipment {
+    /// id of the shipment
+    pub id: String,
+    /// vertiport id where the shipment is picked up
+    pub pickup_vertiport_id: String,
+    /// vertiport id where the shipment is delivered
+    pub delivery_vertiport_id: String,
+    /// weight of the shipment
+    pub weight_grams: i64,
+    /// shipment is ready for pickup from this time
+    pub earliest_pickup_time: DateTime<Tz>,
+    /// shipment must be delivered by this time
+    pub latest_delivery_time: DateTime<Tz>,
+}
+
+/// Request for a vehicle to serve several shipments in one trip
+#[derive(Debug, Clone)]
+pub struct MultiStopRequest {
+    /// vehicle serving the shipments
+    pub vehicle_id: String,
+    /// vertiport where the vehicle is parked
+    pub start_vertiport_id: String,
+    /// time from which the vehicle is available
+    pub start_time: DateTime<Tz>,
+    /// payload limit of the vehicle
+    pub capacity_grams: i64,
+    /// shipments to pick up and deliver
+    pub shipments: Vec<Shipment>,
+}
+
+/// Type of a stop
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StopKind {
+    /// shipment is loaded
+    Pickup,
+    /// shipment is unloaded
+    Delivery,
+}
+
+/// A stop of a multi-stop plan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stop {
+    /// id of the shipment loaded or unloaded
+    pub shipment_id: String,
+    /// pickup or delivery
+    pub kind: StopKind,
+    /// vertiport of the stop
+    pub vertiport_id: String,
+    /// time the vehicle is at the vertiport
+    pub time: DateTime<Tz>,
+}
+
+/// Ordered stops and the chained flight plans serving them
+#[derive(Debug, Clone)]
+pub struct MultiStopPlan {
+    /// stops in the order they are served
+    pub stops: Vec<Stop>,
+    /// flight plans between the stops, with the cargo on board for each leg
+    pub flight_plans: Vec<FlightPlanData>,
+}
+
+/// Stops as (shipment index, kind)
+type StopOrder = Vec<(usize, StopKind)>;
+
+/// Search state of the branch and bound over stop orderings
+struct Search<'a, F> {
+    request: &'a MultiStopRequest,
+    flight_minutes: F,
+    /// current partial ordering
+    order: StopOrder,
+    /// final arrival and ordering of the best complete ordering so far
+    best: Option<(DateTime<Tz>, StopOrder)>,
+}
+
+impl<F> Search<'_, F>
+where
+    F: Fn(&str, &str) -> Option<i64>,
+{
+    fn run(
+        &mut self,
+        vertiport_id: &str,
+        time: DateTime<Tz>,
+        load_grams: i64,
+        picked: &mut [bool],
+        delivered: &mut [bool],
+    ) {
+        if let Some((best_time, _)) = &self.best {
+            if time >= *best_time {
+                return;
+            }
+        }
+        if delivered.iter().all(|delivered| *delivered) {
+            self.best = Some((time, self.order.clone()));
+            return;
+        }
+        for (index, shipment) in self.request.shipments.iter().enumerate() {
+            let (kind, next_vertiport_id) = if !picked[index] {
+                (StopKind::Pickup, &shipment.pickup_vertiport_id)
+            } else if !delivered[index] {
+                (StopKind::Delivery, &shipment.delivery_vertiport_id)
+            } else {
+                continue;
+            };
+            let arrival = if next_vertiport_id == vertiport_id {
+                time
+            } else {
+                let Some(minutes) = (self.flight_minutes)(vertiport_id, next_vertiport_id) else {
+                    continue;
+                };
+                time + Duration::minutes(minutes)
+            };
+            self.order.push((index, kind));
+            match kind {
+                StopKind::Pickup => {
+                    if load_grams + shipment.weight_grams <= self.request.capacity_grams {
+                        picked[index] = true;
+                        self.run(
+                            next_vertiport_id,
+                            arrival.max(shipment.earliest_pickup_time),
+                            load_grams + shipment.weight_grams,
+                            picked,
+                            delivered,
+                        );
+                        picked[index] = false;
+                    }
+                }
+                StopKind::Delivery => {
+                    if arrival <= shipment.latest_delivery_time {
+                        delivered[index] = true;
+                        self.run(
+                            next_vertiport_id,
+                            arrival,
+                            load_grams - shipment.weight_grams,
+                            picked,
+                            delivered,
+                        );
+                        delivered[index] = false;
+                    }
+                }
+            }
+            self.order.pop();
+        }
+    }
+}
+
+/// Plans the stops of a multi-stop request using the given flight time estimate
+///
+/// # Arguments
+/// * `request` - vehicle and shipments to serve
+/// * `flight_minutes` - flight time in minutes between two vertiports, `None` if there is no route
+///
+/// # Returns
+/// The stops and flight plans with the earliest final arrival
+pub fn plan_multi_stop_with<F>(
+    request: &MultiStopRequest,
+    flight_minutes: F,
+) -> Result<MultiStopPlan, String>
+where
+    F: Fn(&str, &str) -> Option<i64>,
+{
+    info!(
+        "Planning {} shipments for vehicle {}",
+        request.shipments.len(),
+        request.vehicle_id
+    );
+    if request.shipments.is_empty() {
+        return Err("No shipments in the request".to_string());
+    }
+    if request.shipments.len() > MAX_MULTI_STOP_SHIPMENTS {
+        return Err(format!(
+            "Too many shipments in the request, max is {}",
+            MAX_MULTI_STOP_SHIPMENTS
+        ));
+    }
+    let mut search = Search {
+        request,
+        flight_minutes,
+        order: vec![],
+        best: None,
+    };
+    let count = request.shipments.len();
+    search.run(
+        &request.start_vertiport_id,
+        request.start_time,
+        0,
+        &mut vec![false; count],
+        &mut vec![false; count],
+    );
+    let Some((_, order)) = search.best else {
+        return Err("No stop order satisfies capacity and time windows".to_string());
+    };
+
+    // replay the best order to build the stops and flight plans
+    let mut stops: Vec<Stop> = vec![];
+    let mut flight_plans: Vec<FlightPlanData> = vec![];
+    let mut vertiport_id = request.start_vertiport_id.clone();
+    let mut time = request.start_time;
+    let mut on_board: Vec<i64> = vec![];
+    for (index, kind) in order {
+        let shipment = &request.shipments[index];
+        let next_vertiport_id = match kind {
+            StopKind::Pickup => &shipment.pickup_vertiport_id,
+            StopKind::Delivery => &shipment.delivery_vertiport_id,
+        };
+        if *next_vertiport_id != vertiport_id {
+            let minutes = (search.flight_minutes)(&vertiport_id, next_vertiport_id)
+                .ok_or("Route between stops not found")?;
+            let mut flight_plan = create_flight_plan_data(
+                request.vehicle_id.clone(),
+                vertiport_id.clone(),
+                next_vertiport_id.clone(),
+                time,
+                time + Duration::minutes(minutes),
+            );
+            flight_plan.cargo_weight_grams = on_board.clone();
+            flight_plans.push(flight_plan);
+            time += Duration::minutes(minutes);
+            vertiport_id = next_vertiport_id.clone();
+        }
+        match kind {
+            StopKind::Pickup => {
+                time = time.max(shipment.earliest_pickup_time);
+                on_board.push(shipment.weight_grams);
+            }
+            StopKind::Delivery => {
+                if let Some(position) = on_board
+                    .iter()
+                    .position(|weight| *weight == shipment.weight_grams)
+                {
+                    on_board.remove(position);
+                }
+            }
+        }
+        stops.push(Stop {
+            shipment_id: shipment.id.clone(),
+            kind,
+            vertiport_id: vertiport_id.clone(),
+            time,
+        });
+    }
+    debug!("Multi-stop plan: {:?}", stops);
+    Ok(MultiStopPlan {
+        stops,
+        flight_plans,
+    })
+}
+
+/// Plans the stops of a multi-stop request with flight times estimated from the router
+pub fn plan_multi_stop(request: &MultiStopRequest) -> Result<MultiStopPlan, String> {
+    plan_multi_stop_with(request, |from, to| {
+        estimate_flight_time_between(from, to).ok()
+    })
+}
+
+#[cfg(test)]
+mod multistop_tests {
+    use super::*;
+    use crate::test_support::mock_flight_minutes;
+    use chrono::TimeZone;
+
+    fn start() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2022, 10, 25, 10, 0, 0).unwrap()
+    }
+
+    fn shipment(id: &str, from: &str, to: &str, weight_grams: i64) -> Shipment {
+        Shipment {
+            id: id.to_string(),
+            pickup_vertiport_id: from.to_string(),
+            delivery_vertiport_id: to.to_string(),
+            weight_grams,
+            earliest_pickup_time: start(),
+            latest_delivery_time: start() + Duration::hours(4),
+        }
+    }
+
+    fn request(shipments: Vec<Shipment>, capacity_grams: i64) -> MultiStopRequest {
+        MultiStopRequest {
+            vehicle_id: "v1".to_string(),
+            start_vertiport_id: "A".to_string(),
+            start_time: start(),
+            capacity_grams,
+            shipments,
+        }
+    }
+
+    fn stop_ids(plan: &MultiStopPlan) -> Vec<(String, StopKind)> {
+        plan.stops
+            .iter()
+            .map(|stop| (stop.shipment_id.clone(), stop.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_pickups_along_the_way() {
+        let request = request(
+            vec![shipment("s1", "A", "D", 100), shipment("s2", "B", "C", 100)],
+            1000,
+        );
+        let plan = plan_multi_stop_with(&request, mock_flight_minutes).unwrap();
+        assert_eq!(
+            stop_ids(&plan),
+            vec![
+                ("s1".to_string(), StopKind::Pickup),
+                ("s2".to_string(), StopKind::Pickup),
+                ("s2".to_string(), StopKind::Delivery),
+                ("s1".to_string(), StopKind::Delivery),
+            ]
+        );
+        assert_eq!(plan.flight_plans.len(), 3);
+        assert_eq!(plan.flight_plans[1].cargo_weight_grams, vec![100, 100]);
+        assert_eq!(
+            plan.stops.last().unwrap().time,
+            start() + Duration::minutes(60)
+        );
+    }
+
+    #[test]
+    fn test_capacity_forces_delivery_first() {
+        let request = request(
+            vec![shipment("s1", "A", "C", 600), shipment("s2", "B", "D", 600)],
+            1000,
+        );
+        let plan = plan_multi_stop_with(&request, mock_flight_minutes).unwrap();
+        assert_eq!(
+            stop_ids(&plan),
+            vec![
+                ("s1".to_string(), StopKind::Pickup),
+                ("s1".to_string(), StopKind::Delivery),
+                ("s2".to_string(), StopKind::Pickup),
+                ("s2".to_string(), StopKind::Delivery),
+            ]
+        );
+        assert!(plan.flight_plans.iter().all(|flight_plan| flight_plan
+            .cargo_weight_grams
+            .iter()
+            .sum::<i64>()
+            <= 1000));
+    }
+
+    #[test]
+    fn test_waits_for_pickup_window() {
+        let mut late = shipment("s1", "B", "C", 100);
+        late.earliest_pickup_time = start() + Duration::minutes(45);
+        let plan = plan_multi_stop_with(&request(vec![late], 1000), mock_flight_minutes).unwrap();
+        assert_eq!(plan.stops[0].time, start() + Duration::minutes(45));
+        assert_eq!(plan.stops[1].time, start() + Duration::minutes(65));
+    }
+
+    #[test]
+    fn test_infeasible_time_window() {
+        let mut urgent = shipment("s1", "A", "D", 100);
+        urgent.latest_delivery_time = start() + Duration::minutes(30);
+        assert!(plan_multi_stop_with(&request(vec![urgent], 1000), mock_flight_minutes).is_err());
+    }
+}
@@ -0,0 +1,345 @@
+//! Import of vertiport networks from CSV and JSON files.
+//!
+//! Test environments and operators who don't run svc-storage can describe
+//! their network in a file instead, and initialize the router from it with
+//! [`init_router_from_csv`] or [`init_router_from_json`]. Each vertiport has:
+//! * `id` - id of the vertiport
+//! * `latitude`, `longitude` - location of the vertiport in degrees
+//! * `altitude_meters` - altitude of the vertiport, `0` if missing
+//! * `pads` - number of vertipads, `1` if missing
+//! * `schedule` - RRULE schedule of the vertiport, always open if missing
+//!
+//! CSV files start with a header row naming their columns, in any order.
+//! Fields containing commas, quotes or line breaks, such as multi-line
+//! schedules, are quoted with `"`. JSON files hold an array of objects with
+//! the same fields.
+//!
+//! The imported records convert to storage [`Vertiport`]s and [`Vertipad`]s,
+//! so they can also be passed to
+//! [`get_possible_flights`](crate::router_state::get_possible_flights).
+
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::location::Location;
+use crate::node::Node;
+use crate::router_state::{init_router_from_nodes, Vertipad, Vertiport};
+use crate::status::Status;
+
+/// Vertiport described in an imported network file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VertiportRecord {
+    /// id of the vertiport
+    pub id: String,
+    /// latitude in degrees
+    pub latitude: f32,
+    /// longitude in degrees
+    pub longitude: f32,
+    /// altitude in meters
+    #[serde(default)]
+    pub altitude_meters: f32,
+    /// number of vertipads
+    #[serde(default = "default_pads")]
+    pub pads: u32,
+    /// RRULE schedule of the vertiport
+    #[serde(default)]
+    pub schedule: Option<String>,
+}
+
+/// Vertiports have a single pad unless stated otherwise
+fn default_pads() -> u32 {
+    1
+}
+
+impl VertiportRecord {
+    /// Node of the vertiport in the routing graph
+    pub fn node(&self) -> Node {
+        Node {
+            uid: self.id.clone(),
+            location: Location {
+                latitude: OrderedFloat(self.latitude),
+                longitude: OrderedFloat(self.longitude),
+                altitude_meters: OrderedFloat(self.altitude_meters),
+            },
+            forward_to: None,
+            status: Status::Ok,
+            schedule: self.schedule.clone(),
+        }
+    }
+
+    /// Vertipads of the vertiport, with ids `<vertiport id>-pad-<n>` starting at 1
+    /// Vertipads share the schedule of their vertiport
+    pub fn vertipads(&self) -> Vec<Vertipad> {
+        (1..=self.pads)
+            .map(|pad| Vertipad {
+                id: format!("{}-pad-{}", self.id, pad),
+                data: Some(crate::resources::vertipad::Data {
+                    vertiport_id: self.id.clone(),
+                    name: format!("Pad {}", pad),
+                    latitude: self.latitude as f64,
+                    longitude: self.longitude as f64,
+                    enabled: true,
+                    schedule: self.schedule.clone(),
+                    ..Default::default()
+                }),
+            })
+            .collect()
+    }
+}
+
+impl From<&VertiportRecord> for Vertiport {
+    fn from(record: &VertiportRecord) -> Self {
+        Vertiport {
+            id: record.id.clone(),
+            data: Some(crate::resources::vertiport::Data {
+                name: record.id.clone(),
+                latitude: record.latitude as f64,
+                longitude: record.longitude as f64,
+                schedule: record.schedule.clone(),
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+/// Splits CSV text into rows of fields
+/// Quoted fields may contain separators, line breaks and `""` escaped quotes
+fn split_csv(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, _) => field.push(c),
+            (false, '"') => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (false, _) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unterminated quoted CSV field".to_string());
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    // skip blank lines
+    rows.retain(|row| row.iter().any(|field| !field.is_empty()));
+    Ok(rows)
+}
+
+/// Parses a CSV field, using `default` if it is missing or empty
+fn parse_field<T: std::str::FromStr>(
+    value: Option<&String>,
+    column: &str,
+    line: usize,
+    default: Option<T>,
+) -> Result<T, String> {
+    match value
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+    {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("Invalid {} on row {}: {}", column, line, value)),
+        None => default.ok_or_else(|| format!("Missing {} on row {}", column, line)),
+    }
+}
+
+/// Parses the vertiports of a CSV network description
+pub fn parse_vertiports_csv(text: &str) -> Result<Vec<VertiportRecord>, String> {
+    let mut rows = split_csv(text)?.into_iter();
+    let header: Vec<String> = rows
+        .next()
+        .ok_or_else(|| "Missing CSV header".to_string())?
+        .iter()
+        .map(|column| column.trim().to_lowercase())
+        .collect();
+    let column = |name: &str| header.iter().position(|column| column == name);
+    let (Some(id), Some(latitude), Some(longitude)) =
+        (column("id"), column("latitude"), column("longitude"))
+    else {
+        return Err("CSV header must name id, latitude and longitude columns".to_string());
+    };
+    let altitude_meters = column("altitude_meters");
+    let pads = column("pads");
+    let schedule = column("schedule");
+
+    rows.enumerate()
+        .map(|(index, row)| {
+            // the header is the first row
+            let line = index + 2;
+            let field = |position: Option<usize>| position.and_then(|position| row.get(position));
+            Ok(VertiportRecord {
+                id: parse_field(field(Some(id)), "id", line, None)?,
+                latitude: parse_field(field(Some(latitude)), "latitude", line, None)?,
+                longitude: parse_field(field(Some(longitude)), "longitude", line, None)?,
+                altitude_meters: parse_field(
+                    field(altitude_meters),
+                    "altitude_meters",
+                    line,
+                    Some(0.0),
+                )?,
+                pads: parse_field(field(pads), "pads", line, Some(default_pads()))?,
+                schedule: field(schedule)
+                    .map(|schedule| schedule.trim().to_string())
+                    .filter(|schedule| !schedule.is_empty()),
+            })
+        })
+        .collect()
+}
+
+/// Parses the vertiports of a JSON network description
+pub fn parse_vertiports_json(text: &str) -> Result<Vec<VertiportRecord>, String> {
+    serde_json::from_str(text).map_err(|e| e.to_string())
+}
+
+/// Reads the vertiports of a CSV network file
+pub fn read_vertiports_csv(path: impl AsRef<Path>) -> Result<Vec<VertiportRecord>, String> {
+    parse_vertiports_csv(&read_file(path.as_ref())?)
+}
+
+/// Reads the vertiports of a JSON network file
+pub fn read_vertiports_json(path: impl AsRef<Path>) -> Result<Vec<VertiportRecord>, String> {
+    parse_vertiports_json(&read_file(path.as_ref())?)
+}
+
+/// Reads a network file to a string
+fn read_file(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+/// Initializes the router with the vertiports of imported records, like
+/// [`init_router_from_vertiports`](crate::router_state::init_router_from_vertiports)
+pub fn init_router_from_records(records: &[VertiportRecord]) -> Result<(), String> {
+    info!(
+        "Initializing router from {} imported vertiports",
+        records.len()
+    );
+    init_router_from_nodes(records.iter().map(VertiportRecord::node).collect())
+}
+
+/// Initializes the router with the vertiports of a CSV network file
+pub fn init_router_from_csv(path: impl AsRef<Path>) -> Result<(), String> {
+    init_router_from_records(&read_vertiports_csv(path)?)
+}
+
+/// Initializes the router with the vertiports of a JSON network file
+pub fn init_router_from_json(path: impl AsRef<Path>) -> Result<(), String> {
+    init_router_from_records(&read_vertiports_json(path)?)
+}
+
+#[cfg(test)]
+mod network_import_tests {
+    use super::*;
+
+    const CSV: &str = "id,latitude,longitude,altitude_meters,pads,schedule\r\n\
+        SFO,37.615223,-122.389977,4,2,\"DTSTART:20221020T180000Z;DURATION:PT14H\n\
+        RRULE:FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR\"\r\n\
+        \n\
+        OAK,37.712569,-122.219743,,,\r\n";
+
+    #[test]
+    fn test_parse_vertiports_csv() {
+        let records = parse_vertiports_csv(CSV).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].id, "SFO");
+        assert_eq!(records[0].altitude_meters, 4.0);
+        assert_eq!(records[0].pads, 2);
+        assert_eq!(
+            records[0].schedule.as_deref(),
+            Some("DTSTART:20221020T180000Z;DURATION:PT14H\nRRULE:FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR")
+        );
+        assert_eq!(
+            records[1],
+            VertiportRecord {
+                id: "OAK".to_string(),
+                latitude: 37.712569,
+                longitude: -122.219743,
+                altitude_meters: 0.0,
+                pads: 1,
+                schedule: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_vertiports_csv_errors() {
+        assert!(parse_vertiports_csv("").is_err());
+        assert!(parse_vertiports_csv("id,lat,lon\nSFO,37.6,-122.4\n").is_err());
+        assert_eq!(
+            parse_vertiports_csv("longitude,id,latitude\n-122.4,SFO,north\n"),
+            Err("Invalid latitude on row 2: north".to_string())
+        );
+        assert_eq!(
+            parse_vertiports_csv("id,latitude,longitude\nSFO,37.6,\n"),
+            Err("Missing longitude on row 2".to_string())
+        );
+        assert!(parse_vertiports_csv("id,latitude,longitude\n\"SFO,37.6,-122.4\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_vertiports_json() {
+        let records = parse_vertiports_json(
+            r#"[
+                {"id": "SFO", "latitude": 37.615223, "longitude": -122.389977, "altitude_meters": 4.0, "pads": 2},
+                {"id": "OAK", "latitude": 37.712569, "longitude": -122.219743, "schedule": "RRULE:FREQ=DAILY"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].pads, 2);
+        assert_eq!(records[0].schedule, None);
+        assert_eq!(records[1].pads, 1);
+        assert_eq!(records[1].altitude_meters, 0.0);
+        assert_eq!(records[1].schedule.as_deref(), Some("RRULE:FREQ=DAILY"));
+        assert!(parse_vertiports_json(r#"[{"id": "SFO"}]"#).is_err());
+    }
+
+    #[test]
+    fn test_storage_objects() {
+        let records = parse_vertiports_csv(CSV).unwrap();
+        let vertiport = Vertiport::from(&records[0]);
+        let data = vertiport.data.unwrap();
+        assert_eq!(vertiport.id, "SFO");
+        assert_eq!(data.latitude, 37.615223f32 as f64);
+        assert_eq!(data.schedule, records[0].schedule);
+
+        let vertipads = records[0].vertipads();
+        assert_eq!(
+            vertipads
+                .iter()
+                .map(|vertipad| vertipad.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["SFO-pad-1", "SFO-pad-2"]
+        );
+        assert!(vertipads.iter().all(|vertipad| {
+            let data = vertipad.data.as_ref().unwrap();
+            data.vertiport_id == "SFO" && data.enabled
+        }));
+
+        let node = records[0].node();
+        assert_eq!(node.location.altitude_meters, OrderedFloat(4.0));
+        assert_eq!(node.status, Status::Ok);
+    }
+
+    #[test]
+    fn test_read_missing_file() {
+        let error = read_vertiports_json("/nonexistent/network.json").unwrap_err();
+        assert!(error.starts_with("Failed to read /nonexistent/network.json"));
+    }
+}
@@ -0,0 +1,204 @@
+//! Noise abatement over sensitive areas.
+//!
+//! A [`NoiseZone`] is a polygon over a noise-sensitive area, such as a
+//! residential neighborhood or a hospital, weighted by the hour of the day in
+//! the zone's local time. Once loaded with [`load_noise_zones`], routes
+//! queried with a departure time, see
+//! [`RouteQuery::departure_time`](crate::router_state::RouteQuery::departure_time),
+//! pay the weight of each zone they overfly per kilometer of the leg, so they
+//! shift to water or highway corridors during quiet hours.
+
+use chrono::{DateTime, Timelike};
+use rrule::Tz;
+
+use crate::context::current_context;
+use crate::haversine;
+use crate::location::Location;
+use crate::weather::polygon_intersects_leg;
+
+/// Hours in a day
+const HOURS_PER_DAY: usize = 24;
+
+/// Local hour the night starts, see [`NoiseZone::with_night_weight`]
+pub const NIGHT_START_HOUR: u32 = 22;
+/// Local hour the night ends, see [`NoiseZone::with_night_weight`]
+pub const NIGHT_END_HOUR: u32 = 7;
+
+/// Noise-sensitive area weighted by the hour of the day
+#[derive(Debug, Clone)]
+pub struct NoiseZone {
+    /// id of the zone, a zone loaded with the same id replaces it
+    pub id: String,
+    /// vertices of the polygon, altitudes are ignored
+    pub polygon: Vec<Location>,
+    /// timezone of the zone, the hours of the weights are local
+    pub timezone: Tz,
+    /// penalty per kilometer of a leg overflying the zone, by local hour
+    pub hourly_weights: [f32; HOURS_PER_DAY],
+}
+
+impl NoiseZone {
+    /// Creates a zone overflown without penalty at any hour
+    pub fn new(id: &str, polygon: Vec<Location>, timezone: Tz) -> Self {
+        NoiseZone {
+            id: id.to_string(),
+            polygon,
+            timezone,
+            hourly_weights: [0.0; HOURS_PER_DAY],
+        }
+    }
+
+    /// Sets the weight of the hours from `start_hour` until `end_hour`, local
+    /// time, spanning midnight if `end_hour` is before `start_hour`
+    pub fn with_weight(mut self, start_hour: u32, end_hour: u32, weight: f32) -> Self {
+        let mut hour = start_hour as usize % HOURS_PER_DAY;
+        loop {
+            self.hourly_weights[hour] = weight;
+            hour = (hour + 1) % HOURS_PER_DAY;
+            if hour == end_hour as usize % HOURS_PER_DAY {
+                break;
+            }
+        }
+        self
+    }
+
+    /// Sets the weight of the night, from [`NIGHT_START_HOUR`] until
+    /// [`NIGHT_END_HOUR`] local time
+    pub fn with_night_weight(self, weight: f32) -> Self {
+        self.with_weight(NIGHT_START_HOUR, NIGHT_END_HOUR, weight)
+    }
+
+    /// Gets the weight of the zone at `time`
+    pub fn weight_at(&self, time: DateTime<Tz>) -> f32 {
+        let hour = time.with_timezone(&self.timezone).hour() as usize;
+        self.hourly_weights[hour].max(0.0)
+    }
+}
+
+/// Extra cost of a leg flown at `time` for the noise zones it overflies, to
+/// use as the penalty of
+/// [`get_route_with_penalty`](crate::router_state::get_route_with_penalty)
+///
+/// The weights scale the distance of the leg, so they match the cost of a
+/// router weighting legs by their distance. All legs of a route are costed
+/// at the departure time.
+pub fn noise_penalty(
+    zones: &[NoiseZone],
+    time: DateTime<Tz>,
+    from: &Location,
+    to: &Location,
+) -> f32 {
+    let weight: f32 = zones
+        .iter()
+        .filter(|zone| polygon_intersects_leg(&zone.polygon, from, to))
+        .map(|zone| zone.weight_at(time))
+        .sum();
+    if weight <= 0.0 {
+        return 0.0;
+    }
+    haversine::distance(from, to) * weight
+}
+
+/// Adds noise zones, replacing the zones with the same ids
+pub fn load_noise_zones(zones: Vec<NoiseZone>) {
+    match current_context().noise_zones.write() {
+        Ok(mut current) => {
+            for zone in zones {
+                current.insert(zone.id.clone(), zone);
+            }
+        }
+        Err(_) => error!("Noise zones unavailable"),
+    }
+}
+
+/// Removes a noise zone
+pub fn remove_noise_zone(id: &str) {
+    match current_context().noise_zones.write() {
+        Ok(mut current) => {
+            current.remove(id);
+        }
+        Err(_) => error!("Noise zones unavailable"),
+    }
+}
+
+/// Gets the loaded noise zones
+pub fn get_noise_zones() -> Vec<NoiseZone> {
+    match current_context().noise_zones.read() {
+        Ok(current) => current.values().cloned().collect(),
+        Err(_) => {
+            error!("Noise zones unavailable");
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod noise_tests {
+    use super::*;
+    use crate::node::{AsNode, Node};
+    use crate::router::engine::Router;
+    use crate::test_support::mock_location;
+    use chrono::TimeZone;
+    use chrono_tz::Tz as ChronoTz;
+
+    fn distance(from: &dyn AsNode, to: &dyn AsNode) -> f32 {
+        haversine::distance(&from.as_node().location, &to.as_node().location)
+    }
+
+    fn utc(hour: u32) -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2030, 1, 15, hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_noise_zone_weights() {
+        let zone = NoiseZone::new("zone", vec![], Tz::Tz(ChronoTz::America__New_York))
+            .with_night_weight(2.0)
+            .with_weight(12, 13, 0.5);
+        // 22:00 local is 03:00 UTC in winter
+        assert_eq!(zone.weight_at(utc(3)), 2.0);
+        assert_eq!(zone.weight_at(utc(11)), 2.0);
+        assert_eq!(zone.weight_at(utc(12)), 0.0);
+        assert_eq!(zone.weight_at(utc(17)), 0.5);
+        assert_eq!(zone.weight_at(utc(18)), 0.0);
+    }
+
+    #[test]
+    fn test_route_avoids_zone_at_night() {
+        // the direct leg A-B overflies a neighborhood, C is over the water
+        let nodes = vec![
+            Node::new("noise-A", 0.0, 0.0),
+            Node::new("noise-B", 0.0, 0.4),
+            Node::new("noise-C", 0.05, 0.2),
+        ];
+        let zone = NoiseZone::new(
+            "neighborhood",
+            vec![
+                mock_location(-0.02, 0.15),
+                mock_location(0.02, 0.15),
+                mock_location(0.02, 0.25),
+                mock_location(-0.02, 0.25),
+            ],
+            Tz::UTC,
+        )
+        .with_night_weight(1.0);
+        let zones = vec![zone];
+        assert_eq!(
+            noise_penalty(&zones, utc(23), &nodes[0].location, &nodes[2].location),
+            0.0
+        );
+        let router = Router::new(&nodes, 50.0, distance, distance);
+        let route_at = |time: DateTime<Tz>| {
+            router
+                .find_shortest_path_with_penalty(&nodes[0], &nodes[1], |from, to| {
+                    Some(noise_penalty(&zones, time, &from.location, &to.location))
+                })
+                .unwrap()
+                .1
+                .len()
+        };
+        assert_eq!(route_at(utc(12)), 2);
+        assert_eq!(route_at(utc(23)), 3);
+        assert_eq!(route_at(utc(6)), 3);
+        assert_eq!(route_at(utc(7)), 2);
+    }
+}
@@ -0,0 +1,199 @@
+//! Notifications of changes of the network.
+//!
+//! Services built on the router keep their own caches of routes and
+//! validated flight plans. They subscribe with [`on_graph_changed`] and
+//! [`on_node_status_changed`] to be called when the router is initialized
+//! or a graph patch is applied with
+//! [`Router::apply_patch`](crate::router::engine::Router::apply_patch), and
+//! stop with [`unsubscribe`]. Callbacks are called synchronously on the
+//! thread changing the network, so they should only record the change.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::context::current_context;
+use crate::status::Status;
+
+/// Change of the graph of a router
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphChange {
+    /// version of the graph after the change
+    pub version: u64,
+    /// ids of the nodes added, changed or removed, or with a changed edge
+    pub affected_node_ids: HashSet<String>,
+}
+
+/// Change of the operating status of a node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeStatusChange {
+    /// id of the node
+    pub node_id: String,
+    /// status before the change
+    pub previous: Status,
+    /// status after the change
+    pub current: Status,
+}
+
+/// Id of a subscription, to [`unsubscribe`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+/// Callback of a subscription
+#[derive(Clone)]
+pub(crate) enum Callback {
+    GraphChanged(Arc<dyn Fn(&GraphChange) + Send + Sync>),
+    NodeStatusChanged(Arc<dyn Fn(&NodeStatusChange) + Send + Sync>),
+}
+
+/// Id of the next subscription
+static NEXT_SUBSCRIPTION_ID: AtomicU64 = AtomicU64::new(0);
+
+fn subscribe(callback: Callback) -> SubscriptionId {
+    let id = SubscriptionId(NEXT_SUBSCRIPTION_ID.fetch_add(1, Ordering::Relaxed));
+    match current_context().subscriptions.write() {
+        Ok(mut subscriptions) => subscriptions.push((id, callback)),
+        Err(_) => error!("Change notifications unavailable"),
+    }
+    id
+}
+
+/// Calls `callback` whenever the graph of a router changes
+pub fn on_graph_changed(callback: impl Fn(&GraphChange) + Send + Sync + 'static) -> SubscriptionId {
+    subscribe(Callback::GraphChanged(Arc::new(callback)))
+}
+
+/// Calls `callback` whenever the status of a node changes
+pub fn on_node_status_changed(
+    callback: impl Fn(&NodeStatusChange) + Send + Sync + 'static,
+) -> SubscriptionId {
+    subscribe(Callback::NodeStatusChanged(Arc::new(callback)))
+}
+
+/// Stops calling the callback of a subscription
+pub fn unsubscribe(id: SubscriptionId) {
+    match current_context().subscriptions.write() {
+        Ok(mut subscriptions) => subscriptions.retain(|(other, _)| *other != id),
+        Err(_) => error!("Change notifications unavailable"),
+    }
+}
+
+/// Gets the callbacks, so they are called without holding the lock and
+/// may subscribe or unsubscribe
+fn get_callbacks() -> Vec<Callback> {
+    match current_context().subscriptions.read() {
+        Ok(subscriptions) => subscriptions
+            .iter()
+            .map(|(_, callback)| callback.clone())
+            .collect(),
+        Err(_) => {
+            error!("Change notifications unavailable");
+            vec![]
+        }
+    }
+}
+
+/// Notifies the subscribers of a change of the graph
+pub(crate) fn notify_graph_changed(change: &GraphChange) {
+    debug!("Graph changed to version {}", change.version);
+    for callback in get_callbacks() {
+        if let Callback::GraphChanged(callback) = callback {
+            callback(change);
+        }
+    }
+}
+
+/// Notifies the subscribers of a change of the status of a node
+pub(crate) fn notify_node_status_changed(change: &NodeStatusChange) {
+    debug!(
+        "Node {} changed from {:?} to {:?}",
+        change.node_id, change.previous, change.current
+    );
+    for callback in get_callbacks() {
+        if let Callback::NodeStatusChanged(callback) = callback {
+            callback(change);
+        }
+    }
+}
+
+#[cfg(test)]
+mod notifications_tests {
+    use super::*;
+    use crate::graph_patch::{GraphPatch, GraphSnapshot};
+    use crate::haversine;
+    use crate::node::{AsNode, Node};
+    use crate::router::engine::Router;
+    use std::sync::Mutex;
+
+    fn node(uid: &str, longitude: f32, status: Status) -> Node {
+        Node::new(uid, 0.0, longitude).with_status(status)
+    }
+
+    fn distance(from: &dyn AsNode, to: &dyn AsNode) -> f32 {
+        haversine::distance(&from.as_node().location, &to.as_node().location)
+    }
+
+    #[test]
+    fn test_notify_patch() {
+        // other tests may patch routers concurrently, only record these nodes
+        let is_tested = |id: &str| id.starts_with("notifications-");
+        let graph_changes = Arc::new(Mutex::new(vec![]));
+        let status_changes = Arc::new(Mutex::new(vec![]));
+        let graph_subscription = on_graph_changed({
+            let graph_changes = graph_changes.clone();
+            move |change| {
+                if change.affected_node_ids.iter().any(|id| is_tested(id)) {
+                    graph_changes.lock().unwrap().push(change.clone());
+                }
+            }
+        });
+        let status_subscription = on_node_status_changed({
+            let status_changes = status_changes.clone();
+            move |change| {
+                if is_tested(&change.node_id) {
+                    status_changes.lock().unwrap().push(change.clone());
+                }
+            }
+        });
+
+        let old_nodes = vec![
+            node("notifications-A", 0.0, Status::Ok),
+            node("notifications-B", 0.3, Status::Ok),
+        ];
+        let new_nodes = vec![
+            node("notifications-A", 0.0, Status::Ok),
+            node("notifications-B", 0.3, Status::Closed),
+        ];
+        let mut router = Router::new(&old_nodes, 40.0, distance, distance);
+        let patch = GraphPatch::between(
+            &GraphSnapshot::from_router(&router),
+            &GraphSnapshot::from_nodes(1, &new_nodes, 40.0, distance, distance),
+        );
+        router.apply_patch(&patch).unwrap();
+
+        assert_eq!(
+            *graph_changes.lock().unwrap(),
+            vec![GraphChange {
+                version: 1,
+                affected_node_ids: HashSet::from(["notifications-B".to_string()]),
+            }]
+        );
+        assert_eq!(
+            *status_changes.lock().unwrap(),
+            vec![NodeStatusChange {
+                node_id: "notifications-B".to_string(),
+                previous: Status::Ok,
+                current: Status::Closed,
+            }]
+        );
+
+        unsubscribe(graph_subscription);
+        unsubscribe(status_subscription);
+        notify_node_status_changed(&NodeStatusChange {
+            node_id: "notifications-A".to_string(),
+            previous: Status::Ok,
+            current: Status::Closed,
+        });
+        assert_eq!(status_changes.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,137 @@
+//! Operators of the fleet and the filtering of their assets.
+//!
+//! Vehicles and vertiports may belong to different operators, tagged with
+//! their `operator_id`. A single instance of the library serves several
+//! tenants by querying with an [`OperatorFilter`] listing the operators whose
+//! assets the tenant may use: its own and those of the operators it has a
+//! usage agreement with. Assets every operator may use, e.g. public
+//! vertiports, are opted in with their `shared` flag. Assets of an unknown
+//! operator, e.g. loaded from svc-storage which doesn't store the operator,
+//! are only used by queries without a filter.
+
+use std::collections::HashSet;
+
+use crate::router_state::{Vehicle, Vertiport};
+
+/// The operators whose assets a query may use
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OperatorFilter {
+    /// ids of the allowed operators
+    allowed_operator_ids: HashSet<String>,
+}
+
+impl OperatorFilter {
+    /// Creates a filter allowing only the shared assets
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows the assets of an operator
+    pub fn with_operator(mut self, operator_id: &str) -> Self {
+        self.allowed_operator_ids.insert(operator_id.to_string());
+        self
+    }
+
+    /// Whether an asset of the given operator may be used, never for an
+    /// unknown operator
+    pub fn allows(&self, operator_id: Option<&str>) -> bool {
+        operator_id.is_some_and(|operator_id| self.allowed_operator_ids.contains(operator_id))
+    }
+
+    /// Whether the vehicle may be used, always if it is shared
+    pub fn allows_vehicle(&self, vehicle: &Vehicle) -> bool {
+        vehicle
+            .data
+            .as_ref()
+            .is_some_and(|data| data.shared || self.allows(data.operator_id.as_deref()))
+    }
+
+    /// Whether the vertiport may be used, always if it is shared
+    pub fn allows_vertiport(&self, vertiport: &Vertiport) -> bool {
+        vertiport
+            .data
+            .as_ref()
+            .is_some_and(|data| data.shared || self.allows(data.operator_id.as_deref()))
+    }
+
+    /// Keeps the vehicles that may be used
+    pub fn filter_vehicles(&self, vehicles: Vec<Vehicle>) -> Vec<Vehicle> {
+        vehicles
+            .into_iter()
+            .filter(|vehicle| self.allows_vehicle(vehicle))
+            .collect()
+    }
+
+    /// Keeps the vertiports that may be used
+    pub fn filter_vertiports(&self, vertiports: Vec<Vertiport>) -> Vec<Vertiport> {
+        vertiports
+            .into_iter()
+            .filter(|vertiport| self.allows_vertiport(vertiport))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod operator_tests {
+    use super::*;
+    use crate::test_support::{MockVehicle, MockVertiport};
+
+    #[test]
+    fn test_filter_vehicles() {
+        let vehicles = vec![
+            MockVehicle::new("vehicle-A")
+                .with_operator("operator-A")
+                .build(),
+            MockVehicle::new("vehicle-B")
+                .with_operator("operator-B")
+                .build(),
+            MockVehicle::new("vehicle-shared")
+                .with_operator("operator-B")
+                .shared()
+                .build(),
+            // unknown operator, e.g. loaded from svc-storage
+            MockVehicle::new("vehicle-unknown").build(),
+            Vehicle {
+                id: "vehicle-no-data".to_string(),
+                data: None,
+            },
+        ];
+        let ids = |filter: &OperatorFilter| {
+            filter
+                .filter_vehicles(vehicles.clone())
+                .into_iter()
+                .map(|vehicle| vehicle.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&OperatorFilter::new()), vec!["vehicle-shared"]);
+        assert_eq!(
+            ids(&OperatorFilter::new().with_operator("operator-A")),
+            vec!["vehicle-A", "vehicle-shared"]
+        );
+        assert_eq!(
+            ids(&OperatorFilter::new()
+                .with_operator("operator-A")
+                .with_operator("operator-B")),
+            vec!["vehicle-A", "vehicle-B", "vehicle-shared"]
+        );
+    }
+
+    #[test]
+    fn test_allows_vertiport() {
+        let vertiport = MockVertiport::new("SFO", 37.6213, -122.379)
+            .with_operator("operator-B")
+            .build();
+        assert!(!OperatorFilter::new()
+            .with_operator("operator-A")
+            .allows_vertiport(&vertiport));
+        assert!(OperatorFilter::new()
+            .with_operator("operator-B")
+            .allows_vertiport(&vertiport));
+        assert_eq!(
+            OperatorFilter::new()
+                .with_operator("operator-A")
+                .filter_vertiports(vec![vertiport]),
+            vec![]
+        );
+    }
+}
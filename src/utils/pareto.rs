@@ -0,0 +1,181 @@
+//! Multi-objective routing.
+//!
+//! [`get_route`](crate::router_state::get_route) returns the single route
+//! minimizing the weight of the graph. A faster route may use more energy
+//! or overfly riskier areas though, and which tradeoff is best is up to the
+//! scheduler or the customer. [`get_pareto_routes`] returns the Pareto
+//! frontier over the flight time, the energy and the ground risk of the
+//! routes instead: every route of the frontier is better than the others for
+//! at least one [`RouteObjectives`] component.
+
+use crate::diversion::ENERGY_KWH_PER_KM;
+use crate::ground_risk::{get_ground_risk_grid, RiskGrid};
+use crate::haversine;
+use crate::location::Location;
+use crate::node::Node;
+use crate::router::engine::Router;
+use crate::router_state::{estimate_flight_time_minutes, Aircraft, RouteQuery, ARROW_CARGO_ROUTER};
+
+/// Criteria of a route to minimize
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct RouteObjectives {
+    /// flight time in minutes, including the takeoff and landing of each leg
+    pub time_minutes: f32,
+    /// energy used by an empty aircraft
+    pub energy_kwh: f32,
+    /// ground risk overflown, see [`RiskGrid::path_risk`]
+    pub risk: f32,
+}
+
+impl RouteObjectives {
+    /// Objectives of a leg between two locations, without risk if there is no grid
+    pub fn of_leg(from: &Location, to: &Location, risk_grid: Option<&RiskGrid>) -> Self {
+        let distance_km = haversine::distance(from, to);
+        RouteObjectives {
+            time_minutes: estimate_flight_time_minutes(distance_km, Aircraft::Cargo),
+            energy_kwh: distance_km * ENERGY_KWH_PER_KM,
+            risk: risk_grid.map_or(0.0, |grid| grid.path_risk(from, to)),
+        }
+    }
+
+    /// Checks if the objectives are at most as high as `other` for every criterion
+    pub fn dominates(&self, other: &RouteObjectives) -> bool {
+        self.time_minutes <= other.time_minutes
+            && self.energy_kwh <= other.energy_kwh
+            && self.risk <= other.risk
+    }
+
+    fn to_array(self) -> [f32; 3] {
+        [self.time_minutes, self.energy_kwh, self.risk]
+    }
+
+    fn from_array([time_minutes, energy_kwh, risk]: [f32; 3]) -> Self {
+        RouteObjectives {
+            time_minutes,
+            energy_kwh,
+            risk,
+        }
+    }
+}
+
+/// A route of the Pareto frontier
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParetoRoute {
+    /// ids of the vertiports along the route, from departure to destination
+    pub vertiport_ids: Vec<String>,
+    /// locations along the route
+    pub locations: Vec<Location>,
+    /// criteria of the route
+    pub objectives: RouteObjectives,
+}
+
+/// Finds the Pareto frontier of routes between two nodes on the given router
+///
+/// # Arguments
+/// * `router` - router with the graph of vertiports
+/// * `from` - departure node
+/// * `to` - destination node
+/// * `risk_grid` - ground risk of the area, risk is 0 without a grid
+/// * `max_routes` - max number of routes returned, spread from the fastest
+///   route to the one with the lowest energy use
+///
+/// # Returns
+/// The routes of the frontier from the fastest to the slowest
+pub fn find_pareto_routes_with(
+    router: &Router,
+    from: &Node,
+    to: &Node,
+    risk_grid: Option<&RiskGrid>,
+    max_routes: usize,
+) -> Result<Vec<ParetoRoute>, String> {
+    let frontier = router
+        .find_pareto_paths(
+            from,
+            to,
+            |from, to| {
+                Some(RouteObjectives::of_leg(&from.location, &to.location, risk_grid).to_array())
+            },
+            max_routes,
+        )
+        .map_err(|e| e.to_string())?;
+    debug!("Pareto frontier of {} routes", frontier.len());
+    frontier
+        .into_iter()
+        .map(|(objectives, path)| {
+            let nodes = path
+                .into_iter()
+                .map(|index| {
+                    router
+                        .get_node_by_id(index)
+                        .ok_or_else(|| format!("Node not found by index {:?}", index))
+                })
+                .collect::<Result<Vec<&Node>, String>>()?;
+            Ok(ParetoRoute {
+                vertiport_ids: nodes.iter().map(|node| node.uid.clone()).collect(),
+                locations: nodes.iter().map(|node| node.location).collect(),
+                objectives: RouteObjectives::from_array(objectives),
+            })
+        })
+        .collect()
+}
+
+/// Gets the Pareto frontier of routes between the vertiports of the query,
+/// using the configured [ground risk layer](crate::ground_risk::set_ground_risk_layer)
+/// See [`find_pareto_routes_with`]
+pub fn get_pareto_routes(req: RouteQuery, max_routes: usize) -> Result<Vec<ParetoRoute>, String> {
+    let router = ARROW_CARGO_ROUTER
+        .get()
+        .ok_or("Arrow XL router not initialized. Try to initialize it first.")?;
+    find_pareto_routes_with(
+        router,
+        req.from,
+        req.to,
+        get_ground_risk_grid().as_ref(),
+        max_routes,
+    )
+}
+
+#[cfg(test)]
+mod pareto_tests {
+    use super::*;
+
+    #[test]
+    fn test_pareto_routes() {
+        // a risky area lies on the direct leg from A to C, B is north of it
+        let nodes = vec![
+            Node::new("A", 0.0, 0.0),
+            Node::new("B", 0.2, 0.25),
+            Node::new("C", 0.0, 0.5),
+        ];
+        let router = Router::new(
+            &nodes,
+            100.0,
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+        );
+        let grid = RiskGrid::new(-0.05, 0.2, 0.1, vec![vec![10.0]]).unwrap();
+
+        let routes = find_pareto_routes_with(&router, &nodes[0], &nodes[2], None, 5).unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].vertiport_ids, vec!["A", "C"]);
+        assert_eq!(routes[0].objectives.risk, 0.0);
+
+        let routes =
+            find_pareto_routes_with(&router, &nodes[0], &nodes[2], Some(&grid), 5).unwrap();
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].vertiport_ids, vec!["A", "C"]);
+        assert_eq!(routes[1].vertiport_ids, vec!["A", "B", "C"]);
+        assert_eq!(routes[1].locations.len(), 3);
+        assert!(routes[0].objectives.risk > 0.0);
+        assert_eq!(routes[1].objectives.risk, 0.0);
+        assert!(routes[0].objectives.time_minutes < routes[1].objectives.time_minutes);
+        assert!(routes[0].objectives.energy_kwh < routes[1].objectives.energy_kwh);
+        assert!(!routes[0].objectives.dominates(&routes[1].objectives));
+        assert!(!routes[1].objectives.dominates(&routes[0].objectives));
+
+        let routes =
+            find_pareto_routes_with(&router, &nodes[0], &nodes[2], Some(&grid), 1).unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].vertiport_ids, vec!["A", "C"]);
+    }
+}
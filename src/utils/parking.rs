@@ -0,0 +1,529 @@
+//! Overnight parking of the vehicles.
+//!
+//! At the end of the day every vehicle must be parked at a vertiport with a
+//! pad and a charger free for it. [`plan_overnight_parking`] keeps vehicles
+//! where they land after their last flight when there is room for them, and
+//! repositions the others to the nearest vertiport with room left. The
+//! repositioning flight plans are added to the existing flight plans of the
+//! next day, so the vehicles are located at their overnight vertiport.
+//!
+//! [`plan_overnight_parking_for_demand`] repositions the vehicles to the
+//! vertiports where a [`DemandForecast`] expects the first requests of the
+//! next day instead.
+
+use chrono::{DateTime, Duration};
+use ordered_float::OrderedFloat;
+use rrule::Tz;
+
+use crate::demand::DemandForecast;
+use crate::router_state::{
+    create_flight_plan_data, estimate_flight_time_between, get_vehicle_free_time,
+    get_vehicle_scheduled_location, FlightPlan, FlightPlanData, Vehicle, Vertipad,
+};
+
+/// Vertiport where vehicles can park overnight
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParkingSite {
+    /// id of the vertiport
+    pub vertiport_id: String,
+    /// number of pads available overnight
+    pub pads: u32,
+    /// number of chargers available overnight
+    pub chargers: u32,
+}
+
+impl ParkingSite {
+    /// Creates a site with the enabled vertipads of a vertiport
+    pub fn from_vertipads(vertiport_id: &str, vertipads: &[Vertipad], chargers: u32) -> Self {
+        ParkingSite {
+            vertiport_id: vertiport_id.to_string(),
+            pads: vertipads
+                .iter()
+                .filter(|vertipad| vertipad.data.as_ref().is_some_and(|data| data.enabled))
+                .count() as u32,
+            chargers,
+        }
+    }
+
+    /// Number of vehicles the site can park, each needing a pad and a charger
+    pub fn capacity(&self) -> u32 {
+        self.pads.min(self.chargers)
+    }
+}
+
+/// Overnight vertiport of a vehicle
+#[derive(Debug, Clone, PartialEq)]
+pub struct OvernightParking {
+    /// id of the vehicle
+    pub vehicle_id: String,
+    /// id of the vertiport the vehicle parks at
+    pub vertiport_id: String,
+    /// flight bringing the vehicle to the vertiport, None if it's already there
+    pub repositioning_flight: Option<FlightPlanData>,
+}
+
+/// Overnight vertiports of the vehicles
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ParkingPlan {
+    /// parked vehicles, in the order they were assigned
+    pub assignments: Vec<OvernightParking>,
+    /// vehicles without room at a reachable site, or that can't be located
+    pub unassigned_vehicle_ids: Vec<String>,
+}
+
+impl ParkingPlan {
+    /// Gets the repositioning flights as flight plans with ids
+    /// `overnight-<vehicle id>`, to add to the existing flight plans
+    pub fn repositioning_flight_plans(&self) -> Vec<FlightPlan> {
+        self.assignments
+            .iter()
+            .filter_map(|parking| {
+                Some(FlightPlan {
+                    id: format!("overnight-{}", parking.vehicle_id),
+                    data: Some(parking.repositioning_flight.clone()?),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Assigns each vehicle an overnight vertiport using the given flight time estimate
+///
+/// Vehicles landing at a site after their last flight stay there while the
+/// site has room, in the order of `vehicles`. The other vehicles are then
+/// repositioned by shortest flight first, each to the site it reaches
+/// soonest among those with room left.
+///
+/// # Arguments
+/// * `sites` - vertiports where vehicles can park overnight
+/// * `vehicles` - vehicles to park
+/// * `end_of_service` - repositioning flights depart at this time, or once
+///   the vehicle lands from its last flight
+/// * `existing_flight_plans` - flight plans of the day locating the vehicles
+/// * `flight_minutes` - flight time in minutes between two vertiports, `None` if there is no route
+pub fn plan_overnight_parking_with<F>(
+    sites: &[ParkingSite],
+    vehicles: &[Vehicle],
+    end_of_service: DateTime<Tz>,
+    existing_flight_plans: &[FlightPlan],
+    flight_minutes: F,
+) -> ParkingPlan
+where
+    F: Fn(&str, &str) -> Option<i64>,
+{
+    plan_parking(
+        sites,
+        vehicles,
+        end_of_service,
+        existing_flight_plans,
+        flight_minutes,
+        &vec![0.0; sites.len()],
+    )
+}
+
+/// Assigns each vehicle an overnight vertiport near the first requests of
+/// the next day, using the given flight time estimate
+///
+/// Vehicles landing at a site after their last flight stay there while the
+/// site has room, like [`plan_overnight_parking_with`]. The other vehicles
+/// are first repositioned to the sites with the most requests expected in
+/// `first_wave` left unserved by the vehicles parked there, the nearest
+/// vehicle first, until no site with room lacks vehicles. The vehicles left
+/// are repositioned by shortest flight first.
+///
+/// # Arguments
+/// * `forecast` - the requests expected at the sites
+/// * `first_wave` - start and end of the first requests of the next day
+///
+/// See [`plan_overnight_parking_with`] for the other arguments.
+pub fn plan_overnight_parking_for_demand_with<F>(
+    sites: &[ParkingSite],
+    vehicles: &[Vehicle],
+    end_of_service: DateTime<Tz>,
+    existing_flight_plans: &[FlightPlan],
+    forecast: &dyn DemandForecast,
+    first_wave: (DateTime<Tz>, DateTime<Tz>),
+    flight_minutes: F,
+) -> ParkingPlan
+where
+    F: Fn(&str, &str) -> Option<i64>,
+{
+    let demand: Vec<f32> = sites
+        .iter()
+        .map(|site| {
+            forecast.expected_requests_between(&site.vertiport_id, first_wave.0, first_wave.1)
+        })
+        .collect();
+    plan_parking(
+        sites,
+        vehicles,
+        end_of_service,
+        existing_flight_plans,
+        flight_minutes,
+        &demand,
+    )
+}
+
+/// Assigns each vehicle an overnight vertiport, repositioning vehicles to
+/// the sites lacking the most vehicles for `demand`, the requests expected
+/// at each site, first
+fn plan_parking<F>(
+    sites: &[ParkingSite],
+    vehicles: &[Vehicle],
+    end_of_service: DateTime<Tz>,
+    existing_flight_plans: &[FlightPlan],
+    flight_minutes: F,
+    demand: &[f32],
+) -> ParkingPlan
+where
+    F: Fn(&str, &str) -> Option<i64>,
+{
+    info!(
+        "Planning the overnight parking of {} vehicles at {} sites",
+        vehicles.len(),
+        sites.len()
+    );
+    let mut plan = ParkingPlan::default();
+    let mut room: Vec<u32> = sites.iter().map(ParkingSite::capacity).collect();
+    // requests expected at each site not served by the vehicles parked there
+    let mut shortfall = demand.to_vec();
+    // vehicles to reposition, with the vertiport and time they are free
+    let mut to_reposition: Vec<(&Vehicle, String, DateTime<Tz>)> = vec![];
+    for vehicle in vehicles {
+        let free_time = get_vehicle_free_time(vehicle, end_of_service, existing_flight_plans);
+        let vertiport_id =
+            match get_vehicle_scheduled_location(vehicle, free_time, existing_flight_plans) {
+                Ok((vertiport_id, _)) => vertiport_id,
+                Err(e) => {
+                    debug!("Unable to locate vehicle: {}", e);
+                    plan.unassigned_vehicle_ids.push(vehicle.id.clone());
+                    continue;
+                }
+            };
+        let site = sites
+            .iter()
+            .position(|site| site.vertiport_id == vertiport_id);
+        match site {
+            Some(index) if room[index] > 0 => {
+                room[index] -= 1;
+                shortfall[index] -= 1.0;
+                plan.assignments.push(OvernightParking {
+                    vehicle_id: vehicle.id.clone(),
+                    vertiport_id,
+                    repositioning_flight: None,
+                });
+            }
+            _ => to_reposition.push((vehicle, vertiport_id, free_time)),
+        }
+    }
+
+    // (minutes, vehicle, site) of every flight to a site
+    let mut flights: Vec<(i64, usize, usize)> = vec![];
+    for (vehicle_index, (_, from, _)) in to_reposition.iter().enumerate() {
+        for (site_index, site) in sites.iter().enumerate() {
+            if let Some(minutes) = flight_minutes(from, &site.vertiport_id) {
+                flights.push((minutes, vehicle_index, site_index));
+            }
+        }
+    }
+    flights.sort();
+    let mut repositioned = vec![false; to_reposition.len()];
+    let mut assign = |(minutes, vehicle_index, site_index): (i64, usize, usize),
+                      repositioned: &mut [bool],
+                      room: &mut [u32],
+                      shortfall: &mut [f32]| {
+        repositioned[vehicle_index] = true;
+        room[site_index] -= 1;
+        shortfall[site_index] -= 1.0;
+        let (vehicle, from, departure_time) = &to_reposition[vehicle_index];
+        let vertiport_id = sites[site_index].vertiport_id.clone();
+        debug!(
+            "Repositioning vehicle {} from {} to {} for the night",
+            vehicle.id, from, vertiport_id
+        );
+        plan.assignments.push(OvernightParking {
+            vehicle_id: vehicle.id.clone(),
+            vertiport_id: vertiport_id.clone(),
+            repositioning_flight: Some(create_flight_plan_data(
+                vehicle.id.clone(),
+                from.clone(),
+                vertiport_id,
+                *departure_time,
+                *departure_time + Duration::minutes(minutes),
+            )),
+        });
+    };
+    // the nearest vehicle to the site lacking the most vehicles, while any does
+    loop {
+        let flight = flights
+            .iter()
+            .filter(|(_, vehicle_index, site_index)| {
+                !repositioned[*vehicle_index]
+                    && room[*site_index] > 0
+                    && shortfall[*site_index] > 0.0
+            })
+            .min_by(|(minutes_a, _, site_a), (minutes_b, _, site_b)| {
+                OrderedFloat(shortfall[*site_b])
+                    .cmp(&OrderedFloat(shortfall[*site_a]))
+                    .then(minutes_a.cmp(minutes_b))
+            })
+            .copied();
+        let Some(flight) = flight else {
+            break;
+        };
+        assign(flight, &mut repositioned, &mut room, &mut shortfall);
+    }
+    // then by shortest flight
+    for flight in flights {
+        let (_, vehicle_index, site_index) = flight;
+        if repositioned[vehicle_index] || room[site_index] == 0 {
+            continue;
+        }
+        assign(flight, &mut repositioned, &mut room, &mut shortfall);
+    }
+    for ((vehicle, _, _), repositioned) in to_reposition.iter().zip(repositioned) {
+        if !repositioned {
+            warn!("No overnight parking for vehicle {}", vehicle.id);
+            plan.unassigned_vehicle_ids.push(vehicle.id.clone());
+        }
+    }
+    plan
+}
+
+/// Assigns each vehicle an overnight vertiport, repositioning vehicles along
+/// the routes of the router
+/// See [`plan_overnight_parking_with`]
+pub fn plan_overnight_parking(
+    sites: &[ParkingSite],
+    vehicles: &[Vehicle],
+    end_of_service: DateTime<Tz>,
+    existing_flight_plans: &[FlightPlan],
+) -> ParkingPlan {
+    plan_overnight_parking_with(
+        sites,
+        vehicles,
+        end_of_service,
+        existing_flight_plans,
+        |from, to| estimate_flight_time_between(from, to).ok(),
+    )
+}
+
+/// Assigns each vehicle an overnight vertiport near the first requests of
+/// the next day, repositioning vehicles along the routes of the router
+/// See [`plan_overnight_parking_for_demand_with`]
+pub fn plan_overnight_parking_for_demand(
+    sites: &[ParkingSite],
+    vehicles: &[Vehicle],
+    end_of_service: DateTime<Tz>,
+    existing_flight_plans: &[FlightPlan],
+    forecast: &dyn DemandForecast,
+    first_wave: (DateTime<Tz>, DateTime<Tz>),
+) -> ParkingPlan {
+    plan_overnight_parking_for_demand_with(
+        sites,
+        vehicles,
+        end_of_service,
+        existing_flight_plans,
+        forecast,
+        first_wave,
+        |from, to| estimate_flight_time_between(from, to).ok(),
+    )
+}
+
+#[cfg(test)]
+mod parking_tests {
+    use super::*;
+    use crate::resources::vertipad;
+    use crate::test_support::MockVehicle;
+    use chrono::{TimeZone, Timelike};
+
+    fn end_of_service() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2030, 1, 1, 22, 0, 0).unwrap()
+    }
+
+    fn site(vertiport_id: &str, pads: u32, chargers: u32) -> ParkingSite {
+        ParkingSite {
+            vertiport_id: vertiport_id.to_string(),
+            pads,
+            chargers,
+        }
+    }
+
+    fn minutes(from: &str, to: &str) -> Option<i64> {
+        match (from, to) {
+            (from, to) if from == to => Some(0),
+            ("OAK", "SFO") | ("SFO", "OAK") => Some(20),
+            ("OAK", "SJC") | ("SJC", "OAK") => Some(35),
+            ("SFO", "SJC") | ("SJC", "SFO") => Some(40),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_site_capacity() {
+        let vertipads: Vec<Vertipad> = [true, true, false]
+            .into_iter()
+            .enumerate()
+            .map(|(index, enabled)| Vertipad {
+                id: format!("pad-{}", index),
+                data: Some(vertipad::Data {
+                    enabled,
+                    ..Default::default()
+                }),
+            })
+            .collect();
+        let site = ParkingSite::from_vertipads("SFO", &vertipads, 3);
+        assert_eq!(site.pads, 2);
+        assert_eq!(site.capacity(), 2);
+        assert_eq!(
+            ParkingSite::from_vertipads("SFO", &vertipads, 1).capacity(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_plan_overnight_parking() {
+        let sites = vec![site("SFO", 2, 1), site("SJC", 4, 4)];
+        let vehicles = vec![
+            MockVehicle::new("vehicle-1")
+                .with_last_vertiport("SFO")
+                .build(),
+            MockVehicle::new("vehicle-2")
+                .with_last_vertiport("SFO")
+                .build(),
+            MockVehicle::new("vehicle-3")
+                .with_last_vertiport("OAK")
+                .build(),
+            MockVehicle::new("vehicle-4")
+                .with_last_vertiport("LAX")
+                .build(),
+        ];
+        // vehicle-1 lands at OAK after the end of service
+        let last_flight = FlightPlan {
+            id: "last-flight".to_string(),
+            data: Some(create_flight_plan_data(
+                "vehicle-1".to_string(),
+                "SFO".to_string(),
+                "OAK".to_string(),
+                end_of_service() - Duration::minutes(10),
+                end_of_service() + Duration::minutes(10),
+            )),
+        };
+
+        let plan = plan_overnight_parking_with(
+            &sites,
+            &vehicles,
+            end_of_service(),
+            &[last_flight],
+            minutes,
+        );
+        let parking = |vehicle_id: &str| {
+            plan.assignments
+                .iter()
+                .find(|parking| parking.vehicle_id == vehicle_id)
+                .unwrap()
+        };
+        // vehicle-2 takes the only charger at SFO
+        assert_eq!(parking("vehicle-2").vertiport_id, "SFO");
+        assert!(parking("vehicle-2").repositioning_flight.is_none());
+        for vehicle_id in ["vehicle-1", "vehicle-3"] {
+            assert_eq!(parking(vehicle_id).vertiport_id, "SJC");
+            let flight = parking(vehicle_id).repositioning_flight.as_ref().unwrap();
+            assert_eq!(flight.departure_vertiport_id.as_deref(), Some("OAK"));
+        }
+        // departs once landed at OAK
+        assert_eq!(
+            parking("vehicle-1")
+                .repositioning_flight
+                .as_ref()
+                .unwrap()
+                .scheduled_departure
+                .as_ref()
+                .unwrap()
+                .seconds,
+            (end_of_service() + Duration::minutes(10)).timestamp()
+        );
+        assert_eq!(plan.unassigned_vehicle_ids, vec!["vehicle-4"]);
+
+        let flight_plans = plan.repositioning_flight_plans();
+        assert_eq!(flight_plans.len(), 2);
+        assert!(flight_plans
+            .iter()
+            .any(|flight_plan| flight_plan.id == "overnight-vehicle-3"));
+    }
+
+    /// One request expected at SJC at 7:00-8:00
+    struct MorningDemand;
+
+    impl DemandForecast for MorningDemand {
+        fn bucket_minutes(&self) -> i64 {
+            60
+        }
+
+        fn expected_requests(&self, vertiport_id: &str, time: DateTime<Tz>) -> f32 {
+            if vertiport_id == "SJC" && time.hour() == 7 {
+                1.0
+            } else {
+                0.0
+            }
+        }
+    }
+
+    #[test]
+    fn test_plan_overnight_parking_for_demand() {
+        let sites = vec![site("SFO", 4, 4), site("SJC", 4, 4)];
+        let vehicles = vec![
+            MockVehicle::new("vehicle-1")
+                .with_last_vertiport("OAK")
+                .build(),
+            MockVehicle::new("vehicle-2")
+                .with_last_vertiport("OAK")
+                .build(),
+            MockVehicle::new("vehicle-3")
+                .with_last_vertiport("OAK")
+                .build(),
+        ];
+        let vertiport_id = |plan: &ParkingPlan, vehicle_id: &str| {
+            plan.assignments
+                .iter()
+                .find(|parking| parking.vehicle_id == vehicle_id)
+                .unwrap()
+                .vertiport_id
+                .clone()
+        };
+
+        // without demand all go to the nearest site
+        let plan = plan_overnight_parking_with(&sites, &vehicles, end_of_service(), &[], minutes);
+        assert!(["vehicle-1", "vehicle-2", "vehicle-3"]
+            .iter()
+            .all(|vehicle_id| vertiport_id(&plan, vehicle_id) == "SFO"));
+
+        let morning = end_of_service() + Duration::hours(9);
+        let plan = plan_overnight_parking_for_demand_with(
+            &sites,
+            &vehicles,
+            end_of_service(),
+            &[],
+            &MorningDemand,
+            (morning, morning + Duration::hours(2)),
+            minutes,
+        );
+        assert_eq!(plan.assignments.len(), 3);
+        assert_eq!(vertiport_id(&plan, "vehicle-1"), "SJC");
+        assert_eq!(vertiport_id(&plan, "vehicle-2"), "SFO");
+        assert_eq!(vertiport_id(&plan, "vehicle-3"), "SFO");
+
+        // no room left at SJC
+        let sites = vec![site("SFO", 4, 4), site("SJC", 0, 4)];
+        let plan = plan_overnight_parking_for_demand_with(
+            &sites,
+            &vehicles,
+            end_of_service(),
+            &[],
+            &MorningDemand,
+            (morning, morning + Duration::hours(2)),
+            minutes,
+        );
+        assert_eq!(vertiport_id(&plan, "vehicle-1"), "SFO");
+    }
+}
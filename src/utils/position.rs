@@ -0,0 +1,257 @@
+//! Estimated position of a vehicle at a given time.
+//!
+//! [`get_vehicle_scheduled_location`](crate::router_state::get_vehicle_scheduled_location)
+//! only tells which vertiport a vehicle is at or flying to. This module
+//! estimates the 3D position of an in-flight vehicle by interpolating along
+//! the route of its current flight plan. The vehicle is assumed to stay at
+//! the departure vertiport during loading and takeoff, to fly the route at a
+//! constant speed and to be at the destination during landing and unloading.
+
+use chrono::DateTime;
+use ordered_float::OrderedFloat;
+use rrule::Tz;
+
+use crate::amendment::PlanTimes;
+use crate::haversine;
+use crate::location::Location;
+use crate::router_state::{
+    get_node_by_id, get_route, Aircraft, FlightPlan, RouteQuery, Vehicle,
+    LANDING_AND_UNLOADING_TIME_MIN, LOADING_AND_TAKEOFF_TIME_MIN,
+};
+
+/// Estimated position of a vehicle
+#[derive(Debug, Clone, PartialEq)]
+pub struct VehiclePosition {
+    /// estimated location, including altitude
+    pub location: Location,
+    /// flight plan being flown, `None` if the vehicle is parked
+    pub flight_plan_id: Option<String>,
+    /// fraction of the route flown, from 0.0 to 1.0
+    pub progress: f32,
+}
+
+/// Returns the location at the given fraction of the route length
+///
+/// Latitude, longitude and altitude are interpolated linearly within a leg of the route.
+pub fn interpolate_along_route(route: &[Location], fraction: f32) -> Option<Location> {
+    let first = route.first()?;
+    let legs: Vec<f32> = route
+        .windows(2)
+        .map(|leg| haversine::distance(&leg[0], &leg[1]))
+        .collect();
+    let total: f32 = legs.iter().sum();
+    if total <= 0.0 {
+        return Some(*first);
+    }
+    let mut remaining = fraction.clamp(0.0, 1.0) * total;
+    for (leg, length) in route.windows(2).zip(legs) {
+        if remaining <= length && length > 0.0 {
+            let ratio = remaining / length;
+            let lerp = |from: OrderedFloat<f32>, to: OrderedFloat<f32>| {
+                OrderedFloat(from.into_inner() + (to.into_inner() - from.into_inner()) * ratio)
+            };
+            return Some(Location {
+                latitude: lerp(leg[0].latitude, leg[1].latitude),
+                longitude: lerp(leg[0].longitude, leg[1].longitude),
+                altitude_meters: lerp(leg[0].altitude_meters, leg[1].altitude_meters),
+            });
+        }
+        remaining -= length;
+    }
+    route.last().copied()
+}
+
+/// Fraction of the route flown at `timestamp` for a flight between
+/// `departure` and `arrival`, in seconds since epoch
+fn route_progress(departure: i64, arrival: i64, timestamp: i64) -> f32 {
+    let airborne_from = departure + (LOADING_AND_TAKEOFF_TIME_MIN * 60.0) as i64;
+    let airborne_to = arrival - (LANDING_AND_UNLOADING_TIME_MIN * 60.0) as i64;
+    if airborne_to <= airborne_from {
+        // too short to account for takeoff and landing
+        return if arrival <= departure {
+            1.0
+        } else {
+            ((timestamp - departure) as f32 / (arrival - departure) as f32).clamp(0.0, 1.0)
+        };
+    }
+    ((timestamp - airborne_from) as f32 / (airborne_to - airborne_from) as f32).clamp(0.0, 1.0)
+}
+
+/// Estimates the position of a vehicle at `timestamp`
+///
+/// # Arguments
+/// * `vehicle` - vehicle to locate
+/// * `timestamp` - time of the estimate
+/// * `existing_flight_plans` - scheduled flight plans
+/// * `vertiport_location` - location of a vertiport by id
+/// * `route` - route between two vertiports by id
+///
+/// # Returns
+/// The estimated position, `None` if the vehicle's location is unknown
+pub fn get_vehicle_scheduled_position_with<L, R>(
+    vehicle: &Vehicle,
+    timestamp: DateTime<Tz>,
+    existing_flight_plans: &[FlightPlan],
+    vertiport_location: L,
+    route: R,
+) -> Option<VehiclePosition>
+where
+    L: Fn(&str) -> Option<Location>,
+    R: Fn(&str, &str) -> Option<Vec<Location>>,
+{
+    let current = existing_flight_plans
+        .iter()
+        .filter(|flight_plan| {
+            flight_plan
+                .data
+                .as_ref()
+                .is_some_and(|data| data.vehicle_id == vehicle.id)
+        })
+        .filter_map(|flight_plan| PlanTimes::from_flight_plan(flight_plan).ok())
+        .filter(|plan| plan.departure <= timestamp.timestamp())
+        .max_by_key(|plan| plan.departure);
+    let Some(plan) = current else {
+        let vertiport_id = vehicle.data.as_ref()?.last_vertiport_id.as_ref()?;
+        return Some(VehiclePosition {
+            location: vertiport_location(vertiport_id)?,
+            flight_plan_id: None,
+            progress: 0.0,
+        });
+    };
+    if plan.arrival <= timestamp.timestamp() {
+        return Some(VehiclePosition {
+            location: vertiport_location(&plan.destination_vertiport_id)?,
+            flight_plan_id: None,
+            progress: 0.0,
+        });
+    }
+    let progress = route_progress(plan.departure, plan.arrival, timestamp.timestamp());
+    let locations = route(&plan.departure_vertiport_id, &plan.destination_vertiport_id)
+        .filter(|locations| !locations.is_empty())
+        .or_else(|| {
+            Some(vec![
+                vertiport_location(&plan.departure_vertiport_id)?,
+                vertiport_location(&plan.destination_vertiport_id)?,
+            ])
+        })?;
+    debug!(
+        "Vehicle {} flew {:.0}% of flight plan {}",
+        vehicle.id,
+        progress * 100.0,
+        plan.id
+    );
+    Some(VehiclePosition {
+        location: interpolate_along_route(&locations, progress)?,
+        flight_plan_id: Some(plan.id),
+        progress,
+    })
+}
+
+/// Estimates the position of a vehicle at `timestamp` along the routes of the router
+pub fn get_vehicle_scheduled_position(
+    vehicle: &Vehicle,
+    timestamp: DateTime<Tz>,
+    existing_flight_plans: &[FlightPlan],
+) -> Option<VehiclePosition> {
+    get_vehicle_scheduled_position_with(
+        vehicle,
+        timestamp,
+        existing_flight_plans,
+        |vertiport_id| get_node_by_id(vertiport_id).ok().map(|node| node.location),
+        |from, to| {
+            let (locations, _) = get_route(RouteQuery::new(
+                Aircraft::Cargo,
+                get_node_by_id(from).ok()?,
+                get_node_by_id(to).ok()?,
+            ))
+            .ok()?;
+            Some(locations)
+        },
+    )
+}
+
+#[cfg(test)]
+mod position_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use crate::test_support::mock_location_at_altitude;
+    use chrono::{Duration, TimeZone};
+
+    fn vertiport_location(vertiport_id: &str) -> Option<Location> {
+        match vertiport_id {
+            "A" => Some(mock_location_at_altitude(0.0, 0.0, 0.0)),
+            "B" => Some(mock_location_at_altitude(0.0, 2.0, 100.0)),
+            _ => None,
+        }
+    }
+
+    fn route(from: &str, to: &str) -> Option<Vec<Location>> {
+        Some(vec![
+            vertiport_location(from)?,
+            mock_location_at_altitude(0.0, 1.0, 500.0),
+            vertiport_location(to)?,
+        ])
+    }
+
+    #[test]
+    fn test_interpolate_along_route() {
+        let route = route("A", "B").unwrap();
+        assert_eq!(interpolate_along_route(&route, 0.0), Some(route[0]));
+        assert_eq!(interpolate_along_route(&route, 1.0), Some(route[2]));
+        let middle = interpolate_along_route(&route, 0.5).unwrap();
+        assert!((middle.longitude.into_inner() - 1.0).abs() < 0.01);
+        assert!((middle.altitude_meters.into_inner() - 500.0).abs() < 5.0);
+        let quarter = interpolate_along_route(&route, 0.25).unwrap();
+        assert!((quarter.longitude.into_inner() - 0.5).abs() < 0.01);
+        assert!((quarter.altitude_meters.into_inner() - 250.0).abs() < 5.0);
+        assert_eq!(interpolate_along_route(&[], 0.5), None);
+    }
+
+    #[test]
+    fn test_vehicle_scheduled_position() {
+        let start = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap();
+        let vehicle = Vehicle {
+            id: "v1".to_string(),
+            data: Some(crate::resources::vehicle::Data {
+                last_vertiport_id: Some("A".to_string()),
+                ..Default::default()
+            }),
+        };
+        // 10 minutes takeoff, 40 minutes in the air, 10 minutes landing
+        let flight_plans = vec![FlightPlan {
+            id: "fp1".to_string(),
+            data: Some(create_flight_plan_data(
+                "v1".to_string(),
+                "A".to_string(),
+                "B".to_string(),
+                start + Duration::minutes(60),
+                start + Duration::minutes(120),
+            )),
+        }];
+        let position_at = |minutes: i64| {
+            get_vehicle_scheduled_position_with(
+                &vehicle,
+                start + Duration::minutes(minutes),
+                &flight_plans,
+                vertiport_location,
+                route,
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            position_at(0).location,
+            mock_location_at_altitude(0.0, 0.0, 0.0)
+        );
+        assert_eq!(position_at(0).flight_plan_id, None);
+        assert_eq!(position_at(65).progress, 0.0);
+        let in_flight = position_at(90);
+        assert_eq!(in_flight.flight_plan_id, Some("fp1".to_string()));
+        assert_eq!(in_flight.progress, 0.5);
+        assert!((in_flight.location.longitude.into_inner() - 1.0).abs() < 0.01);
+        assert_eq!(position_at(115).progress, 1.0);
+        assert_eq!(
+            position_at(130).location,
+            mock_location_at_altitude(0.0, 2.0, 100.0)
+        );
+    }
+}
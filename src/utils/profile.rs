@@ -0,0 +1,217 @@
+//! Vertical and energy profile of a route.
+//!
+//! For each segment of a route, [`route_profile_with`] gives the distance
+//! flown so far, the altitude assigned to the segment, the ground speed in
+//! the wind and the energy used, so operators can check that a flight is
+//! feasible and downstream systems can chart it. Speeds follow the
+//! [`predict_eta`](crate::eta::predict_eta) model and energy the
+//! [`diversion`](crate::diversion) model. Takeoff and landing are not
+//! segments of the profile.
+
+use serde::{Deserialize, Serialize};
+
+use crate::diversion::{ENERGY_KWH_PER_KM, PAYLOAD_ENERGY_KWH_PER_KM_PER_KG};
+use crate::eta::{bearing_degrees, get_eta_model, ground_speed_kmh, AircraftProfile, Wind};
+use crate::haversine;
+use crate::location::Location;
+use crate::router_state::Aircraft;
+use crate::terrain::get_terrain_clearance;
+
+/// Segment of a route between two consecutive locations
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileSegment {
+    /// start of the segment
+    pub from: Location,
+    /// end of the segment
+    pub to: Location,
+    /// length of the segment
+    pub distance_km: f32,
+    /// distance from the start of the route to the end of the segment
+    pub cumulative_distance_km: f32,
+    /// altitude the segment is flown at, in meters above sea level
+    pub altitude_meters: f32,
+    /// ground speed along the segment
+    pub speed_kmh: f32,
+    /// time flying the segment
+    pub minutes: f32,
+    /// energy used flying the segment
+    pub energy_kwh: f32,
+    /// energy used from the start of the route to the end of the segment
+    pub cumulative_energy_kwh: f32,
+}
+
+/// Profile of a whole route
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct RouteProfile {
+    /// segments in route order
+    pub segments: Vec<ProfileSegment>,
+    /// length of the route
+    pub distance_km: f32,
+    /// time en route
+    pub minutes: f32,
+    /// energy used en route
+    pub energy_kwh: f32,
+}
+
+/// Computes the profile of a route
+///
+/// # Arguments
+/// * `route` - locations along the route, from departure to destination
+/// * `aircraft` - cruise speed of the aircraft
+/// * `wind` - wind along the route, still air if `None`
+/// * `cruise_altitude_meters` - altitude assigned to every segment, or the
+///   higher altitude of the ends of each segment if `None`
+/// * `payload_grams` - weight of the cargo carried
+///
+/// # Returns
+/// The profile, without segments for a route of less than two locations
+pub fn route_profile_with(
+    route: &[Location],
+    aircraft: &AircraftProfile,
+    wind: Option<Wind>,
+    cruise_altitude_meters: Option<f32>,
+    payload_grams: i64,
+) -> RouteProfile {
+    let payload_kg = payload_grams.max(0) as f32 / 1000.0;
+    let energy_kwh_per_km = ENERGY_KWH_PER_KM + PAYLOAD_ENERGY_KWH_PER_KM_PER_KG * payload_kg;
+    let mut profile = RouteProfile::default();
+    for leg in route.windows(2) {
+        let (from, to) = (leg[0], leg[1]);
+        let distance_km = haversine::distance(&from, &to);
+        let speed_kmh =
+            ground_speed_kmh(aircraft.cruise_speed_kmh, wind, bearing_degrees(&from, &to));
+        let minutes = distance_km / speed_kmh * 60.0;
+        let energy_kwh = distance_km * energy_kwh_per_km;
+        profile.distance_km += distance_km;
+        profile.minutes += minutes;
+        profile.energy_kwh += energy_kwh;
+        profile.segments.push(ProfileSegment {
+            from,
+            to,
+            distance_km,
+            cumulative_distance_km: profile.distance_km,
+            altitude_meters: cruise_altitude_meters.unwrap_or_else(|| {
+                from.altitude_meters
+                    .into_inner()
+                    .max(to.altitude_meters.into_inner())
+            }),
+            speed_kmh,
+            minutes,
+            energy_kwh,
+            cumulative_energy_kwh: profile.energy_kwh,
+        });
+    }
+    profile
+}
+
+/// Computes the profile of a route flown by the aircraft type in the wind
+/// of the [ETA model](crate::eta::set_eta_model), at the cruise altitude of
+/// the [terrain clearance](crate::terrain::set_terrain_clearance) if set
+/// See [`route_profile_with`]
+pub fn route_profile(route: &[Location], aircraft: Aircraft, payload_grams: i64) -> RouteProfile {
+    route_profile_with(
+        route,
+        &AircraftProfile::of(aircraft),
+        get_eta_model().wind,
+        get_terrain_clearance().map(|clearance| clearance.cruise_altitude_meters),
+        payload_grams,
+    )
+}
+
+#[cfg(test)]
+mod profile_tests {
+    use super::*;
+    use crate::test_support::mock_location_at_altitude;
+
+    #[test]
+    fn test_route_profile() {
+        let route = vec![
+            mock_location_at_altitude(0.0, 0.0, 0.0),
+            mock_location_at_altitude(0.0, 0.1, 300.0),
+            mock_location_at_altitude(0.1, 0.1, 100.0),
+        ];
+        let aircraft = AircraftProfile {
+            cruise_speed_kmh: 60.0,
+            takeoff_minutes: 5.0,
+            landing_minutes: 5.0,
+        };
+        let profile = route_profile_with(&route, &aircraft, None, None, 100_000);
+        assert_eq!(profile.segments.len(), 2);
+        let leg_km = haversine::distance(&route[0], &route[1]);
+        let first = &profile.segments[0];
+        assert_eq!(first.distance_km, leg_km);
+        assert_eq!(first.altitude_meters, 300.0);
+        assert_eq!(first.speed_kmh, 60.0);
+        assert!((first.minutes - leg_km).abs() < 1e-4);
+        // 0.4 kWh/km empty and 0.2 kWh/km for 100 kg
+        assert!((first.energy_kwh - leg_km * 0.6).abs() < 1e-4);
+        let last = &profile.segments[1];
+        assert_eq!(last.altitude_meters, 300.0);
+        assert_eq!(last.cumulative_distance_km, profile.distance_km);
+        assert_eq!(last.cumulative_energy_kwh, profile.energy_kwh);
+        assert!((profile.distance_km - 2.0 * leg_km).abs() < 0.01);
+
+        // headwind flying east, crosswind flying north
+        let wind = Wind {
+            speed_kmh: 20.0,
+            from_degrees: 90.0,
+        };
+        let profile = route_profile_with(&route, &aircraft, Some(wind), Some(450.0), 0);
+        assert!((profile.segments[0].speed_kmh - 40.0).abs() < 0.01);
+        assert!((profile.segments[1].speed_kmh - 60.0).abs() < 0.01);
+        assert!(profile
+            .segments
+            .iter()
+            .all(|segment| segment.altitude_meters == 450.0));
+        assert!((profile.energy_kwh - profile.distance_km * 0.4).abs() < 1e-4);
+
+        assert_eq!(
+            route_profile(&route[..1], Aircraft::Cargo, 0),
+            RouteProfile::default()
+        );
+    }
+
+    #[test]
+    fn test_route_profile_edge_cases() {
+        let aircraft = AircraftProfile {
+            cruise_speed_kmh: 60.0,
+            takeoff_minutes: 5.0,
+            landing_minutes: 5.0,
+        };
+        assert_eq!(
+            route_profile_with(&[], &aircraft, None, None, 0),
+            RouteProfile::default()
+        );
+
+        // a vehicle hovering over the same location flies an empty segment
+        let hover = vec![
+            mock_location_at_altitude(0.0, 0.0, 0.0),
+            mock_location_at_altitude(0.0, 0.0, 0.0),
+        ];
+        let wind = Wind {
+            speed_kmh: 20.0,
+            from_degrees: 90.0,
+        };
+        let profile = route_profile_with(&hover, &aircraft, Some(wind), None, 10_000);
+        assert_eq!(profile.segments.len(), 1);
+        assert_eq!(profile.segments[0].distance_km, 0.0);
+        assert_eq!(profile.segments[0].minutes, 0.0);
+        assert_eq!(profile.energy_kwh, 0.0);
+
+        // a headwind faster than the aircraft still lets it make progress,
+        // and a negative payload weighs nothing
+        let route = vec![
+            mock_location_at_altitude(0.0, 0.0, 0.0),
+            mock_location_at_altitude(0.0, 0.1, 0.0),
+        ];
+        let storm = Wind {
+            speed_kmh: 100.0,
+            from_degrees: 90.0,
+        };
+        let profile = route_profile_with(&route, &aircraft, Some(storm), None, -5_000);
+        let segment = &profile.segments[0];
+        assert!(segment.speed_kmh > 0.0 && segment.speed_kmh < 60.0);
+        assert!(segment.minutes.is_finite());
+        assert_eq!(segment.energy_kwh, segment.distance_km * ENERGY_KWH_PER_KM);
+    }
+}
@@ -0,0 +1,364 @@
+//! Async scheduling with vehicles and flight plans fetched on demand.
+//!
+//! [`get_possible_flights`](crate::router_state::get_possible_flights) needs
+//! every vehicle and flight plan of the network up front. Large deployments
+//! can instead implement [`VehicleProvider`] and [`FlightPlanProvider`] and
+//! call [`get_possible_flights_async`], which only fetches the flight plans
+//! around the requested window, and fetches vehicles page by page until
+//! every departure slot that lacked a vehicle is served. The earlier flight
+//! plans of each fetched vehicle counting towards its maintenance usage or
+//! its projected state of charge are fetched with it, see
+//! [`vehicle_history_since`].
+//!
+//! The library does not depend on an async runtime, so the futures can be
+//! run by any executor.
+
+use chrono::{DateTime, Duration};
+use prost_types::Timestamp;
+use rrule::Tz;
+use std::collections::{BTreeMap, HashSet};
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::audit::{CandidateSlot, RejectionReason, SlotOutcome};
+use crate::clock::get_clock;
+use crate::context::{current_context, RouterContext};
+use crate::crew::get_crew_provider;
+use crate::flight_plan_stream::vehicle_history_since;
+use crate::reservation::get_held_flight_plans_with;
+use crate::router_state::{
+    find_possible_flights, FlightPlan, PossibleFlight, Vehicle, Vertipad, Vertiport,
+};
+use crate::time::validate_flight_window;
+use crate::utm::{deconflict_flight, flight_plan_intent, get_utm_service};
+
+/// Flight plans this long before and after the requested window are fetched,
+/// to locate vehicles and check the turnaround of their previous flights
+pub const FLIGHT_PLAN_WINDOW_MARGIN_HOURS: i64 = 24;
+
+/// Source of the vehicles of the fleet
+pub trait VehicleProvider: Sync {
+    /// Fetches the page of vehicles with the given index, starting at 0
+    /// An empty page ends the fleet
+    fn vehicles(&self, page: usize) -> impl Future<Output = Result<Vec<Vehicle>, String>> + Send;
+}
+
+/// Source of the existing flight plans of the network
+pub trait FlightPlanProvider: Sync {
+    /// Fetches the flight plans departing or arriving between `from` and `to`
+    fn flight_plans_between(
+        &self,
+        from: DateTime<Tz>,
+        to: DateTime<Tz>,
+    ) -> impl Future<Output = Result<Vec<FlightPlan>, String>> + Send;
+
+    /// Fetches the flight plans of a vehicle departing or arriving between
+    /// `from` and `to`, by default filtering
+    /// [`flight_plans_between`](Self::flight_plans_between)
+    fn vehicle_flight_plans_between(
+        &self,
+        vehicle_id: &str,
+        from: DateTime<Tz>,
+        to: DateTime<Tz>,
+    ) -> impl Future<Output = Result<Vec<FlightPlan>, String>> + Send {
+        async move {
+            Ok(self
+                .flight_plans_between(from, to)
+                .await?
+                .into_iter()
+                .filter(|flight_plan| {
+                    flight_plan
+                        .data
+                        .as_ref()
+                        .is_some_and(|data| data.vehicle_id == vehicle_id)
+                })
+                .collect())
+        }
+    }
+}
+
+/// Vehicles held in memory, served in pages of `page_size`
+#[derive(Debug, Clone, Default)]
+pub struct VehiclePages {
+    /// vehicles of the fleet
+    pub vehicles: Vec<Vehicle>,
+    /// number of vehicles per page
+    pub page_size: usize,
+}
+
+impl VehicleProvider for VehiclePages {
+    async fn vehicles(&self, page: usize) -> Result<Vec<Vehicle>, String> {
+        if self.page_size == 0 {
+            return Err("Page size must be positive".to_string());
+        }
+        Ok(self
+            .vehicles
+            .chunks(self.page_size)
+            .nth(page)
+            .map(<[Vehicle]>::to_vec)
+            .unwrap_or_default())
+    }
+}
+
+impl FlightPlanProvider for Vec<FlightPlan> {
+    async fn flight_plans_between(
+        &self,
+        from: DateTime<Tz>,
+        to: DateTime<Tz>,
+    ) -> Result<Vec<FlightPlan>, String> {
+        let (from, to) = (from.timestamp(), to.timestamp());
+        Ok(self
+            .iter()
+            .filter(|flight_plan| {
+                let Some(data) = &flight_plan.data else {
+                    return false;
+                };
+                [&data.scheduled_departure, &data.scheduled_arrival]
+                    .into_iter()
+                    .flatten()
+                    .any(|time| from <= time.seconds && time.seconds <= to)
+            })
+            .cloned()
+            .collect())
+    }
+}
+
+/// Creates all possible flight plans based on the given request, fetching
+/// vehicles and flight plans from providers
+///
+/// Like [`get_possible_flights`](crate::router_state::get_possible_flights),
+/// flight plans held in the reservation ledger are treated as existing flight
+/// plans, if a UTM service is set, only flights with intents accepted by
+/// the service are returned and, if a crew provider is set, only flights with
+/// a pilot within its duty limits are returned. Each departure slot is served by the first page
+/// of vehicles able to fly it. Decisions are not recorded in the audit trail,
+/// as the fleet is never fully loaded.
+#[allow(clippy::too_many_arguments)]
+pub async fn get_possible_flights_async(
+    vertiport_depart: Vertiport,
+    vertiport_arrive: Vertiport,
+    vertipads_depart: Vec<Vertipad>,
+    vertipads_arrive: Vec<Vertipad>,
+    earliest_departure_time: Option<Timestamp>,
+    latest_arrival_time: Option<Timestamp>,
+    vehicle_provider: &impl VehicleProvider,
+    flight_plan_provider: &impl FlightPlanProvider,
+) -> Result<Vec<PossibleFlight>, String> {
+    get_possible_flights_async_with(
+        vertiport_depart,
+        vertiport_arrive,
+        vertipads_depart,
+        vertipads_arrive,
+        earliest_departure_time,
+        latest_arrival_time,
+        vehicle_provider,
+        flight_plan_provider,
+        &current_context(),
+    )
+    .await
+}
+
+/// Creates all possible flight plans based on the given request, fetching
+/// vehicles and flight plans from providers, with the clock, reservation
+/// holds, telemetry, models and services of `context`
+/// See [`get_possible_flights_async`] for the arguments and result
+#[allow(clippy::too_many_arguments)]
+pub async fn get_possible_flights_async_with(
+    vertiport_depart: Vertiport,
+    vertiport_arrive: Vertiport,
+    vertipads_depart: Vec<Vertipad>,
+    vertipads_arrive: Vec<Vertipad>,
+    earliest_departure_time: Option<Timestamp>,
+    latest_arrival_time: Option<Timestamp>,
+    vehicle_provider: &impl VehicleProvider,
+    flight_plan_provider: &impl FlightPlanProvider,
+    context: &Arc<RouterContext>,
+) -> Result<Vec<PossibleFlight>, String> {
+    let (earliest, latest) = validate_flight_window(
+        earliest_departure_time.as_ref(),
+        latest_arrival_time.as_ref(),
+    )?;
+    let margin = Duration::hours(FLIGHT_PLAN_WINDOW_MARGIN_HOURS);
+    let mut existing_flight_plans = flight_plan_provider
+        .flight_plans_between(earliest - margin, latest + margin)
+        .await?;
+    let (clock, utm) = context.scope(|| (get_clock(), get_utm_service()));
+    existing_flight_plans.extend(context.scope(|| get_held_flight_plans_with(clock.as_ref())));
+    let mut flight_plan_ids: HashSet<String> = existing_flight_plans
+        .iter()
+        .map(|flight_plan| flight_plan.id.clone())
+        .collect();
+
+    // possible flights by departure slot, served by the first page able to fly them
+    // Each page evaluates the same slots in the same order, with the same delays as
+    // they don't depend on the vehicles, so a slot is keyed by its index.
+    let mut flights: BTreeMap<usize, PossibleFlight> = BTreeMap::new();
+    for page in 0.. {
+        let vehicles = vehicle_provider.vehicles(page).await?;
+        if vehicles.is_empty() {
+            break;
+        }
+        // flight plans counting towards the use and charge of the vehicles
+        let history_until = earliest - margin;
+        for vehicle in &vehicles {
+            let Some(since) = vehicle_history_since(vehicle).filter(|since| *since < history_until)
+            else {
+                continue;
+            };
+            let history = flight_plan_provider
+                .vehicle_flight_plans_between(&vehicle.id, since, history_until)
+                .await?;
+            existing_flight_plans.extend(
+                history
+                    .into_iter()
+                    .filter(|flight_plan| flight_plan_ids.insert(flight_plan.id.clone())),
+            );
+        }
+        let mut candidates: Vec<CandidateSlot> = vec![];
+        let result = context.scope(|| {
+            find_possible_flights(
+                &vertiport_depart,
+                &vertiport_arrive,
+                &vertipads_depart,
+                &vertipads_arrive,
+                earliest_departure_time.clone(),
+                latest_arrival_time.clone(),
+                &vehicles,
+                &existing_flight_plans,
+                clock.as_ref(),
+                None,
+                get_crew_provider().as_deref(),
+                None,
+                &mut candidates,
+            )
+        });
+        match result {
+            Ok(page_flights) => {
+                // a flight is returned for each accepted slot, in slot order
+                let accepted_slots = candidates
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, candidate)| {
+                        matches!(candidate.outcome, SlotOutcome::Accepted { .. })
+                    })
+                    .map(|(slot, _)| slot);
+                for (slot, flight) in accepted_slots.zip(page_flights) {
+                    flights.entry(slot).or_insert(flight);
+                }
+            }
+            // the request itself failed before any slot was evaluated
+            Err(e) if candidates.is_empty() => return Err(e),
+            Err(_) => {}
+        }
+        let waiting_for_vehicle = candidates.iter().enumerate().any(|(slot, candidate)| {
+            candidate.outcome == SlotOutcome::Rejected(RejectionReason::NoVehicleAvailable)
+                && !flights.contains_key(&slot)
+        });
+        if !waiting_for_vehicle {
+            break;
+        }
+    }
+
+    let mut flights: Vec<PossibleFlight> = flights.into_values().collect();
+    if let Some(service) = utm.as_deref() {
+        flights.retain_mut(|flight| {
+            let intents = std::iter::once(&flight.flight_plan)
+                .chain(&flight.deadhead_flight_plans)
+                .map(flight_plan_intent)
+                .collect::<Result<Vec<_>, String>>();
+            match intents.and_then(|intents| deconflict_flight(service, &intents)) {
+                Ok(intent_ids) => {
+                    flight.utm_intent_ids = intent_ids;
+                    true
+                }
+                Err(e) => {
+                    debug!("UTM service rejected flight: {}", e);
+                    false
+                }
+            }
+        });
+    }
+    if flights.is_empty() {
+        return Err("No flight plans found for given time window".to_string());
+    }
+    Ok(flights)
+}
+
+#[cfg(test)]
+mod providers_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use crate::test_support::block_on;
+    use chrono::TimeZone;
+
+    fn vehicle(id: &str) -> Vehicle {
+        Vehicle {
+            id: id.to_string(),
+            data: None,
+        }
+    }
+
+    #[test]
+    fn test_vehicle_pages() {
+        let pages = VehiclePages {
+            vehicles: vec![vehicle("a"), vehicle("b"), vehicle("c")],
+            page_size: 2,
+        };
+        assert_eq!(block_on(pages.vehicles(0)).unwrap().len(), 2);
+        assert_eq!(block_on(pages.vehicles(1)).unwrap(), vec![vehicle("c")]);
+        assert!(block_on(pages.vehicles(2)).unwrap().is_empty());
+        let unpaged = VehiclePages {
+            page_size: 0,
+            ..pages
+        };
+        assert!(block_on(unpaged.vehicles(0)).is_err());
+    }
+
+    #[test]
+    fn test_flight_plans_between() {
+        let start = Tz::UTC.with_ymd_and_hms(2030, 1, 1, 8, 0, 0).unwrap();
+        let flight_plans: Vec<FlightPlan> = [0, 3, 10]
+            .iter()
+            .map(|hours| FlightPlan {
+                id: format!("plan-{}", hours),
+                data: Some(create_flight_plan_data(
+                    "vehicle".to_string(),
+                    "SFO".to_string(),
+                    "OAK".to_string(),
+                    start + Duration::hours(*hours),
+                    start + Duration::hours(*hours) + Duration::minutes(30),
+                )),
+            })
+            .collect();
+        let fetched = block_on(
+            flight_plans
+                .flight_plans_between(start + Duration::minutes(15), start + Duration::hours(3)),
+        )
+        .unwrap();
+        assert_eq!(
+            fetched
+                .iter()
+                .map(|flight_plan| flight_plan.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["plan-0", "plan-3"]
+        );
+    }
+
+    #[test]
+    fn test_time_window_required() {
+        let result = block_on(get_possible_flights_async(
+            Vertiport::default(),
+            Vertiport::default(),
+            vec![],
+            vec![],
+            None,
+            None,
+            &VehiclePages::default(),
+            &Vec::<FlightPlan>::new(),
+        ));
+        assert_eq!(
+            result.unwrap_err(),
+            "Both earliest departure and latest arrival time must be specified"
+        );
+    }
+}
@@ -0,0 +1,563 @@
+//! Python bindings of the router.
+//!
+//! Analysis notebooks call the same node generation, routing and scheduling
+//! logic as production through the `router` Python module:
+//! * [`Node`] and the `generate_nodes` functions build networks
+//! * `Router` routes on a network owned by Python
+//! * `init_router`, `get_route` and `get_possible_flights` run the
+//!   scheduling entry points on the cargo router
+//!
+//! Times are passed as Unix timestamps in seconds. Build the module with
+//! `cargo rustc --release --features python,pyo3/extension-module --crate-type cdylib`
+//! and copy `librouter.so` to `router.so` on the Python path.
+
+use std::mem::ManuallyDrop;
+
+use ordered_float::OrderedFloat;
+use prost_types::Timestamp;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::context::current_context;
+use crate::distance::Distance;
+use crate::generator;
+use crate::haversine;
+use crate::location::Location;
+use crate::node::Node;
+use crate::resources::vehicle::Data as VehicleData;
+use crate::resources::vertipad::Data as VertipadData;
+use crate::resources::vertiport::Data as VertiportData;
+use crate::router::engine::Router;
+use crate::router_state::{
+    self, Aircraft, FlightPlan, FlightPlanData, PossibleFlight, RouteQuery, Vehicle, Vertipad,
+    Vertiport,
+};
+
+/// Locations along a route as latitude, longitude and altitude, and its
+/// length in kilometers
+type PyRoute = (Vec<(f32, f32, f32)>, f32);
+
+/// Converts the result of a route query for Python
+fn to_py_route(route: Result<(Vec<Location>, f32), String>) -> PyResult<PyRoute> {
+    let (locations, distance_km) = route.map_err(PyValueError::new_err)?;
+    let locations = locations
+        .iter()
+        .map(|location| {
+            (
+                location.latitude.into_inner(),
+                location.longitude.into_inner(),
+                location.altitude_meters.into_inner(),
+            )
+        })
+        .collect();
+    Ok((locations, distance_km))
+}
+
+/// Converts a Unix timestamp in seconds from Python
+fn to_timestamp(seconds: Option<i64>) -> Option<Timestamp> {
+    seconds.map(|seconds| Timestamp { seconds, nanos: 0 })
+}
+
+/// A node of the network
+#[pyclass(name = "Node", module = "router")]
+#[derive(Debug, Clone)]
+pub struct PyNode(pub Node);
+
+#[pymethods]
+impl PyNode {
+    #[new]
+    #[pyo3(signature = (uid, latitude, longitude, altitude_meters = 0.0, schedule = None))]
+    fn new(
+        uid: String,
+        latitude: f32,
+        longitude: f32,
+        altitude_meters: f32,
+        schedule: Option<String>,
+    ) -> Self {
+        PyNode(Node {
+            schedule,
+            ..Node::new(uid, latitude, longitude).with_altitude_meters(altitude_meters)
+        })
+    }
+
+    /// id of the node
+    #[getter]
+    fn uid(&self) -> &str {
+        &self.0.uid
+    }
+
+    /// latitude in degrees
+    #[getter]
+    fn latitude(&self) -> f32 {
+        self.0.location.latitude.into_inner()
+    }
+
+    /// longitude in degrees
+    #[getter]
+    fn longitude(&self) -> f32 {
+        self.0.location.longitude.into_inner()
+    }
+
+    /// altitude in meters
+    #[getter]
+    fn altitude_meters(&self) -> f32 {
+        self.0.location.altitude_meters.into_inner()
+    }
+
+    /// calendar of the node as RRule string
+    #[getter]
+    fn schedule(&self) -> Option<&str> {
+        self.0.schedule.as_deref()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Node('{}', {}, {})",
+            self.0.uid, self.0.location.latitude, self.0.location.longitude
+        )
+    }
+}
+
+impl PyNode {
+    /// The node as a storage vertiport
+    fn to_vertiport(&self) -> Vertiport {
+        Vertiport {
+            id: self.0.uid.clone(),
+            data: Some(VertiportData {
+                latitude: self.0.location.latitude.into_inner() as f64,
+                longitude: self.0.location.longitude.into_inner() as f64,
+                schedule: self.0.schedule.clone(),
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+/// Generates random nodes, the same for the same seed
+#[pyfunction]
+#[pyo3(signature = (capacity, seed = None))]
+fn generate_nodes(capacity: i32, seed: Option<u64>) -> Vec<PyNode> {
+    let nodes = match seed {
+        Some(seed) => generator::generate_nodes_with_seed(seed, capacity),
+        None => generator::generate_nodes(capacity),
+    };
+    nodes.into_iter().map(PyNode).collect()
+}
+
+/// Generates random nodes within `radius_km` of a location, the same for the
+/// same seed
+#[pyfunction]
+#[pyo3(signature = (latitude, longitude, radius_km, capacity, seed = None))]
+fn generate_nodes_near(
+    latitude: f32,
+    longitude: f32,
+    radius_km: f32,
+    capacity: i32,
+    seed: Option<u64>,
+) -> Vec<PyNode> {
+    let location = Location {
+        latitude: OrderedFloat(latitude),
+        longitude: OrderedFloat(longitude),
+        altitude_meters: OrderedFloat(0.0),
+    };
+    let radius = Distance::from_km(radius_km);
+    let nodes = match seed {
+        Some(seed) => generator::generate_nodes_near_with_seed(seed, &location, radius, capacity),
+        None => generator::generate_nodes_near(&location, radius, capacity),
+    };
+    nodes.into_iter().map(PyNode).collect()
+}
+
+/// A router owning its nodes, connecting the nodes at most
+/// `max_distance_km` apart and weighting legs with their distance
+///
+/// The router isn't shared between threads, so it is only usable from the
+/// Python thread that built it.
+#[pyclass(name = "Router", module = "router", unsendable)]
+pub struct PyRouter {
+    /// router borrowing the nodes, dropped before them
+    router: ManuallyDrop<Router<'static>>,
+    /// box of the nodes, freed with the router
+    boxed_nodes: *mut [Node],
+}
+
+impl Drop for PyRouter {
+    fn drop(&mut self) {
+        // nothing borrows the nodes once the router is dropped, and they
+        // were boxed by `PyRouter::new`
+        unsafe {
+            ManuallyDrop::drop(&mut self.router);
+            drop(Box::from_raw(self.boxed_nodes));
+        }
+    }
+}
+
+#[pymethods]
+impl PyRouter {
+    #[new]
+    fn new(nodes: Vec<PyNode>, max_distance_km: f32) -> Self {
+        let nodes: Box<[Node]> = nodes.into_iter().map(|node| node.0).collect();
+        let boxed_nodes = Box::into_raw(nodes);
+        // the nodes are only freed after the router, see `Drop`
+        let nodes: &'static [Node] = unsafe { &*boxed_nodes };
+        let router = Router::new(
+            nodes,
+            max_distance_km,
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+        );
+        PyRouter {
+            router: ManuallyDrop::new(router),
+            boxed_nodes,
+        }
+    }
+
+    /// Finds the shortest route between the nodes with the ids
+    fn get_route(&self, from_uid: &str, to_uid: &str) -> PyResult<PyRoute> {
+        let node = |uid: &str| {
+            self.router
+                .get_node_by_uid(uid)
+                .ok_or_else(|| PyValueError::new_err(format!("Node not found by id: {}", uid)))
+        };
+        let query = RouteQuery::new(Aircraft::Cargo, node(from_uid)?, node(to_uid)?);
+        to_py_route(router_state::get_route_with(
+            &self.router,
+            &current_context(),
+            query,
+        ))
+    }
+
+    /// Precomputes the shortest paths between all pairs of nodes, so routes
+    /// are looked up instead of searched
+    fn precompute_all_pairs(&self) {
+        self.router.precompute_all_pairs();
+    }
+
+    fn __len__(&self) -> usize {
+        self.router.get_node_count()
+    }
+}
+
+/// Initializes the cargo router with the nodes, once per process
+#[pyfunction]
+fn init_router(nodes: Vec<PyNode>) -> PyResult<()> {
+    router_state::init_router_from_nodes(nodes.into_iter().map(|node| node.0).collect())
+        .map_err(PyValueError::new_err)
+}
+
+/// Finds the shortest route between the nodes with the ids on the cargo
+/// router
+#[pyfunction]
+fn get_route(from_uid: &str, to_uid: &str) -> PyResult<PyRoute> {
+    if !router_state::is_router_initialized() {
+        return Err(PyValueError::new_err("Router not initialized"));
+    }
+    let from = router_state::get_node_by_id(from_uid).map_err(PyValueError::new_err)?;
+    let to = router_state::get_node_by_id(to_uid).map_err(PyValueError::new_err)?;
+    to_py_route(router_state::get_route(RouteQuery::new(
+        Aircraft::Cargo,
+        from,
+        to,
+    )))
+}
+
+/// A vertipad of a vertiport
+#[pyclass(name = "Vertipad", module = "router")]
+#[derive(Debug, Clone)]
+pub struct PyVertipad(pub Vertipad);
+
+#[pymethods]
+impl PyVertipad {
+    #[new]
+    #[pyo3(signature = (id, vertiport_id, schedule = None, enabled = true, occupied = false))]
+    fn new(
+        id: String,
+        vertiport_id: String,
+        schedule: Option<String>,
+        enabled: bool,
+        occupied: bool,
+    ) -> Self {
+        PyVertipad(Vertipad {
+            id,
+            data: Some(VertipadData {
+                vertiport_id,
+                schedule,
+                enabled,
+                occupied,
+                ..Default::default()
+            }),
+        })
+    }
+
+    /// id of the vertipad
+    #[getter]
+    fn id(&self) -> &str {
+        &self.0.id
+    }
+}
+
+/// A vehicle of the fleet
+#[pyclass(name = "Vehicle", module = "router")]
+#[derive(Debug, Clone)]
+pub struct PyVehicle(pub Vehicle);
+
+#[pymethods]
+impl PyVehicle {
+    #[new]
+    #[pyo3(signature = (id, last_vertiport_id = None, schedule = None))]
+    fn new(id: String, last_vertiport_id: Option<String>, schedule: Option<String>) -> Self {
+        PyVehicle(Vehicle {
+            id,
+            data: Some(VehicleData {
+                last_vertiport_id,
+                schedule,
+                ..Default::default()
+            }),
+        })
+    }
+
+    /// id of the vehicle
+    #[getter]
+    fn id(&self) -> &str {
+        &self.0.id
+    }
+}
+
+/// A flight plan, existing or found by `get_possible_flights`
+#[pyclass(name = "FlightPlan", module = "router")]
+#[derive(Debug, Clone)]
+pub struct PyFlightPlan(pub FlightPlan);
+
+#[pymethods]
+impl PyFlightPlan {
+    #[new]
+    #[pyo3(signature = (
+        id,
+        vehicle_id,
+        departure_vertiport_id,
+        destination_vertiport_id,
+        scheduled_departure,
+        scheduled_arrival,
+        departure_vertipad_id = String::new(),
+        destination_vertipad_id = String::new(),
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        id: String,
+        vehicle_id: String,
+        departure_vertiport_id: String,
+        destination_vertiport_id: String,
+        scheduled_departure: i64,
+        scheduled_arrival: i64,
+        departure_vertipad_id: String,
+        destination_vertipad_id: String,
+    ) -> Self {
+        PyFlightPlan(FlightPlan {
+            id,
+            data: Some(FlightPlanData {
+                vehicle_id,
+                departure_vertiport_id: Some(departure_vertiport_id),
+                destination_vertiport_id: Some(destination_vertiport_id),
+                scheduled_departure: to_timestamp(Some(scheduled_departure)),
+                scheduled_arrival: to_timestamp(Some(scheduled_arrival)),
+                departure_vertipad_id,
+                destination_vertipad_id,
+                ..Default::default()
+            }),
+        })
+    }
+
+    /// id of the flight plan, empty for a possible flight
+    #[getter]
+    fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    /// id of the vehicle
+    #[getter]
+    fn vehicle_id(&self) -> Option<&str> {
+        self.data().map(|data| data.vehicle_id.as_str())
+    }
+
+    /// id of the departure vertiport
+    #[getter]
+    fn departure_vertiport_id(&self) -> Option<&str> {
+        self.data()?.departure_vertiport_id.as_deref()
+    }
+
+    /// id of the destination vertiport
+    #[getter]
+    fn destination_vertiport_id(&self) -> Option<&str> {
+        self.data()?.destination_vertiport_id.as_deref()
+    }
+
+    /// id of the departure vertipad
+    #[getter]
+    fn departure_vertipad_id(&self) -> Option<&str> {
+        self.data().map(|data| data.departure_vertipad_id.as_str())
+    }
+
+    /// id of the destination vertipad
+    #[getter]
+    fn destination_vertipad_id(&self) -> Option<&str> {
+        self.data()
+            .map(|data| data.destination_vertipad_id.as_str())
+    }
+
+    /// scheduled departure as a Unix timestamp in seconds
+    #[getter]
+    fn scheduled_departure(&self) -> Option<i64> {
+        Some(self.data()?.scheduled_departure.as_ref()?.seconds)
+    }
+
+    /// scheduled arrival as a Unix timestamp in seconds
+    #[getter]
+    fn scheduled_arrival(&self) -> Option<i64> {
+        Some(self.data()?.scheduled_arrival.as_ref()?.seconds)
+    }
+
+    /// distance of the flight in meters
+    #[getter]
+    fn flight_distance_meters(&self) -> Option<i64> {
+        self.data().map(|data| data.flight_distance_meters)
+    }
+}
+
+impl PyFlightPlan {
+    /// Data of the flight plan
+    fn data(&self) -> Option<&FlightPlanData> {
+        self.0.data.as_ref()
+    }
+}
+
+/// A flight found by `get_possible_flights`
+#[pyclass(name = "PossibleFlight", module = "router", get_all)]
+#[derive(Debug, Clone)]
+pub struct PyPossibleFlight {
+    /// flight plan of the requested flight
+    pub flight_plan: PyFlightPlan,
+    /// deadhead flight plans bringing the vehicle to the departure vertiport
+    pub deadhead_flight_plans: Vec<PyFlightPlan>,
+    /// vertiport to divert to if the destination becomes unavailable
+    pub alternate_vertiport_id: String,
+    /// median arrival as a Unix timestamp in seconds
+    pub eta_p50: i64,
+    /// total estimated operating cost of the flight and its deadhead flights
+    pub cost: f32,
+    /// minutes the flight was delayed to queue for a pad
+    pub queue_minutes: i64,
+}
+
+impl From<PossibleFlight> for PyPossibleFlight {
+    fn from(flight: PossibleFlight) -> Self {
+        let plan = |data| {
+            PyFlightPlan(FlightPlan {
+                id: String::new(),
+                data: Some(data),
+            })
+        };
+        PyPossibleFlight {
+            eta_p50: flight.eta.p50().timestamp(),
+            cost: flight.cost.total(),
+            flight_plan: plan(flight.flight_plan),
+            deadhead_flight_plans: flight.deadhead_flight_plans.into_iter().map(plan).collect(),
+            alternate_vertiport_id: flight.alternate_vertiport_id,
+            queue_minutes: flight.queue_minutes,
+        }
+    }
+}
+
+/// Finds all possible flights between two vertiports of the cargo router
+/// within a time window, see [`router_state::get_possible_flights`]
+#[pyfunction]
+#[pyo3(signature = (
+    departure,
+    arrival,
+    departure_vertipads,
+    arrival_vertipads,
+    earliest_departure,
+    latest_arrival,
+    vehicles,
+    existing_flight_plans = Vec::new(),
+))]
+#[allow(clippy::too_many_arguments)]
+fn get_possible_flights(
+    departure: PyNode,
+    arrival: PyNode,
+    departure_vertipads: Vec<PyVertipad>,
+    arrival_vertipads: Vec<PyVertipad>,
+    earliest_departure: Option<i64>,
+    latest_arrival: Option<i64>,
+    vehicles: Vec<PyVehicle>,
+    existing_flight_plans: Vec<PyFlightPlan>,
+) -> PyResult<Vec<PyPossibleFlight>> {
+    let flights = router_state::get_possible_flights(
+        departure.to_vertiport(),
+        arrival.to_vertiport(),
+        departure_vertipads.into_iter().map(|pad| pad.0).collect(),
+        arrival_vertipads.into_iter().map(|pad| pad.0).collect(),
+        to_timestamp(earliest_departure),
+        to_timestamp(latest_arrival),
+        vehicles.into_iter().map(|vehicle| vehicle.0).collect(),
+        existing_flight_plans
+            .into_iter()
+            .map(|plan| plan.0)
+            .collect(),
+    )
+    .map_err(PyValueError::new_err)?;
+    Ok(flights.into_iter().map(PyPossibleFlight::from).collect())
+}
+
+/// The `router` Python module
+#[pymodule]
+#[pyo3(name = "router")]
+fn router_module(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyNode>()?;
+    module.add_class::<PyRouter>()?;
+    module.add_class::<PyVertipad>()?;
+    module.add_class::<PyVehicle>()?;
+    module.add_class::<PyFlightPlan>()?;
+    module.add_class::<PyPossibleFlight>()?;
+    module.add_function(wrap_pyfunction!(generate_nodes, module)?)?;
+    module.add_function(wrap_pyfunction!(generate_nodes_near, module)?)?;
+    module.add_function(wrap_pyfunction!(init_router, module)?)?;
+    module.add_function(wrap_pyfunction!(get_route, module)?)?;
+    module.add_function(wrap_pyfunction!(get_possible_flights, module)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod python_tests {
+    use super::*;
+
+    #[test]
+    fn test_router_get_route() {
+        let nodes = vec![
+            PyNode::new("A".to_string(), 0.0, 0.0, 0.0, None),
+            PyNode::new("B".to_string(), 0.0, 0.1, 0.0, None),
+            PyNode::new(
+                "C".to_string(),
+                0.0,
+                0.2,
+                0.0,
+                Some("FREQ=DAILY".to_string()),
+            ),
+        ];
+        let vertiport = nodes[2].to_vertiport();
+        assert_eq!(vertiport.id, "C");
+        assert_eq!(
+            vertiport.data.unwrap().schedule.as_deref(),
+            Some("FREQ=DAILY")
+        );
+
+        let router = PyRouter::new(nodes, 12.0);
+        assert_eq!(router.__len__(), 3);
+        let (locations, distance_km) = router.get_route("A", "C").unwrap();
+        assert_eq!(
+            locations,
+            vec![(0.0, 0.0, 0.0), (0.0, 0.1, 0.0), (0.0, 0.2, 0.0)]
+        );
+        assert!((distance_km - 22.24).abs() < 0.1);
+        assert!(router.get_route("A", "D").is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! Queueing for the pads of a vertiport.
+//!
+//! When more movements are scheduled at a vertiport than it has pads, a
+//! flight doesn't have to be discarded: it can hold until a pad frees up. A
+//! [`PadTimeline`] tracks when the pads of a vertiport are occupied by the
+//! takeoffs and landings of the existing flight plans and returns the
+//! expected delay of a candidate slot.
+//! [`get_possible_flights`](crate::router_state::get_possible_flights) nudges
+//! a slot whose vertiports are busy by up to [`MAX_PAD_QUEUE_MINUTES`].
+
+use chrono::{DateTime, Duration};
+use rrule::Tz;
+
+use crate::amendment::PlanTimes;
+use crate::router_state::{
+    FlightPlan, LANDING_AND_UNLOADING_TIME_MIN, LOADING_AND_TAKEOFF_TIME_MIN,
+};
+
+/// Max delay of a slot queueing for a pad, queued departures stay before the
+/// next departure slot
+pub const MAX_PAD_QUEUE_MINUTES: i64 = 4;
+
+/// Occupancy of the pads of a vertiport over time
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PadTimeline {
+    /// number of pads, at least one
+    pads: usize,
+    /// start and end in seconds since epoch of each occupancy, by start
+    occupancy: Vec<(i64, i64)>,
+}
+
+impl PadTimeline {
+    /// Creates the timeline of free pads, a vertiport without pads is treated
+    /// as having a single one
+    pub fn new(pads: usize) -> Self {
+        PadTimeline {
+            pads: pads.max(1),
+            occupancy: vec![],
+        }
+    }
+
+    /// Creates the timeline of a vertiport occupied by the existing flight plans
+    /// Departures occupy a pad for loading and takeoff, arrivals for landing
+    /// and unloading
+    pub fn for_vertiport(
+        vertiport_id: &str,
+        pads: usize,
+        existing_flight_plans: &[FlightPlan],
+    ) -> Self {
+        let loading = LOADING_AND_TAKEOFF_TIME_MIN as i64 * 60;
+        let unloading = LANDING_AND_UNLOADING_TIME_MIN as i64 * 60;
+        let mut timeline = PadTimeline::new(pads);
+        for plan in existing_flight_plans
+            .iter()
+            .filter_map(|flight_plan| PlanTimes::from_flight_plan(flight_plan).ok())
+        {
+            if plan.departure_vertiport_id == vertiport_id {
+                timeline.push(plan.departure, plan.departure + loading);
+            }
+            if plan.destination_vertiport_id == vertiport_id {
+                timeline.push(plan.arrival - unloading, plan.arrival);
+            }
+        }
+        timeline
+    }
+
+    /// Occupies a pad from `start` to `end`
+    pub fn with_occupancy(mut self, start: DateTime<Tz>, end: DateTime<Tz>) -> Self {
+        self.push(start.timestamp(), end.timestamp());
+        self
+    }
+
+    fn push(&mut self, start: i64, end: i64) {
+        let index = self.occupancy.partition_point(|&(other, _)| other <= start);
+        self.occupancy.insert(index, (start, end));
+    }
+
+    /// Most pads occupied at once between `from` and `to`, in seconds since epoch
+    fn max_occupied(&self, from: i64, to: i64) -> usize {
+        std::iter::once(from)
+            .chain(
+                self.occupancy
+                    .iter()
+                    .map(|&(start, _)| start)
+                    .filter(|&start| from < start && start < to),
+            )
+            .map(|time| {
+                self.occupancy
+                    .iter()
+                    .filter(|&&(start, end)| start <= time && time < end)
+                    .count()
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Checks if a pad is free for `minutes` from `start`
+    pub fn is_free(&self, start: DateTime<Tz>, minutes: i64) -> bool {
+        let start = start.timestamp();
+        self.max_occupied(start, start + minutes * 60) < self.pads
+    }
+
+    /// Expected delay in whole minutes before a pad is free for `minutes` from
+    /// `start`, 0 if a pad is free right away
+    pub fn expected_delay_minutes(&self, start: DateTime<Tz>, minutes: i64) -> i64 {
+        let requested = start.timestamp();
+        // a pad frees up at the end of an occupancy
+        let mut delays: Vec<i64> = self
+            .occupancy
+            .iter()
+            .filter(|&&(_, end)| end > requested)
+            .map(|&(_, end)| (end - requested + 59) / 60)
+            .collect();
+        delays.sort_unstable();
+        std::iter::once(0)
+            .chain(delays)
+            .find(|&delay| self.is_free(start + Duration::minutes(delay), minutes))
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod queueing_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use chrono::TimeZone;
+
+    fn at(minute: i64) -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2030, 1, 1, 8, 0, 0).unwrap() + Duration::minutes(minute)
+    }
+
+    #[test]
+    fn test_expected_delay() {
+        let timeline = PadTimeline::new(1)
+            .with_occupancy(at(0), at(10))
+            .with_occupancy(at(12), at(20));
+        assert_eq!(timeline.expected_delay_minutes(at(-20), 10), 0);
+        assert_eq!(timeline.expected_delay_minutes(at(-5), 10), 25);
+        // the gap between the occupancies is too short
+        assert_eq!(timeline.expected_delay_minutes(at(5), 10), 15);
+        assert_eq!(timeline.expected_delay_minutes(at(5), 2), 5);
+        assert_eq!(timeline.expected_delay_minutes(at(20), 10), 0);
+
+        // a second pad takes one of the overlapping occupancies
+        let timeline = timeline.with_occupancy(at(5), at(15));
+        assert_eq!(timeline.expected_delay_minutes(at(5), 10), 15);
+        let mut timeline = timeline;
+        timeline.pads = 2;
+        assert_eq!(timeline.expected_delay_minutes(at(5), 5), 10);
+        assert!(timeline.is_free(at(15), 5));
+        assert!(!timeline.is_free(at(8), 5));
+    }
+
+    #[test]
+    fn test_timeline_of_vertiport() {
+        let flight_plans = vec![FlightPlan {
+            id: "plan".to_string(),
+            data: Some(create_flight_plan_data(
+                "vehicle".to_string(),
+                "SFO".to_string(),
+                "OAK".to_string(),
+                at(0),
+                at(40),
+            )),
+        }];
+        let sfo = PadTimeline::for_vertiport("SFO", 0, &flight_plans);
+        assert_eq!(sfo.expected_delay_minutes(at(0), 5), 10);
+        assert_eq!(sfo.expected_delay_minutes(at(10), 5), 0);
+        let oak = PadTimeline::for_vertiport("OAK", 1, &flight_plans);
+        assert_eq!(oak.expected_delay_minutes(at(25), 10), 15);
+        assert!(oak.is_free(at(20), 10));
+    }
+}
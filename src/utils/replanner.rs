@@ -0,0 +1,519 @@
+//! Rolling-horizon replanning of the fleet schedule.
+//!
+//! The [`Replanner`] keeps a schedule for the shipments picked up within the
+//! next `horizon` and repairs it incrementally on every [`ReplanEvent`]
+//! instead of recomputing it from scratch. Only the vehicles affected by an
+//! event are replanned; flights which already departed are kept as they are.
+//! Shipments which no longer fit their vehicle are reinserted into the fleet,
+//! with a stability penalty for moving them to another vehicle so that
+//! previously promised flights change as little as possible.
+
+use chrono::{DateTime, Duration};
+use prost_types::Timestamp;
+use rrule::Tz;
+
+use crate::multistop::Shipment;
+use crate::router_state::{estimate_flight_time_between, FlightPlanData};
+use crate::schedule::TimeSlot;
+use crate::vrp::{
+    plan_insertion, Activity, ChargingConfig, DaySchedule, FleetVehicle, Insertion,
+    VehicleSchedule, VehicleState,
+};
+
+/// Max number of times an insertion is postponed to avoid vertiport closures
+const MAX_CLOSURE_RETRIES: usize = 10;
+
+/// Event which requires the schedule to be repaired
+#[derive(Debug, Clone)]
+pub enum ReplanEvent {
+    /// A new shipment is booked
+    Booking(Shipment),
+    /// A booked shipment is cancelled
+    Cancellation(String),
+    /// A vehicle can't start any new activity for the given minutes from now
+    Delay {
+        /// id of the delayed vehicle
+        vehicle_id: String,
+        /// length of the delay
+        minutes: i64,
+    },
+    /// A vertiport is closed for takeoffs and landings due to weather
+    Weather {
+        /// id of the closed vertiport
+        vertiport_id: String,
+        /// time of the closure
+        closure: TimeSlot,
+    },
+}
+
+/// Outcome of handling an event
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplanResult {
+    /// ids of shipments whose vehicle or flights changed
+    pub changed: Vec<String>,
+    /// ids of shipments in the horizon which are not scheduled
+    pub unassigned: Vec<String>,
+}
+
+/// A shipment assigned to a vehicle and the activities serving it
+#[derive(Debug, Clone)]
+struct Entry {
+    shipment: Shipment,
+    activities: Vec<Activity>,
+    /// state of the vehicle after the shipment is delivered
+    state: VehicleState,
+}
+
+impl Entry {
+    /// Returns true if the first flight serving the shipment already departed
+    fn is_started(&self, now: DateTime<Tz>) -> bool {
+        self.activities
+            .iter()
+            .filter_map(|activity| activity.flight_plan())
+            .filter_map(|flight_plan| flight_plan.scheduled_departure.as_ref())
+            .any(|departure| departure.seconds < now.timestamp())
+    }
+}
+
+/// Incremental replanner over a rolling horizon
+pub struct Replanner<F> {
+    fleet: Vec<FleetVehicle>,
+    charging: ChargingConfig,
+    flight_minutes: F,
+    horizon: Duration,
+    stability_penalty_minutes: i64,
+    now: DateTime<Tz>,
+    /// assigned shipments per vehicle, in the order they are served
+    entries: Vec<Vec<Entry>>,
+    /// time before which a vehicle can't start new activities
+    not_before: Vec<Option<DateTime<Tz>>>,
+    /// booked shipments beyond the horizon
+    pending: Vec<Shipment>,
+    /// shipments in the horizon which could not be scheduled
+    unassigned: Vec<Shipment>,
+    /// closed vertiports
+    closures: Vec<(String, TimeSlot)>,
+}
+
+impl Replanner<fn(&str, &str) -> Option<i64>> {
+    /// Creates a replanner with flight times estimated from the router
+    pub fn new(
+        fleet: Vec<FleetVehicle>,
+        charging: ChargingConfig,
+        horizon_hours: i64,
+        stability_penalty_minutes: i64,
+        now: DateTime<Tz>,
+    ) -> Self {
+        fn router_flight_minutes(from: &str, to: &str) -> Option<i64> {
+            estimate_flight_time_between(from, to).ok()
+        }
+        Replanner::with_flight_minutes(
+            fleet,
+            charging,
+            horizon_hours,
+            stability_penalty_minutes,
+            now,
+            router_flight_minutes,
+        )
+    }
+}
+
+impl<F> Replanner<F>
+where
+    F: Fn(&str, &str) -> Option<i64>,
+{
+    /// Creates a replanner using the given flight time estimate
+    pub fn with_flight_minutes(
+        fleet: Vec<FleetVehicle>,
+        charging: ChargingConfig,
+        horizon_hours: i64,
+        stability_penalty_minutes: i64,
+        now: DateTime<Tz>,
+        flight_minutes: F,
+    ) -> Self {
+        let vehicles = fleet.len();
+        Replanner {
+            fleet,
+            charging,
+            flight_minutes,
+            horizon: Duration::hours(horizon_hours),
+            stability_penalty_minutes,
+            now,
+            entries: vec![vec![]; vehicles],
+            not_before: vec![None; vehicles],
+            pending: vec![],
+            unassigned: vec![],
+            closures: vec![],
+        }
+    }
+
+    /// Returns the current schedule of the fleet
+    pub fn schedule(&self) -> DaySchedule {
+        DaySchedule {
+            vehicles: self
+                .fleet
+                .iter()
+                .zip(&self.entries)
+                .map(|(vehicle, entries)| VehicleSchedule {
+                    vehicle_id: vehicle.vehicle_id.clone(),
+                    activities: entries
+                        .iter()
+                        .flat_map(|entry| entry.activities.clone())
+                        .collect(),
+                })
+                .collect(),
+            unassigned: self
+                .unassigned
+                .iter()
+                .map(|shipment| shipment.id.clone())
+                .collect(),
+        }
+    }
+
+    /// Moves the horizon forward and schedules the shipments entering it
+    pub fn advance(&mut self, now: DateTime<Tz>) -> ReplanResult {
+        self.now = now;
+        let horizon_end = now + self.horizon;
+        let (entering, pending): (Vec<Shipment>, Vec<Shipment>) = self
+            .pending
+            .drain(..)
+            .partition(|shipment| shipment.earliest_pickup_time < horizon_end);
+        self.pending = pending;
+        let mut changed = vec![];
+        for shipment in entering {
+            self.insert(shipment, None, &mut changed);
+        }
+        self.finish(changed)
+    }
+
+    /// Repairs the schedule after an event
+    pub fn handle(&mut self, event: ReplanEvent) -> ReplanResult {
+        info!("Replanning for event {:?}", event);
+        let mut changed = vec![];
+        match event {
+            ReplanEvent::Booking(shipment) => {
+                if shipment.earliest_pickup_time >= self.now + self.horizon {
+                    debug!("Shipment {} is beyond the horizon", shipment.id);
+                    self.pending.push(shipment);
+                } else {
+                    self.insert(shipment, None, &mut changed);
+                }
+            }
+            ReplanEvent::Cancellation(shipment_id) => {
+                self.pending.retain(|shipment| shipment.id != shipment_id);
+                self.unassigned
+                    .retain(|shipment| shipment.id != shipment_id);
+                let vehicle = self.entries.iter().position(|entries| {
+                    entries.iter().any(|entry| entry.shipment.id == shipment_id)
+                });
+                if let Some(index) = vehicle {
+                    self.entries[index].retain(|entry| entry.shipment.id != shipment_id);
+                    self.repair_vehicle(index, &mut changed);
+                }
+            }
+            ReplanEvent::Delay {
+                vehicle_id,
+                minutes,
+            } => {
+                if let Some(index) = self
+                    .fleet
+                    .iter()
+                    .position(|vehicle| vehicle.vehicle_id == vehicle_id)
+                {
+                    self.not_before[index] = Some(self.now + Duration::minutes(minutes));
+                    self.repair_vehicle(index, &mut changed);
+                }
+            }
+            ReplanEvent::Weather {
+                vertiport_id,
+                closure,
+            } => {
+                self.closures.push((vertiport_id, closure));
+                for index in 0..self.fleet.len() {
+                    let affected = self.entries[index].iter().any(|entry| {
+                        !entry.is_started(self.now)
+                            && self.blocking_closure(&entry.activities).is_some()
+                    });
+                    if affected {
+                        self.repair_vehicle(index, &mut changed);
+                    }
+                }
+            }
+        }
+        // freed capacity may fit previously unassigned shipments
+        for shipment in std::mem::take(&mut self.unassigned) {
+            self.insert(shipment, None, &mut changed);
+        }
+        self.finish(changed)
+    }
+
+    fn finish(&self, mut changed: Vec<String>) -> ReplanResult {
+        changed.sort();
+        changed.dedup();
+        ReplanResult {
+            changed,
+            unassigned: self
+                .unassigned
+                .iter()
+                .map(|shipment| shipment.id.clone())
+                .collect(),
+        }
+    }
+
+    /// Returns the end of the first closure one of the flights takes off or lands in
+    fn blocking_closure(&self, activities: &[Activity]) -> Option<DateTime<Tz>> {
+        activities
+            .iter()
+            .filter_map(|activity| activity.flight_plan())
+            .find_map(|flight_plan| {
+                self.closures.iter().find_map(|(vertiport_id, closure)| {
+                    let touches = |vertiport: &Option<String>, time: &Option<Timestamp>| {
+                        vertiport.as_deref() == Some(vertiport_id.as_str())
+                            && time.as_ref().is_some_and(|time| {
+                                time.seconds >= closure.start.timestamp()
+                                    && time.seconds < closure.end.timestamp()
+                            })
+                    };
+                    let blocked = touches(
+                        &flight_plan.departure_vertiport_id,
+                        &flight_plan.scheduled_departure,
+                    ) || touches(
+                        &flight_plan.destination_vertiport_id,
+                        &flight_plan.scheduled_arrival,
+                    );
+                    blocked.then_some(closure.end)
+                })
+            })
+    }
+
+    /// Plans the shipment after `state`, postponed past any vertiport closures
+    fn plan(&self, index: usize, state: &VehicleState, shipment: &Shipment) -> Option<Insertion> {
+        let mut state = state.clone();
+        if let Some(not_before) = self.not_before[index] {
+            state.time = state.time.max(not_before);
+        }
+        for _ in 0..MAX_CLOSURE_RETRIES {
+            let insertion = plan_insertion(
+                &self.fleet[index],
+                &state,
+                shipment,
+                &self.charging,
+                &self.flight_minutes,
+            )?;
+            match self.blocking_closure(&insertion.activities) {
+                None => return Some(insertion),
+                Some(end) => state.time = end,
+            }
+        }
+        None
+    }
+
+    /// Returns the state of the vehicle after all its assigned shipments
+    fn final_state(&self, index: usize) -> VehicleState {
+        match self.entries[index].last() {
+            Some(entry) => entry.state.clone(),
+            None => VehicleState::initial(&self.fleet[index]),
+        }
+    }
+
+    /// Replans the shipments of a vehicle which didn't start yet, in their current order
+    fn repair_vehicle(&mut self, index: usize, changed: &mut Vec<String>) {
+        debug!(
+            "Repairing schedule of vehicle {}",
+            self.fleet[index].vehicle_id
+        );
+        let entries = std::mem::take(&mut self.entries[index]);
+        let mut released = vec![];
+        for entry in entries {
+            if entry.is_started(self.now) {
+                self.entries[index].push(entry);
+                continue;
+            }
+            let state = self.final_state(index);
+            match self.plan(index, &state, &entry.shipment) {
+                Some(insertion) => {
+                    if insertion.activities != entry.activities {
+                        changed.push(entry.shipment.id.clone());
+                    }
+                    self.entries[index].push(Entry {
+                        shipment: entry.shipment,
+                        activities: insertion.activities,
+                        state: insertion.state,
+                    });
+                }
+                None => released.push(entry.shipment),
+            }
+        }
+        for shipment in released {
+            changed.push(shipment.id.clone());
+            self.insert(shipment, Some(index), changed);
+        }
+    }
+
+    /// Appends the shipment to the vehicle which delivers it earliest, moving
+    /// away from `previous` vehicle is penalized
+    fn insert(&mut self, shipment: Shipment, previous: Option<usize>, changed: &mut Vec<String>) {
+        let best = (0..self.fleet.len())
+            .filter_map(|index| {
+                let insertion = self.plan(index, &self.final_state(index), &shipment)?;
+                let mut cost = (insertion.state.time - self.now).num_minutes();
+                if previous.is_some_and(|previous| previous != index) {
+                    cost += self.stability_penalty_minutes;
+                }
+                Some((cost, index, insertion))
+            })
+            .min_by_key(|(cost, index, _)| (*cost, *index));
+        match best {
+            Some((_, index, insertion)) => {
+                debug!(
+                    "Shipment {} assigned to vehicle {}",
+                    shipment.id, self.fleet[index].vehicle_id
+                );
+                if previous.is_none() {
+                    changed.push(shipment.id.clone());
+                }
+                self.entries[index].push(Entry {
+                    shipment,
+                    activities: insertion.activities,
+                    state: insertion.state,
+                });
+            }
+            None => {
+                debug!("Shipment {} could not be scheduled", shipment.id);
+                self.unassigned.push(shipment);
+            }
+        }
+    }
+}
+
+/// Returns the flight plans of a schedule
+pub fn schedule_flight_plans(schedule: &DaySchedule) -> Vec<FlightPlanData> {
+    schedule
+        .vehicles
+        .iter()
+        .flat_map(|vehicle| vehicle.activities.iter())
+        .filter_map(|activity| activity.flight_plan().cloned())
+        .collect()
+}
+
+#[cfg(test)]
+mod replanner_tests {
+    use super::*;
+    use crate::test_support::mock_flight_minutes;
+    use chrono::TimeZone;
+
+    fn start() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap()
+    }
+
+    fn shipment(id: &str, from: &str, to: &str, ready_min: i64, due_min: i64) -> Shipment {
+        Shipment {
+            id: id.to_string(),
+            pickup_vertiport_id: from.to_string(),
+            delivery_vertiport_id: to.to_string(),
+            weight_grams: 1000,
+            earliest_pickup_time: start() + Duration::minutes(ready_min),
+            latest_delivery_time: start() + Duration::minutes(due_min),
+        }
+    }
+
+    fn replanner() -> Replanner<fn(&str, &str) -> Option<i64>> {
+        let fleet = vec!["v1", "v2"]
+            .into_iter()
+            .map(|id| FleetVehicle {
+                vehicle_id: id.to_string(),
+                start_vertiport_id: "A".to_string(),
+                available_from: start(),
+                capacity_grams: 5000,
+            })
+            .collect();
+        Replanner::with_flight_minutes(
+            fleet,
+            ChargingConfig::default(),
+            4,
+            60,
+            start(),
+            mock_flight_minutes as fn(&str, &str) -> Option<i64>,
+        )
+    }
+
+    fn vehicle_of(replanner: &Replanner<fn(&str, &str) -> Option<i64>>, id: &str) -> Option<usize> {
+        replanner
+            .entries
+            .iter()
+            .position(|entries| entries.iter().any(|entry| entry.shipment.id == id))
+    }
+
+    #[test]
+    fn test_booking_and_cancellation() {
+        let mut replanner = replanner();
+        let result = replanner.handle(ReplanEvent::Booking(shipment("s1", "A", "B", 0, 60)));
+        assert_eq!(result.changed, vec!["s1".to_string()]);
+        replanner.handle(ReplanEvent::Booking(shipment("s2", "B", "C", 20, 120)));
+        assert_eq!(vehicle_of(&replanner, "s2"), Some(0));
+
+        let result = replanner.handle(ReplanEvent::Cancellation("s1".to_string()));
+        // s2 now needs a repositioning flight first
+        assert_eq!(result.changed, vec!["s2".to_string()]);
+        assert_eq!(replanner.schedule().vehicles[0].activities.len(), 2);
+    }
+
+    #[test]
+    fn test_booking_beyond_horizon() {
+        let mut replanner = replanner();
+        let result = replanner.handle(ReplanEvent::Booking(shipment("s1", "A", "B", 300, 400)));
+        assert!(result.changed.is_empty());
+        assert!(vehicle_of(&replanner, "s1").is_none());
+        let result = replanner.advance(start() + Duration::hours(2));
+        assert_eq!(result.changed, vec!["s1".to_string()]);
+    }
+
+    #[test]
+    fn test_delay_keeps_vehicle_when_possible() {
+        let mut replanner = replanner();
+        replanner.handle(ReplanEvent::Booking(shipment("s1", "A", "B", 0, 120)));
+        assert_eq!(vehicle_of(&replanner, "s1"), Some(0));
+        // the shipment still makes its deadline with the delayed vehicle
+        let result = replanner.handle(ReplanEvent::Delay {
+            vehicle_id: "v1".to_string(),
+            minutes: 30,
+        });
+        assert_eq!(result.changed, vec!["s1".to_string()]);
+        assert_eq!(vehicle_of(&replanner, "s1"), Some(0));
+    }
+
+    #[test]
+    fn test_delay_moves_shipment_missing_deadline() {
+        let mut replanner = replanner();
+        replanner.handle(ReplanEvent::Booking(shipment("s1", "A", "B", 0, 40)));
+        assert_eq!(vehicle_of(&replanner, "s1"), Some(0));
+        replanner.handle(ReplanEvent::Delay {
+            vehicle_id: "v1".to_string(),
+            minutes: 30,
+        });
+        assert_eq!(vehicle_of(&replanner, "s1"), Some(1));
+    }
+
+    #[test]
+    fn test_weather_postpones_flights() {
+        let mut replanner = replanner();
+        replanner.handle(ReplanEvent::Booking(shipment("s1", "A", "B", 0, 240)));
+        let result = replanner.handle(ReplanEvent::Weather {
+            vertiport_id: "B".to_string(),
+            closure: TimeSlot {
+                start: start(),
+                end: start() + Duration::minutes(60),
+            },
+        });
+        assert_eq!(result.changed, vec!["s1".to_string()]);
+        let flight_plans = schedule_flight_plans(&replanner.schedule());
+        assert_eq!(
+            flight_plans[0]
+                .scheduled_departure
+                .as_ref()
+                .unwrap()
+                .seconds,
+            (start() + Duration::minutes(60)).timestamp()
+        );
+    }
+}
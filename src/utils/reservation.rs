@@ -0,0 +1,248 @@
+//! Reservation ledger holding returned flight plans until they are confirmed.
+//!
+//! Between [`get_possible_flights`](crate::router_state::get_possible_flights)
+//! returning a draft flight plan and the client confirming the booking, another
+//! request could be offered the same vehicle and pads. A hold reserves the
+//! vehicle, pads and time window of the draft flight plan (including its
+//! deadhead flights) for a number of minutes. Active holds are treated as
+//! existing flight plans by the availability checks and expire automatically.
+
+use chrono::{DateTime, Duration};
+use rrule::Tz;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::clock::{get_clock, now, Clock};
+use crate::conflict::{validate_flight_plans, FlightPlanRef};
+use crate::context::current_context;
+use crate::router_state::{FlightPlan, FlightPlanData};
+
+/// Flight plans held for a client until confirmation or expiry
+#[derive(Debug, Clone)]
+pub struct Hold {
+    /// id of the hold
+    pub id: String,
+    /// held flight plans (the flight plan and its deadhead flights)
+    pub flight_plans: Vec<FlightPlanData>,
+    /// time when the hold expires
+    pub expires_at: DateTime<Tz>,
+}
+
+/// Ledger of holds on flight plans
+#[derive(Debug, Default)]
+pub struct ReservationLedger {
+    holds: HashMap<String, Hold>,
+}
+
+impl ReservationLedger {
+    /// Creates an empty ledger
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Holds flight plans for `minutes`, fails if they conflict with an active hold
+    pub fn hold(
+        &mut self,
+        flight_plans: Vec<FlightPlanData>,
+        minutes: i64,
+        now: DateTime<Tz>,
+    ) -> Result<String, String> {
+        if minutes <= 0 {
+            return Err("Hold duration must be positive".to_string());
+        }
+        self.purge_expired(now);
+        let conflicts = validate_flight_plans(&flight_plans, &self.active_flight_plans(now));
+        if conflicts.iter().any(|conflict| {
+            conflict
+                .flight_plans
+                .iter()
+                .any(|flight_plan| matches!(flight_plan, FlightPlanRef::Existing(_)))
+        }) {
+            debug!("Hold rejected, conflicts: {:?}", conflicts);
+            return Err("Flight plan conflicts with an existing hold".to_string());
+        }
+        let id = Uuid::new_v4().to_string();
+        let expires_at = now + Duration::minutes(minutes);
+        info!(
+            "Holding {} flight plan(s) until {}",
+            flight_plans.len(),
+            expires_at
+        );
+        self.holds.insert(
+            id.clone(),
+            Hold {
+                id: id.clone(),
+                flight_plans,
+                expires_at,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Releases a hold, returns it if it was still active
+    ///
+    /// Used both when the booking is confirmed (and the flight plans become
+    /// existing flight plans) and when the client cancels.
+    pub fn release(&mut self, hold_id: &str, now: DateTime<Tz>) -> Option<Hold> {
+        self.purge_expired(now);
+        self.holds.remove(hold_id)
+    }
+
+    /// Removes all holds which expired at `now`
+    pub fn purge_expired(&mut self, now: DateTime<Tz>) {
+        self.holds.retain(|id, hold| {
+            let active = hold.expires_at > now;
+            if !active {
+                debug!("Hold {} expired", id);
+            }
+            active
+        });
+    }
+
+    /// Returns held flight plans which are active at `now`, with the hold id as flight plan id
+    pub fn active_flight_plans(&self, now: DateTime<Tz>) -> Vec<FlightPlan> {
+        self.holds
+            .values()
+            .filter(|hold| hold.expires_at > now)
+            .flat_map(|hold| {
+                hold.flight_plans.iter().map(|data| FlightPlan {
+                    id: hold.id.clone(),
+                    data: Some(data.clone()),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Holds flight plans for `minutes` in the ledger of the current
+/// [context](crate::context)
+pub fn hold_flight_plans(
+    flight_plans: Vec<FlightPlanData>,
+    minutes: i64,
+) -> Result<String, String> {
+    current_context()
+        .reservations
+        .lock()
+        .map_err(|_| "Reservation ledger unavailable".to_string())?
+        .hold(flight_plans, minutes, now())
+}
+
+/// Releases a hold from the ledger of the current context
+pub fn release_hold(hold_id: &str) -> Result<Option<Hold>, String> {
+    Ok(current_context()
+        .reservations
+        .lock()
+        .map_err(|_| "Reservation ledger unavailable".to_string())?
+        .release(hold_id, now()))
+}
+
+/// Gets the flight plans held at the time of the clock from the ledger of the current context
+pub fn get_held_flight_plans_with(clock: &dyn Clock) -> Vec<FlightPlan> {
+    match current_context().reservations.lock() {
+        Ok(ledger) => ledger.active_flight_plans(clock.now()),
+        Err(_) => {
+            error!("Reservation ledger unavailable");
+            vec![]
+        }
+    }
+}
+
+/// Gets the currently held flight plans from the ledger of the current context
+pub fn get_held_flight_plans() -> Vec<FlightPlan> {
+    get_held_flight_plans_with(get_clock().as_ref())
+}
+
+#[cfg(test)]
+mod reservation_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use chrono::TimeZone;
+
+    fn start() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2022, 10, 25, 10, 0, 0).unwrap()
+    }
+
+    fn draft(vehicle_id: &str, from: &str, to: &str, dep_min: i64) -> FlightPlanData {
+        let departure = start() + Duration::minutes(dep_min);
+        create_flight_plan_data(
+            vehicle_id.to_string(),
+            from.to_string(),
+            to.to_string(),
+            departure,
+            departure + Duration::minutes(30),
+        )
+    }
+
+    #[test]
+    fn test_hold_blocks_conflicting_hold() {
+        let mut ledger = ReservationLedger::new();
+        let now = start() - Duration::hours(1);
+        ledger
+            .hold(vec![draft("v1", "A", "B", 0)], 15, now)
+            .unwrap();
+        assert!(ledger
+            .hold(vec![draft("v1", "C", "D", 10)], 15, now)
+            .is_err());
+        assert!(ledger
+            .hold(vec![draft("v2", "C", "D", 10)], 15, now)
+            .is_ok());
+        assert_eq!(ledger.active_flight_plans(now).len(), 2);
+    }
+
+    #[test]
+    fn test_hold_expires() {
+        let mut ledger = ReservationLedger::new();
+        let now = start() - Duration::hours(1);
+        ledger
+            .hold(vec![draft("v1", "A", "B", 0)], 15, now)
+            .unwrap();
+        let later = now + Duration::minutes(15);
+        assert!(ledger.active_flight_plans(later).is_empty());
+        assert!(ledger
+            .hold(vec![draft("v1", "A", "B", 0)], 15, later)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_release_hold() {
+        let mut ledger = ReservationLedger::new();
+        let now = start() - Duration::hours(1);
+        let id = ledger
+            .hold(
+                vec![draft("v1", "C", "A", -40), draft("v1", "A", "B", 0)],
+                15,
+                now,
+            )
+            .unwrap();
+        assert_eq!(ledger.active_flight_plans(now).len(), 2);
+        let hold = ledger.release(&id, now).unwrap();
+        assert_eq!(hold.flight_plans.len(), 2);
+        assert!(ledger.active_flight_plans(now).is_empty());
+        assert!(ledger.release(&id, now).is_none());
+    }
+
+    #[test]
+    fn test_held_flight_plans_at_clock_time() {
+        use crate::clock::SimulatedClock;
+
+        let id = hold_flight_plans(vec![draft("clock-v1", "A", "B", 0)], 15).unwrap();
+        let is_held = |clock: &SimulatedClock| {
+            get_held_flight_plans_with(clock)
+                .iter()
+                .any(|flight_plan| flight_plan.data.as_ref().unwrap().vehicle_id == "clock-v1")
+        };
+        let clock = SimulatedClock::new(now());
+        assert!(is_held(&clock));
+        clock.advance(Duration::minutes(20));
+        assert!(!is_held(&clock));
+        release_hold(&id).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_hold_duration() {
+        let mut ledger = ReservationLedger::new();
+        assert!(ledger
+            .hold(vec![draft("v1", "A", "B", 0)], 0, start())
+            .is_err());
+    }
+}
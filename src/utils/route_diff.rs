@@ -0,0 +1,162 @@
+//! Comparison of two routes.
+//!
+//! When a route is recomputed, e.g. after the graph of the router changed,
+//! or a planned route is checked against the one flown, [`compare_routes`]
+//! reports which legs the routes share, where the second route leaves and
+//! rejoins the first one and how much longer it is. Routes are the
+//! locations of the vertiports along them, as returned by
+//! [`get_route`](crate::router_state::get_route).
+
+use std::collections::HashSet;
+
+use crate::haversine;
+use crate::location::Location;
+use crate::router_state::{estimate_flight_time_minutes, Aircraft};
+
+/// Differences between a base route and another route
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RouteDiff {
+    /// legs flown by both routes in the same direction, in the order of the base route
+    pub shared_legs: Vec<(Location, Location)>,
+    /// locations of both routes where the other route leaves the base route
+    pub divergence_points: Vec<Location>,
+    /// locations of both routes where the other route rejoins the base route
+    pub convergence_points: Vec<Location>,
+    /// length of the other route minus the length of the base route
+    pub distance_delta_km: f32,
+    /// flight time of the other route minus the flight time of the base
+    /// route, each leg with its takeoff and landing
+    pub time_delta_minutes: f32,
+}
+
+impl RouteDiff {
+    /// Checks if both routes fly the same legs
+    pub fn is_identical(&self) -> bool {
+        self.divergence_points.is_empty()
+            && self.convergence_points.is_empty()
+            && self.distance_delta_km == 0.0
+    }
+}
+
+/// Length of a route in kilometers
+fn route_distance_km(route: &[Location]) -> f32 {
+    route
+        .windows(2)
+        .map(|leg| haversine::distance(&leg[0], &leg[1]))
+        .sum()
+}
+
+/// Flight time of a route in minutes, each leg with its takeoff and landing
+fn route_time_minutes(route: &[Location]) -> f32 {
+    route
+        .windows(2)
+        .map(|leg| {
+            estimate_flight_time_minutes(haversine::distance(&leg[0], &leg[1]), Aircraft::Cargo)
+        })
+        .sum()
+}
+
+/// Compares `other` to the `base` route
+///
+/// # Arguments
+/// * `base` - the reference route, e.g. before a graph update or as planned
+/// * `other` - the compared route, e.g. after a graph update or as flown
+pub fn compare_routes(base: &[Location], other: &[Location]) -> RouteDiff {
+    let base_legs: HashSet<(Location, Location)> =
+        base.windows(2).map(|leg| (leg[0], leg[1])).collect();
+    let other_legs: HashSet<(Location, Location)> =
+        other.windows(2).map(|leg| (leg[0], leg[1])).collect();
+    let base_locations: HashSet<&Location> = base.iter().collect();
+
+    let mut diff = RouteDiff {
+        shared_legs: base
+            .windows(2)
+            .map(|leg| (leg[0], leg[1]))
+            .filter(|leg| other_legs.contains(leg))
+            .collect(),
+        distance_delta_km: route_distance_km(other) - route_distance_km(base),
+        time_delta_minutes: route_time_minutes(other) - route_time_minutes(base),
+        ..Default::default()
+    };
+    for (index, location) in other.iter().enumerate() {
+        if !base_locations.contains(location) {
+            continue;
+        }
+        let leaves = other
+            .get(index + 1)
+            .is_some_and(|next| !base_legs.contains(&(*location, *next)));
+        let rejoins = index
+            .checked_sub(1)
+            .is_some_and(|previous| !base_legs.contains(&(other[previous], *location)));
+        if leaves {
+            diff.divergence_points.push(*location);
+        }
+        if rejoins {
+            diff.convergence_points.push(*location);
+        }
+    }
+    debug!(
+        "Routes share {} legs and diverge at {} locations",
+        diff.shared_legs.len(),
+        diff.divergence_points.len()
+    );
+    diff
+}
+
+#[cfg(test)]
+mod route_diff_tests {
+    use super::*;
+    use crate::test_support::mock_location;
+
+    fn location(longitude: f32, latitude: f32) -> Location {
+        mock_location(latitude, longitude)
+    }
+
+    #[test]
+    fn test_identical_routes() {
+        let route = vec![location(0.0, 0.0), location(0.2, 0.0), location(0.4, 0.0)];
+        let diff = compare_routes(&route, &route);
+        assert!(diff.is_identical());
+        assert_eq!(diff.shared_legs.len(), 2);
+        assert_eq!(diff.time_delta_minutes, 0.0);
+    }
+
+    #[test]
+    fn test_detour() {
+        let a = location(0.0, 0.0);
+        let b = location(0.2, 0.0);
+        let c = location(0.4, 0.0);
+        let d = location(0.6, 0.0);
+        let detour = location(0.3, 0.1);
+        let base = vec![a, b, c, d];
+        let other = vec![a, b, detour, d];
+
+        let diff = compare_routes(&base, &other);
+        assert!(!diff.is_identical());
+        assert_eq!(diff.shared_legs, vec![(a, b)]);
+        assert_eq!(diff.divergence_points, vec![b]);
+        assert_eq!(diff.convergence_points, vec![d]);
+        assert!(diff.distance_delta_km > 0.0);
+        assert!(diff.time_delta_minutes > 0.0);
+
+        // flying the base route again
+        let diff = compare_routes(&other, &base);
+        assert_eq!(diff.divergence_points, vec![b]);
+        assert_eq!(diff.convergence_points, vec![d]);
+        assert!(diff.distance_delta_km < 0.0);
+    }
+
+    #[test]
+    fn test_extra_stop() {
+        let a = location(0.0, 0.0);
+        let b = location(0.2, 0.0);
+        let c = location(0.4, 0.0);
+        let diff = compare_routes(&[a, c], &[a, b, c]);
+        assert!(diff.shared_legs.is_empty());
+        assert_eq!(diff.divergence_points, vec![a]);
+        assert_eq!(diff.convergence_points, vec![c]);
+        // the extra landing and takeoff
+        assert!((diff.time_delta_minutes - 20.0).abs() < 0.01);
+        assert!(diff.distance_delta_km.abs() < 0.01);
+    }
+}
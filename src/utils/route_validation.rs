@@ -0,0 +1,285 @@
+//! Validation of routes produced outside the router.
+//!
+//! Operators may file a route of their own, e.g. drawn by hand around an
+//! event. [`validate_route`] checks it against the rules the router applies
+//! to the routes it builds, and returns every issue found so the operator
+//! can amend the route:
+//! * the range of the aircraft on each leg,
+//! * the severe [weather cells](crate::weather) valid during the flight,
+//! * the [route constraints](crate::constraints), e.g. geofences and
+//!   altitude caps,
+//! * the [terrain clearance](crate::terrain) if set,
+//! * the [separation minima](crate::separation) from the active flights if
+//!   set, the route departing at the requested time.
+
+use chrono::{DateTime, Duration};
+use rrule::Tz;
+use std::sync::Arc;
+
+use crate::amendment::timestamp_to_datetime;
+use crate::constraints::{get_route_constraints, RouteConstraint};
+use crate::haversine;
+use crate::location::Location;
+use crate::reservation::get_held_flight_plans;
+use crate::router_state::{
+    estimate_flight_time_minutes, Aircraft, FlightPlan, ARROW_CARGO_CONSTRAINT,
+};
+use crate::separation::{
+    first_loss_of_separation, get_active_trajectories, get_separation_minima, Trajectory,
+};
+use crate::terrain::{get_terrain_clearance, ClearanceViolation};
+use crate::weather::{get_weather_cells_during, WeatherSeverity};
+
+/// Issue found on a route
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouteIssue {
+    /// the route has less than two locations
+    NoLeg,
+    /// a leg is longer than the aircraft can fly
+    OutOfRange {
+        /// index of the leg in the route
+        leg: usize,
+        /// length of the leg
+        distance_km: f32,
+        /// range of the aircraft
+        range_km: f32,
+    },
+    /// a leg crosses severe weather during the flight
+    SevereWeather {
+        /// index of the leg in the route
+        leg: usize,
+        /// id of the weather cell
+        cell_id: String,
+    },
+    /// the route violates a registered constraint
+    Constraint {
+        /// name of the constraint
+        name: String,
+        /// description of the violation
+        description: String,
+    },
+    /// a leg is flown below the terrain clearance
+    TerrainClearance(ClearanceViolation),
+    /// the flight loses separation from an active flight
+    LossOfSeparation {
+        /// id of the active flight plan
+        flight_plan_id: String,
+        /// first time the flights are too close
+        time: DateTime<Tz>,
+    },
+}
+
+/// Result of the validation of a route
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteValidationReport {
+    /// length of the route
+    pub distance_km: f32,
+    /// requested departure time, including loading and takeoff
+    pub departure_time: DateTime<Tz>,
+    /// estimated arrival time, including landing and unloading
+    pub arrival_time: DateTime<Tz>,
+    /// issues found, empty if the route can be flown
+    pub issues: Vec<RouteIssue>,
+}
+
+impl RouteValidationReport {
+    /// Checks if no issue was found
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Longest leg the aircraft can fly
+fn range_km(aircraft: Aircraft) -> f32 {
+    match aircraft {
+        Aircraft::Cargo => ARROW_CARGO_CONSTRAINT.km(),
+    }
+}
+
+/// Validates a route against the existing flight plans
+///
+/// # Arguments
+/// * `route` - locations along the route, from departure to destination,
+///   flown in straight legs between them
+/// * `departure_time` - departure time of the flight
+/// * `aircraft` - aircraft flying the route
+/// * `existing_flight_plans` - flight plans the flight must keep separated
+///   from
+/// * `constraints` - constraints the route must comply with
+///
+/// # Returns
+/// The report of the issues of the route
+pub fn validate_route_with(
+    route: &[Location],
+    departure_time: DateTime<Tz>,
+    aircraft: Aircraft,
+    existing_flight_plans: &[FlightPlan],
+    constraints: &[Arc<dyn RouteConstraint>],
+) -> RouteValidationReport {
+    let distance_km: f32 = route
+        .windows(2)
+        .map(|leg| haversine::distance(&leg[0], &leg[1]))
+        .sum();
+    let arrival_time = departure_time
+        + Duration::seconds(
+            (estimate_flight_time_minutes(distance_km, aircraft) * 60.0).round() as i64,
+        );
+    let mut report = RouteValidationReport {
+        distance_km,
+        departure_time,
+        arrival_time,
+        issues: vec![],
+    };
+    if route.len() < 2 {
+        report.issues.push(RouteIssue::NoLeg);
+        return report;
+    }
+
+    let range_km = range_km(aircraft);
+    let weather_cells = get_weather_cells_during(departure_time, arrival_time);
+    for (leg, ends) in route.windows(2).enumerate() {
+        let distance_km = haversine::distance(&ends[0], &ends[1]);
+        if distance_km > range_km {
+            report.issues.push(RouteIssue::OutOfRange {
+                leg,
+                distance_km,
+                range_km,
+            });
+        }
+        report.issues.extend(
+            weather_cells
+                .iter()
+                .filter(|cell| {
+                    cell.severity == WeatherSeverity::Severe
+                        && cell.intersects_leg(&ends[0], &ends[1])
+                })
+                .map(|cell| RouteIssue::SevereWeather {
+                    leg,
+                    cell_id: cell.id.clone(),
+                }),
+        );
+    }
+
+    for constraint in constraints {
+        if let Err(description) = constraint.check_route(route) {
+            report.issues.push(RouteIssue::Constraint {
+                name: constraint.name().to_string(),
+                description,
+            });
+        }
+    }
+
+    if let Some(clearance) = get_terrain_clearance() {
+        report.issues.extend(
+            clearance
+                .check_route(route)
+                .into_iter()
+                .map(RouteIssue::TerrainClearance),
+        );
+    }
+
+    if let Some(minima) = get_separation_minima() {
+        let trajectory = Trajectory {
+            flight_plan_id: String::new(),
+            route: route.to_vec(),
+            departure: departure_time.timestamp(),
+            arrival: arrival_time.timestamp(),
+        };
+        let active = get_active_trajectories(existing_flight_plans, departure_time, arrival_time);
+        report.issues.extend(active.iter().filter_map(|other| {
+            let time = first_loss_of_separation(&minima, &trajectory, other)?;
+            Some(RouteIssue::LossOfSeparation {
+                flight_plan_id: other.flight_plan_id.clone(),
+                time: timestamp_to_datetime(time),
+            })
+        }));
+    }
+
+    debug!(
+        "Validated route of {} km with {} issues",
+        distance_km,
+        report.issues.len()
+    );
+    report
+}
+
+/// Validates a route against the flight plans held in the reservation
+/// ledger and the registered route constraints, see [`validate_route_with`]
+pub fn validate_route(
+    route: &[Location],
+    departure_time: DateTime<Tz>,
+    aircraft: Aircraft,
+) -> RouteValidationReport {
+    validate_route_with(
+        route,
+        departure_time,
+        aircraft,
+        &get_held_flight_plans(),
+        &get_route_constraints(),
+    )
+}
+
+#[cfg(test)]
+mod route_validation_tests {
+    use super::*;
+    use crate::test_support::mock_location;
+    use chrono::TimeZone;
+
+    /// Bans the legs ending north of the 60th parallel
+    struct NorthernBan;
+
+    impl RouteConstraint for NorthernBan {
+        fn name(&self) -> &str {
+            "route-validation-northern-ban"
+        }
+
+        fn check_leg(&self, _from: &Location, to: &Location) -> Result<(), String> {
+            if to.latitude.into_inner() > 60.0 {
+                return Err("north of the 60th parallel".to_string());
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_validate_route() {
+        let departure = Tz::UTC.with_ymd_and_hms(2030, 1, 1, 8, 0, 0).unwrap();
+        let route = [mock_location(61.0, 10.0), mock_location(61.0, 10.5)];
+        let constraints: Vec<Arc<dyn RouteConstraint>> = vec![Arc::new(NorthernBan)];
+        let report = validate_route_with(&route, departure, Aircraft::Cargo, &[], &[]);
+        assert!(report.is_valid(), "{:?}", report.issues);
+        assert!((report.distance_km - haversine::distance(&route[0], &route[1])).abs() < 1e-3);
+        // 10 minutes of takeoff and landing each at 60 km/h
+        let seconds = (report.arrival_time - departure).num_seconds() as f32;
+        assert!((seconds - (20.0 + report.distance_km) * 60.0).abs() <= 1.0);
+
+        let route = [
+            mock_location(59.0, 10.0),
+            mock_location(61.0, 10.0),
+            mock_location(59.0, 10.0),
+        ];
+        let report = validate_route_with(&route, departure, Aircraft::Cargo, &[], &constraints);
+        assert!(!report.is_valid());
+        assert!(matches!(
+            report.issues[0],
+            RouteIssue::OutOfRange { leg: 0, .. }
+        ));
+        assert!(matches!(
+            report.issues[1],
+            RouteIssue::OutOfRange { leg: 1, .. }
+        ));
+        assert_eq!(
+            report.issues[2],
+            RouteIssue::Constraint {
+                name: "route-validation-northern-ban".to_string(),
+                description: "north of the 60th parallel".to_string(),
+            }
+        );
+        assert_eq!(report.issues.len(), 3);
+
+        assert_eq!(
+            validate_route(&route[..1], departure, Aircraft::Cargo).issues,
+            vec![RouteIssue::NoLeg]
+        );
+    }
+}
@@ -1,26 +1,67 @@
 //! Stores the state of the router
 
+use crate::alternate::{find_alternate_vertiport, MAX_PAYLOAD_GRAMS};
+use crate::amendment::{timestamp_to_datetime, PlanTimes};
+use crate::approach::usable_vertipads;
+use crate::audit::{
+    is_audit_enabled, record, AuditRecord, CandidateSlot, RecordedQuery, RejectionReason,
+};
+use crate::capacity::is_within_vertiport_capacity;
+use crate::clock::{get_clock, Clock};
+use crate::congestion::{congestion_penalty, get_congestion_multipliers};
+use crate::constraints::{
+    check_leg_constraints, check_route_constraints, get_route_constraints, RouteConstraint,
+};
+use crate::context::{current_context, RouterContext};
+use crate::cost::{estimate_leg_cost, get_cost_model, get_route_distance_km, CostEstimate};
+use crate::crew::{assign_crew, get_crew_provider, CrewProvider};
+use crate::curfew::overlaps_curfew;
+use crate::distance::Distance;
+use crate::energy::has_sufficient_charge;
+use crate::eta::{get_eta_model, predict_eta, AircraftProfile, EtaDistribution};
+use crate::fleet_state::get_vehicle_location_with;
 use crate::generator::generate_nodes_near;
+use crate::haversine;
 use crate::location::Location;
-use crate::node::Node;
-use crate::router::engine::{Algorithm, Router};
+use crate::maintenance::is_within_maintenance_interval;
+use crate::metrics::{
+    increment_counter, record_histogram, AVAILABILITY_CHECK_SECONDS, PLANS_RETURNED,
+    ROUTES_COMPUTED, SLOTS_EVALUATED,
+};
+use crate::node::{AsNode, Node};
+use crate::noise::{get_noise_zones, noise_penalty};
+use crate::notifications::{notify_graph_changed, GraphChange};
+use crate::operator::OperatorFilter;
+use crate::queueing::{PadTimeline, MAX_PAD_QUEUE_MINUTES};
+use crate::reservation::get_held_flight_plans_with;
+use crate::router::engine::{Algorithm, Disjointness, PathLimits, Router};
 use crate::schedule::Calendar;
-use crate::{haversine, status};
-use chrono::{DateTime, Duration, NaiveDateTime, TimeZone};
+use crate::separation::{
+    find_separated_delay_minutes, get_active_trajectories, get_separation_minima, Trajectory,
+};
+use crate::time::{datetime_to_timestamp, validate_flight_window};
+use crate::turnaround::{is_vehicle_turned_around, LegKind};
+use crate::utm::{
+    deconflict_flight, flight_plan_intent, get_utm_service, OperationIntent, UtmService,
+};
+use crate::weather::{get_weather_cells_during, weather_penalty};
+use chrono::{DateTime, Duration};
 use once_cell::sync::OnceCell;
 use ordered_float::OrderedFloat;
+use petgraph::graph::NodeIndex;
 use prost_types::Timestamp;
 use rrule::Tz;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+use uuid::Uuid;
 
 // Expose so svc-scheduler doesn't assume same svc-storage version
-pub use svc_storage_client_grpc::resources::flight_plan::{
-    Data as FlightPlanData, Object as FlightPlan,
-};
-pub use svc_storage_client_grpc::resources::vehicle::Object as Vehicle;
-pub use svc_storage_client_grpc::resources::vertipad::Object as Vertipad;
-pub use svc_storage_client_grpc::resources::vertiport::Object as Vertiport;
+pub use crate::resources::flight_plan::{Data as FlightPlanData, Object as FlightPlan};
+pub use crate::resources::vehicle::Object as Vehicle;
+pub use crate::resources::vertipad::Object as Vertipad;
+pub use crate::resources::vertiport::Object as Vertiport;
 
 /// Query struct for generating nodes near a location.
 #[derive(Debug, Copy, Clone)]
@@ -28,20 +69,78 @@ pub struct NearbyLocationQuery {
     ///location
     pub location: Location,
     ///radius
-    pub radius: f32,
+    pub radius: Distance,
     ///capacity
     pub capacity: i32,
 }
 
 /// Query struct to find a route between two nodes
+///
+/// The nodes are borrowed from the router the query is made on, e.g. with
+/// [`get_node_by_id`] for the cargo router or
+/// [`Router::get_node_by_uid`] for a router owned by the caller.
 #[derive(Debug, Copy, Clone)]
-pub struct RouteQuery {
+pub struct RouteQuery<'a> {
     ///aircraft
     pub aircraft: Aircraft,
     ///from
-    pub from: &'static Node,
+    pub from: &'a Node,
     ///to
-    pub to: &'static Node,
+    pub to: &'a Node,
+    /// departure time, costs the legs congested at that time of day, see
+    /// [`set_congestion_multipliers`](crate::congestion::set_congestion_multipliers),
+    /// and the legs overflying noise zones, see
+    /// [`load_noise_zones`](crate::noise::load_noise_zones)
+    pub departure_time: Option<DateTime<Tz>>,
+    /// max intermediate landings, 0 for a direct flight, None if unlimited
+    pub max_hops: Option<u32>,
+    /// max flight time of the route, including the takeoff and landing of
+    /// each leg, None if unlimited
+    pub max_duration: Option<Duration>,
+    /// name of the A* heuristic registered on the router, see
+    /// [`Router::register_heuristic`], None to search without heuristic,
+    /// unused by the search within `max_hops` or `max_duration`
+    pub heuristic: Option<&'a str>,
+}
+
+impl<'a> RouteQuery<'a> {
+    /// Creates a query of the route between two nodes, without departure
+    /// time, limits or heuristic
+    pub fn new(aircraft: Aircraft, from: &'a Node, to: &'a Node) -> Self {
+        RouteQuery {
+            aircraft,
+            from,
+            to,
+            departure_time: None,
+            max_hops: None,
+            max_duration: None,
+            heuristic: None,
+        }
+    }
+
+    /// Sets the departure time
+    pub fn with_departure_time(mut self, departure_time: DateTime<Tz>) -> Self {
+        self.departure_time = Some(departure_time);
+        self
+    }
+
+    /// Sets the max intermediate landings
+    pub fn with_max_hops(mut self, max_hops: u32) -> Self {
+        self.max_hops = Some(max_hops);
+        self
+    }
+
+    /// Sets the max flight time of the route
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Sets the name of the A* heuristic registered on the router
+    pub fn with_heuristic(mut self, heuristic: &'a str) -> Self {
+        self.heuristic = Some(heuristic);
+        self
+    }
 }
 
 /// Enum with all Aircraft types
@@ -55,7 +154,8 @@ pub static NODES: OnceCell<Vec<Node>> = OnceCell::new();
 /// Cargo router
 pub static ARROW_CARGO_ROUTER: OnceCell<Router> = OnceCell::new();
 
-static ARROW_CARGO_CONSTRAINT: f32 = 75.0;
+/// Range of the cargo aircraft, legs between vertiports farther apart are not flown
+pub(crate) static ARROW_CARGO_CONSTRAINT: Distance = Distance::from_km(75.0);
 /// SF central location
 pub static SAN_FRANCISCO: Location = Location {
     latitude: OrderedFloat(37.7749),
@@ -81,7 +181,7 @@ fn time_ranges_overlap(start1: i64, end1: i64, start2: i64, end2: i64) -> bool {
 }
 
 /// Helper function to create a flight plan data object from 5 required parameters
-fn create_flight_plan_data(
+pub(crate) fn create_flight_plan_data(
     vehicle_id: String,
     departure_vertiport_id: String,
     arrival_vertiport_id: String,
@@ -95,14 +195,8 @@ fn create_flight_plan_data(
         weather_conditions: None,
         departure_vertiport_id: Some(departure_vertiport_id),
         destination_vertiport_id: Some(arrival_vertiport_id),
-        scheduled_departure: Some(Timestamp {
-            seconds: departure_time.timestamp(),
-            nanos: departure_time.timestamp_subsec_nanos() as i32,
-        }),
-        scheduled_arrival: Some(Timestamp {
-            seconds: arrival_time.timestamp(),
-            nanos: arrival_time.timestamp_subsec_nanos() as i32,
-        }),
+        scheduled_departure: Some(datetime_to_timestamp(&departure_time)),
+        scheduled_arrival: Some(datetime_to_timestamp(&arrival_time)),
         actual_departure: None,
         actual_arrival: None,
         flight_release_approval: None,
@@ -116,16 +210,62 @@ fn create_flight_plan_data(
     }
 }
 
+/// Scheduled times and vertiports of the flight plans
+/// Flight plans missing their data, scheduled times or vertiports are skipped and reported
+pub(crate) fn valid_plan_times(
+    existing_flight_plans: &[FlightPlan],
+) -> impl Iterator<Item = PlanTimes> + '_ {
+    existing_flight_plans.iter().filter_map(|flight_plan| {
+        let has_vertiports = flight_plan.data.as_ref().is_some_and(|data| {
+            data.departure_vertiport_id.is_some() && data.destination_vertiport_id.is_some()
+        });
+        let plan = PlanTimes::from_flight_plan(flight_plan).and_then(|plan| {
+            if has_vertiports {
+                Ok(plan)
+            } else {
+                Err(format!("Flight plan {} has no vertiports", flight_plan.id))
+            }
+        });
+        match plan {
+            Ok(plan) => Some(plan),
+            Err(e) => {
+                warn!("Skipping malformed flight plan: {}", e);
+                None
+            }
+        }
+    })
+}
+
 /// Checks if a vehicle is available for a given time window date_from to
 ///    date_from + flight_duration_minutes (this includes takeoff and landing time)
 /// This checks both static schedule of the aircraft and existing flight plans which might overlap.
+/// Vehicles the flight would take past their maintenance interval are not available
+/// Vehicles not projected to land from the flight with their reserve charge are not available
+/// Returns an error if the vehicle has no data or an invalid schedule
+/// Schedules in local time name their timezone in a `X-WR-TIMEZONE` line, see [`Calendar`]
 pub fn is_vehicle_available(
     vehicle: &Vehicle,
     date_from: DateTime<Tz>,
     flight_duration_minutes: i64,
     existing_flight_plans: &[FlightPlan],
 ) -> Result<bool, String> {
-    let vehicle_data = vehicle.data.as_ref().unwrap();
+    let vehicle_data = vehicle
+        .data
+        .as_ref()
+        .ok_or_else(|| format!("Vehicle {} has no data", vehicle.id))?;
+
+    if !is_within_maintenance_interval(vehicle, flight_duration_minutes, existing_flight_plans) {
+        return Ok(false);
+    }
+
+    if !has_sufficient_charge(
+        vehicle,
+        date_from,
+        flight_duration_minutes,
+        existing_flight_plans,
+    ) {
+        return Ok(false);
+    }
 
     // TODO R3: What's the default if a schedule isn't provided?
     let Some(vehicle_schedule) = vehicle_data.schedule.as_ref() else {
@@ -136,13 +276,10 @@ pub fn is_vehicle_available(
     let Ok(vehicle_schedule) = Calendar::from_str(vehicle_schedule) else {
         debug!(
             "Invalid schedule for vehicle {}: {}",
-            vehicle.id,
-            vehicle_schedule
+            vehicle.id, vehicle_schedule
         );
 
-        return Err(
-            "Invalid schedule for vehicle.".to_string(),
-        );
+        return Err("Invalid schedule for vehicle.".to_string());
     };
 
     let date_to = date_from + Duration::minutes(flight_duration_minutes);
@@ -152,43 +289,28 @@ pub fn is_vehicle_available(
     }
 
     //check if vehicle is available as per existing flight plans
-    let conflicting_flight_plans_count = existing_flight_plans
-        .iter()
-        .filter(|flight_plan| {
-            flight_plan.data.as_ref().unwrap().vehicle_id == vehicle.id
-                && time_ranges_overlap(
-                    flight_plan
-                        .data
-                        .as_ref()
-                        .unwrap()
-                        .scheduled_departure
-                        .as_ref()
-                        .unwrap()
-                        .seconds,
-                    flight_plan
-                        .data
-                        .as_ref()
-                        .unwrap()
-                        .scheduled_arrival
-                        .as_ref()
-                        .unwrap()
-                        .seconds,
-                    date_from.timestamp(),
-                    date_to.timestamp(),
-                )
-        })
-        .count();
-    if conflicting_flight_plans_count > 0 {
-        return Ok(false);
-    }
-
-    Ok(true)
+    let has_conflicting_flight_plan = valid_plan_times(existing_flight_plans).any(|plan| {
+        plan.vehicle_id == vehicle.id
+            && time_ranges_overlap(
+                plan.departure,
+                plan.arrival,
+                date_from.timestamp(),
+                date_to.timestamp(),
+            )
+    });
+    Ok(!has_conflicting_flight_plan)
 }
 
 /// Checks if vertiport is available for a given time window from date_from to date_from + duration
 /// of how long vertiport is blocked by takeoff/landing
 /// This checks both static schedule of vertiport and existing flight plans which might overlap.
 /// is_departure_vertiport is used to determine if we are checking for departure or arrival vertiport
+/// Movements are also limited by the hourly capacity and the curfew configured for the vertiport
+/// Vertiports without a schedule are always open; an invalid schedule is an error
+/// Schedules in the local time of the vertiport name its timezone in a `X-WR-TIMEZONE` line,
+/// see [`Calendar`]
+/// Only the vertipads usable in the surface wind of the vertiport are counted, a vertiport
+/// whose vertipads are all unusable is unavailable
 pub fn is_vertiport_available(
     vertiport_id: String,
     vertiport_schedule: Option<String>,
@@ -196,13 +318,25 @@ pub fn is_vertiport_available(
     date_from: DateTime<Tz>,
     existing_flight_plans: &[FlightPlan],
     is_departure_vertiport: bool,
-) -> (bool, Vec<(String, i64)>) {
-    let mut num_vertipads = vertipads.len();
+) -> Result<(bool, Vec<(String, i64)>), String> {
+    let mut num_vertipads = usable_vertipads(&vertiport_id, vertipads).len();
     if num_vertipads == 0 {
+        if !vertipads.is_empty() {
+            return Ok((false, vec![]));
+        }
         num_vertipads = 1
     };
-    let vertiport_schedule =
-        Calendar::from_str(vertiport_schedule.as_ref().unwrap().as_str()).unwrap();
+    let vertiport_schedule = vertiport_schedule
+        .map(|schedule| {
+            Calendar::from_str(&schedule).map_err(|_| {
+                debug!(
+                    "Invalid schedule for vertiport {}: {}",
+                    vertiport_id, schedule
+                );
+                format!("Invalid schedule for vertiport {}", vertiport_id)
+            })
+        })
+        .transpose()?;
     let block_vertiport_minutes: i64 = if is_departure_vertiport {
         LOADING_AND_TAKEOFF_TIME_MIN as i64
     } else {
@@ -210,67 +344,29 @@ pub fn is_vertiport_available(
     };
     let date_to = date_from + Duration::minutes(block_vertiport_minutes);
     //check if vertiport is available as per schedule
-    if !vertiport_schedule.is_available_between(date_from, date_to) {
-        return (false, vec![]);
+    if let Some(vertiport_schedule) = vertiport_schedule {
+        if !vertiport_schedule.is_available_between(date_from, date_to) {
+            return Ok((false, vec![]));
+        }
     }
-    let conflicting_flight_plans_count = existing_flight_plans
-        .iter()
-        .filter(|flight_plan| {
-            if is_departure_vertiport {
-                flight_plan
-                    .data
-                    .as_ref()
-                    .unwrap()
-                    .departure_vertiport_id
-                    .clone()
-                    .unwrap()
-                    == vertiport_id
-                    && flight_plan
-                        .data
-                        .as_ref()
-                        .unwrap()
-                        .scheduled_departure
-                        .as_ref()
-                        .unwrap()
-                        .seconds
-                        > date_from.timestamp() - block_vertiport_minutes * 60
-                    && flight_plan
-                        .data
-                        .as_ref()
-                        .unwrap()
-                        .scheduled_departure
-                        .as_ref()
-                        .unwrap()
-                        .seconds
-                        < date_to.timestamp() + block_vertiport_minutes * 60
+    //check if vertiport has capacity left for another movement
+    if !is_within_vertiport_capacity(&vertiport_id, date_from, existing_flight_plans) {
+        return Ok((false, vec![]));
+    }
+    //check if the takeoff or landing falls into the vertiport's curfew
+    if overlaps_curfew(&vertiport_id, date_from, date_to) {
+        return Ok((false, vec![]));
+    }
+    let blocked_from = date_from.timestamp() - block_vertiport_minutes * 60;
+    let blocked_to = date_to.timestamp() + block_vertiport_minutes * 60;
+    let conflicting_flight_plans_count = valid_plan_times(existing_flight_plans)
+        .filter(|plan| {
+            let (plan_vertiport_id, movement) = if is_departure_vertiport {
+                (&plan.departure_vertiport_id, plan.departure)
             } else {
-                flight_plan
-                    .data
-                    .as_ref()
-                    .unwrap()
-                    .destination_vertiport_id
-                    .clone()
-                    .unwrap()
-                    == vertiport_id
-                    && flight_plan
-                        .data
-                        .as_ref()
-                        .unwrap()
-                        .scheduled_arrival
-                        .as_ref()
-                        .unwrap()
-                        .seconds
-                        > date_from.timestamp() - block_vertiport_minutes * 60
-                    && flight_plan
-                        .data
-                        .as_ref()
-                        .unwrap()
-                        .scheduled_arrival
-                        .as_ref()
-                        .unwrap()
-                        .seconds
-                        < date_to.timestamp() + block_vertiport_minutes * 60
-            }
+                (&plan.destination_vertiport_id, plan.arrival)
+            };
+            *plan_vertiport_id == vertiport_id && blocked_from < movement && movement < blocked_to
         })
         .count();
     let res = if num_vertipads > 1 {
@@ -287,7 +383,7 @@ pub fn is_vertiport_available(
         "Checking {} is departure: {}, is available for {} - {}? {}",
         vertiport_id, is_departure_vertiport, date_from, date_to, res.0,
     );
-    res
+    Ok(res)
 }
 
 ///Finds all vehicles which are parked at or in flight to the vertiport at specific timestamp
@@ -298,76 +394,25 @@ pub fn get_all_vehicles_scheduled_for_vertiport(
     timestamp: DateTime<Tz>,
     existing_flight_plans: &[FlightPlan],
 ) -> Vec<(String, i64)> {
-    let mut vehicles_plans_sorted: HashMap<String, Vec<FlightPlan>> = HashMap::new();
-    existing_flight_plans
-        .iter()
-        .filter(|flight_plan| {
-            flight_plan
-                .data
-                .as_ref()
-                .unwrap()
-                .destination_vertiport_id
-                .as_ref()
-                .unwrap()
-                == vertiport_id
-                && flight_plan
-                    .data
-                    .as_ref()
-                    .unwrap()
-                    .scheduled_arrival
-                    .as_ref()
-                    .unwrap()
-                    .seconds // arrival time needs to be less than 2x time needed - to allow landing and and then take off again)
-                    < timestamp.timestamp() + LANDING_AND_UNLOADING_TIME_MIN as i64 * 60
+    // latest scheduled arrival of each vehicle
+    let mut latest_arrivals: HashMap<String, i64> = HashMap::new();
+    // arrival time needs to be less than 2x time needed - to allow landing and and then take off again)
+    valid_plan_times(existing_flight_plans)
+        .filter(|plan| {
+            plan.destination_vertiport_id == vertiport_id
+                && plan.arrival < timestamp.timestamp() + LANDING_AND_UNLOADING_TIME_MIN as i64 * 60
         })
-        .for_each(|flight_plan| {
-            let vehicle_id = flight_plan.data.as_ref().unwrap().vehicle_id.clone();
-            let entry = vehicles_plans_sorted.entry(vehicle_id).or_default();
-            entry.push(flight_plan.clone());
-        });
-    //sort by scheduled arrival, latest first
-    vehicles_plans_sorted
-        .iter_mut()
-        .for_each(|(_, flight_plans)| {
-            flight_plans.sort_by(|a, b| {
-                b.data
-                    .as_ref()
-                    .unwrap()
-                    .scheduled_arrival
-                    .as_ref()
-                    .unwrap()
-                    .seconds
-                    .cmp(
-                        &a.data
-                            .as_ref()
-                            .unwrap()
-                            .scheduled_arrival
-                            .as_ref()
-                            .unwrap()
-                            .seconds,
-                    )
-            });
+        .for_each(|plan| {
+            let latest_arrival = latest_arrivals
+                .entry(plan.vehicle_id)
+                .or_insert(plan.arrival);
+            *latest_arrival = (*latest_arrival).max(plan.arrival);
         });
-    //return only the latest flight plan for each vehicle
-    let vehicles = vehicles_plans_sorted
-        .iter()
-        .map(|(vehicle_id, flight_plans)| {
-            let mut minutes_to_arrival = (flight_plans
-                .first()
-                .unwrap()
-                .data
-                .as_ref()
-                .unwrap()
-                .scheduled_arrival
-                .as_ref()
-                .unwrap()
-                .seconds
-                - timestamp.timestamp())
-                / 60;
-            if minutes_to_arrival < 0 {
-                minutes_to_arrival = 0;
-            }
-            (vehicle_id.clone(), minutes_to_arrival)
+    let vehicles = latest_arrivals
+        .into_iter()
+        .map(|(vehicle_id, arrival)| {
+            let minutes_to_arrival = ((arrival - timestamp.timestamp()) / 60).max(0);
+            (vehicle_id, minutes_to_arrival)
         })
         .collect();
     debug!(
@@ -381,94 +426,52 @@ pub fn get_all_vehicles_scheduled_for_vertiport(
 /// Returns tuple of (vertiport_id, minutes_to_arrival)
 /// If minutes_to_arrival is 0, vehicle is parked at the vertiport,
 /// otherwise it is in flight to the vertiport and should arrive in minutes_to_arrival
+/// Returns an error if the vehicle has no flight plan before the timestamp
+/// and no last vertiport
 pub fn get_vehicle_scheduled_location(
     vehicle: &Vehicle,
     timestamp: DateTime<Tz>,
     existing_flight_plans: &[FlightPlan],
-) -> (String, i64) {
-    let mut vehicle_flight_plans = existing_flight_plans
-        .iter()
-        .filter(|flight_plan| {
-            flight_plan.data.as_ref().unwrap().vehicle_id == vehicle.id
-                && flight_plan
-                    .data
-                    .as_ref()
-                    .unwrap()
-                    .scheduled_departure
-                    .as_ref()
-                    .unwrap()
-                    .seconds
-                    <= timestamp.timestamp()
-        })
-        .collect::<Vec<&FlightPlan>>();
-    vehicle_flight_plans.sort_by(|a, b| {
-        b.data
-            .as_ref()
-            .unwrap()
-            .scheduled_departure
+) -> Result<(String, i64), String> {
+    // the latest departure, the first one listed if several depart at the same time
+    let last_flight_plan = valid_plan_times(existing_flight_plans)
+        .filter(|plan| plan.vehicle_id == vehicle.id && plan.departure <= timestamp.timestamp())
+        .min_by_key(|plan| std::cmp::Reverse(plan.departure));
+    let Some(vehicle_flight_plan) = last_flight_plan else {
+        return vehicle
+            .data
             .as_ref()
-            .unwrap()
-            .seconds
-            .cmp(
-                &a.data
-                    .as_ref()
-                    .unwrap()
-                    .scheduled_departure
-                    .as_ref()
-                    .unwrap()
-                    .seconds,
-            )
-    });
-    if vehicle_flight_plans.is_empty() {
-        return (
-            vehicle
-                .data
-                .as_ref()
-                .unwrap()
-                .last_vertiport_id
-                .as_ref()
-                .unwrap()
-                .clone(),
-            0,
-        );
-    }
-    let vehicle_flight_plan = vehicle_flight_plans.first().unwrap();
+            .and_then(|data| data.last_vertiport_id.clone())
+            .map(|vertiport_id| (vertiport_id, 0))
+            .ok_or_else(|| {
+                format!(
+                    "Vehicle {} has no flight plan nor last vertiport",
+                    vehicle.id
+                )
+            });
+    };
     debug!(
         "Vehicle {} had last flight plan {} with destination {}",
-        vehicle.id,
-        vehicle_flight_plan.id.clone(),
-        vehicle_flight_plan
-            .data
-            .as_ref()
-            .unwrap()
-            .destination_vertiport_id
-            .as_ref()
-            .unwrap()
+        vehicle.id, vehicle_flight_plan.id, vehicle_flight_plan.destination_vertiport_id
     );
-    let mut minutes_to_arrival = (vehicle_flight_plan
-        .data
-        .as_ref()
-        .unwrap()
-        .scheduled_arrival
-        .as_ref()
-        .unwrap()
-        .seconds
-        - timestamp.timestamp())
-        / 60;
-    if minutes_to_arrival < 0 {
-        minutes_to_arrival = 0;
-    }
-    (
-        vehicle_flight_plan
-            .data
-            .as_ref()
-            .unwrap()
-            .destination_vertiport_id
-            .as_ref()
-            .unwrap()
-            .to_string(),
+    let minutes_to_arrival = ((vehicle_flight_plan.arrival - timestamp.timestamp()) / 60).max(0);
+    Ok((
+        vehicle_flight_plan.destination_vertiport_id,
         minutes_to_arrival,
-    )
+    ))
+}
+
+/// Gets the time a vehicle is free after its last scheduled flight, or `after`
+/// if the vehicle lands before it
+pub(crate) fn get_vehicle_free_time(
+    vehicle: &Vehicle,
+    after: DateTime<Tz>,
+    existing_flight_plans: &[FlightPlan],
+) -> DateTime<Tz> {
+    valid_plan_times(existing_flight_plans)
+        .filter(|plan| plan.vehicle_id == vehicle.id)
+        .map(|plan| timestamp_to_datetime(plan.arrival))
+        .fold(after, DateTime::max)
 }
 
 /// Gets flight durations from all vertiports in current router to the requested vertiport
@@ -476,19 +479,18 @@ pub fn get_vehicle_scheduled_location(
 /// so this function only filters the edges and calculates flight duration based on the distance
 pub fn get_all_flight_durations_to_vertiport(vertiport_id: &str) -> HashMap<&Node, i64> {
     let mut durations = HashMap::new();
-    ARROW_CARGO_ROUTER
-        .get()
-        .unwrap()
-        .edges
-        .iter()
-        .for_each(|edge| {
-            if edge.to.uid == vertiport_id {
-                durations.insert(
-                    edge.from,
-                    estimate_flight_time_minutes(f32::from(edge.cost), Aircraft::Cargo) as i64,
-                );
-            }
-        });
+    let Some(router) = ARROW_CARGO_ROUTER.get() else {
+        error!("Router not initialized");
+        return durations;
+    };
+    router.edges().for_each(|edge| {
+        if edge.to_id == vertiport_id {
+            durations.insert(
+                edge.from,
+                estimate_flight_time_minutes(edge.cost, Aircraft::Cargo) as i64,
+            );
+        }
+    });
     durations
 }
 
@@ -504,7 +506,7 @@ fn find_nearest_gap_for_reroute_flight(
     let mut time_from: Option<DateTime<Tz>> = None;
     for i in 0..6 {
         let added_time = date_from + Duration::minutes(i * LOADING_AND_TAKEOFF_TIME_MIN as i64);
-        let (dep, vehicles_dep) = is_vertiport_available(
+        let departure = is_vertiport_available(
             vertiport_id.clone(),
             vertiport_schedule.clone(),
             vertipads,
@@ -512,7 +514,7 @@ fn find_nearest_gap_for_reroute_flight(
             existing_flight_plans,
             true,
         );
-        let (arr, vehicles_arr) = is_vertiport_available(
+        let arrival = is_vertiport_available(
             vertiport_id.clone(),
             vertiport_schedule.clone(),
             vertipads,
@@ -520,6 +522,13 @@ fn find_nearest_gap_for_reroute_flight(
             existing_flight_plans,
             false,
         );
+        let ((dep, vehicles_dep), (arr, vehicles_arr)) = match (departure, arrival) {
+            (Ok(departure), Ok(arrival)) => (departure, arrival),
+            (Err(e), _) | (_, Err(e)) => {
+                debug!("Unable to find a gap for a reroute flight: {}", e);
+                return None;
+            }
+        };
         if (dep || vehicles_dep.contains(&(vehicle_id.clone(), 0)))
             && (arr || vehicles_arr.contains(&(vehicle_id.clone(), 0)))
         {
@@ -537,25 +546,37 @@ fn find_nearest_gap_for_reroute_flight(
 pub fn find_deadhead_flight_plan(
     nearest_vertiports_from_departure: &Vec<&Node>,
     departure_vertiport_durations: &HashMap<&Node, i64>,
-    vehicles: &Vec<Vehicle>,
+    vehicles: &[Vehicle],
     vertiport_depart: &Vertiport,
     vertipads_depart: &[Vertipad],
     departure_time: DateTime<Tz>,
     existing_flight_plans: &[FlightPlan],
     block_aircraft_and_vertiports_minutes: i64,
+    clock: &dyn Clock,
 ) -> (Option<Vehicle>, Option<FlightPlanData>) {
     for &vertiport in nearest_vertiports_from_departure {
-        let n_duration = *departure_vertiport_durations.get(vertiport).unwrap();
+        let Some(&n_duration) = departure_vertiport_durations.get(vertiport) else {
+            debug!("DH: No flight duration from vertiport id:{}", vertiport.uid);
+            continue;
+        };
         for vehicle in vehicles {
             debug!(
                 "DH: Checking vehicle id:{} for departure time: {}",
                 &vehicle.id, departure_time
             );
-            let (vehicle_dest_vertiport, _minutes_to_arrival) = get_vehicle_scheduled_location(
+            let location = get_vehicle_location_with(
+                clock,
                 vehicle,
                 departure_time - Duration::minutes(n_duration),
                 existing_flight_plans,
             );
+            let (vehicle_dest_vertiport, _minutes_to_arrival) = match location {
+                Ok(location) => location,
+                Err(e) => {
+                    debug!("DH: Unable to locate vehicle: {}", e);
+                    continue;
+                }
+            };
             if vehicle_dest_vertiport != *vertiport.uid {
                 debug!(
                     "DH: Vehicle id:{} not at or arriving to vertiport id:{}",
@@ -563,6 +584,14 @@ pub fn find_deadhead_flight_plan(
                 );
                 continue;
             }
+            if !is_vehicle_turned_around(
+                vehicle,
+                departure_time - Duration::minutes(n_duration),
+                LegKind::Deadhead,
+                existing_flight_plans,
+            ) {
+                continue;
+            }
 
             let result = is_vehicle_available(
                 vehicle,
@@ -571,12 +600,15 @@ pub fn find_deadhead_flight_plan(
                 existing_flight_plans,
             );
 
-            let Ok(is_vehicle_available) = result else {
-                debug!(
-                    "Unable to determine vehicle availability: (id {}) {}",
-                    &vehicle.id, result.err().unwrap()
-                );
-                continue;
+            let is_vehicle_available = match result {
+                Ok(is_vehicle_available) => is_vehicle_available,
+                Err(e) => {
+                    debug!(
+                        "Unable to determine vehicle availability: (id {}) {}",
+                        &vehicle.id, e
+                    );
+                    continue;
+                }
             };
 
             if !is_vehicle_available {
@@ -586,7 +618,7 @@ pub fn find_deadhead_flight_plan(
                         );
                 continue;
             }
-            let (is_departure_vertiport_available, _) = is_vertiport_available(
+            let departure = is_vertiport_available(
                 vertiport.uid.clone(),
                 vertiport.schedule.clone(),
                 &[],
@@ -594,14 +626,25 @@ pub fn find_deadhead_flight_plan(
                 existing_flight_plans,
                 true,
             );
-            let (is_arrival_vertiport_available, _) = is_vertiport_available(
+            let arrival = is_vertiport_available(
                 vertiport_depart.id.clone(),
-                vertiport_depart.data.as_ref().unwrap().schedule.clone(),
+                vertiport_depart
+                    .data
+                    .as_ref()
+                    .and_then(|data| data.schedule.clone()),
                 vertipads_depart,
                 departure_time - Duration::minutes(LANDING_AND_UNLOADING_TIME_MIN as i64),
                 existing_flight_plans,
                 false,
             );
+            let ((is_departure_vertiport_available, _), (is_arrival_vertiport_available, _)) =
+                match (departure, arrival) {
+                    (Ok(departure), Ok(arrival)) => (departure, arrival),
+                    (Err(e), _) | (_, Err(e)) => {
+                        debug!("DH: Unable to check vertiport availability: {}", e);
+                        continue;
+                    }
+                };
             debug!(
                 "DH: DEPARTURE TIME: {}, {}, {}",
                 departure_time, is_departure_vertiport_available, is_arrival_vertiport_available
@@ -650,33 +693,34 @@ pub fn find_rerouted_vehicle_flight_plan(
     arrival_time: &DateTime<Tz>,
     existing_flight_plans: &[FlightPlan],
 ) -> Option<FlightPlanData> {
-    let found_vehicle = vehicles_at_arrival_airport
+    let (found_vehicle_id, _) = vehicles_at_arrival_airport
         .iter() //if there is a parked vehicle at the arrival vertiport, we can move it to some other vertiport
-        .find(|(_, minutes_to_arrival)| *minutes_to_arrival == 0);
-    found_vehicle?;
+        .find(|(_, minutes_to_arrival)| *minutes_to_arrival == 0)?;
     debug!("Checking if idle vehicle from the arrival airport can be re-routed");
     //todo this should re-route the vehicle to the nearest vertiport or HUB, but
     // we don't have vertipads or HUB id in the graph to do this.
     // So we are just re-routing to the same vertiport in the future time instead
     let found_gap = find_nearest_gap_for_reroute_flight(
         vertiport_arrive.id.clone(),
-        vertiport_arrive.data.as_ref().unwrap().schedule.clone(),
+        vertiport_arrive
+            .data
+            .as_ref()
+            .and_then(|data| data.schedule.clone()),
         vertipads_arrive,
         *arrival_time,
-        found_vehicle.unwrap().0.clone(),
+        found_vehicle_id.clone(),
         existing_flight_plans,
-    );
-    found_gap?;
+    )?;
     debug!(
         "Found a gap for re-routing idle vehicle from the arrival vertiport {}",
-        found_gap.unwrap()
+        found_gap
     );
     Some(create_flight_plan_data(
-        found_vehicle.unwrap().0.clone(),
+        found_vehicle_id.clone(),
         vertiport_arrive.id.clone(),
         vertiport_arrive.id.clone(),
-        found_gap.unwrap(),
-        found_gap.unwrap()
+        found_gap,
+        found_gap
             + Duration::minutes(
                 LANDING_AND_UNLOADING_TIME_MIN as i64 + LOADING_AND_TAKEOFF_TIME_MIN as i64,
             ),
@@ -699,14 +743,44 @@ pub fn get_nearest_vertiports_vertiport_id(
     (sorted_vertiports_by_durations, vertiport_durations)
 }
 
+/// A flight plan found by [`get_possible_flights`]
+#[derive(Debug, Clone)]
+pub struct PossibleFlight {
+    /// flight plan of the requested flight
+    pub flight_plan: FlightPlanData,
+    /// deadhead flight plans bringing the vehicle to the departure vertiport
+    pub deadhead_flight_plans: Vec<FlightPlanData>,
+    /// vertiport to divert to if the destination becomes unavailable
+    pub alternate_vertiport_id: String,
+    /// predicted distribution of the arrival time
+    pub eta: EtaDistribution,
+    /// estimated operating cost of the flight and its deadhead flights
+    pub cost: CostEstimate,
+    /// ids of the operation intents accepted by the UTM service, empty
+    /// without a UTM service
+    pub utm_intent_ids: Vec<String>,
+    /// minutes the flight was delayed from its departure slot to queue for a
+    /// pad at a busy vertiport
+    pub queue_minutes: i64,
+}
+
 /// Creates all possible flight plans based on the given request
+/// Flight plans held in the reservation ledger are treated as existing flight plans
+/// Each flight plan designates an alternate vertiport reachable from the destination with reserve energy
+/// and the heaviest cargo, the slots are rejected if there is none
+/// and carries the estimated operating cost of the flight and its deadhead flights
+/// If a UTM service is set, only the flights with intents accepted by the service are returned
+/// A slot whose vertiports are busy is delayed by up to [`MAX_PAD_QUEUE_MINUTES`] to queue for a pad,
+/// departing before the next slot
+/// If a crew provider is set, each vehicle is assigned a pilot within its duty limits, and flights
+/// without a pilot are not returned
 /// * `vertiport_depart` - Departure vertiport - svc-storage format
 /// * `vertiport_arrive` - Arrival vertiport - svc-storage format
 /// * `earliest_departure_time` - Earliest departure time of the time window
 /// * `latest_arrival_time` - Latest arrival time of the time window
 /// * `aircrafts` - Aircrafts serving the route and vertiports
 /// # Returns
-/// A vector of flight plans
+/// A vector of flight plans with their deadhead flight plans, alternate vertiport and predicted arrival
 #[allow(clippy::too_many_arguments)]
 pub fn get_possible_flights(
     vertiport_depart: Vertiport,
@@ -717,34 +791,396 @@ pub fn get_possible_flights(
     latest_arrival_time: Option<Timestamp>,
     vehicles: Vec<Vehicle>,
     existing_flight_plans: Vec<FlightPlan>,
-) -> Result<Vec<(FlightPlanData, Vec<FlightPlanData>)>, String> {
-    info!("Finding possible flights");
-    if earliest_departure_time.is_none() || latest_arrival_time.is_none() {
-        error!("Both earliest departure and latest arrival time must be specified");
-        return Err(
-            "Both earliest departure and latest arrival time must be specified".to_string(),
-        );
+) -> Result<Vec<PossibleFlight>, String> {
+    get_possible_flights_with(
+        vertiport_depart,
+        vertiport_arrive,
+        vertipads_depart,
+        vertipads_arrive,
+        earliest_departure_time,
+        latest_arrival_time,
+        vehicles,
+        existing_flight_plans,
+        &current_context(),
+    )
+}
+
+/// Creates all possible flight plans for a tenant, using only the vertiports
+/// and vehicles of the operators allowed by `filter`
+/// The route and the deadhead flights only go through the vertiports of
+/// `vertiports` allowed by `filter`, a vertiport of the graph missing from
+/// `vertiports` is foreign. The alternate vertiport may be any vertiport, as
+/// a diversion is an emergency.
+/// Existing flight plans of all operators are kept, as they occupy the vertipads
+/// of shared vertiports
+/// * `vertiports` - Vertiports of the network - svc-storage format
+///
+/// See [`get_possible_flights`] for the other arguments and result
+/// # Errors
+/// If the departure or arrival vertiport isn't allowed by `filter`
+#[allow(clippy::too_many_arguments)]
+pub fn get_possible_flights_for_operators(
+    filter: &OperatorFilter,
+    vertiports: &[Vertiport],
+    vertiport_depart: Vertiport,
+    vertiport_arrive: Vertiport,
+    vertipads_depart: Vec<Vertipad>,
+    vertipads_arrive: Vec<Vertipad>,
+    earliest_departure_time: Option<Timestamp>,
+    latest_arrival_time: Option<Timestamp>,
+    vehicles: Vec<Vehicle>,
+    existing_flight_plans: Vec<FlightPlan>,
+) -> Result<Vec<PossibleFlight>, String> {
+    for vertiport in [&vertiport_depart, &vertiport_arrive] {
+        if !filter.allows_vertiport(vertiport) {
+            warn!("Vertiport {} not allowed for the operators", vertiport.id);
+            return Err(format!(
+                "Vertiport {} not allowed for the operators",
+                vertiport.id
+            ));
+        }
+    }
+    let allowed_vertiport_ids: HashSet<String> = vertiports
+        .iter()
+        .chain([&vertiport_depart, &vertiport_arrive])
+        .filter(|vertiport| filter.allows_vertiport(vertiport))
+        .map(|vertiport| vertiport.id.clone())
+        .collect();
+    let clock = get_clock();
+    query_possible_flights(
+        &vertiport_depart,
+        &vertiport_arrive,
+        &vertipads_depart,
+        &vertipads_arrive,
+        earliest_departure_time,
+        latest_arrival_time,
+        &filter.filter_vehicles(vehicles),
+        &with_held_flight_plans(existing_flight_plans, clock.as_ref()),
+        clock.as_ref(),
+        Some(&allowed_vertiport_ids),
+    )
+}
+
+/// A vertiport a flight may depart from or arrive at, with its vertipads
+#[derive(Debug, Clone)]
+pub struct VertiportCandidate {
+    /// the vertiport - svc-storage format
+    pub vertiport: Vertiport,
+    /// vertipads of the vertiport
+    pub vertipads: Vec<Vertipad>,
+    /// minutes on the ground between the customer and the vertiport
+    pub ground_minutes: f32,
+}
+
+impl VertiportCandidate {
+    /// Creates a candidate without a ground leg to or from the vertiport
+    pub fn new(vertiport: Vertiport, vertipads: Vec<Vertipad>) -> Self {
+        VertiportCandidate {
+            vertiport,
+            vertipads,
+            ground_minutes: 0.0,
+        }
+    }
+
+    /// Sets the minutes on the ground between the customer and the vertiport,
+    /// e.g. estimated with a [`GroundLegEstimator`](crate::ground::GroundLegEstimator)
+    pub fn with_ground_minutes(mut self, ground_minutes: f32) -> Self {
+        self.ground_minutes = ground_minutes;
+        self
+    }
+}
+
+/// Seconds from the scheduled departure to the scheduled arrival of a flight plan
+fn scheduled_seconds(flight_plan: &FlightPlanData) -> i64 {
+    match (
+        &flight_plan.scheduled_departure,
+        &flight_plan.scheduled_arrival,
+    ) {
+        (Some(departure), Some(arrival)) => arrival.seconds - departure.seconds,
+        _ => 0,
+    }
+}
+
+/// Creates all possible flight plans between any of the departure and any of
+/// the arrival candidates, e.g. the vertiports nearest to the customer
+/// Each combination of distinct vertiports is queried like [`get_possible_flights`];
+/// combinations without a route are skipped
+/// * `departures` - Candidate departure vertiports
+/// * `arrivals` - Candidate arrival vertiports
+/// * `earliest_departure_time` - Earliest departure time of the time window
+/// * `latest_arrival_time` - Latest arrival time of the time window
+/// # Returns
+/// The possible flights of all combinations, quickest first: by the total
+/// time of the ground legs, the deadhead flights and the flight, then by
+/// arrival. The error of the last combination if none could be queried
+pub fn get_possible_flights_from_candidates(
+    departures: &[VertiportCandidate],
+    arrivals: &[VertiportCandidate],
+    earliest_departure_time: Option<Timestamp>,
+    latest_arrival_time: Option<Timestamp>,
+    vehicles: Vec<Vehicle>,
+    existing_flight_plans: Vec<FlightPlan>,
+) -> Result<Vec<PossibleFlight>, String> {
+    validate_flight_window(
+        earliest_departure_time.as_ref(),
+        latest_arrival_time.as_ref(),
+    )?;
+    let clock = get_clock();
+    let existing_flight_plans = with_held_flight_plans(existing_flight_plans, clock.as_ref());
+    let mut possible_flights: Vec<(i64, PossibleFlight)> = vec![];
+    let mut last_error = None;
+    let mut queried = false;
+    for departure in departures {
+        for arrival in arrivals {
+            if departure.vertiport.id == arrival.vertiport.id {
+                continue;
+            }
+            match query_possible_flights(
+                &departure.vertiport,
+                &arrival.vertiport,
+                &departure.vertipads,
+                &arrival.vertipads,
+                earliest_departure_time.clone(),
+                latest_arrival_time.clone(),
+                &vehicles,
+                &existing_flight_plans,
+                clock.as_ref(),
+                None,
+            ) {
+                Ok(flights) => {
+                    queried = true;
+                    let ground_seconds =
+                        ((departure.ground_minutes + arrival.ground_minutes) * 60.0).round() as i64;
+                    possible_flights.extend(flights.into_iter().map(|flight| {
+                        let total_seconds = ground_seconds
+                            + scheduled_seconds(&flight.flight_plan)
+                            + flight
+                                .deadhead_flight_plans
+                                .iter()
+                                .map(scheduled_seconds)
+                                .sum::<i64>();
+                        (total_seconds, flight)
+                    }));
+                }
+                Err(e) => {
+                    warn!(
+                        "No possible flights from {} to {}: {}",
+                        departure.vertiport.id, arrival.vertiport.id, e
+                    );
+                    last_error = Some(e);
+                }
+            }
+        }
+    }
+    if !queried {
+        if let Some(e) = last_error {
+            return Err(e);
+        }
+    }
+    possible_flights.sort_by_key(|(total_seconds, flight)| {
+        (
+            *total_seconds,
+            flight
+                .flight_plan
+                .scheduled_arrival
+                .as_ref()
+                .map(|time| time.seconds),
+        )
+    });
+    Ok(possible_flights
+        .into_iter()
+        .map(|(_, flight)| flight)
+        .collect())
+}
+
+/// Creates all possible flight plans based on the given request, with the
+/// clock, reservation holds, telemetry, models and services of `context`
+/// instead of the current context
+/// The decision is recorded in the audit trail if the context has an audit sink
+/// See [`get_possible_flights`] for the arguments and result
+#[allow(clippy::too_many_arguments)]
+pub fn get_possible_flights_with(
+    vertiport_depart: Vertiport,
+    vertiport_arrive: Vertiport,
+    vertipads_depart: Vec<Vertipad>,
+    vertipads_arrive: Vec<Vertipad>,
+    earliest_departure_time: Option<Timestamp>,
+    latest_arrival_time: Option<Timestamp>,
+    vehicles: Vec<Vehicle>,
+    existing_flight_plans: Vec<FlightPlan>,
+    context: &Arc<RouterContext>,
+) -> Result<Vec<PossibleFlight>, String> {
+    context.scope(|| {
+        let clock = get_clock();
+        query_possible_flights(
+            &vertiport_depart,
+            &vertiport_arrive,
+            &vertipads_depart,
+            &vertipads_arrive,
+            earliest_departure_time,
+            latest_arrival_time,
+            &vehicles,
+            &with_held_flight_plans(existing_flight_plans, clock.as_ref()),
+            clock.as_ref(),
+            None,
+        )
+    })
+}
+
+/// Creates all possible flight plans like [`get_possible_flights_with`],
+/// only going through the vertiports of `allowed_vertiport_ids` if set
+/// `existing_flight_plans` must include the flight plans held in the
+/// reservation ledger, see [`with_held_flight_plans`]
+/// Existing flight plans and the flight plans held in the reservation ledger
+fn with_held_flight_plans(
+    mut existing_flight_plans: Vec<FlightPlan>,
+    clock: &dyn Clock,
+) -> Vec<FlightPlan> {
+    existing_flight_plans.extend(get_held_flight_plans_with(clock));
+    existing_flight_plans
+}
+
+#[allow(clippy::too_many_arguments)]
+fn query_possible_flights(
+    vertiport_depart: &Vertiport,
+    vertiport_arrive: &Vertiport,
+    vertipads_depart: &[Vertipad],
+    vertipads_arrive: &[Vertipad],
+    earliest_departure_time: Option<Timestamp>,
+    latest_arrival_time: Option<Timestamp>,
+    vehicles: &[Vehicle],
+    existing_flight_plans: &[FlightPlan],
+    clock: &dyn Clock,
+    allowed_vertiport_ids: Option<&HashSet<String>>,
+) -> Result<Vec<PossibleFlight>, String> {
+    let query = is_audit_enabled().then(|| {
+        RecordedQuery::new(
+            vertiport_depart,
+            vertiport_arrive,
+            vertipads_depart,
+            vertipads_arrive,
+            &earliest_departure_time,
+            &latest_arrival_time,
+            vehicles,
+            existing_flight_plans,
+        )
+    });
+    let mut candidates = vec![];
+    let result = find_possible_flights(
+        vertiport_depart,
+        vertiport_arrive,
+        vertipads_depart,
+        vertipads_arrive,
+        earliest_departure_time,
+        latest_arrival_time,
+        vehicles,
+        existing_flight_plans,
+        clock,
+        get_utm_service().as_deref(),
+        get_crew_provider().as_deref(),
+        allowed_vertiport_ids,
+        &mut candidates,
+    );
+    if let Some(query) = query {
+        record(&AuditRecord::new(
+            clock.now(),
+            query,
+            candidates,
+            (&result).into(),
+        ));
     }
+    result
+}
+
+/// Creates all possible flight plans based on the given request, without
+/// consulting the reservation ledger
+/// The route and the deadhead flights only go through the vertiports of
+/// `allowed_vertiport_ids` if set
+/// Each candidate departure slot is added to `candidates` with the decision taken
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn find_possible_flights(
+    vertiport_depart: &Vertiport,
+    vertiport_arrive: &Vertiport,
+    vertipads_depart: &[Vertipad],
+    vertipads_arrive: &[Vertipad],
+    earliest_departure_time: Option<Timestamp>,
+    latest_arrival_time: Option<Timestamp>,
+    vehicles: &[Vehicle],
+    existing_flight_plans: &[FlightPlan],
+    clock: &dyn Clock,
+    utm: Option<&dyn UtmService>,
+    crew: Option<&dyn CrewProvider>,
+    allowed_vertiport_ids: Option<&HashSet<String>>,
+    candidates: &mut Vec<CandidateSlot>,
+) -> Result<Vec<PossibleFlight>, String> {
+    let _query = info_span!(
+        "find_possible_flights",
+        query_id = %Uuid::new_v4(),
+        departure_vertiport = %vertiport_depart.id,
+        arrival_vertiport = %vertiport_arrive.id,
+    )
+    .entered();
+    info!("Finding possible flights");
+    let (earliest_departure_time, latest_arrival_time) = validate_flight_window(
+        earliest_departure_time.as_ref(),
+        latest_arrival_time.as_ref(),
+    )
+    .map_err(|e| {
+        error!("Invalid time window: {}", e);
+        e
+    })?;
+    let vertiport_schedule = |vertiport: &Vertiport| {
+        vertiport
+            .data
+            .as_ref()
+            .map(|data| data.schedule.clone())
+            .ok_or_else(|| format!("Vertiport {} has no data", vertiport.id))
+    };
+    let departure_vertiport_schedule = vertiport_schedule(vertiport_depart)?;
+    let arrival_vertiport_schedule = vertiport_schedule(vertiport_arrive)?;
     //1. Find route and cost between requested vertiports
+    let route_span = info_span!("route").entered();
     info!("[1/5]: Finding route between vertiports");
     if !is_router_initialized() {
         error!("Router not initialized");
         return Err("Router not initialized".to_string());
     }
-    let (route, cost) = get_route(RouteQuery {
-        from: get_node_by_id(&vertiport_depart.id)?,
-        to: get_node_by_id(&vertiport_arrive.id)?,
-        aircraft: Aircraft::Cargo,
-    })?;
+    let is_allowed = |vertiport_id: &str| {
+        allowed_vertiport_ids.is_none_or(|allowed| allowed.contains(vertiport_id))
+    };
+    //1.0 Avoid the weather cells valid during the requested window
+    let weather_cells = get_weather_cells_during(earliest_departure_time, latest_arrival_time);
+    let (route, cost) = get_route_with_penalty(
+        RouteQuery::new(
+            Aircraft::Cargo,
+            get_node_by_id(&vertiport_depart.id)?,
+            get_node_by_id(&vertiport_arrive.id)?,
+        )
+        .with_departure_time(earliest_departure_time),
+        |from, to| {
+            if !is_allowed(&from.uid) || !is_allowed(&to.uid) {
+                return None;
+            }
+            weather_penalty(&weather_cells, &from.location, &to.location)
+        },
+    )?;
     debug!("Route: {:?}", route);
     debug!("Cost: {:?}", cost);
     if route.is_empty() {
         error!("No route found");
         return Err("Route between vertiports not found".to_string());
     }
-    //1.1 Create a sorted vector of vertiports nearest to the departure and arrival vertiport (in case we need to create a deadhead flight)
-    let (nearest_vertiports_from_departure, departure_vertiport_durations) =
-        get_nearest_vertiports_vertiport_id(&vertiport_depart);
+    //1.1 Find an alternate vertiport for the destination; the cargo weight is not known yet,
+    // so the alternate must be reachable with the heaviest cargo
+    let alternate_vertiport_id = find_alternate_vertiport(&vertiport_arrive.id, MAX_PAYLOAD_GRAMS)?;
+    if alternate_vertiport_id.is_none() {
+        warn!("No alternate vertiport reachable from the arrival vertiport");
+    }
+    //1.2 Create a sorted vector of vertiports nearest to the departure and arrival vertiport (in case we need to create a deadhead flight)
+    let (mut nearest_vertiports_from_departure, departure_vertiport_durations) =
+        get_nearest_vertiports_vertiport_id(vertiport_depart);
+    nearest_vertiports_from_departure.retain(|vertiport| is_allowed(&vertiport.uid));
+    route_span.exit();
 
     //2. calculate blocking times for each vertiport and aircraft
     info!("[2/5]: Calculating blocking times");
@@ -756,9 +1192,8 @@ pub fn get_possible_flights(
         block_aircraft_and_vertiports_minutes
     );
 
-    let time_window_duration_minutes: f32 = ((latest_arrival_time.as_ref().unwrap().seconds
-        - earliest_departure_time.as_ref().unwrap().seconds)
-        / 60) as f32;
+    let time_window_duration_minutes: f32 =
+        (latest_arrival_time - earliest_departure_time).num_minutes() as f32;
     debug!(
         "Time window duration in minutes: {}",
         time_window_duration_minutes
@@ -780,35 +1215,168 @@ pub fn get_possible_flights(
         "[3/5]: Checking vertiport schedules and flight plans for {} possible flight plans",
         num_flight_options
     );
-    let mut flight_plans: Vec<(FlightPlanData, Vec<FlightPlanData>)> = vec![];
-    for i in 0..num_flight_options {
-        let mut deadhead_flights: Vec<FlightPlanData> = vec![];
-        let departure_time = Tz::UTC.from_utc_datetime(
-            &NaiveDateTime::from_timestamp_opt(
-                earliest_departure_time.as_ref().unwrap().seconds
-                    + i * 60 * FLIGHT_PLAN_GAP_MINUTES as i64,
-                earliest_departure_time.as_ref().unwrap().nanos as u32,
-            )
-            .ok_or("Invalid departure_time")?,
+    let eta_model = get_eta_model();
+    let cost_model = get_cost_model();
+    let latest_arrival_seconds = latest_arrival_time.timestamp();
+    let separation = get_separation_minima().map(|minima| {
+        let active = get_active_trajectories(
+            existing_flight_plans,
+            earliest_departure_time,
+            latest_arrival_time,
         );
-        let arrival_time =
-            departure_time + Duration::minutes(block_aircraft_and_vertiports_minutes as i64);
-        let (is_departure_vertiport_available, _) = is_vertiport_available(
+        (minima, active)
+    });
+    let departure_timeline = PadTimeline::for_vertiport(
+        &vertiport_depart.id,
+        usable_vertipads(&vertiport_depart.id, vertipads_depart).len(),
+        existing_flight_plans,
+    );
+    let arrival_timeline = PadTimeline::for_vertiport(
+        &vertiport_arrive.id,
+        usable_vertipads(&vertiport_arrive.id, vertipads_arrive).len(),
+        existing_flight_plans,
+    );
+    // availability of the departure and arrival vertiports for a flight
+    let check_vertiports = |departure_time: DateTime<Tz>, arrival_time: DateTime<Tz>| {
+        let departure = is_vertiport_available(
             vertiport_depart.id.clone(),
-            vertiport_depart.data.as_ref().unwrap().schedule.clone(),
-            &vertipads_depart,
+            departure_vertiport_schedule.clone(),
+            vertipads_depart,
             departure_time,
-            &existing_flight_plans,
+            existing_flight_plans,
             true,
-        );
-        let (is_arrival_vertiport_available, vehicles_at_arrival_airport) = is_vertiport_available(
+        )?;
+        let arrival = is_vertiport_available(
             vertiport_arrive.id.clone(),
-            vertiport_arrive.data.as_ref().unwrap().schedule.clone(),
-            &vertipads_arrive,
+            arrival_vertiport_schedule.clone(),
+            vertipads_arrive,
             arrival_time - Duration::minutes(LANDING_AND_UNLOADING_TIME_MIN as i64),
-            &existing_flight_plans,
+            existing_flight_plans,
             false,
-        );
+        )?;
+        Ok::<_, String>((departure, arrival))
+    };
+    let mut flight_plans: Vec<PossibleFlight> = vec![];
+    let availability_span = info_span!("availability_scan", slots = num_flight_options).entered();
+    let availability_started = Instant::now();
+    for i in 0..num_flight_options {
+        increment_counter(SLOTS_EVALUATED, 1);
+        let mut deadhead_flights: Vec<FlightPlanData> = vec![];
+        let mut departure_time =
+            earliest_departure_time + Duration::minutes(i * FLIGHT_PLAN_GAP_MINUTES as i64);
+        let mut arrival_time =
+            departure_time + Duration::minutes(block_aircraft_and_vertiports_minutes as i64);
+        let _slot = debug_span!("slot", departure_time = %departure_time).entered();
+        let Some(alternate_vertiport_id) = &alternate_vertiport_id else {
+            candidates.push(CandidateSlot::rejected(
+                departure_time,
+                RejectionReason::NoAlternateVertiport,
+            ));
+            continue;
+        };
+        // stagger the departure if the flight would converge with an active flight
+        if let Some((minima, active)) = &separation {
+            let trajectory = Trajectory {
+                flight_plan_id: String::new(),
+                route: route.clone(),
+                departure: departure_time.timestamp(),
+                arrival: arrival_time.timestamp(),
+            };
+            let max_delay_minutes = minima
+                .max_stagger_minutes
+                .min((latest_arrival_seconds - arrival_time.timestamp()) / 60);
+            let Some(delay) =
+                find_separated_delay_minutes(minima, &trajectory, active, max_delay_minutes)
+            else {
+                debug!(
+                    "Flight can't keep separated for departure time {}",
+                    departure_time
+                );
+                candidates.push(CandidateSlot::rejected(
+                    departure_time,
+                    RejectionReason::SeparationConflict,
+                ));
+                continue;
+            };
+            departure_time += Duration::minutes(delay);
+            arrival_time += Duration::minutes(delay);
+        }
+        let (
+            (mut is_departure_vertiport_available, _),
+            (mut is_arrival_vertiport_available, mut vehicles_at_arrival_airport),
+        ) = check_vertiports(departure_time, arrival_time)?;
+        //3.1 queue for a pad rather than discard the slot if a vertiport is busy
+        let mut queue_minutes = 0;
+        if !(is_departure_vertiport_available && is_arrival_vertiport_available) {
+            // the next instant both vertiports have a pad free after `delay`
+            let pad_delay = |mut delay: i64| loop {
+                let delayed = Duration::minutes(delay);
+                let wait = departure_timeline
+                    .expected_delay_minutes(
+                        departure_time + delayed,
+                        LOADING_AND_TAKEOFF_TIME_MIN as i64,
+                    )
+                    .max(arrival_timeline.expected_delay_minutes(
+                        arrival_time + delayed
+                            - Duration::minutes(LANDING_AND_UNLOADING_TIME_MIN as i64),
+                        LANDING_AND_UNLOADING_TIME_MIN as i64,
+                    ));
+                if wait == 0 {
+                    break delay;
+                }
+                delay += wait;
+            };
+            // the queued departure stays before the next slot so it can't
+            // duplicate the flight of that slot
+            let next_slot_time = earliest_departure_time
+                + Duration::minutes((i + 1) * FLIGHT_PLAN_GAP_MINUTES as i64);
+            let max_delay_minutes = MAX_PAD_QUEUE_MINUTES
+                .min((latest_arrival_seconds - arrival_time.timestamp()) / 60)
+                .min((next_slot_time - departure_time).num_minutes() - 1);
+            let mut delay = pad_delay(1);
+            debug!(
+                "Vertiports busy for departure time {}, expected delay {} minutes",
+                departure_time, delay
+            );
+            while delay <= max_delay_minutes {
+                if let Some((minima, active)) = &separation {
+                    let trajectory = Trajectory {
+                        flight_plan_id: String::new(),
+                        route: route.clone(),
+                        departure: departure_time.timestamp(),
+                        arrival: arrival_time.timestamp(),
+                    }
+                    .delayed(delay);
+                    match find_separated_delay_minutes(
+                        minima,
+                        &trajectory,
+                        active,
+                        max_delay_minutes - delay,
+                    ) {
+                        Some(0) => (),
+                        Some(stagger) => {
+                            delay = pad_delay(delay + stagger);
+                            continue;
+                        }
+                        None => break,
+                    }
+                }
+                let delayed = Duration::minutes(delay);
+                let (departure, arrival) =
+                    check_vertiports(departure_time + delayed, arrival_time + delayed)?;
+                if departure.0 && arrival.0 {
+                    departure_time += delayed;
+                    arrival_time += delayed;
+                    is_departure_vertiport_available = true;
+                    is_arrival_vertiport_available = true;
+                    vehicles_at_arrival_airport = arrival.1;
+                    queue_minutes = delay;
+                    break;
+                }
+                // closed by the vertiport schedule rather than a busy pad
+                delay = pad_delay(delay + 1);
+            }
+        }
         debug!(
             "DEPARTURE TIME: {}, ARRIVAL TIME: {}, {}, {}",
             departure_time,
@@ -821,6 +1389,10 @@ pub fn get_possible_flights(
                 "Departure vertiport not available for departure time {}",
                 departure_time
             );
+            candidates.push(CandidateSlot::rejected(
+                departure_time,
+                RejectionReason::DepartureVertiportUnavailable,
+            ));
             continue;
         }
         if !is_arrival_vertiport_available {
@@ -830,26 +1402,38 @@ pub fn get_possible_flights(
             );
             let found_rerouted_vehicle_flight_plan = find_rerouted_vehicle_flight_plan(
                 &vehicles_at_arrival_airport,
-                &vertiport_arrive,
-                &vertipads_arrive,
+                vertiport_arrive,
+                vertipads_arrive,
                 &arrival_time,
-                &existing_flight_plans,
+                existing_flight_plans,
             );
             if let Some(flight_plan) = found_rerouted_vehicle_flight_plan {
                 deadhead_flights.push(flight_plan);
             } else {
                 debug!("No rerouted vehicle found");
+                candidates.push(CandidateSlot::rejected(
+                    departure_time,
+                    RejectionReason::ArrivalVertiportUnavailable,
+                ));
                 continue;
             }
         }
+        let vehicle_span = info_span!("vehicle_matching").entered();
         let mut available_vehicle: Option<Vehicle> = None;
-        for vehicle in &vehicles {
+        for vehicle in vehicles {
             debug!(
                 "Checking vehicle id:{} for departure time: {}",
                 &vehicle.id, departure_time
             );
-            let (vehicle_vertiport_id, minutes_to_arrival) =
-                get_vehicle_scheduled_location(vehicle, departure_time, &existing_flight_plans);
+            let location =
+                get_vehicle_location_with(clock, vehicle, departure_time, existing_flight_plans);
+            let (vehicle_vertiport_id, minutes_to_arrival) = match location {
+                Ok(location) => location,
+                Err(e) => {
+                    debug!("Unable to locate vehicle: {}", e);
+                    continue;
+                }
+            };
             if vehicle_vertiport_id != vertiport_depart.id || minutes_to_arrival > 0 {
                 debug!(
                     "Vehicle id:{} not available at location for requested time {}. It is/will be at vertiport id: {} in {} minutes",
@@ -857,19 +1441,30 @@ pub fn get_possible_flights(
                 );
                 continue;
             }
+            if !is_vehicle_turned_around(
+                vehicle,
+                departure_time,
+                LegKind::Loaded,
+                existing_flight_plans,
+            ) {
+                continue;
+            }
             let result = is_vehicle_available(
                 vehicle,
                 departure_time,
                 block_aircraft_and_vertiports_minutes as i64,
-                &existing_flight_plans,
+                existing_flight_plans,
             );
 
-            let Ok(is_vehicle_available) = result else {
-                debug!(
-                    "Could not determine vehicle availability: (id {}) {}",
-                    &vehicle.id, result.unwrap_err()
-                );
-                continue;
+            let is_vehicle_available = match result {
+                Ok(is_vehicle_available) => is_vehicle_available,
+                Err(e) => {
+                    debug!(
+                        "Could not determine vehicle availability: (id {}) {}",
+                        &vehicle.id, e
+                    );
+                    continue;
+                }
             };
 
             if !is_vehicle_available {
@@ -896,38 +1491,114 @@ pub fn get_possible_flights(
             let (a_vehicle, deadhead_flight_plan) = find_deadhead_flight_plan(
                 &nearest_vertiports_from_departure,
                 &departure_vertiport_durations,
-                &vehicles,
-                &vertiport_depart,
-                &vertipads_depart,
+                vehicles,
+                vertiport_depart,
+                vertipads_depart,
                 departure_time,
-                &existing_flight_plans,
+                existing_flight_plans,
                 block_aircraft_and_vertiports_minutes as i64,
+                clock,
             );
-            if a_vehicle.is_some() {
-                available_vehicle = a_vehicle;
-                deadhead_flights.push(deadhead_flight_plan.unwrap());
+            if let (Some(vehicle), Some(deadhead_flight_plan)) = (a_vehicle, deadhead_flight_plan) {
+                available_vehicle = Some(vehicle);
+                deadhead_flights.push(deadhead_flight_plan);
             }
         }
-        if available_vehicle.is_none() {
+        vehicle_span.exit();
+        let Some(available_vehicle) = available_vehicle else {
             debug!(
                 "DH: No available vehicles for departure time {} (including deadhead flights)",
                 departure_time
             );
+            candidates.push(CandidateSlot::rejected(
+                departure_time,
+                RejectionReason::NoVehicleAvailable,
+            ));
             continue;
-        }
+        };
+        let vehicle_id = available_vehicle.id;
         //4. should check other constraints (cargo weight, number of passenger seats)
         //info!("[4/5]: Checking other constraints (cargo weight, number of passenger seats)");
-        flight_plans.push((
-            create_flight_plan_data(
-                available_vehicle.unwrap().id.clone(),
-                vertiport_depart.id.clone(),
-                vertiport_arrive.id.clone(),
+        let mut flight_plan = create_flight_plan_data(
+            vehicle_id.clone(),
+            vertiport_depart.id.clone(),
+            vertiport_arrive.id.clone(),
+            departure_time,
+            arrival_time,
+        );
+        //4.1 assign pilots within their duty limits to the flight and its deadhead flights
+        if let Some(crew) = crew {
+            let flights: Vec<&FlightPlanData> = std::iter::once(&flight_plan)
+                .chain(&deadhead_flights)
+                .collect();
+            let pilots = match assign_crew(crew, &flights, existing_flight_plans) {
+                Ok(pilots) => pilots,
+                Err(e) => {
+                    debug!("No crew for departure time {}: {}", departure_time, e);
+                    candidates.push(CandidateSlot::rejected(
+                        departure_time,
+                        RejectionReason::NoPilotAvailable,
+                    ));
+                    continue;
+                }
+            };
+            for data in std::iter::once(&mut flight_plan).chain(&mut deadhead_flights) {
+                data.pilot_id = pilots.get(&data.vehicle_id).cloned().unwrap_or_default();
+            }
+        }
+        //4.2 deconflict the flight and its deadhead flights with the UTM service
+        let mut utm_intent_ids = vec![];
+        if let Some(service) = utm {
+            let mut intents = vec![OperationIntent::new(&flight_plan, route.clone())?];
+            for deadhead_flight in &deadhead_flights {
+                intents.push(flight_plan_intent(deadhead_flight)?);
+            }
+            match deconflict_flight(service, &intents) {
+                Ok(intent_ids) => utm_intent_ids = intent_ids,
+                Err(e) => {
+                    debug!(
+                        "UTM service rejected departure time {}: {}",
+                        departure_time, e
+                    );
+                    candidates.push(CandidateSlot::rejected(
+                        departure_time,
+                        RejectionReason::UtmRejected,
+                    ));
+                    continue;
+                }
+            }
+        }
+        candidates.push(CandidateSlot::accepted(departure_time, &vehicle_id));
+        let mut flight_cost = estimate_leg_cost(&cost_model, &flight_plan, cost, LegKind::Loaded);
+        for deadhead_flight in &deadhead_flights {
+            let distance_km = get_route_distance_km(deadhead_flight).unwrap_or_else(|e| {
+                error!("Unable to route deadhead flight: {}", e);
+                0.0
+            });
+            flight_cost +=
+                estimate_leg_cost(&cost_model, deadhead_flight, distance_km, LegKind::Deadhead);
+        }
+        flight_plans.push(PossibleFlight {
+            flight_plan,
+            deadhead_flight_plans: deadhead_flights,
+            alternate_vertiport_id: alternate_vertiport_id.clone(),
+            eta: predict_eta(
                 departure_time,
-                arrival_time,
+                &route,
+                &AircraftProfile::of(Aircraft::Cargo),
+                &eta_model,
             ),
-            deadhead_flights,
-        ));
+            cost: flight_cost,
+            utm_intent_ids,
+            queue_minutes,
+        });
     }
+    availability_span.exit();
+    record_histogram(
+        AVAILABILITY_CHECK_SECONDS,
+        availability_started.elapsed().as_secs_f64(),
+    );
+    record_histogram(PLANS_RETURNED, flight_plans.len() as f64);
     if flight_plans.is_empty() {
         return Err("No flight plans found for given time window".to_string());
     }
@@ -955,6 +1626,19 @@ pub fn estimate_flight_time_minutes(distance_km: f32, aircraft: Aircraft) -> f32
     }
 }
 
+/// Estimates the flight time in minutes between two vertiports along the route of the router
+pub fn estimate_flight_time_between(
+    from_vertiport_id: &str,
+    to_vertiport_id: &str,
+) -> Result<i64, String> {
+    let (_, cost) = get_route(RouteQuery::new(
+        Aircraft::Cargo,
+        get_node_by_id(from_vertiport_id)?,
+        get_node_by_id(to_vertiport_id)?,
+    ))?;
+    Ok(estimate_flight_time_minutes(cost, Aircraft::Cargo) as i64)
+}
+
 /// gets node by id
 pub fn get_node_by_id(id: &str) -> Result<&'static Node, String> {
     debug!("id: {}", id);
@@ -967,40 +1651,18 @@ pub fn get_node_by_id(id: &str) -> Result<&'static Node, String> {
 }
 
 /// Initialize the router with vertiports from the storage service
+/// Returns an error if a vertiport has no data
 pub fn init_router_from_vertiports(vertiports: &[Vertiport]) -> Result<(), String> {
     info!("Initializing router from vertiports");
     let nodes = vertiports
         .iter()
-        .map(|vertiport| Node {
-            uid: vertiport.id.clone(),
-            location: Location {
-                latitude: OrderedFloat(
-                    vertiport
-                        .data
-                        .as_ref()
-                        .ok_or_else(|| format!("Something went wrong when parsing latitude data of vertiport id: {}", vertiport.id))
-                        .unwrap()
-                        .latitude as f32,
-                ),
-                longitude: OrderedFloat(
-                    vertiport
-                        .data
-                        .as_ref()
-                        .ok_or_else(|| format!("Something went wrong when parsing longitude data of vertiport id: {}", vertiport.id))
-                        .unwrap()
-                        .longitude as f32,
-                ),
-                altitude_meters: OrderedFloat(0.0),
-            },
-            forward_to: None,
-            status: status::Status::Ok,
-            schedule: vertiport
-                .data
-                .as_ref()
-                .ok_or_else(|| format!("Something went wrong when parsing schedule data of vertiport id: {}", vertiport.id))
-                .unwrap().schedule.clone(),
-        })
-        .collect();
+        .map(|vertiport| Node::try_from(vertiport).map_err(String::from))
+        .collect::<Result<Vec<Node>, String>>()?;
+    init_router_from_nodes(nodes)
+}
+
+/// Initialize the router with the given vertiport nodes
+pub(crate) fn init_router_from_nodes(nodes: Vec<Node>) -> Result<(), String> {
     NODES.set(nodes).map_err(|_| "Failed to set NODES")?;
     init_router()
 }
@@ -1016,14 +1678,18 @@ pub fn get_nearest_vertiports<'a>(
     let mut dst_vertiport = &vertiports[0];
     debug!("src_location: {:?}", src_location);
     debug!("dst_location: {:?}", dst_location);
-    let mut src_distance = haversine::distance(src_location, &src_vertiport.location);
-    let mut dst_distance = haversine::distance(dst_location, &dst_vertiport.location);
+    let src_distances =
+        haversine::distance_batch_by(src_location, vertiports, |vertiport| &vertiport.location);
+    let dst_distances =
+        haversine::distance_batch_by(dst_location, vertiports, |vertiport| &vertiport.location);
+    let mut src_distance = src_distances[0];
+    let mut dst_distance = dst_distances[0];
     debug!("src_distance: {}", src_distance);
     debug!("dst_distance: {}", dst_distance);
-    for vertiport in vertiports {
+    for ((vertiport, new_src_distance), new_dst_distance) in
+        vertiports.iter().zip(src_distances).zip(dst_distances)
+    {
         debug!("checking vertiport: {:?}", vertiport);
-        let new_src_distance = haversine::distance(src_location, &vertiport.location);
-        let new_dst_distance = haversine::distance(dst_location, &vertiport.location);
         debug!("new_src_distance: {}", new_src_distance);
         debug!("new_dst_distance: {}", new_dst_distance);
         if new_src_distance < src_distance {
@@ -1059,54 +1725,238 @@ pub fn is_router_initialized() -> bool {
 }
 
 /// Get route
+/// Returns the locations along the route and its length in kilometers
 pub fn get_route(req: RouteQuery) -> Result<(Vec<Location>, f32), String> {
+    let router = ARROW_CARGO_ROUTER
+        .get()
+        .ok_or("Arrow XL router not initialized. Try to initialize it first.")?;
+    get_route_with(router, &current_context(), req)
+}
+
+/// Get route on the given router, e.g. a router embedded in a service
+/// instead of the cargo router, with the constraints, congestion and noise
+/// zones of `context`
+/// Returns the locations along the route and its length in kilometers
+pub fn get_route_with(
+    router: &Router,
+    context: &Arc<RouterContext>,
+    req: RouteQuery,
+) -> Result<(Vec<Location>, f32), String> {
+    context.scope(|| get_route_in_current_context(router, req))
+}
+
+/// Get route on the given router like [`get_route_with`], with the current
+/// context
+fn get_route_in_current_context(
+    router: &Router,
+    req: RouteQuery,
+) -> Result<(Vec<Location>, f32), String> {
+    // without constraints, the precomputed shortest paths are looked up
+    let unconstrained = req.max_hops.is_none()
+        && req.max_duration.is_none()
+        && req.heuristic.is_none()
+        && get_route_constraints().is_empty()
+        && (req.departure_time.is_none()
+            || (get_congestion_multipliers().is_none() && get_noise_zones().is_empty()));
+    if unconstrained && router.has_all_pairs() {
+        debug!("Looking up precomputed route");
+        let (cost, path) = router
+            .find_shortest_path(req.from, req.to, Algorithm::PrecomputedAllPairs, None)
+            .map_err(|e| format!("{:?}", e))?;
+        info!("Finished getting route with cost: {}", cost);
+        increment_counter(ROUTES_COMPUTED, 1);
+        return path_to_route(router, &path);
+    }
+    find_route_with(router, req, |_, _| Some(0.0))
+}
+
+/// Locations along a path of the router and its length in kilometers
+fn path_to_route(router: &Router, path: &[NodeIndex]) -> Result<(Vec<Location>, f32), String> {
+    let locations = path
+        .iter()
+        .map(|node_idx| {
+            router
+                .get_node_by_id(*node_idx)
+                .map(|node| node.location)
+                .ok_or(format!("Node not found by index {:?}", *node_idx))
+        })
+        .collect::<Result<Vec<Location>, String>>()?;
+    let distance_km = locations
+        .windows(2)
+        .map(|leg| haversine::distance(&leg[0], &leg[1]))
+        .sum();
+    Ok((locations, distance_km))
+}
+
+/// Get route, adding `penalty` to the cost of each leg or skipping the leg
+/// if it returns None, e.g. with
+/// [`weather_penalty`](crate::weather::weather_penalty)
+/// Returns the locations along the route and its length in kilometers
+pub fn get_route_with_penalty(
+    req: RouteQuery,
+    penalty: impl Fn(&Node, &Node) -> Option<f32>,
+) -> Result<(Vec<Location>, f32), String> {
+    let router = ARROW_CARGO_ROUTER
+        .get()
+        .ok_or("Arrow XL router not initialized. Try to initialize it first.")?;
+    find_route_with(router, req, penalty)
+}
+
+/// Find a route on the given router, adding `penalty` to the cost of each
+/// leg like [`get_route_with_penalty`]
+/// Legs rejected by the [registered route constraints](crate::constraints::register_route_constraint)
+/// are skipped, and a route found in violation of one of them is an error
+/// Returns the locations along the route and its length in kilometers
+pub fn find_route_with(
+    router: &Router,
+    req: RouteQuery,
+    penalty: impl Fn(&Node, &Node) -> Option<f32>,
+) -> Result<(Vec<Location>, f32), String> {
+    find_route_with_constraints(router, req, &get_route_constraints(), penalty)
+}
+
+/// Find a route on the given router like [`find_route_with`], complying
+/// with `constraints` instead of the registered route constraints
+pub fn find_route_with_constraints(
+    router: &Router,
+    req: RouteQuery,
+    constraints: &[Arc<dyn RouteConstraint>],
+    penalty: impl Fn(&Node, &Node) -> Option<f32>,
+) -> Result<(Vec<Location>, f32), String> {
     debug!("Getting route");
     let RouteQuery {
         from,
         to,
-        aircraft: _,
+        aircraft,
+        departure_time,
+        max_hops,
+        max_duration,
+        heuristic,
     } = req;
+    let congestion = departure_time.zip(get_congestion_multipliers());
+    let noise_zones = departure_time.map(|departure_time| (departure_time, get_noise_zones()));
+    let limits = PathLimits {
+        max_legs: max_hops.map(|max_hops| max_hops as usize + 1),
+        max_duration: max_duration.map(|max_duration| max_duration.num_seconds() as f32 / 60.0),
+    };
 
-    if ARROW_CARGO_ROUTER.get().is_none() {
-        return Err("Arrow XL router not initialized. Try to initialize it first.".to_string());
-    }
-    let result = ARROW_CARGO_ROUTER
-        .get()
-        .as_ref()
-        .ok_or("Can't access router")
-        .unwrap()
-        .find_shortest_path(from, to, Algorithm::Dijkstra, None);
-
-    let Ok((cost, path)) = result else {
-        return Err(format!("{:?}", result.unwrap_err()));
+    let penalty = |from: &Node, to: &Node| {
+        check_leg_constraints(constraints, &from.location, &to.location).ok()?;
+        let mut penalty = penalty(from, to)?;
+        if let Some((departure_time, zones)) = &noise_zones {
+            penalty += noise_penalty(zones, *departure_time, &from.location, &to.location);
+        }
+        match &congestion {
+            Some((departure_time, multipliers)) => {
+                Some(penalty + congestion_penalty(multipliers, *departure_time, from, to)?)
+            }
+            None => Some(penalty),
+        }
+    };
+    let result = if !limits.is_unlimited() {
+        router.find_shortest_path_with_limits(from, to, penalty, limits, |from, to| {
+            estimate_flight_time_minutes(
+                haversine::distance(&from.location, &to.location),
+                aircraft,
+            )
+        })
+    } else if let Some(heuristic) = heuristic {
+        router.find_shortest_path_with_heuristic(from, to, penalty, heuristic)
+    } else {
+        router.find_shortest_path_with_penalty(from, to, penalty)
     };
 
+    let (cost, path) = result.map_err(|e| format!("{:?}", e))?;
+
     debug!("cost: {}", cost);
     debug!("path: {:?}", path);
-    let locations = path
-        .iter()
-        .map(|node_idx| {
-            ARROW_CARGO_ROUTER
-                .get()
-                .as_ref()
-                .ok_or("Can't access router")
-                .unwrap()
-                .get_node_by_id(*node_idx)
-                .ok_or(format!("Node not found by index {:?}", *node_idx))
-                .unwrap()
-                .location
-        })
-        .collect::<Vec<Location>>();
+    // the cost of the router may include more than the distance, e.g. landing fees
+    let (locations, distance_km) = path_to_route(router, &path)?;
     debug!("locations: {:?}", locations);
+    check_route_constraints(constraints, &locations)?;
     info!("Finished getting route with cost: {}", cost);
-    Ok((locations, cost))
+    increment_counter(ROUTES_COMPUTED, 1);
+    Ok((locations, distance_km))
+}
+
+/// Get up to `k` alternative routes between two nodes that don't share a
+/// corridor, or an intermediate vertiport with [`Disjointness::Node`], e.g.
+/// a primary and a backup route for contingency planning
+/// Legs rejected by the registered route constraints are skipped, and the
+/// routes found in violation of one of them are left out
+/// Returns the locations along each route and its length in kilometers,
+/// from the shortest route
+pub fn find_disjoint_routes(
+    from: &Node,
+    to: &Node,
+    k: usize,
+    disjointness: Disjointness,
+) -> Result<Vec<(Vec<Location>, f32)>, String> {
+    let router = ARROW_CARGO_ROUTER
+        .get()
+        .ok_or("Arrow XL router not initialized. Try to initialize it first.")?;
+    find_disjoint_routes_with(router, from, to, k, disjointness, &get_route_constraints())
+}
+
+/// Get up to `k` alternative routes between two nodes on the given router
+/// like [`find_disjoint_routes`], complying with `constraints`
+pub fn find_disjoint_routes_with(
+    router: &Router,
+    from: &Node,
+    to: &Node,
+    k: usize,
+    disjointness: Disjointness,
+    constraints: &[Arc<dyn RouteConstraint>],
+) -> Result<Vec<(Vec<Location>, f32)>, String> {
+    let paths = router
+        .find_disjoint_paths_with_penalty(from, to, k, disjointness, |from, to| {
+            check_leg_constraints(constraints, &from.location, &to.location)
+                .ok()
+                .map(|_| 0.0)
+        })
+        .map_err(|e| format!("{:?}", e))?;
+    debug!("Found {} disjoint routes", paths.len());
+    let mut routes = vec![];
+    for (_, path) in paths {
+        let route = path_to_route(router, &path)?;
+        match check_route_constraints(constraints, &route.0) {
+            Ok(()) => routes.push(route),
+            Err(e) => debug!("Disjoint route left out: {}", e),
+        }
+    }
+    Ok(routes)
 }
 
 /// Initializes the router for the given aircraft
 pub fn init_router() -> Result<(), String> {
-    if NODES.get().is_none() {
+    init_router_with_cost(|from, to| {
+        haversine::distance(&from.as_node().location, &to.as_node().location)
+    })
+}
+
+/// Initializes the router for the given aircraft, weighting the legs of the
+/// graph with `cost_function` instead of their distance, e.g. with
+/// [`distance_with_landing_fees`](crate::cost::distance_with_landing_fees)
+/// Routes are still reported with their distance in kilometers
+pub fn init_router_with_cost(
+    cost_function: fn(&dyn AsNode, &dyn AsNode) -> f32,
+) -> Result<(), String> {
+    init_router_with(
+        |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+        cost_function,
+    )
+}
+
+/// Initializes the router for the given aircraft, connecting only the nodes
+/// for which `constraint_function` is within the range of the aircraft, e.g.
+/// with [`distance_if_clear`](crate::terrain::distance_if_clear)
+pub fn init_router_with(
+    constraint_function: fn(&dyn AsNode, &dyn AsNode) -> f32,
+    cost_function: fn(&dyn AsNode, &dyn AsNode) -> f32,
+) -> Result<(), String> {
+    let Some(nodes) = NODES.get() else {
         return Err("Nodes not initialized. Try to get some nodes first.".to_string());
-    }
+    };
     if ARROW_CARGO_ROUTER.get().is_some() {
         return Err(
             "Router already initialized. Try to use the router instead of initializing it."
@@ -1115,28 +1965,212 @@ pub fn init_router() -> Result<(), String> {
     }
     ARROW_CARGO_ROUTER
         .set(Router::new(
-            NODES.get().as_ref().unwrap(),
-            ARROW_CARGO_CONSTRAINT,
-            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
-            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+            nodes,
+            ARROW_CARGO_CONSTRAINT.km(),
+            constraint_function,
+            cost_function,
         ))
-        .map_err(|_| "Failed to initialize router".to_string())
+        .map_err(|_| "Failed to initialize router".to_string())?;
+    notify_graph_changed(&GraphChange {
+        version: 0,
+        affected_node_ids: nodes.iter().map(|node| node.uid.clone()).collect(),
+    });
+    Ok(())
+}
+
+/// Selects landmarks and precomputes their costs on the initialized router,
+/// to query routes with [`Algorithm::AStarLandmarks`](crate::router::engine::Algorithm::AStarLandmarks)
+/// or the [`LANDMARK_HEURISTIC`](crate::router::engine::LANDMARK_HEURISTIC)
+pub fn init_landmarks(count: usize) -> Result<(), String> {
+    let router = ARROW_CARGO_ROUTER
+        .get()
+        .ok_or("Arrow XL router not initialized. Try to initialize it first.")?;
+    router.preprocess_landmarks(count);
+    Ok(())
+}
+
+/// Precomputes the shortest paths between all pairs of vertiports on the
+/// initialized router, so [`get_route`] looks routes up instead of
+/// searching them. Suits regional deployments of up to a few hundred
+/// vertiports, see [`Router::with_all_pairs`]
+pub fn init_all_pairs() -> Result<(), String> {
+    let router = ARROW_CARGO_ROUTER
+        .get()
+        .ok_or("Arrow XL router not initialized. Try to initialize it first.")?;
+    router.precompute_all_pairs();
+    Ok(())
 }
 
 #[cfg(test)]
 mod router_tests {
     use super::{
-        get_nearby_nodes, get_nearest_vertiports, get_route, init_router, Aircraft,
+        find_disjoint_routes_with, find_route_with_constraints, get_nearby_nodes,
+        get_nearest_vertiports, get_route, get_route_with, init_router, Aircraft,
         NearbyLocationQuery, RouteQuery, SAN_FRANCISCO,
     };
+    use crate::constraints::{register_route_constraint, RouteConstraint};
+    use crate::context::RouterContext;
+    use crate::distance::Distance;
+    use crate::generator::generate_nodes_near;
+    use crate::haversine;
     use crate::location::Location;
+    use crate::node::{AsNode, Node};
+    use crate::router::engine::{Disjointness, Router};
     use ordered_float::OrderedFloat;
+    use std::sync::Arc;
+
+    /// A service owning its router and context instead of using the cargo
+    /// router and the global context
+    struct RoutingService<'a> {
+        router: Router<'a>,
+        context: Arc<RouterContext>,
+    }
+
+    impl RoutingService<'_> {
+        fn route(&self, from_id: &str, to_id: &str) -> Result<(Vec<Location>, f32), String> {
+            let node = |id| {
+                self.router
+                    .get_node_by_uid(id)
+                    .ok_or(format!("Node not found by id: {}", id))
+            };
+            get_route_with(
+                &self.router,
+                &self.context,
+                RouteQuery::new(Aircraft::Cargo, node(from_id)?, node(to_id)?),
+            )
+        }
+    }
+
+    #[test]
+    fn test_route_with_owned_router() {
+        let nodes = generate_nodes_near(&SAN_FRANCISCO, Distance::from_km(10.0), 20);
+        let service = RoutingService {
+            router: Router::new(
+                &nodes,
+                100.0,
+                |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+                |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+            ),
+            context: Arc::new(RouterContext::new()),
+        };
+        let (route, distance_km) = service.route(&nodes[0].uid, &nodes[1].uid).unwrap();
+        assert_eq!(route, vec![nodes[0].location, nodes[1].location]);
+        assert_eq!(
+            distance_km,
+            haversine::distance(&nodes[0].location, &nodes[1].location)
+        );
+        assert!(service.route(&nodes[0].uid, "unknown").is_err());
+    }
+
+    /// Bans the legs ending at a latitude, from any latitude if `from` is None
+    struct LatitudeBan {
+        from: Option<f32>,
+        to: f32,
+    }
+
+    impl RouteConstraint for LatitudeBan {
+        fn name(&self) -> &str {
+            "latitude-ban"
+        }
+
+        fn check_leg(&self, from: &Location, to: &Location) -> Result<(), String> {
+            let banned_from = self
+                .from
+                .is_none_or(|latitude| from.latitude.into_inner() == latitude);
+            if banned_from && to.latitude.into_inner() == self.to {
+                return Err(format!("leg to latitude {}", self.to));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_route_detours_around_constraint() {
+        // the direct leg A-B is shorter than the detour through C
+        let nodes = vec![
+            Node::new("A", 0.0, 0.0),
+            Node::new("B", 0.1, 0.4),
+            Node::new("C", 0.2, 0.2),
+        ];
+        let distance = |from: &dyn AsNode, to: &dyn AsNode| {
+            haversine::distance(&from.as_node().location, &to.as_node().location)
+        };
+        let router = Router::new(&nodes, 100.0, distance, distance);
+        let query = || RouteQuery::new(Aircraft::Cargo, &nodes[0], &nodes[1]);
+        let route = |constraints: &[Arc<dyn RouteConstraint>]| {
+            find_route_with_constraints(&router, query(), constraints, |_, _| Some(0.0))
+                .map(|(route, _)| route)
+        };
+        assert_eq!(
+            route(&[]).unwrap(),
+            vec![nodes[0].location, nodes[1].location]
+        );
+
+        // the direct leg is banned
+        let detour: Vec<Arc<dyn RouteConstraint>> = vec![Arc::new(LatitudeBan {
+            from: Some(0.0),
+            to: 0.1,
+        })];
+        assert_eq!(
+            route(&detour).unwrap(),
+            vec![nodes[0].location, nodes[2].location, nodes[1].location]
+        );
+        // the detour lands once
+        let limited = |max_hops: u32| {
+            find_route_with_constraints(
+                &router,
+                query().with_max_hops(max_hops),
+                &detour,
+                |_, _| Some(0.0),
+            )
+            .map(|(route, _)| route)
+        };
+        assert!(limited(0).unwrap().is_empty());
+        assert_eq!(limited(1).unwrap().len(), 3);
+        let disjoint = find_disjoint_routes_with(
+            &router,
+            &nodes[0],
+            &nodes[1],
+            3,
+            Disjointness::Edge,
+            &detour,
+        )
+        .unwrap();
+        assert_eq!(disjoint.len(), 1);
+        assert_eq!(disjoint[0].0.len(), 3);
+
+        // a constraint registered in a context only applies to its routes
+        let restricted = Arc::new(RouterContext::new());
+        restricted.scope(|| register_route_constraint(detour[0].clone()));
+        let route_in = |context: &Arc<RouterContext>| {
+            get_route_with(&router, context, query()).map(|(route, _)| route)
+        };
+        assert_eq!(route_in(&restricted).unwrap().len(), 3);
+        assert_eq!(route_in(&Arc::new(RouterContext::new())).unwrap().len(), 2);
+
+        // no route left to B, an empty route like for unconnected nodes
+        let blocked: Vec<Arc<dyn RouteConstraint>> = vec![Arc::new(LatitudeBan {
+            from: None,
+            to: 0.1,
+        })];
+        assert!(route(&blocked).unwrap().is_empty());
+        assert!(find_disjoint_routes_with(
+            &router,
+            &nodes[0],
+            &nodes[1],
+            3,
+            Disjointness::Edge,
+            &blocked
+        )
+        .unwrap()
+        .is_empty());
+    }
 
     #[test]
     fn test_router() {
         let nodes = get_nearby_nodes(NearbyLocationQuery {
             location: SAN_FRANCISCO,
-            radius: 25.0,
+            radius: Distance::from_km(25.0),
             capacity: 20,
         });
 
@@ -1155,14 +2189,182 @@ mod router_tests {
         };
         let (src, dst) = get_nearest_vertiports(&src_location, &dst_location, nodes);
         println!("src: {:?}, dst: {:?}", src.location, dst.location);
-        let (route, cost) = get_route(RouteQuery {
-            from: src,
-            to: dst,
-            aircraft: Aircraft::Cargo,
-        })
-        .unwrap();
+        let (route, cost) = get_route(RouteQuery::new(Aircraft::Cargo, src, dst)).unwrap();
         println!("route: {:?}", route);
         assert!(route.len() > 0, "Route should not be empty");
         assert!(cost > 0.0, "Cost should be greater than 0");
     }
 }
+
+#[cfg(test)]
+mod availability_tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn start() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2031, 3, 1, 8, 0, 0).unwrap()
+    }
+
+    fn vehicle(last_vertiport_id: Option<&str>) -> Vehicle {
+        Vehicle {
+            id: "malformed-v1".to_string(),
+            data: Some(crate::resources::vehicle::Data {
+                last_vertiport_id: last_vertiport_id.map(str::to_string),
+                schedule: Some(
+                    "DTSTART:20221020T000000Z;DURATION:PT1H\nRRULE:FREQ=DAILY".to_string(),
+                ),
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// a valid flight plan of the vehicle followed by malformed ones
+    fn flight_plans() -> Vec<FlightPlan> {
+        let mut without_times = create_flight_plan_data(
+            "malformed-v1".to_string(),
+            "malformed-A".to_string(),
+            "malformed-C".to_string(),
+            start(),
+            start() + Duration::minutes(30),
+        );
+        without_times.scheduled_arrival = None;
+        let mut without_destination = without_times.clone();
+        without_destination.scheduled_arrival = without_times.scheduled_departure.clone();
+        without_destination.destination_vertiport_id = None;
+        vec![
+            FlightPlan {
+                id: "valid".to_string(),
+                data: Some(create_flight_plan_data(
+                    "malformed-v1".to_string(),
+                    "malformed-A".to_string(),
+                    "malformed-B".to_string(),
+                    start() - Duration::hours(2),
+                    start() - Duration::hours(1),
+                )),
+            },
+            FlightPlan {
+                id: "without-data".to_string(),
+                data: None,
+            },
+            FlightPlan {
+                id: "without-times".to_string(),
+                data: Some(without_times),
+            },
+            FlightPlan {
+                id: "without-destination".to_string(),
+                data: Some(without_destination),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_vehicle_availability_skips_malformed_flight_plans() {
+        let flight_plans = flight_plans();
+        assert_eq!(
+            is_vehicle_available(&vehicle(None), start(), 30, &flight_plans),
+            Ok(true)
+        );
+        assert_eq!(
+            is_vehicle_available(
+                &vehicle(None),
+                start() - Duration::minutes(90),
+                30,
+                &flight_plans
+            ),
+            Ok(false)
+        );
+        let without_data = Vehicle {
+            id: "malformed-v2".to_string(),
+            data: None,
+        };
+        assert_eq!(
+            is_vehicle_available(&without_data, start(), 30, &flight_plans),
+            Err("Vehicle malformed-v2 has no data".to_string())
+        );
+    }
+
+    #[test]
+    fn test_vehicle_location_skips_malformed_flight_plans() {
+        let flight_plans = flight_plans();
+        assert_eq!(
+            get_vehicle_scheduled_location(&vehicle(None), start(), &flight_plans),
+            Ok(("malformed-B".to_string(), 0))
+        );
+        assert_eq!(
+            get_vehicle_scheduled_location(
+                &vehicle(Some("malformed-A")),
+                start() - Duration::hours(3),
+                &flight_plans
+            ),
+            Ok(("malformed-A".to_string(), 0))
+        );
+        assert!(get_vehicle_scheduled_location(
+            &vehicle(None),
+            start() - Duration::hours(3),
+            &flight_plans
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_vertiport_schedules() {
+        let flight_plans = flight_plans();
+        assert_eq!(
+            is_vertiport_available(
+                "malformed-C".to_string(),
+                None,
+                &[],
+                start(),
+                &flight_plans,
+                false
+            ),
+            Ok((true, vec![]))
+        );
+        assert_eq!(
+            is_vertiport_available(
+                "malformed-C".to_string(),
+                Some("not a schedule".to_string()),
+                &[],
+                start(),
+                &flight_plans,
+                false
+            ),
+            Err("Invalid schedule for vertiport malformed-C".to_string())
+        );
+    }
+
+    #[test]
+    fn test_local_schedules_across_dst() {
+        // closed from 20:00 to 08:00 New York time
+        let schedule = "X-WR-TIMEZONE:America/New_York\n\
+            DTSTART:20221020T200000;DURATION:PT12H\n\
+            RRULE:FREQ=DAILY";
+        let vehicle = Vehicle {
+            id: "local-v1".to_string(),
+            data: Some(crate::resources::vehicle::Data {
+                schedule: Some(schedule.to_string()),
+                ..Default::default()
+            }),
+        };
+        let vertiport_available = |date_from: DateTime<Tz>| {
+            is_vertiport_available(
+                "local-A".to_string(),
+                Some(schedule.to_string()),
+                &[],
+                date_from,
+                &[],
+                true,
+            )
+            .unwrap()
+            .0
+        };
+        // 12:30 UTC is 08:30 EDT in summer and 07:30 EST in winter
+        let summer = Tz::UTC.with_ymd_and_hms(2031, 7, 9, 12, 30, 0).unwrap();
+        let winter = Tz::UTC.with_ymd_and_hms(2031, 1, 8, 12, 30, 0).unwrap();
+        assert!(vertiport_available(summer));
+        assert!(!vertiport_available(winter));
+        assert!(vertiport_available(winter + Duration::hours(1)));
+        assert_eq!(is_vehicle_available(&vehicle, summer, 30, &[]), Ok(true));
+        assert_eq!(is_vehicle_available(&vehicle, winter, 30, &[]), Ok(false));
+    }
+}
@@ -0,0 +1,410 @@
+//! What-if analysis of network changes.
+//!
+//! [`evaluate_scenario`] applies hypothetical changes, such as closing or
+//! adding a vertiport or removing aircraft, to a copy of the network and
+//! reports which routes appear, disappear or change length and which
+//! scheduled flight plans could no longer be flown. The live router is not
+//! touched.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::distance::Distance;
+use crate::haversine;
+use crate::node::Node;
+use crate::router::engine::Router;
+use crate::router_state::{FlightPlan, Vehicle, ARROW_CARGO_CONSTRAINT, NODES};
+use crate::status::Status;
+
+/// Route lengths differing by less than this are considered unchanged
+const ROUTE_DISTANCE_TOLERANCE_KM: f32 = 0.001;
+
+/// Hypothetical change to the network
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScenarioChange {
+    /// the vertiport with the given id is closed
+    CloseVertiport(String),
+    /// the vertiport is added to the network
+    AddVertiport(Node),
+    /// the vehicles with the given ids are taken out of service
+    RemoveVehicles(Vec<String>),
+}
+
+/// Network, fleet and schedule a scenario is evaluated against
+#[derive(Debug, Clone, Default)]
+pub struct NetworkState {
+    /// vertiports of the network
+    pub nodes: Vec<Node>,
+    /// vehicles of the fleet
+    pub vehicles: Vec<Vehicle>,
+    /// scheduled flight plans
+    pub flight_plans: Vec<FlightPlan>,
+}
+
+impl NetworkState {
+    /// Applies a change to the state
+    pub fn apply(&mut self, change: &ScenarioChange) -> Result<(), String> {
+        match change {
+            ScenarioChange::CloseVertiport(vertiport_id) => {
+                let node = self
+                    .nodes
+                    .iter_mut()
+                    .find(|node| node.uid == *vertiport_id)
+                    .ok_or_else(|| format!("Vertiport not found by id: {}", vertiport_id))?;
+                node.status = Status::Closed;
+            }
+            ScenarioChange::AddVertiport(node) => {
+                if self.nodes.iter().any(|existing| existing.uid == node.uid) {
+                    return Err(format!("Vertiport already exists: {}", node.uid));
+                }
+                self.nodes.push(node.clone());
+            }
+            ScenarioChange::RemoveVehicles(vehicle_ids) => {
+                for vehicle_id in vehicle_ids {
+                    let index = self
+                        .vehicles
+                        .iter()
+                        .position(|vehicle| vehicle.id == *vehicle_id)
+                        .ok_or_else(|| format!("Vehicle not found by id: {}", vehicle_id))?;
+                    self.vehicles.remove(index);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Route between two vertiports whose availability or length changes
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteChange {
+    /// id of the departure vertiport
+    pub from_vertiport_id: String,
+    /// id of the destination vertiport
+    pub to_vertiport_id: String,
+    /// length of the route before the changes, `None` if there is no route
+    pub baseline_distance_km: Option<f32>,
+    /// length of the route after the changes, `None` if there is no route
+    pub scenario_distance_km: Option<f32>,
+}
+
+/// Reason a scheduled flight plan can no longer be flown
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FlightPlanImpact {
+    /// the departure or destination vertiport with the given id is closed
+    VertiportClosed(String),
+    /// the vehicle with the given id was removed
+    VehicleRemoved(String),
+    /// there is no route between the departure and destination vertiport anymore
+    RouteUnavailable,
+}
+
+/// Scheduled flight plan affected by the changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AffectedFlightPlan {
+    /// id of the flight plan
+    pub flight_plan_id: String,
+    /// why the flight plan can no longer be flown
+    pub impact: FlightPlanImpact,
+}
+
+/// Impact of a scenario on the network
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScenarioReport {
+    /// number of ordered vertiport pairs connected by a route before the changes
+    pub baseline_connected_pairs: usize,
+    /// number of ordered vertiport pairs connected by a route after the changes
+    pub scenario_connected_pairs: usize,
+    /// routes gained, lost or changed in length, ordered by vertiport ids
+    pub route_changes: Vec<RouteChange>,
+    /// flight plans which can no longer be flown, in the order of the schedule
+    pub affected_flight_plans: Vec<AffectedFlightPlan>,
+}
+
+/// Route distances between all pairs of open vertiports, keyed by (from, to) id
+fn route_distances(nodes: &[Node], constraint: f32) -> BTreeMap<(String, String), f32> {
+    let open: Vec<Node> = nodes
+        .iter()
+        .filter(|node| node.status == Status::Ok)
+        .cloned()
+        .collect();
+    let router = Router::new(
+        &open,
+        constraint,
+        |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+        |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+    );
+    let mut distances = BTreeMap::new();
+    for from in &open {
+        for (index, (distance_km, _)) in
+            router.find_shortest_paths_from_location(&from.location, constraint)
+        {
+            let Some(to) = router.get_node_by_id(index) else {
+                continue;
+            };
+            if to.uid != from.uid {
+                distances.insert((from.uid.clone(), to.uid.clone()), distance_km);
+            }
+        }
+    }
+    distances
+}
+
+/// Evaluates hypothetical changes against the given state
+///
+/// # Arguments
+/// * `baseline` - network, fleet and schedule before the changes
+/// * `changes` - changes applied in order
+/// * `constraint` - max distance of a leg of the graph
+///
+/// # Returns
+/// The impact of the changes on the routes and the scheduled flight plans,
+/// or an error if a change refers to an unknown vertiport or vehicle
+pub fn evaluate_scenario_with(
+    baseline: &NetworkState,
+    changes: &[ScenarioChange],
+    constraint: Distance,
+) -> Result<ScenarioReport, String> {
+    let mut scenario = baseline.clone();
+    for change in changes {
+        scenario.apply(change)?;
+    }
+    info!("Evaluating scenario with {} change(s)", changes.len());
+
+    let baseline_distances = route_distances(&baseline.nodes, constraint.km());
+    let scenario_distances = route_distances(&scenario.nodes, constraint.km());
+    let pairs: BTreeSet<&(String, String)> = baseline_distances
+        .keys()
+        .chain(scenario_distances.keys())
+        .collect();
+    let route_changes: Vec<RouteChange> = pairs
+        .into_iter()
+        .filter_map(|pair| {
+            let before = baseline_distances.get(pair).copied();
+            let after = scenario_distances.get(pair).copied();
+            let unchanged = match (before, after) {
+                (Some(before), Some(after)) => (before - after).abs() < ROUTE_DISTANCE_TOLERANCE_KM,
+                _ => false,
+            };
+            (!unchanged).then(|| RouteChange {
+                from_vertiport_id: pair.0.clone(),
+                to_vertiport_id: pair.1.clone(),
+                baseline_distance_km: before,
+                scenario_distance_km: after,
+            })
+        })
+        .collect();
+
+    let closed: HashSet<&str> = scenario
+        .nodes
+        .iter()
+        .filter(|node| node.status != Status::Ok)
+        .map(|node| node.uid.as_str())
+        .collect();
+    let vehicles: HashSet<&str> = scenario
+        .vehicles
+        .iter()
+        .map(|vehicle| vehicle.id.as_str())
+        .collect();
+    let removed_vehicles: HashSet<&str> = baseline
+        .vehicles
+        .iter()
+        .map(|vehicle| vehicle.id.as_str())
+        .filter(|vehicle_id| !vehicles.contains(vehicle_id))
+        .collect();
+    let affected_flight_plans = scenario
+        .flight_plans
+        .iter()
+        .filter_map(|flight_plan| {
+            let data = flight_plan.data.as_ref()?;
+            let from = data.departure_vertiport_id.clone().unwrap_or_default();
+            let to = data.destination_vertiport_id.clone().unwrap_or_default();
+            let impact = if closed.contains(from.as_str()) {
+                FlightPlanImpact::VertiportClosed(from)
+            } else if closed.contains(to.as_str()) {
+                FlightPlanImpact::VertiportClosed(to)
+            } else if removed_vehicles.contains(data.vehicle_id.as_str()) {
+                FlightPlanImpact::VehicleRemoved(data.vehicle_id.clone())
+            } else if from != to
+                && baseline_distances.contains_key(&(from.clone(), to.clone()))
+                && !scenario_distances.contains_key(&(from, to))
+            {
+                FlightPlanImpact::RouteUnavailable
+            } else {
+                return None;
+            };
+            Some(AffectedFlightPlan {
+                flight_plan_id: flight_plan.id.clone(),
+                impact,
+            })
+        })
+        .collect();
+
+    Ok(ScenarioReport {
+        baseline_connected_pairs: baseline_distances.len(),
+        scenario_connected_pairs: scenario_distances.len(),
+        route_changes,
+        affected_flight_plans,
+    })
+}
+
+/// Evaluates hypothetical changes against the vertiports of the router
+///
+/// # Arguments
+/// * `changes` - changes applied in order
+/// * `vehicles` - vehicles of the fleet
+/// * `flight_plans` - scheduled flight plans
+///
+/// # Returns
+/// The impact of the changes on the routes and the scheduled flight plans
+pub fn evaluate_scenario(
+    changes: &[ScenarioChange],
+    vehicles: &[Vehicle],
+    flight_plans: &[FlightPlan],
+) -> Result<ScenarioReport, String> {
+    let nodes = NODES
+        .get()
+        .ok_or("Nodes not initialized. Try to get some nodes first.")?;
+    let baseline = NetworkState {
+        nodes: nodes.clone(),
+        vehicles: vehicles.to_vec(),
+        flight_plans: flight_plans.to_vec(),
+    };
+    evaluate_scenario_with(&baseline, changes, ARROW_CARGO_CONSTRAINT)
+}
+
+#[cfg(test)]
+mod scenario_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use chrono::{Duration, TimeZone};
+    use rrule::Tz;
+
+    /// vertiport on the equator at the given longitude
+    fn node(uid: &str, longitude: f32) -> Node {
+        Node::new(uid, 0.0, longitude)
+    }
+
+    fn vehicle(id: &str) -> Vehicle {
+        Vehicle {
+            id: id.to_string(),
+            data: None,
+        }
+    }
+
+    fn flight_plan(id: &str, vehicle_id: &str, from: &str, to: &str) -> FlightPlan {
+        let departure = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap();
+        FlightPlan {
+            id: id.to_string(),
+            data: Some(create_flight_plan_data(
+                vehicle_id.to_string(),
+                from.to_string(),
+                to.to_string(),
+                departure,
+                departure + Duration::minutes(30),
+            )),
+        }
+    }
+
+    /// A - B - C about 44 km apart in a line; A and C only connect through B
+    fn baseline() -> NetworkState {
+        NetworkState {
+            nodes: vec![node("A", 0.0), node("B", 0.4), node("C", 0.8)],
+            vehicles: vec![vehicle("v1"), vehicle("v2"), vehicle("v3")],
+            flight_plans: vec![
+                flight_plan("fp1", "v1", "A", "C"),
+                flight_plan("fp2", "v2", "A", "B"),
+                flight_plan("fp3", "v3", "C", "B"),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_no_changes() {
+        let report = evaluate_scenario_with(&baseline(), &[], Distance::from_km(50.0)).unwrap();
+        assert_eq!(report.baseline_connected_pairs, 6);
+        assert_eq!(report.scenario_connected_pairs, 6);
+        assert!(report.route_changes.is_empty());
+        assert!(report.affected_flight_plans.is_empty());
+    }
+
+    #[test]
+    fn test_close_vertiport() {
+        let report = evaluate_scenario_with(
+            &baseline(),
+            &[ScenarioChange::CloseVertiport("B".to_string())],
+            Distance::from_km(50.0),
+        )
+        .unwrap();
+        assert_eq!(report.scenario_connected_pairs, 0);
+        assert_eq!(report.route_changes.len(), 6);
+        assert!(report
+            .route_changes
+            .iter()
+            .all(|change| change.scenario_distance_km.is_none()));
+        assert_eq!(
+            report.affected_flight_plans,
+            vec![
+                AffectedFlightPlan {
+                    flight_plan_id: "fp1".to_string(),
+                    impact: FlightPlanImpact::RouteUnavailable,
+                },
+                AffectedFlightPlan {
+                    flight_plan_id: "fp2".to_string(),
+                    impact: FlightPlanImpact::VertiportClosed("B".to_string()),
+                },
+                AffectedFlightPlan {
+                    flight_plan_id: "fp3".to_string(),
+                    impact: FlightPlanImpact::VertiportClosed("B".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_add_vertiport_and_remove_vehicles() {
+        let report = evaluate_scenario_with(
+            &baseline(),
+            &[
+                ScenarioChange::AddVertiport(node("D", 1.2)),
+                ScenarioChange::RemoveVehicles(vec!["v1".to_string(), "v3".to_string()]),
+            ],
+            Distance::from_km(50.0),
+        )
+        .unwrap();
+        assert_eq!(report.scenario_connected_pairs, 12);
+        assert_eq!(report.route_changes.len(), 6);
+        assert!(report
+            .route_changes
+            .iter()
+            .all(|change| change.baseline_distance_km.is_none()
+                && (change.from_vertiport_id == "D" || change.to_vertiport_id == "D")));
+        let affected: Vec<&str> = report
+            .affected_flight_plans
+            .iter()
+            .map(|affected| affected.flight_plan_id.as_str())
+            .collect();
+        assert_eq!(affected, vec!["fp1", "fp3"]);
+    }
+
+    #[test]
+    fn test_unknown_references() {
+        let baseline = baseline();
+        assert!(evaluate_scenario_with(
+            &baseline,
+            &[ScenarioChange::CloseVertiport("X".to_string())],
+            Distance::from_km(50.0)
+        )
+        .is_err());
+        assert!(evaluate_scenario_with(
+            &baseline,
+            &[ScenarioChange::AddVertiport(node("A", 0.0))],
+            Distance::from_km(50.0)
+        )
+        .is_err());
+        assert!(evaluate_scenario_with(
+            &baseline,
+            &[ScenarioChange::RemoveVehicles(vec!["v9".to_string()])],
+            Distance::from_km(50.0)
+        )
+        .is_err());
+    }
+}
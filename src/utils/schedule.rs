@@ -1,7 +1,7 @@
 //! Provides calendar/scheduling utilities
 //! Parses and serializes string RRULEs with duration and provides api to query if time slot is available.
 
-use chrono::{DateTime, Duration};
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone};
 use chrono_tz::Tz as ChronoTz;
 use iso8601_duration::Duration as DurationParser;
 pub use rrule::{RRuleSet, Tz};
@@ -9,7 +9,7 @@ use std::fmt::Display;
 use std::str::FromStr;
 
 /// formats chrono::DateTime to string in format: `YYYYMMDDThhmmssZ`, e.g. 20221026T133000Z
-fn datetime_to_ical_format(dt: &DateTime<Tz>) -> String {
+pub(crate) fn datetime_to_ical_format(dt: &DateTime<Tz>) -> String {
     debug!("datetime_to_ical_format: {:?}", dt);
     let mut tz_prefix = String::new();
     let mut tz_postfix = String::new();
@@ -32,6 +32,115 @@ fn datetime_to_ical_format(dt: &DateTime<Tz>) -> String {
     format!("{}{}{}", tz_prefix, dt, tz_postfix)
 }
 
+/// formats a date-time property, e.g. `DTSTART:20221020T180000Z` or
+/// `DTSTART;TZID=America/New_York:20221020T080000`
+pub(crate) fn datetime_property(name: &str, dt: &DateTime<Tz>) -> String {
+    let value = datetime_to_ical_format(dt);
+    if value.starts_with(';') {
+        format!("{}{}", name, value)
+    } else {
+        format!("{}:{}", name, value)
+    }
+}
+
+/// parses string in format: `YYYYMMDDThhmmssZ`, e.g. 20221026T133000Z into UTC chrono::DateTime
+/// A floating time without the `Z` suffix, e.g. 20221026T093000, is in the given timezone;
+/// it is rejected without a timezone or if it is skipped by a DST shift
+fn datetime_from_ical_format(dt: &str, timezone: Option<&Tz>) -> Option<DateTime<Tz>> {
+    let dt = dt.trim();
+    if let Some(utc) = dt.strip_suffix('Z') {
+        let naive = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some(Tz::UTC.from_utc_datetime(&naive));
+    }
+    let naive = NaiveDateTime::parse_from_str(dt, "%Y%m%dT%H%M%S").ok()?;
+    timezone?.from_local_datetime(&naive).earliest()
+}
+
+/// Calendar line naming the timezone of the floating local times of the calendar, e.g.
+///   "X-WR-TIMEZONE:America/New_York"
+const TIMEZONE_PROPERTY: &str = "X-WR-TIMEZONE:";
+
+/// Longest duration of a blocking event, so date arithmetic with it can't overflow
+const MAX_EVENT_DURATION_DAYS: f32 = 3660.0;
+
+/// Parses an iso8601 duration string (e.g. `PT1H30M`) into a chrono Duration
+/// Durations longer than [`MAX_EVENT_DURATION_DAYS`] are rejected
+pub(crate) fn parse_duration(duration: &str) -> Option<Duration> {
+    let d = DurationParser::parse(duration).ok()?;
+    let days = d.day + d.hour / 24.0 + d.minute / 1440.0 + d.second / 86400.0;
+    if !(0.0..=MAX_EVENT_DURATION_DAYS).contains(&days) {
+        return None;
+    }
+    Some(
+        Duration::days(d.day as i64)
+            + Duration::hours(d.hour as i64)
+            + Duration::minutes(d.minute as i64)
+            + Duration::seconds(d.second as i64),
+    )
+}
+
+/// A time interval from `start` (inclusive) to `end` (exclusive)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeSlot {
+    /// Start of the time slot
+    pub start: DateTime<Tz>,
+    /// End of the time slot
+    pub end: DateTime<Tz>,
+}
+
+impl TimeSlot {
+    /// Returns true if two time slots overlap (touching slots are not considered overlapping)
+    pub fn overlaps(&self, other: &TimeSlot) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
+/// Sorts time slots and merges the overlapping or touching ones, dropping empty slots
+pub fn normalize_slots(mut slots: Vec<TimeSlot>) -> Vec<TimeSlot> {
+    slots.retain(|slot| slot.start < slot.end);
+    slots.sort_by_key(|slot| slot.start);
+    let mut normalized: Vec<TimeSlot> = Vec::with_capacity(slots.len());
+    for slot in slots {
+        match normalized.last_mut() {
+            Some(last) if slot.start <= last.end => {
+                if slot.end > last.end {
+                    last.end = slot.end;
+                }
+            }
+            _ => normalized.push(slot),
+        }
+    }
+    normalized
+}
+
+/// Removes all `busy` slots from the `free` slots
+/// Both inputs are normalized first; the result is normalized as well
+pub fn subtract_slots(free: Vec<TimeSlot>, busy: Vec<TimeSlot>) -> Vec<TimeSlot> {
+    let busy = normalize_slots(busy);
+    let mut result = vec![];
+    for slot in normalize_slots(free) {
+        let mut start = slot.start;
+        for block in busy.iter().filter(|block| block.overlaps(&slot)) {
+            if block.start > start {
+                result.push(TimeSlot {
+                    start,
+                    end: block.start,
+                });
+            }
+            if block.end > start {
+                start = block.end;
+            }
+        }
+        if start < slot.end {
+            result.push(TimeSlot {
+                start,
+                end: slot.end,
+            });
+        }
+    }
+    result
+}
+
 /// Wraps rruleset and their duration
 #[derive(Debug)]
 pub struct RecurrentEvent {
@@ -45,6 +154,8 @@ pub struct RecurrentEvent {
 pub struct Calendar {
     ///Vec of rrulesets and their duration
     pub events: Vec<RecurrentEvent>,
+    ///Ad-hoc blocked time slots, e.g. a pad closed for inspection on a specific day
+    pub blackouts: Vec<TimeSlot>,
 }
 
 impl FromStr for Calendar {
@@ -56,20 +167,97 @@ impl FromStr for Calendar {
     /// Duration has to be the last part of the RRULE_SET header after DTSTART e.g.
     ///   "DTSTART:20221020T180000Z;DURATION:PT1H" not "DURATION:PT1H;DTSTART:20221020T180000Z"
     /// Duration is in ISO8601 format (`iso8601_duration` crate)
+    /// Local operating hours can be given with a timezone, recurrences then follow DST shifts e.g.
+    ///   "DTSTART;TZID=America/New_York:20221020T200000;DURATION:PT12H"
+    /// A `X-WR-TIMEZONE` line sets the timezone of all the floating local times (without `Z`
+    /// suffix or `TZID`) of the calendar, e.g. the timezone of a vertiport
+    ///   "X-WR-TIMEZONE:America/New_York"
+    /// EXDATE and EXRULE lines lift the blocking event for the given dates
+    /// Ad-hoc blackouts are written as separate lines with start and end of the period, in UTC
+    /// or in the timezone of the calendar, e.g.
+    ///   "BLACKOUT:20221025T090000Z/20221025T120000Z"
     fn from_str(calendar_str: &str) -> Result<Self, Self::Err> {
+        Calendar::parse(calendar_str, None)
+    }
+}
+
+/// Pins a floating date-time property (no `Z` suffix and no `TZID`) to the given timezone, e.g.
+///   "DTSTART:20221020T200000" -> "DTSTART;TZID=America/New_York:20221020T200000"
+fn localize_property(line: &str, timezone: &Tz) -> String {
+    let Tz::Tz(tz) = timezone else {
+        return line.to_string();
+    };
+    for name in ["DTSTART", "RDATE", "EXDATE"] {
+        let Some(value) = line
+            .strip_prefix(name)
+            .and_then(|rest| rest.strip_prefix(':'))
+        else {
+            continue;
+        };
+        if value.ends_with('Z') {
+            break;
+        }
+        return format!("{};TZID={}:{}", name, tz.name(), value);
+    }
+    line.to_string()
+}
+
+impl Calendar {
+    /// Parses a calendar whose floating local times (without `Z` suffix or `TZID`)
+    /// are in the given timezone, e.g. operating hours of a vertiport in local time
+    /// Times with an explicit `Z` suffix or `TZID` are kept as they are, and a
+    /// `X-WR-TIMEZONE` line of the calendar takes precedence over the given timezone
+    #[allow(clippy::result_unit_err)]
+    pub fn from_str_with_timezone(calendar_str: &str, timezone: Tz) -> Result<Self, ()> {
+        Calendar::parse(calendar_str, Some(timezone))
+    }
+
+    fn parse(calendar_str: &str, timezone: Option<Tz>) -> Result<Self, ()> {
         debug!("Parsing calendar: {}", calendar_str);
-        let rrule_sets: Vec<&str> = calendar_str
-            .split("DTSTART:")
+        let lines: Vec<&str> = calendar_str
+            .split('\n')
+            .map(|s| s.trim())
             .filter(|s| !s.is_empty())
             .collect();
+        let mut timezone = timezone;
+        for name in lines
+            .iter()
+            .filter_map(|line| line.strip_prefix(TIMEZONE_PROPERTY))
+        {
+            let Ok(tz) = ChronoTz::from_str(name.trim()) else {
+                error!("Invalid calendar timezone: {}", name);
+                return Err(());
+            };
+            timezone = Some(Tz::Tz(tz));
+        }
+        let mut blackouts: Vec<TimeSlot> = Vec::new();
+        let mut rrule_sets: Vec<Vec<String>> = Vec::new();
+        for line in lines {
+            if line.starts_with(TIMEZONE_PROPERTY) {
+                continue;
+            }
+            if let Some(period) = line.strip_prefix("BLACKOUT:") {
+                blackouts.push(parse_blackout(period, timezone.as_ref())?);
+                continue;
+            }
+            let line = match &timezone {
+                Some(timezone) => localize_property(line, timezone),
+                None => line.to_string(),
+            };
+            if line.starts_with("DTSTART") {
+                rrule_sets.push(vec![line]);
+                continue;
+            }
+            let Some(rrule_set) = rrule_sets.last_mut() else {
+                error!("Calendar does not start with DTSTART: {}", line);
+                return Err(());
+            };
+            rrule_set.push(line);
+        }
         debug!("rrule_sets: {:?}", rrule_sets);
         let mut recurrent_events: Vec<RecurrentEvent> = Vec::new();
-        for rrule_set_str in rrule_sets {
-            debug!("rrule_set_str: {}", rrule_set_str);
-            let rrules_with_header: Vec<&str> = rrule_set_str
-                .split('\n')
-                .filter(|s| !s.is_empty())
-                .collect();
+        for rrules_with_header in rrule_sets {
+            debug!("rrules_with_header: {:?}", rrules_with_header);
             if rrules_with_header.len() < 2 {
                 error!(
                     "Invalid rrule with header length: {}",
@@ -77,7 +265,7 @@ impl FromStr for Calendar {
                 );
                 return Err(());
             }
-            let header = rrules_with_header[0];
+            let header = &rrules_with_header[0];
             let rrules = &rrules_with_header[1..];
             let header_parts: Vec<&str> = header
                 .split(";DURATION:")
@@ -89,7 +277,11 @@ impl FromStr for Calendar {
             }
             let dtstart = header_parts[0];
             let duration = header_parts[1];
-            let str = "DTSTART:".to_owned() + dtstart + "\n" + rrules.join("\n").as_str();
+            if parse_duration(duration).is_none() {
+                error!("Invalid event duration: {}", duration);
+                return Err(());
+            }
+            let str = dtstart.to_owned() + "\n" + rrules.join("\n").as_str();
             let rrset_res = RRuleSet::from_str(&str);
 
             let Ok(rrule_set) = rrset_res else {
@@ -105,10 +297,32 @@ impl FromStr for Calendar {
         debug!("Parsed calendar: {:?}", recurrent_events);
         Ok(Calendar {
             events: recurrent_events,
+            blackouts,
         })
     }
 }
 
+/// Parses a blackout period with UTC start and end, e.g. `20221025T090000Z/20221025T120000Z`,
+/// or with floating start and end in the timezone of the calendar
+fn parse_blackout(period: &str, timezone: Option<&Tz>) -> Result<TimeSlot, ()> {
+    let Some((start, end)) = period.split_once('/') else {
+        error!("Invalid blackout period: {}", period);
+        return Err(());
+    };
+    let (Some(start), Some(end)) = (
+        datetime_from_ical_format(start, timezone),
+        datetime_from_ical_format(end, timezone),
+    ) else {
+        error!("Invalid blackout period: {}", period);
+        return Err(());
+    };
+    if start >= end {
+        error!("Blackout period ends before it starts: {}", period);
+        return Err(());
+    }
+    Ok(TimeSlot { start, end })
+}
+
 impl Display for Calendar {
     /// Formats `Calendar` into multiline string which can be stored in the database
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -116,8 +330,8 @@ impl Display for Calendar {
         for event in &self.events {
             writeln!(
                 f,
-                "DTSTART:{};DURATION:{}",
-                datetime_to_ical_format(event.rrule_set.get_dt_start()),
+                "{};DURATION:{}",
+                datetime_property("DTSTART", event.rrule_set.get_dt_start()),
                 &event.duration
             )
             .expect(&err_msg);
@@ -125,9 +339,24 @@ impl Display for Calendar {
                 writeln!(f, "RRULE:{}", rrule).expect(&err_msg);
             }
             for rdate in event.rrule_set.get_rdate() {
-                writeln!(f, "RDATE:{}", datetime_to_ical_format(rdate)).expect(&err_msg);
+                writeln!(f, "{}", datetime_property("RDATE", rdate)).expect(&err_msg);
+            }
+            for exrule in event.rrule_set.get_exrule() {
+                writeln!(f, "EXRULE:{}", exrule).expect(&err_msg);
+            }
+            for exdate in event.rrule_set.get_exdate() {
+                writeln!(f, "{}", datetime_property("EXDATE", exdate)).expect(&err_msg);
             }
         }
+        for blackout in &self.blackouts {
+            writeln!(
+                f,
+                "BLACKOUT:{}/{}",
+                datetime_to_ical_format(&blackout.start.with_timezone(&Tz::UTC)),
+                datetime_to_ical_format(&blackout.end.with_timezone(&Tz::UTC))
+            )
+            .expect(&err_msg);
+        }
         Ok(())
     }
 }
@@ -166,8 +395,24 @@ impl Calendar {
 
         debug!("Adjusted start_time: {}", start_time);
         debug!("Adjusted end_time: {}", end_time);
+        let requested_slot = TimeSlot {
+            start: start_time,
+            end: end_time,
+        };
+        if self
+            .blackouts
+            .iter()
+            .any(|blackout| blackout.overlaps(&requested_slot))
+        {
+            debug!("Time slot is blacked out");
+            return false;
+        }
         for event in &self.events {
             let duration = &event.duration;
+            // do the math in local time of the event so the recurrences follow DST shifts
+            let timezone = event.rrule_set.get_dt_start().timezone();
+            let start_time = start_time.with_timezone(&timezone);
+            let end_time = end_time.with_timezone(&timezone);
             // check standard rrule time - if event(block) start time is between two dates,
             // then it will be found and time slot will be marked as not available
             let (events, _) = &event
@@ -180,12 +425,11 @@ impl Calendar {
                 debug!("Time slot is not available");
                 return false;
             }
-            let d = DurationParser::parse(duration).expect("Failed to parse duration");
-            let adjusted_start_time = start_time
-                - Duration::days(d.day as i64)
-                - Duration::hours(d.hour as i64)
-                - Duration::minutes(d.minute as i64)
-                - Duration::seconds(d.second as i64);
+            let Some(d) = parse_duration(duration) else {
+                error!("Invalid event duration: {}", duration);
+                return false;
+            };
+            let adjusted_start_time = start_time - d;
             // here we check if event(block) start time + duration is between two dates,
             // then it will be found and time slot will be marked as not available
             let (events, _) = &event
@@ -203,12 +447,120 @@ impl Calendar {
         debug!("Time slot is available");
         true
     }
+
+    /// Returns the blocking events of the calendar which overlap the given time window,
+    /// clipped to the window and normalized
+    /// An event with an invalid duration blocks the whole window
+    /// * `start_time` - start of the time window
+    /// * `end_time`   - end of the time window
+    pub fn busy_slots(&self, start_time: DateTime<Tz>, end_time: DateTime<Tz>) -> Vec<TimeSlot> {
+        let mut busy: Vec<TimeSlot> = self
+            .blackouts
+            .iter()
+            .map(|blackout| TimeSlot {
+                start: blackout.start.max(start_time),
+                end: blackout.end.min(end_time),
+            })
+            .collect();
+        for event in &self.events {
+            let Some(duration) = parse_duration(&event.duration) else {
+                error!("Invalid event duration: {}", event.duration);
+                busy.push(TimeSlot {
+                    start: start_time,
+                    end: end_time,
+                });
+                continue;
+            };
+            // events starting up to `duration` before the window can still overlap it
+            let timezone = event.rrule_set.get_dt_start().timezone();
+            let (occurrences, _) = event
+                .rrule_set
+                .clone()
+                .after((start_time - duration).with_timezone(&timezone))
+                .before(end_time.with_timezone(&timezone))
+                .all(u16::MAX);
+            for occurrence in occurrences {
+                busy.push(TimeSlot {
+                    start: occurrence.max(start_time),
+                    end: (occurrence + duration).min(end_time),
+                });
+            }
+        }
+        normalize_slots(busy)
+    }
+
+    /// Returns the timezone the calendar events are defined in (UTC if there are no events)
+    pub fn timezone(&self) -> Tz {
+        self.events
+            .first()
+            .map(|event| event.rrule_set.get_dt_start().timezone())
+            .unwrap_or(Tz::UTC)
+    }
+
+    /// Blocks the calendar for an ad-hoc period, e.g. maintenance or inspection
+    /// * `start_time` - start of the blackout
+    /// * `end_time`   - end of the blackout
+    pub fn add_blackout(&mut self, start_time: DateTime<Tz>, end_time: DateTime<Tz>) {
+        self.blackouts.push(TimeSlot {
+            start: start_time,
+            end: end_time,
+        });
+    }
+
+    /// Returns the normalized list of free time slots within the given time window
+    /// * `start_time` - start of the time window
+    /// * `end_time`   - end of the time window
+    pub fn free_slots(&self, start_time: DateTime<Tz>, end_time: DateTime<Tz>) -> Vec<TimeSlot> {
+        subtract_slots(
+            vec![TimeSlot {
+                start: start_time,
+                end: end_time,
+            }],
+            self.busy_slots(start_time, end_time),
+        )
+    }
+
+    /// Returns the normalized list of time slots within the given time window
+    /// where both this and the `other` calendar are available
+    /// e.g. joint availability of a vehicle and a vertiport
+    /// * `other`      - calendar to intersect with
+    /// * `start_time` - start of the time window
+    /// * `end_time`   - end of the time window
+    pub fn intersect(
+        &self,
+        other: &Calendar,
+        start_time: DateTime<Tz>,
+        end_time: DateTime<Tz>,
+    ) -> Vec<TimeSlot> {
+        subtract_slots(
+            self.free_slots(start_time, end_time),
+            other.busy_slots(start_time, end_time),
+        )
+    }
+
+    /// Returns the normalized list of free time slots within the given time window
+    /// after removing the `busy_intervals` (e.g. existing flight plans)
+    /// * `busy_intervals` - additional blocked time slots, need not be sorted
+    /// * `start_time`     - start of the time window
+    /// * `end_time`       - end of the time window
+    pub fn subtract(
+        &self,
+        busy_intervals: &[TimeSlot],
+        start_time: DateTime<Tz>,
+        end_time: DateTime<Tz>,
+    ) -> Vec<TimeSlot> {
+        subtract_slots(
+            self.free_slots(start_time, end_time),
+            busy_intervals.to_vec(),
+        )
+    }
 }
 
 #[cfg(test)]
 mod calendar_tests {
-    use super::Calendar;
+    use super::{Calendar, TimeSlot};
     use chrono::TimeZone;
+    use chrono_tz::Tz as ChronoTz;
     use rrule::Tz;
     use std::str::FromStr;
 
@@ -225,6 +577,26 @@ mod calendar_tests {
     DTSTART:20221026T133000Z;DURATION:PT3H\n\
     RDATE:20221026T133000Z";
 
+    const _WITH_TUESDAY_EXCEPTION: &str = "\n\
+    DTSTART:20221020T120000Z;DURATION:PT1H\n\
+    RRULE:FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR\n\
+    EXDATE:20221025T120000Z";
+
+    const _WITH_INSPECTION_BLACKOUT: &str = "\n\
+    BLACKOUT:20221025T090000Z/20221025T110000Z";
+
+    const CAL_NEW_YORK_8AM_8PM: &str =
+        "DTSTART;TZID=America/New_York:20221020T200000;DURATION:PT12H\n\
+    RRULE:FREQ=DAILY";
+
+    const CAL_FLOATING_8AM_8PM: &str = "DTSTART:20221020T200000;DURATION:PT12H\n\
+    RRULE:FREQ=DAILY";
+
+    const CAL_LOCAL_8AM_8PM: &str = "X-WR-TIMEZONE:America/New_York\n\
+    DTSTART:20221020T200000;DURATION:PT12H\n\
+    RRULE:FREQ=DAILY\n\
+    BLACKOUT:20231105T090000/20231105T120000";
+
     const INVALID_CALENDAR: &str = "DURATION:PT3H;DTSTART:20221026T133000Z;\n\
     RRULE:FREQ=WEEKLY;BYDAY=SA,SU";
 
@@ -319,6 +691,307 @@ mod calendar_tests {
         assert_eq!(calendar.events[0].duration, "PT14H");
     }
 
+    #[test]
+    fn test_free_slots() {
+        let calendar =
+            Calendar::from_str(&(CAL_WORKDAYS_8AM_6PM.to_owned() + _WITH_1HR_DAILY_BREAK)).unwrap();
+        let start = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 0, 0, 0).unwrap();
+        let end = Tz::UTC.with_ymd_and_hms(2022, 10, 26, 0, 0, 0).unwrap();
+        let free = calendar.free_slots(start, end);
+        assert_eq!(
+            free,
+            vec![
+                TimeSlot {
+                    start: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap(),
+                    end: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 12, 0, 0).unwrap(),
+                },
+                TimeSlot {
+                    start: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 13, 0, 0).unwrap(),
+                    end: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 18, 0, 0).unwrap(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_intersect_calendars() {
+        let vertiport = Calendar::from_str(CAL_WORKDAYS_8AM_6PM).unwrap();
+        let vehicle =
+            Calendar::from_str(&(CAL_WORKDAYS_8AM_6PM.to_owned() + _WITH_ONE_OFF_BLOCK)).unwrap();
+        let start = Tz::UTC.with_ymd_and_hms(2022, 10, 26, 0, 0, 0).unwrap();
+        let end = Tz::UTC.with_ymd_and_hms(2022, 10, 27, 0, 0, 0).unwrap();
+        let free = vertiport.intersect(&vehicle, start, end);
+        assert_eq!(
+            free,
+            vec![
+                TimeSlot {
+                    start: Tz::UTC.with_ymd_and_hms(2022, 10, 26, 8, 0, 0).unwrap(),
+                    end: Tz::UTC.with_ymd_and_hms(2022, 10, 26, 13, 30, 0).unwrap(),
+                },
+                TimeSlot {
+                    start: Tz::UTC.with_ymd_and_hms(2022, 10, 26, 16, 30, 0).unwrap(),
+                    end: Tz::UTC.with_ymd_and_hms(2022, 10, 26, 18, 0, 0).unwrap(),
+                },
+            ]
+        );
+        assert_eq!(free, vehicle.intersect(&vertiport, start, end));
+    }
+
+    #[test]
+    fn test_subtract_busy_intervals() {
+        let calendar = Calendar::from_str(CAL_WORKDAYS_8AM_6PM).unwrap();
+        let start = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 0, 0, 0).unwrap();
+        let end = Tz::UTC.with_ymd_and_hms(2022, 10, 26, 0, 0, 0).unwrap();
+        // unsorted and overlapping flight blocks
+        let busy = vec![
+            TimeSlot {
+                start: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 10, 30, 0).unwrap(),
+                end: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 11, 0, 0).unwrap(),
+            },
+            TimeSlot {
+                start: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 10, 0, 0).unwrap(),
+                end: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 10, 45, 0).unwrap(),
+            },
+            TimeSlot {
+                start: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 17, 30, 0).unwrap(),
+                end: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 19, 0, 0).unwrap(),
+            },
+        ];
+        let free = calendar.subtract(&busy, start, end);
+        assert_eq!(
+            free,
+            vec![
+                TimeSlot {
+                    start: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap(),
+                    end: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 10, 0, 0).unwrap(),
+                },
+                TimeSlot {
+                    start: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 11, 0, 0).unwrap(),
+                    end: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 17, 30, 0).unwrap(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_calendar_with_exception_date() {
+        let calendar =
+            Calendar::from_str(&(CAL_WORKDAYS_8AM_6PM.to_owned() + _WITH_TUESDAY_EXCEPTION))
+                .unwrap();
+
+        // daily break is lifted on Tuesday 25th
+        let mut start = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 11, 30, 0).unwrap();
+        let mut end = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 12, 30, 0).unwrap();
+        assert_eq!(calendar.is_available_between(start, end), true);
+
+        start = Tz::UTC.with_ymd_and_hms(2022, 10, 26, 11, 30, 0).unwrap();
+        end = Tz::UTC.with_ymd_and_hms(2022, 10, 26, 12, 30, 0).unwrap();
+        assert_eq!(calendar.is_available_between(start, end), false);
+    }
+
+    #[test]
+    fn test_calendar_with_blackout() {
+        let mut calendar =
+            Calendar::from_str(&(CAL_WORKDAYS_8AM_6PM.to_owned() + _WITH_INSPECTION_BLACKOUT))
+                .unwrap();
+        assert_eq!(calendar.blackouts.len(), 1);
+
+        let mut start = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 10, 30, 0).unwrap();
+        let mut end = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 11, 30, 0).unwrap();
+        assert_eq!(calendar.is_available_between(start, end), false);
+
+        start = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 11, 0, 0).unwrap();
+        end = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 12, 0, 0).unwrap();
+        assert_eq!(calendar.is_available_between(start, end), true);
+
+        calendar.add_blackout(
+            Tz::UTC.with_ymd_and_hms(2022, 10, 25, 11, 45, 0).unwrap(),
+            Tz::UTC.with_ymd_and_hms(2022, 10, 25, 13, 0, 0).unwrap(),
+        );
+        assert_eq!(calendar.is_available_between(start, end), false);
+
+        start = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 0, 0, 0).unwrap();
+        end = Tz::UTC.with_ymd_and_hms(2022, 10, 26, 0, 0, 0).unwrap();
+        assert_eq!(
+            calendar.free_slots(start, end),
+            vec![
+                TimeSlot {
+                    start: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap(),
+                    end: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 9, 0, 0).unwrap(),
+                },
+                TimeSlot {
+                    start: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 11, 0, 0).unwrap(),
+                    end: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 11, 45, 0).unwrap(),
+                },
+                TimeSlot {
+                    start: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 13, 0, 0).unwrap(),
+                    end: Tz::UTC.with_ymd_and_hms(2022, 10, 25, 18, 0, 0).unwrap(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_save_and_load_exceptions_and_blackouts() {
+        let orig_cal_str = &(CAL_WORKDAYS_8AM_6PM.to_owned()
+            + _WITH_TUESDAY_EXCEPTION
+            + _WITH_INSPECTION_BLACKOUT);
+        let calendar = Calendar::from_str(orig_cal_str).unwrap();
+        let cal_str = calendar.to_string();
+        assert!(cal_str.contains("EXDATE:20221025T120000Z"));
+        assert!(cal_str.contains("BLACKOUT:20221025T090000Z/20221025T110000Z"));
+        let calendar = Calendar::from_str(&cal_str).unwrap();
+        assert_eq!(calendar.events.len(), 3);
+        assert_eq!(calendar.blackouts.len(), 1);
+        assert_eq!(calendar.events[2].rrule_set.get_exdate().len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_blackout() {
+        let calendar = Calendar::from_str(
+            &(CAL_WORKDAYS_8AM_6PM.to_owned() + "\nBLACKOUT:20221025T110000Z/20221025T090000Z"),
+        );
+        assert!(calendar.is_err());
+    }
+
+    #[test]
+    fn test_invalid_duration() {
+        for duration in ["PT1X", "P99999999999D", "PT"] {
+            let calendar = Calendar::from_str(&format!(
+                "DTSTART:20221020T180000Z;DURATION:{}\nRRULE:FREQ=DAILY",
+                duration
+            ));
+            assert!(calendar.is_err(), "{} accepted", duration);
+        }
+    }
+
+    #[test]
+    fn test_local_time_across_dst() {
+        let calendar = Calendar::from_str(CAL_NEW_YORK_8AM_8PM).unwrap();
+        assert!(matches!(
+            calendar.timezone(),
+            Tz::Tz(ChronoTz::America__New_York)
+        ));
+
+        // 08:30 - 09:30 EDT (UTC-4) is open in summer
+        let mut start = Tz::UTC.with_ymd_and_hms(2023, 7, 12, 12, 30, 0).unwrap();
+        let mut end = Tz::UTC.with_ymd_and_hms(2023, 7, 12, 13, 30, 0).unwrap();
+        assert_eq!(calendar.is_available_between(start, end), true);
+
+        // the same UTC time is 07:30 - 08:30 EST (UTC-5) in winter
+        start = Tz::UTC.with_ymd_and_hms(2023, 1, 11, 12, 30, 0).unwrap();
+        end = Tz::UTC.with_ymd_and_hms(2023, 1, 11, 13, 30, 0).unwrap();
+        assert_eq!(calendar.is_available_between(start, end), false);
+
+        start = Tz::UTC.with_ymd_and_hms(2023, 1, 11, 13, 0, 0).unwrap();
+        end = Tz::UTC.with_ymd_and_hms(2023, 1, 11, 14, 0, 0).unwrap();
+        assert_eq!(calendar.is_available_between(start, end), true);
+
+        let free = calendar.free_slots(
+            Tz::UTC.with_ymd_and_hms(2023, 1, 11, 0, 0, 0).unwrap(),
+            Tz::UTC.with_ymd_and_hms(2023, 1, 12, 0, 0, 0).unwrap(),
+        );
+        assert_eq!(
+            free,
+            vec![
+                TimeSlot {
+                    start: Tz::UTC.with_ymd_and_hms(2023, 1, 11, 0, 0, 0).unwrap(),
+                    end: Tz::UTC.with_ymd_and_hms(2023, 1, 11, 1, 0, 0).unwrap(),
+                },
+                TimeSlot {
+                    start: Tz::UTC.with_ymd_and_hms(2023, 1, 11, 13, 0, 0).unwrap(),
+                    end: Tz::UTC.with_ymd_and_hms(2023, 1, 12, 0, 0, 0).unwrap(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_floating_time_with_timezone() {
+        let calendar =
+            Calendar::from_str_with_timezone(CAL_FLOATING_8AM_8PM, Tz::America__New_York).unwrap();
+        assert!(matches!(
+            calendar.timezone(),
+            Tz::Tz(ChronoTz::America__New_York)
+        ));
+        assert_eq!(
+            calendar.to_string(),
+            Calendar::from_str(CAL_NEW_YORK_8AM_8PM)
+                .unwrap()
+                .to_string()
+        );
+
+        // UTC times are kept as they are
+        let calendar =
+            Calendar::from_str_with_timezone(CAL_WORKDAYS_8AM_6PM, Tz::America__New_York).unwrap();
+        assert!(matches!(calendar.timezone(), Tz::Tz(ChronoTz::UTC)));
+    }
+
+    #[test]
+    fn test_calendar_timezone_line() {
+        let calendar = Calendar::from_str(CAL_LOCAL_8AM_8PM).unwrap();
+        assert!(matches!(
+            calendar.timezone(),
+            Tz::Tz(ChronoTz::America__New_York)
+        ));
+
+        // 08:30 - 09:30 local time is open before and after the end of DST
+        let mut start = Tz::UTC.with_ymd_and_hms(2023, 11, 4, 12, 30, 0).unwrap();
+        let mut end = Tz::UTC.with_ymd_and_hms(2023, 11, 4, 13, 30, 0).unwrap();
+        assert_eq!(calendar.is_available_between(start, end), true);
+        start = Tz::UTC.with_ymd_and_hms(2023, 11, 6, 12, 30, 0).unwrap();
+        end = Tz::UTC.with_ymd_and_hms(2023, 11, 6, 13, 30, 0).unwrap();
+        assert_eq!(calendar.is_available_between(start, end), false);
+        start = Tz::UTC.with_ymd_and_hms(2023, 11, 6, 13, 30, 0).unwrap();
+        end = Tz::UTC.with_ymd_and_hms(2023, 11, 6, 14, 30, 0).unwrap();
+        assert_eq!(calendar.is_available_between(start, end), true);
+
+        // the blackout is in local time too, 09:00 EST on the day DST ends
+        assert_eq!(
+            calendar.blackouts,
+            vec![TimeSlot {
+                start: Tz::UTC.with_ymd_and_hms(2023, 11, 5, 14, 0, 0).unwrap(),
+                end: Tz::UTC.with_ymd_and_hms(2023, 11, 5, 17, 0, 0).unwrap(),
+            }]
+        );
+        let reloaded = Calendar::from_str(&calendar.to_string()).unwrap();
+        assert_eq!(reloaded.to_string(), calendar.to_string());
+
+        // the line takes precedence over the timezone of the asset
+        let calendar =
+            Calendar::from_str_with_timezone(CAL_LOCAL_8AM_8PM, Tz::Europe__Berlin).unwrap();
+        assert!(matches!(
+            calendar.timezone(),
+            Tz::Tz(ChronoTz::America__New_York)
+        ));
+
+        // floating times need a timezone
+        assert!(Calendar::from_str("BLACKOUT:20231105T090000/20231105T120000").is_err());
+        assert!(Calendar::from_str(
+            &CAL_LOCAL_8AM_8PM.replace("America/New_York", "America/Gotham")
+        )
+        .is_err());
+        // 02:30 doesn't exist on the day DST starts
+        assert!(Calendar::from_str_with_timezone(
+            "BLACKOUT:20230312T023000/20230312T040000",
+            Tz::America__New_York
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_save_and_load_local_calendar() {
+        let calendar = Calendar::from_str(CAL_NEW_YORK_8AM_8PM).unwrap();
+        let cal_str = calendar.to_string();
+        assert!(cal_str.starts_with("DTSTART;TZID=America/New_York:20221020T200000;DURATION:PT12H"));
+        let calendar = Calendar::from_str(&cal_str).unwrap();
+        assert_eq!(calendar.events.len(), 1);
+        assert!(matches!(
+            calendar.timezone(),
+            Tz::Tz(ChronoTz::America__New_York)
+        ));
+    }
+
     #[test]
     #[should_panic]
     fn test_invalid_input() {
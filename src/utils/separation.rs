@@ -0,0 +1,389 @@
+//! Separation minima between concurrent flights.
+//!
+//! Flights are assumed to fly their routes as estimated by
+//! [`position`](crate::position): on the ground during loading and takeoff,
+//! then at a constant speed until landing. Two airborne flights lose
+//! separation when they are both laterally and vertically closer than the
+//! configured [`SeparationMinima`]. Once minima are set with
+//! [`set_separation_minima`],
+//! [`get_possible_flights`](crate::router_state::get_possible_flights)
+//! staggers the departure of a flight that would converge with an active one
+//! by up to [`SeparationMinima::max_stagger_minutes`].
+
+use chrono::DateTime;
+use rrule::Tz;
+
+use crate::amendment::PlanTimes;
+use crate::context::current_context;
+use crate::haversine;
+use crate::location::Location;
+use crate::position::interpolate_along_route;
+use crate::router_state::{
+    get_node_by_id, get_route, Aircraft, FlightPlan, RouteQuery, LANDING_AND_UNLOADING_TIME_MIN,
+    LOADING_AND_TAKEOFF_TIME_MIN,
+};
+
+/// Interval between the positions compared for separation
+const SEPARATION_SAMPLE_SECONDS: i64 = 15;
+/// Delay added to a departure at each staggering attempt
+const STAGGER_STEP_MINUTES: i64 = 1;
+
+/// Minimum distances between airborne flights
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeparationMinima {
+    /// minimum horizontal distance
+    pub lateral_km: f32,
+    /// minimum difference of altitude
+    pub vertical_meters: f32,
+    /// max delay of a departure to keep separated
+    pub max_stagger_minutes: i64,
+}
+
+impl Default for SeparationMinima {
+    fn default() -> Self {
+        SeparationMinima {
+            lateral_km: 0.5,
+            vertical_meters: 100.0,
+            // staggered departures stay before the next departure slot
+            max_stagger_minutes: 4,
+        }
+    }
+}
+
+/// Route of a flight over time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trajectory {
+    /// id of the flight plan, empty for a draft
+    pub flight_plan_id: String,
+    /// locations along the route
+    pub route: Vec<Location>,
+    /// departure time in seconds since epoch, including loading and takeoff
+    pub departure: i64,
+    /// arrival time in seconds since epoch, including landing and unloading
+    pub arrival: i64,
+}
+
+impl Trajectory {
+    /// Start and end of the time spent flying the route
+    pub fn airborne(&self) -> (i64, i64) {
+        let from = self.departure + (LOADING_AND_TAKEOFF_TIME_MIN * 60.0) as i64;
+        let to = self.arrival - (LANDING_AND_UNLOADING_TIME_MIN * 60.0) as i64;
+        if to <= from {
+            // too short to account for takeoff and landing
+            return (self.departure, self.arrival);
+        }
+        (from, to)
+    }
+
+    /// Estimated location at `timestamp`, None if the flight is not airborne
+    pub fn location_at(&self, timestamp: i64) -> Option<Location> {
+        let (from, to) = self.airborne();
+        if timestamp < from || timestamp > to {
+            return None;
+        }
+        let progress = if to > from {
+            (timestamp - from) as f32 / (to - from) as f32
+        } else {
+            1.0
+        };
+        interpolate_along_route(&self.route, progress)
+    }
+
+    /// Same trajectory departing `minutes` later
+    pub fn delayed(&self, minutes: i64) -> Trajectory {
+        Trajectory {
+            departure: self.departure + minutes * 60,
+            arrival: self.arrival + minutes * 60,
+            ..self.clone()
+        }
+    }
+}
+
+/// First time two flights are closer than the minima
+///
+/// # Returns
+/// The time in seconds since epoch, None if the flights keep separated
+pub fn first_loss_of_separation(
+    minima: &SeparationMinima,
+    a: &Trajectory,
+    b: &Trajectory,
+) -> Option<i64> {
+    let (a_from, a_to) = a.airborne();
+    let (b_from, b_to) = b.airborne();
+    let (from, to) = (a_from.max(b_from), a_to.min(b_to));
+    if from > to {
+        return None;
+    }
+    (from..=to)
+        .step_by(SEPARATION_SAMPLE_SECONDS as usize)
+        .chain(std::iter::once(to))
+        .find(|timestamp| {
+            let (Some(a), Some(b)) = (a.location_at(*timestamp), b.location_at(*timestamp)) else {
+                return false;
+            };
+            haversine::distance(&a, &b) < minima.lateral_km
+                && (a.altitude_meters.into_inner() - b.altitude_meters.into_inner()).abs()
+                    < minima.vertical_meters
+        })
+}
+
+/// Smallest delay of a flight keeping it separated from the active flights
+///
+/// # Arguments
+/// * `minima` - Separation to keep
+/// * `trajectory` - Flight at its requested departure time
+/// * `active` - Flights already scheduled
+/// * `max_delay_minutes` - Max delay of the departure
+///
+/// # Returns
+/// The delay in minutes, None if every delay up to the max loses separation
+pub fn find_separated_delay_minutes(
+    minima: &SeparationMinima,
+    trajectory: &Trajectory,
+    active: &[Trajectory],
+    max_delay_minutes: i64,
+) -> Option<i64> {
+    (0..=max_delay_minutes.max(0))
+        .step_by(STAGGER_STEP_MINUTES as usize)
+        .find(|delay| {
+            let delayed = trajectory.delayed(*delay);
+            active
+                .iter()
+                .all(|other| first_loss_of_separation(minima, &delayed, other).is_none())
+        })
+}
+
+/// Trajectories of the flight plans flown between `start` and `end`
+///
+/// # Arguments
+/// * `flight_plans` - Scheduled flight plans
+/// * `start` - Start of the time window
+/// * `end` - End of the time window
+/// * `route` - Route between two vertiports by id
+pub fn get_active_trajectories_with<R>(
+    flight_plans: &[FlightPlan],
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+    route: R,
+) -> Vec<Trajectory>
+where
+    R: Fn(&str, &str) -> Option<Vec<Location>>,
+{
+    flight_plans
+        .iter()
+        .filter_map(|flight_plan| PlanTimes::from_flight_plan(flight_plan).ok())
+        .filter(|plan| plan.departure < end.timestamp() && plan.arrival > start.timestamp())
+        .filter_map(|plan| {
+            let Some(locations) =
+                route(&plan.departure_vertiport_id, &plan.destination_vertiport_id)
+            else {
+                debug!("No route for flight plan {}", plan.id);
+                return None;
+            };
+            Some(Trajectory {
+                flight_plan_id: plan.id,
+                route: locations,
+                departure: plan.departure,
+                arrival: plan.arrival,
+            })
+        })
+        .collect()
+}
+
+/// Trajectories of the flight plans flown between `start` and `end` along
+/// the routes of the router
+pub fn get_active_trajectories(
+    flight_plans: &[FlightPlan],
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+) -> Vec<Trajectory> {
+    get_active_trajectories_with(flight_plans, start, end, |from, to| {
+        let (locations, _) = get_route(RouteQuery::new(
+            Aircraft::Cargo,
+            get_node_by_id(from).ok()?,
+            get_node_by_id(to).ok()?,
+        ))
+        .ok()?;
+        Some(locations)
+    })
+}
+
+/// Sets the minima enforced between the possible flights and active flights
+pub fn set_separation_minima(minima: SeparationMinima) {
+    match current_context().separation_minima.write() {
+        Ok(mut current) => *current = Some(minima),
+        Err(_) => error!("Separation minima unavailable"),
+    }
+}
+
+/// Stops enforcing separation minima
+pub fn clear_separation_minima() {
+    match current_context().separation_minima.write() {
+        Ok(mut current) => *current = None,
+        Err(_) => error!("Separation minima unavailable"),
+    }
+}
+
+/// Gets the minima enforced between flights, if any
+pub fn get_separation_minima() -> Option<SeparationMinima> {
+    current_context()
+        .separation_minima
+        .read()
+        .map(|minima| *minima)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod separation_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use crate::test_support::mock_location_at_altitude;
+    use chrono::{Duration, TimeZone};
+
+    fn noon() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2030, 6, 1, 12, 0, 0).unwrap()
+    }
+
+    /// 40 minutes block time with 20 minutes airborne
+    fn trajectory(id: &str, route: Vec<Location>, departure: DateTime<Tz>) -> Trajectory {
+        Trajectory {
+            flight_plan_id: id.to_string(),
+            route,
+            departure: departure.timestamp(),
+            arrival: (departure + Duration::minutes(40)).timestamp(),
+        }
+    }
+
+    #[test]
+    fn test_location_at() {
+        let flight = trajectory(
+            "sep-1",
+            vec![
+                mock_location_at_altitude(0.0, 0.0, 0.0),
+                mock_location_at_altitude(0.0, 0.2, 0.0),
+            ],
+            noon(),
+        );
+        assert_eq!(flight.location_at(noon().timestamp()), None);
+        let middle = flight
+            .location_at((noon() + Duration::minutes(20)).timestamp())
+            .unwrap();
+        assert!((middle.longitude.into_inner() - 0.1).abs() < 1e-4);
+        assert_eq!(
+            flight.location_at((noon() + Duration::minutes(35)).timestamp()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_crossing_flights_lose_separation() {
+        let minima = SeparationMinima::default();
+        let east = trajectory(
+            "sep-east",
+            vec![
+                mock_location_at_altitude(0.0, -0.1, 0.0),
+                mock_location_at_altitude(0.0, 0.1, 0.0),
+            ],
+            noon(),
+        );
+        let north = trajectory(
+            "sep-north",
+            vec![
+                mock_location_at_altitude(-0.1, 0.0, 0.0),
+                mock_location_at_altitude(0.1, 0.0, 0.0),
+            ],
+            noon(),
+        );
+        // both reach the crossing 20 minutes after departure
+        let lost = first_loss_of_separation(&minima, &east, &north).unwrap();
+        assert!((lost - (noon() + Duration::minutes(20)).timestamp()).abs() < 60);
+
+        assert!(first_loss_of_separation(&minima, &east, &north.delayed(3)).is_none());
+
+        let above = trajectory(
+            "sep-above",
+            vec![
+                mock_location_at_altitude(-0.1, 0.0, 300.0),
+                mock_location_at_altitude(0.1, 0.0, 300.0),
+            ],
+            noon(),
+        );
+        assert!(first_loss_of_separation(&minima, &east, &above).is_none());
+    }
+
+    #[test]
+    fn test_find_separated_delay() {
+        let minima = SeparationMinima::default();
+        let east = trajectory(
+            "sep-east",
+            vec![
+                mock_location_at_altitude(0.0, -0.1, 0.0),
+                mock_location_at_altitude(0.0, 0.1, 0.0),
+            ],
+            noon(),
+        );
+        let north = trajectory(
+            "",
+            vec![
+                mock_location_at_altitude(-0.1, 0.0, 0.0),
+                mock_location_at_altitude(0.1, 0.0, 0.0),
+            ],
+            noon(),
+        );
+        let delay =
+            find_separated_delay_minutes(&minima, &north, std::slice::from_ref(&east), 4).unwrap();
+        assert!(delay > 0 && delay <= 4);
+        assert_eq!(
+            find_separated_delay_minutes(&minima, &north, &[], 4),
+            Some(0)
+        );
+
+        // the same route at the same time needs a delay
+        let same = trajectory("", east.route.clone(), noon());
+        assert_eq!(
+            find_separated_delay_minutes(&minima, &same, &[east], 0),
+            None
+        );
+    }
+
+    #[test]
+    fn test_active_trajectories() {
+        let flight_plan = create_flight_plan_data(
+            "sep-vehicle".to_string(),
+            "sep-A".to_string(),
+            "sep-B".to_string(),
+            noon(),
+            noon() + Duration::minutes(40),
+        );
+        let flight_plans = vec![FlightPlan {
+            id: "sep-plan".to_string(),
+            data: Some(flight_plan),
+        }];
+        let route = |from: &str, to: &str| {
+            (from == "sep-A" && to == "sep-B").then(|| {
+                vec![
+                    mock_location_at_altitude(0.0, 0.0, 0.0),
+                    mock_location_at_altitude(0.0, 0.2, 0.0),
+                ]
+            })
+        };
+
+        let active = get_active_trajectories_with(
+            &flight_plans,
+            noon() + Duration::minutes(30),
+            noon() + Duration::hours(1),
+            route,
+        );
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].flight_plan_id, "sep-plan");
+        assert_eq!(active[0].departure, noon().timestamp());
+
+        assert!(get_active_trajectories_with(
+            &flight_plans,
+            noon() + Duration::minutes(40),
+            noon() + Duration::hours(1),
+            route,
+        )
+        .is_empty());
+    }
+}
@@ -0,0 +1,516 @@
+//! Discrete-event simulation of the fleet.
+//!
+//! A [`Simulation`] plays a set of flight plans against the network on a
+//! virtual clock and emits the resulting events: departures, arrivals, pad
+//! occupancy and charging. In [`SimulationMode::Replay`] flights happen at
+//! their actual times, or scheduled times if not flown yet, without any
+//! constraints. In [`SimulationMode::Forward`] a vehicle only departs once it
+//! arrived from its previous flight and finished charging, and an arriving
+//! vehicle holds until a pad is free, so delays propagate through the
+//! schedule the way they would under load.
+
+use chrono::{DateTime, Duration};
+use rrule::Tz;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+use crate::amendment::{timestamp_to_datetime, PlanTimes};
+use crate::clock::Clock;
+use crate::router_state::FlightPlan;
+
+/// How flight plans are played
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SimulationMode {
+    /// flights happen at their actual or scheduled times
+    Replay,
+    /// flights wait for their vehicle and for a free pad
+    Forward,
+}
+
+/// Configuration of a simulation
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationConfig {
+    /// how flight plans are played
+    pub mode: SimulationMode,
+    /// charging time of a vehicle after each arrival
+    pub charge_minutes: i64,
+    /// number of pads by vertiport id, vertiports not listed have unlimited pads
+    pub pads: HashMap<String, usize>,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        SimulationConfig {
+            mode: SimulationMode::Forward,
+            charge_minutes: 0,
+            pads: HashMap::new(),
+        }
+    }
+}
+
+/// What happened in a simulation event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimulationEventKind {
+    /// a vehicle took off
+    Departure {
+        /// id of the flight plan
+        flight_plan_id: String,
+        /// id of the vehicle
+        vehicle_id: String,
+        /// id of the departure vertiport
+        vertiport_id: String,
+        /// departure delay in minutes
+        delay_minutes: i64,
+    },
+    /// a vehicle landed
+    Arrival {
+        /// id of the flight plan
+        flight_plan_id: String,
+        /// id of the vehicle
+        vehicle_id: String,
+        /// id of the destination vertiport
+        vertiport_id: String,
+        /// arrival delay in minutes
+        delay_minutes: i64,
+    },
+    /// an arriving vehicle started holding because no pad was free
+    Holding {
+        /// id of the flight plan
+        flight_plan_id: String,
+        /// id of the vehicle
+        vehicle_id: String,
+        /// id of the destination vertiport
+        vertiport_id: String,
+    },
+    /// a vehicle took a pad
+    PadOccupied {
+        /// id of the vertiport
+        vertiport_id: String,
+        /// id of the vehicle
+        vehicle_id: String,
+        /// pads occupied at the vertiport afterwards
+        occupied_pads: usize,
+    },
+    /// a vehicle left a pad
+    PadReleased {
+        /// id of the vertiport
+        vertiport_id: String,
+        /// id of the vehicle
+        vehicle_id: String,
+        /// pads occupied at the vertiport afterwards
+        occupied_pads: usize,
+    },
+    /// a vehicle started charging
+    ChargingStarted {
+        /// id of the vehicle
+        vehicle_id: String,
+        /// id of the vertiport
+        vertiport_id: String,
+    },
+    /// a vehicle finished charging
+    ChargingFinished {
+        /// id of the vehicle
+        vehicle_id: String,
+        /// id of the vertiport
+        vertiport_id: String,
+    },
+}
+
+/// An event of the simulation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulationEvent {
+    /// time of the event on the virtual clock
+    pub time: DateTime<Tz>,
+    /// what happened
+    pub kind: SimulationEventKind,
+}
+
+/// Work scheduled on the virtual clock
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Pending {
+    // arrivals and charging come first at the same time to free vehicles and pads
+    Arrive(usize),
+    ChargingDone(usize),
+    Depart(usize),
+}
+
+/// A flight plan being simulated
+#[derive(Debug, Clone)]
+struct SimulatedFlight {
+    plan: PlanTimes,
+    /// departure and arrival in replay mode, in seconds since epoch
+    replay_departure: i64,
+    replay_arrival: i64,
+}
+
+/// Discrete-event simulation of flight plans on a virtual clock
+#[derive(Debug)]
+pub struct Simulation {
+    config: SimulationConfig,
+    clock: i64,
+    sequence: u64,
+    queue: BinaryHeap<Reverse<(i64, Pending, u64)>>,
+    flights: Vec<SimulatedFlight>,
+    /// vehicles in flight or charging
+    busy_vehicles: HashMap<String, bool>,
+    /// departures waiting for their vehicle, by vehicle id
+    waiting_departures: HashMap<String, VecDeque<usize>>,
+    /// arrivals holding for a pad, by vertiport id
+    holding_arrivals: HashMap<String, VecDeque<usize>>,
+    occupied_pads: HashMap<String, usize>,
+}
+
+impl Simulation {
+    /// Creates a simulation of the flight plans with the virtual clock set to `start`
+    ///
+    /// Flight plans without times are left out. Every vehicle starts on a pad
+    /// at the departure vertiport of its first flight.
+    pub fn new(start: DateTime<Tz>, flight_plans: &[FlightPlan], config: SimulationConfig) -> Self {
+        let mut simulation = Simulation {
+            config,
+            clock: start.timestamp(),
+            sequence: 0,
+            queue: BinaryHeap::new(),
+            flights: vec![],
+            busy_vehicles: HashMap::new(),
+            waiting_departures: HashMap::new(),
+            holding_arrivals: HashMap::new(),
+            occupied_pads: HashMap::new(),
+        };
+        for flight_plan in flight_plans {
+            let Ok(plan) = PlanTimes::from_flight_plan(flight_plan) else {
+                debug!("Skipping flight plan {} without times", flight_plan.id);
+                continue;
+            };
+            let data = flight_plan.data.as_ref();
+            let replay_departure = data
+                .and_then(|data| data.actual_departure.as_ref())
+                .map_or(plan.departure, |timestamp| timestamp.seconds);
+            let replay_arrival = data
+                .and_then(|data| data.actual_arrival.as_ref())
+                .map_or(plan.arrival, |timestamp| timestamp.seconds);
+            simulation.flights.push(SimulatedFlight {
+                plan,
+                replay_departure,
+                replay_arrival,
+            });
+        }
+
+        let mut first_flights: HashMap<&str, &SimulatedFlight> = HashMap::new();
+        for flight in &simulation.flights {
+            first_flights
+                .entry(&flight.plan.vehicle_id)
+                .and_modify(|first| {
+                    if flight.plan.departure < first.plan.departure {
+                        *first = flight;
+                    }
+                })
+                .or_insert(flight);
+        }
+        for flight in first_flights.into_values() {
+            *simulation
+                .occupied_pads
+                .entry(flight.plan.departure_vertiport_id.clone())
+                .or_default() += 1;
+        }
+
+        for index in 0..simulation.flights.len() {
+            let departure = match simulation.config.mode {
+                SimulationMode::Replay => simulation.flights[index].replay_departure,
+                SimulationMode::Forward => simulation.flights[index].plan.departure,
+            };
+            simulation.schedule(departure, Pending::Depart(index));
+        }
+        simulation
+    }
+
+    /// Current time of the virtual clock
+    pub fn now(&self) -> DateTime<Tz> {
+        timestamp_to_datetime(self.clock)
+    }
+
+    /// Checks if all scheduled work is done
+    pub fn is_finished(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn schedule(&mut self, time: i64, pending: Pending) {
+        self.sequence += 1;
+        self.queue.push(Reverse((time, pending, self.sequence)));
+    }
+
+    fn event(&self, kind: SimulationEventKind) -> SimulationEvent {
+        SimulationEvent {
+            time: self.now(),
+            kind,
+        }
+    }
+
+    fn has_free_pad(&self, vertiport_id: &str) -> bool {
+        match self.config.pads.get(vertiport_id) {
+            Some(pads) => self.occupied_pads.get(vertiport_id).copied().unwrap_or(0) < *pads,
+            None => true,
+        }
+    }
+
+    fn depart(&mut self, index: usize, events: &mut Vec<SimulationEvent>) {
+        let flight = &self.flights[index];
+        let vehicle_id = flight.plan.vehicle_id.clone();
+        let forward = self.config.mode == SimulationMode::Forward;
+        if forward
+            && self
+                .busy_vehicles
+                .get(&vehicle_id)
+                .copied()
+                .unwrap_or(false)
+        {
+            self.waiting_departures
+                .entry(vehicle_id)
+                .or_default()
+                .push_back(index);
+            return;
+        }
+        let vertiport_id = flight.plan.departure_vertiport_id.clone();
+        let scheduled_departure = flight.plan.departure;
+        let arrival = if forward {
+            self.clock + flight.plan.arrival - flight.plan.departure
+        } else {
+            flight.replay_arrival
+        };
+        events.push(self.event(SimulationEventKind::Departure {
+            flight_plan_id: flight.plan.id.clone(),
+            vehicle_id: vehicle_id.clone(),
+            vertiport_id: vertiport_id.clone(),
+            delay_minutes: (self.clock - scheduled_departure) / 60,
+        }));
+        self.busy_vehicles.insert(vehicle_id.clone(), true);
+
+        let occupied = self.occupied_pads.entry(vertiport_id.clone()).or_default();
+        *occupied = occupied.saturating_sub(1);
+        let occupied_pads = *occupied;
+        events.push(self.event(SimulationEventKind::PadReleased {
+            vertiport_id: vertiport_id.clone(),
+            vehicle_id,
+            occupied_pads,
+        }));
+        let holding = self
+            .holding_arrivals
+            .get_mut(&vertiport_id)
+            .and_then(|holding| holding.pop_front());
+        if let Some(holding) = holding {
+            self.land(holding, events);
+        }
+        self.schedule(arrival, Pending::Arrive(index));
+    }
+
+    fn arrive(&mut self, index: usize, events: &mut Vec<SimulationEvent>) {
+        let plan = &self.flights[index].plan;
+        let vertiport_id = plan.destination_vertiport_id.clone();
+        if self.config.mode == SimulationMode::Forward && !self.has_free_pad(&vertiport_id) {
+            events.push(self.event(SimulationEventKind::Holding {
+                flight_plan_id: plan.id.clone(),
+                vehicle_id: plan.vehicle_id.clone(),
+                vertiport_id: vertiport_id.clone(),
+            }));
+            self.holding_arrivals
+                .entry(vertiport_id)
+                .or_default()
+                .push_back(index);
+            return;
+        }
+        self.land(index, events);
+    }
+
+    fn land(&mut self, index: usize, events: &mut Vec<SimulationEvent>) {
+        let plan = self.flights[index].plan.clone();
+        events.push(self.event(SimulationEventKind::Arrival {
+            flight_plan_id: plan.id.clone(),
+            vehicle_id: plan.vehicle_id.clone(),
+            vertiport_id: plan.destination_vertiport_id.clone(),
+            delay_minutes: (self.clock - plan.arrival) / 60,
+        }));
+        let occupied = self
+            .occupied_pads
+            .entry(plan.destination_vertiport_id.clone())
+            .or_default();
+        *occupied += 1;
+        let occupied_pads = *occupied;
+        events.push(self.event(SimulationEventKind::PadOccupied {
+            vertiport_id: plan.destination_vertiport_id.clone(),
+            vehicle_id: plan.vehicle_id.clone(),
+            occupied_pads,
+        }));
+        events.push(self.event(SimulationEventKind::ChargingStarted {
+            vehicle_id: plan.vehicle_id.clone(),
+            vertiport_id: plan.destination_vertiport_id.clone(),
+        }));
+        let charged = self.clock + Duration::minutes(self.config.charge_minutes).num_seconds();
+        self.schedule(charged, Pending::ChargingDone(index));
+    }
+
+    fn finish_charging(&mut self, index: usize, events: &mut Vec<SimulationEvent>) {
+        let plan = &self.flights[index].plan;
+        let vehicle_id = plan.vehicle_id.clone();
+        events.push(self.event(SimulationEventKind::ChargingFinished {
+            vehicle_id: vehicle_id.clone(),
+            vertiport_id: plan.destination_vertiport_id.clone(),
+        }));
+        self.busy_vehicles.insert(vehicle_id.clone(), false);
+        let waiting = self
+            .waiting_departures
+            .get_mut(&vehicle_id)
+            .and_then(|waiting| {
+                // the earliest scheduled flight goes next
+                let next = (0..waiting.len())
+                    .min_by_key(|&position| self.flights[waiting[position]].plan.departure)?;
+                waiting.remove(next)
+            });
+        if let Some(waiting) = waiting {
+            self.schedule(self.clock, Pending::Depart(waiting));
+        }
+    }
+
+    /// Advances the virtual clock to the next scheduled work and returns its events
+    ///
+    /// Returns an empty vector once the simulation is finished.
+    pub fn step(&mut self) -> Vec<SimulationEvent> {
+        let mut events = vec![];
+        let Some(Reverse((time, pending, _))) = self.queue.pop() else {
+            return events;
+        };
+        self.clock = self.clock.max(time);
+        match pending {
+            Pending::Depart(index) => self.depart(index, &mut events),
+            Pending::Arrive(index) => self.arrive(index, &mut events),
+            Pending::ChargingDone(index) => self.finish_charging(index, &mut events),
+        }
+        events
+    }
+
+    /// Runs the simulation up to and including `until` and returns the events in order
+    pub fn run_until(&mut self, until: DateTime<Tz>) -> Vec<SimulationEvent> {
+        let mut events = vec![];
+        while let Some(Reverse((time, _, _))) = self.queue.peek() {
+            if *time > until.timestamp() {
+                break;
+            }
+            events.extend(self.step());
+        }
+        self.clock = self.clock.max(until.timestamp());
+        events
+    }
+
+    /// Runs the simulation until all scheduled work is done and returns the events in order
+    pub fn run(&mut self) -> Vec<SimulationEvent> {
+        let mut events = vec![];
+        while !self.is_finished() {
+            events.extend(self.step());
+        }
+        info!(
+            "Simulation finished at {} with {} events",
+            self.now(),
+            events.len()
+        );
+        events
+    }
+}
+
+/// The virtual clock of the simulation, for functions taking a [`Clock`]
+impl Clock for Simulation {
+    fn now(&self) -> DateTime<Tz> {
+        Simulation::now(self)
+    }
+}
+
+#[cfg(test)]
+mod simulation_tests {
+    use super::*;
+    use crate::test_support::{mock_flight_plan, mock_start};
+    use prost_types::Timestamp;
+
+    fn departures(events: &[SimulationEvent]) -> Vec<(String, i64)> {
+        events
+            .iter()
+            .filter_map(|event| match &event.kind {
+                SimulationEventKind::Departure {
+                    flight_plan_id,
+                    delay_minutes,
+                    ..
+                } => Some((flight_plan_id.clone(), *delay_minutes)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_forward_simulation_propagates_charging_delay() {
+        let flight_plans = vec![
+            mock_flight_plan("fp1", "v1", "A", "B", 0),
+            mock_flight_plan("fp2", "v1", "B", "A", 40),
+        ];
+        let config = SimulationConfig {
+            charge_minutes: 20,
+            ..Default::default()
+        };
+        let mut simulation = Simulation::new(mock_start(), &flight_plans, config);
+        let events = simulation.run();
+        // fp1 arrives at 30 and charges until 50
+        assert_eq!(
+            departures(&events),
+            vec![("fp1".to_string(), 0), ("fp2".to_string(), 10)]
+        );
+        assert_eq!(simulation.now(), mock_start() + Duration::minutes(100));
+        assert!(events.windows(2).all(|pair| pair[0].time <= pair[1].time));
+    }
+
+    #[test]
+    fn test_holding_for_pad() {
+        // v2 occupies the only pad at B until it departs at 45
+        let flight_plans = vec![
+            mock_flight_plan("fp1", "v1", "A", "B", 0),
+            mock_flight_plan("fp2", "v2", "B", "C", 45),
+        ];
+        let config = SimulationConfig {
+            pads: HashMap::from([("B".to_string(), 1)]),
+            ..Default::default()
+        };
+        let mut simulation = Simulation::new(mock_start(), &flight_plans, config);
+        let events = simulation.run_until(mock_start() + Duration::minutes(30));
+        assert!(matches!(
+            events.last().map(|event| &event.kind),
+            Some(SimulationEventKind::Holding { .. })
+        ));
+        let events = simulation.run();
+        let arrival = events
+            .iter()
+            .find(|event| {
+                matches!(&event.kind, SimulationEventKind::Arrival { flight_plan_id, .. } if flight_plan_id == "fp1")
+            })
+            .unwrap();
+        assert_eq!(arrival.time, mock_start() + Duration::minutes(45));
+    }
+
+    #[test]
+    fn test_replay_uses_actual_times() {
+        let mut flight_plans = vec![
+            mock_flight_plan("fp1", "v1", "A", "B", 0),
+            mock_flight_plan("fp2", "v1", "B", "A", 30),
+        ];
+        if let Some(data) = flight_plans[0].data.as_mut() {
+            data.actual_departure = Some(Timestamp {
+                seconds: (mock_start() + Duration::minutes(5)).timestamp(),
+                nanos: 0,
+            });
+        }
+        let config = SimulationConfig {
+            mode: SimulationMode::Replay,
+            charge_minutes: 20,
+            ..Default::default()
+        };
+        let events = Simulation::new(mock_start(), &flight_plans, config).run();
+        assert_eq!(
+            departures(&events),
+            vec![("fp1".to_string(), 5), ("fp2".to_string(), 0)]
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//! Spatial queries on a set of nodes.
+//!
+//! [`get_nearest_vertiports`](crate::router_state::get_nearest_vertiports)
+//! only returns the single nearest vertiport to each end of a trip.
+//! Dispatching from several depots needs all nodes around a location, either
+//! within a radius with [`nodes_within_radius`] or the closest few with
+//! [`k_nearest_nodes`]. Both scan the nodes with batched haversine distances.
+//!
+//! [`snap_to_network`] projects a location, such as a customer address or
+//! the live position of an aircraft, onto the nearest edge of the router, to
+//! monitor whether a flight follows its route or replan from mid-route.
+//! [`is_within_corridor`] checks a live position against a single leg of the
+//! filed route with the same cross-track math.
+
+use ordered_float::OrderedFloat;
+
+use crate::distance::Distance;
+use crate::haversine;
+use crate::location::Location;
+use crate::node::Node;
+use crate::router::engine::{EdgeView, Router};
+use crate::router_state::ARROW_CARGO_ROUTER;
+
+/// Projection of a location onto the nearest edge of the network
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NetworkSnap<'a> {
+    /// the edge nearest to the location
+    pub edge: EdgeView<'a>,
+    /// the point of the edge nearest to the location, on the ground
+    pub point: Location,
+    /// distance from the start of the edge to the point, along the edge
+    pub along_track: Distance,
+    /// distance from the location to the point
+    pub cross_track: Distance,
+}
+
+/// Finds the nodes within a radius of a location
+///
+/// # Arguments
+/// * `location` - The location to search around.
+/// * `radius` - The largest distance of a node, included.
+/// * `nodes` - The nodes to search, e.g. the vertiports of the router.
+///
+/// # Returns
+/// The nodes with their distance to the location, nearest first. Nodes at
+/// the same distance keep their order.
+pub fn nodes_within_radius<'a>(
+    location: &Location,
+    radius: Distance,
+    nodes: &'a [Node],
+) -> Vec<(&'a Node, Distance)> {
+    let mut nearby: Vec<(&Node, Distance)> = by_distance(location, nodes)
+        .filter(|(_, distance)| *distance <= radius)
+        .collect();
+    nearby.sort_by_key(|(_, distance)| *distance);
+    nearby
+}
+
+/// Finds the `k` nodes nearest to a location
+///
+/// # Arguments
+/// * `location` - The location to search around.
+/// * `k` - The number of nodes to find.
+/// * `nodes` - The nodes to search, e.g. the vertiports of the router.
+///
+/// # Returns
+/// Up to `k` nodes with their distance to the location, nearest first. Nodes
+/// at the same distance keep their order.
+pub fn k_nearest_nodes<'a>(
+    location: &Location,
+    k: usize,
+    nodes: &'a [Node],
+) -> Vec<(&'a Node, Distance)> {
+    let mut nearest: Vec<(&Node, Distance)> = by_distance(location, nodes).collect();
+    nearest.sort_by_key(|(_, distance)| *distance);
+    nearest.truncate(k);
+    nearest
+}
+
+/// Projects a location onto the nearest edge of the given router
+///
+/// Edges are great circle arcs between their nodes. A location beyond an
+/// end of its nearest edge snaps to that end.
+///
+/// # Arguments
+/// * `router` - router with the graph of vertiports
+/// * `location` - the location to snap, its altitude is ignored
+///
+/// # Returns
+/// The nearest edge with the projected point, `None` if the router has no edges
+pub fn snap_to_network_with<'a>(
+    router: &'a Router<'a>,
+    location: &Location,
+) -> Option<NetworkSnap<'a>> {
+    router
+        .edges()
+        .map(|edge| snap_to_edge(edge, location))
+        .min_by_key(|snap| snap.cross_track)
+}
+
+/// Projects a location onto the nearest edge of the cargo router
+/// See [`snap_to_network_with`]
+pub fn snap_to_network(location: &Location) -> Result<Option<NetworkSnap<'static>>, String> {
+    let router = ARROW_CARGO_ROUTER
+        .get()
+        .ok_or("Arrow XL router not initialized. Try to initialize it first.")?;
+    Ok(snap_to_network_with(router, location))
+}
+
+/// Checks whether a position keeps within the corridor of a leg
+///
+/// The corridor extends `max_deviation_m` on each side of the great circle
+/// arc of the leg and around its ends. The altitude of the position is
+/// ignored.
+///
+/// # Arguments
+/// * `position` - live position of the aircraft
+/// * `leg` - start and end of the filed leg
+/// * `max_deviation_m` - largest distance from the leg in meters, included
+pub fn is_within_corridor(
+    position: &Location,
+    leg: (&Location, &Location),
+    max_deviation_m: f32,
+) -> bool {
+    cross_track_distance(position, leg).meters() <= max_deviation_m
+}
+
+/// Distance from a position to the nearest point of the great circle arc of
+/// a leg, or to its nearest end if the position is beyond it
+/// See [`is_within_corridor`]
+pub fn cross_track_distance(position: &Location, leg: (&Location, &Location)) -> Distance {
+    project_onto_leg(position, leg).2
+}
+
+/// Projects a location onto the great circle arc of an edge
+fn snap_to_edge<'a>(edge: EdgeView<'a>, location: &Location) -> NetworkSnap<'a> {
+    let (point, along_track, cross_track) =
+        project_onto_leg(location, (&edge.from.location, &edge.to.location));
+    NetworkSnap {
+        edge,
+        point,
+        along_track,
+        cross_track,
+    }
+}
+
+/// Projects a location onto the great circle arc of a leg
+///
+/// # Returns
+/// The nearest point of the leg, on the ground, the distance to it along
+/// the leg and the distance from the location to it
+fn project_onto_leg(
+    location: &Location,
+    (start, end): (&Location, &Location),
+) -> (Location, Distance, Distance) {
+    let length = angular_distance(start, end);
+    let to_location = angular_distance(start, location);
+    let bearing_difference = bearing(start, location) - bearing(start, end);
+    // angular distance from the arc
+    let cross_track = (to_location.sin() * bearing_difference.sin())
+        .clamp(-1.0, 1.0)
+        .asin();
+    // angular distance along the arc to the foot of the perpendicular, negative behind the start
+    let along_track = (to_location.cos() / cross_track.cos())
+        .clamp(-1.0, 1.0)
+        .acos()
+        .copysign(bearing_difference.cos());
+    let along_track = along_track.clamp(0.0, length);
+    let point = destination(start, bearing(start, end), along_track);
+    (
+        point,
+        Distance::from_km((along_track * haversine::get_earth_radius().km()) as f32),
+        Distance::from_km(haversine::distance(location, &point)),
+    )
+}
+
+/// Latitude and longitude of a location in radians
+fn radians(location: &Location) -> (f64, f64) {
+    (
+        (location.latitude.into_inner() as f64).to_radians(),
+        (location.longitude.into_inner() as f64).to_radians(),
+    )
+}
+
+/// Central angle between two locations in radians
+fn angular_distance(from: &Location, to: &Location) -> f64 {
+    let ((lat1, lon1), (lat2, lon2)) = (radians(from), radians(to));
+    let a = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    2.0 * a.sqrt().atan2((1.0 - a).sqrt())
+}
+
+/// Initial bearing of the great circle from one location to another in radians
+fn bearing(from: &Location, to: &Location) -> f64 {
+    let ((lat1, lon1), (lat2, lon2)) = (radians(from), radians(to));
+    (((lon2 - lon1).sin()) * lat2.cos())
+        .atan2(lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * (lon2 - lon1).cos())
+}
+
+/// Location at an angular distance from a location along a bearing
+fn destination(from: &Location, bearing: f64, angle: f64) -> Location {
+    let (lat1, lon1) = radians(from);
+    let lat2 = (lat1.sin() * angle.cos() + lat1.cos() * angle.sin() * bearing.cos())
+        .clamp(-1.0, 1.0)
+        .asin();
+    let lon2 = lon1
+        + (bearing.sin() * angle.sin() * lat1.cos()).atan2(angle.cos() - lat1.sin() * lat2.sin());
+    Location {
+        latitude: OrderedFloat(lat2.to_degrees() as f32),
+        longitude: OrderedFloat(lon2.to_degrees() as f32),
+        altitude_meters: OrderedFloat(0.0),
+    }
+}
+
+/// Pairs each node with its distance to the location
+fn by_distance<'a>(
+    location: &Location,
+    nodes: &'a [Node],
+) -> impl Iterator<Item = (&'a Node, Distance)> {
+    let distances = haversine::distance_batch_by(location, nodes, |node| &node.location);
+    nodes
+        .iter()
+        .zip(distances.into_iter().map(Distance::from_km))
+}
+
+#[cfg(test)]
+mod spatial_tests {
+    use super::*;
+    use crate::generator::generate_nodes_near_with_seed;
+    use crate::router_state::SAN_FRANCISCO;
+    use crate::test_support::mock_location;
+
+    #[test]
+    fn test_nodes_within_radius() {
+        let nodes =
+            generate_nodes_near_with_seed(3629, &SAN_FRANCISCO, Distance::from_km(50.0), 200);
+        let radius = Distance::from_km(20.0);
+        let nearby = nodes_within_radius(&SAN_FRANCISCO, radius, &nodes);
+        assert!(!nearby.is_empty() && nearby.len() < nodes.len());
+        assert!(nearby.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        for node in &nodes {
+            let distance = Distance::from_km(haversine::distance(&SAN_FRANCISCO, &node.location));
+            assert_eq!(
+                nearby.iter().any(|(nearby, _)| nearby.uid == node.uid),
+                distance <= radius
+            );
+        }
+        assert!(nodes_within_radius(&SAN_FRANCISCO, Distance::ZERO, &nodes).is_empty());
+    }
+
+    #[test]
+    fn test_k_nearest_nodes() {
+        let nodes =
+            generate_nodes_near_with_seed(3629, &SAN_FRANCISCO, Distance::from_km(50.0), 200);
+        let nearest = k_nearest_nodes(&SAN_FRANCISCO, 5, &nodes);
+        assert_eq!(nearest.len(), 5);
+        let farthest = nearest[4].1;
+        assert_eq!(
+            nodes_within_radius(&SAN_FRANCISCO, farthest, &nodes)[..5],
+            nearest[..]
+        );
+        assert_eq!(
+            k_nearest_nodes(&SAN_FRANCISCO, 500, &nodes).len(),
+            nodes.len()
+        );
+        assert!(k_nearest_nodes(&SAN_FRANCISCO, 3, &[]).is_empty());
+    }
+
+    fn distance(from: &dyn crate::node::AsNode, to: &dyn crate::node::AsNode) -> f32 {
+        haversine::distance(&from.as_node().location, &to.as_node().location)
+    }
+
+    #[test]
+    fn test_snap_to_network() {
+        // an L of two edges along the equator and the meridian 0.5
+        let nodes = vec![
+            Node::new("snap-A", 0.0, 0.0),
+            Node::new("snap-B", 0.0, 0.5),
+            Node::new("snap-C", 0.5, 0.5),
+        ];
+        let router = Router::new(&nodes, 60.0, distance, distance);
+
+        // north of the middle of A-B
+        let snap = snap_to_network_with(&router, &mock_location(0.1, 0.25)).unwrap();
+        let leg = (snap.edge.from.uid.as_str(), snap.edge.to.uid.as_str());
+        assert!(leg == ("snap-A", "snap-B") || leg == ("snap-B", "snap-A"));
+        assert!(snap.point.latitude.into_inner().abs() < 1e-4);
+        assert!((snap.point.longitude.into_inner() - 0.25).abs() < 1e-4);
+        assert!((snap.along_track.km() - 27.8).abs() < 0.1);
+        assert!((snap.cross_track.km() - 11.1).abs() < 0.1);
+
+        // beyond the end of A-B and of B-C, snaps to B
+        let snap = snap_to_network_with(&router, &mock_location(-0.1, 0.6)).unwrap();
+        assert!(haversine::distance(&snap.point, &nodes[1].location) < 0.01);
+        assert!(
+            (snap.cross_track.km()
+                - haversine::distance(&mock_location(-0.1, 0.6), &nodes[1].location))
+            .abs()
+                < 0.01
+        );
+
+        // on the network
+        let snap = snap_to_network_with(&router, &mock_location(0.2, 0.5)).unwrap();
+        assert!(snap.cross_track.km() < 0.01);
+
+        let isolated = Router::new(&nodes, 0.0, distance, distance);
+        assert!(snap_to_network_with(&isolated, &mock_location(0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn test_is_within_corridor() {
+        let (start, end) = (mock_location(0.0, 0.0), mock_location(0.0, 1.0));
+        // 0.001° north of the middle of the leg, 111 m off
+        let position = mock_location(0.001, 0.5);
+        assert!((cross_track_distance(&position, (&start, &end)).meters() - 111.2).abs() < 1.0);
+        assert!(is_within_corridor(&position, (&start, &end), 150.0));
+        assert!(!is_within_corridor(&position, (&start, &end), 100.0));
+        // the direction of the leg doesn't matter
+        assert!(is_within_corridor(&position, (&end, &start), 150.0));
+
+        // on the leg
+        assert!(is_within_corridor(
+            &mock_location(0.0, 0.25),
+            (&start, &end),
+            1.0
+        ));
+        // on the great circle, but beyond the end of the leg
+        assert!(!is_within_corridor(
+            &mock_location(0.0, 1.01),
+            (&start, &end),
+            1000.0
+        ));
+        assert!(is_within_corridor(
+            &mock_location(0.0, 1.005),
+            (&start, &end),
+            1000.0
+        ));
+        // around the start of a leg of no length
+        assert!(is_within_corridor(
+            &mock_location(0.0, 0.0005),
+            (&start, &start),
+            100.0
+        ));
+    }
+}
@@ -0,0 +1,267 @@
+//! Terrain and obstacle clearance of routes.
+//!
+//! An [`ElevationProvider`] gives the height of the highest terrain or
+//! obstacle at a location. [`TerrainClearance`] samples the legs of a route
+//! against it to flag the ones flown too close to the ground at the assigned
+//! altitude, and [`distance_if_clear`] keeps such legs out of the graph of the
+//! router so routes are built around them.
+
+use ordered_float::OrderedFloat;
+use std::sync::Arc;
+
+use crate::context::current_context;
+use crate::haversine;
+use crate::location::Location;
+use crate::node::AsNode;
+
+/// Distance between the samples of a leg checked for clearance
+pub const CLEARANCE_SAMPLE_SPACING_KM: f32 = 0.1;
+
+/// Source of terrain and obstacle heights
+pub trait ElevationProvider: Send + Sync {
+    /// Height in meters above sea level of the highest terrain or obstacle
+    /// at the location, None if it is unknown
+    fn elevation_meters(&self, location: &Location) -> Option<f32>;
+}
+
+impl<F> ElevationProvider for F
+where
+    F: Fn(&Location) -> Option<f32> + Send + Sync,
+{
+    fn elevation_meters(&self, location: &Location) -> Option<f32> {
+        self(location)
+    }
+}
+
+/// Point of a route flown below the minimum clearance
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClearanceViolation {
+    /// index of the leg in the route
+    pub leg: usize,
+    /// location of the lowest clearance along the leg
+    pub location: Location,
+    /// height of the terrain or obstacle, None if it is unknown
+    pub elevation_meters: Option<f32>,
+}
+
+/// Minimum clearance to keep above terrain and obstacles
+#[derive(Clone)]
+pub struct TerrainClearance {
+    /// heights of the terrain and obstacles
+    pub provider: Arc<dyn ElevationProvider>,
+    /// altitude assigned to the legs, in meters above sea level
+    pub cruise_altitude_meters: f32,
+    /// minimum height above terrain and obstacles
+    pub min_clearance_meters: f32,
+}
+
+impl TerrainClearance {
+    /// Checks a leg flown at the cruise altitude
+    ///
+    /// # Arguments
+    /// * `leg` - Index of the leg reported in the violation
+    /// * `from` - Start of the leg
+    /// * `to` - End of the leg
+    ///
+    /// # Returns
+    /// The point of the leg with the lowest clearance if it is below the
+    /// minimum or over unknown terrain, None if the leg is clear
+    pub fn check_leg(
+        &self,
+        leg: usize,
+        from: &Location,
+        to: &Location,
+    ) -> Option<ClearanceViolation> {
+        let samples =
+            ((haversine::distance(from, to) / CLEARANCE_SAMPLE_SPACING_KM).ceil() as usize).max(1);
+        let ceiling = self.cruise_altitude_meters - self.min_clearance_meters;
+        let mut worst: Option<ClearanceViolation> = None;
+
+        for i in 0..=samples {
+            let fraction = i as f32 / samples as f32;
+            let location = Location {
+                latitude: OrderedFloat(
+                    from.latitude.into_inner()
+                        + (to.latitude.into_inner() - from.latitude.into_inner()) * fraction,
+                ),
+                longitude: OrderedFloat(
+                    from.longitude.into_inner()
+                        + (to.longitude.into_inner() - from.longitude.into_inner()) * fraction,
+                ),
+                altitude_meters: OrderedFloat(self.cruise_altitude_meters),
+            };
+            let elevation = self.provider.elevation_meters(&location);
+            let lower = match (elevation, &worst) {
+                (None, _) => {
+                    // unknown terrain can't be cleared
+                    return Some(ClearanceViolation {
+                        leg,
+                        location,
+                        elevation_meters: None,
+                    });
+                }
+                (Some(elevation), _) if elevation <= ceiling => false,
+                (Some(_), None) => true,
+                (Some(elevation), Some(current)) => current
+                    .elevation_meters
+                    .is_some_and(|worst| elevation > worst),
+            };
+            if lower {
+                worst = Some(ClearanceViolation {
+                    leg,
+                    location,
+                    elevation_meters: elevation,
+                });
+            }
+        }
+
+        worst
+    }
+
+    /// Checks every leg of a route flown at the cruise altitude
+    ///
+    /// # Returns
+    /// The violations of the legs below the minimum clearance, in route order
+    pub fn check_route(&self, route: &[Location]) -> Vec<ClearanceViolation> {
+        route
+            .windows(2)
+            .enumerate()
+            .filter_map(|(leg, ends)| self.check_leg(leg, &ends[0], &ends[1]))
+            .collect()
+    }
+}
+
+/// Sets the clearance legs of the router must keep
+pub fn set_terrain_clearance(clearance: TerrainClearance) {
+    match current_context().terrain_clearance.write() {
+        Ok(mut current) => *current = Some(clearance),
+        Err(_) => error!("Terrain clearance unavailable"),
+    }
+}
+
+/// Removes the clearance check, legs of the router are no longer checked
+pub fn clear_terrain_clearance() {
+    match current_context().terrain_clearance.write() {
+        Ok(mut current) => *current = None,
+        Err(_) => error!("Terrain clearance unavailable"),
+    }
+}
+
+/// Gets the clearance legs of the router must keep, if any
+pub fn get_terrain_clearance() -> Option<TerrainClearance> {
+    current_context()
+        .terrain_clearance
+        .read()
+        .map(|clearance| clearance.clone())
+        .unwrap_or_default()
+}
+
+/// Constraint function returning the distance of a leg, or infinity if the
+/// leg violates the terrain clearance, for
+/// [`Router::new`](crate::router::engine::Router::new) or
+/// [`init_router_with`](crate::router_state::init_router_with)
+///
+/// The clearance is checked when the graph is built, so it must be set
+/// before the router is initialized.
+pub fn distance_if_clear(from: &dyn AsNode, to: &dyn AsNode) -> f32 {
+    let from = &from.as_node().location;
+    let to = &to.as_node().location;
+    match get_terrain_clearance() {
+        Some(clearance) if clearance.check_leg(0, from, to).is_some() => f32::INFINITY,
+        _ => haversine::distance(from, to),
+    }
+}
+
+#[cfg(test)]
+mod terrain_tests {
+    use super::*;
+    use crate::node::Node;
+    use crate::router::engine::{Algorithm, Router};
+    use crate::test_support::mock_location;
+
+    /// Flat ground with a 400 m ridge between longitudes 0.1 and 0.2 north
+    /// of the equator
+    fn ridge(location: &Location) -> Option<f32> {
+        let longitude = location.longitude.into_inner();
+        if location.latitude.into_inner() > 0.0 && (0.1..=0.2).contains(&longitude) {
+            Some(400.0)
+        } else {
+            Some(50.0)
+        }
+    }
+
+    fn clearance() -> TerrainClearance {
+        TerrainClearance {
+            provider: Arc::new(ridge),
+            cruise_altitude_meters: 450.0,
+            min_clearance_meters: 150.0,
+        }
+    }
+
+    #[test]
+    fn test_check_leg() {
+        let clearance = clearance();
+        let violation = clearance
+            .check_leg(2, &mock_location(0.05, 0.0), &mock_location(0.05, 0.3))
+            .unwrap();
+        assert_eq!(violation.leg, 2);
+        assert_eq!(violation.elevation_meters, Some(400.0));
+        let longitude = violation.location.longitude.into_inner();
+        assert!((0.1..=0.2).contains(&longitude));
+
+        assert!(clearance
+            .check_leg(0, &mock_location(-0.05, 0.0), &mock_location(-0.05, 0.3))
+            .is_none());
+    }
+
+    #[test]
+    fn test_check_route() {
+        let clearance = clearance();
+        let route = vec![
+            mock_location(-0.05, 0.0),
+            mock_location(-0.05, 0.3),
+            mock_location(0.05, 0.3),
+            mock_location(0.05, 0.0),
+        ];
+        let violations = clearance.check_route(&route);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].leg, 2);
+    }
+
+    #[test]
+    fn test_unknown_terrain_is_a_violation() {
+        let clearance = TerrainClearance {
+            provider: Arc::new(|_: &Location| None),
+            ..clearance()
+        };
+        let violation = clearance
+            .check_leg(0, &mock_location(-0.05, 0.0), &mock_location(-0.05, 0.3))
+            .unwrap();
+        assert_eq!(violation.elevation_meters, None);
+    }
+
+    #[test]
+    fn test_routes_avoid_terrain() {
+        // the direct leg crosses the ridge, the detour passes south of it
+        let nodes = vec![
+            Node::new("terrain-A", 0.05, 0.0),
+            Node::new("terrain-B", -0.08, 0.15),
+            Node::new("terrain-C", 0.05, 0.3),
+        ];
+        let route = || {
+            let router = Router::new(&nodes, 100.0, distance_if_clear, |from, to| {
+                haversine::distance(&from.as_node().location, &to.as_node().location)
+            });
+            let (_, path) = router
+                .find_shortest_path(&nodes[0], &nodes[2], Algorithm::Dijkstra, None)
+                .unwrap();
+            path.len()
+        };
+
+        set_terrain_clearance(clearance());
+        let with_clearance = route();
+        clear_terrain_clearance();
+        assert_eq!(with_clearance, 3);
+        assert_eq!(route(), 2);
+    }
+}
@@ -0,0 +1,316 @@
+//! Mock storage fixtures for tests.
+//!
+//! Builders of realistic vertiports, vertipads, vehicles and flight plans,
+//! and of locations, enabled in downstream crates with the `test_support`
+//! feature, so tests don't repeat storage literals. The schedules are valid RRULE calendars and
+//! all times are set, e.g.
+//! ```
+//! # #[cfg(feature = "test_support")] {
+//! use router::test_support::{MockFlightPlan, MockVehicle, mock_start};
+//!
+//! let vehicle = MockVehicle::new("vehicle-1").with_last_vertiport("SFO").build();
+//! let flight_plan = MockFlightPlan::new("plan-1", &vehicle.id, "SFO", "OAK")
+//!     .with_departure(mock_start())
+//!     .build();
+//! assert!(flight_plan.data.unwrap().scheduled_arrival.is_some());
+//! # }
+//! ```
+
+use chrono::{DateTime, Duration, TimeZone};
+use ordered_float::OrderedFloat;
+use rrule::Tz;
+use std::future::Future;
+use std::pin::pin;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+use crate::location::Location;
+use crate::network_import::VertiportRecord;
+use crate::resources::vehicle;
+use crate::router_state::{create_flight_plan_data, FlightPlan, Vehicle, Vertipad, Vertiport};
+use crate::time::datetime_to_timestamp;
+
+/// Start of the mock operations, Tuesday 2030-01-01 08:00 UTC
+pub fn mock_start() -> DateTime<Tz> {
+    Tz::UTC.with_ymd_and_hms(2030, 1, 1, 8, 0, 0).unwrap()
+}
+
+/// Flight minutes between the vertiports A, B, C and D on a line, 20 minutes
+/// apart, None for any other vertiport
+pub fn mock_flight_minutes(from: &str, to: &str) -> Option<i64> {
+    let position = |id: &str| ["A", "B", "C", "D"].iter().position(|&other| other == id);
+    Some((position(from)? as i64 - position(to)? as i64).abs() * 20)
+}
+
+/// Location on the ground at the given coordinates in degrees, like the
+/// location of [`Node::new`](crate::node::Node::new)
+pub fn mock_location(latitude: f32, longitude: f32) -> Location {
+    mock_location_at_altitude(latitude, longitude, 0.0)
+}
+
+/// Location at the given coordinates in degrees and altitude in meters
+pub fn mock_location_at_altitude(latitude: f32, longitude: f32, altitude_meters: f32) -> Location {
+    Location {
+        latitude: OrderedFloat(latitude),
+        longitude: OrderedFloat(longitude),
+        altitude_meters: OrderedFloat(altitude_meters),
+    }
+}
+
+/// Vertiport schedule closing every night from 22:00 to 06:00 UTC
+pub const NIGHTLY_CLOSURE: &str = "DTSTART:20221020T220000Z;DURATION:PT8H\nRRULE:FREQ=DAILY";
+
+/// Vehicle schedule blocking a daily maintenance from 02:00 to 04:00 UTC
+pub const DAILY_MAINTENANCE: &str = "DTSTART:20221020T020000Z;DURATION:PT2H\nRRULE:FREQ=DAILY";
+
+/// Builder of a mock vertiport and its vertipads
+#[derive(Debug, Clone)]
+pub struct MockVertiport {
+    record: VertiportRecord,
+    operator_id: Option<String>,
+    shared: bool,
+}
+
+impl MockVertiport {
+    /// Creates a vertiport with a single pad, closed at night
+    pub fn new(id: &str, latitude: f32, longitude: f32) -> Self {
+        MockVertiport {
+            record: VertiportRecord {
+                id: id.to_string(),
+                latitude,
+                longitude,
+                altitude_meters: 0.0,
+                pads: 1,
+                schedule: Some(NIGHTLY_CLOSURE.to_string()),
+            },
+            operator_id: None,
+            shared: false,
+        }
+    }
+
+    /// Sets the number of vertipads
+    pub fn with_pads(mut self, pads: u32) -> Self {
+        self.record.pads = pads;
+        self
+    }
+
+    /// Sets the schedule, always open if None
+    pub fn with_schedule(mut self, schedule: Option<&str>) -> Self {
+        self.record.schedule = schedule.map(str::to_string);
+        self
+    }
+
+    /// Sets the operator of the vertiport, unknown by default
+    pub fn with_operator(mut self, operator_id: &str) -> Self {
+        self.operator_id = Some(operator_id.to_string());
+        self
+    }
+
+    /// Lets every operator use the vertiport, e.g. a public vertiport
+    pub fn shared(mut self) -> Self {
+        self.shared = true;
+        self
+    }
+
+    /// Gets the vertiport as an imported network record, e.g. to initialize
+    /// the router with [`init_router_from_records`](crate::network_import::init_router_from_records)
+    pub fn record(&self) -> VertiportRecord {
+        self.record.clone()
+    }
+
+    /// Gets the vertipads, with ids `<vertiport id>-pad-<n>` starting at 1
+    pub fn vertipads(&self) -> Vec<Vertipad> {
+        self.record.vertipads()
+    }
+
+    /// Builds the vertiport
+    pub fn build(&self) -> Vertiport {
+        let mut vertiport = Vertiport::from(&self.record);
+        if let Some(data) = vertiport.data.as_mut() {
+            data.operator_id = self.operator_id.clone();
+            data.shared = self.shared;
+        }
+        vertiport
+    }
+}
+
+/// Builder of a mock vehicle
+#[derive(Debug, Clone)]
+pub struct MockVehicle {
+    id: String,
+    data: vehicle::Data,
+}
+
+impl MockVehicle {
+    /// Creates a vehicle with a daily maintenance window, not parked at any vertiport
+    pub fn new(id: &str) -> Self {
+        let start = mock_start();
+        MockVehicle {
+            id: id.to_string(),
+            data: vehicle::Data {
+                vehicle_model_id: "mock-cargo-model".to_string(),
+                serial_number: format!("SN-{}", id),
+                registration_number: format!("N-{}", id),
+                description: Some("Mock cargo vehicle".to_string()),
+                schedule: Some(DAILY_MAINTENANCE.to_string()),
+                last_maintenance: Some(datetime_to_timestamp(&(start - Duration::days(30)))),
+                next_maintenance: Some(datetime_to_timestamp(&(start + Duration::days(30)))),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Sets the vertiport the vehicle was last parked at
+    pub fn with_last_vertiport(mut self, vertiport_id: &str) -> Self {
+        self.data.last_vertiport_id = Some(vertiport_id.to_string());
+        self
+    }
+
+    /// Sets the last maintenance, 30 days before [`mock_start`] by default
+    pub fn with_last_maintenance(mut self, last_maintenance: DateTime<Tz>) -> Self {
+        self.data.last_maintenance = Some(datetime_to_timestamp(&last_maintenance));
+        self
+    }
+
+    /// Sets the vehicle model
+    pub fn with_model(mut self, vehicle_model_id: &str) -> Self {
+        self.data.vehicle_model_id = vehicle_model_id.to_string();
+        self
+    }
+
+    /// Sets the schedule, always available if None
+    pub fn with_schedule(mut self, schedule: Option<&str>) -> Self {
+        self.data.schedule = schedule.map(str::to_string);
+        self
+    }
+
+    /// Sets the operator of the vehicle, unknown by default
+    pub fn with_operator(mut self, operator_id: &str) -> Self {
+        self.data.operator_id = Some(operator_id.to_string());
+        self
+    }
+
+    /// Lets every operator use the vehicle
+    pub fn shared(mut self) -> Self {
+        self.data.shared = true;
+        self
+    }
+
+    /// Builds the vehicle
+    pub fn build(&self) -> Vehicle {
+        Vehicle {
+            id: self.id.clone(),
+            data: Some(self.data.clone()),
+        }
+    }
+}
+
+/// Builder of a mock flight plan
+#[derive(Debug, Clone)]
+pub struct MockFlightPlan {
+    id: String,
+    vehicle_id: String,
+    departure_vertiport_id: String,
+    destination_vertiport_id: String,
+    departure: DateTime<Tz>,
+    duration: Duration,
+    cargo_weight_grams: Vec<i64>,
+}
+
+impl MockFlightPlan {
+    /// Creates a 30 minute flight plan departing at [`mock_start`]
+    pub fn new(
+        id: &str,
+        vehicle_id: &str,
+        departure_vertiport_id: &str,
+        destination_vertiport_id: &str,
+    ) -> Self {
+        MockFlightPlan {
+            id: id.to_string(),
+            vehicle_id: vehicle_id.to_string(),
+            departure_vertiport_id: departure_vertiport_id.to_string(),
+            destination_vertiport_id: destination_vertiport_id.to_string(),
+            departure: mock_start(),
+            duration: Duration::minutes(30),
+            cargo_weight_grams: vec![],
+        }
+    }
+
+    /// Sets the scheduled departure
+    pub fn with_departure(mut self, departure: DateTime<Tz>) -> Self {
+        self.departure = departure;
+        self
+    }
+
+    /// Sets the time from the scheduled departure to the scheduled arrival
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Sets the weights of the cargo items
+    pub fn with_cargo_weight_grams(mut self, cargo_weight_grams: Vec<i64>) -> Self {
+        self.cargo_weight_grams = cargo_weight_grams;
+        self
+    }
+
+    /// Builds the flight plan, using the first pad of each vertiport
+    pub fn build(&self) -> FlightPlan {
+        let mut data = create_flight_plan_data(
+            self.vehicle_id.clone(),
+            self.departure_vertiport_id.clone(),
+            self.destination_vertiport_id.clone(),
+            self.departure,
+            self.departure + self.duration,
+        );
+        data.cargo_weight_grams = self.cargo_weight_grams.clone();
+        data.departure_vertipad_id = format!("{}-pad-1", self.departure_vertiport_id);
+        data.destination_vertipad_id = format!("{}-pad-1", self.destination_vertiport_id);
+        data.flight_plan_submitted =
+            Some(datetime_to_timestamp(&(self.departure - Duration::days(1))));
+        FlightPlan {
+            id: self.id.clone(),
+            data: Some(data),
+        }
+    }
+}
+
+/// 30 minute flight plan departing `minutes` after [`mock_start`]
+pub fn mock_flight_plan(
+    id: &str,
+    vehicle_id: &str,
+    departure_vertiport_id: &str,
+    destination_vertiport_id: &str,
+    minutes: i64,
+) -> FlightPlan {
+    MockFlightPlan::new(
+        id,
+        vehicle_id,
+        departure_vertiport_id,
+        destination_vertiport_id,
+    )
+    .with_departure(mock_start() + Duration::minutes(minutes))
+    .build()
+}
+
+/// Runs a future which never waits on I/O to completion, e.g. a query of
+/// [`get_possible_flights_async`](crate::providers::get_possible_flights_async)
+/// with providers holding their data in memory
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    fn raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            raw_waker()
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    // the waker does nothing, so its null data pointer is never read
+    let waker = unsafe { Waker::from_raw(raw_waker()) };
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Conversions between storage timestamps and date times.
+//!
+//! Flight plans carry `prost_types::Timestamp`s while the router computes with
+//! `chrono` date times in an `rrule::Tz` timezone. The converters here fail
+//! with an error instead of panicking on timestamps out of the range of
+//! `chrono` or with invalid nanoseconds.
+
+use chrono::{DateTime, NaiveDateTime, TimeZone};
+use prost_types::Timestamp;
+use rrule::Tz;
+
+/// Nanoseconds in a second
+const NANOS_PER_SECOND: i32 = 1_000_000_000;
+
+/// Converts seconds and nanoseconds since the epoch into a UTC date time
+pub fn seconds_to_datetime(seconds: i64, nanos: u32) -> Result<DateTime<Tz>, String> {
+    NaiveDateTime::from_timestamp_opt(seconds, nanos)
+        .map(|datetime| Tz::UTC.from_utc_datetime(&datetime))
+        .ok_or_else(|| format!("Time out of range: {}s {}ns", seconds, nanos))
+}
+
+/// Converts a storage timestamp into a UTC date time
+pub fn timestamp_to_datetime(timestamp: &Timestamp) -> Result<DateTime<Tz>, String> {
+    if !(0..NANOS_PER_SECOND).contains(&timestamp.nanos) {
+        return Err(format!("Invalid nanoseconds: {}", timestamp.nanos));
+    }
+    seconds_to_datetime(timestamp.seconds, timestamp.nanos as u32)
+}
+
+/// Converts an optional storage timestamp into a UTC date time, naming the
+/// missing field in the error
+pub fn required_datetime(
+    timestamp: Option<&Timestamp>,
+    name: &str,
+) -> Result<DateTime<Tz>, String> {
+    timestamp_to_datetime(timestamp.ok_or_else(|| format!("Missing {}", name))?)
+}
+
+/// Validates the requested time window of a flight
+///
+/// # Returns
+/// The earliest departure and latest arrival time, or an error if either is
+/// missing or invalid, or if the window doesn't end after it starts
+pub fn validate_flight_window(
+    earliest_departure_time: Option<&Timestamp>,
+    latest_arrival_time: Option<&Timestamp>,
+) -> Result<(DateTime<Tz>, DateTime<Tz>), String> {
+    let (Some(earliest_departure_time), Some(latest_arrival_time)) =
+        (earliest_departure_time, latest_arrival_time)
+    else {
+        return Err(
+            "Both earliest departure and latest arrival time must be specified".to_string(),
+        );
+    };
+    let earliest_departure_time = timestamp_to_datetime(earliest_departure_time)?;
+    let latest_arrival_time = timestamp_to_datetime(latest_arrival_time)?;
+    if latest_arrival_time <= earliest_departure_time {
+        return Err("Latest arrival time must be after earliest departure time".to_string());
+    }
+    Ok((earliest_departure_time, latest_arrival_time))
+}
+
+/// Converts a date time into a storage timestamp
+pub fn datetime_to_timestamp<T: TimeZone>(datetime: &DateTime<T>) -> Timestamp {
+    Timestamp {
+        seconds: datetime.timestamp(),
+        // leap seconds are represented by nanoseconds past a second
+        nanos: datetime
+            .timestamp_subsec_nanos()
+            .min(NANOS_PER_SECOND as u32 - 1) as i32,
+    }
+}
+
+#[cfg(test)]
+mod time_tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let datetime = Tz::UTC
+            .with_ymd_and_hms(2030, 6, 1, 8, 30, 15)
+            .unwrap()
+            .with_timezone(&Tz::America__Los_Angeles);
+        let timestamp = datetime_to_timestamp(&datetime);
+        assert_eq!(timestamp.seconds, 1906533015);
+        assert_eq!(
+            timestamp_to_datetime(&timestamp),
+            Ok(datetime.with_timezone(&Tz::UTC))
+        );
+        assert_eq!(
+            seconds_to_datetime(1906533015, 0),
+            Ok(datetime.with_timezone(&Tz::UTC))
+        );
+    }
+
+    #[test]
+    fn test_invalid_timestamps() {
+        assert!(timestamp_to_datetime(&Timestamp {
+            seconds: 0,
+            nanos: -1
+        })
+        .is_err());
+        assert!(timestamp_to_datetime(&Timestamp {
+            seconds: 0,
+            nanos: NANOS_PER_SECOND
+        })
+        .is_err());
+        assert!(timestamp_to_datetime(&Timestamp {
+            seconds: i64::MAX,
+            nanos: 0
+        })
+        .is_err());
+        assert_eq!(
+            required_datetime(None, "scheduled departure"),
+            Err("Missing scheduled departure".to_string())
+        );
+    }
+
+    #[test]
+    fn test_validate_flight_window() {
+        let earliest = Timestamp {
+            seconds: 1906533015,
+            nanos: 0,
+        };
+        let latest = Timestamp {
+            seconds: 1906536615,
+            nanos: 0,
+        };
+        let (from, to) = validate_flight_window(Some(&earliest), Some(&latest)).unwrap();
+        assert_eq!(to - from, chrono::Duration::hours(1));
+        assert_eq!(
+            validate_flight_window(Some(&earliest), None),
+            Err("Both earliest departure and latest arrival time must be specified".to_string())
+        );
+        assert_eq!(
+            validate_flight_window(Some(&latest), Some(&earliest)),
+            Err("Latest arrival time must be after earliest departure time".to_string())
+        );
+        assert!(validate_flight_window(
+            Some(&earliest),
+            Some(&Timestamp {
+                seconds: i64::MAX,
+                nanos: 0
+            })
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,363 @@
+//! Synthetic traffic for load testing.
+//!
+//! [`generate_flight_plans`] schedules every vehicle on back-to-back flights
+//! between random vertiports over a time horizon, so the resulting flight
+//! plans never overlap for a vehicle, each flight departs from where the
+//! previous one arrived and lasts as long as the flight time estimated from
+//! the distance between the vertiports. [`generate_booking_requests`] draws
+//! requests for flights between random vertiports over the same horizon, to
+//! be passed to [`get_possible_flights`](crate::router_state::get_possible_flights)
+//! along with the flight plans. Like the other generators, each has a
+//! `_with_seed` and a `_with_rng` variant.
+
+use chrono::{DateTime, Duration};
+use prost_types::Timestamp;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rrule::Tz;
+
+use crate::generator::generate_uuid;
+use crate::haversine;
+use crate::location::Location;
+use crate::router_state::{
+    create_flight_plan_data, estimate_flight_time_minutes, Aircraft, FlightPlan, Vehicle,
+    Vertiport, FLIGHT_PLAN_GAP_MINUTES,
+};
+use crate::time::datetime_to_timestamp;
+
+/// Longest a vehicle stays parked between two generated flights, on top of the gap
+const MAX_IDLE_MINUTES: i64 = 120;
+/// Largest slack of a booking request beyond the flight time
+const MAX_BOOKING_SLACK_MINUTES: i64 = 180;
+
+/// A request for a flight between two vertiports within a time window
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookingRequest {
+    /// id of the departure vertiport
+    pub departure_vertiport_id: String,
+    /// id of the arrival vertiport
+    pub arrival_vertiport_id: String,
+    /// earliest time the flight may depart
+    pub earliest_departure_time: Timestamp,
+    /// latest time the flight may arrive
+    pub latest_arrival_time: Timestamp,
+}
+
+/// Generate flight plans of the vehicles between the vertiports, departing
+/// and arriving between `start` and `end`.
+///
+/// A vehicle starts at its last vertiport if it is one of `vertiports`, or
+/// else at a random one, and then flies to random other vertiports, staying
+/// parked for at least [`FLIGHT_PLAN_GAP_MINUTES`] after each flight.
+///
+/// # Returns
+/// The flight plans ordered by vehicle and departure, or an error if there
+/// are less than two vertiports, a vertiport has no data or `end` is not
+/// after `start`.
+pub fn generate_flight_plans(
+    vertiports: &[Vertiport],
+    vehicles: &[Vehicle],
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+) -> Result<Vec<FlightPlan>, String> {
+    generate_flight_plans_with_rng(&mut rand::thread_rng(), vertiports, vehicles, start, end)
+}
+
+/// Generate flight plans, the same for the same seed.
+///
+/// See [`generate_flight_plans`].
+pub fn generate_flight_plans_with_seed(
+    seed: u64,
+    vertiports: &[Vertiport],
+    vehicles: &[Vehicle],
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+) -> Result<Vec<FlightPlan>, String> {
+    generate_flight_plans_with_rng(
+        &mut StdRng::seed_from_u64(seed),
+        vertiports,
+        vehicles,
+        start,
+        end,
+    )
+}
+
+/// Generate flight plans drawn from `rng`.
+///
+/// See [`generate_flight_plans`].
+pub fn generate_flight_plans_with_rng(
+    rng: &mut impl Rng,
+    vertiports: &[Vertiport],
+    vehicles: &[Vehicle],
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+) -> Result<Vec<FlightPlan>, String> {
+    let locations = vertiport_locations(vertiports, start, end)?;
+    let mut flight_plans = vec![];
+    for vehicle in vehicles {
+        let last_vertiport_id = vehicle
+            .data
+            .as_ref()
+            .and_then(|data| data.last_vertiport_id.as_deref());
+        let mut current = match locations
+            .iter()
+            .position(|(id, _)| Some(*id) == last_vertiport_id)
+        {
+            Some(current) => current,
+            None => rng.gen_range(0..locations.len()),
+        };
+        let mut departure = start + Duration::minutes(rng.gen_range(0..=MAX_IDLE_MINUTES));
+        loop {
+            let destination = other_vertiport(rng, locations.len(), current);
+            let arrival =
+                departure + flight_duration(&locations[current].1, &locations[destination].1);
+            if arrival > end {
+                break;
+            }
+            flight_plans.push(FlightPlan {
+                id: generate_uuid(rng),
+                data: Some(create_flight_plan_data(
+                    vehicle.id.clone(),
+                    locations[current].0.to_string(),
+                    locations[destination].0.to_string(),
+                    departure,
+                    arrival,
+                )),
+            });
+            current = destination;
+            departure = arrival
+                + Duration::minutes(
+                    FLIGHT_PLAN_GAP_MINUTES as i64 + rng.gen_range(0..=MAX_IDLE_MINUTES),
+                );
+        }
+    }
+    Ok(flight_plans)
+}
+
+/// Generate requests for flights between two different vertiports, with
+/// an earliest departure between `start` and `end`.
+///
+/// The window of each request lasts the estimated flight time plus a random
+/// slack of up to [`MAX_BOOKING_SLACK_MINUTES`].
+///
+/// # Returns
+/// The requests, or an error if there are less than two vertiports, a
+/// vertiport has no data or `end` is not after `start`.
+pub fn generate_booking_requests(
+    vertiports: &[Vertiport],
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+    capacity: i32,
+) -> Result<Vec<BookingRequest>, String> {
+    generate_booking_requests_with_rng(&mut rand::thread_rng(), vertiports, start, end, capacity)
+}
+
+/// Generate booking requests, the same for the same seed.
+///
+/// See [`generate_booking_requests`].
+pub fn generate_booking_requests_with_seed(
+    seed: u64,
+    vertiports: &[Vertiport],
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+    capacity: i32,
+) -> Result<Vec<BookingRequest>, String> {
+    generate_booking_requests_with_rng(
+        &mut StdRng::seed_from_u64(seed),
+        vertiports,
+        start,
+        end,
+        capacity,
+    )
+}
+
+/// Generate booking requests drawn from `rng`.
+///
+/// See [`generate_booking_requests`].
+pub fn generate_booking_requests_with_rng(
+    rng: &mut impl Rng,
+    vertiports: &[Vertiport],
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+    capacity: i32,
+) -> Result<Vec<BookingRequest>, String> {
+    let locations = vertiport_locations(vertiports, start, end)?;
+    let horizon_seconds = (end - start).num_seconds();
+    Ok((0..capacity)
+        .map(|_| {
+            let departure = rng.gen_range(0..locations.len());
+            let arrival = other_vertiport(rng, locations.len(), departure);
+            let earliest_departure_time =
+                start + Duration::minutes(rng.gen_range(0..horizon_seconds) / 60);
+            let latest_arrival_time = earliest_departure_time
+                + flight_duration(&locations[departure].1, &locations[arrival].1)
+                + Duration::minutes(rng.gen_range(0..=MAX_BOOKING_SLACK_MINUTES));
+            BookingRequest {
+                departure_vertiport_id: locations[departure].0.to_string(),
+                arrival_vertiport_id: locations[arrival].0.to_string(),
+                earliest_departure_time: datetime_to_timestamp(&earliest_departure_time),
+                latest_arrival_time: datetime_to_timestamp(&latest_arrival_time),
+            }
+        })
+        .collect())
+}
+
+/// Gets the id and location of each vertiport, checking there are enough
+/// vertiports to fly between within a valid horizon
+fn vertiport_locations(
+    vertiports: &[Vertiport],
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+) -> Result<Vec<(&str, Location)>, String> {
+    if end <= start {
+        return Err("The end of the horizon must be after its start".to_string());
+    }
+    if vertiports.len() < 2 {
+        return Err("At least two vertiports are required".to_string());
+    }
+    vertiports
+        .iter()
+        .map(|vertiport| {
+            let data = vertiport
+                .data
+                .as_ref()
+                .ok_or_else(|| format!("Vertiport {} has no data", vertiport.id))?;
+            Ok((
+                vertiport.id.as_str(),
+                Location {
+                    latitude: (data.latitude as f32).into(),
+                    longitude: (data.longitude as f32).into(),
+                    altitude_meters: 0.0.into(),
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Picks a random vertiport index other than `current`
+fn other_vertiport(rng: &mut impl Rng, count: usize, current: usize) -> usize {
+    let others = (0..count)
+        .filter(|index| *index != current)
+        .collect::<Vec<_>>();
+    *others.choose(rng).unwrap_or(&current)
+}
+
+/// Estimated duration of a cargo flight between two locations, rounded up to the minute
+fn flight_duration(from: &Location, to: &Location) -> Duration {
+    let minutes = estimate_flight_time_minutes(haversine::distance(from, to), Aircraft::Cargo);
+    Duration::minutes(minutes.ceil() as i64)
+}
+
+#[cfg(test)]
+mod traffic_tests {
+    use super::*;
+    use crate::amendment::PlanTimes;
+    use crate::resources::{vehicle, vertiport};
+    use chrono::TimeZone;
+    use std::collections::HashMap;
+
+    fn vertiports() -> Vec<Vertiport> {
+        [
+            ("SFO", 37.6213, -122.379),
+            ("OAK", 37.7126, -122.2197),
+            ("SJC", 37.3639, -121.9289),
+        ]
+        .into_iter()
+        .map(|(id, latitude, longitude)| Vertiport {
+            id: id.to_string(),
+            data: Some(vertiport::Data {
+                latitude,
+                longitude,
+                ..Default::default()
+            }),
+        })
+        .collect()
+    }
+
+    fn vehicles() -> Vec<Vehicle> {
+        (1..=4)
+            .map(|n| Vehicle {
+                id: format!("vehicle-{}", n),
+                data: Some(vehicle::Data {
+                    last_vertiport_id: (n == 1).then(|| "OAK".to_string()),
+                    ..Default::default()
+                }),
+            })
+            .collect()
+    }
+
+    fn horizon() -> (DateTime<Tz>, DateTime<Tz>) {
+        let start = Tz::UTC.with_ymd_and_hms(2030, 1, 1, 6, 0, 0).unwrap();
+        (start, start + Duration::hours(12))
+    }
+
+    #[test]
+    fn test_generate_flight_plans() {
+        let (start, end) = horizon();
+        let flight_plans =
+            generate_flight_plans_with_seed(3622, &vertiports(), &vehicles(), start, end).unwrap();
+        assert!(flight_plans.len() >= vehicles().len());
+        assert_eq!(
+            flight_plans,
+            generate_flight_plans_with_seed(3622, &vertiports(), &vehicles(), start, end).unwrap()
+        );
+
+        let vertiports = vertiports();
+        let locations = vertiport_locations(&vertiports, start, end)
+            .unwrap()
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        let mut last_plans: HashMap<String, PlanTimes> = HashMap::new();
+        for flight_plan in &flight_plans {
+            let plan = PlanTimes::from_flight_plan(flight_plan).unwrap();
+            assert_ne!(plan.departure_vertiport_id, plan.destination_vertiport_id);
+            assert!(plan.departure >= start.timestamp() && plan.arrival <= end.timestamp());
+            let duration = flight_duration(
+                &locations[plan.departure_vertiport_id.as_str()],
+                &locations[plan.destination_vertiport_id.as_str()],
+            );
+            assert_eq!(plan.arrival - plan.departure, duration.num_seconds());
+            match last_plans.get(&plan.vehicle_id) {
+                Some(last) => {
+                    assert_eq!(last.destination_vertiport_id, plan.departure_vertiport_id);
+                    assert!(plan.departure - last.arrival >= FLIGHT_PLAN_GAP_MINUTES as i64 * 60);
+                }
+                None if plan.vehicle_id == "vehicle-1" => {
+                    assert_eq!(plan.departure_vertiport_id, "OAK")
+                }
+                None => (),
+            }
+            last_plans.insert(plan.vehicle_id.clone(), plan);
+        }
+        assert_eq!(last_plans.len(), vehicles().len());
+    }
+
+    #[test]
+    fn test_generate_booking_requests() {
+        let (start, end) = horizon();
+        let requests =
+            generate_booking_requests_with_seed(3622, &vertiports(), start, end, 50).unwrap();
+        assert_eq!(requests.len(), 50);
+        for request in requests {
+            assert_ne!(request.departure_vertiport_id, request.arrival_vertiport_id);
+            assert!(request.earliest_departure_time.seconds >= start.timestamp());
+            assert!(request.earliest_departure_time.seconds < end.timestamp());
+            // long enough for the flight between the closest vertiports, OAK and SFO
+            assert!(
+                request.latest_arrival_time.seconds - request.earliest_departure_time.seconds
+                    >= 20 * 60
+            );
+        }
+    }
+
+    #[test]
+    fn test_generate_invalid_traffic() {
+        let (start, end) = horizon();
+        assert!(generate_flight_plans(&vertiports()[..1], &vehicles(), start, end).is_err());
+        assert!(generate_flight_plans(&vertiports(), &vehicles(), end, start).is_err());
+        let mut vertiports = vertiports();
+        vertiports[0].data = None;
+        assert!(generate_booking_requests(&vertiports, start, end, 10).is_err());
+    }
+}
@@ -0,0 +1,405 @@
+//! Turnaround time between consecutive flights of a vehicle.
+//!
+//! The flight block time already includes a constant loading and unloading
+//! time, but the time a vehicle needs on the ground before its next flight
+//! (unloading, recharging, pre-flight checks, loading) depends on the aircraft
+//! and on whether the legs carry cargo or are deadhead flights. A
+//! [`TurnaroundModel`] set with [`set_turnaround_model`] is consulted when a
+//! vehicle's next flight is chained to its previous one. The default model
+//! adds no extra time.
+//!
+//! At large vertiports a vehicle leaves the pad it landed on for a parking
+//! or charging stand, and taxis or is towed back to a pad before its next
+//! flight. The minutes of each of these movements are configured per
+//! vertiport id with [`set_taxi_minutes`] and added to the turnaround,
+//! unless the vehicle departs from the pad it landed on.
+
+use chrono::{DateTime, Duration};
+use rrule::Tz;
+use std::collections::HashMap;
+
+use crate::amendment::PlanTimes;
+use crate::context::current_context;
+use crate::router_state::{FlightPlan, FlightPlanData, Vehicle};
+
+/// Whether a leg carries cargo
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LegKind {
+    /// leg carrying cargo
+    Loaded,
+    /// empty repositioning leg
+    Deadhead,
+}
+
+impl LegKind {
+    /// Gets the kind of a flight plan from its cargo
+    pub fn of(flight_plan: &FlightPlanData) -> Self {
+        if flight_plan
+            .cargo_weight_grams
+            .iter()
+            .any(|weight| *weight > 0)
+        {
+            LegKind::Loaded
+        } else {
+            LegKind::Deadhead
+        }
+    }
+}
+
+/// Model of the ground time between two consecutive flights of a vehicle
+pub trait TurnaroundModel: Send + Sync {
+    /// Minutes needed after the arrival of the `previous` leg before the `next` leg can depart
+    fn turnaround_minutes(&self, vehicle: &Vehicle, previous: LegKind, next: LegKind) -> i64;
+}
+
+/// Same turnaround time for every vehicle and leg
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ConstantTurnaround(pub i64);
+
+impl TurnaroundModel for ConstantTurnaround {
+    fn turnaround_minutes(&self, _vehicle: &Vehicle, _previous: LegKind, _next: LegKind) -> i64 {
+        self.0
+    }
+}
+
+/// Ground activities of a turnaround, in minutes
+#[derive(Debug, Copy, Clone, Default)]
+pub struct TurnaroundTimes {
+    /// unloading after a loaded leg
+    pub unloading_minutes: i64,
+    /// recharging between any two legs
+    pub recharging_minutes: i64,
+    /// pre-flight checks before any leg
+    pub preflight_minutes: i64,
+    /// loading before a loaded leg
+    pub loading_minutes: i64,
+}
+
+impl TurnaroundTimes {
+    /// Total turnaround minutes between the two legs
+    pub fn total_minutes(&self, previous: LegKind, next: LegKind) -> i64 {
+        let unloading = match previous {
+            LegKind::Loaded => self.unloading_minutes,
+            LegKind::Deadhead => 0,
+        };
+        let loading = match next {
+            LegKind::Loaded => self.loading_minutes,
+            LegKind::Deadhead => 0,
+        };
+        unloading + self.recharging_minutes + self.preflight_minutes + loading
+    }
+}
+
+/// Turnaround times by vehicle model
+#[derive(Debug, Clone, Default)]
+pub struct TurnaroundTable {
+    /// times for vehicle models not in the table
+    pub default: TurnaroundTimes,
+    /// times by vehicle model id
+    pub by_vehicle_model: HashMap<String, TurnaroundTimes>,
+}
+
+impl TurnaroundModel for TurnaroundTable {
+    fn turnaround_minutes(&self, vehicle: &Vehicle, previous: LegKind, next: LegKind) -> i64 {
+        vehicle
+            .data
+            .as_ref()
+            .and_then(|data| self.by_vehicle_model.get(&data.vehicle_model_id))
+            .unwrap_or(&self.default)
+            .total_minutes(previous, next)
+    }
+}
+
+/// Sets the turnaround model used when chaining flights
+pub fn set_turnaround_model(model: Box<dyn TurnaroundModel>) {
+    match current_context().turnaround_model.write() {
+        Ok(mut current) => *current = model,
+        Err(_) => error!("Turnaround model unavailable"),
+    }
+}
+
+/// Sets the minutes to taxi or tow a vehicle between a pad and a parking or
+/// charging stand of a vertiport
+pub fn set_taxi_minutes(vertiport_id: &str, minutes: i64) {
+    match current_context().taxi_minutes.write() {
+        Ok(mut taxi_minutes) => {
+            taxi_minutes.insert(vertiport_id.to_string(), minutes);
+        }
+        Err(_) => error!("Taxi minutes unavailable"),
+    }
+}
+
+/// Removes the taxi minutes of a vertiport, vehicles no longer move between
+/// its pads and stands
+pub fn clear_taxi_minutes(vertiport_id: &str) {
+    match current_context().taxi_minutes.write() {
+        Ok(mut taxi_minutes) => {
+            taxi_minutes.remove(vertiport_id);
+        }
+        Err(_) => error!("Taxi minutes unavailable"),
+    }
+}
+
+/// Gets the minutes to taxi or tow a vehicle between a pad and a stand of a
+/// vertiport, 0 if none are configured
+pub fn get_taxi_minutes(vertiport_id: &str) -> i64 {
+    current_context()
+        .taxi_minutes
+        .read()
+        .ok()
+        .and_then(|taxi_minutes| taxi_minutes.get(vertiport_id).copied())
+        .unwrap_or(0)
+        .max(0)
+}
+
+/// Minutes moving a vehicle on the ground of a vertiport between landing and
+/// departing again, to a stand and back to a pad
+///
+/// # Arguments
+/// * `vertiport_id` - the vertiport of the turnaround
+/// * `arrival_vertipad_id` - pad the vehicle landed on
+/// * `departure_vertipad_id` - pad of the next departure, None if not
+///   assigned yet, in which case it's assumed to be another pad
+///
+/// # Returns
+/// No time if the vehicle departs from the pad it landed on, twice the
+/// taxi minutes of the vertiport otherwise
+pub fn ground_movement_minutes(
+    vertiport_id: &str,
+    arrival_vertipad_id: &str,
+    departure_vertipad_id: Option<&str>,
+) -> i64 {
+    match departure_vertipad_id {
+        Some(pad) if !pad.is_empty() && pad == arrival_vertipad_id => 0,
+        _ => 2 * get_taxi_minutes(vertiport_id),
+    }
+}
+
+/// Checks with the given model if the vehicle finished the turnaround after
+/// its previous flight in time for a `next` leg departing at `departure_time`
+/// from a pad not assigned yet
+pub fn is_vehicle_turned_around_with(
+    model: &dyn TurnaroundModel,
+    vehicle: &Vehicle,
+    departure_time: DateTime<Tz>,
+    next: LegKind,
+    existing_flight_plans: &[FlightPlan],
+) -> bool {
+    is_vehicle_turned_around_at_pad_with(
+        model,
+        vehicle,
+        departure_time,
+        next,
+        None,
+        existing_flight_plans,
+    )
+}
+
+/// Checks with the given model if the vehicle finished the turnaround after
+/// its previous flight, including its movements on the ground, in time for a
+/// `next` leg departing at `departure_time` from `departure_vertipad_id`
+pub fn is_vehicle_turned_around_at_pad_with(
+    model: &dyn TurnaroundModel,
+    vehicle: &Vehicle,
+    departure_time: DateTime<Tz>,
+    next: LegKind,
+    departure_vertipad_id: Option<&str>,
+    existing_flight_plans: &[FlightPlan],
+) -> bool {
+    let previous = existing_flight_plans
+        .iter()
+        .filter(|flight_plan| {
+            flight_plan
+                .data
+                .as_ref()
+                .is_some_and(|data| data.vehicle_id == vehicle.id)
+        })
+        .filter_map(|flight_plan| {
+            let plan = PlanTimes::from_flight_plan(flight_plan).ok()?;
+            (plan.departure <= departure_time.timestamp()).then_some((plan, flight_plan))
+        })
+        .max_by_key(|(plan, _)| plan.departure);
+    let Some((plan, flight_plan)) = previous else {
+        return true;
+    };
+    let Some(data) = flight_plan.data.as_ref() else {
+        return true;
+    };
+    let minutes = model.turnaround_minutes(vehicle, LegKind::of(data), next)
+        + ground_movement_minutes(
+            &plan.destination_vertiport_id,
+            &plan.destination_vertipad_id,
+            departure_vertipad_id,
+        );
+    let ready = plan.arrival + Duration::minutes(minutes).num_seconds();
+    if ready > departure_time.timestamp() {
+        debug!(
+            "Vehicle {} needs {} minutes of turnaround after flight plan {}",
+            vehicle.id, minutes, flight_plan.id
+        );
+        return false;
+    }
+    true
+}
+
+/// Checks if the vehicle finished the turnaround after its previous flight in
+/// time for a `next` leg departing at `departure_time`
+pub fn is_vehicle_turned_around(
+    vehicle: &Vehicle,
+    departure_time: DateTime<Tz>,
+    next: LegKind,
+    existing_flight_plans: &[FlightPlan],
+) -> bool {
+    match current_context().turnaround_model.read() {
+        Ok(model) => is_vehicle_turned_around_with(
+            model.as_ref(),
+            vehicle,
+            departure_time,
+            next,
+            existing_flight_plans,
+        ),
+        Err(_) => {
+            error!("Turnaround model unavailable");
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod turnaround_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use chrono::TimeZone;
+
+    fn vehicle(model: &str) -> Vehicle {
+        Vehicle {
+            id: "v1".to_string(),
+            data: Some(crate::resources::vehicle::Data {
+                vehicle_model_id: model.to_string(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn table() -> TurnaroundTable {
+        TurnaroundTable {
+            default: TurnaroundTimes {
+                unloading_minutes: 10,
+                recharging_minutes: 20,
+                preflight_minutes: 5,
+                loading_minutes: 10,
+            },
+            by_vehicle_model: HashMap::from([(
+                "fast-charge".to_string(),
+                TurnaroundTimes {
+                    unloading_minutes: 10,
+                    recharging_minutes: 5,
+                    preflight_minutes: 5,
+                    loading_minutes: 10,
+                },
+            )]),
+        }
+    }
+
+    #[test]
+    fn test_turnaround_table() {
+        let table = table();
+        let loaded = LegKind::Loaded;
+        let deadhead = LegKind::Deadhead;
+        assert_eq!(table.turnaround_minutes(&vehicle("x"), loaded, loaded), 45);
+        assert_eq!(
+            table.turnaround_minutes(&vehicle("x"), loaded, deadhead),
+            35
+        );
+        assert_eq!(
+            table.turnaround_minutes(&vehicle("x"), deadhead, deadhead),
+            25
+        );
+        assert_eq!(
+            table.turnaround_minutes(&vehicle("fast-charge"), loaded, loaded),
+            30
+        );
+    }
+
+    #[test]
+    fn test_is_vehicle_turned_around() {
+        let start = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap();
+        let mut data = create_flight_plan_data(
+            "v1".to_string(),
+            "A".to_string(),
+            "B".to_string(),
+            start,
+            start + Duration::minutes(30),
+        );
+        data.cargo_weight_grams = vec![1000];
+        let flight_plans = vec![FlightPlan {
+            id: "fp1".to_string(),
+            data: Some(data),
+        }];
+        let table = table();
+        let vehicle = vehicle("x");
+        let check = |minutes: i64, next: LegKind| {
+            is_vehicle_turned_around_with(
+                &table,
+                &vehicle,
+                start + Duration::minutes(minutes),
+                next,
+                &flight_plans,
+            )
+        };
+        assert!(!check(70, LegKind::Loaded));
+        assert!(check(75, LegKind::Loaded));
+        assert!(check(65, LegKind::Deadhead));
+        // before the previous flight
+        assert!(check(-60, LegKind::Loaded));
+        assert!(is_vehicle_turned_around_with(
+            &ConstantTurnaround(0),
+            &vehicle,
+            start + Duration::minutes(30),
+            LegKind::Loaded,
+            &flight_plans,
+        ));
+    }
+
+    #[test]
+    fn test_turnaround_with_taxi() {
+        let start = Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap();
+        let mut data = create_flight_plan_data(
+            "v1".to_string(),
+            "A".to_string(),
+            "TAXI-B".to_string(),
+            start,
+            start + Duration::minutes(30),
+        );
+        data.destination_vertipad_id = "pad-1".to_string();
+        let flight_plans = vec![FlightPlan {
+            id: "fp1".to_string(),
+            data: Some(data),
+        }];
+        let vehicle = vehicle("x");
+        let check = |minutes: i64, pad: Option<&str>| {
+            is_vehicle_turned_around_at_pad_with(
+                &ConstantTurnaround(10),
+                &vehicle,
+                start + Duration::minutes(minutes),
+                LegKind::Deadhead,
+                pad,
+                &flight_plans,
+            )
+        };
+        assert!(check(40, None));
+
+        set_taxi_minutes("TAXI-B", 5);
+        assert_eq!(get_taxi_minutes("TAXI-B"), 5);
+        assert_eq!(ground_movement_minutes("TAXI-B", "pad-1", Some("pad-1")), 0);
+        // to a stand and back to a pad
+        assert!(!check(45, None));
+        assert!(!check(45, Some("pad-2")));
+        assert!(check(50, Some("pad-2")));
+        assert!(check(40, Some("pad-1")));
+
+        clear_taxi_minutes("TAXI-B");
+        assert_eq!(get_taxi_minutes("TAXI-B"), 0);
+        assert!(check(40, None));
+    }
+}
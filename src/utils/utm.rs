@@ -0,0 +1,359 @@
+//! Strategic deconfliction with a UAS traffic management (UTM) service.
+//!
+//! Before [`get_possible_flights`](crate::router_state::get_possible_flights)
+//! returns a flight, the operation intents of the flight and of its deadhead
+//! flights can be submitted to an external [`UtmService`] set with
+//! [`set_utm_service`]. Flights with an intent in conflict with other
+//! operations or with the constraints of the airspace are not returned, and
+//! the intents already accepted for them are withdrawn. The ids of the
+//! accepted intents are returned with the flight, so the caller can withdraw
+//! the intents of the flights it doesn't book.
+
+use chrono::DateTime;
+use rrule::Tz;
+use std::sync::{Arc, Mutex};
+
+use crate::amendment::{timestamp_to_datetime, PlanTimes};
+use crate::context::current_context;
+use crate::location::Location;
+use crate::router_state::{get_node_by_id, get_route, Aircraft, FlightPlanData, RouteQuery};
+
+/// Planned operation submitted to the UTM service
+#[derive(Debug, Clone, PartialEq)]
+pub struct OperationIntent {
+    /// vehicle flying the operation
+    pub vehicle_id: String,
+    /// vertiport the operation departs from
+    pub departure_vertiport_id: String,
+    /// vertiport the operation arrives at
+    pub destination_vertiport_id: String,
+    /// locations along the route
+    pub route: Vec<Location>,
+    /// start of the operation, including loading and takeoff
+    pub start: DateTime<Tz>,
+    /// end of the operation, including landing and unloading
+    pub end: DateTime<Tz>,
+}
+
+impl OperationIntent {
+    /// Creates the intent of a flight plan flown along `route`
+    pub fn new(data: &FlightPlanData, route: Vec<Location>) -> Result<Self, String> {
+        let plan = PlanTimes::from_data("draft", data)?;
+        Ok(OperationIntent {
+            vehicle_id: plan.vehicle_id,
+            departure_vertiport_id: plan.departure_vertiport_id,
+            destination_vertiport_id: plan.destination_vertiport_id,
+            route,
+            start: timestamp_to_datetime(plan.departure),
+            end: timestamp_to_datetime(plan.arrival),
+        })
+    }
+}
+
+/// Creates the intent of a flight plan flown along the route of the router
+/// between its vertiports
+pub fn flight_plan_intent(data: &FlightPlanData) -> Result<OperationIntent, String> {
+    let (Some(from), Some(to)) = (
+        data.departure_vertiport_id.as_deref(),
+        data.destination_vertiport_id.as_deref(),
+    ) else {
+        return Err("Flight plan has no departure or destination vertiport".to_string());
+    };
+    let from = get_node_by_id(from)?;
+    let route = if from.uid == to {
+        vec![from.location]
+    } else {
+        let (route, _) = get_route(RouteQuery::new(Aircraft::Cargo, from, get_node_by_id(to)?))?;
+        route
+    };
+    OperationIntent::new(data, route)
+}
+
+/// Operation of another operator in conflict with an intent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UtmConflict {
+    /// id of the conflicting operation
+    pub operation_id: String,
+    /// description of the conflict
+    pub description: String,
+}
+
+/// Airspace constraint an intent doesn't comply with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UtmConstraint {
+    /// id of the constraint
+    pub constraint_id: String,
+    /// description of the constraint
+    pub description: String,
+}
+
+/// Response of the UTM service to an operation intent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntentResponse {
+    /// the intent is accepted and can be referenced by its id
+    Accepted {
+        /// id of the intent in the UTM service
+        intent_id: String,
+    },
+    /// the intent conflicts with other operations
+    Conflicts(Vec<UtmConflict>),
+    /// the intent doesn't comply with airspace constraints
+    Constrained(Vec<UtmConstraint>),
+}
+
+/// External UAS traffic management service
+pub trait UtmService: Send + Sync {
+    /// Submits an operation intent for strategic deconfliction
+    fn submit_intent(&self, intent: &OperationIntent) -> Result<IntentResponse, String>;
+
+    /// Withdraws an accepted intent
+    fn withdraw_intent(&self, intent_id: &str) -> Result<(), String>;
+}
+
+/// Submits the intents of a flight and its deadhead flights
+///
+/// # Arguments
+/// * `service` - UTM service deconflicting the intents
+/// * `intents` - intents of the flight and its deadhead flights
+///
+/// # Returns
+/// The ids of the accepted intents, or the reason an intent was not accepted.
+/// Intents accepted before a rejected one are withdrawn.
+pub fn deconflict_flight(
+    service: &dyn UtmService,
+    intents: &[OperationIntent],
+) -> Result<Vec<String>, String> {
+    let mut intent_ids: Vec<String> = vec![];
+    for intent in intents {
+        let reason = match service.submit_intent(intent) {
+            Ok(IntentResponse::Accepted { intent_id }) => {
+                intent_ids.push(intent_id);
+                continue;
+            }
+            Ok(IntentResponse::Conflicts(conflicts)) => format!(
+                "Intent conflicts with operations {:?}",
+                conflicts
+                    .iter()
+                    .map(|conflict| conflict.operation_id.as_str())
+                    .collect::<Vec<_>>()
+            ),
+            Ok(IntentResponse::Constrained(constraints)) => format!(
+                "Intent violates constraints {:?}",
+                constraints
+                    .iter()
+                    .map(|constraint| constraint.constraint_id.as_str())
+                    .collect::<Vec<_>>()
+            ),
+            Err(e) => format!("UTM service unavailable: {}", e),
+        };
+        for intent_id in &intent_ids {
+            if let Err(e) = service.withdraw_intent(intent_id) {
+                error!("Unable to withdraw intent {}: {}", intent_id, e);
+            }
+        }
+        return Err(reason);
+    }
+    Ok(intent_ids)
+}
+
+/// UTM service deconflicting against configured operations and constraints
+/// by time only, for tests
+#[derive(Debug, Default)]
+pub struct MockUtmService {
+    /// operations of other operators by id, with their start and end
+    operations: Vec<(String, DateTime<Tz>, DateTime<Tz>)>,
+    /// constraints by id, with their start and end
+    constraints: Vec<(String, DateTime<Tz>, DateTime<Tz>)>,
+    /// accepted intents by id
+    accepted: Mutex<Vec<(String, OperationIntent)>>,
+    /// number of intents submitted, used for the intent ids
+    submitted: Mutex<usize>,
+}
+
+impl MockUtmService {
+    /// Creates a service accepting every intent
+    pub fn new() -> Self {
+        MockUtmService::default()
+    }
+
+    /// Adds an operation conflicting with the intents overlapping it
+    pub fn with_operation(mut self, id: &str, start: DateTime<Tz>, end: DateTime<Tz>) -> Self {
+        self.operations.push((id.to_string(), start, end));
+        self
+    }
+
+    /// Adds a constraint rejecting the intents overlapping it
+    pub fn with_constraint(mut self, id: &str, start: DateTime<Tz>, end: DateTime<Tz>) -> Self {
+        self.constraints.push((id.to_string(), start, end));
+        self
+    }
+
+    /// Gets the intents accepted and not withdrawn
+    pub fn accepted_intents(&self) -> Vec<(String, OperationIntent)> {
+        self.accepted
+            .lock()
+            .map(|accepted| accepted.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl UtmService for MockUtmService {
+    fn submit_intent(&self, intent: &OperationIntent) -> Result<IntentResponse, String> {
+        let overlapping = |windows: &[(String, DateTime<Tz>, DateTime<Tz>)]| {
+            windows
+                .iter()
+                .filter(|(_, start, end)| *start < intent.end && intent.start < *end)
+                .map(|(id, _, _)| id.clone())
+                .collect::<Vec<_>>()
+        };
+        let constraints = overlapping(&self.constraints);
+        if !constraints.is_empty() {
+            return Ok(IntentResponse::Constrained(
+                constraints
+                    .into_iter()
+                    .map(|constraint_id| UtmConstraint {
+                        constraint_id,
+                        description: "Airspace restricted".to_string(),
+                    })
+                    .collect(),
+            ));
+        }
+        let conflicts = overlapping(&self.operations);
+        if !conflicts.is_empty() {
+            return Ok(IntentResponse::Conflicts(
+                conflicts
+                    .into_iter()
+                    .map(|operation_id| UtmConflict {
+                        operation_id,
+                        description: "Overlapping operation".to_string(),
+                    })
+                    .collect(),
+            ));
+        }
+
+        let mut submitted = self.submitted.lock().map_err(|e| e.to_string())?;
+        *submitted += 1;
+        let intent_id = format!("mock-intent-{}", *submitted);
+        self.accepted
+            .lock()
+            .map_err(|e| e.to_string())?
+            .push((intent_id.clone(), intent.clone()));
+        Ok(IntentResponse::Accepted { intent_id })
+    }
+
+    fn withdraw_intent(&self, intent_id: &str) -> Result<(), String> {
+        let mut accepted = self.accepted.lock().map_err(|e| e.to_string())?;
+        let count = accepted.len();
+        accepted.retain(|(id, _)| id != intent_id);
+        if accepted.len() == count {
+            return Err(format!("Unknown intent {}", intent_id));
+        }
+        Ok(())
+    }
+}
+
+/// Sets the service deconflicting the possible flights
+pub fn set_utm_service(service: Arc<dyn UtmService>) {
+    match current_context().utm_service.write() {
+        Ok(mut current) => *current = Some(service),
+        Err(_) => error!("UTM service unavailable"),
+    }
+}
+
+/// Stops deconflicting the possible flights
+pub fn clear_utm_service() {
+    match current_context().utm_service.write() {
+        Ok(mut current) => *current = None,
+        Err(_) => error!("UTM service unavailable"),
+    }
+}
+
+/// Gets the service deconflicting the possible flights, if any
+pub fn get_utm_service() -> Option<Arc<dyn UtmService>> {
+    current_context()
+        .utm_service
+        .read()
+        .map(|service| service.clone())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod utm_tests {
+    use super::*;
+    use crate::router_state::create_flight_plan_data;
+    use chrono::{Duration, TimeZone};
+
+    fn noon() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2030, 6, 1, 12, 0, 0).unwrap()
+    }
+
+    fn intent(vehicle_id: &str, departure: DateTime<Tz>) -> OperationIntent {
+        let data = create_flight_plan_data(
+            vehicle_id.to_string(),
+            "utm-A".to_string(),
+            "utm-B".to_string(),
+            departure,
+            departure + Duration::minutes(30),
+        );
+        OperationIntent::new(&data, vec![]).unwrap()
+    }
+
+    #[test]
+    fn test_operation_intent() {
+        let intent = intent("utm-vehicle", noon());
+        assert_eq!(intent.vehicle_id, "utm-vehicle");
+        assert_eq!(intent.departure_vertiport_id, "utm-A");
+        assert_eq!(intent.destination_vertiport_id, "utm-B");
+        assert_eq!(intent.start, noon());
+        assert_eq!(intent.end, noon() + Duration::minutes(30));
+    }
+
+    #[test]
+    fn test_mock_responses() {
+        let service = MockUtmService::new()
+            .with_operation("op-1", noon(), noon() + Duration::hours(1))
+            .with_constraint(
+                "tfr-1",
+                noon() + Duration::hours(2),
+                noon() + Duration::hours(3),
+            );
+
+        let response = service.submit_intent(&intent("utm-1", noon())).unwrap();
+        assert!(
+            matches!(response, IntentResponse::Conflicts(conflicts) if conflicts[0].operation_id == "op-1")
+        );
+        let response = service
+            .submit_intent(&intent("utm-1", noon() + Duration::hours(2)))
+            .unwrap();
+        assert!(matches!(response, IntentResponse::Constrained(_)));
+        let response = service
+            .submit_intent(&intent("utm-1", noon() + Duration::hours(4)))
+            .unwrap();
+        let IntentResponse::Accepted { intent_id } = response else {
+            panic!("Intent not accepted: {:?}", response);
+        };
+        assert_eq!(service.accepted_intents().len(), 1);
+
+        service.withdraw_intent(&intent_id).unwrap();
+        assert!(service.accepted_intents().is_empty());
+        assert!(service.withdraw_intent(&intent_id).is_err());
+    }
+
+    #[test]
+    fn test_deconflict_flight() {
+        let service =
+            MockUtmService::new().with_operation("op-1", noon(), noon() + Duration::hours(1));
+
+        let deadhead = intent("utm-1", noon() - Duration::hours(1));
+        let flight = intent("utm-1", noon() + Duration::hours(1));
+        let intent_ids = deconflict_flight(&service, &[deadhead.clone(), flight]).unwrap();
+        assert_eq!(intent_ids.len(), 2);
+        assert_eq!(service.accepted_intents().len(), 2);
+
+        // the accepted deadhead intent is withdrawn with the rejected flight
+        let flight = intent("utm-2", noon());
+        let result = deconflict_flight(&service, &[deadhead, flight]);
+        assert!(result.unwrap_err().contains("op-1"));
+        assert_eq!(service.accepted_intents().len(), 2);
+    }
+}
@@ -0,0 +1,417 @@
+//! Daily fleet vehicle routing.
+//!
+//! [`solve_daily_vrp`] builds a full-day schedule for every aircraft of the
+//! fleet from the day's confirmed shipments, instead of matching one request
+//! at a time. Shipments are inserted in order of their delivery deadline into
+//! the schedule of the vehicle which delivers them earliest. A vehicle flies
+//! empty to the pickup vertiport if needed (repositioning) and recharges at
+//! its current vertiport when the next flights would exceed its endurance.
+//! Flights are postponed to avoid takeoffs and landings during vertiport curfews.
+//! Each shipment is flown directly from pickup to delivery; combining
+//! shipments on one flight is left to the consolidation logic.
+
+use chrono::{DateTime, Duration};
+use rrule::Tz;
+
+use crate::curfew::next_departure_outside_curfew;
+use crate::multistop::Shipment;
+use crate::router_state::{create_flight_plan_data, estimate_flight_time_between, FlightPlanData};
+
+/// A vehicle of the fleet available for the day
+#[derive(Debug, Clone)]
+pub struct FleetVehicle {
+    /// id of the vehicle
+    pub vehicle_id: String,
+    /// vertiport where the vehicle starts the day
+    pub start_vertiport_id: String,
+    /// time from which the vehicle is available
+    pub available_from: DateTime<Tz>,
+    /// payload limit of the vehicle
+    pub capacity_grams: i64,
+}
+
+/// Charging parameters of the fleet
+#[derive(Debug, Copy, Clone)]
+pub struct ChargingConfig {
+    /// flight minutes possible on a full charge
+    pub max_flight_minutes: i64,
+    /// minutes needed for a full charge
+    pub charge_minutes: i64,
+}
+
+impl Default for ChargingConfig {
+    fn default() -> Self {
+        ChargingConfig {
+            max_flight_minutes: 120,
+            charge_minutes: 45,
+        }
+    }
+}
+
+/// An activity of a vehicle in the daily schedule
+#[derive(Debug, Clone, PartialEq)]
+pub enum Activity {
+    /// empty flight to the next pickup
+    Reposition(FlightPlanData),
+    /// flight carrying a shipment
+    Delivery {
+        /// id of the delivered shipment
+        shipment_id: String,
+        /// flight plan of the delivery
+        flight_plan: FlightPlanData,
+    },
+    /// charging at a vertiport
+    Charge {
+        /// vertiport where the vehicle charges
+        vertiport_id: String,
+        /// start of charging
+        start: DateTime<Tz>,
+        /// end of charging
+        end: DateTime<Tz>,
+    },
+}
+
+impl Activity {
+    /// Returns the flight plan of a flight activity
+    pub fn flight_plan(&self) -> Option<&FlightPlanData> {
+        match self {
+            Activity::Reposition(flight_plan) => Some(flight_plan),
+            Activity::Delivery { flight_plan, .. } => Some(flight_plan),
+            Activity::Charge { .. } => None,
+        }
+    }
+}
+
+/// Daily schedule of a vehicle
+#[derive(Debug, Clone)]
+pub struct VehicleSchedule {
+    /// id of the vehicle
+    pub vehicle_id: String,
+    /// activities in chronological order
+    pub activities: Vec<Activity>,
+}
+
+/// Daily schedule of the fleet
+#[derive(Debug, Clone)]
+pub struct DaySchedule {
+    /// schedules of all vehicles, in fleet order
+    pub vehicles: Vec<VehicleSchedule>,
+    /// ids of shipments which could not be scheduled
+    pub unassigned: Vec<String>,
+}
+
+/// Where a vehicle is after its last scheduled activity
+#[derive(Debug, Clone)]
+pub(crate) struct VehicleState {
+    pub(crate) vertiport_id: String,
+    pub(crate) time: DateTime<Tz>,
+    pub(crate) flight_minutes_since_charge: i64,
+}
+
+impl VehicleState {
+    /// State of the vehicle at the start of the day
+    pub(crate) fn initial(vehicle: &FleetVehicle) -> Self {
+        VehicleState {
+            vertiport_id: vehicle.start_vertiport_id.clone(),
+            time: vehicle.available_from,
+            flight_minutes_since_charge: 0,
+        }
+    }
+}
+
+/// Activities needed to append a shipment to a vehicle's schedule
+pub(crate) struct Insertion {
+    pub(crate) activities: Vec<Activity>,
+    pub(crate) state: VehicleState,
+}
+
+/// Plans the activities for `vehicle` to deliver `shipment` after its current activities
+pub(crate) fn plan_insertion<F>(
+    vehicle: &FleetVehicle,
+    state: &VehicleState,
+    shipment: &Shipment,
+    charging: &ChargingConfig,
+    flight_minutes: &F,
+) -> Option<Insertion>
+where
+    F: Fn(&str, &str) -> Option<i64>,
+{
+    if shipment.weight_grams > vehicle.capacity_grams {
+        return None;
+    }
+    let reposition_minutes = if state.vertiport_id == shipment.pickup_vertiport_id {
+        0
+    } else {
+        flight_minutes(&state.vertiport_id, &shipment.pickup_vertiport_id)?
+    };
+    let delivery_minutes = flight_minutes(
+        &shipment.pickup_vertiport_id,
+        &shipment.delivery_vertiport_id,
+    )?;
+    if reposition_minutes + delivery_minutes > charging.max_flight_minutes {
+        return None;
+    }
+
+    let mut activities = vec![];
+    let mut time = state.time;
+    let mut flight_minutes_since_charge = state.flight_minutes_since_charge;
+    if flight_minutes_since_charge + reposition_minutes + delivery_minutes
+        > charging.max_flight_minutes
+    {
+        let end = time + Duration::minutes(charging.charge_minutes);
+        activities.push(Activity::Charge {
+            vertiport_id: state.vertiport_id.clone(),
+            start: time,
+            end,
+        });
+        time = end;
+        flight_minutes_since_charge = 0;
+    }
+    if reposition_minutes > 0 {
+        // leave as late as possible so the vehicle doesn't wait at the pickup
+        let departure = next_departure_outside_curfew(
+            &state.vertiport_id,
+            &shipment.pickup_vertiport_id,
+            time.max(shipment.earliest_pickup_time - Duration::minutes(reposition_minutes)),
+            reposition_minutes,
+        )?;
+        let arrival = departure + Duration::minutes(reposition_minutes);
+        activities.push(Activity::Reposition(create_flight_plan_data(
+            vehicle.vehicle_id.clone(),
+            state.vertiport_id.clone(),
+            shipment.pickup_vertiport_id.clone(),
+            departure,
+            arrival,
+        )));
+        time = arrival;
+    }
+    let departure = next_departure_outside_curfew(
+        &shipment.pickup_vertiport_id,
+        &shipment.delivery_vertiport_id,
+        time.max(shipment.earliest_pickup_time),
+        delivery_minutes,
+    )?;
+    let arrival = departure + Duration::minutes(delivery_minutes);
+    if arrival > shipment.latest_delivery_time {
+        return None;
+    }
+    let mut flight_plan = create_flight_plan_data(
+        vehicle.vehicle_id.clone(),
+        shipment.pickup_vertiport_id.clone(),
+        shipment.delivery_vertiport_id.clone(),
+        departure,
+        arrival,
+    );
+    flight_plan.cargo_weight_grams = vec![shipment.weight_grams];
+    activities.push(Activity::Delivery {
+        shipment_id: shipment.id.clone(),
+        flight_plan,
+    });
+    Some(Insertion {
+        activities,
+        state: VehicleState {
+            vertiport_id: shipment.delivery_vertiport_id.clone(),
+            time: arrival,
+            flight_minutes_since_charge: flight_minutes_since_charge
+                + reposition_minutes
+                + delivery_minutes,
+        },
+    })
+}
+
+/// Builds the daily fleet schedule using the given flight time estimate
+///
+/// # Arguments
+/// * `shipments` - confirmed shipments of the day
+/// * `fleet` - vehicles available for the day
+/// * `charging` - endurance and charging time of the vehicles
+/// * `flight_minutes` - flight time in minutes between two vertiports, `None` if there is no route
+///
+/// # Returns
+/// Schedule for every vehicle and the shipments which could not be served
+pub fn solve_daily_vrp_with<F>(
+    shipments: &[Shipment],
+    fleet: &[FleetVehicle],
+    charging: &ChargingConfig,
+    flight_minutes: F,
+) -> DaySchedule
+where
+    F: Fn(&str, &str) -> Option<i64>,
+{
+    info!(
+        "Scheduling {} shipments for {} vehicles",
+        shipments.len(),
+        fleet.len()
+    );
+    let mut states: Vec<VehicleState> = fleet.iter().map(VehicleState::initial).collect();
+    let mut schedules: Vec<VehicleSchedule> = fleet
+        .iter()
+        .map(|vehicle| VehicleSchedule {
+            vehicle_id: vehicle.vehicle_id.clone(),
+            activities: vec![],
+        })
+        .collect();
+    let mut unassigned: Vec<String> = vec![];
+
+    let mut order: Vec<&Shipment> = shipments.iter().collect();
+    order.sort_by_key(|shipment| (shipment.latest_delivery_time, shipment.earliest_pickup_time));
+    for shipment in order {
+        let best = fleet
+            .iter()
+            .enumerate()
+            .filter_map(|(index, vehicle)| {
+                plan_insertion(vehicle, &states[index], shipment, charging, &flight_minutes)
+                    .map(|insertion| (index, insertion))
+            })
+            .min_by_key(|(_, insertion)| insertion.state.time);
+        match best {
+            Some((index, insertion)) => {
+                debug!(
+                    "Shipment {} assigned to vehicle {}",
+                    shipment.id, fleet[index].vehicle_id
+                );
+                schedules[index].activities.extend(insertion.activities);
+                states[index] = insertion.state;
+            }
+            None => {
+                debug!("Shipment {} could not be scheduled", shipment.id);
+                unassigned.push(shipment.id.clone());
+            }
+        }
+    }
+    info!(
+        "Scheduled {} of {} shipments",
+        shipments.len() - unassigned.len(),
+        shipments.len()
+    );
+    DaySchedule {
+        vehicles: schedules,
+        unassigned,
+    }
+}
+
+/// Builds the daily fleet schedule with flight times estimated from the router
+pub fn solve_daily_vrp(
+    shipments: &[Shipment],
+    fleet: &[FleetVehicle],
+    charging: &ChargingConfig,
+) -> DaySchedule {
+    solve_daily_vrp_with(shipments, fleet, charging, |from, to| {
+        estimate_flight_time_between(from, to).ok()
+    })
+}
+
+#[cfg(test)]
+mod vrp_tests {
+    use super::*;
+    use crate::test_support::mock_flight_minutes;
+    use chrono::TimeZone;
+
+    fn start() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2022, 10, 25, 8, 0, 0).unwrap()
+    }
+
+    fn shipment(id: &str, from: &str, to: &str, ready_min: i64, due_min: i64) -> Shipment {
+        Shipment {
+            id: id.to_string(),
+            pickup_vertiport_id: from.to_string(),
+            delivery_vertiport_id: to.to_string(),
+            weight_grams: 1000,
+            earliest_pickup_time: start() + Duration::minutes(ready_min),
+            latest_delivery_time: start() + Duration::minutes(due_min),
+        }
+    }
+
+    fn vehicle(id: &str, vertiport_id: &str) -> FleetVehicle {
+        FleetVehicle {
+            vehicle_id: id.to_string(),
+            start_vertiport_id: vertiport_id.to_string(),
+            available_from: start(),
+            capacity_grams: 5000,
+        }
+    }
+
+    #[test]
+    fn test_chains_shipments_on_one_vehicle() {
+        let shipments = vec![
+            shipment("s1", "A", "B", 0, 60),
+            shipment("s2", "B", "C", 30, 120),
+        ];
+        let schedule = solve_daily_vrp_with(
+            &shipments,
+            &[vehicle("v1", "A")],
+            &ChargingConfig::default(),
+            mock_flight_minutes,
+        );
+        assert!(schedule.unassigned.is_empty());
+        assert_eq!(schedule.vehicles[0].activities.len(), 2);
+    }
+
+    #[test]
+    fn test_repositions_to_pickup() {
+        let shipments = vec![shipment("s1", "C", "D", 60, 240)];
+        let schedule = solve_daily_vrp_with(
+            &shipments,
+            &[vehicle("v1", "A")],
+            &ChargingConfig::default(),
+            mock_flight_minutes,
+        );
+        let Activity::Reposition(flight_plan) = &schedule.vehicles[0].activities[0] else {
+            panic!("expected a repositioning flight");
+        };
+        assert_eq!(flight_plan.destination_vertiport_id.as_deref(), Some("C"));
+        // leaves just in time for the pickup
+        assert_eq!(
+            flight_plan.scheduled_arrival.as_ref().unwrap().seconds,
+            (start() + Duration::minutes(60)).timestamp()
+        );
+    }
+
+    #[test]
+    fn test_picks_vehicle_delivering_earliest() {
+        let shipments = vec![shipment("s1", "C", "D", 0, 240)];
+        let schedule = solve_daily_vrp_with(
+            &shipments,
+            &[vehicle("v1", "A"), vehicle("v2", "C")],
+            &ChargingConfig::default(),
+            mock_flight_minutes,
+        );
+        assert!(schedule.vehicles[0].activities.is_empty());
+        assert_eq!(schedule.vehicles[1].activities.len(), 1);
+    }
+
+    #[test]
+    fn test_charges_when_endurance_exceeded() {
+        let shipments = vec![
+            shipment("s1", "A", "D", 0, 600),
+            shipment("s2", "D", "A", 0, 600),
+        ];
+        let charging = ChargingConfig {
+            max_flight_minutes: 90,
+            charge_minutes: 30,
+        };
+        let schedule = solve_daily_vrp_with(
+            &shipments,
+            &[vehicle("v1", "A")],
+            &charging,
+            mock_flight_minutes,
+        );
+        assert!(schedule.unassigned.is_empty());
+        assert!(matches!(
+            schedule.vehicles[0].activities[1],
+            Activity::Charge { .. }
+        ));
+    }
+
+    #[test]
+    fn test_unassigned_shipment() {
+        let shipments = vec![shipment("s1", "A", "D", 0, 30)];
+        let schedule = solve_daily_vrp_with(
+            &shipments,
+            &[vehicle("v1", "A")],
+            &ChargingConfig::default(),
+            mock_flight_minutes,
+        );
+        assert_eq!(schedule.unassigned, vec!["s1".to_string()]);
+    }
+}
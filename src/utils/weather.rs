@@ -0,0 +1,304 @@
+//! Weather cells avoided by the router.
+//!
+//! A [`WeatherCell`] is a polygon of hazardous weather, such as a storm or an
+//! icing area, valid during a time window. Unlike the graph of the router,
+//! which is built once, the cells are evaluated for every route query: legs
+//! crossing a cell valid during the requested window are penalized according
+//! to its severity, or left out of the route if the weather is severe.
+//! Cells are ingested with [`ingest_weather_cells`] as forecasts are updated.
+
+use chrono::DateTime;
+use rrule::Tz;
+
+use crate::context::current_context;
+use crate::location::Location;
+
+/// Distance added to a leg crossing a cell of light weather
+pub const LIGHT_WEATHER_PENALTY_KM: f32 = 10.0;
+/// Distance added to a leg crossing a cell of moderate weather
+pub const MODERATE_WEATHER_PENALTY_KM: f32 = 50.0;
+
+/// Severity of the weather within a cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WeatherSeverity {
+    /// legs through the cell are penalized slightly
+    Light,
+    /// legs through the cell are penalized heavily
+    Moderate,
+    /// legs through the cell can't be flown
+    Severe,
+}
+
+impl WeatherSeverity {
+    /// Distance added to a leg crossing a cell, None if the leg can't be flown
+    pub fn penalty_km(&self) -> Option<f32> {
+        match self {
+            WeatherSeverity::Light => Some(LIGHT_WEATHER_PENALTY_KM),
+            WeatherSeverity::Moderate => Some(MODERATE_WEATHER_PENALTY_KM),
+            WeatherSeverity::Severe => None,
+        }
+    }
+}
+
+/// Area of hazardous weather during a time window
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeatherCell {
+    /// id of the cell, a cell ingested with the same id replaces it
+    pub id: String,
+    /// vertices of the polygon, altitudes are ignored
+    pub polygon: Vec<Location>,
+    /// severity of the weather
+    pub severity: WeatherSeverity,
+    /// start of the validity of the cell
+    pub valid_from: DateTime<Tz>,
+    /// end of the validity of the cell
+    pub valid_until: DateTime<Tz>,
+}
+
+impl WeatherCell {
+    /// Checks if the cell is valid at some point between `start` and `end`
+    pub fn is_valid_during(&self, start: DateTime<Tz>, end: DateTime<Tz>) -> bool {
+        self.valid_from < end && start < self.valid_until
+    }
+
+    /// Checks if the location is within the polygon of the cell
+    pub fn contains(&self, location: &Location) -> bool {
+        polygon_contains(&self.polygon, location)
+    }
+
+    /// Checks if the straight leg between two locations crosses the cell
+    pub fn intersects_leg(&self, from: &Location, to: &Location) -> bool {
+        polygon_intersects_leg(&self.polygon, from, to)
+    }
+}
+
+/// Checks if the location is within the polygon
+pub(crate) fn polygon_contains(polygon: &[Location], location: &Location) -> bool {
+    let (x, y) = coordinates(location);
+    let mut inside = false;
+    for (i, vertex) in polygon.iter().enumerate() {
+        let (x1, y1) = coordinates(vertex);
+        let (x2, y2) = coordinates(&polygon[(i + 1) % polygon.len()]);
+        if (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Checks if the straight leg between two locations crosses the polygon
+pub(crate) fn polygon_intersects_leg(polygon: &[Location], from: &Location, to: &Location) -> bool {
+    if polygon.len() < 3 {
+        return false;
+    }
+    polygon_contains(polygon, from)
+        || polygon_contains(polygon, to)
+        || (0..polygon.len())
+            .any(|i| segments_intersect(from, to, &polygon[i], &polygon[(i + 1) % polygon.len()]))
+}
+
+/// Longitude and latitude of a location, as planar coordinates
+fn coordinates(location: &Location) -> (f32, f32) {
+    (
+        location.longitude.into_inner(),
+        location.latitude.into_inner(),
+    )
+}
+
+/// Checks if the segments a-b and c-d cross each other
+fn segments_intersect(a: &Location, b: &Location, c: &Location, d: &Location) -> bool {
+    let orientation = |p: (f32, f32), q: (f32, f32), r: (f32, f32)| {
+        ((q.0 - p.0) * (r.1 - p.1) - (q.1 - p.1) * (r.0 - p.0)).signum()
+    };
+    let (a, b, c, d) = (
+        coordinates(a),
+        coordinates(b),
+        coordinates(c),
+        coordinates(d),
+    );
+    orientation(a, b, c) != orientation(a, b, d) && orientation(c, d, a) != orientation(c, d, b)
+}
+
+/// Penalty of a leg for the weather cells it crosses
+///
+/// # Arguments
+/// * `cells` - Weather cells to avoid
+/// * `from` - Start of the leg
+/// * `to` - End of the leg
+///
+/// # Returns
+/// The sum of the penalties of the crossed cells, None if one of them is
+/// severe
+pub fn weather_penalty(cells: &[WeatherCell], from: &Location, to: &Location) -> Option<f32> {
+    cells
+        .iter()
+        .filter(|cell| cell.intersects_leg(from, to))
+        .map(|cell| cell.severity.penalty_km())
+        .sum()
+}
+
+/// Adds weather cells, replacing the cells with the same ids
+pub fn ingest_weather_cells(cells: Vec<WeatherCell>) {
+    match current_context().weather_cells.write() {
+        Ok(mut current) => {
+            for cell in cells {
+                current.insert(cell.id.clone(), cell);
+            }
+        }
+        Err(_) => error!("Weather cells unavailable"),
+    }
+}
+
+/// Removes a weather cell, e.g. when it dissipates before the end of its validity
+pub fn remove_weather_cell(id: &str) {
+    match current_context().weather_cells.write() {
+        Ok(mut current) => {
+            current.remove(id);
+        }
+        Err(_) => error!("Weather cells unavailable"),
+    }
+}
+
+/// Removes the weather cells no longer valid at `time`
+pub fn remove_expired_weather_cells(time: DateTime<Tz>) {
+    match current_context().weather_cells.write() {
+        Ok(mut current) => current.retain(|_, cell| cell.valid_until > time),
+        Err(_) => error!("Weather cells unavailable"),
+    }
+}
+
+/// Gets the weather cells valid at some point between `start` and `end`
+pub fn get_weather_cells_during(start: DateTime<Tz>, end: DateTime<Tz>) -> Vec<WeatherCell> {
+    match current_context().weather_cells.read() {
+        Ok(current) => current
+            .values()
+            .filter(|cell| cell.is_valid_during(start, end))
+            .cloned()
+            .collect(),
+        Err(_) => {
+            error!("Weather cells unavailable");
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod weather_tests {
+    use super::*;
+    use crate::haversine;
+    use crate::node::Node;
+    use crate::router::engine::Router;
+    use crate::test_support::mock_location;
+    use chrono::{Duration, TimeZone};
+
+    fn noon() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2030, 6, 1, 12, 0, 0).unwrap()
+    }
+
+    /// Square cell between longitudes 0.2 and 0.3, valid from noon to 14:00
+    fn cell(id: &str, severity: WeatherSeverity) -> WeatherCell {
+        WeatherCell {
+            id: id.to_string(),
+            polygon: vec![
+                mock_location(-0.05, 0.2),
+                mock_location(-0.05, 0.3),
+                mock_location(0.05, 0.3),
+                mock_location(0.05, 0.2),
+            ],
+            severity,
+            valid_from: noon(),
+            valid_until: noon() + Duration::hours(2),
+        }
+    }
+
+    #[test]
+    fn test_intersects_leg() {
+        let cell = cell("wx-intersects", WeatherSeverity::Severe);
+        assert!(cell.contains(&mock_location(0.0, 0.25)));
+        assert!(!cell.contains(&mock_location(0.0, 0.35)));
+        // crossing the cell without a vertex inside
+        assert!(cell.intersects_leg(&mock_location(0.0, 0.0), &mock_location(0.0, 0.5)));
+        // ending inside the cell
+        assert!(cell.intersects_leg(&mock_location(0.0, 0.0), &mock_location(0.0, 0.25)));
+        assert!(!cell.intersects_leg(&mock_location(0.1, 0.0), &mock_location(0.1, 0.5)));
+    }
+
+    #[test]
+    fn test_is_valid_during() {
+        let cell = cell("wx-valid", WeatherSeverity::Light);
+        assert!(cell.is_valid_during(noon() - Duration::hours(1), noon() + Duration::minutes(1)));
+        assert!(cell.is_valid_during(noon() + Duration::hours(1), noon() + Duration::hours(5)));
+        assert!(!cell.is_valid_during(noon() - Duration::hours(1), noon()));
+        assert!(!cell.is_valid_during(noon() + Duration::hours(2), noon() + Duration::hours(3)));
+    }
+
+    #[test]
+    fn test_weather_penalty() {
+        let from = mock_location(0.0, 0.0);
+        let to = mock_location(0.0, 0.5);
+        let light = cell("wx-light", WeatherSeverity::Light);
+        let moderate = cell("wx-moderate", WeatherSeverity::Moderate);
+        let severe = cell("wx-severe", WeatherSeverity::Severe);
+        assert_eq!(weather_penalty(&[], &from, &to), Some(0.0));
+        assert_eq!(
+            weather_penalty(&[light.clone(), moderate], &from, &to),
+            Some(LIGHT_WEATHER_PENALTY_KM + MODERATE_WEATHER_PENALTY_KM)
+        );
+        assert_eq!(weather_penalty(&[light, severe], &from, &to), None);
+    }
+
+    #[test]
+    fn test_ingest_weather_cells() {
+        ingest_weather_cells(vec![
+            cell("wx-ingest-1", WeatherSeverity::Light),
+            cell("wx-ingest-2", WeatherSeverity::Light),
+        ]);
+        ingest_weather_cells(vec![cell("wx-ingest-1", WeatherSeverity::Severe)]);
+        remove_weather_cell("wx-ingest-2");
+
+        let ids = |start: DateTime<Tz>| {
+            get_weather_cells_during(start, start + Duration::hours(1))
+                .into_iter()
+                .filter(|cell| cell.id.starts_with("wx-ingest"))
+                .map(|cell| (cell.id, cell.severity))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ids(noon()),
+            vec![("wx-ingest-1".to_string(), WeatherSeverity::Severe)]
+        );
+        assert!(ids(noon() + Duration::hours(3)).is_empty());
+
+        remove_expired_weather_cells(noon() + Duration::hours(2));
+        assert!(ids(noon()).is_empty());
+    }
+
+    #[test]
+    fn test_routes_avoid_weather() {
+        // the direct leg crosses the cell, the detour passes north of it
+        let nodes = vec![
+            Node::new("wx-A", 0.0, 0.0),
+            Node::new("wx-B", 0.3, 0.25),
+            Node::new("wx-C", 0.0, 0.5),
+        ];
+        let router = Router::new(
+            &nodes,
+            100.0,
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+            |from, to| haversine::distance(&from.as_node().location, &to.as_node().location),
+        );
+        let route = |cells: &[WeatherCell]| {
+            let (_, path) = router
+                .find_shortest_path_with_penalty(&nodes[0], &nodes[2], |from, to| {
+                    weather_penalty(cells, &from.location, &to.location)
+                })
+                .unwrap();
+            path.len()
+        };
+
+        assert_eq!(route(&[]), 2);
+        assert_eq!(route(&[cell("wx-route", WeatherSeverity::Light)]), 2);
+        assert_eq!(route(&[cell("wx-route", WeatherSeverity::Moderate)]), 3);
+        assert_eq!(route(&[cell("wx-route", WeatherSeverity::Severe)]), 3);
+    }
+}
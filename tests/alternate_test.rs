@@ -0,0 +1,59 @@
+//! Flights to a vertiport without an alternate reachable when loaded.
+//! The router is initialized once per test binary, so the network of these
+//! tests lives in its own binary.
+
+use chrono::Duration;
+use router::audit::{
+    clear_audit_sink, set_audit_sink, MemoryAuditSink, RejectionReason, SlotOutcome,
+};
+use router::network_import::init_router_from_records;
+use router::router_state::get_possible_flights;
+use router::test_support::{mock_start, MockVehicle, MockVertiport};
+use router::time::datetime_to_timestamp;
+use std::sync::Arc;
+
+/// ORIGIN is 33 km from DESTINATION and OUTPOST 24 km, within the reserve
+/// range of an empty aircraft but not of a loaded one.
+#[test]
+fn test_slots_rejected_without_loaded_alternate() {
+    let origin = MockVertiport::new("ORIGIN", 0.0, 0.0);
+    let destination = MockVertiport::new("DESTINATION", 0.0, 0.3);
+    let outpost = MockVertiport::new("OUTPOST", 0.0, 0.52);
+    let records: Vec<_> = [&origin, &destination, &outpost]
+        .into_iter()
+        .map(MockVertiport::record)
+        .collect();
+    init_router_from_records(&records).unwrap();
+
+    let sink = Arc::new(MemoryAuditSink::new());
+    set_audit_sink(sink.clone());
+    let flights = get_possible_flights(
+        origin.build(),
+        destination.build(),
+        origin.vertipads(),
+        destination.vertipads(),
+        Some(datetime_to_timestamp(&mock_start())),
+        Some(datetime_to_timestamp(
+            &(mock_start() + Duration::minutes(180)),
+        )),
+        vec![MockVehicle::new("vehicle")
+            .with_last_vertiport("ORIGIN")
+            .build()],
+        vec![],
+    );
+    clear_audit_sink();
+
+    // every slot is rejected, as for any other reason
+    assert_eq!(
+        flights.unwrap_err(),
+        "No flight plans found for given time window"
+    );
+    let records = sink.records();
+    assert_eq!(records.len(), 1);
+    assert!(!records[0].candidates.is_empty());
+    assert!(records[0]
+        .candidates
+        .iter()
+        .all(|candidate| candidate.outcome
+            == SlotOutcome::Rejected(RejectionReason::NoAlternateVertiport)));
+}
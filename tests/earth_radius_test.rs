@@ -0,0 +1,43 @@
+//! Radius of the Earth set for all distances. The radius is global, so these
+//! tests live in their own binary.
+
+use router::haversine::{self, EarthRadius};
+use router::node::{AsNode, Node};
+use router::router::engine::{Algorithm, Router};
+
+fn distance(from: &dyn AsNode, to: &dyn AsNode) -> f32 {
+    haversine::distance(&from.as_node().location, &to.as_node().location)
+}
+
+#[test]
+fn test_router_built_before_the_radius_changes() {
+    let nodes = vec![Node::new("A", 0.0, 0.0), Node::new("B", 0.0, 5.0)];
+    let expected =
+        |radius| haversine::distance_with(radius, &nodes[0].location, &nodes[1].location);
+
+    haversine::set_earth_radius(EarthRadius::Authalic);
+    assert_eq!(haversine::get_earth_radius(), EarthRadius::Authalic);
+    assert_eq!(
+        haversine::distance(&nodes[0].location, &nodes[1].location),
+        expected(EarthRadius::Authalic)
+    );
+    let authalic = Router::new(&nodes, 1000.0, distance, distance).with_all_pairs();
+    assert_eq!(authalic.earth_radius(), EarthRadius::Authalic);
+
+    // the router and its precomputed paths keep the radius they were built on
+    haversine::set_earth_radius(EarthRadius::Mean);
+    for algorithm in [Algorithm::Dijkstra, Algorithm::PrecomputedAllPairs] {
+        let (cost, path) = authalic
+            .find_shortest_path(&nodes[0], &nodes[1], algorithm, None)
+            .unwrap();
+        assert_eq!(cost, expected(EarthRadius::Authalic));
+        assert_eq!(authalic.get_total_distance(&path).unwrap(), cost);
+    }
+
+    let mean = Router::new(&nodes, 1000.0, distance, distance);
+    assert_eq!(mean.earth_radius(), EarthRadius::Mean);
+    let (cost, _) = mean
+        .find_shortest_path(&nodes[0], &nodes[1], Algorithm::Dijkstra, None)
+        .unwrap();
+    assert_eq!(cost, expected(EarthRadius::Mean));
+}
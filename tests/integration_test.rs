@@ -1,2 +1,514 @@
 //! Hello world example for Rust.
 //! This is a crate description, needed or else missing_docs warning will occur.
+
+use chrono::Duration;
+use router::context::RouterContext;
+use router::energy::{set_vehicle_battery, VehicleBattery};
+use router::flight_plan_stream::get_possible_flights_streamed;
+use router::maintenance::{set_maintenance_interval, MaintenanceInterval};
+use router::network_import::init_router_from_records;
+use router::operator::OperatorFilter;
+use router::providers::{get_possible_flights_async, VehiclePages, VehicleProvider};
+use router::router_state::{
+    get_possible_flights, get_possible_flights_for_operators, get_possible_flights_from_candidates,
+    FlightPlan, PossibleFlight, Vehicle, VertiportCandidate,
+};
+use router::separation::{set_separation_minima, SeparationMinima};
+use router::test_support::{block_on, mock_start, MockFlightPlan, MockVehicle, MockVertiport};
+use router::time::datetime_to_timestamp;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Once};
+
+static INIT_ROUTER: Once = Once::new();
+
+/// SFO, OAK and SJC, with two pads at SFO
+fn vertiports() -> (MockVertiport, MockVertiport, MockVertiport) {
+    let sfo = MockVertiport::new("SFO", 37.6213, -122.379).with_pads(2);
+    let oak = MockVertiport::new("OAK", 37.7126, -122.2197);
+    let sjc = MockVertiport::new("SJC", 37.3639, -121.9289);
+    INIT_ROUTER.call_once(|| {
+        init_router_from_records(&[sfo.record(), oak.record(), sjc.record()]).unwrap()
+    });
+    (sfo, oak, sjc)
+}
+
+#[test]
+fn test_possible_flights_with_mock_fixtures() {
+    let (sfo, oak, _) = vertiports();
+
+    let vehicles = vec![
+        MockVehicle::new("vehicle-1")
+            .with_last_vertiport("SFO")
+            .build(),
+        MockVehicle::new("vehicle-2")
+            .with_last_vertiport("SJC")
+            .build(),
+    ];
+    // vehicle-2 is busy flying to SFO during the requested window
+    let existing_flight_plans = vec![MockFlightPlan::new("plan-1", "vehicle-2", "SJC", "SFO")
+        .with_departure(mock_start() + Duration::minutes(15))
+        .build()];
+
+    let flights = get_possible_flights(
+        sfo.build(),
+        oak.build(),
+        sfo.vertipads(),
+        oak.vertipads(),
+        Some(datetime_to_timestamp(&mock_start())),
+        Some(datetime_to_timestamp(
+            &(mock_start() + Duration::minutes(90)),
+        )),
+        vehicles,
+        existing_flight_plans,
+    )
+    .unwrap();
+
+    assert!(!flights.is_empty());
+    let first = &flights[0].flight_plan;
+    assert_eq!(first.vehicle_id, "vehicle-1");
+    assert_eq!(
+        first.scheduled_departure,
+        Some(datetime_to_timestamp(&mock_start()))
+    );
+    assert!(flights[0].deadhead_flight_plans.is_empty());
+}
+
+#[test]
+fn test_possible_flights_from_candidates() {
+    let (sfo, oak, sjc) = vertiports();
+    let candidate = |vertiport: &MockVertiport| {
+        VertiportCandidate::new(vertiport.build(), vertiport.vertipads())
+    };
+    let vehicles = vec![
+        MockVehicle::new("vehicle-1")
+            .with_last_vertiport("SFO")
+            .build(),
+        MockVehicle::new("vehicle-2")
+            .with_last_vertiport("SJC")
+            .build(),
+    ];
+
+    let flights = get_possible_flights_from_candidates(
+        &[candidate(&sfo), candidate(&sjc), candidate(&oak)],
+        &[candidate(&oak)],
+        Some(datetime_to_timestamp(&mock_start())),
+        Some(datetime_to_timestamp(&(mock_start() + Duration::hours(3)))),
+        vehicles,
+        vec![],
+    )
+    .unwrap();
+
+    let departures = flights
+        .iter()
+        .map(|flight| flight.flight_plan.departure_vertiport_id.clone().unwrap())
+        .collect::<Vec<_>>();
+    assert!(departures.contains(&"SFO".to_string()));
+    assert!(departures.contains(&"SJC".to_string()));
+    assert!(!departures.contains(&"OAK".to_string()));
+    // SFO is closer to OAK, so its flights are the quickest
+    let flight_seconds = |flight: &PossibleFlight| {
+        let plan = &flight.flight_plan;
+        plan.scheduled_arrival.as_ref().unwrap().seconds
+            - plan.scheduled_departure.as_ref().unwrap().seconds
+    };
+    assert_eq!(departures[0], "SFO");
+    assert!(flights
+        .windows(2)
+        .all(|pair| flight_seconds(&pair[0]) <= flight_seconds(&pair[1])));
+
+    // an hour on the ground to SFO makes SJC quicker
+    let flights = get_possible_flights_from_candidates(
+        &[candidate(&sfo).with_ground_minutes(60.0), candidate(&sjc)],
+        &[candidate(&oak)],
+        Some(datetime_to_timestamp(&mock_start())),
+        Some(datetime_to_timestamp(&(mock_start() + Duration::hours(3)))),
+        vec![
+            MockVehicle::new("vehicle-1")
+                .with_last_vertiport("SFO")
+                .build(),
+            MockVehicle::new("vehicle-2")
+                .with_last_vertiport("SJC")
+                .build(),
+        ],
+        vec![],
+    )
+    .unwrap();
+    assert_eq!(
+        flights[0].flight_plan.departure_vertiport_id.as_deref(),
+        Some("SJC")
+    );
+}
+
+#[test]
+fn test_possible_flights_for_operators() {
+    let (sfo, oak, sjc) = vertiports();
+    let sfo = sfo.shared();
+    let oak_a = oak.clone().with_operator("operator-A");
+    let oak_b = oak.clone().with_operator("operator-B");
+    let vehicles = vec![
+        MockVehicle::new("vehicle-A")
+            .with_last_vertiport("SFO")
+            .with_operator("operator-A")
+            .build(),
+        MockVehicle::new("vehicle-B")
+            .with_last_vertiport("SFO")
+            .with_operator("operator-B")
+            .build(),
+    ];
+    let query = |filter: &OperatorFilter, arrival: &MockVertiport| {
+        get_possible_flights_for_operators(
+            filter,
+            &[sfo.build(), arrival.build(), sjc.build()],
+            sfo.build(),
+            arrival.build(),
+            sfo.vertipads(),
+            arrival.vertipads(),
+            Some(datetime_to_timestamp(&mock_start())),
+            Some(datetime_to_timestamp(
+                &(mock_start() + Duration::minutes(90)),
+            )),
+            vehicles.clone(),
+            vec![],
+        )
+    };
+
+    let tenant_a = OperatorFilter::new().with_operator("operator-A");
+    let flights = query(&tenant_a, &oak_a).unwrap();
+    assert!(!flights.is_empty());
+    assert!(flights
+        .iter()
+        .all(|flight| flight.flight_plan.vehicle_id == "vehicle-A"));
+    assert!(query(&tenant_a, &oak_b).is_err());
+
+    let tenant_b = OperatorFilter::new().with_operator("operator-B");
+    let flights = query(&tenant_b, &oak_b).unwrap();
+    assert!(!flights.is_empty());
+    assert!(flights
+        .iter()
+        .all(|flight| flight.flight_plan.vehicle_id == "vehicle-B"));
+
+    // unknown operator, e.g. a vertiport loaded from svc-storage
+    assert!(query(&tenant_a, &oak).is_err());
+}
+
+#[test]
+fn test_possible_flights_queue_for_busy_pad() {
+    let (sfo, oak, _) = vertiports();
+    let query = |existing_flight_plans| {
+        get_possible_flights(
+            sfo.build(),
+            oak.build(),
+            sfo.vertipads(),
+            oak.vertipads(),
+            Some(datetime_to_timestamp(&mock_start())),
+            Some(datetime_to_timestamp(
+                &(mock_start() + Duration::minutes(90)),
+            )),
+            vec![MockVehicle::new("vehicle-1")
+                .with_last_vertiport("SFO")
+                .build()],
+            existing_flight_plans,
+        )
+        .unwrap()
+    };
+    let flights = query(vec![]);
+    assert_eq!(flights[0].queue_minutes, 0);
+    let arrival = mock_start()
+        + Duration::seconds(
+            flights[0]
+                .flight_plan
+                .scheduled_arrival
+                .as_ref()
+                .unwrap()
+                .seconds
+                - mock_start().timestamp(),
+        );
+
+    // another vehicle lands on the single pad of OAK shortly before
+    let landing = MockFlightPlan::new("plan-1", "vehicle-2", "SJC", "OAK")
+        .with_departure(arrival - Duration::minutes(47))
+        .build();
+    let flights = query(vec![landing]);
+    assert_eq!(flights[0].queue_minutes, 3);
+    assert_eq!(
+        flights[0].flight_plan.scheduled_departure,
+        Some(datetime_to_timestamp(
+            &(mock_start() + Duration::minutes(3))
+        ))
+    );
+}
+
+#[test]
+fn test_queued_flight_departs_before_next_slot() {
+    let (sfo, oak, _) = vertiports();
+    // a flight staggered to keep separated from the crossing flight can only
+    // queue for the pad of OAK until the next slot
+    let existing_flight_plans = vec![
+        MockFlightPlan::new("plan-1", "vehicle-2", "SFO", "OAK")
+            .with_departure(mock_start() + Duration::minutes(2))
+            .build(),
+        MockFlightPlan::new("plan-2", "vehicle-3", "OAK", "SFO")
+            .with_departure(mock_start())
+            .build(),
+    ];
+    let context = Arc::new(RouterContext::new());
+    let flights = context
+        .scope(|| {
+            set_separation_minima(SeparationMinima::default());
+            get_possible_flights(
+                sfo.build(),
+                oak.build(),
+                sfo.vertipads(),
+                oak.vertipads(),
+                Some(datetime_to_timestamp(&mock_start())),
+                Some(datetime_to_timestamp(
+                    &(mock_start() + Duration::minutes(60)),
+                )),
+                vec![MockVehicle::new("vehicle-1")
+                    .with_last_vertiport("SFO")
+                    .build()],
+                existing_flight_plans,
+            )
+        })
+        .unwrap();
+
+    let departures: Vec<i64> = flights
+        .iter()
+        .map(|flight| {
+            flight
+                .flight_plan
+                .scheduled_departure
+                .as_ref()
+                .unwrap()
+                .seconds
+        })
+        .collect();
+    assert!(!departures.is_empty());
+    assert!(departures.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+/// Four 30 minute flights of the vehicle between SFO and OAK, from 10 to 7
+/// days before the start, the vehicle parked back at SFO
+fn flights_days_ago(vehicle_id: &str) -> Vec<FlightPlan> {
+    (0..4)
+        .map(|i| {
+            let (from, to) = if i % 2 == 0 {
+                ("SFO", "OAK")
+            } else {
+                ("OAK", "SFO")
+            };
+            MockFlightPlan::new(&format!("{}-{}", vehicle_id, i), vehicle_id, from, to)
+                .with_departure(mock_start() - Duration::days(10 - i))
+                .build()
+        })
+        .collect()
+}
+
+#[test]
+fn test_streamed_flights_count_vehicle_history() {
+    let (sfo, oak, _) = vertiports();
+    let query = |vehicle: &MockVehicle| -> Result<Vec<PossibleFlight>, String> {
+        get_possible_flights_streamed(
+            sfo.build(),
+            oak.build(),
+            sfo.vertipads(),
+            oak.vertipads(),
+            Some(datetime_to_timestamp(&mock_start())),
+            Some(datetime_to_timestamp(
+                &(mock_start() + Duration::minutes(90)),
+            )),
+            vec![vehicle.build()],
+            flights_days_ago(&vehicle.build().id),
+        )
+    };
+
+    // maintained 30 days ago, due after 4 flights
+    let maintained = MockVehicle::new("vehicle-streamed-usage").with_last_vertiport("SFO");
+    assert!(query(&maintained).is_ok());
+    set_maintenance_interval(
+        "vehicle-streamed-usage",
+        MaintenanceInterval {
+            max_flight_minutes: None,
+            max_cycles: Some(4),
+        },
+    );
+    assert!(query(&maintained).is_err());
+
+    // fully charged 11 days ago, 4 kWh used by each flight
+    let charged = MockVehicle::new("vehicle-streamed-charge").with_last_vertiport("SFO");
+    assert!(query(&charged).is_ok());
+    set_vehicle_battery("vehicle-streamed-charge", battery_charged_days_ago(11));
+    assert!(query(&charged).is_err());
+}
+
+#[test]
+fn test_async_flights_count_vehicle_history() {
+    let (sfo, oak, _) = vertiports();
+    let query = |vehicle: &MockVehicle| -> Result<Vec<PossibleFlight>, String> {
+        block_on(get_possible_flights_async(
+            sfo.build(),
+            oak.build(),
+            sfo.vertipads(),
+            oak.vertipads(),
+            Some(datetime_to_timestamp(&mock_start())),
+            Some(datetime_to_timestamp(
+                &(mock_start() + Duration::minutes(90)),
+            )),
+            &VehiclePages {
+                vehicles: vec![vehicle.build()],
+                page_size: 1,
+            },
+            &flights_days_ago(&vehicle.build().id),
+        ))
+    };
+
+    // maintained 30 days ago, due after 4 flights
+    let maintained = MockVehicle::new("vehicle-async-usage").with_last_vertiport("SFO");
+    assert!(query(&maintained).is_ok());
+    set_maintenance_interval(
+        "vehicle-async-usage",
+        MaintenanceInterval {
+            max_flight_minutes: None,
+            max_cycles: Some(4),
+        },
+    );
+    assert!(query(&maintained).is_err());
+
+    // fully charged 11 days ago, 4 kWh used by each flight
+    let charged = MockVehicle::new("vehicle-async-charge").with_last_vertiport("SFO");
+    assert!(query(&charged).is_ok());
+    set_vehicle_battery("vehicle-async-charge", battery_charged_days_ago(11));
+    assert!(query(&charged).is_err());
+}
+
+/// 20 kWh battery fully charged `days` before the start, keeping 20% of reserve
+fn battery_charged_days_ago(days: i64) -> VehicleBattery {
+    VehicleBattery {
+        capacity_kwh: 20.0,
+        charge_rate_kw: 50.0,
+        reserve_state_of_charge: 0.2,
+        state_of_charge: 1.0,
+        updated_at: mock_start() - Duration::days(days),
+        charging_blocks: vec![],
+    }
+}
+
+/// Pages of vehicles counting the pages fetched
+struct CountedPages {
+    pages: VehiclePages,
+    fetched: AtomicUsize,
+}
+
+impl CountedPages {
+    fn new(vehicles: Vec<Vehicle>) -> Self {
+        CountedPages {
+            pages: VehiclePages {
+                vehicles,
+                page_size: 1,
+            },
+            fetched: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl VehicleProvider for CountedPages {
+    async fn vehicles(&self, page: usize) -> Result<Vec<Vehicle>, String> {
+        self.fetched.fetch_add(1, Ordering::SeqCst);
+        self.pages.vehicles(page).await
+    }
+}
+
+/// Vehicle and departure of each flight
+fn departures(flights: &[PossibleFlight]) -> Vec<(String, i64)> {
+    flights
+        .iter()
+        .map(|flight| {
+            (
+                flight.flight_plan.vehicle_id.clone(),
+                flight
+                    .flight_plan
+                    .scheduled_departure
+                    .as_ref()
+                    .unwrap()
+                    .seconds,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_async_flights_paging() {
+    let (sfo, oak, _) = vertiports();
+    let query = |vehicles: &CountedPages, existing_flight_plans: &Vec<FlightPlan>| {
+        block_on(get_possible_flights_async(
+            sfo.build(),
+            oak.build(),
+            sfo.vertipads(),
+            oak.vertipads(),
+            Some(datetime_to_timestamp(&mock_start())),
+            Some(datetime_to_timestamp(
+                &(mock_start() + Duration::minutes(90)),
+            )),
+            vehicles,
+            existing_flight_plans,
+        ))
+        .unwrap()
+    };
+    // a vehicle which can't be located, and two vehicles parked at SFO
+    let lost = MockVehicle::new("vehicle-paging-lost").build();
+    let parked = |id: &str| MockVehicle::new(id).with_last_vertiport("SFO").build();
+
+    // the first page serves no slot, the second page all of them
+    let vehicles = CountedPages::new(vec![
+        lost.clone(),
+        parked("vehicle-paging-1"),
+        parked("vehicle-paging-2"),
+    ]);
+    let flights = query(&vehicles, &vec![]);
+    assert!(departures(&flights)
+        .iter()
+        .all(|(vehicle_id, _)| vehicle_id == "vehicle-paging-1"));
+    assert_eq!(vehicles.fetched.load(Ordering::SeqCst), 2);
+
+    // vehicle-paging-1 is busy for the first slots, served by the next page
+    let busy = vec![
+        MockFlightPlan::new("plan-paging", "vehicle-paging-1", "SFO", "SJC")
+            .with_departure(mock_start() - Duration::minutes(10))
+            .with_duration(Duration::minutes(20))
+            .build(),
+        MockFlightPlan::new("plan-paging-back", "vehicle-paging-1", "SJC", "SFO")
+            .with_departure(mock_start() + Duration::minutes(10))
+            .with_duration(Duration::minutes(20))
+            .build(),
+    ];
+    let vehicles = CountedPages::new(vec![
+        parked("vehicle-paging-1"),
+        lost,
+        parked("vehicle-paging-2"),
+    ]);
+    let flights = query(&vehicles, &busy);
+    assert_eq!(vehicles.fetched.load(Ordering::SeqCst), 3);
+    let served = departures(&flights);
+    assert!(served
+        .iter()
+        .any(|(vehicle_id, _)| vehicle_id == "vehicle-paging-1"));
+    assert!(served
+        .iter()
+        .any(|(vehicle_id, _)| vehicle_id == "vehicle-paging-2"));
+
+    // same flights as the synchronous search with all the vehicles
+    let sync_flights = get_possible_flights(
+        sfo.build(),
+        oak.build(),
+        sfo.vertipads(),
+        oak.vertipads(),
+        Some(datetime_to_timestamp(&mock_start())),
+        Some(datetime_to_timestamp(
+            &(mock_start() + Duration::minutes(90)),
+        )),
+        vehicles.pages.vehicles.clone(),
+        busy,
+    )
+    .unwrap();
+    assert_eq!(served, departures(&sync_flights));
+}
@@ -0,0 +1,98 @@
+//! Flights of a tenant on a network shared with other operators.
+//! The router is initialized once per test binary, so the network of these
+//! tests lives in its own binary.
+
+use chrono::Duration;
+use router::network_import::init_router_from_records;
+use router::operator::OperatorFilter;
+use router::router_state::{get_possible_flights_for_operators, Vertiport};
+use router::test_support::{mock_start, MockVehicle, MockVertiport};
+use router::time::datetime_to_timestamp;
+use std::sync::Once;
+
+static INIT_ROUTER: Once = Once::new();
+
+/// Network of operator-A, split in two by CENTER of operator-B: WEST and
+/// EAST are too far apart for a direct leg. NORTH of operator-A is the
+/// alternate of EAST, and SOUTH of operator-B is near WEST.
+fn vertiports() -> [MockVertiport; 5] {
+    let vertiports = [
+        MockVertiport::new("WEST", 0.0, 0.0).with_operator("operator-A"),
+        MockVertiport::new("CENTER", 0.0, 0.5).with_operator("operator-B"),
+        MockVertiport::new("EAST", 0.0, 1.0).with_operator("operator-A"),
+        MockVertiport::new("NORTH", 0.1, 1.0).with_operator("operator-A"),
+        MockVertiport::new("SOUTH", -0.3, 0.0).with_operator("operator-B"),
+    ];
+    INIT_ROUTER.call_once(|| {
+        let records: Vec<_> = vertiports.iter().map(MockVertiport::record).collect();
+        init_router_from_records(&records).unwrap()
+    });
+    vertiports
+}
+
+fn query(network: &[Vertiport], vehicle: &MockVehicle) -> Result<usize, String> {
+    let [west, _, east, ..] = vertiports();
+    get_possible_flights_for_operators(
+        &OperatorFilter::new().with_operator("operator-A"),
+        network,
+        west.build(),
+        east.build(),
+        west.vertipads(),
+        east.vertipads(),
+        Some(datetime_to_timestamp(&mock_start())),
+        Some(datetime_to_timestamp(
+            &(mock_start() + Duration::minutes(180)),
+        )),
+        vec![vehicle.build()],
+        vec![],
+    )
+    .map(|flights| flights.len())
+}
+
+#[test]
+fn test_no_route_through_foreign_vertiport() {
+    let [west, center, east, north, south] = vertiports();
+    let vehicle = MockVehicle::new("vehicle-route")
+        .with_last_vertiport("WEST")
+        .with_operator("operator-A");
+
+    let network = [
+        west.build(),
+        center.build(),
+        east.build(),
+        north.build(),
+        south.build(),
+    ];
+    assert!(query(&network, &vehicle).is_err());
+    // a vertiport missing from the network is foreign
+    assert!(query(&[west.build(), east.build()], &vehicle).is_err());
+
+    let network = [west.build(), center.shared().build(), east.build()];
+    assert!(query(&network, &vehicle).unwrap() > 0);
+}
+
+#[test]
+fn test_no_deadhead_from_foreign_vertiport() {
+    let [west, center, east, north, south] = vertiports();
+    let vehicle = MockVehicle::new("vehicle-deadhead")
+        .with_last_vertiport("SOUTH")
+        .with_operator("operator-A");
+
+    let network = [
+        west.build(),
+        center.clone().shared().build(),
+        east.build(),
+        north.build(),
+        south.build(),
+    ];
+    assert!(query(&network, &vehicle).is_err());
+
+    let network = [
+        west.build(),
+        center.shared().build(),
+        east.build(),
+        north.build(),
+        south.shared().build(),
+    ];
+    assert!(query(&network, &vehicle).unwrap() > 0);
+}